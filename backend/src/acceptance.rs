@@ -0,0 +1,73 @@
+//! Order acceptance. A new order waits in `pending` until its vendor accepts
+//! it, moving it to `processing`, or declines it. Declining puts the quantity
+//! back into stock and refunds a paid order to the customer's wallet; orders
+//! still pending `order_acceptance_hours` after they were placed are declined
+//! by a background task.
+
+use crate::{db, digests, email, email_templates, settings};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// How often the background task looks for orders past the acceptance window.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Hours a vendor has to accept a new order.
+pub async fn window_hours(pool: &PgPool) -> i64 {
+    settings::get_i64(pool, settings::ORDER_ACCEPTANCE_HOURS).await.max(1)
+}
+
+/// Decline a pending order and let its customer know about the refund.
+/// Returns the amount refunded, or None if the order is no longer pending.
+pub async fn decline_order(pool: &PgPool, order_id: i32) -> Result<Option<f64>, sqlx::Error> {
+    let Some(refunded) = db::decline_pending_order(pool, order_id).await? else {
+        return Ok(None);
+    };
+
+    if let Some((customer_id, _, quantity, product_name)) = db::get_shipping_order_summary(pool, order_id).await? {
+        let summary = format!(
+            "Order #{} ({} x {}) was declined; KSh {:.2} was refunded to your wallet",
+            order_id, quantity, product_name, refunded
+        );
+        if !digests::queued_for_digest(pool, customer_id, &summary).await {
+            if let Ok(customer) = db::get_user_by_id(pool, customer_id).await {
+                let language = email_templates::user_language(pool, customer_id).await;
+                if let Err(e) = email::send_order_declined_email(pool, &customer.email, &customer.username, order_id, &product_name, quantity, refunded, &language).await {
+                    eprintln!("Failed to send order declined email to {}: {:?}", customer.email, e);
+                }
+            }
+        }
+    }
+
+    Ok(Some(refunded))
+}
+
+/// Decline every order still pending after the acceptance window. Returns the number declined.
+pub async fn decline_overdue_orders(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let overdue = db::get_overdue_pending_orders(pool, window_hours(pool).await).await?;
+
+    let mut declined = 0;
+    for order_id in overdue {
+        match decline_order(pool, order_id).await {
+            Ok(Some(_)) => declined += 1,
+            // Accepted or cancelled since it was listed
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to auto-decline order {}: {:?}", order_id, e),
+        }
+    }
+    Ok(declined)
+}
+
+/// Run `decline_overdue_orders` periodically for the life of the process.
+pub fn spawn_acceptance_task(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match decline_overdue_orders(&pool).await {
+                Ok(0) => {}
+                Ok(count) => println!("📦 Declined {} order(s) not accepted in time", count),
+                Err(e) => eprintln!("Order acceptance check failed: {:?}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,152 @@
+//! Read-only SQL for admins. A statement must be a single SELECT; it runs in
+//! a read-only transaction as `READER_ROLE`, with a statement timeout, and at
+//! most `MAX_ROWS` rows come back. The keyword check only gives clear errors
+//! for obvious mistakes; the role, which can do nothing but read the app's
+//! tables, and the read-only transaction are what stop writes and server
+//! functions.
+
+use serde_json::Value;
+
+/// Most rows a query returns; the response says when more were available.
+pub const MAX_ROWS: usize = 500;
+
+/// Statement timeout for admin queries, in milliseconds.
+pub const STATEMENT_TIMEOUT_MS: u64 = 5_000;
+
+/// Minutes an emailed admin query code stays valid.
+pub const CODE_VALID_MINUTES: i64 = 10;
+
+/// Wrong codes an admin may send before their current code stops working.
+pub const CODE_MAX_ATTEMPTS: i32 = 5;
+
+/// Role admin queries run as. It has SELECT on the app's tables and nothing
+/// else; `db::init_schema` creates it when the database user may create roles.
+pub const READER_ROLE: &str = "fmp_admin_query_reader";
+
+/// Words that write, lock, or change the session, anywhere outside a literal.
+const FORBIDDEN_KEYWORDS: [&str; 22] = [
+    "insert", "update", "delete", "merge", "upsert", "truncate", "drop", "alter", "create", "grant", "revoke",
+    "copy", "vacuum", "analyze", "call", "do", "lock", "into", "share", "set", "reset", "listen",
+];
+
+/// Functions with side effects a read-only transaction doesn't prevent.
+const FORBIDDEN_FUNCTION_PREFIXES: [&str; 11] = [
+    "pg_terminate_backend", "pg_cancel_backend", "pg_reload_conf", "pg_rotate_logfile", "pg_advisory",
+    "pg_read", "pg_ls_", "pg_notify", "lo_", "dblink", "set_config",
+];
+
+/// A bound query parameter. Statements refer to them as `$1`, `$2`, ...
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryParam {
+    Text(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Null,
+}
+
+impl TryFrom<&Value> for QueryParam {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(text) => Ok(QueryParam::Text(text.clone())),
+            Value::Number(n) => Ok(n.as_i64().map(QueryParam::Int).unwrap_or_else(|| QueryParam::Float(n.as_f64().unwrap_or(0.0)))),
+            Value::Bool(b) => Ok(QueryParam::Bool(*b)),
+            Value::Null => Ok(QueryParam::Null),
+            _ => Err("Parameters must be strings, numbers, booleans or null".to_string()),
+        }
+    }
+}
+
+/// Lowercase words of `sql` outside string literals, quoted identifiers and
+/// comments. Understands doubled quotes, backslash escapes in `E'...'`
+/// strings, `--` line comments and nested `/* */` block comments.
+fn words(sql: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        let starts_token = matches!(c, '\'' | '"') || (c == '-' && chars.peek() == Some(&'-')) || (c == '/' && chars.peek() == Some(&'*'));
+        if starts_token || !(c.is_ascii_alphanumeric() || c == '_') {
+            // An E right before the quote marks an escape string, not a word
+            let escapes = c == '\'' && current.eq_ignore_ascii_case("e");
+            if escapes {
+                current.clear();
+            } else if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            match c {
+                '\'' | '"' => {
+                    while let Some(next) = chars.next() {
+                        if escapes && next == '\\' {
+                            chars.next();
+                        } else if next == c {
+                            break;
+                        }
+                    }
+                }
+                '-' if starts_token => {
+                    for next in chars.by_ref() {
+                        if next == '\n' {
+                            break;
+                        }
+                    }
+                }
+                '/' if starts_token => {
+                    chars.next();
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match chars.next() {
+                            Some('*') if chars.peek() == Some(&'/') => {
+                                chars.next();
+                                depth -= 1;
+                            }
+                            Some('/') if chars.peek() == Some(&'*') => {
+                                chars.next();
+                                depth += 1;
+                            }
+                            Some(_) => {}
+                            None => break,
+                        }
+                    }
+                }
+                _ => {}
+            }
+        } else {
+            current.push(c.to_ascii_lowercase());
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// The statement to run, without surrounding whitespace or a trailing `;`,
+/// or why it was refused.
+pub fn validate(sql: &str) -> Result<&str, String> {
+    let sql = sql.trim();
+    let sql = sql.strip_suffix(';').unwrap_or(sql).trim_end();
+    if sql.is_empty() {
+        return Err("Query is empty".to_string());
+    }
+    if sql.contains(';') {
+        return Err("Only a single statement is allowed".to_string());
+    }
+
+    let words = words(sql);
+    if words.first().map(String::as_str) != Some("select") {
+        return Err("Only SELECT statements are allowed".to_string());
+    }
+    if let Some(word) = words.iter().find(|word| FORBIDDEN_KEYWORDS.contains(&word.as_str())) {
+        return Err(format!("`{}` is not allowed in a read-only query", word.to_uppercase()));
+    }
+    if let Some(word) = words
+        .iter()
+        .find(|word| FORBIDDEN_FUNCTION_PREFIXES.iter().any(|prefix| word.starts_with(prefix)))
+    {
+        return Err(format!("`{}` is not allowed in a read-only query", word));
+    }
+    Ok(sql)
+}
@@ -0,0 +1,66 @@
+//! Audit trail for support impersonation. Requests made with an impersonation
+//! token must belong to a live session (so admins can revoke them early) and
+//! are recorded in `audit_log` under both the impersonated user and the admin.
+
+use crate::db;
+use crate::models::{verify_jwt, Claims};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use sqlx::PgPool;
+
+/// Lifetime of an impersonation token.
+pub const IMPERSONATION_TTL_MINUTES: i64 = 15;
+
+/// JWT from the Authorization header, or `?token=` for WebSocket upgrades.
+fn request_token(req: &ServiceRequest) -> Option<String> {
+    if let Some(header) = req.headers().get(AUTHORIZATION).and_then(|h| h.to_str().ok()) {
+        return header.strip_prefix("Bearer ").map(str::to_string);
+    }
+    url::form_urlencoded::parse(req.query_string().as_bytes())
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Claims of the request's token if it is an impersonation token.
+fn impersonation_claims(req: &ServiceRequest) -> Option<Claims> {
+    let claims = verify_jwt(&request_token(req)?).ok()?;
+    claims.impersonated_by.map(|_| claims)
+}
+
+/// Middleware: reject revoked/expired impersonation sessions and audit every
+/// request made under one. Other requests pass through untouched.
+pub async fn impersonation_guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(claims) = impersonation_claims(&req) else {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    };
+    let Some(pool) = req.app_data::<web::Data<PgPool>>().cloned() else {
+        return Ok(req.into_response(HttpResponse::InternalServerError().json("Database unavailable")));
+    };
+    let admin_id = claims.impersonated_by.unwrap_or_default();
+    let session_id = claims.jti.clone().unwrap_or_default();
+
+    match db::is_impersonation_session_active(&pool, &session_id, admin_id, claims.sub).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Ok(req.into_response(HttpResponse::Unauthorized().json("Impersonation session has ended")));
+        }
+        Err(_) => {
+            return Ok(req.into_response(HttpResponse::InternalServerError().json("Failed to check impersonation session")));
+        }
+    }
+
+    let action = format!("{} {}", req.method(), req.path());
+    let response = next.call(req).await?;
+    let details = format!("session {}; status {}", session_id, response.status().as_u16());
+    if let Err(e) = db::record_audit_event(&pool, Some(claims.sub), Some(admin_id), &action, Some(&details)).await {
+        eprintln!("❌ Failed to audit impersonated request {}: {:?}", action, e);
+    }
+
+    Ok(response.map_into_boxed_body())
+}
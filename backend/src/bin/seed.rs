@@ -0,0 +1,189 @@
+//! Demo data for local development: `cargo run --bin seed [-- --vendors N --customers N --products N]`.
+//! Creates an admin, verified and pending vendors, customers, products across
+//! categories, and some orders and reviews through the regular `db` functions.
+//! Does nothing if the database has already been seeded, and refuses to run
+//! against anything that looks like a production database.
+
+use backend::db;
+use backend::models::Role;
+use sqlx::PgPool;
+
+/// Password of every seeded account.
+const SEED_PASSWORD: &str = "Seed#pass123";
+
+/// Username whose presence marks the database as already seeded.
+const SEED_ADMIN: &str = "seed_admin";
+
+const LOCATIONS: [&str; 5] = ["Nairobi", "Nakuru", "Kisumu", "Eldoret", "Thika"];
+
+/// (name, category, price in KSh, tags)
+const CATALOG: [(&str, &str, f64, &[&str]); 12] = [
+    ("Fresh Tomatoes", "Vegetables", 50.0, &["local"]),
+    ("Spinach Bundle", "Vegetables", 25.0, &["organic", "leafy"]),
+    ("Carrots", "Vegetables", 40.0, &["local"]),
+    ("Sukuma Wiki", "Vegetables", 20.0, &["leafy"]),
+    ("Bananas", "Fruit", 30.0, &["local"]),
+    ("Avocados", "Fruit", 80.0, &["organic"]),
+    ("Mangoes", "Fruit", 60.0, &["seasonal"]),
+    ("Fresh Milk", "Dairy", 65.0, &["farm-fresh"]),
+    ("Free-range Eggs", "Dairy", 15.0, &["farm-fresh"]),
+    ("Maize Flour", "Grains", 140.0, &["staple"]),
+    ("Rosecoco Beans", "Legumes", 120.0, &["staple"]),
+    ("Raw Honey", "Pantry", 350.0, &["organic"]),
+];
+
+struct SeedConfig {
+    vendors: usize,
+    customers: usize,
+    products_per_vendor: usize,
+}
+
+impl SeedConfig {
+    fn from_args() -> Result<Self, String> {
+        let mut config = SeedConfig { vendors: 3, customers: 5, products_per_vendor: 4 };
+        let mut args = std::env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let target = match flag.as_str() {
+                "--vendors" => &mut config.vendors,
+                "--customers" => &mut config.customers,
+                "--products" => &mut config.products_per_vendor,
+                other => return Err(format!("Unknown argument: {}", other)),
+            };
+            *target = args
+                .next()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| format!("{} needs a number", flag))?;
+        }
+        if config.vendors == 0 || config.customers == 0 {
+            return Err("--vendors and --customers must be at least 1".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// Refuse DSNs that mention production, and non-local hosts unless
+/// SEED_ALLOW_REMOTE=true.
+fn check_database_url(database_url: &str) -> Result<(), String> {
+    if database_url.to_lowercase().contains("prod") {
+        return Err("Refusing to seed a database that looks like production".to_string());
+    }
+    let url = url::Url::parse(database_url).map_err(|e| format!("Invalid DATABASE_URL: {}", e))?;
+    let local = match url.host_str() {
+        None | Some("") | Some("localhost") | Some("127.0.0.1") | Some("[::1]") => true,
+        // A unix socket directory given as ?host=/path
+        _ => false,
+    } || url.query_pairs().any(|(key, value)| key == "host" && value.starts_with('/'));
+    if !local && std::env::var("SEED_ALLOW_REMOTE").as_deref() != Ok("true") {
+        return Err(format!(
+            "Refusing to seed non-local database host {:?}; set SEED_ALLOW_REMOTE=true to override",
+            url.host_str().unwrap_or_default()
+        ));
+    }
+    Ok(())
+}
+
+async fn create_user(pool: &PgPool, username: &str, role: Role, location: &str, phone: &str) -> Result<i32, sqlx::Error> {
+    let email = format!("{}@example.com", username);
+    let user = db::create_user(pool, username, &email, SEED_PASSWORD, &role, None, Some(location), Some(phone)).await?;
+    Ok(user.id)
+}
+
+async fn seed(pool: &PgPool, config: &SeedConfig) -> Result<(), sqlx::Error> {
+    create_user(pool, SEED_ADMIN, Role::Admin, LOCATIONS[0], "0700000000").await?;
+
+    let mut vendor_ids = Vec::new();
+    for n in 1..=config.vendors {
+        let location = LOCATIONS[n % LOCATIONS.len()];
+        let vendor_id = create_user(pool, &format!("seed_vendor{}", n), Role::Vendor, location, &format!("07101{:05}", n)).await?;
+        db::update_user_verification(pool, vendor_id, true).await?;
+        vendor_ids.push(vendor_id);
+    }
+    // Waiting for an admin to review them
+    create_user(pool, "seed_pending_vendor", Role::Vendor, LOCATIONS[1], "0710200001").await?;
+
+    let mut customer_ids = Vec::new();
+    for n in 1..=config.customers {
+        let location = LOCATIONS[n % LOCATIONS.len()];
+        customer_ids.push(create_user(pool, &format!("seed_customer{}", n), Role::Customer, location, &format!("07203{:05}", n)).await?);
+    }
+
+    let mut product_ids = Vec::new();
+    for (v, vendor_id) in vendor_ids.iter().enumerate() {
+        for p in 0..config.products_per_vendor {
+            let (name, category, price, tags) = CATALOG[(v * config.products_per_vendor + p) % CATALOG.len()];
+            let product = db::create_product(pool, name, price, category, &format!("{} from seed_vendor{}", name, v + 1), 100, None, *vendor_id).await?;
+            let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+            db::set_product_tags(pool, product.id as i32, &tags).await?;
+            product_ids.push(product.id as i32);
+        }
+    }
+
+    // A spread of orders in different states, and reviews for delivered ones
+    const STATUSES: [&str; 4] = ["pending", "shipped", "delivered", "delivered"];
+    for (c, customer_id) in customer_ids.iter().enumerate() {
+        for (o, status) in STATUSES.iter().enumerate() {
+            let Some(&product_id) = product_ids.get((c * 3 + o * 5) % product_ids.len().max(1)) else { continue };
+            let address = LOCATIONS[(c + 1) % LOCATIONS.len()];
+            let order = db::create_shipping_order(pool, *customer_id, product_id, (o as i32 % 3) + 1, address).await?;
+            if *status != "pending" {
+                db::update_shipping_status(pool, order.id, status, Some(&format!("SEED{:06}", order.id))).await?;
+            }
+            if *status == "delivered" {
+                let rating = 3 + ((c + o) % 3) as i32;
+                // A customer reviews each product once; repeats are simply skipped
+                let _ = db::create_review(pool, *customer_id, product_id, rating, Some("Fresh and delivered on time")).await;
+            }
+        }
+    }
+
+    println!(
+        "Seeded 1 admin, {} verified + 1 pending vendors, {} customers and {} products. Password for all: {}",
+        vendor_ids.len(),
+        customer_ids.len(),
+        product_ids.len(),
+        SEED_PASSWORD
+    );
+    Ok(())
+}
+
+#[actix_web::main]
+async fn main() {
+    dotenv::dotenv().ok();
+
+    let config = match SeedConfig::from_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}\nUsage: seed [--vendors N] [--customers N] [--products N]", e);
+            std::process::exit(2);
+        }
+    };
+    let database_url = match std::env::var("DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("DATABASE_URL must be set to seed a database");
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = check_database_url(&database_url) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    let pool = db::init_db().await;
+    match db::find_user_by_username(&pool, SEED_ADMIN).await {
+        Ok(Some(_)) => {
+            println!("Database already seeded ({} exists); nothing to do", SEED_ADMIN);
+            return;
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Failed to check for existing seed data: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Err(e) = seed(&pool, &config).await {
+        eprintln!("Seeding failed: {:?}", e);
+        std::process::exit(1);
+    }
+}
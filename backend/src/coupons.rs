@@ -0,0 +1,120 @@
+//! Vendor coupons at checkout. A coupon belongs to one vendor and takes a
+//! percentage off that vendor's items only; other vendors' items in the same
+//! cart are never discounted by it. Each vendor group takes at most one coupon.
+
+use crate::db;
+use crate::models::CartItem;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+
+/// Discount one coupon gives a vendor group, stored on the payment transaction at checkout.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CouponDiscount {
+    pub vendor_id: i32,
+    pub coupon_id: i32,
+    pub code: String,
+    pub percent_off: f64,
+    pub amount: f64,
+}
+
+/// Why a coupon code can't be used on this cart.
+#[derive(Debug)]
+pub enum CouponError {
+    Unknown(String),
+    Inactive(String),
+    Expired(String),
+    UsedUp(String),
+    /// The coupon's vendor has nothing in the cart
+    NotApplicable(String),
+    /// Two codes for the same vendor
+    OnePerVendor { vendor_id: i32 },
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for CouponError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CouponError::Unknown(code) => write!(f, "Coupon {} does not exist", code),
+            CouponError::Inactive(code) => write!(f, "Coupon {} is no longer offered", code),
+            CouponError::Expired(code) => write!(f, "Coupon {} has expired", code),
+            CouponError::UsedUp(code) => write!(f, "Coupon {} has been used the maximum number of times", code),
+            CouponError::NotApplicable(code) => {
+                write!(f, "Coupon {} only applies to products from a vendor not in your cart", code)
+            }
+            CouponError::OnePerVendor { vendor_id } => {
+                write!(f, "Only one coupon can be used for vendor {}", vendor_id)
+            }
+            CouponError::Database(err) => write!(f, "Coupon database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CouponError {}
+
+impl From<sqlx::Error> for CouponError {
+    fn from(err: sqlx::Error) -> Self {
+        CouponError::Database(err)
+    }
+}
+
+/// Codes are matched without regard to case or surrounding spaces.
+pub fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+/// Discount on one line item, rounded to the cent.
+pub fn line_discount(line_total: f64, percent_off: f64) -> f64 {
+    (line_total * percent_off).round() / 100.0
+}
+
+/// Check `codes` against the cart and work out each vendor group's discount.
+/// A coupon only counts towards its own vendor's items.
+pub async fn apply(pool: &PgPool, items: &[CartItem], codes: &[String]) -> Result<Vec<CouponDiscount>, CouponError> {
+    let mut codes: Vec<String> = codes.iter().map(|code| normalize_code(code)).filter(|code| !code.is_empty()).collect();
+    codes.sort();
+    codes.dedup();
+    if codes.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let coupons = db::get_coupons_by_code(pool, &codes).await?;
+    let now = chrono::Utc::now();
+    let mut discounts: BTreeMap<i32, CouponDiscount> = BTreeMap::new();
+    for code in codes {
+        let coupon = coupons.iter().find(|c| c.code == code).ok_or_else(|| CouponError::Unknown(code.clone()))?;
+        if !coupon.active {
+            return Err(CouponError::Inactive(code));
+        }
+        if coupon.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(CouponError::Expired(code));
+        }
+        if coupon.max_uses.is_some_and(|max| coupon.times_used >= max) {
+            return Err(CouponError::UsedUp(code));
+        }
+        if discounts.contains_key(&coupon.vendor_id) {
+            return Err(CouponError::OnePerVendor { vendor_id: coupon.vendor_id });
+        }
+
+        let vendor_items: Vec<&CartItem> =
+            items.iter().filter(|item| item.product.vendor_id as i32 == coupon.vendor_id).collect();
+        if vendor_items.is_empty() {
+            return Err(CouponError::NotApplicable(code));
+        }
+        let amount: f64 = vendor_items
+            .iter()
+            .map(|item| line_discount(item.product.price * item.quantity as f64, coupon.percent_off))
+            .sum();
+        discounts.insert(
+            coupon.vendor_id,
+            CouponDiscount {
+                vendor_id: coupon.vendor_id,
+                coupon_id: coupon.id,
+                code,
+                percent_off: coupon.percent_off,
+                amount: (amount * 100.0).round() / 100.0,
+            },
+        );
+    }
+    Ok(discounts.into_values().collect())
+}
@@ -0,0 +1,72 @@
+//! Display-only currency conversion. Everything is priced and charged in KSh;
+//! other currencies are shown alongside using a rate from a `RateSource`.
+
+use crate::settings;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// Currency all prices are stored and charged in.
+pub const BASE_CURRENCY: &str = "KES";
+
+/// Supplies exchange rates. Implement this to plug in a live rates feed.
+pub trait RateSource: Send + Sync {
+    /// KSh per one unit of `currency` (e.g. 129.0 for USD), or None if unsupported.
+    fn kes_per_unit(&self, currency: &str) -> Option<f64>;
+}
+
+/// A fixed table of rates, keyed by upper-case ISO code.
+#[derive(Default)]
+pub struct StaticRates {
+    rates: HashMap<String, f64>,
+}
+
+impl StaticRates {
+    pub fn with_rate(mut self, currency: &str, kes_per_unit: f64) -> Self {
+        self.rates.insert(currency.to_uppercase(), kes_per_unit);
+        self
+    }
+
+    /// Rates configured by admins in the settings table.
+    pub async fn from_settings(pool: &PgPool) -> Self {
+        StaticRates::default().with_rate("USD", settings::get_f64(pool, settings::USD_EXCHANGE_RATE).await)
+    }
+}
+
+impl RateSource for StaticRates {
+    fn kes_per_unit(&self, currency: &str) -> Option<f64> {
+        self.rates
+            .get(&currency.to_uppercase())
+            .copied()
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+    }
+}
+
+/// A KSh amount shown in another currency.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DisplayPrice {
+    pub currency: String,
+    pub amount: f64,
+    /// KSh per unit of `currency` used for the conversion
+    pub rate: f64,
+}
+
+/// Convert a KSh amount at `kes_per_unit`, rounded to cents.
+pub fn convert(amount_kes: f64, kes_per_unit: f64) -> f64 {
+    (amount_kes / kes_per_unit * 100.0).round() / 100.0
+}
+
+/// `amount_kes` in `currency`, or None if the source has no rate for it.
+pub fn display_price(source: &dyn RateSource, amount_kes: f64, currency: &str) -> Option<DisplayPrice> {
+    let rate = source.kes_per_unit(currency)?;
+    Some(DisplayPrice {
+        currency: currency.to_uppercase(),
+        amount: convert(amount_kes, rate),
+        rate,
+    })
+}
+
+/// True for codes meaning the base currency, which need no conversion.
+pub fn is_base_currency(currency: &str) -> bool {
+    currency.eq_ignore_ascii_case(BASE_CURRENCY) || currency.eq_ignore_ascii_case("KSH")
+}
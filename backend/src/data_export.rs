@@ -0,0 +1,193 @@
+//! Personal data export (`GET /account/export`). The bundle is one JSON
+//! object holding the user's profile and everything they created or took
+//! part in. It is streamed section by section, row by row, so large histories
+//! never have to be held in memory.
+//!
+//! Other users appear only by username: message counterparts, vendors on
+//! orders and followed vendors. Customers on a vendor's sales are left out.
+
+use actix_web::web::Bytes;
+use futures_util::{Stream, TryStreamExt};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+
+/// Minimum minutes between two exports by the same user.
+pub const EXPORT_INTERVAL_MINUTES: i32 = 60;
+
+/// The profile, without the password hash.
+const PROFILE_SQL: &str = r#"
+    SELECT row_to_json(u)::text FROM (
+        SELECT id, username, email, secondary_email, role, profile_image, verified,
+               mpesa_number, mpesa_verified, payment_preference, location_string, latitude, longitude,
+               wallet_balance, pending_balance, email_notifications, notification_frequency, language,
+               verification_document_type, verification_submitted_at, verified_at
+        FROM users WHERE id = $1
+    ) u
+"#;
+
+/// Array sections of the bundle, in order. Each query yields one JSON object per row.
+const SECTIONS: [(&str, &str); 9] = [
+    (
+        "orders",
+        r#"
+        SELECT row_to_json(o)::text FROM (
+            SELECT s.id, s.order_id, s.product_id, p.name AS product_name, v.username AS vendor,
+                   s.quantity, s.total_amount, s.shipping_fee, s.discount, s.shipping_status, s.tracking_number,
+                   s.shipping_address, s.created_at, s.updated_at
+            FROM shipping_orders s
+            JOIN products p ON p.id = s.product_id
+            JOIN users v ON v.id = s.vendor_id
+            WHERE s.customer_id = $1
+            ORDER BY s.id
+        ) o
+        "#,
+    ),
+    (
+        "payments",
+        r#"
+        SELECT row_to_json(t)::text FROM (
+            SELECT id, checkout_request_id, mpesa_receipt_number, phone_number, amount::FLOAT8 AS amount,
+                   status, transaction_date, created_at
+            FROM payment_transactions WHERE user_id = $1
+            ORDER BY id
+        ) t
+        "#,
+    ),
+    (
+        "products",
+        r#"
+        SELECT row_to_json(p)::text FROM (
+            SELECT id, name, price, category, description, image, quantity, is_featured, removed_at
+            FROM products WHERE vendor_id = $1
+            ORDER BY id
+        ) p
+        "#,
+    ),
+    (
+        "sales",
+        r#"
+        SELECT row_to_json(s)::text FROM (
+            SELECT s.id, s.order_id, s.product_id, p.name AS product_name, s.quantity, s.total_amount,
+                   s.shipping_fee, s.shipping_status, s.created_at
+            FROM shipping_orders s
+            JOIN products p ON p.id = s.product_id
+            WHERE s.vendor_id = $1
+            ORDER BY s.id
+        ) s
+        "#,
+    ),
+    (
+        "reviews",
+        r#"
+        SELECT row_to_json(r)::text FROM (
+            SELECT r.id, r.product_id, p.name AS product_name, r.rating, r.comment, r.created_at, r.removed_at
+            FROM reviews r
+            JOIN products p ON p.id = r.product_id
+            WHERE r.customer_id = $1
+            ORDER BY r.id
+        ) r
+        "#,
+    ),
+    (
+        "messages",
+        r#"
+        SELECT row_to_json(m)::text FROM (
+            SELECT m.id,
+                   CASE WHEN m.sender_id = $1 THEN 'sent' ELSE 'received' END AS direction,
+                   other.username AS counterpart,
+                   m.content, m.attachment, m.is_read, m.created_at, m.updated_at
+            FROM messages m
+            JOIN users other ON other.id = CASE WHEN m.sender_id = $1 THEN m.receiver_id ELSE m.sender_id END
+            WHERE m.sender_id = $1 OR m.receiver_id = $1
+            ORDER BY m.id
+        ) m
+        "#,
+    ),
+    (
+        "follows",
+        r#"
+        SELECT row_to_json(f)::text FROM (
+            SELECT v.username AS vendor, f.created_at
+            FROM follows f
+            JOIN users v ON v.id = f.vendor_id
+            WHERE f.follower_id = $1
+            ORDER BY f.id
+        ) f
+        "#,
+    ),
+    (
+        "wishlist",
+        r#"
+        SELECT row_to_json(w)::text FROM (
+            SELECT w.product_id, p.name AS product_name, w.created_at
+            FROM wishlist_items w
+            JOIN products p ON p.id = w.product_id
+            WHERE w.user_id = $1
+            ORDER BY w.id
+        ) w
+        "#,
+    ),
+    (
+        "wallet_history",
+        r#"
+        SELECT row_to_json(l)::text FROM (
+            SELECT id, entry_type, amount, reference, created_at
+            FROM wallet_ledger WHERE user_id = $1
+            ORDER BY id
+        ) l
+        "#,
+    ),
+];
+
+type Chunk = Result<Bytes, std::io::Error>;
+
+/// The user's export as a stream of JSON chunks. A database error part way
+/// through ends the stream with an error, so the client sees a broken
+/// download rather than a truncated bundle that looks complete.
+pub fn export_stream(pool: PgPool, user_id: i32) -> impl Stream<Item = Chunk> + 'static {
+    let (tx, rx) = mpsc::channel::<Chunk>(32);
+    tokio::spawn(async move {
+        if let Err(e) = write_export(&pool, user_id, &tx).await {
+            eprintln!("❌ Data export for user {} failed: {:?}", user_id, e);
+            let _ = tx.send(Err(std::io::Error::other("export failed"))).await;
+        }
+    });
+    futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) })
+}
+
+/// Send `text` on; false once the client has gone away.
+async fn send(tx: &mpsc::Sender<Chunk>, text: impl Into<String>) -> bool {
+    tx.send(Ok(Bytes::from(text.into()))).await.is_ok()
+}
+
+async fn write_export(pool: &PgPool, user_id: i32, tx: &mpsc::Sender<Chunk>) -> Result<(), sqlx::Error> {
+    let profile: String = sqlx::query_scalar(PROFILE_SQL).bind(user_id).fetch_one(pool).await?;
+    let header = format!(
+        "{{\"exported_at\":{},\"profile\":{}",
+        serde_json::Value::String(chrono::Utc::now().to_rfc3339()),
+        profile
+    );
+    if !send(tx, header).await {
+        return Ok(());
+    }
+
+    for (name, sql) in SECTIONS {
+        if !send(tx, format!(",\"{}\":[", name)).await {
+            return Ok(());
+        }
+        let mut rows = sqlx::query_scalar::<_, String>(sql).bind(user_id).fetch(pool);
+        let mut first = true;
+        while let Some(row) = rows.try_next().await? {
+            let chunk = if first { row } else { format!(",{}", row) };
+            first = false;
+            if !send(tx, chunk).await {
+                return Ok(());
+            }
+        }
+        if !send(tx, "]").await {
+            return Ok(());
+        }
+    }
+    send(tx, "}").await;
+    Ok(())
+}
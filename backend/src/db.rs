@@ -1,5 +1,8 @@
 use sqlx::{PgPool, postgres::PgPoolOptions, Row};
-use crate::models::{User, Role, CartItem, Product};
+use crate::models::{User, Role, CartItem, Product, ProductAttributes, ProductImage};
+use crate::mpesa::PaymentStatus;
+use crate::admin_query::QueryParam;
+use futures_util::TryStreamExt;
 use bcrypt::{hash, verify, DEFAULT_COST};
 
 // Database helpers: initialize connection and provide CRUD operations used
@@ -11,7 +14,7 @@ pub async fn init_db() -> PgPool {
     let database_url = std::env::var("DATABASE_URL")
         .unwrap_or_else(|_| "postgresql:///farmers_market?user=wangs".to_string());
     println!("Connecting to database: {}", database_url);
-    let pool = PgPoolOptions::new()
+    let pool = pool_options()
         .max_connections(5)
         .acquire_timeout(std::time::Duration::from_secs(10))
         .connect(&database_url)
@@ -20,6 +23,25 @@ pub async fn init_db() -> PgPool {
 
     println!("✅ Database connected successfully!");
     
+    init_schema(&pool).await;
+
+    pool
+}
+
+/// Pool options shared by the server and tests. Every connection runs in UTC,
+/// so timestamps are stored and printed in UTC whatever the server's zone.
+pub fn pool_options() -> PgPoolOptions {
+    PgPoolOptions::new().after_connect(|conn, _meta| {
+        Box::pin(async move {
+            sqlx::Executor::execute(conn, "SET TIME ZONE 'UTC'").await?;
+            Ok(())
+        })
+    })
+}
+
+/// Create all tables and apply additive column migrations on `pool`.
+/// Safe to run repeatedly; also used to provision fresh test databases.
+pub async fn init_schema(pool: &PgPool) {
     // NOTE: Schema creation/migration code has been commented out since tables already exist.
     // If you need to recreate the schema, run the SQL scripts manually or uncomment below.
     
@@ -28,7 +50,7 @@ pub async fn init_db() -> PgPool {
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS users (
-            id INTEGER PRIMARY KEY,
+            id SERIAL PRIMARY KEY,
             username TEXT UNIQUE NOT NULL,
             email TEXT UNIQUE NOT NULL,
             password_hash TEXT NOT NULL,
@@ -41,74 +63,129 @@ pub async fn init_db() -> PgPool {
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to create users table");
 
-    // Add unique constraint for mpesa_number if not exists
-    let _ = sqlx::query(
-        "ALTER TABLE users ADD CONSTRAINT unique_mpesa_number UNIQUE (mpesa_number)"
-    )
-    .execute(&pool)
-    .await;
-    
     // Add unique constraint for email if not exists (redundant but safe)
     let _ = sqlx::query(
         "ALTER TABLE users ADD CONSTRAINT unique_email UNIQUE (email)"
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     // Alter table to add verification_document column if it doesn't exist (for existing databases)
     let _ = sqlx::query(
         "ALTER TABLE users ADD COLUMN IF NOT EXISTS verification_document TEXT"
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     let _ = sqlx::query(
         "ALTER TABLE users ADD COLUMN IF NOT EXISTS verification_submitted_at TIMESTAMP WITH TIME ZONE"
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     let _ = sqlx::query(
         "ALTER TABLE users ADD COLUMN IF NOT EXISTS mpesa_number VARCHAR(20)"
     )
-    .execute(&pool)
+    .execute(pool)
+    .await;
+
+    // Add unique constraint for mpesa_number if not exists (must follow the column)
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD CONSTRAINT unique_mpesa_number UNIQUE (mpesa_number)"
+    )
+    .execute(pool)
+    .await;
+
+    // Set once the user confirms an OTP sent for their current mpesa_number
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS mpesa_verified BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
     .await;
 
     let _ = sqlx::query(
         "ALTER TABLE users ADD COLUMN IF NOT EXISTS payment_preference VARCHAR(50) DEFAULT 'monthly'" // 'after_order' or 'monthly'
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     let _ = sqlx::query(
         "ALTER TABLE users ADD COLUMN IF NOT EXISTS secondary_email VARCHAR(255)"
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     // Add location column for manual location input
     let _ = sqlx::query(
         "ALTER TABLE users ADD COLUMN IF NOT EXISTS location_string TEXT"
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     // Add wallet_balance column for vendor earnings
     let _ = sqlx::query(
         "ALTER TABLE users ADD COLUMN IF NOT EXISTS wallet_balance FLOAT8 NOT NULL DEFAULT 0.0"
     )
-    .execute(&pool)
+    .execute(pool)
+    .await;
+
+    // Last line of defence against overdrawn wallets (errors harmlessly once it exists)
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD CONSTRAINT wallet_balance_non_negative CHECK (wallet_balance >= 0)"
+    )
+    .execute(pool)
+    .await;
+
+    // Earnings of vendors paid monthly, held until the next payout sweep
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS pending_balance FLOAT8 NOT NULL DEFAULT 0.0"
+    )
+    .execute(pool)
     .await;
 
     // Add verification_rejected_reason column for tracking rejection reasons
     let _ = sqlx::query(
         "ALTER TABLE users ADD COLUMN IF NOT EXISTS verification_rejected_reason TEXT"
     )
-    .execute(&pool)
+    .execute(pool)
+    .await;
+
+    // Set when a vendor uploads a new document after being rejected; cleared by the next decision
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS verification_resubmitted BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await;
+
+    // MIME type detected from the verification document's contents
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS verification_document_type TEXT"
+    )
+    .execute(pool)
+    .await;
+
+    // When the vendor was approved; verification documents are purged a
+    // retention period after this unless an admin chose to retain them
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS verified_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS verification_document_retained BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await;
+    // Vendors approved before this was tracked start their retention window now
+    let _ = sqlx::query(
+        "UPDATE users SET verified_at = CURRENT_TIMESTAMP
+         WHERE role = 'Vendor' AND verified = TRUE AND verified_at IS NULL AND verification_document IS NOT NULL"
+    )
+    .execute(pool)
     .await;
 
     // Create products table if not exists
@@ -126,7 +203,7 @@ pub async fn init_db() -> PgPool {
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to create products table");
 
@@ -145,7 +222,7 @@ pub async fn init_db() -> PgPool {
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to create reviews table");
 
@@ -159,7 +236,7 @@ pub async fn init_db() -> PgPool {
             vendor_id INTEGER NOT NULL REFERENCES users(id),
             quantity INTEGER NOT NULL,
             total_amount FLOAT8 NOT NULL,
-            shipping_status VARCHAR(50) DEFAULT 'pending', -- 'pending', 'shipped', 'delivered', 'cancelled'
+            shipping_status VARCHAR(50) DEFAULT 'pending', -- 'pending', 'processing', 'shipped', 'delivered', 'cancelled', 'declined'
             tracking_number VARCHAR(255),
             shipping_address TEXT,
             created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
@@ -167,7 +244,7 @@ pub async fn init_db() -> PgPool {
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to create shipping_orders table");
 
@@ -184,7 +261,7 @@ pub async fn init_db() -> PgPool {
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to create cart_items table");
 
@@ -202,7 +279,7 @@ pub async fn init_db() -> PgPool {
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to create messages table");
 
@@ -218,7 +295,7 @@ pub async fn init_db() -> PgPool {
         END $$;
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to add updated_at column to messages table");
 
@@ -234,7 +311,7 @@ pub async fn init_db() -> PgPool {
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to create follows table");
 
@@ -257,7 +334,7 @@ pub async fn init_db() -> PgPool {
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to create payment_transactions table");
 
@@ -278,7 +355,7 @@ pub async fn init_db() -> PgPool {
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to create vendor_reports table");
 
@@ -286,7 +363,7 @@ pub async fn init_db() -> PgPool {
     let _ = sqlx::query(
         "ALTER TABLE products ADD COLUMN IF NOT EXISTS quantity INTEGER NOT NULL DEFAULT 0"
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     // Create password_reset_codes table if not exists
@@ -302,554 +379,5683 @@ pub async fn init_db() -> PgPool {
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to create password_reset_codes table");
 
     // Drop old index and create new one for username
     let _ = sqlx::query("DROP INDEX IF EXISTS idx_password_reset_phone_expires")
-        .execute(&pool)
+        .execute(pool)
         .await;
     
     // Create index on username and expires_at for efficient lookups
     let _ = sqlx::query(
         "CREATE INDEX IF NOT EXISTS idx_password_reset_username_expires ON password_reset_codes(username, expires_at)"
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     // Safe migration: Add username column if migrating from phone_number
     let _ = sqlx::query(
         "ALTER TABLE password_reset_codes ADD COLUMN IF NOT EXISTS username VARCHAR(50)"
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
     
     // Drop phone_number column if it exists (cleanup for existing tables)
     let _ = sqlx::query(
         "ALTER TABLE password_reset_codes DROP COLUMN IF EXISTS phone_number"
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
-    
-    pool
-}
-
-/// Create a new user and return the created `User` record.
-/// Passwords are hashed before insertion.
-pub async fn create_user(pool: &PgPool, username: &str, email: &str, password: &str, role: &Role, profile_image: Option<&str>, location_string: Option<&str>, mpesa_number: Option<&str>) -> Result<User, sqlx::Error> {
-    let password_hash = hash(password, DEFAULT_COST).map_err(|_| sqlx::Error::RowNotFound)?;
-    let role_str = match role {
-        Role::Admin => "Admin",
-        Role::Customer => "Customer",
-        Role::Vendor => "Vendor",
-    };
 
-    // Customers are auto-verified, Vendors need admin verification when they upload profile image
-    let verified = matches!(role, Role::Customer);
+    // Soft-delete marker; deleted accounts keep their row so order history still resolves
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await;
 
-    let row = if let Some(image) = profile_image {
-        sqlx::query(
-            r#"
-            INSERT INTO users (username, email, password_hash, role, profile_image, verified, location_string, mpesa_number)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-            RETURNING id, username, email, role, profile_image, verified, location_string, mpesa_number
-            "#,
-        )
-        .bind(username)
-        .bind(email)
-        .bind(password_hash)
-        .bind(role_str)
-        .bind(image)
-        .bind(verified)  // Customers auto-verified, Vendors need admin verification
-        .bind(location_string)
-        .bind(mpesa_number)
-        .fetch_one(pool)
-        .await?
-    } else {
-        sqlx::query(
-            r#"
-            INSERT INTO users (username, email, password_hash, role, verified, location_string, mpesa_number)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
-            RETURNING id, username, email, role, profile_image, verified, location_string, mpesa_number
-            "#,
-        )
-        .bind(username)
-        .bind(email)
-        .bind(password_hash)
-        .bind(role_str)
-        .bind(verified)  // Customers auto-verified, Vendors need admin verification
-        .bind(location_string)
-        .bind(mpesa_number)
-        .fetch_one(pool)
-        .await?
-    };
+    // Coordinates captured by the location update endpoint
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS latitude FLOAT8"
+    )
+    .execute(pool)
+    .await;
 
-    let user = User {
-        id: row.try_get(0)?,
-        username: row.try_get(1)?,
-        email: row.try_get(2)?,
-        role: match row.try_get::<String, _>(3)?.as_str() {
-            "Admin" => Role::Admin,
-            "Customer" => Role::Customer,
-            "Vendor" => Role::Vendor,
-            _ => Role::Customer,
-        },
-        profile_image: row.try_get(4)?,
-        verified: row.try_get(5)?,
-        banned: false,
-        verification_document: None,
-        verification_rejected_reason: None,
-        secondary_email: None,
-        mpesa_number: row.try_get(7)?,
-        payment_preference: None,
-        location_string: row.try_get(6).ok(),
-        wallet_balance: 0.0,
-    };
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS longitude FLOAT8"
+    )
+    .execute(pool)
+    .await;
 
-    Ok(user)
-}
+    // Delivery verification / escrow columns on shipping orders
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS customer_verified BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await;
 
-/// Verify credentials and return the authenticated `User` record.
-pub async fn authenticate_user(pool: &PgPool, username: &str, password: &str) -> Result<User, sqlx::Error> {
-    let row = sqlx::query(
-        r#"
-        SELECT id, username, email, password_hash, role, profile_image, verified, banned, secondary_email, mpesa_number, payment_preference, location_string, wallet_balance, verification_rejected_reason
-        FROM users
-        WHERE username = $1
-        "#,
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS payment_released BOOLEAN NOT NULL DEFAULT FALSE"
     )
-    .bind(username)
-    .fetch_one(pool)
-    .await?;
+    .execute(pool)
+    .await;
 
-    let stored_hash: String = row.try_get(3)?;
-    let is_valid = verify(password, &stored_hash).map_err(|_| sqlx::Error::RowNotFound)?;
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS verification_requested_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await;
 
-    if !is_valid {
-        return Err(sqlx::Error::RowNotFound);
-    }
+    let _ = sqlx::query(
+        "ALTER TABLE cart_items ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP"
+    )
+    .execute(pool)
+    .await;
 
-    // Check if user is banned
-    let banned: bool = row.try_get(7)?;
-    if banned {
-        return Err(sqlx::Error::RowNotFound);
-    }
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS email_notifications BOOLEAN NOT NULL DEFAULT TRUE"
+    )
+    .execute(pool)
+    .await;
 
-    let user = User {
-        id: row.try_get(0)?,
-        username: row.try_get(1)?,
-        email: row.try_get(2)?,
-        role: match row.try_get::<String, _>(4)?.as_str() {
-            "Admin" => Role::Admin,
-            "Customer" => Role::Customer,
-            "Vendor" => Role::Vendor,
-            _ => Role::Customer,
-        },
-        profile_image: row.try_get(5)?,
-        verified: row.try_get(6)?,
-        banned: row.try_get(7)?,
-        verification_document: None,
-        verification_rejected_reason: row.try_get(13)?,
-        secondary_email: row.try_get(8)?,
-        mpesa_number: row.try_get(9)?,
-        payment_preference: row.try_get(10)?,
-        location_string: row.try_get(11)?,
-        wallet_balance: row.try_get(12).unwrap_or(0.0),
-    };
+    // 'instant', 'hourly' or 'daily' (see `digests`)
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS notification_frequency VARCHAR(10) NOT NULL DEFAULT 'instant'"
+    )
+    .execute(pool)
+    .await;
 
-    Ok(user)
-}
+    // Language of the user's emails (see `email_templates`)
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS language VARCHAR(10) NOT NULL DEFAULT 'en'"
+    )
+    .execute(pool)
+    .await;
 
-/// Fetch cart items for a user, including product details.
-pub async fn get_cart_items(pool: &PgPool, user_id: i32) -> Result<Vec<CartItem>, sqlx::Error> {
-    let rows = sqlx::query(
-        r#"
-        SELECT
-            ci.id, ci.user_id, ci.product_id, ci.quantity,
-            p.id as p_id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
-        FROM cart_items ci
-        JOIN products p ON ci.product_id = p.id
-        WHERE ci.user_id = $1
-        "#,
+    // Smallest subtotal a vendor accepts per order; 0 means no minimum
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS min_order_value FLOAT8 NOT NULL DEFAULT 0"
     )
-    .bind(user_id)
-    .fetch_all(pool)
-    .await?;
+    .execute(pool)
+    .await;
 
-    let mut cart_items = Vec::new();
-    for row in rows {
+    // Holiday mode: a paused vendor's products are hidden from listings and can't be
+    // added to carts, while their existing orders carry on
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS is_paused BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await;
+
+    // Vendors who only take new conversations from customers who have bought from them
+    let _ = sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS buyers_only_messages BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await;
+
+    let _ = sqlx::query(
+        "ALTER TABLE products ADD COLUMN IF NOT EXISTS is_featured BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await;
+
+    let _ = sqlx::query(
+        "ALTER TABLE products ADD COLUMN IF NOT EXISTS featured_until TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await;
+
+    // Soft deletion by moderators: removed products and reviews stay for
+    // existing orders and the audit trail but are hidden everywhere else
+    let _ = sqlx::query(
+        "ALTER TABLE products ADD COLUMN IF NOT EXISTS removed_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query(
+        "ALTER TABLE reviews ADD COLUMN IF NOT EXISTS removed_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await;
+
+    // Set when automated moderation holds a listing for admin review; flagged
+    // products are hidden from shoppers until an admin approves them
+    let _ = sqlx::query(
+        "ALTER TABLE products ADD COLUMN IF NOT EXISTS flagged_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query(
+        "ALTER TABLE products ADD COLUMN IF NOT EXISTS flag_reason TEXT"
+    )
+    .execute(pool)
+    .await;
+
+    // When a product was listed; products from before this column count as listed at migration
+    let _ = sqlx::query(
+        "ALTER TABLE products ADD COLUMN IF NOT EXISTS created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()"
+    )
+    .execute(pool)
+    .await;
+
+    // Structured product details shoppers filter and compare on
+    for column in [
+        "unit TEXT",
+        "weight_grams INTEGER",
+        "origin TEXT",
+        "is_organic BOOLEAN NOT NULL DEFAULT FALSE",
+        "harvest_date DATE",
+    ] {
+        let _ = sqlx::query(&format!("ALTER TABLE products ADD COLUMN IF NOT EXISTS {}", column))
+            .execute(pool)
+            .await;
+    }
+
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS shipping_fee FLOAT8 NOT NULL DEFAULT 0"
+    )
+    .execute(pool)
+    .await;
+
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS shipping_option_id INTEGER"
+    )
+    .execute(pool)
+    .await;
+
+    // Optional image shared in chat, stored as a data URL like other images
+    let _ = sqlx::query(
+        "ALTER TABLE messages ADD COLUMN IF NOT EXISTS attachment TEXT"
+    )
+    .execute(pool)
+    .await;
+
+    // When the recipient's socket acknowledged the message
+    let _ = sqlx::query(
+        "ALTER TABLE messages ADD COLUMN IF NOT EXISTS delivered_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await;
+
+    // Payment that created the order, for the M-Pesa receipt on invoices
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS payment_transaction_id INTEGER REFERENCES payment_transactions(id) ON DELETE SET NULL"
+    )
+    .execute(pool)
+    .await;
+
+    // An order groups the shipping orders bought together; each shipping order is
+    // one line item with its own fulfillment status
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS orders (
+            id SERIAL PRIMARY KEY,
+            customer_id INTEGER NOT NULL REFERENCES users(id),
+            payment_transaction_id INTEGER REFERENCES payment_transactions(id) ON DELETE SET NULL,
+            shipping_address TEXT,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create orders table");
+
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS order_id INTEGER REFERENCES orders(id)"
+    )
+    .execute(pool)
+    .await;
+
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS idx_shipping_orders_order ON shipping_orders (order_id)")
+        .execute(pool)
+        .await;
+
+    // Give shipping orders from before orders existed a parent: one per payment
+    // for those paid together, otherwise one each
+    sqlx::query(
+        r#"
+        DO $$
+        DECLARE
+            grouped RECORD;
+            new_order_id INTEGER;
+        BEGIN
+            FOR grouped IN
+                SELECT MIN(customer_id) AS customer_id, payment_transaction_id,
+                       MIN(shipping_address) AS shipping_address, MIN(created_at) AS created_at
+                FROM shipping_orders
+                WHERE order_id IS NULL AND payment_transaction_id IS NOT NULL
+                GROUP BY payment_transaction_id
+            LOOP
+                INSERT INTO orders (customer_id, payment_transaction_id, shipping_address, created_at)
+                VALUES (grouped.customer_id, grouped.payment_transaction_id, grouped.shipping_address, grouped.created_at)
+                RETURNING id INTO new_order_id;
+                UPDATE shipping_orders SET order_id = new_order_id
+                WHERE order_id IS NULL AND payment_transaction_id = grouped.payment_transaction_id;
+            END LOOP;
+
+            FOR grouped IN
+                SELECT id, customer_id, shipping_address, created_at FROM shipping_orders WHERE order_id IS NULL
+            LOOP
+                INSERT INTO orders (customer_id, shipping_address, created_at)
+                VALUES (grouped.customer_id, grouped.shipping_address, grouped.created_at)
+                RETURNING id INTO new_order_id;
+                UPDATE shipping_orders SET order_id = new_order_id WHERE id = grouped.id;
+            END LOOP;
+        END $$
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to migrate shipping orders into orders");
+
+    // Set once a cancelled order's quantity has gone back into stock
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS inventory_restocked BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await;
+
+    // Set when the vendor accepts an order, moving it from 'pending' to 'processing'
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS accepted_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await;
+
+    // JSON list of per-vendor shipping charges quoted at checkout
+    let _ = sqlx::query(
+        "ALTER TABLE payment_transactions ADD COLUMN IF NOT EXISTS shipping_charges TEXT"
+    )
+    .execute(pool)
+    .await;
+
+    // X-Request-Id of the checkout that started the payment, for tracing callbacks
+    let _ = sqlx::query(
+        "ALTER TABLE payment_transactions ADD COLUMN IF NOT EXISTS request_id VARCHAR(128)"
+    )
+    .execute(pool)
+    .await;
+
+    // Checkout request id of the first STK push of a payment attempt, set on resent prompts
+    let _ = sqlx::query(
+        "ALTER TABLE payment_transactions ADD COLUMN IF NOT EXISTS attempt_id VARCHAR(255)"
+    )
+    .execute(pool)
+    .await;
+
+    // Stock held for a checkout until its payment completes, fails or the hold expires.
+    // Held units are already taken out of products.quantity.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS stock_reservations (
+            id SERIAL PRIMARY KEY,
+            checkout_request_id VARCHAR(255) NOT NULL,
+            product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+            quantity INTEGER NOT NULL,
+            status VARCHAR(20) NOT NULL DEFAULT 'held',
+            expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create stock_reservations table");
+
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS idx_stock_reservations_checkout ON stock_reservations (checkout_request_id)")
+        .execute(pool)
+        .await;
+    // Customer who checked out, so a new checkout can release their earlier holds
+    let _ = sqlx::query(
+        "ALTER TABLE stock_reservations ADD COLUMN IF NOT EXISTS user_id INTEGER REFERENCES users(id) ON DELETE CASCADE"
+    )
+    .execute(pool)
+    .await;
+
+    // Runtime-configurable key/value settings (see `settings` module)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS settings (
+            key VARCHAR(100) PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create settings table");
+
+    // Settings that were editable but never read by anything
+    let _ = sqlx::query(
+        "DELETE FROM settings WHERE key IN ('commission_rate', 'auto_release_days', 'max_order_quantity', 'max_order_amount')"
+    )
+    .execute(pool)
+    .await;
+
+    // One-time codes confirming ownership of an M-Pesa number
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS phone_verification_codes (
+            id SERIAL PRIMARY KEY,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            phone_number VARCHAR(20) NOT NULL,
+            verification_code VARCHAR(10) NOT NULL,
+            expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            used BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create phone_verification_codes table");
+    // Wrong guesses at the current code; it's invalidated after PHONE_CODE_MAX_ATTEMPTS
+    let _ = sqlx::query(
+        "ALTER TABLE phone_verification_codes ADD COLUMN IF NOT EXISTS failed_attempts INTEGER NOT NULL DEFAULT 0"
+    )
+    .execute(pool)
+    .await;
+
+    // One-time codes unlocking the admin SQL console, emailed to the admin
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS admin_query_codes (
+            id SERIAL PRIMARY KEY,
+            admin_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            verification_code VARCHAR(10) NOT NULL,
+            expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            used BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create admin_query_codes table");
+
+    // Wrong guesses at the current code; it's invalidated after admin_query::CODE_MAX_ATTEMPTS
+    let _ = sqlx::query(
+        "ALTER TABLE admin_query_codes ADD COLUMN IF NOT EXISTS failed_attempts INTEGER NOT NULL DEFAULT 0"
+    )
+    .execute(pool)
+    .await;
+
+    // Products a customer has saved for later
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS wishlist_items (
+            id SERIAL PRIMARY KEY,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(user_id, product_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create wishlist_items table");
+
+    // When each customer was last reminded about an abandoned cart
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS cart_reminders (
+            user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+            last_reminded_at TIMESTAMP WITH TIME ZONE NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create cart_reminders table");
+
+    // Free-form product tags ("organic", "local", ...), stored normalized
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS product_tags (
+            product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+            tag VARCHAR(50) NOT NULL,
+            PRIMARY KEY (product_id, tag)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create product_tags table");
+
+    // A vendor's public response to a review of their product (one per review)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS review_replies (
+            id SERIAL PRIMARY KEY,
+            review_id INTEGER NOT NULL UNIQUE REFERENCES reviews(id) ON DELETE CASCADE,
+            vendor_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            content TEXT NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create review_replies table");
+
+    // One helpful / not-helpful vote per user per review
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS review_votes (
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            review_id INTEGER NOT NULL REFERENCES reviews(id) ON DELETE CASCADE,
+            helpful BOOLEAN NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (user_id, review_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create review_votes table");
+
+    // Vendor appeals against a ban or report-based suspension
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS appeals (
+            id SERIAL PRIMARY KEY,
+            vendor_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            message TEXT NOT NULL,
+            status VARCHAR(20) NOT NULL DEFAULT 'pending',
+            admin_notes TEXT,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            resolved_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create appeals table");
+
+    // Emails whose first send failed transiently, retried with backoff (see `email_queue`).
+    // status: 'pending' (waiting for next_attempt_at), 'sent', 'failed' (out of attempts),
+    // or 'expired' (its content, e.g. a one-time code, stopped working before it was sent)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS email_outbox (
+            id SERIAL PRIMARY KEY,
+            recipient VARCHAR(255) NOT NULL,
+            subject TEXT NOT NULL,
+            body TEXT NOT NULL,
+            html TEXT NOT NULL,
+            status VARCHAR(10) NOT NULL DEFAULT 'pending',
+            attempts INTEGER NOT NULL DEFAULT 1,
+            last_error TEXT NOT NULL,
+            next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            sent_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create email_outbox table");
+    // When set, the email is dropped instead of retried past this time
+    let _ = sqlx::query("ALTER TABLE email_outbox ADD COLUMN IF NOT EXISTS expires_at TIMESTAMP WITH TIME ZONE")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_email_outbox_due ON email_outbox(next_attempt_at) WHERE status = 'pending'"
+    )
+    .execute(pool)
+    .await;
+
+    // M-Pesa callbacks that couldn't be applied, kept with their payload so admins can retry them
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS callback_failures (
+            id SERIAL PRIMARY KEY,
+            checkout_request_id VARCHAR(255) NOT NULL,
+            payload TEXT NOT NULL,
+            error TEXT NOT NULL,
+            retry_count INTEGER NOT NULL DEFAULT 0,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            last_retried_at TIMESTAMP WITH TIME ZONE,
+            resolved_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create callback_failures table");
+
+    // Vendor-defined delivery options; deactivated rather than deleted so past orders keep their reference
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS shipping_options (
+            id SERIAL PRIMARY KEY,
+            vendor_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            name VARCHAR(100) NOT NULL,
+            method VARCHAR(20) NOT NULL,
+            fee FLOAT8 NOT NULL DEFAULT 0,
+            free_threshold FLOAT8,
+            per_km_rate FLOAT8,
+            active BOOLEAN NOT NULL DEFAULT TRUE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create shipping_options table");
+
+    // Vendor discount codes; they only ever discount the owning vendor's products
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS coupons (
+            id SERIAL PRIMARY KEY,
+            vendor_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            code VARCHAR(32) NOT NULL UNIQUE,
+            percent_off FLOAT8 NOT NULL CHECK (percent_off > 0 AND percent_off <= 100),
+            max_uses INTEGER,
+            times_used INTEGER NOT NULL DEFAULT 0,
+            expires_at TIMESTAMP WITH TIME ZONE,
+            active BOOLEAN NOT NULL DEFAULT TRUE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create coupons table");
+
+    // JSON list of per-vendor coupon discounts applied at checkout
+    let _ = sqlx::query(
+        "ALTER TABLE payment_transactions ADD COLUMN IF NOT EXISTS coupon_discounts TEXT"
+    )
+    .execute(pool)
+    .await;
+
+    // Coupon discount taken off a line item; total_amount is already net of it
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS discount FLOAT8 NOT NULL DEFAULT 0"
+    )
+    .execute(pool)
+    .await;
+
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS coupon_id INTEGER REFERENCES coupons(id) ON DELETE SET NULL"
+    )
+    .execute(pool)
+    .await;
+
+    // VAT rate overrides per product category (lowercased); a rate of 0 makes the category exempt
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS vat_rates (
+            category VARCHAR(100) PRIMARY KEY,
+            rate FLOAT8 NOT NULL,
+            updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create vat_rates table");
+
+    // VAT included in a line item's (net of discount) total_amount, worked out at payment
+    let _ = sqlx::query(
+        "ALTER TABLE shipping_orders ADD COLUMN IF NOT EXISTS tax_amount FLOAT8 NOT NULL DEFAULT 0"
+    )
+    .execute(pool)
+    .await;
+
+    // Every shipping status an order moves to, with when; feeds vendor fulfilment metrics
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS shipping_status_history (
+            id SERIAL PRIMARY KEY,
+            shipping_order_id INTEGER NOT NULL REFERENCES shipping_orders(id) ON DELETE CASCADE,
+            status VARCHAR(50) NOT NULL,
+            changed_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create shipping_status_history table");
+
+    let _ = sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_shipping_status_history_order ON shipping_status_history(shipping_order_id)"
+    )
+    .execute(pool)
+    .await;
+
+    // One row per monthly payout sweep (period = 'YYYY-MM') so each month is swept once
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS payout_sweeps (
+            period VARCHAR(7) PRIMARY KEY,
+            swept_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create payout_sweeps table");
+
+    // User-to-user blocks; either direction stops messaging between the pair
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS blocks (
+            blocker_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            blocked_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (blocker_id, blocked_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create blocks table");
+
+    // Append-only record of sensitive actions; impersonated requests carry both user ids
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS audit_log (
+            id SERIAL PRIMARY KEY,
+            actor_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+            impersonated_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+            action VARCHAR(255) NOT NULL,
+            details TEXT,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create audit_log table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS impersonation_sessions (
+            id VARCHAR(64) PRIMARY KEY,
+            admin_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            revoked_at TIMESTAMP WITH TIME ZONE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create impersonation_sessions table");
+
+    // Signed-in devices: one row per login, holding the hash of its current refresh token
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS user_sessions (
+            id SERIAL PRIMARY KEY,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            refresh_token_hash VARCHAR(64) NOT NULL UNIQUE,
+            user_agent TEXT,
+            ip_address VARCHAR(64),
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            last_used_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            revoked_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create user_sessions table");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_user_sessions_user ON user_sessions (user_id)")
+        .execute(pool)
+        .await
+        .expect("Failed to create user_sessions index");
+
+    // Product page views for vendor analytics; viewer_ip stands in for anonymous viewers when debouncing
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS product_views (
+            id SERIAL PRIMARY KEY,
+            product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+            viewer_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+            viewer_ip VARCHAR(64),
+            viewed_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create product_views table");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_product_views_product ON product_views (product_id, viewed_at)")
+        .execute(pool)
+        .await
+        .expect("Failed to create product_views index");
+
+    // Prefix lookups for search suggestions (lower(name) LIKE 'prefix%')
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_products_name_prefix ON products (lower(name) text_pattern_ops)")
+        .execute(pool)
+        .await
+        .expect("Failed to create products name index");
+
+    // Each signed-in user's latest view per product, trimmed to a fixed history length
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS recently_viewed (
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+            viewed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (user_id, product_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create recently_viewed table");
+
+    // Per-user conversation state; archived conversations stay hidden until a newer message
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS conversation_state (
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            other_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            archived_at TIMESTAMP WITH TIME ZONE,
+            PRIMARY KEY (user_id, other_user_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create conversation_state table");
+
+    // Reverse-geocoding results per grid cell (hundredths of a degree)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS geocode_cache (
+            lat_cell INTEGER NOT NULL,
+            lon_cell INTEGER NOT NULL,
+            location_string TEXT NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            PRIMARY KEY (lat_cell, lon_cell)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create geocode_cache table");
+
+    // In-app notifications shown to a user
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS notifications (
+            id SERIAL PRIMARY KEY,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            kind VARCHAR(50) NOT NULL,
+            title VARCHAR(255) NOT NULL,
+            body TEXT NOT NULL,
+            reference VARCHAR(255),
+            read BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create notifications table");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_notifications_user ON notifications (user_id, created_at)")
+        .execute(pool)
+        .await
+        .expect("Failed to create notifications index");
+
+    // Email notifications held for the next digest of users not on 'instant'
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS email_digest_items (
+            id SERIAL PRIMARY KEY,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            summary TEXT NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            sent_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create email_digest_items table");
+
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS idx_email_digest_items_pending ON email_digest_items (user_id) WHERE sent_at IS NULL")
+        .execute(pool)
+        .await;
+
+    // Extra product photos shown as a gallery after the primary image
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS product_images (
+            id SERIAL PRIMARY KEY,
+            product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+            image TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create product_images table");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_product_images_product ON product_images (product_id, position)")
+        .execute(pool)
+        .await
+        .expect("Failed to create product_images index");
+
+    // Vendor broadcasts to their followers
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS announcements (
+            id SERIAL PRIMARY KEY,
+            vendor_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            title VARCHAR(255) NOT NULL,
+            body TEXT NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create announcements table");
+
+    // Every change to a withdrawable wallet balance: credits are positive, withdrawals negative
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS wallet_ledger (
+            id SERIAL PRIMARY KEY,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            entry_type VARCHAR(32) NOT NULL,
+            amount FLOAT8 NOT NULL,
+            reference VARCHAR(255),
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create wallet_ledger table");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_wallet_ledger_user ON wallet_ledger (user_id, created_at)")
+        .execute(pool)
+        .await
+        .expect("Failed to create wallet_ledger index");
+
+    // When each user last downloaded their data export
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS data_exports (
+            id SERIAL PRIMARY KEY,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create data_exports table");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_data_exports_user ON data_exports (user_id, created_at)")
+        .execute(pool)
+        .await
+        .expect("Failed to create data_exports index");
+
+    // At most one open appeal per vendor
+    let _ = sqlx::query(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_appeals_one_pending ON appeals(vendor_id) WHERE status = 'pending'"
+    )
+    .execute(pool)
+    .await;
+
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS idx_product_tags_tag ON product_tags(tag)")
+        .execute(pool)
+        .await;
+
+    // Role the admin SQL console runs queries as: it can read the app's tables
+    // and nothing else. Needs a database user that may create roles; without
+    // it the console refuses to run.
+    let role = crate::admin_query::READER_ROLE;
+    let _ = sqlx::query(&format!(
+        "DO $$ BEGIN IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = '{role}') THEN CREATE ROLE {role} NOLOGIN; END IF; END $$"
+    ))
+    .execute(pool)
+    .await;
+    for statement in [
+        format!("GRANT {role} TO CURRENT_USER"),
+        format!("GRANT USAGE ON SCHEMA public TO {role}"),
+        format!("GRANT SELECT ON ALL TABLES IN SCHEMA public TO {role}"),
+    ] {
+        let _ = sqlx::query(&statement).execute(pool).await;
+    }
+}
+
+/// Create a new user and return the created `User` record.
+/// Passwords are hashed before insertion.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_user(pool: &PgPool, username: &str, email: &str, password: &str, role: &Role, profile_image: Option<&str>, location_string: Option<&str>, mpesa_number: Option<&str>) -> Result<User, sqlx::Error> {
+    let password_hash = hash(password, DEFAULT_COST).map_err(|_| sqlx::Error::RowNotFound)?;
+    let role_str = match role {
+        Role::Admin => "Admin",
+        Role::Customer => "Customer",
+        Role::Vendor => "Vendor",
+    };
+
+    // Customers are auto-verified, Vendors need admin verification when they upload profile image
+    let verified = matches!(role, Role::Customer);
+
+    let row = if let Some(image) = profile_image {
+        sqlx::query(
+            r#"
+            INSERT INTO users (username, email, password_hash, role, profile_image, verified, location_string, mpesa_number)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id, username, email, role, profile_image, verified, location_string, mpesa_number
+            "#,
+        )
+        .bind(username)
+        .bind(email)
+        .bind(password_hash)
+        .bind(role_str)
+        .bind(image)
+        .bind(verified)  // Customers auto-verified, Vendors need admin verification
+        .bind(location_string)
+        .bind(mpesa_number)
+        .fetch_one(pool)
+        .await?
+    } else {
+        sqlx::query(
+            r#"
+            INSERT INTO users (username, email, password_hash, role, verified, location_string, mpesa_number)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, username, email, role, profile_image, verified, location_string, mpesa_number
+            "#,
+        )
+        .bind(username)
+        .bind(email)
+        .bind(password_hash)
+        .bind(role_str)
+        .bind(verified)  // Customers auto-verified, Vendors need admin verification
+        .bind(location_string)
+        .bind(mpesa_number)
+        .fetch_one(pool)
+        .await?
+    };
+
+    let user = User {
+        id: row.try_get(0)?,
+        username: row.try_get(1)?,
+        email: row.try_get(2)?,
+        role: match row.try_get::<String, _>(3)?.as_str() {
+            "Admin" => Role::Admin,
+            "Customer" => Role::Customer,
+            "Vendor" => Role::Vendor,
+            _ => Role::Customer,
+        },
+        profile_image: row.try_get(4)?,
+        verified: row.try_get(5)?,
+        banned: false,
+        verification_document: None,
+        verification_rejected_reason: None,
+        secondary_email: None,
+        mpesa_number: row.try_get(7)?,
+        payment_preference: None,
+        location_string: row.try_get(6).ok(),
+        wallet_balance: 0.0,
+    };
+
+    Ok(user)
+}
+
+/// Verify credentials and return the authenticated `User` record.
+pub async fn authenticate_user(pool: &PgPool, username: &str, password: &str) -> Result<User, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT id, username, email, password_hash, role, profile_image, verified, banned, secondary_email, mpesa_number, payment_preference, location_string, wallet_balance, verification_rejected_reason
+        FROM users
+        WHERE username = $1 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(username)
+    .fetch_one(pool)
+    .await?;
+
+    let stored_hash: String = row.try_get(3)?;
+    let is_valid = verify(password, &stored_hash).map_err(|_| sqlx::Error::RowNotFound)?;
+
+    if !is_valid {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    // Check if user is banned
+    let banned: bool = row.try_get(7)?;
+    if banned {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    let user = User {
+        id: row.try_get(0)?,
+        username: row.try_get(1)?,
+        email: row.try_get(2)?,
+        role: match row.try_get::<String, _>(4)?.as_str() {
+            "Admin" => Role::Admin,
+            "Customer" => Role::Customer,
+            "Vendor" => Role::Vendor,
+            _ => Role::Customer,
+        },
+        profile_image: row.try_get(5)?,
+        verified: row.try_get(6)?,
+        banned: row.try_get(7)?,
+        verification_document: None,
+        verification_rejected_reason: row.try_get(13)?,
+        secondary_email: row.try_get(8)?,
+        mpesa_number: row.try_get(9)?,
+        payment_preference: row.try_get(10)?,
+        location_string: row.try_get(11)?,
+        wallet_balance: row.try_get(12).unwrap_or(0.0),
+    };
+
+    Ok(user)
+}
+
+/// Fetch cart items for a user, including product details.
+pub async fn get_cart_items(pool: &PgPool, user_id: i32) -> Result<Vec<CartItem>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            ci.id, ci.user_id, ci.product_id, ci.quantity,
+            p.id as p_id, p.name, p.price, p.category, p.description, p.image, p.quantity as p_quantity, p.vendor_id
+        FROM cart_items ci
+        JOIN products p ON ci.product_id = p.id
+        WHERE ci.user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut cart_items = Vec::new();
+    for row in rows {
+        let product = Product {
+            id: row.try_get::<i32, _>("p_id")? as u32,
+            name: row.try_get("name")?,
+            price: row.try_get::<f64, _>("price")?,
+            category: row.try_get("category")?,
+            description: row.try_get::<Option<String>, _>("description")?,
+            image: row.try_get::<Option<String>, _>("image")?,
+            quantity: row.try_get("p_quantity")?,
+            vendor_id: row.try_get::<i32, _>("vendor_id")? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
+        };
+
+        let cart_item = CartItem {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            product_id: row.try_get("product_id")?,
+            quantity: row.try_get("quantity")?,
+            product,
+        };
+        cart_items.push(cart_item);
+    }
+
+    Ok(cart_items)
+}
+
+/// Attach a verification document to a user account, with its detected MIME
+/// type. A document sent after a rejection is a resubmission: the rejection
+/// reason is cleared and the account flagged until an admin decides again.
+/// Returns whether it is a resubmission.
+pub async fn upload_verification_document(pool: &PgPool, user_id: i32, document: &str, content_type: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        UPDATE users
+        SET verification_document = $1,
+            verification_document_type = $2,
+            verification_submitted_at = CURRENT_TIMESTAMP,
+            verification_resubmitted = verification_resubmitted OR verification_rejected_reason IS NOT NULL,
+            verification_rejected_reason = NULL
+        WHERE id = $3
+        RETURNING verification_resubmitted
+        "#,
+    )
+    .bind(document)
+    .bind(content_type)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// A user's verification document and its MIME type (None for documents
+/// uploaded before types were recorded).
+pub async fn get_user_verification_document(pool: &PgPool, user_id: i32) -> Result<Option<(String, Option<String>)>, sqlx::Error> {
+    let result: Option<(Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT verification_document, verification_document_type FROM users WHERE id = $1"
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(result.and_then(|(doc, content_type)| doc.map(|doc| (doc, content_type))))
+}
+
+use serde::{Deserialize, Serialize};
+use crate::models::VendorReport;
+
+pub async fn create_vendor_report(
+    pool: &PgPool,
+    customer_id: i32,
+    vendor_id: i32,
+    product_id: Option<i32>,
+    report_type: &str,
+    description: Option<&str>
+) -> Result<VendorReport, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO vendor_reports (customer_id, vendor_id, product_id, report_type, description)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, customer_id, vendor_id, product_id, report_type, description, status, admin_notes,
+                  created_at::text, updated_at::text
+        "#,
+    )
+    .bind(customer_id)
+    .bind(vendor_id)
+    .bind(product_id)
+    .bind(report_type)
+    .bind(description)
+    .fetch_one(pool)
+    .await?;
+
+    // Get customer and vendor usernames, and product name if provided
+    let customer_username = sqlx::query_scalar(
+        "SELECT username FROM users WHERE id = $1"
+    )
+    .bind(customer_id)
+    .fetch_one(pool)
+    .await?;
+
+    let vendor_username = sqlx::query_scalar(
+        "SELECT username FROM users WHERE id = $1"
+    )
+    .bind(vendor_id)
+    .fetch_one(pool)
+    .await?;
+
+    let product_name = if let Some(pid) = product_id {
+        Some(sqlx::query_scalar("SELECT name FROM products WHERE id = $1")
+            .bind(pid)
+            .fetch_one(pool)
+            .await?)
+    } else {
+        None
+    };
+
+    Ok(VendorReport {
+        id: row.try_get("id")?,
+        customer_id: row.try_get("customer_id")?,
+        vendor_id: row.try_get("vendor_id")?,
+        product_id: row.try_get("product_id")?,
+        report_type: row.try_get("report_type")?,
+        description: row.try_get("description")?,
+        status: row.try_get("status")?,
+        admin_notes: row.try_get("admin_notes")?,
+        created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+        updated_at: row.try_get::<String, _>("updated_at").unwrap_or_else(|_| "?".to_string()),
+        customer_username,
+        vendor_username,
+        product_name,
+    })
+}
+
+pub async fn count_vendor_reports(pool: &PgPool, vendor_id: i32) -> Result<i32, sqlx::Error> {
+    let row: (i32,) = sqlx::query_as(
+        "SELECT COUNT(*)::int as report_count FROM vendor_reports WHERE vendor_id = $1"
+    )
+    .bind(vendor_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(row.0)
+}
+
+/// The report types customers can file (those of the report form), and how much
+/// each counts toward suspension. Types stored before this list existed count 1.0.
+const REPORT_WEIGHTS: &[(&str, f64)] = &[
+    ("non_delivery", 2.0),
+    ("wrong_product", 1.0),
+    ("damaged_product", 1.0),
+    ("other", 1.0),
+];
+
+/// Whether `report_type` is one of `REPORT_WEIGHTS`.
+pub fn is_report_type(report_type: &str) -> bool {
+    REPORT_WEIGHTS.iter().any(|(name, _)| *name == report_type)
+}
+
+/// The report types customers can file, for error messages.
+pub fn report_types() -> Vec<&'static str> {
+    REPORT_WEIGHTS.iter().map(|(name, _)| *name).collect()
+}
+
+pub fn report_weight(report_type: &str) -> f64 {
+    REPORT_WEIGHTS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(report_type))
+        .map(|(_, weight)| *weight)
+        .unwrap_or(1.0)
+}
+
+/// Weighted report score per vendor, ignoring dismissed reports.
+/// Vendors without counted reports are absent from the map.
+pub async fn vendor_report_scores(pool: &PgPool, vendor_ids: &[i32]) -> Result<std::collections::HashMap<i32, f64>, sqlx::Error> {
+    let rows: Vec<(i32, String, i64)> = sqlx::query_as(
+        r#"
+        SELECT vendor_id, report_type, COUNT(*)
+        FROM vendor_reports
+        WHERE vendor_id = ANY($1) AND status <> 'dismissed'
+        GROUP BY vendor_id, report_type
+        "#,
+    )
+    .bind(vendor_ids)
+    .fetch_all(pool)
+    .await?;
+
+    let mut scores = std::collections::HashMap::new();
+    for (vendor_id, report_type, count) in rows {
+        *scores.entry(vendor_id).or_insert(0.0) += report_weight(&report_type) * count as f64;
+    }
+    Ok(scores)
+}
+
+/// Vendors among `vendor_ids` whose weighted report score reaches the configured threshold.
+pub async fn suspended_vendor_ids(pool: &PgPool, vendor_ids: &[i32]) -> Result<std::collections::HashSet<i32>, sqlx::Error> {
+    let threshold = crate::settings::get_f64(pool, crate::settings::REPORT_SUSPENSION_THRESHOLD).await;
+    Ok(vendor_report_scores(pool, vendor_ids)
+        .await?
+        .into_iter()
+        .filter(|(_, score)| *score >= threshold)
+        .map(|(vendor_id, _)| vendor_id)
+        .collect())
+}
+
+/// Remove products whose vendor is currently suspended by reports. Suspension
+/// follows the report score, so it takes effect in listings straight away.
+pub async fn drop_suspended_vendors(pool: &PgPool, products: &mut Vec<Product>) -> Result<(), sqlx::Error> {
+    let suspended = suspended_vendors_of(pool, products).await?;
+    products.retain(|p| !suspended.contains(&(p.vendor_id as i32)));
+    Ok(())
+}
+
+/// Vendors of `products` who are suspended by reports.
+async fn suspended_vendors_of(pool: &PgPool, products: &[Product]) -> Result<std::collections::HashSet<i32>, sqlx::Error> {
+    let mut vendor_ids: Vec<i32> = products.iter().map(|p| p.vendor_id as i32).collect();
+    vendor_ids.sort_unstable();
+    vendor_ids.dedup();
+    suspended_vendor_ids(pool, &vendor_ids).await
+}
+
+/// Whether a vendor is suspended from selling because of reports.
+pub async fn is_vendor_suspended(pool: &PgPool, vendor_id: i32) -> Result<bool, sqlx::Error> {
+    Ok(suspended_vendor_ids(pool, &[vendor_id]).await?.contains(&vendor_id))
+}
+
+pub async fn get_all_vendor_reports(pool: &PgPool) -> Result<Vec<VendorReport>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            vr.id, vr.customer_id, vr.vendor_id, vr.product_id, vr.report_type,
+            vr.description, vr.status, vr.admin_notes, vr.created_at::text, vr.updated_at::text,
+            cu.username as customer_username, vu.username as vendor_username,
+            p.name as product_name
+        FROM vendor_reports vr
+        JOIN users cu ON vr.customer_id = cu.id
+        JOIN users vu ON vr.vendor_id = vu.id
+        LEFT JOIN products p ON vr.product_id = p.id
+        ORDER BY vr.created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut reports = Vec::new();
+    for row in rows {
+        reports.push(VendorReport {
+            id: row.try_get("id")?,
+            customer_id: row.try_get("customer_id")?,
+            vendor_id: row.try_get("vendor_id")?,
+            product_id: row.try_get("product_id")?,
+            report_type: row.try_get("report_type")?,
+            description: row.try_get("description")?,
+            status: row.try_get("status")?,
+            admin_notes: row.try_get("admin_notes")?,
+            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+            updated_at: row.try_get::<String, _>("updated_at").unwrap_or_else(|_| "?".to_string()),
+            customer_username: row.try_get("customer_username")?,
+            vendor_username: row.try_get("vendor_username")?,
+            product_name: row.try_get("product_name")?,
+        });
+    }
+
+    Ok(reports)
+}
+
+pub async fn update_report_status(
+    pool: &PgPool,
+    report_id: i32,
+    status: &str,
+    admin_notes: Option<&str>
+) -> Result<(), sqlx::Error> {
+    let query = if let Some(notes) = admin_notes {
+        sqlx::query(
+            "UPDATE vendor_reports SET status = $1, admin_notes = $2, updated_at = CURRENT_TIMESTAMP WHERE id = $3"
+        )
+        .bind(status)
+        .bind(notes)
+        .bind(report_id)
+    } else {
+        sqlx::query(
+            "UPDATE vendor_reports SET status = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2"
+        )
+        .bind(status)
+        .bind(report_id)
+    };
+
+    query.execute(pool).await?;
+    Ok(())
+}
+
+pub async fn add_to_cart(pool: &PgPool, user_id: i32, product_id: i32, quantity: i32) -> Result<CartItem, sqlx::Error> {
+    // Check if item already exists in cart
+    let existing_row = sqlx::query(
+        "SELECT id, quantity FROM cart_items WHERE user_id = $1 AND product_id = $2"
+    )
+    .bind(user_id)
+    .bind(product_id)
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(row) = existing_row {
+        let existing_id: i32 = row.try_get(0)?;
+        let existing_quantity: i32 = row.try_get(1)?;
+        let new_quantity = existing_quantity + quantity;
+
+        // Update existing item
+        sqlx::query(
+            "UPDATE cart_items SET quantity = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2"
+        )
+        .bind(new_quantity)
+        .bind(existing_id)
+        .execute(pool)
+        .await?;
+
+        // Return updated cart item with product info - use manual construction to avoid compile-time validation
+        let row = sqlx::query(
+            r#"
+            SELECT
+                ci.id, ci.user_id, ci.product_id, ci.quantity,
+                p.id as p_id, p.name, p.price, p.category, p.description, p.image, p.quantity as p_quantity, p.vendor_id
+            FROM cart_items ci
+            JOIN products p ON ci.product_id = p.id
+            WHERE ci.id = $1
+            "#,
+        )
+        .bind(existing_id)
+        .fetch_one(pool)
+        .await?;
+
+        let product = Product {
+            id: row.try_get::<i32, _>("p_id")? as u32,
+            name: row.try_get("name")?,
+            price: row.try_get::<f64, _>("price")?,
+            category: row.try_get("category")?,
+            description: row.try_get::<Option<String>, _>("description")?,
+            image: row.try_get::<Option<String>, _>("image")?,
+            quantity: row.try_get("p_quantity")?,
+            vendor_id: row.try_get::<i32, _>("vendor_id")? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
+        };
+
+        Ok(CartItem {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            product_id: row.try_get("product_id")?,
+            quantity: row.try_get("quantity")?,
+            product,
+        })
+    } else {
+        // Insert new item
+        let row = sqlx::query(
+            r#"
+            INSERT INTO cart_items (user_id, product_id, quantity)
+            VALUES ($1, $2, $3)
+            RETURNING
+                id, user_id, product_id, quantity,
+                (SELECT name FROM products WHERE id = $2) as p_name,
+                (SELECT price FROM products WHERE id = $2) as p_price,
+                (SELECT category FROM products WHERE id = $2) as p_category,
+                (SELECT description FROM products WHERE id = $2) as p_description,
+                (SELECT image FROM products WHERE id = $2) as p_image,
+                (SELECT quantity FROM products WHERE id = $2) as p_quantity,
+                (SELECT vendor_id FROM products WHERE id = $2) as p_vendor_id
+            "#,
+        )
+        .bind(user_id)
+        .bind(product_id)
+        .bind(quantity)
+        .fetch_one(pool)
+        .await?;
+
+        let product = Product {
+            id: product_id as u32,
+            name: row.try_get("p_name")?,
+            price: row.try_get::<f64, _>("p_price")?,
+            category: row.try_get("p_category")?,
+            description: row.try_get::<Option<String>, _>("p_description")?,
+            image: row.try_get::<Option<String>, _>("p_image")?,
+            quantity: row.try_get("p_quantity")?,
+            vendor_id: row.try_get::<i32, _>("p_vendor_id")? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
+        };
+
+        let cart_item = CartItem {
+            id: row.try_get("id")?,
+            user_id,
+            product_id,
+            quantity,
+            product,
+        };
+
+        Ok(cart_item)
+    }
+}
+
+/// Quantity of `product_id` already in the user's cart (if any) and the number of distinct items in it.
+pub async fn get_cart_usage(pool: &PgPool, user_id: i32, product_id: i32) -> Result<(Option<i32>, i64), sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT MAX(quantity) FILTER (WHERE product_id = $2), COUNT(*)
+        FROM cart_items WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(product_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// Why one item of a cart batch wasn't added
+#[derive(Debug, PartialEq)]
+pub enum CartBatchRejection {
+    InvalidQuantity,
+    ProductNotFound,
+    VendorPaused,
+    /// The cart would hold more than the allowed number of distinct products
+    MaxCartItems,
+    /// The cart would hold more than the allowed quantity of this product
+    MaxItemQuantity,
+    /// The cart would hold more units than the product has in stock
+    OutOfStock { available: i32 },
+}
+
+/**
+ * Add several (product_id, quantity) items to a cart in one transaction
+ *
+ * Items are applied in order, each on top of the cart as the previous ones
+ * left it, and topping up products already in the cart. An item that fails
+ * validation is skipped without affecting the rest. Returns one outcome per
+ * item, in the order given.
+ */
+pub async fn add_to_cart_batch(
+    pool: &PgPool,
+    user_id: i32,
+    items: &[(i32, i32)],
+    max_items: i64,
+    max_quantity: i64,
+) -> Result<Vec<Result<(), CartBatchRejection>>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let rows: Vec<(i32, i32, i32)> = sqlx::query_as(
+        "SELECT product_id, id, quantity FROM cart_items WHERE user_id = $1 FOR UPDATE"
+    )
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut cart: std::collections::HashMap<i32, (i32, i32)> =
+        rows.into_iter().map(|(product_id, id, quantity)| (product_id, (id, quantity))).collect();
+
+    let mut outcomes = Vec::with_capacity(items.len());
+    for &(product_id, quantity) in items {
+        if quantity < 1 {
+            outcomes.push(Err(CartBatchRejection::InvalidQuantity));
+            continue;
+        }
+
+        let product: Option<(i32, bool)> = sqlx::query_as(
+            r#"
+            SELECT p.quantity, u.is_paused
+            FROM products p JOIN users u ON u.id = p.vendor_id
+            WHERE p.id = $1 AND p.removed_at IS NULL
+            "#,
+        )
+        .bind(product_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some((stock, paused)) = product else {
+            outcomes.push(Err(CartBatchRejection::ProductNotFound));
+            continue;
+        };
+        if paused {
+            outcomes.push(Err(CartBatchRejection::VendorPaused));
+            continue;
+        }
+
+        let existing = cart.get(&product_id).copied();
+        if existing.is_none() && cart.len() as i64 >= max_items {
+            outcomes.push(Err(CartBatchRejection::MaxCartItems));
+            continue;
+        }
+        let new_quantity = i64::from(existing.map_or(0, |(_, q)| q)) + i64::from(quantity);
+        if new_quantity > max_quantity {
+            outcomes.push(Err(CartBatchRejection::MaxItemQuantity));
+            continue;
+        }
+        if new_quantity > i64::from(stock) {
+            outcomes.push(Err(CartBatchRejection::OutOfStock { available: stock }));
+            continue;
+        }
+
+        let new_quantity = new_quantity as i32;
+        let id = match existing {
+            Some((id, _)) => {
+                sqlx::query("UPDATE cart_items SET quantity = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2")
+                    .bind(new_quantity)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+                id
+            }
+            None => {
+                sqlx::query_scalar("INSERT INTO cart_items (user_id, product_id, quantity) VALUES ($1, $2, $3) RETURNING id")
+                    .bind(user_id)
+                    .bind(product_id)
+                    .bind(new_quantity)
+                    .fetch_one(&mut *tx)
+                    .await?
+            }
+        };
+        cart.insert(product_id, (id, new_quantity));
+        outcomes.push(Ok(()));
+    }
+
+    tx.commit().await?;
+    Ok(outcomes)
+}
+
+pub async fn update_cart_item_quantity(pool: &PgPool, cart_item_id: i32, user_id: i32, quantity: i32) -> Result<CartItem, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        UPDATE cart_items SET quantity = $1, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $2 AND user_id = $3
+        RETURNING
+            id, user_id, product_id, quantity,
+            (SELECT name FROM products WHERE id = product_id) as p_name,
+            (SELECT price FROM products WHERE id = product_id) as p_price,
+            (SELECT category FROM products WHERE id = product_id) as p_category,
+            (SELECT description FROM products WHERE id = product_id) as p_description,
+            (SELECT image FROM products WHERE id = product_id) as p_image,
+            (SELECT quantity FROM products WHERE id = product_id) as p_quantity,
+            (SELECT vendor_id FROM products WHERE id = product_id) as p_vendor_id
+        "#,
+    )
+    .bind(quantity)
+    .bind(cart_item_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    let product = Product {
+        id: row.try_get::<i32, _>("product_id")? as u32,
+        name: row.try_get("p_name")?,
+        price: row.try_get::<f64, _>("p_price")?,
+        category: row.try_get("p_category")?,
+        description: row.try_get::<Option<String>, _>("p_description")?,
+        image: row.try_get::<Option<String>, _>("p_image")?,
+        quantity: row.try_get("p_quantity")?,
+        vendor_id: row.try_get::<i32, _>("p_vendor_id")? as u32,
+        tags: Vec::new(),
+        gallery: Vec::new(),
+        attributes: ProductAttributes::default(),
+    };
+
+    let cart_item = CartItem {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        product_id: row.try_get("product_id")?,
+        quantity: row.try_get("quantity")?,
+        product,
+    };
+
+    Ok(cart_item)
+}
+
+pub async fn remove_from_cart_with_user(pool: &PgPool, cart_item_id: i32, user_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM cart_items WHERE id = $1 AND user_id = $2")
+        .bind(cart_item_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// Admin user management functions
+pub async fn get_all_users(pool: &PgPool) -> Result<Vec<User>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, username, email, role, profile_image, verified, banned, secondary_email, mpesa_number, payment_preference, location_string
+        FROM users
+        WHERE deleted_at IS NULL
+        ORDER BY id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut users = Vec::new();
+    for row in rows {
+        let user = User {
+            id: row.try_get(0)?,
+            username: row.try_get(1)?,
+            email: row.try_get(2)?,
+            role: match row.try_get::<String, _>(3)?.as_str() {
+                "Admin" => Role::Admin,
+                "Customer" => Role::Customer,
+                "Vendor" => Role::Vendor,
+                _ => Role::Customer,
+            },
+            profile_image: row.try_get(4)?,
+            verified: row.try_get(5)?,
+            banned: row.try_get(6)?,
+            verification_document: None,
+            verification_rejected_reason: None,
+            secondary_email: row.try_get(7)?,
+            mpesa_number: row.try_get(8)?,
+            payment_preference: row.try_get(9)?,
+            location_string: row.try_get(10)?,
+            wallet_balance: 0.0,
+        };
+        users.push(user);
+    }
+
+    Ok(users)
+}
+
+pub async fn get_pending_vendors(pool: &PgPool) -> Result<Vec<crate::models::VendorVerification>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, username, email, profile_image, mpesa_number, payment_preference, verification_resubmitted,
+               to_char(verification_submitted_at::timestamptz AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"')
+        FROM users
+        WHERE role = 'Vendor' AND verified = false AND profile_image IS NOT NULL
+        ORDER BY id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut vendors = Vec::new();
+    for row in rows {
+        let vendor = crate::models::VendorVerification {
+            id: row.try_get(0)?,
+            username: row.try_get(1)?,
+            email: row.try_get(2)?,
+            profile_image: row.try_get(3)?,
+            mpesa_number: row.try_get(4)?,
+            payment_preference: row.try_get(5)?,
+            is_resubmission: row.try_get(6)?,
+            submitted_at: row.try_get(7)?,
+        };
+        vendors.push(vendor);
+    }
+
+    Ok(vendors)
+}
+
+pub async fn update_user_role(pool: &PgPool, user_id: i32, new_role: &Role) -> Result<(), sqlx::Error> {
+    let role_str = match new_role {
+        Role::Admin => "Admin",
+        Role::Customer => "Customer",
+        Role::Vendor => "Vendor",
+    };
+
+    sqlx::query(
+        "UPDATE users SET role = $1 WHERE id = $2",
+    )
+    .bind(role_str)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Reason shown to vendors whose verification is rejected.
+const VERIFICATION_REJECTED_REASON: &str =
+    "Verification denied due to non-human image upload. Please upload a clear image of yourself.";
+
+/// Approving clears any previous rejection reason and starts the document's
+/// retention window; rejecting removes the verification document and records
+/// the rejection reason. Either way a pending resubmission has been dealt with.
+const UPDATE_USER_VERIFICATION: &str = r#"
+    UPDATE users
+    SET verified = $1,
+        verified_at = CASE WHEN $1 THEN CURRENT_TIMESTAMP END,
+        verification_document = CASE WHEN $1 THEN verification_document END,
+        verification_document_type = CASE WHEN $1 THEN verification_document_type END,
+        verification_rejected_reason = CASE WHEN $1 THEN NULL ELSE $3 END,
+        verification_resubmitted = FALSE
+    WHERE id = $2
+"#;
+
+pub async fn update_user_verification(pool: &PgPool, user_id: i32, verified: bool) -> Result<(), sqlx::Error> {
+    sqlx::query(UPDATE_USER_VERIFICATION)
+        .bind(verified)
+        .bind(user_id)
+        .bind(VERIFICATION_REJECTED_REASON)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Approve or reject several vendors in one transaction, auditing each change
+/// under `admin_id`. Ids that aren't active vendors are left alone. Returns
+/// the role of every active user found and the (id, email, username) of the
+/// vendors updated.
+pub async fn bulk_update_user_verification(
+    pool: &PgPool,
+    admin_id: i32,
+    user_ids: &[i32],
+    verified: bool,
+) -> Result<(std::collections::HashMap<i32, String>, Vec<(i32, String, String)>), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let rows: Vec<(i32, String, String, String)> = sqlx::query_as(
+        "SELECT id, role, email, username FROM users WHERE id = ANY($1) AND deleted_at IS NULL ORDER BY id FOR UPDATE"
+    )
+    .bind(user_ids)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut roles = std::collections::HashMap::new();
+    let mut updated = Vec::new();
+    for (id, role, email, username) in rows {
+        if role == "Vendor" {
+            sqlx::query(UPDATE_USER_VERIFICATION)
+                .bind(verified)
+                .bind(id)
+                .bind(VERIFICATION_REJECTED_REASON)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("INSERT INTO audit_log (actor_id, action, details) VALUES ($1, $2, $3)")
+                .bind(admin_id)
+                .bind(format!("verification.{} user {}", if verified { "approve" } else { "reject" }, id))
+                .bind("bulk verification")
+                .execute(&mut *tx)
+                .await?;
+            updated.push((id, email, username));
+        }
+        roles.insert(id, role);
+    }
+
+    tx.commit().await?;
+    Ok((roles, updated))
+}
+
+/// Keep (or stop keeping) a vendor's verification document past the retention
+/// window, auditing the change under `admin_id`. Returns false if the user has
+/// no verification document.
+pub async fn set_verification_document_retained(
+    pool: &PgPool,
+    admin_id: i32,
+    user_id: i32,
+    retained: bool,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query(
+        "UPDATE users SET verification_document_retained = $1 WHERE id = $2 AND verification_document IS NOT NULL"
+    )
+    .bind(retained)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query("INSERT INTO audit_log (actor_id, action, details) VALUES ($1, $2, $3)")
+        .bind(admin_id)
+        .bind(format!("verification.{} user {}", if retained { "retain_document" } else { "release_document" }, user_id))
+        .bind(if retained { "document kept past the retention window" } else { "document subject to retention window" })
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Clear the verification documents of vendors approved more than
+/// `retention_days` ago, except those an admin chose to retain. Each purge
+/// leaves an audit note that the document existed and was reviewed. Returns
+/// the ids of the vendors whose documents were removed.
+pub async fn purge_verification_documents(pool: &PgPool, retention_days: i32) -> Result<Vec<i32>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let purged: Vec<(i32, Option<String>, Option<String>, String)> = sqlx::query_as(
+        r#"
+        WITH expired AS (
+            SELECT id, verification_document_type,
+                   to_char(verification_submitted_at::timestamptz AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS submitted_at,
+                   to_char(verified_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS verified_at
+            FROM users
+            WHERE role = 'Vendor' AND verified = TRUE
+              AND verification_document IS NOT NULL
+              AND verification_document_retained = FALSE
+              AND verified_at < NOW() - make_interval(days => $1)
+            FOR UPDATE
+        )
+        UPDATE users u
+        SET verification_document = NULL, verification_document_type = NULL
+        FROM expired e
+        WHERE u.id = e.id
+        RETURNING e.id, e.verification_document_type, e.submitted_at, e.verified_at
+        "#,
+    )
+    .bind(retention_days)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for (id, content_type, submitted_at, verified_at) in &purged {
+        sqlx::query("INSERT INTO audit_log (actor_id, action, details) VALUES (NULL, $1, $2)")
+            .bind(format!("verification.document_purged user {}", id))
+            .bind(format!(
+                "{} document submitted {} was reviewed and approved {}; removed after the {}-day retention window",
+                content_type.as_deref().unwrap_or("unknown"),
+                submitted_at.as_deref().unwrap_or("unknown"),
+                verified_at,
+                retention_days
+            ))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(purged.into_iter().map(|(id, ..)| id).collect())
+}
+
+pub async fn ban_user(pool: &PgPool, user_id: i32, banned: bool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE users SET banned = $1 WHERE id = $2",
+    )
+    .bind(banned)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_user_profile_image(pool: &PgPool, user_id: i32, profile_image: &str) -> Result<(), sqlx::Error> {
+    // Check user role to determine verification status
+    let role_row = sqlx::query("SELECT role FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    
+    let role: String = role_row.try_get(0)?;
+    
+    // Vendors need admin verification when uploading profile image, Customers are auto-verified
+    if role == "Vendor" {
+        sqlx::query(
+            "UPDATE users SET profile_image = $1, verified = false WHERE id = $2",
+        )
+        .bind(profile_image)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    } else {
+        // Customers remain verified or get auto-verified
+        sqlx::query(
+            "UPDATE users SET profile_image = $1, verified = true WHERE id = $2",
+        )
+        .bind(profile_image)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn update_user_profile(pool: &PgPool, user_id: i32, new_username: Option<&str>, new_email: Option<&str>, secondary_email: Option<&str>, mpesa_number: Option<&str>, payment_preference: Option<&str>) -> Result<(), sqlx::Error> {
+    if let Some(username) = new_username {
+        sqlx::query(
+            "UPDATE users SET username = $1 WHERE id = $2",
+        )
+        .bind(username)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    }
+
+    if let Some(email) = new_email {
+        sqlx::query(
+            "UPDATE users SET email = $1 WHERE id = $2",
+        )
+        .bind(email)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    }
+
+    if let Some(sec_email) = secondary_email {
+        sqlx::query(
+            "UPDATE users SET secondary_email = $1 WHERE id = $2",
+        )
+        .bind(sec_email)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    }
+
+    if let Some(mpesa) = mpesa_number {
+        sqlx::query(
+            // A changed number must be verified again before payouts
+            "UPDATE users SET mpesa_verified = (mpesa_verified AND mpesa_number IS NOT DISTINCT FROM $1), mpesa_number = $1 WHERE id = $2",
+        )
+        .bind(mpesa)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    }
+
+    if let Some(pref) = payment_preference {
+        sqlx::query(
+            "UPDATE users SET payment_preference = $1 WHERE id = $2",
+        )
+        .bind(pref)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn set_email_notifications(pool: &PgPool, user_id: i32, enabled: bool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET email_notifications = $1 WHERE id = $2")
+        .bind(enabled)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Set how often a user gets notification emails; see `digests::NOTIFICATION_FREQUENCIES`.
+pub async fn set_notification_frequency(pool: &PgPool, user_id: i32, frequency: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET notification_frequency = $1 WHERE id = $2")
+        .bind(frequency)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Set the language a user's emails are written in; see `email_templates::languages`.
+pub async fn set_user_language(pool: &PgPool, user_id: i32, language: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET language = $1 WHERE id = $2")
+        .bind(language)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The language a user's emails are written in, or None if the user doesn't exist.
+pub async fn get_user_language(pool: &PgPool, user_id: i32) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT language FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Set the smallest subtotal the vendor accepts in one order (0 for none).
+pub async fn set_min_order_value(pool: &PgPool, vendor_id: i32, min_order_value: f64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET min_order_value = $1 WHERE id = $2 AND role = 'Vendor'")
+        .bind(min_order_value)
+        .bind(vendor_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Turn a vendor's buyers-only messaging on or off.
+pub async fn set_buyers_only_messages(pool: &PgPool, vendor_id: i32, enabled: bool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET buyers_only_messages = $1 WHERE id = $2 AND role = 'Vendor'")
+        .bind(enabled)
+        .bind(vendor_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Whether `sender_id` may message `receiver_id`. Only vendors with buyers-only
+/// messaging turned on restrict this: they hear from customers who have ordered
+/// from them, mutual followers, and anyone they already have a conversation with.
+pub async fn may_message(pool: &PgPool, sender_id: i32, receiver_id: i32) -> Result<bool, sqlx::Error> {
+    let allowed: Option<bool> = sqlx::query_scalar(
+        r#"
+        SELECT NOT u.buyers_only_messages
+            OR EXISTS (SELECT 1 FROM shipping_orders WHERE customer_id = $1 AND vendor_id = $2)
+            OR (EXISTS (SELECT 1 FROM follows WHERE follower_id = $1 AND vendor_id = $2)
+                AND EXISTS (SELECT 1 FROM follows WHERE follower_id = $2 AND vendor_id = $1))
+            OR EXISTS (
+                SELECT 1 FROM messages
+                WHERE (sender_id = $1 AND receiver_id = $2) OR (sender_id = $2 AND receiver_id = $1)
+            )
+        FROM users u
+        WHERE u.id = $2 AND u.role = 'Vendor'
+        "#,
+    )
+    .bind(sender_id)
+    .bind(receiver_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(allowed.unwrap_or(true))
+}
+
+/// Pause or resume a vendor's sales.
+pub async fn set_vendor_paused(pool: &PgPool, vendor_id: i32, paused: bool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET is_paused = $1 WHERE id = $2 AND role = 'Vendor'")
+        .bind(paused)
+        .bind(vendor_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Vendors among `vendor_ids` that have paused their sales.
+pub async fn paused_vendor_ids(pool: &PgPool, vendor_ids: &[i32]) -> Result<std::collections::HashSet<i32>, sqlx::Error> {
+    let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM users WHERE id = ANY($1) AND is_paused")
+        .bind(vendor_ids)
+        .fetch_all(pool)
+        .await?;
+    Ok(ids.into_iter().collect())
+}
+
+/// Whether the product's vendor has paused their sales.
+pub async fn is_product_vendor_paused(pool: &PgPool, product_id: i32) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM products p JOIN users u ON u.id = p.vendor_id WHERE p.id = $1 AND u.is_paused)"
+    )
+    .bind(product_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// Username and minimum order value of each of the given vendors.
+pub async fn get_vendor_order_minimums(pool: &PgPool, vendor_ids: &[i32]) -> Result<std::collections::HashMap<i32, (String, f64)>, sqlx::Error> {
+    let rows: Vec<(i32, String, f64)> = sqlx::query_as("SELECT id, username, min_order_value FROM users WHERE id = ANY($1)")
+        .bind(vendor_ids)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(|(id, username, minimum)| (id, (username, minimum))).collect())
+}
+
+/// Hold a one-line `summary` of a notification for the user's next digest.
+/// Returns false, queuing nothing, for users on 'instant' who should be
+/// emailed straight away.
+pub async fn queue_digest_item(pool: &PgPool, user_id: i32, summary: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO email_digest_items (user_id, summary)
+        SELECT id, $2 FROM users WHERE id = $1 AND notification_frequency <> 'instant'
+        "#,
+    )
+    .bind(user_id)
+    .bind(summary)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/**
+ * Claim the queued items of every digest that is due
+ *
+ * A user's digest is due once their oldest unsent item is an hour ('hourly')
+ * or a day ('daily') old. Claimed items are marked sent so each goes out once.
+ * Returns (user_id, username, email, summary), oldest item first per user.
+ */
+pub async fn claim_due_digest_items(pool: &PgPool) -> Result<Vec<(i32, String, String, String)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        WITH due AS (
+            SELECT d.user_id
+            FROM email_digest_items d
+            JOIN users u ON u.id = d.user_id
+            WHERE d.sent_at IS NULL AND u.deleted_at IS NULL
+            GROUP BY d.user_id, u.notification_frequency
+            HAVING MIN(d.created_at) <= NOW() - CASE u.notification_frequency
+                WHEN 'hourly' THEN INTERVAL '1 hour'
+                WHEN 'daily' THEN INTERVAL '1 day'
+                ELSE INTERVAL '0'
+            END
+        ),
+        claimed AS (
+            UPDATE email_digest_items d SET sent_at = NOW()
+            FROM due
+            WHERE d.user_id = due.user_id AND d.sent_at IS NULL
+            RETURNING d.id, d.user_id, d.summary, d.created_at
+        )
+        SELECT c.user_id, u.username, u.email, c.summary
+        FROM claimed c
+        JOIN users u ON u.id = c.user_id
+        ORDER BY c.user_id, c.created_at, c.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn reset_user_password(pool: &PgPool, user_id: i32, new_password: &str) -> Result<(), sqlx::Error> {
+    let password_hash = hash(new_password, DEFAULT_COST).map_err(|_| sqlx::Error::RowNotFound)?;
+
+    sqlx::query(
+        "UPDATE users SET password_hash = $1 WHERE id = $2",
+    )
+    .bind(password_hash)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_user(pool: &PgPool, user_id: i32) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(ANONYMIZE_USER_SQL)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Soft-delete: keep the row for orders/reviews/messages but scrub PII and
+/// replace the password hash so the account can no longer authenticate.
+const ANONYMIZE_USER_SQL: &str = r#"
+    UPDATE users
+    SET deleted_at = CURRENT_TIMESTAMP,
+        username = 'deleted_user_' || id,
+        email = 'deleted_user_' || id || '@deleted.invalid',
+        password_hash = '!',
+        secondary_email = NULL,
+        mpesa_number = NULL,
+        profile_image = NULL,
+        location_string = NULL,
+        latitude = NULL,
+        longitude = NULL,
+        verification_document = NULL,
+        verification_document_type = NULL
+    WHERE id = $1 AND deleted_at IS NULL
+"#;
+
+/// Self-service account deletion: drop the user's cart and messages, then
+/// anonymize the profile. Runs in one transaction.
+pub async fn delete_own_account(pool: &PgPool, user_id: i32) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM cart_items WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("DELETE FROM messages WHERE sender_id = $1 OR receiver_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let result = sqlx::query(ANONYMIZE_USER_SQL)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Count orders (as customer or vendor) that are neither cancelled nor settled.
+pub async fn count_open_orders(pool: &PgPool, user_id: i32) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*) FROM shipping_orders
+        WHERE (customer_id = $1 OR vendor_id = $1)
+        AND COALESCE(shipping_status, 'pending') NOT IN ('cancelled', 'declined')
+        AND payment_released = FALSE
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// Check `password` against the stored hash for `user_id`.
+pub async fn verify_user_password(pool: &PgPool, user_id: i32, password: &str) -> Result<bool, sqlx::Error> {
+    let stored_hash: String = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = $1 AND deleted_at IS NULL")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(verify(password, &stored_hash).unwrap_or(false))
+}
+
+/// Clear the soft-delete marker. PII is not restored; the admin must reset the
+/// password (and the user may update their profile) before the account is usable.
+pub async fn reactivate_user(pool: &PgPool, user_id: i32) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// List soft-deleted accounts as `(id, username, role, deleted_at)` for admin review.
+pub async fn get_deleted_users(pool: &PgPool) -> Result<Vec<(i32, String, String, String)>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, username, role, deleted_at::text AS deleted_at FROM users WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut users = Vec::new();
+    for row in rows {
+        users.push((row.try_get("id")?, row.try_get("username")?, row.try_get("role")?, row.try_get("deleted_at")?));
+    }
+    Ok(users)
+}
+
+/// Whether `user_id` refers to an existing, non-deleted account.
+pub async fn is_user_active(pool: &PgPool, user_id: i32) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND deleted_at IS NULL)")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+}
+
+/// Fetch all products, optionally filtered by vendor ID or user location.
+/// Filters by matching location_string (e.g., "Nakuru" matches vendors with "Nakuru" in their location).
+/// List products. With `featured_first`, currently featured products sort ahead of the rest.
+pub async fn get_all_products(pool: &PgPool, vendor_filter: Option<i32>, user_location: Option<String>, tag: Option<&str>, featured_first: bool, in_stock_only: bool, is_organic: Option<bool>) -> Result<Vec<Product>, sqlx::Error> {
+    let tag = tag.and_then(normalize_tag);
+    let rows = if let Some(vendor_id) = vendor_filter {
+        sqlx::query(
+            r#"
+            SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
+            FROM products p
+            WHERE p.vendor_id = $1 AND p.removed_at IS NULL
+            AND ($2::text IS NULL OR EXISTS (SELECT 1 FROM product_tags t WHERE t.product_id = p.id AND t.tag = $2))
+            AND (NOT $4 OR p.quantity > 0)
+            AND ($5::boolean IS NULL OR p.is_organic = $5)
+            ORDER BY ($3 AND (p.is_featured AND (p.featured_until IS NULL OR p.featured_until > NOW()))) DESC, p.id
+            "#,
+        )
+        .bind(vendor_id)
+        .bind(&tag)
+        .bind(featured_first)
+        .bind(in_stock_only)
+        .bind(is_organic)
+        .fetch_all(pool)
+        .await?
+    } else if let Some(location) = user_location {
+        // Filter products by vendors whose location contains the user's location
+        sqlx::query(
+            r#"
+            SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
+            FROM products p
+            JOIN users u ON p.vendor_id = u.id
+            WHERE u.verified = TRUE AND u.banned = FALSE AND u.deleted_at IS NULL AND u.is_paused = FALSE AND p.removed_at IS NULL AND p.flagged_at IS NULL
+            AND LOWER(u.location_string) LIKE LOWER($1)
+            AND ($2::text IS NULL OR EXISTS (SELECT 1 FROM product_tags t WHERE t.product_id = p.id AND t.tag = $2))
+            AND (NOT $4 OR p.quantity > 0)
+            AND ($5::boolean IS NULL OR p.is_organic = $5)
+            ORDER BY ($3 AND (p.is_featured AND (p.featured_until IS NULL OR p.featured_until > NOW()))) DESC, p.id
+            "#,
+        )
+        .bind(format!("%{}%", location))
+        .bind(&tag)
+        .bind(featured_first)
+        .bind(in_stock_only)
+        .bind(is_organic)
+        .fetch_all(pool)
+        .await?
+    } else {
+        // Return all products from verified vendors that aren't paused
+        sqlx::query(
+            r#"
+            SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
+            FROM products p
+            JOIN users u ON p.vendor_id = u.id
+            WHERE u.verified = TRUE AND u.banned = FALSE AND u.deleted_at IS NULL AND u.is_paused = FALSE AND p.removed_at IS NULL AND p.flagged_at IS NULL
+            AND ($1::text IS NULL OR EXISTS (SELECT 1 FROM product_tags t WHERE t.product_id = p.id AND t.tag = $1))
+            AND (NOT $3 OR p.quantity > 0)
+            AND ($4::boolean IS NULL OR p.is_organic = $4)
+            ORDER BY ($2 AND (p.is_featured AND (p.featured_until IS NULL OR p.featured_until > NOW()))) DESC, p.id
+            "#,
+        )
+        .bind(&tag)
+        .bind(featured_first)
+        .bind(in_stock_only)
+        .bind(is_organic)
+        .fetch_all(pool)
+        .await?
+    };
+
+    let mut products = Vec::new();
+    for row in rows {
+        let product = Product {
+            id: row.try_get::<i32, _>(0)? as u32,
+            name: row.try_get(1)?,
+            price: row.try_get::<f64, _>(2)?,
+            category: row.try_get(3)?,
+            description: row.try_get::<Option<String>, _>(4)?,
+            image: row.try_get::<Option<String>, _>(5)?,
+            quantity: row.try_get(6)?,
+            vendor_id: row.try_get::<i32, _>(7)? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
+        };
+        products.push(product);
+    }
+
+    // Shoppers don't see products from suspended vendors; vendors still see their own
+    if vendor_filter.is_none() {
+        drop_suspended_vendors(pool, &mut products).await?;
+    }
+
+    attach_tags(pool, &mut products).await?;
+    attach_gallery(pool, &mut products).await?;
+    attach_attributes(pool, &mut products).await?;
+    Ok(products)
+}
+
+/// A vendor's storefront: their products, optionally filtered by tag and a name or
+/// description search, or None if the vendor doesn't exist or is unverified,
+/// banned, deleted or suspended.
+pub async fn get_vendor_storefront_products(
+    pool: &PgPool,
+    vendor_id: i32,
+    tag: Option<&str>,
+    search: Option<&str>,
+    featured_first: bool,
+) -> Result<Option<Vec<Product>>, sqlx::Error> {
+    let visible: bool = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM users
+            WHERE id = $1 AND role = 'Vendor' AND verified = TRUE AND banned = FALSE AND deleted_at IS NULL
+        )
+        "#,
+    )
+    .bind(vendor_id)
+    .fetch_one(pool)
+    .await?;
+    if !visible || is_vendor_suspended(pool, vendor_id).await? {
+        return Ok(None);
+    }
+
+    let tag = tag.and_then(normalize_tag);
+    let pattern = search
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(|q| format!("%{}%", escape_like(&q.to_lowercase())));
+    let rows = sqlx::query(
+        r#"
+        SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
+        FROM products p
+        WHERE p.vendor_id = $1 AND p.removed_at IS NULL AND p.flagged_at IS NULL
+        AND ($2::text IS NULL OR EXISTS (SELECT 1 FROM product_tags t WHERE t.product_id = p.id AND t.tag = $2))
+        AND ($3::text IS NULL OR lower(p.name) LIKE $3 OR lower(COALESCE(p.description, '')) LIKE $3)
+        ORDER BY ($4 AND (p.is_featured AND (p.featured_until IS NULL OR p.featured_until > NOW()))) DESC, p.id
+        "#,
+    )
+    .bind(vendor_id)
+    .bind(&tag)
+    .bind(&pattern)
+    .bind(featured_first)
+    .fetch_all(pool)
+    .await?;
+
+    let mut products = Vec::new();
+    for row in rows {
+        products.push(Product {
+            id: row.try_get::<i32, _>(0)? as u32,
+            name: row.try_get(1)?,
+            price: row.try_get::<f64, _>(2)?,
+            category: row.try_get(3)?,
+            description: row.try_get::<Option<String>, _>(4)?,
+            image: row.try_get::<Option<String>, _>(5)?,
+            quantity: row.try_get(6)?,
+            vendor_id: row.try_get::<i32, _>(7)? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
+        });
+    }
+
+    attach_tags(pool, &mut products).await?;
+    attach_gallery(pool, &mut products).await?;
+    attach_attributes(pool, &mut products).await?;
+    Ok(Some(products))
+}
+
+/// Id and name of each of the vendor's listed (not removed) products.
+pub async fn get_vendor_product_names(pool: &PgPool, vendor_id: i32) -> Result<Vec<(i32, String)>, sqlx::Error> {
+    sqlx::query_as("SELECT id, name FROM products WHERE vendor_id = $1 AND removed_at IS NULL ORDER BY id")
+        .bind(vendor_id)
+        .fetch_all(pool)
+        .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_product(pool: &PgPool, name: &str, price: f64, category: &str, description: &str, quantity: i32, image: Option<&str>, vendor_id: i32) -> Result<Product, sqlx::Error> {
+    let row = if let Some(img) = image {
+        sqlx::query(
+            r#"
+            INSERT INTO products (name, price, category, description, quantity, image, vendor_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, name, price, category, description, quantity, image, vendor_id
+            "#,
+        )
+        .bind(name)
+        .bind(price)
+        .bind(category)
+        .bind(description)
+        .bind(quantity)
+        .bind(img)
+        .bind(vendor_id)
+        .fetch_one(pool)
+        .await?
+    } else {
+        sqlx::query(
+            r#"
+            INSERT INTO products (name, price, category, description, quantity, vendor_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, name, price, category, description, quantity, image, vendor_id
+            "#,
+        )
+        .bind(name)
+        .bind(price)
+        .bind(category)
+        .bind(description)
+        .bind(quantity)
+        .bind(vendor_id)
+        .fetch_one(pool)
+        .await?
+    };
+
+    let product = Product {
+        id: row.try_get::<i32, _>(0)? as u32,
+        name: row.try_get(1)?,
+        price: row.try_get::<f64, _>(2)?,
+        category: row.try_get(3)?,
+        description: row.try_get::<Option<String>, _>(4)?,
+        quantity: row.try_get(5)?,
+        image: row.try_get::<Option<String>, _>(6)?,
+        vendor_id: row.try_get::<i32, _>(7)? as u32,
+        tags: Vec::new(),
+        gallery: Vec::new(),
+        attributes: ProductAttributes::default(),
+    };
+
+    Ok(product)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn update_product(pool: &PgPool, product_id: i32, name: &str, price: f64, category: &str, description: &str, quantity: i32, image: Option<&str>, vendor_id: i32) -> Result<Product, sqlx::Error> {
+    let row = if let Some(img) = image {
+        sqlx::query(
+            r#"
+            UPDATE products
+            SET name = $1, price = $2, category = $3, description = $4, quantity = $5, image = $6
+            WHERE id = $7 AND vendor_id = $8 AND removed_at IS NULL
+            RETURNING id, name, price, category, description, quantity, image, vendor_id
+            "#,
+        )
+        .bind(name)
+        .bind(price)
+        .bind(category)
+        .bind(description)
+        .bind(quantity)
+        .bind(img)
+        .bind(product_id)
+        .bind(vendor_id)
+        .fetch_one(pool)
+        .await?
+    } else {
+        sqlx::query(
+            r#"
+            UPDATE products
+            SET name = $1, price = $2, category = $3, description = $4, quantity = $5
+            WHERE id = $6 AND vendor_id = $7 AND removed_at IS NULL
+            RETURNING id, name, price, category, description, quantity, image, vendor_id
+            "#,
+        )
+        .bind(name)
+        .bind(price)
+        .bind(category)
+        .bind(description)
+        .bind(quantity)
+        .bind(product_id)
+        .bind(vendor_id)
+        .fetch_one(pool)
+        .await?
+    };
+
+    let product = Product {
+        id: row.try_get::<i32, _>(0)? as u32,
+        name: row.try_get(1)?,
+        price: row.try_get::<f64, _>(2)?,
+        category: row.try_get(3)?,
+        description: row.try_get::<Option<String>, _>(4)?,
+        quantity: row.try_get(5)?,
+        image: row.try_get::<Option<String>, _>(6)?,
+        vendor_id: row.try_get::<i32, _>(7)? as u32,
+        tags: Vec::new(),
+        gallery: Vec::new(),
+        attributes: ProductAttributes::default(),
+    };
+
+    Ok(product)
+}
+
+pub async fn delete_product(pool: &PgPool, product_id: i32, vendor_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM products WHERE id = $1 AND vendor_id = $2")
+        .bind(product_id)
+        .bind(vendor_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// A product's (name, category, description, flag reason) as last moderated.
+/// A flagged product has a reason; `flag_reason` can only be empty if it isn't flagged.
+pub async fn get_product_listing(pool: &PgPool, product_id: i32) -> Result<Option<(String, String, Option<String>, Option<String>)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT name, category, description,
+               CASE WHEN flagged_at IS NULL THEN NULL ELSE COALESCE(flag_reason, '') END
+        FROM products WHERE id = $1 AND removed_at IS NULL
+        "#,
+    )
+    .bind(product_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Record the outcome of automated moderation on a product: `Some(reason)`
+/// holds it for admin review, `None` publishes it.
+pub async fn set_product_flag(pool: &PgPool, product_id: i32, reason: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE products
+        SET flagged_at = CASE WHEN $1::text IS NULL THEN NULL ELSE COALESCE(flagged_at, CURRENT_TIMESTAMP) END,
+            flag_reason = $1
+        WHERE id = $2
+        "#,
+    )
+    .bind(reason)
+    .bind(product_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Products held by automated moderation, oldest first.
+pub async fn get_flagged_products(pool: &PgPool) -> Result<Vec<crate::models::FlaggedProduct>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT p.id, p.name, p.category, p.description, p.vendor_id, u.username AS vendor_username,
+               p.flag_reason, p.flagged_at
+        FROM products p
+        JOIN users u ON u.id = p.vendor_id
+        WHERE p.flagged_at IS NOT NULL AND p.removed_at IS NULL
+        ORDER BY p.flagged_at, p.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(crate::models::FlaggedProduct {
+                id: row.try_get("id")?,
+                name: row.try_get("name")?,
+                category: row.try_get("category")?,
+                description: row.try_get("description")?,
+                vendor_id: row.try_get("vendor_id")?,
+                vendor_username: row.try_get("vendor_username")?,
+                flag_reason: row.try_get("flag_reason")?,
+                flagged_at: row.try_get("flagged_at")?,
+            })
+        })
+        .collect()
+}
+
+/// Publish a flagged product after review. Audited under `admin_id`; returns
+/// false if the product isn't waiting for review.
+pub async fn approve_flagged_product(pool: &PgPool, admin_id: i32, product_id: i32) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let approved: Option<(String, i32, Option<String>)> = sqlx::query_as(
+        r#"
+        WITH held AS (
+            SELECT id, flag_reason FROM products
+            WHERE id = $1 AND flagged_at IS NOT NULL AND removed_at IS NULL
+            FOR UPDATE
+        )
+        UPDATE products p SET flagged_at = NULL, flag_reason = NULL
+        FROM held
+        WHERE p.id = held.id
+        RETURNING p.name, p.vendor_id, held.flag_reason
+        "#,
+    )
+    .bind(product_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((name, vendor_id, reason)) = approved else {
+        return Ok(false);
+    };
+    sqlx::query("INSERT INTO audit_log (actor_id, action, details) VALUES ($1, $2, $3)")
+        .bind(admin_id)
+        .bind(format!("moderation.product_approve product {}", product_id))
+        .bind(format!("'{}' of vendor {}; flagged: {}", name, vendor_id, reason.as_deref().unwrap_or("no reason")))
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Moderation: hide any product regardless of owner. It stays attached to
+/// existing orders but leaves listings, carts and wishlists. Audited under
+/// `admin_id`; returns false if there is no such (unremoved) product.
+pub async fn remove_product_as_admin(pool: &PgPool, admin_id: i32, product_id: i32, reason: Option<&str>) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let removed: Option<(String, i32)> = sqlx::query_as(
+        "UPDATE products SET removed_at = CURRENT_TIMESTAMP, is_featured = FALSE
+         WHERE id = $1 AND removed_at IS NULL
+         RETURNING name, vendor_id"
+    )
+    .bind(product_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((name, vendor_id)) = removed else {
+        return Ok(false);
+    };
+
+    sqlx::query("DELETE FROM cart_items WHERE product_id = $1")
+        .bind(product_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM wishlist_items WHERE product_id = $1")
+        .bind(product_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO audit_log (actor_id, action, details) VALUES ($1, $2, $3)")
+        .bind(admin_id)
+        .bind(format!("moderation.product_remove product {}", product_id))
+        .bind(format!("'{}' of vendor {}; reason: {}", name, vendor_id, reason.unwrap_or("none given")))
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Moderation: correct a product's name, category or description (unset
+/// fields are kept), auditing each change under `admin_id`. None if there is
+/// no such (unremoved) product.
+pub async fn update_product_as_admin(
+    pool: &PgPool,
+    admin_id: i32,
+    product_id: i32,
+    changes: &crate::models::AdminProductUpdate,
+) -> Result<Option<Product>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let current: Option<(String, String, Option<String>)> = sqlx::query_as(
+        "SELECT name, category, description FROM products WHERE id = $1 AND removed_at IS NULL FOR UPDATE"
+    )
+    .bind(product_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((name, category, description)) = current else {
+        return Ok(None);
+    };
+
+    let row = sqlx::query(
+        r#"
+        UPDATE products
+        SET name = COALESCE($1, name), category = COALESCE($2, category), description = COALESCE($3, description)
+        WHERE id = $4
+        RETURNING id, name, price, category, description, quantity, image, vendor_id
+        "#,
+    )
+    .bind(&changes.name)
+    .bind(&changes.category)
+    .bind(&changes.description)
+    .bind(product_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let mut edits = Vec::new();
+    if let Some(new_name) = changes.name.as_ref().filter(|n| **n != name) {
+        edits.push(format!("name '{}' -> '{}'", name, new_name));
+    }
+    if let Some(new_category) = changes.category.as_ref().filter(|c| **c != category) {
+        edits.push(format!("category '{}' -> '{}'", category, new_category));
+    }
+    if changes.description.is_some() && changes.description != description {
+        edits.push("description changed".to_string());
+    }
+    sqlx::query("INSERT INTO audit_log (actor_id, action, details) VALUES ($1, $2, $3)")
+        .bind(admin_id)
+        .bind(format!("moderation.product_edit product {}", product_id))
+        .bind(format!(
+            "{}; reason: {}",
+            if edits.is_empty() { "no changes".to_string() } else { edits.join("; ") },
+            changes.reason.as_deref().unwrap_or("none given")
+        ))
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some(Product {
+        id: row.try_get::<i32, _>(0)? as u32,
+        name: row.try_get(1)?,
+        price: row.try_get::<f64, _>(2)?,
+        category: row.try_get(3)?,
+        description: row.try_get::<Option<String>, _>(4)?,
+        quantity: row.try_get(5)?,
+        image: row.try_get::<Option<String>, _>(6)?,
+        vendor_id: row.try_get::<i32, _>(7)? as u32,
+        tags: Vec::new(),
+        gallery: Vec::new(),
+        attributes: ProductAttributes::default(),
+    }))
+}
+
+/// Moderation: hide any review from product pages and ratings. Audited under
+/// `admin_id`; returns false if there is no such (unremoved) review.
+pub async fn remove_review_as_admin(pool: &PgPool, admin_id: i32, review_id: i32, reason: Option<&str>) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let removed: Option<(i32, i32, i32)> = sqlx::query_as(
+        "UPDATE reviews SET removed_at = CURRENT_TIMESTAMP
+         WHERE id = $1 AND removed_at IS NULL
+         RETURNING customer_id, product_id, rating"
+    )
+    .bind(review_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((customer_id, product_id, rating)) = removed else {
+        return Ok(false);
+    };
+
+    sqlx::query("INSERT INTO audit_log (actor_id, action, details) VALUES ($1, $2, $3)")
+        .bind(admin_id)
+        .bind(format!("moderation.review_remove review {}", review_id))
+        .bind(format!(
+            "{}-star review of product {} by customer {}; reason: {}",
+            rating, product_id, customer_id, reason.unwrap_or("none given")
+        ))
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn get_all_cart_items(pool: &PgPool) -> Result<Vec<CartItem>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            ci.id, ci.user_id, ci.product_id, ci.quantity,
+            p.id as p_id, p.name, p.price, p.category, p.description, p.image, p.quantity as p_quantity, p.vendor_id,
+            u.username as user_name
+        FROM cart_items ci
+        JOIN products p ON ci.product_id = p.id
+        JOIN users u ON ci.user_id = u.id
+        ORDER BY ci.updated_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut cart_items = Vec::new();
+    for row in rows {
         let product = Product {
             id: row.try_get::<i32, _>("p_id")? as u32,
             name: row.try_get("name")?,
             price: row.try_get::<f64, _>("price")?,
             category: row.try_get("category")?,
-            description: row.try_get::<Option<String>, _>("description")?,
-            image: row.try_get::<Option<String>, _>("image")?,
-            quantity: row.try_get("quantity")?,
-            vendor_id: row.try_get::<i32, _>("vendor_id")? as u32,
-        };
+            description: row.try_get::<Option<String>, _>("description")?,
+            image: row.try_get::<Option<String>, _>("image")?,
+            quantity: row.try_get("p_quantity")?,
+            vendor_id: row.try_get::<i32, _>("vendor_id")? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
+        };
+
+        let cart_item = CartItem {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            product_id: row.try_get("product_id")?,
+            quantity: row.try_get("quantity")?,
+            product,
+        };
+        cart_items.push(cart_item);
+    }
+
+    Ok(cart_items)
+}
+
+// Message functions
+pub async fn send_message(pool: &PgPool, sender_id: i32, receiver_id: i32, content: &str) -> Result<crate::models::Message, sqlx::Error> {
+    send_message_with_attachment(pool, sender_id, receiver_id, content, None).await
+}
+
+pub async fn send_message_with_attachment(
+    pool: &PgPool,
+    sender_id: i32,
+    receiver_id: i32,
+    content: &str,
+    attachment: Option<&str>,
+) -> Result<crate::models::Message, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO messages (sender_id, receiver_id, content, attachment)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, sender_id, receiver_id, content, attachment, is_read, to_char(created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at
+        "#,
+    )
+    .bind(sender_id)
+    .bind(receiver_id)
+    .bind(content)
+    .bind(attachment)
+    .fetch_one(pool)
+    .await?;
+
+    // Get usernames
+    let sender_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(sender_id)
+        .fetch_one(pool)
+        .await?;
+
+    let receiver_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(receiver_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(crate::models::Message {
+        id: row.try_get("id")?,
+        sender_id: row.try_get("sender_id")?,
+        receiver_id: row.try_get("receiver_id")?,
+        content: row.try_get("content")?,
+        attachment: row.try_get("attachment")?,
+        is_read: row.try_get("is_read")?,
+        created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "2025-01-01T00:00:00Z".to_string()),
+        sender_username,
+        receiver_username,
+    })
+}
+
+pub async fn get_messages_between_users(pool: &PgPool, user1_id: i32, user2_id: i32) -> Result<Vec<crate::models::Message>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            m.id, m.sender_id, m.receiver_id, m.content, m.attachment, m.is_read, 
+            to_char(m.created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at,
+            su.username as sender_username, ru.username as receiver_username
+        FROM messages m
+        JOIN users su ON m.sender_id = su.id
+        JOIN users ru ON m.receiver_id = ru.id
+        WHERE (m.sender_id = $1 AND m.receiver_id = $2) OR (m.sender_id = $2 AND m.receiver_id = $1)
+        ORDER BY m.created_at ASC
+        "#,
+    )
+    .bind(user1_id)
+    .bind(user2_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut messages = Vec::new();
+    for row in rows {
+        messages.push(crate::models::Message {
+            id: row.try_get("id")?,
+            sender_id: row.try_get("sender_id")?,
+            receiver_id: row.try_get("receiver_id")?,
+            content: row.try_get("content")?,
+            attachment: row.try_get("attachment")?,
+            is_read: row.try_get("is_read")?,
+            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "2025-01-01T00:00:00Z".to_string()),
+            sender_username: row.try_get("sender_username")?,
+            receiver_username: row.try_get("receiver_username")?,
+        });
+    }
+
+    Ok(messages)
+}
+
+pub async fn get_user_conversations(pool: &PgPool, user_id: i32) -> Result<Vec<crate::models::Conversation>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        WITH user_messages AS (
+            SELECT DISTINCT ON (
+                LEAST(sender_id, receiver_id),
+                GREATEST(sender_id, receiver_id)
+            )
+                *,
+                CASE
+                    WHEN sender_id = $1 THEN receiver_id
+                    ELSE sender_id
+                END as other_user_id
+            FROM messages
+            WHERE sender_id = $1 OR receiver_id = $1
+            ORDER BY LEAST(sender_id, receiver_id), GREATEST(sender_id, receiver_id), created_at DESC, id DESC
+        ),
+        unread_counts AS (
+            SELECT
+                sender_id as other_user_id,
+                COUNT(*) as unread_count
+            FROM messages
+            WHERE receiver_id = $1 AND is_read = FALSE
+            GROUP BY sender_id
+        )
+        SELECT
+            um.other_user_id as id,
+            u.username,
+            u.profile_image,
+            CASE
+                WHEN um.content = '' AND um.attachment IS NOT NULL THEN '[image]'
+                ELSE um.content
+            END as last_message,
+            to_char(um.created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as last_message_time,
+            COALESCE(uc.unread_count, 0) as unread_count
+        FROM user_messages um
+        JOIN users u ON u.id = um.other_user_id
+        LEFT JOIN unread_counts uc ON uc.other_user_id = um.other_user_id
+        WHERE NOT EXISTS (
+            SELECT 1 FROM blocks b
+            WHERE (b.blocker_id = $1 AND b.blocked_id = um.other_user_id)
+               OR (b.blocker_id = um.other_user_id AND b.blocked_id = $1)
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM conversation_state cs
+            WHERE cs.user_id = $1 AND cs.other_user_id = um.other_user_id
+              AND cs.archived_at >= um.created_at
+        )
+        ORDER BY um.created_at DESC, um.id DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut conversations = Vec::new();
+    for row in rows {
+        conversations.push(crate::models::Conversation {
+            id: row.try_get("id")?,
+            username: row.try_get("username")?,
+            profile_image: row.try_get("profile_image")?,
+            last_message: row.try_get("last_message")?,
+            last_message_time: row.try_get("last_message_time")?,
+            unread_count: row.try_get::<i64, _>("unread_count")? as i32,
+        });
+    }
+
+    Ok(conversations)
+}
+
+/// Hide the conversation with `other_user_id` from `user_id`'s list until a newer
+/// message arrives. The other party's view is unaffected. Returns false if there
+/// are no messages between them.
+pub async fn archive_conversation(pool: &PgPool, user_id: i32, other_user_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO conversation_state (user_id, other_user_id, archived_at)
+        SELECT $1, $2, CURRENT_TIMESTAMP
+        WHERE EXISTS (
+            SELECT 1 FROM messages
+            WHERE (sender_id = $1 AND receiver_id = $2) OR (sender_id = $2 AND receiver_id = $1)
+        )
+        ON CONFLICT (user_id, other_user_id) DO UPDATE SET archived_at = EXCLUDED.archived_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(other_user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Mark all messages sent by other_user_id to user_id as read. Returns the ids newly marked.
+pub async fn mark_messages_as_read(pool: &PgPool, user_id: i32, other_user_id: i32) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar(
+        "UPDATE messages SET is_read = TRUE, delivered_at = COALESCE(delivered_at, NOW())
+         WHERE sender_id = $1 AND receiver_id = $2 AND is_read = FALSE RETURNING id"
+    )
+    .bind(other_user_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Mark which of `message_ids` reached `user_id` from `sender_id`. Ids of other
+/// conversations, and messages already marked delivered, are left alone; the
+/// ones newly marked come back.
+pub async fn mark_messages_delivered(pool: &PgPool, user_id: i32, sender_id: i32, message_ids: &[i32]) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar(
+        "UPDATE messages SET delivered_at = NOW()
+         WHERE id = ANY($3) AND sender_id = $2 AND receiver_id = $1 AND delivered_at IS NULL
+         RETURNING id"
+    )
+    .bind(user_id)
+    .bind(sender_id)
+    .bind(message_ids)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn edit_message(pool: &PgPool, message_id: i32, user_id: i32, new_content: &str) -> Result<crate::models::Message, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        UPDATE messages 
+        SET content = $1, updated_at = NOW() 
+        WHERE id = $2 AND sender_id = $3
+        RETURNING id, sender_id, receiver_id, content, attachment, is_read, 
+                  to_char(COALESCE(updated_at, created_at), 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at
+        "#
+    )
+    .bind(new_content)
+    .bind(message_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    // Get sender and receiver usernames
+    let user_row = sqlx::query(
+        r#"
+        SELECT 
+            s.username as sender_username,
+            r.username as receiver_username
+        FROM messages m
+        JOIN users s ON m.sender_id = s.id
+        JOIN users r ON m.receiver_id = r.id
+        WHERE m.id = $1
+        "#
+    )
+    .bind(message_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(crate::models::Message {
+        id: row.try_get("id")?,
+        sender_id: row.try_get("sender_id")?,
+        receiver_id: row.try_get("receiver_id")?,
+        content: row.try_get("content")?,
+        attachment: row.try_get("attachment")?,
+        is_read: row.try_get("is_read")?,
+        created_at: row.try_get("created_at")?,
+        sender_username: user_row.try_get("sender_username")?,
+        receiver_username: user_row.try_get("receiver_username")?,
+    })
+}
+
+pub async fn delete_message(pool: &PgPool, message_id: i32, user_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "DELETE FROM messages WHERE id = $1 AND sender_id = $2"
+    )
+    .bind(message_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// Follow functions
+/// Follow a vendor. Following again is not an error: the existing follow comes
+/// back with `false` for "newly created". RowNotFound if `vendor_id` isn't a vendor.
+pub async fn follow_vendor(pool: &PgPool, follower_id: i32, vendor_id: i32) -> Result<(crate::models::Follow, bool), sqlx::Error> {
+    // A concurrent unfollow can remove the row between the insert and the lookup; try again then
+    for _ in 0..5 {
+        let inserted = sqlx::query(
+            r#"
+            WITH inserted AS (
+                INSERT INTO follows (follower_id, vendor_id)
+                SELECT $1, id FROM users WHERE id = $2 AND role = 'Vendor' AND deleted_at IS NULL
+                ON CONFLICT (follower_id, vendor_id) DO NOTHING
+                RETURNING id, follower_id, vendor_id, created_at
+            )
+            SELECT
+                f.id, f.follower_id, f.vendor_id, f.created_at::text,
+                fu.username as follower_username, vu.username as vendor_username
+            FROM inserted f
+            JOIN users fu ON f.follower_id = fu.id
+            JOIN users vu ON f.vendor_id = vu.id
+            "#,
+        )
+        .bind(follower_id)
+        .bind(vendor_id)
+        .fetch_optional(pool)
+        .await?;
+        if let Some(row) = inserted {
+            return Ok((follow_from_row(&row)?, true));
+        }
+
+        let existing = sqlx::query(
+            r#"
+            SELECT
+                f.id, f.follower_id, f.vendor_id, f.created_at::text,
+                fu.username as follower_username, vu.username as vendor_username
+            FROM follows f
+            JOIN users fu ON f.follower_id = fu.id
+            JOIN users vu ON f.vendor_id = vu.id
+            WHERE f.follower_id = $1 AND f.vendor_id = $2
+            "#,
+        )
+        .bind(follower_id)
+        .bind(vendor_id)
+        .fetch_optional(pool)
+        .await?;
+        if let Some(row) = existing {
+            return Ok((follow_from_row(&row)?, false));
+        }
+
+        let is_vendor: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND role = 'Vendor' AND deleted_at IS NULL)"
+        )
+        .bind(vendor_id)
+        .fetch_one(pool)
+        .await?;
+        if !is_vendor {
+            return Err(sqlx::Error::RowNotFound);
+        }
+    }
+    Err(sqlx::Error::RowNotFound)
+}
+
+fn follow_from_row(row: &sqlx::postgres::PgRow) -> Result<crate::models::Follow, sqlx::Error> {
+    Ok(crate::models::Follow {
+        id: row.try_get("id")?,
+        follower_id: row.try_get("follower_id")?,
+        vendor_id: row.try_get("vendor_id")?,
+        created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+        follower_username: row.try_get("follower_username")?,
+        vendor_username: row.try_get("vendor_username")?,
+    })
+}
+
+/// Stop following a vendor. Returns whether there was a follow to remove.
+pub async fn unfollow_vendor(pool: &PgPool, follower_id: i32, vendor_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM follows WHERE follower_id = $1 AND vendor_id = $2")
+        .bind(follower_id)
+        .bind(vendor_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// (followers, following) counts of a user.
+pub async fn get_follow_counts(pool: &PgPool, user_id: i32) -> Result<(i64, i64), sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM follows WHERE vendor_id = $1),
+            (SELECT COUNT(*) FROM follows WHERE follower_id = $1)
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// How many users follow a vendor.
+pub async fn get_follower_count(pool: &PgPool, vendor_id: i32) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM follows WHERE vendor_id = $1")
+        .bind(vendor_id)
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn is_following(pool: &PgPool, follower_id: i32, vendor_id: i32) -> Result<bool, sqlx::Error> {
+    let result: Option<(i32,)> = sqlx::query_as(
+        "SELECT id FROM follows WHERE follower_id = $1 AND vendor_id = $2"
+    )
+    .bind(follower_id)
+    .bind(vendor_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(result.is_some())
+}
+
+pub async fn get_user_follows(pool: &PgPool, user_id: i32) -> Result<Vec<crate::models::Follow>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            f.id, f.follower_id, f.vendor_id, f.created_at::text,
+            fu.username as follower_username, vu.username as vendor_username
+        FROM follows f
+        JOIN users fu ON f.follower_id = fu.id
+        JOIN users vu ON f.vendor_id = vu.id
+        WHERE f.follower_id = $1
+        ORDER BY f.created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut follows = Vec::new();
+    for row in rows {
+        follows.push(crate::models::Follow {
+            id: row.try_get("id")?,
+            follower_id: row.try_get("follower_id")?,
+            vendor_id: row.try_get("vendor_id")?,
+            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+            follower_username: row.try_get("follower_username")?,
+            vendor_username: row.try_get("vendor_username")?,
+        });
+    }
+
+    Ok(follows)
+}
+
+pub async fn get_vendor_followers(pool: &PgPool, vendor_id: i32) -> Result<Vec<crate::models::Follow>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            f.id, f.follower_id, f.vendor_id, f.created_at::text,
+            fu.username as follower_username, vu.username as vendor_username
+        FROM follows f
+        JOIN users fu ON f.follower_id = fu.id
+        JOIN users vu ON f.vendor_id = vu.id
+        WHERE f.vendor_id = $1
+        ORDER BY f.created_at DESC
+        "#,
+    )
+    .bind(vendor_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut follows = Vec::new();
+    for row in rows {
+        follows.push(crate::models::Follow {
+            id: row.try_get("id")?,
+            follower_id: row.try_get("follower_id")?,
+            vendor_id: row.try_get("vendor_id")?,
+            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+            follower_username: row.try_get("follower_username")?,
+            vendor_username: row.try_get("vendor_username")?,
+        });
+    }
+
+    Ok(follows)
+}
+
+// Review functions
+pub async fn create_review(
+    pool: &PgPool,
+    customer_id: i32,
+    product_id: i32,
+    rating: i32,
+    comment: Option<&str>
+) -> Result<crate::models::Review, sqlx::Error> {
+    // Get vendor_id from product
+    let vendor_id: i32 = sqlx::query_scalar("SELECT vendor_id FROM products WHERE id = $1")
+        .bind(product_id)
+        .fetch_one(pool)
+        .await?;
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO reviews (customer_id, product_id, vendor_id, rating, comment)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, customer_id, product_id, vendor_id, rating, comment, created_at::text
+        "#,
+    )
+    .bind(customer_id)
+    .bind(product_id)
+    .bind(vendor_id)
+    .bind(rating)
+    .bind(comment)
+    .fetch_one(pool)
+    .await?;
+
+    // Get usernames and product name
+    let customer_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(customer_id)
+        .fetch_one(pool)
+        .await?;
+
+    let product_name = sqlx::query_scalar("SELECT name FROM products WHERE id = $1")
+        .bind(product_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(crate::models::Review {
+        id: row.try_get("id")?,
+        customer_id: row.try_get("customer_id")?,
+        product_id: row.try_get("product_id")?,
+        vendor_id: row.try_get("vendor_id")?,
+        rating: row.try_get("rating")?,
+        comment: row.try_get("comment")?,
+        created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+        customer_username,
+        product_name,
+        helpful_count: 0,
+        reply: None,
+    })
+}
+
+/// Reviews of a product, newest first, or most helpful first with `helpful_first`.
+/// A product's reviews in `sort` order, optionally only the (limit, offset) page.
+pub async fn get_product_reviews(
+    pool: &PgPool,
+    product_id: i32,
+    sort: crate::models::ReviewSort,
+    page: Option<(i64, i64)>,
+) -> Result<Vec<crate::models::Review>, sqlx::Error> {
+    use crate::models::ReviewSort;
+
+    let order_by = match sort {
+        ReviewSort::Newest => "r.created_at DESC, r.id DESC",
+        ReviewSort::Highest => "r.rating DESC, r.created_at DESC, r.id DESC",
+        ReviewSort::Lowest => "r.rating ASC, r.created_at DESC, r.id DESC",
+        ReviewSort::Helpful => "helpful_count DESC, r.created_at DESC, r.id DESC",
+    };
+    let (limit, offset) = match page {
+        Some((limit, offset)) => (Some(limit), offset),
+        None => (None, 0),
+    };
+    let rows = sqlx::query(&format!(
+        r#"
+        SELECT
+            r.id, r.customer_id, r.product_id, r.vendor_id, r.rating, r.comment, r.created_at::text,
+            u.username as customer_username, p.name as product_name,
+            (SELECT COUNT(*) FROM review_votes v WHERE v.review_id = r.id AND v.helpful) as helpful_count,
+            rr.id as reply_id, rr.vendor_id as reply_vendor_id, rr.content as reply_content,
+            to_char(rr.created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as reply_created_at,
+            to_char(rr.updated_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as reply_updated_at
+        FROM reviews r
+        JOIN users u ON r.customer_id = u.id
+        JOIN products p ON r.product_id = p.id
+        LEFT JOIN review_replies rr ON rr.review_id = r.id
+        WHERE r.product_id = $1 AND r.removed_at IS NULL
+        ORDER BY {}
+        LIMIT $2 OFFSET $3
+        "#,
+        order_by
+    ))
+    .bind(product_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    let mut reviews = Vec::new();
+    for row in rows {
+        reviews.push(crate::models::Review {
+            id: row.try_get("id")?,
+            customer_id: row.try_get("customer_id")?,
+            product_id: row.try_get("product_id")?,
+            vendor_id: row.try_get("vendor_id")?,
+            rating: row.try_get("rating")?,
+            comment: row.try_get("comment")?,
+            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+            customer_username: row.try_get("customer_username")?,
+            product_name: row.try_get("product_name")?,
+            helpful_count: row.try_get::<i64, _>("helpful_count")? as i32,
+            reply: review_reply_from_row(&row)?,
+        });
+    }
+
+    Ok(reviews)
+}
+
+/// Average rating and number of reviews of a product.
+pub async fn get_product_review_summary(pool: &PgPool, product_id: i32) -> Result<crate::models::ReviewSummary, sqlx::Error> {
+    let (average_rating, review_count): (Option<f64>, i64) = sqlx::query_as(
+        "SELECT AVG(rating)::FLOAT8, COUNT(*) FROM reviews WHERE product_id = $1 AND removed_at IS NULL"
+    )
+    .bind(product_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(crate::models::ReviewSummary { average_rating, review_count })
+}
+
+pub async fn get_customer_reviews(pool: &PgPool, customer_id: i32) -> Result<Vec<crate::models::Review>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            r.id, r.customer_id, r.product_id, r.vendor_id, r.rating, r.comment, r.created_at::text,
+            u.username as customer_username, p.name as product_name,
+            (SELECT COUNT(*) FROM review_votes v WHERE v.review_id = r.id AND v.helpful) as helpful_count,
+            rr.id as reply_id, rr.vendor_id as reply_vendor_id, rr.content as reply_content,
+            to_char(rr.created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as reply_created_at,
+            to_char(rr.updated_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as reply_updated_at
+        FROM reviews r
+        JOIN users u ON r.customer_id = u.id
+        JOIN products p ON r.product_id = p.id
+        LEFT JOIN review_replies rr ON rr.review_id = r.id
+        WHERE r.customer_id = $1 AND r.removed_at IS NULL
+        ORDER BY r.created_at DESC
+        "#,
+    )
+    .bind(customer_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut reviews = Vec::new();
+    for row in rows {
+        reviews.push(crate::models::Review {
+            id: row.try_get("id")?,
+            customer_id: row.try_get("customer_id")?,
+            product_id: row.try_get("product_id")?,
+            vendor_id: row.try_get("vendor_id")?,
+            rating: row.try_get("rating")?,
+            comment: row.try_get("comment")?,
+            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+            customer_username: row.try_get("customer_username")?,
+            product_name: row.try_get("product_name")?,
+            helpful_count: row.try_get::<i64, _>("helpful_count")? as i32,
+            reply: review_reply_from_row(&row)?,
+        });
+    }
+
+    Ok(reviews)
+}
+
+// Shipping order functions
+
+/// Place a single-item order: a new order holding one shipping order.
+pub async fn create_shipping_order(
+    pool: &PgPool,
+    customer_id: i32,
+    product_id: i32,
+    quantity: i32,
+    shipping_address: &str
+) -> Result<crate::models::ShippingOrder, sqlx::Error> {
+    let order_id = create_order(pool, customer_id, shipping_address, None).await?;
+    add_order_item(pool, order_id, product_id, quantity).await
+}
+
+/// The order already created for a payment, with the vendors it has line items
+/// from, so finishing a partly processed payment adds to it instead of starting another.
+pub async fn get_payment_order(pool: &PgPool, payment_transaction_id: i32) -> Result<Option<(i32, Vec<i32>)>, sqlx::Error> {
+    let order_id: Option<i32> = sqlx::query_scalar(
+        "SELECT id FROM orders WHERE payment_transaction_id = $1 ORDER BY id LIMIT 1"
+    )
+    .bind(payment_transaction_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(order_id) = order_id else { return Ok(None) };
+
+    let vendor_ids: Vec<i32> = sqlx::query_scalar("SELECT DISTINCT vendor_id FROM shipping_orders WHERE order_id = $1")
+        .bind(order_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(Some((order_id, vendor_ids)))
+}
+
+/// Start an empty order for `customer_id`; returns its id. Items are added with `add_order_item`.
+pub async fn create_order(
+    pool: &PgPool,
+    customer_id: i32,
+    shipping_address: &str,
+    payment_transaction_id: Option<i32>,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar(
+        "INSERT INTO orders (customer_id, shipping_address, payment_transaction_id) VALUES ($1, $2, $3) RETURNING id"
+    )
+    .bind(customer_id)
+    .bind(shipping_address)
+    .bind(payment_transaction_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// Add a product to an order as its own shipping order, shipped to the order's
+/// address, and deduct it from stock.
+pub async fn add_order_item(
+    pool: &PgPool,
+    order_id: i32,
+    product_id: i32,
+    quantity: i32,
+) -> Result<crate::models::ShippingOrder, sqlx::Error> {
+    insert_order_item(pool, order_id, product_id, quantity, true).await
+}
+
+/// Like `add_order_item` for stock already taken out by a converted
+/// reservation, so it isn't deducted twice.
+pub async fn add_reserved_order_item(
+    pool: &PgPool,
+    order_id: i32,
+    product_id: i32,
+    quantity: i32,
+) -> Result<crate::models::ShippingOrder, sqlx::Error> {
+    insert_order_item(pool, order_id, product_id, quantity, false).await
+}
+
+async fn insert_order_item(
+    pool: &PgPool,
+    order_id: i32,
+    product_id: i32,
+    quantity: i32,
+    deduct_stock: bool,
+) -> Result<crate::models::ShippingOrder, sqlx::Error> {
+    let (customer_id, shipping_address): (i32, Option<String>) =
+        sqlx::query_as("SELECT customer_id, shipping_address FROM orders WHERE id = $1")
+            .bind(order_id)
+            .fetch_one(pool)
+            .await?;
+
+    // Get product details
+    let product_row: (i32, String, f64) = sqlx::query_as(
+        "SELECT vendor_id, name, price FROM products WHERE id = $1"
+    )
+    .bind(product_id)
+    .fetch_one(pool)
+    .await?;
+
+    let vendor_id = product_row.0;
+    let product_name = product_row.1;
+    let price = product_row.2;
+    let total_amount = price * quantity as f64;
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO shipping_orders (customer_id, product_id, vendor_id, quantity, total_amount, shipping_address, order_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, customer_id, product_id, vendor_id, quantity, total_amount, shipping_status,
+                  tracking_number, shipping_address, created_at::text, updated_at::text
+        "#,
+    )
+    .bind(customer_id)
+    .bind(product_id)
+    .bind(vendor_id)
+    .bind(quantity)
+    .bind(total_amount)
+    .bind(&shipping_address)
+    .bind(order_id)
+    .fetch_one(pool)
+    .await?;
+
+    // Get usernames
+    let customer_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(customer_id)
+        .fetch_one(pool)
+        .await?;
+
+    let vendor_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(vendor_id)
+        .fetch_one(pool)
+        .await?;
+
+    // Deduct inventory from product stock
+    if deduct_stock {
+        if let Err(e) = deduct_product_inventory(pool, product_id, quantity).await {
+            eprintln!("Warning: Failed to deduct inventory for product {}: {:?}", product_id, e);
+        }
+    }
+
+    Ok(crate::models::ShippingOrder {
+        id: row.try_get("id")?,
+        customer_id: row.try_get("customer_id")?,
+        product_id: row.try_get("product_id")?,
+        vendor_id: row.try_get("vendor_id")?,
+        quantity: row.try_get("quantity")?,
+        total_amount: row.try_get("total_amount")?,
+        shipping_status: row.try_get("shipping_status")?,
+        tracking_number: row.try_get("tracking_number")?,
+        shipping_address: row.try_get("shipping_address")?,
+        created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+        updated_at: row.try_get::<String, _>("updated_at").unwrap_or_else(|_| "?".to_string()),
+        customer_username,
+        vendor_username,
+        product_name,
+        customer_verified: false,
+        payment_released: false,
+        verification_requested_at: None,
+        shipping_fee: 0.0,
+        discount: 0.0,
+        order_id: Some(order_id),
+        mpesa_receipt_number: None,
+        transaction_date: None,
+    })
+}
+
+/// An order with its line items in the order they were added, or None if it doesn't exist.
+pub async fn get_order(pool: &PgPool, order_id: i32) -> Result<Option<crate::models::Order>, sqlx::Error> {
+    let order = sqlx::query(
+        r#"
+        SELECT o.id, o.customer_id, o.shipping_address, o.payment_transaction_id,
+               pt.mpesa_receipt_number,
+               to_char(pt.transaction_date AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS transaction_date,
+               to_char(o.created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS created_at
+        FROM orders o
+        LEFT JOIN payment_transactions pt ON pt.id = o.payment_transaction_id
+        WHERE o.id = $1
+        "#,
+    )
+    .bind(order_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(order) = order else { return Ok(None) };
+
+    let rows = sqlx::query(
+        r#"
+        SELECT so.id, so.product_id, p.name AS product_name, so.vendor_id, vu.username AS vendor_username,
+               so.quantity, so.total_amount, so.shipping_fee, so.discount, COALESCE(so.shipping_status, 'pending') AS shipping_status,
+               so.tracking_number,
+               to_char(so.updated_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS updated_at
+        FROM shipping_orders so
+        JOIN products p ON so.product_id = p.id
+        JOIN users vu ON so.vendor_id = vu.id
+        WHERE so.order_id = $1
+        ORDER BY so.id
+        "#,
+    )
+    .bind(order_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut items = Vec::new();
+    for row in rows {
+        items.push(crate::models::OrderItem {
+            id: row.try_get("id")?,
+            product_id: row.try_get("product_id")?,
+            product_name: row.try_get("product_name")?,
+            vendor_id: row.try_get("vendor_id")?,
+            vendor_username: row.try_get("vendor_username")?,
+            quantity: row.try_get("quantity")?,
+            total_amount: row.try_get("total_amount")?,
+            shipping_fee: row.try_get("shipping_fee")?,
+            discount: row.try_get("discount")?,
+            shipping_status: row.try_get("shipping_status")?,
+            tracking_number: row.try_get("tracking_number")?,
+            updated_at: row.try_get::<Option<String>, _>("updated_at")?.unwrap_or_default(),
+        });
+    }
+
+    let status = match items.first() {
+        Some(first) if items.iter().all(|item| item.shipping_status == first.shipping_status) => first.shipping_status.clone(),
+        Some(_) => "partially_fulfilled".to_string(),
+        None => "pending".to_string(),
+    };
+    Ok(Some(crate::models::Order {
+        id: order.try_get("id")?,
+        customer_id: order.try_get("customer_id")?,
+        shipping_address: order.try_get("shipping_address")?,
+        payment_transaction_id: order.try_get("payment_transaction_id")?,
+        mpesa_receipt_number: order.try_get("mpesa_receipt_number")?,
+        transaction_date: order.try_get("transaction_date")?,
+        created_at: order.try_get::<Option<String>, _>("created_at")?.unwrap_or_default(),
+        status,
+        items_total: items.iter().map(|item| item.total_amount).sum(),
+        shipping_total: items.iter().map(|item| item.shipping_fee).sum(),
+        items,
+    }))
+}
+
+/**
+ * Deduct inventory quantity from a product
+ * Updates the product's quantity after a purchase
+ */
+pub async fn deduct_product_inventory(
+    pool: &PgPool,
+    product_id: i32,
+    quantity_to_deduct: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE products SET quantity = GREATEST(0, quantity - $1) WHERE id = $2"
+    )
+    .bind(quantity_to_deduct)
+    .bind(product_id)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+/// Note that an order moved to `status`, for the vendor's fulfilment metrics.
+pub async fn record_shipping_status(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    order_id: i32,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO shipping_status_history (shipping_order_id, status) VALUES ($1, LOWER($2))")
+        .bind(order_id)
+        .bind(status)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/**
+ * Return a cancelled order's quantity to its product's stock.
+ * Each order is restocked at most once; returns false if it already was.
+ */
+pub async fn restock_product_inventory(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    order_id: i32,
+) -> Result<bool, sqlx::Error> {
+    let order: Option<(i32, i32)> = sqlx::query_as(
+        "UPDATE shipping_orders SET inventory_restocked = TRUE WHERE id = $1 AND inventory_restocked = FALSE RETURNING product_id, quantity"
+    )
+    .bind(order_id)
+    .fetch_optional(&mut **tx)
+    .await?;
+    let Some((product_id, quantity)) = order else {
+        return Ok(false);
+    };
+
+    sqlx::query("UPDATE products SET quantity = quantity + $1 WHERE id = $2")
+        .bind(quantity)
+        .bind(product_id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(true)
+}
+
+/// Why stock couldn't be reserved for a checkout
+#[derive(Debug)]
+pub enum ReservationError {
+    /// Fewer than the requested units are available
+    OutOfStock { product_id: i32, name: String, available: i32 },
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for ReservationError {
+    fn from(err: sqlx::Error) -> Self {
+        ReservationError::Database(err)
+    }
+}
+
+/**
+ * Hold stock for a checkout
+ *
+ * Takes each (product_id, quantity) out of the product's available stock and
+ * records it under `reference` until `hold_minutes` from now. Either every
+ * item is reserved or none is. Products are locked in id order so concurrent
+ * checkouts can't oversell or deadlock. Any stock `user_id` still holds from
+ * an earlier checkout is released first, so one customer can't tie up stock
+ * by starting checkout over and over.
+ */
+pub async fn reserve_stock(
+    pool: &PgPool,
+    user_id: i32,
+    reference: &str,
+    items: &[(i32, i32)],
+    hold_minutes: i64,
+) -> Result<(), ReservationError> {
+    let mut quantities = std::collections::BTreeMap::new();
+    for &(product_id, quantity) in items {
+        *quantities.entry(product_id).or_insert(0) += quantity;
+    }
+
+    release_held_stock(pool, HeldStock::User(user_id)).await?;
+
+    let mut tx = pool.begin().await?;
+    for (product_id, quantity) in quantities {
+        let reserved = sqlx::query(
+            "UPDATE products SET quantity = quantity - $1 WHERE id = $2 AND quantity >= $1 AND removed_at IS NULL"
+        )
+        .bind(quantity)
+        .bind(product_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if reserved.rows_affected() == 0 {
+            let (name, available): (String, i32) =
+                sqlx::query_as("SELECT name, quantity FROM products WHERE id = $1")
+                    .bind(product_id)
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .unwrap_or_default();
+            return Err(ReservationError::OutOfStock { product_id, name, available });
+        }
+
+        sqlx::query(
+            "INSERT INTO stock_reservations (checkout_request_id, product_id, quantity, expires_at, user_id)
+             VALUES ($1, $2, $3, NOW() + make_interval(mins => $4), $5)"
+        )
+        .bind(reference)
+        .bind(product_id)
+        .bind(quantity)
+        .bind(hold_minutes as i32)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Move a reservation made under a provisional reference to the checkout
+/// request id M-Pesa assigned.
+pub async fn attach_stock_reservation(
+    pool: &PgPool,
+    reference: &str,
+    checkout_request_id: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE stock_reservations SET checkout_request_id = $2 WHERE checkout_request_id = $1")
+        .bind(reference)
+        .bind(checkout_request_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Mark a paid checkout's held stock as sold. Returns the products it covered,
+/// including ones converted by an earlier pass over the same payment; their
+/// order items must not deduct stock again.
+pub async fn convert_stock_reservation(
+    pool: &PgPool,
+    checkout_request_id: &str,
+) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        WITH converted AS (
+            UPDATE stock_reservations SET status = 'converted'
+            WHERE checkout_request_id = $1 AND status = 'held' RETURNING product_id
+        )
+        SELECT product_id FROM converted
+        UNION
+        SELECT product_id FROM stock_reservations WHERE checkout_request_id = $1 AND status = 'converted'
+        "#
+    )
+    .bind(checkout_request_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Return a checkout's held stock, e.g. after its payment failed.
+/// Returns the number of reservations released.
+pub async fn release_stock_reservation(pool: &PgPool, checkout_request_id: &str) -> Result<i64, sqlx::Error> {
+    release_held_stock(pool, HeldStock::Checkout(checkout_request_id)).await
+}
+
+/// Return the stock of every reservation past its expiry.
+/// Returns the number of reservations released.
+pub async fn release_expired_stock_reservations(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    release_held_stock(pool, HeldStock::Expired).await
+}
+
+/// Which held reservations `release_held_stock` gives back.
+enum HeldStock<'a> {
+    Checkout(&'a str),
+    User(i32),
+    Expired,
+}
+
+async fn release_held_stock(pool: &PgPool, which: HeldStock<'_>) -> Result<i64, sqlx::Error> {
+    let (checkout_request_id, user_id) = match which {
+        HeldStock::Checkout(checkout_request_id) => (Some(checkout_request_id), None),
+        HeldStock::User(user_id) => (None, Some(user_id)),
+        HeldStock::Expired => (None, None),
+    };
+    sqlx::query_scalar(
+        r#"
+        WITH released AS (
+            UPDATE stock_reservations SET status = 'released'
+            WHERE status = 'held'
+              AND CASE WHEN $1::text IS NOT NULL THEN checkout_request_id = $1
+                       WHEN $2::int IS NOT NULL THEN user_id = $2
+                       ELSE expires_at < NOW() END
+            RETURNING product_id, quantity
+        ), restocked AS (
+            UPDATE products p SET quantity = p.quantity + r.quantity
+            FROM (SELECT product_id, SUM(quantity)::int AS quantity FROM released GROUP BY product_id) r
+            WHERE p.id = r.product_id
+        )
+        SELECT COUNT(*) FROM released
+        "#
+    )
+    .bind(checkout_request_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+}
+
+/**
+ * Cancel an order that hasn't shipped yet: restock its product and, if it was
+ * paid for, refund the goods and shipping to the customer's wallet.
+ * Returns the amount refunded, or None if the order is no longer pending.
+ */
+pub async fn cancel_pending_order(pool: &PgPool, order_id: i32) -> Result<Option<f64>, sqlx::Error> {
+    close_pending_order(pool, order_id, "cancelled").await
+}
+
+/// Decline an order the vendor hasn't accepted, restocking and refunding it as
+/// for a cancellation. Returns the amount refunded, or None if it's no longer pending.
+pub async fn decline_pending_order(pool: &PgPool, order_id: i32) -> Result<Option<f64>, sqlx::Error> {
+    close_pending_order(pool, order_id, "declined").await
+}
+
+async fn close_pending_order(pool: &PgPool, order_id: i32, status: &str) -> Result<Option<f64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let order: Option<(i32, f64, bool)> = sqlx::query_as(
+        r#"
+        UPDATE shipping_orders SET shipping_status = $2, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND COALESCE(shipping_status, 'pending') = 'pending' AND payment_released = FALSE
+        RETURNING customer_id, total_amount + shipping_fee, payment_transaction_id IS NOT NULL
+        "#,
+    )
+    .bind(order_id)
+    .bind(status)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((customer_id, amount, paid)) = order else {
+        return Ok(None);
+    };
+
+    record_shipping_status(&mut tx, order_id, status).await?;
+    restock_product_inventory(&mut tx, order_id).await?;
+
+    let refunded = if paid { amount } else { 0.0 };
+    if refunded > 0.0 {
+        sqlx::query("UPDATE users SET wallet_balance = wallet_balance + $1 WHERE id = $2")
+            .bind(refunded)
+            .bind(customer_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO wallet_ledger (user_id, entry_type, amount, reference) VALUES ($1, 'order_refund', $2, $3)")
+            .bind(customer_id)
+            .bind(refunded)
+            .bind(format!("order:{}", order_id))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(Some(refunded))
+}
+
+/// Accept a pending order, moving it to `processing`. Returns false if it's no longer pending.
+pub async fn accept_pending_order(pool: &PgPool, order_id: i32) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let accepted = sqlx::query(
+        r#"
+        UPDATE shipping_orders SET shipping_status = 'processing', accepted_at = NOW(), updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND COALESCE(shipping_status, 'pending') = 'pending'
+        "#,
+    )
+    .bind(order_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if accepted {
+        record_shipping_status(&mut tx, order_id, "processing").await?;
+    }
+
+    tx.commit().await?;
+    Ok(accepted)
+}
+
+/// A vendor's orders still waiting to be accepted, oldest first, with the time
+/// each is declined automatically (`window_hours` after it was placed).
+pub async fn get_pending_acceptance_orders(
+    pool: &PgPool,
+    vendor_id: i32,
+    window_hours: i64,
+) -> Result<Vec<crate::models::PendingAcceptanceOrder>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT so.id, so.order_id, so.product_id, p.name AS product_name, so.quantity, so.total_amount,
+               c.username AS customer_username, so.created_at::text AS created_at,
+               to_char((so.created_at + make_interval(hours => $2)) AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS accept_by
+        FROM shipping_orders so
+        JOIN products p ON so.product_id = p.id
+        JOIN users c ON so.customer_id = c.id
+        WHERE so.vendor_id = $1 AND COALESCE(so.shipping_status, 'pending') = 'pending'
+        ORDER BY so.created_at, so.id
+        "#,
+    )
+    .bind(vendor_id)
+    .bind(window_hours as i32)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(crate::models::PendingAcceptanceOrder {
+                id: row.try_get("id")?,
+                order_id: row.try_get("order_id")?,
+                product_id: row.try_get("product_id")?,
+                product_name: row.try_get("product_name")?,
+                quantity: row.try_get("quantity")?,
+                total_amount: row.try_get("total_amount")?,
+                customer_username: row.try_get("customer_username")?,
+                created_at: row.try_get("created_at")?,
+                accept_by: row.try_get("accept_by")?,
+            })
+        })
+        .collect()
+}
+
+/// Customer id, vendor id, quantity and product name of a shipping order, or None if it doesn't exist.
+pub async fn get_shipping_order_summary(pool: &PgPool, order_id: i32) -> Result<Option<(i32, i32, i32, String)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT so.customer_id, so.vendor_id, so.quantity, p.name
+        FROM shipping_orders so JOIN products p ON so.product_id = p.id
+        WHERE so.id = $1
+        "#,
+    )
+    .bind(order_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Ids of pending orders placed more than `window_hours` ago.
+pub async fn get_overdue_pending_orders(pool: &PgPool, window_hours: i64) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT id FROM shipping_orders
+        WHERE COALESCE(shipping_status, 'pending') = 'pending'
+          AND created_at < NOW() - make_interval(hours => $1)
+        ORDER BY id
+        "#,
+    )
+    .bind(window_hours as i32)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_customer_shipping_orders(pool: &PgPool, customer_id: i32) -> Result<Vec<crate::models::ShippingOrder>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            so.id, so.customer_id, so.product_id, so.vendor_id, so.quantity, so.total_amount,
+            so.shipping_status, so.tracking_number, so.shipping_address, so.created_at::text, so.updated_at::text,
+            so.customer_verified, so.payment_released, so.verification_requested_at::text, so.shipping_fee, so.discount, so.order_id,
+            pt.mpesa_receipt_number,
+            to_char(pt.transaction_date AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS transaction_date,
+            cu.username as customer_username, vu.username as vendor_username, p.name as product_name
+        FROM shipping_orders so
+        JOIN users cu ON so.customer_id = cu.id
+        JOIN users vu ON so.vendor_id = vu.id
+        JOIN products p ON so.product_id = p.id
+        LEFT JOIN payment_transactions pt ON pt.id = so.payment_transaction_id
+        WHERE so.customer_id = $1
+        ORDER BY so.created_at DESC
+        "#,
+    )
+    .bind(customer_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut orders = Vec::new();
+    for row in rows {
+        orders.push(crate::models::ShippingOrder {
+            id: row.try_get("id")?,
+            customer_id: row.try_get("customer_id")?,
+            product_id: row.try_get("product_id")?,
+            vendor_id: row.try_get("vendor_id")?,
+            quantity: row.try_get("quantity")?,
+            total_amount: row.try_get("total_amount")?,
+            shipping_status: row.try_get("shipping_status")?,
+            tracking_number: row.try_get("tracking_number")?,
+            shipping_address: row.try_get("shipping_address")?,
+            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+            updated_at: row.try_get::<String, _>("updated_at").unwrap_or_else(|_| "?".to_string()),
+            customer_username: row.try_get("customer_username")?,
+            vendor_username: row.try_get("vendor_username")?,
+            product_name: row.try_get("product_name")?,
+            customer_verified: row.try_get("customer_verified").unwrap_or(false),
+            payment_released: row.try_get("payment_released").unwrap_or(false),
+            verification_requested_at: row.try_get("verification_requested_at").ok(),
+            shipping_fee: row.try_get("shipping_fee")?,
+            discount: row.try_get("discount")?,
+            order_id: row.try_get("order_id")?,
+            mpesa_receipt_number: row.try_get("mpesa_receipt_number")?,
+            transaction_date: row.try_get("transaction_date")?,
+        });
+    }
+
+    Ok(orders)
+}
+
+/// One page of orders across the platform matching `filter`, newest first,
+/// with the total number of matches.
+pub async fn search_orders(
+    pool: &PgPool,
+    filter: &crate::models::OrderSearchFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<crate::models::ShippingOrder>, i64), sqlx::Error> {
+    const MATCHES: &str = r#"
+        FROM shipping_orders so
+        JOIN users cu ON so.customer_id = cu.id
+        JOIN users vu ON so.vendor_id = vu.id
+        JOIN products p ON so.product_id = p.id
+        LEFT JOIN payment_transactions pt ON pt.id = so.payment_transaction_id
+        WHERE ($1::int IS NULL OR so.customer_id = $1)
+          AND ($2::int IS NULL OR so.vendor_id = $2)
+          AND ($3::text IS NULL OR LOWER(so.shipping_status) = LOWER($3))
+          AND ($4::date IS NULL OR so.created_at >= $4)
+          AND ($5::date IS NULL OR so.created_at < $5)
+          AND ($6::float8 IS NULL OR so.total_amount >= $6)
+          AND ($7::float8 IS NULL OR so.total_amount <= $7)
+    "#;
+
+    let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) {}", MATCHES))
+        .bind(filter.customer_id)
+        .bind(filter.vendor_id)
+        .bind(filter.status.as_deref())
+        .bind(filter.created_from)
+        .bind(filter.created_before)
+        .bind(filter.min_amount)
+        .bind(filter.max_amount)
+        .fetch_one(pool)
+        .await?;
+
+    let rows = sqlx::query(&format!(
+        r#"
+        SELECT
+            so.id, so.customer_id, so.product_id, so.vendor_id, so.quantity, so.total_amount,
+            so.shipping_status, so.tracking_number, so.shipping_address,
+            to_char(so.created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS created_at,
+            to_char(so.updated_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS updated_at,
+            so.customer_verified, so.payment_released, so.verification_requested_at::text, so.shipping_fee, so.discount, so.order_id,
+            pt.mpesa_receipt_number,
+            to_char(pt.transaction_date AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS transaction_date,
+            cu.username as customer_username, vu.username as vendor_username, p.name as product_name
+        {}
+        ORDER BY so.created_at DESC, so.id DESC
+        LIMIT $8 OFFSET $9
+        "#,
+        MATCHES
+    ))
+    .bind(filter.customer_id)
+    .bind(filter.vendor_id)
+    .bind(filter.status.as_deref())
+    .bind(filter.created_from)
+    .bind(filter.created_before)
+    .bind(filter.min_amount)
+    .bind(filter.max_amount)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    let mut orders = Vec::new();
+    for row in rows {
+        orders.push(crate::models::ShippingOrder {
+            id: row.try_get("id")?,
+            customer_id: row.try_get("customer_id")?,
+            product_id: row.try_get("product_id")?,
+            vendor_id: row.try_get("vendor_id")?,
+            quantity: row.try_get("quantity")?,
+            total_amount: row.try_get("total_amount")?,
+            shipping_status: row.try_get::<Option<String>, _>("shipping_status")?.unwrap_or_default(),
+            tracking_number: row.try_get("tracking_number")?,
+            shipping_address: row.try_get("shipping_address")?,
+            created_at: row.try_get::<Option<String>, _>("created_at")?.unwrap_or_default(),
+            updated_at: row.try_get::<Option<String>, _>("updated_at")?.unwrap_or_default(),
+            customer_username: row.try_get("customer_username")?,
+            vendor_username: row.try_get("vendor_username")?,
+            product_name: row.try_get("product_name")?,
+            customer_verified: row.try_get("customer_verified")?,
+            payment_released: row.try_get("payment_released")?,
+            verification_requested_at: row.try_get("verification_requested_at")?,
+            shipping_fee: row.try_get("shipping_fee")?,
+            discount: row.try_get("discount")?,
+            order_id: row.try_get("order_id")?,
+            mpesa_receipt_number: row.try_get("mpesa_receipt_number")?,
+            transaction_date: row.try_get("transaction_date")?,
+        });
+    }
+
+    Ok((orders, total))
+}
+
+pub async fn get_vendor_shipping_orders(pool: &PgPool, vendor_id: i32) -> Result<Vec<crate::models::ShippingOrder>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            so.id, so.customer_id, so.product_id, so.vendor_id, so.quantity, so.total_amount,
+            so.shipping_status, so.tracking_number, so.shipping_address, so.created_at::text, so.updated_at::text,
+            so.customer_verified, so.payment_released, so.verification_requested_at::text, so.shipping_fee, so.discount, so.order_id,
+            pt.mpesa_receipt_number,
+            to_char(pt.transaction_date AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS transaction_date,
+            cu.username as customer_username, vu.username as vendor_username, p.name as product_name
+        FROM shipping_orders so
+        JOIN users cu ON so.customer_id = cu.id
+        JOIN users vu ON so.vendor_id = vu.id
+        JOIN products p ON so.product_id = p.id
+        LEFT JOIN payment_transactions pt ON pt.id = so.payment_transaction_id
+        WHERE so.vendor_id = $1
+        ORDER BY so.created_at DESC
+        "#,
+    )
+    .bind(vendor_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut orders = Vec::new();
+    for row in rows {
+        orders.push(crate::models::ShippingOrder {
+            id: row.try_get("id")?,
+            customer_id: row.try_get("customer_id")?,
+            product_id: row.try_get("product_id")?,
+            vendor_id: row.try_get("vendor_id")?,
+            quantity: row.try_get("quantity")?,
+            total_amount: row.try_get("total_amount")?,
+            shipping_status: row.try_get("shipping_status")?,
+            tracking_number: row.try_get("tracking_number")?,
+            shipping_address: row.try_get("shipping_address")?,
+            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
+            updated_at: row.try_get::<String, _>("updated_at").unwrap_or_else(|_| "?".to_string()),
+            customer_username: row.try_get("customer_username")?,
+            vendor_username: row.try_get("vendor_username")?,
+            product_name: row.try_get("product_name")?,
+            customer_verified: row.try_get("customer_verified").unwrap_or(false),
+            payment_released: row.try_get("payment_released").unwrap_or(false),
+            verification_requested_at: row.try_get("verification_requested_at").ok(),
+            shipping_fee: row.try_get("shipping_fee")?,
+            discount: row.try_get("discount")?,
+            order_id: row.try_get("order_id")?,
+            mpesa_receipt_number: row.try_get("mpesa_receipt_number")?,
+            transaction_date: row.try_get("transaction_date")?,
+        });
+    }
+
+    Ok(orders)
+}
+
+pub async fn update_shipping_status(
+    pool: &PgPool,
+    order_id: i32,
+    shipping_status: &str,
+    tracking_number: Option<&str>
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let query = if let Some(tracking) = tracking_number {
+        sqlx::query(
+            "UPDATE shipping_orders SET shipping_status = $1, tracking_number = $2, updated_at = CURRENT_TIMESTAMP WHERE id = $3"
+        )
+        .bind(shipping_status)
+        .bind(tracking)
+        .bind(order_id)
+    } else {
+        sqlx::query(
+            "UPDATE shipping_orders SET shipping_status = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2"
+        )
+        .bind(shipping_status)
+        .bind(order_id)
+    };
+    if query.execute(&mut *tx).await?.rows_affected() > 0 {
+        record_shipping_status(&mut tx, order_id, shipping_status).await?;
+    }
+
+    if shipping_status.eq_ignore_ascii_case("cancelled") {
+        restock_product_inventory(&mut tx, order_id).await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VendorProfile {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    pub profile_image: Option<String>,
+    pub verified: bool,
+    pub total_purchases: i64,
+    pub total_revenue: f64,
+    pub follower_count: i64,
+    /// Smallest subtotal of this vendor's items accepted at checkout; 0 for none
+    pub min_order_value: f64,
+    /// On holiday: products stay on the storefront but can't be bought
+    pub is_paused: bool,
+    /// Only customers who have ordered from the vendor can start a conversation
+    pub buyers_only_messages: bool,
+    /// Average hours from order to shipping; null until enough orders have shipped
+    pub avg_ship_hours: Option<f64>,
+    /// Average hours to reply to a customer's message; null until enough replies
+    pub avg_response_hours: Option<f64>,
+    /// From the viewer to the vendor; omitted unless both have coordinates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+}
+
+pub async fn get_vendor_profile(pool: &PgPool, vendor_id: i32) -> Result<VendorProfile, sqlx::Error> {
+    // Get vendor basic info
+    let vendor_row = sqlx::query(
+        "SELECT id, username, email, profile_image, verified, min_order_value, is_paused, buyers_only_messages FROM users WHERE id = $1 AND role = 'Vendor' AND deleted_at IS NULL"
+    )
+    .bind(vendor_id)
+    .fetch_one(pool)
+    .await?;
+
+    // Get total purchases (number of shipping orders)
+    let total_purchases: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM shipping_orders WHERE vendor_id = $1"
+    )
+    .bind(vendor_id)
+    .fetch_one(pool)
+    .await?;
+
+    // Get total revenue
+    let total_revenue: f64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(total_amount), 0.0) FROM shipping_orders WHERE vendor_id = $1"
+    )
+    .bind(vendor_id)
+    .fetch_one(pool)
+    .await?;
+
+    let follower_count = get_follower_count(pool, vendor_id).await?;
+    let (avg_ship_hours, avg_response_hours) = get_vendor_responsiveness(pool, vendor_id).await?;
+
+    Ok(VendorProfile {
+        id: vendor_row.try_get("id")?,
+        username: vendor_row.try_get("username")?,
+        email: vendor_row.try_get("email")?,
+        profile_image: vendor_row.try_get("profile_image")?,
+        verified: vendor_row.try_get("verified")?,
+        total_purchases,
+        total_revenue,
+        follower_count,
+        min_order_value: vendor_row.try_get("min_order_value")?,
+        is_paused: vendor_row.try_get("is_paused")?,
+        buyers_only_messages: vendor_row.try_get("buyers_only_messages")?,
+        avg_ship_hours,
+        avg_response_hours,
+        distance_km: None,
+    })
+}
+
+/// Fewest orders or conversations a vendor average is shown for.
+pub const RESPONSIVENESS_MIN_SAMPLES: i64 = 3;
+
+/**
+ * Average hours from order to shipping and from a customer's message to the
+ * vendor's reply, each None until there are `RESPONSIVENESS_MIN_SAMPLES` to
+ * average. An order counts as shipped the first time it is marked shipped or
+ * delivered. For messages, each run of customer messages is timed from its
+ * first message to the vendor's next reply; unanswered ones don't count.
+ */
+pub async fn get_vendor_responsiveness(pool: &PgPool, vendor_id: i32) -> Result<(Option<f64>, Option<f64>), sqlx::Error> {
+    let (shipped, avg_ship_hours): (i64, Option<f64>) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*), AVG(EXTRACT(EPOCH FROM (h.shipped_at - s.created_at)) / 3600.0)::FLOAT8
+        FROM shipping_orders s
+        JOIN (
+            SELECT shipping_order_id, MIN(changed_at) AS shipped_at
+            FROM shipping_status_history
+            WHERE status IN ('shipped', 'delivered')
+            GROUP BY shipping_order_id
+        ) h ON h.shipping_order_id = s.id
+        WHERE s.vendor_id = $1
+        "#,
+    )
+    .bind(vendor_id)
+    .fetch_one(pool)
+    .await?;
+
+    let (answered, avg_response_hours): (i64, Option<f64>) = sqlx::query_as(
+        r#"
+        WITH thread AS (
+            SELECT CASE WHEN sender_id = $1 THEN receiver_id ELSE sender_id END AS other_id,
+                   sender_id = $1 AS from_vendor,
+                   created_at,
+                   LAG(sender_id = $1) OVER (
+                       PARTITION BY CASE WHEN sender_id = $1 THEN receiver_id ELSE sender_id END
+                       ORDER BY created_at, id
+                   ) AS after_vendor
+            FROM messages
+            WHERE (sender_id = $1 OR receiver_id = $1) AND sender_id <> receiver_id
+        ),
+        asked AS (
+            SELECT other_id, created_at FROM thread
+            WHERE NOT from_vendor AND after_vendor IS DISTINCT FROM FALSE
+        )
+        SELECT COUNT(reply.replied_at),
+               AVG(EXTRACT(EPOCH FROM (reply.replied_at - a.created_at)) / 3600.0)::FLOAT8
+        FROM asked a
+        CROSS JOIN LATERAL (
+            SELECT MIN(t.created_at) AS replied_at FROM thread t
+            WHERE t.other_id = a.other_id AND t.from_vendor AND t.created_at >= a.created_at
+        ) reply
+        "#,
+    )
+    .bind(vendor_id)
+    .fetch_one(pool)
+    .await?;
+
+    let enough = |samples: i64, avg: Option<f64>| avg.filter(|_| samples >= RESPONSIVENESS_MIN_SAMPLES).map(|hours| (hours * 10.0).round() / 10.0);
+    Ok((enough(shipped, avg_ship_hours), enough(answered, avg_response_hours)))
+}
+
+// Payment Transaction Functions
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_payment_transaction(
+    pool: &PgPool,
+    user_id: i32,
+    checkout_request_id: &str,
+    merchant_request_id: &str,
+    phone_number: &str,
+    amount: f64,
+    cart_item_ids: Option<&str>,
+    request_id: Option<&str>,
+) -> Result<i32, sqlx::Error> {
+    let row: (i32,) = sqlx::query_as(
+        "INSERT INTO payment_transactions (user_id, checkout_request_id, merchant_request_id, phone_number, amount, cart_item_ids, request_id)
+         VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id"
+    )
+    .bind(user_id)
+    .bind(checkout_request_id)
+    .bind(merchant_request_id)
+    .bind(phone_number)
+    .bind(amount)
+    .bind(cart_item_ids)
+    .bind(request_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.0)
+}
+
+const CALLBACK_FAILURE_COLUMNS: &str = r#"
+    id, checkout_request_id, payload, error, retry_count,
+    to_char(created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at,
+    to_char(last_retried_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as last_retried_at,
+    to_char(resolved_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as resolved_at
+"#;
+
+fn callback_failure_from_row(row: &sqlx::postgres::PgRow) -> Result<crate::models::CallbackFailure, sqlx::Error> {
+    Ok(crate::models::CallbackFailure {
+        id: row.try_get("id")?,
+        checkout_request_id: row.try_get("checkout_request_id")?,
+        payload: row.try_get("payload")?,
+        error: row.try_get("error")?,
+        retry_count: row.try_get("retry_count")?,
+        created_at: row.try_get("created_at")?,
+        last_retried_at: row.try_get("last_retried_at")?,
+        resolved_at: row.try_get("resolved_at")?,
+    })
+}
+
+/// Keep an M-Pesa callback that couldn't be applied, with its raw payload, for retrying.
+pub async fn record_callback_failure(pool: &PgPool, checkout_request_id: &str, payload: &str, error: &str) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar("INSERT INTO callback_failures (checkout_request_id, payload, error) VALUES ($1, $2, $3) RETURNING id")
+        .bind(checkout_request_id)
+        .bind(payload)
+        .bind(error)
+        .fetch_one(pool)
+        .await
+}
+
+/// Failed callbacks, oldest first; `resolved` picks retried-successfully or still-open ones.
+pub async fn get_callback_failures(pool: &PgPool, resolved: Option<bool>) -> Result<Vec<crate::models::CallbackFailure>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM callback_failures WHERE ($1::boolean IS NULL OR (resolved_at IS NOT NULL) = $1) ORDER BY created_at, id",
+        CALLBACK_FAILURE_COLUMNS
+    ))
+    .bind(resolved)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(callback_failure_from_row).collect()
+}
+
+pub async fn get_callback_failure(pool: &PgPool, failure_id: i32) -> Result<Option<crate::models::CallbackFailure>, sqlx::Error> {
+    let row = sqlx::query(&format!("SELECT {} FROM callback_failures WHERE id = $1", CALLBACK_FAILURE_COLUMNS))
+        .bind(failure_id)
+        .fetch_optional(pool)
+        .await?;
+    row.as_ref().map(callback_failure_from_row).transpose()
+}
+
+/// Record a retry of a failed callback: resolved when `error` is None, otherwise
+/// the failure stays open with the latest error.
+pub async fn record_callback_retry(pool: &PgPool, failure_id: i32, error: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE callback_failures
+        SET retry_count = retry_count + 1, last_retried_at = NOW(),
+            error = COALESCE($2, error),
+            resolved_at = CASE WHEN $2::text IS NULL THEN NOW() ELSE NULL END
+        WHERE id = $1
+        "#,
+    )
+    .bind(failure_id)
+    .bind(error)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Queue an email whose first send attempt failed; it's retried from `next_attempt_at`
+/// until `expires_at`, if given.
+pub async fn queue_email(
+    pool: &PgPool,
+    recipient: &str,
+    email: &crate::email_templates::RenderedEmail,
+    error: &str,
+    next_attempt_at: chrono::DateTime<chrono::Utc>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar(
+        "INSERT INTO email_outbox (recipient, subject, body, html, last_error, next_attempt_at, expires_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id",
+    )
+    .bind(recipient)
+    .bind(&email.subject)
+    .bind(&email.body)
+    .bind(&email.html)
+    .bind(error)
+    .bind(next_attempt_at)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await
+}
+
+/// Stop retrying queued emails past their `expires_at`. Returns how many expired.
+pub async fn expire_queued_emails(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("UPDATE email_outbox SET status = 'expired' WHERE status = 'pending' AND expires_at <= NOW()")
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Queued emails due for another attempt, oldest first, as (id, recipient, email, attempts so far).
+pub async fn get_due_emails(pool: &PgPool, limit: i64) -> Result<Vec<(i32, String, crate::email_templates::RenderedEmail, i32)>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, recipient, subject, body, html, attempts FROM email_outbox
+         WHERE status = 'pending' AND next_attempt_at <= NOW() AND (expires_at IS NULL OR expires_at > NOW())
+         ORDER BY next_attempt_at, id LIMIT $1",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok((
+                row.try_get("id")?,
+                row.try_get("recipient")?,
+                crate::email_templates::RenderedEmail {
+                    subject: row.try_get("subject")?,
+                    body: row.try_get("body")?,
+                    html: row.try_get("html")?,
+                },
+                row.try_get("attempts")?,
+            ))
+        })
+        .collect()
+}
+
+pub async fn mark_email_sent(pool: &PgPool, email_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE email_outbox SET status = 'sent', attempts = attempts + 1, sent_at = NOW() WHERE id = $1")
+        .bind(email_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Record another failed attempt. The email is tried again at `next_attempt_at`,
+/// or marked failed when that is None.
+pub async fn record_email_failure(
+    pool: &PgPool,
+    email_id: i32,
+    error: &str,
+    next_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE email_outbox
+        SET attempts = attempts + 1, last_error = $2,
+            status = CASE WHEN $3::timestamptz IS NULL THEN 'failed' ELSE 'pending' END,
+            next_attempt_at = COALESCE($3, next_attempt_at)
+        WHERE id = $1
+        "#,
+    )
+    .bind(email_id)
+    .bind(error)
+    .bind(next_attempt_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Queued emails with the given status, newest first.
+pub async fn get_queued_emails(pool: &PgPool, status: &str) -> Result<Vec<crate::models::QueuedEmail>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, recipient, subject, status, attempts, last_error,
+               to_char(next_attempt_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as next_attempt_at,
+               to_char(created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at,
+               to_char(sent_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as sent_at
+        FROM email_outbox WHERE status = $1
+        ORDER BY created_at DESC, id DESC
+        "#,
+    )
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(crate::models::QueuedEmail {
+                id: row.try_get("id")?,
+                recipient: row.try_get("recipient")?,
+                subject: row.try_get("subject")?,
+                status: row.try_get("status")?,
+                attempts: row.try_get("attempts")?,
+                last_error: row.try_get("last_error")?,
+                next_attempt_at: row.try_get("next_attempt_at")?,
+                created_at: row.try_get("created_at")?,
+                sent_at: row.try_get("sent_at")?,
+            })
+        })
+        .collect()
+}
+
+/// Give a failed email a fresh set of attempts, starting now. False unless it had
+/// failed and hasn't expired since.
+pub async fn requeue_failed_email(pool: &PgPool, email_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE email_outbox SET status = 'pending', attempts = 0, next_attempt_at = NOW()
+         WHERE id = $1 AND status = 'failed' AND (expires_at IS NULL OR expires_at > NOW())",
+    )
+    .bind(email_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Update a transaction's status. `transaction_date` uses M-Pesa's `YYYYMMDDHHMMSS`
+/// format, which is East Africa Time rather than UTC.
+pub async fn update_payment_transaction(
+    pool: &PgPool,
+    checkout_request_id: &str,
+    status: &str,
+    mpesa_receipt_number: Option<&str>,
+    transaction_date: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE payment_transactions SET status = $1, mpesa_receipt_number = $2,
+         transaction_date = to_timestamp($3, 'YYYYMMDDHH24MISS')::timestamp AT TIME ZONE 'Africa/Nairobi', updated_at = CURRENT_TIMESTAMP
+         WHERE checkout_request_id = $4"
+    )
+    .bind(status)
+    .bind(mpesa_receipt_number)
+    .bind(transaction_date)
+    .bind(checkout_request_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_payment_transaction_by_checkout_request_id(
+    pool: &PgPool,
+    checkout_request_id: &str,
+) -> Result<crate::models::PaymentTransaction, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT id, user_id, checkout_request_id, merchant_request_id, mpesa_receipt_number,
+         phone_number, amount::float8 AS amount, status, transaction_date::text AS transaction_date, cart_item_ids,
+         request_id, attempt_id, created_at::text, updated_at::text
+         FROM payment_transactions WHERE checkout_request_id = $1"
+    )
+    .bind(checkout_request_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(crate::models::PaymentTransaction {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        checkout_request_id: row.try_get("checkout_request_id")?,
+        merchant_request_id: row.try_get("merchant_request_id")?,
+        mpesa_receipt_number: row.try_get("mpesa_receipt_number")?,
+        phone_number: row.try_get("phone_number")?,
+        amount: row.try_get("amount")?,
+        status: row.try_get::<String, _>("status")?.parse().unwrap_or(PaymentStatus::Unknown),
+        transaction_date: row.try_get("transaction_date")?,
+        cart_item_ids: row.try_get("cart_item_ids")?,
+        request_id: row.try_get("request_id")?,
+        attempt_id: row.try_get("attempt_id")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+/// (resends so far, seconds since the latest prompt) of a payment attempt,
+/// identified by the checkout request id of its first STK push.
+pub async fn get_payment_attempt_activity(pool: &PgPool, attempt_id: &str) -> Result<(i64, f64), sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT COUNT(*) FILTER (WHERE attempt_id = $1),
+               COALESCE(EXTRACT(EPOCH FROM NOW() - MAX(created_at))::float8, 0)
+        FROM payment_transactions
+        WHERE checkout_request_id = $1 OR attempt_id = $1
+        "#,
+    )
+    .bind(attempt_id)
+    .fetch_one(pool)
+    .await
+}
+
+/**
+ * Record a resent STK push
+ *
+ * The new prompt takes over from `previous`: it joins the same attempt, carries
+ * its cart items, shipping and held stock, and `previous` is cancelled so only
+ * the latest prompt is live. Returns false, changing nothing, if `previous`
+ * is no longer initiated.
+ */
+pub async fn record_stk_resend(
+    pool: &PgPool,
+    previous: &crate::models::PaymentTransaction,
+    checkout_request_id: &str,
+    merchant_request_id: &str,
+    request_id: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let superseded = sqlx::query(
+        "UPDATE payment_transactions SET status = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2 AND status = $3"
+    )
+    .bind(PaymentStatus::Cancelled.to_string())
+    .bind(previous.id)
+    .bind(PaymentStatus::Initiated.to_string())
+    .execute(&mut *tx)
+    .await?;
+    if superseded.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO payment_transactions
+            (user_id, checkout_request_id, merchant_request_id, phone_number, amount, cart_item_ids, shipping_charges, coupon_discounts, request_id, attempt_id)
+        SELECT user_id, $2, $3, phone_number, amount, cart_item_ids, shipping_charges, coupon_discounts, $4, COALESCE(attempt_id, checkout_request_id)
+        FROM payment_transactions WHERE id = $1
+        "#,
+    )
+    .bind(previous.id)
+    .bind(checkout_request_id)
+    .bind(merchant_request_id)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("UPDATE stock_reservations SET checkout_request_id = $2 WHERE checkout_request_id = $1")
+        .bind(&previous.checkout_request_id)
+        .bind(checkout_request_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// What became of a success callback for a prompt a resend had superseded.
+#[derive(Debug, PartialEq)]
+pub enum SupersededPayment {
+    /// No other prompt of the attempt was paid, so this one took back the cart
+    /// and held stock from the live prompt (now cancelled) and completes as usual
+    Reclaimed,
+    /// Another prompt of the attempt was already paid, so this one's amount went
+    /// to the customer's wallet (0.0 if an earlier callback already refunded it)
+    Refunded(f64),
+}
+
+/**
+ * Settle a success callback for `superseded`, a prompt cancelled by
+ * `record_stk_resend` that the customer paid anyway. Returns None when no
+ * later prompt replaced it, i.e. it was cancelled for some other reason.
+ */
+pub async fn settle_superseded_payment(
+    pool: &PgPool,
+    superseded: &crate::models::PaymentTransaction,
+) -> Result<Option<SupersededPayment>, sqlx::Error> {
+    let attempt_id = superseded.attempt_id.as_deref().unwrap_or(&superseded.checkout_request_id);
+    let mut tx = pool.begin().await?;
+
+    let siblings: Vec<(String, String)> = sqlx::query_as(
+        "SELECT checkout_request_id, status FROM payment_transactions
+         WHERE (attempt_id = $1 OR checkout_request_id = $1) AND id <> $2
+         FOR UPDATE"
+    )
+    .bind(attempt_id)
+    .bind(superseded.id)
+    .fetch_all(&mut *tx)
+    .await?;
+    if siblings.is_empty() {
+        return Ok(None);
+    }
+
+    let completed = PaymentStatus::Completed.to_string();
+    if siblings.iter().any(|(_, status)| *status == completed) {
+        let refunded: Option<f64> = sqlx::query_scalar(
+            "INSERT INTO wallet_ledger (user_id, entry_type, amount, reference)
+             SELECT $1, 'payment_refund', $2, $3
+             WHERE NOT EXISTS (SELECT 1 FROM wallet_ledger WHERE entry_type = 'payment_refund' AND reference = $3)
+             RETURNING amount"
+        )
+        .bind(superseded.user_id)
+        .bind(superseded.amount)
+        .bind(format!("payment:{}", superseded.checkout_request_id))
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(amount) = refunded {
+            sqlx::query("UPDATE users SET wallet_balance = wallet_balance + $1 WHERE id = $2")
+                .bind(amount)
+                .bind(superseded.user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        return Ok(Some(SupersededPayment::Refunded(refunded.unwrap_or(0.0))));
+    }
+
+    let initiated = PaymentStatus::Initiated.to_string();
+    let live: Vec<String> = siblings
+        .into_iter()
+        .filter(|(_, status)| *status == initiated)
+        .map(|(checkout_request_id, _)| checkout_request_id)
+        .collect();
+    sqlx::query(
+        "UPDATE payment_transactions SET status = $1, updated_at = CURRENT_TIMESTAMP WHERE checkout_request_id = ANY($2)"
+    )
+    .bind(PaymentStatus::Cancelled.to_string())
+    .bind(&live)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE stock_reservations SET checkout_request_id = $2 WHERE checkout_request_id = ANY($1)")
+        .bind(&live)
+        .bind(&superseded.checkout_request_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE payment_transactions SET status = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2")
+        .bind(&initiated)
+        .bind(superseded.id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some(SupersededPayment::Reclaimed))
+}
+
+/// Mark the caller's still-initiated transaction as cancelled.
+/// Returns false when nothing was updated (not found, not theirs, or no longer pending).
+pub async fn cancel_payment_transaction(
+    pool: &PgPool,
+    checkout_request_id: &str,
+    user_id: i32,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE payment_transactions SET status = $1, updated_at = CURRENT_TIMESTAMP
+         WHERE checkout_request_id = $2 AND user_id = $3 AND status = $4"
+    )
+    .bind(PaymentStatus::Cancelled.to_string())
+    .bind(checkout_request_id)
+    .bind(user_id)
+    .bind(PaymentStatus::Initiated.to_string())
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_user_payment_transactions(
+    pool: &PgPool,
+    user_id: i32,
+) -> Result<Vec<crate::models::PaymentTransaction>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, user_id, checkout_request_id, merchant_request_id, mpesa_receipt_number,
+         phone_number, amount::float8 AS amount, status, transaction_date::text AS transaction_date, cart_item_ids,
+         request_id, attempt_id, created_at::text, updated_at::text
+         FROM payment_transactions WHERE user_id = $1 ORDER BY created_at DESC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut transactions = Vec::new();
+    for row in rows {
+        transactions.push(crate::models::PaymentTransaction {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            checkout_request_id: row.try_get("checkout_request_id")?,
+            merchant_request_id: row.try_get("merchant_request_id")?,
+            mpesa_receipt_number: row.try_get("mpesa_receipt_number")?,
+            phone_number: row.try_get("phone_number")?,
+            amount: row.try_get("amount")?,
+            status: row.try_get::<String, _>("status")?.parse().unwrap_or(PaymentStatus::Unknown),
+            transaction_date: row.try_get("transaction_date")?,
+            cart_item_ids: row.try_get("cart_item_ids")?,
+            request_id: row.try_get("request_id")?,
+            attempt_id: row.try_get("attempt_id")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        });
+    }
+
+    Ok(transactions)
+}
+
+// Get vendor sales report
+pub async fn get_vendor_sales_report(
+    pool: &PgPool,
+    vendor_id: i32,
+) -> Result<crate::models::VendorSalesReport, sqlx::Error> {
+    // Get total sales and order count
+    let summary = sqlx::query(
+        r#"
+        SELECT 
+            COALESCE(SUM(total_amount), 0) as total_sales,
+            COALESCE(SUM(tax_amount), 0) as total_tax,
+            COUNT(*) as total_orders
+        FROM shipping_orders
+        WHERE vendor_id = $1 AND shipping_status NOT IN ('cancelled', 'declined')
+        "#,
+    )
+    .bind(vendor_id)
+    .fetch_one(pool)
+    .await?;
+
+    let total_sales: f64 = summary.try_get("total_sales")?;
+    let total_tax: f64 = summary.try_get("total_tax")?;
+    let total_orders: i64 = summary.try_get("total_orders")?;
+
+    // Get sales by product
+    let product_rows = sqlx::query(
+        r#"
+        SELECT 
+            p.id as product_id,
+            p.name as product_name,
+            SUM(so.quantity) as quantity_sold,
+            SUM(so.total_amount) as total_revenue
+        FROM shipping_orders so
+        JOIN products p ON so.product_id = p.id
+        WHERE so.vendor_id = $1 AND so.shipping_status NOT IN ('cancelled', 'declined')
+        GROUP BY p.id, p.name
+        ORDER BY total_revenue DESC
+        "#,
+    )
+    .bind(vendor_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut sales_by_product = Vec::new();
+    for row in product_rows {
+        sales_by_product.push(crate::models::ProductSales {
+            product_id: row.try_get("product_id")?,
+            product_name: row.try_get("product_name")?,
+            quantity_sold: row.try_get::<i64, _>("quantity_sold")? as i32,
+            total_revenue: row.try_get("total_revenue")?,
+        });
+    }
+
+    let views_by_day = get_vendor_daily_views(pool, vendor_id, 30).await?;
+
+    Ok(crate::models::VendorSalesReport {
+        total_sales,
+        total_orders: total_orders as i32,
+        total_profit: total_sales, // For now, profit = sales
+        total_tax: crate::models::round_cents(total_tax),
+        sales_by_product,
+        total_views: views_by_day.iter().map(|d| d.views).sum(),
+        views_by_day,
+    })
+}
+
+// Get customer purchase report
+pub async fn get_customer_purchase_report(
+    pool: &PgPool,
+    customer_id: i32,
+) -> Result<crate::models::CustomerPurchaseReport, sqlx::Error> {
+    // Get total spent and order count
+    let summary = sqlx::query(
+        r#"
+        SELECT 
+            COALESCE(SUM(total_amount), 0) as total_spent,
+            COUNT(*) as total_orders
+        FROM shipping_orders
+        WHERE customer_id = $1
+        "#,
+    )
+    .bind(customer_id)
+    .fetch_one(pool)
+    .await?;
+
+    let total_spent: f64 = summary.try_get("total_spent")?;
+    let total_orders: i64 = summary.try_get("total_orders")?;
+
+    // Get purchases by category
+    let category_rows = sqlx::query(
+        r#"
+        SELECT 
+            p.category,
+            SUM(so.total_amount) as total_spent,
+            SUM(so.quantity) as quantity
+        FROM shipping_orders so
+        JOIN products p ON so.product_id = p.id
+        WHERE so.customer_id = $1
+        GROUP BY p.category
+        ORDER BY total_spent DESC
+        "#,
+    )
+    .bind(customer_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut purchases_by_category = Vec::new();
+    for row in category_rows {
+        purchases_by_category.push(crate::models::CategoryPurchase {
+            category: row.try_get("category")?,
+            total_spent: row.try_get("total_spent")?,
+            quantity: row.try_get::<i64, _>("quantity")? as i32,
+        });
+    }
 
-        let cart_item = CartItem {
-            id: row.try_get("id")?,
-            user_id: row.try_get("user_id")?,
-            product_id: row.try_get("product_id")?,
-            quantity: row.try_get("quantity")?,
-            product,
-        };
-        cart_items.push(cart_item);
+    // Get purchases by vendor
+    let vendor_rows = sqlx::query(
+        r#"
+        SELECT 
+            u.id as vendor_id,
+            u.username as vendor_name,
+            SUM(so.total_amount) as total_spent,
+            COUNT(*) as order_count
+        FROM shipping_orders so
+        JOIN users u ON so.vendor_id = u.id
+        WHERE so.customer_id = $1
+        GROUP BY u.id, u.username
+        ORDER BY total_spent DESC
+        "#,
+    )
+    .bind(customer_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut purchases_by_vendor = Vec::new();
+    for row in vendor_rows {
+        purchases_by_vendor.push(crate::models::VendorPurchase {
+            vendor_id: row.try_get("vendor_id")?,
+            vendor_name: row.try_get("vendor_name")?,
+            total_spent: row.try_get("total_spent")?,
+            order_count: row.try_get::<i64, _>("order_count")? as i32,
+        });
     }
 
-    Ok(cart_items)
+    Ok(crate::models::CustomerPurchaseReport {
+        total_spent,
+        total_orders: total_orders as i32,
+        purchases_by_category,
+        purchases_by_vendor,
+         })
+}
+
+/**
+ * Mark order as delivered and request customer verification
+ */
+/// Vendor id and current shipping status of each existing order among `order_ids`.
+pub async fn get_order_vendors_and_statuses(
+    pool: &PgPool,
+    order_ids: &[i32],
+) -> Result<std::collections::HashMap<i32, (i32, String)>, sqlx::Error> {
+    let rows: Vec<(i32, i32, String)> = sqlx::query_as(
+        "SELECT id, vendor_id, COALESCE(shipping_status, 'pending') FROM shipping_orders WHERE id = ANY($1)"
+    )
+        .bind(order_ids)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(|(id, vendor_id, status)| (id, (vendor_id, status))).collect())
+}
+
+/// Set the status of several of a vendor's orders in one transaction, requesting
+/// customer verification for any that become delivered. Returns the ids updated.
+pub async fn bulk_update_shipping_status(
+    pool: &PgPool,
+    vendor_id: i32,
+    order_ids: &[i32],
+    shipping_status: &str,
+) -> Result<Vec<i32>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let mut updated = Vec::new();
+
+    for order_id in order_ids {
+        let result = sqlx::query(
+            "UPDATE shipping_orders SET shipping_status = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2 AND vendor_id = $3"
+        )
+        .bind(shipping_status)
+        .bind(order_id)
+        .bind(vendor_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            continue;
+        }
+        record_shipping_status(&mut tx, *order_id, shipping_status).await?;
+
+        if shipping_status.to_lowercase() == "delivered" {
+            sqlx::query(
+                "UPDATE shipping_orders SET verification_requested_at = CURRENT_TIMESTAMP WHERE id = $1"
+            )
+            .bind(order_id)
+            .execute(&mut *tx)
+            .await?;
+        } else if shipping_status.eq_ignore_ascii_case("cancelled") {
+            restock_product_inventory(&mut tx, *order_id).await?;
+        }
+        updated.push(*order_id);
+    }
+
+    tx.commit().await?;
+    Ok(updated)
 }
 
-/// Attach a verification document image to a user account.
-pub async fn upload_verification_document(pool: &PgPool, user_id: i32, document_image: &str) -> Result<(), sqlx::Error> {
+pub async fn request_delivery_verification(
+    pool: &PgPool,
+    order_id: i32,
+) -> Result<(), sqlx::Error> {
     sqlx::query(
-        "UPDATE users SET verification_document = $1, verification_submitted_at = CURRENT_TIMESTAMP WHERE id = $2"
+        "UPDATE shipping_orders SET verification_requested_at = CURRENT_TIMESTAMP 
+         WHERE id = $1 AND shipping_status = 'delivered'"
     )
-    .bind(document_image)
-    .bind(user_id)
+    .bind(order_id)
     .execute(pool)
     .await?;
-
+    
     Ok(())
 }
 
-#[allow(dead_code)]
-pub async fn get_user_verification_document(pool: &PgPool, user_id: i32) -> Result<Option<String>, sqlx::Error> {
-    let result: Option<(String,)> = sqlx::query_as(
-        "SELECT verification_document FROM users WHERE id = $1"
+/**
+ * Customer verifies delivery and releases payment to vendor
+ */
+pub async fn verify_delivery_and_release_payment(
+    pool: &PgPool,
+    order_id: i32,
+    customer_id: i32,
+) -> Result<(), sqlx::Error> {
+    // Get order details
+    // The vendor receives the goods total plus any shipping charged on the order
+    let order: (i32, f64, i32, bool) = sqlx::query_as(
+        "SELECT vendor_id, total_amount + shipping_fee, customer_id, payment_released 
+         FROM shipping_orders WHERE id = $1"
     )
-    .bind(user_id)
-    .fetch_optional(pool)
+    .bind(order_id)
+    .fetch_one(pool)
     .await?;
 
-    Ok(result.map(|(doc,)| doc))
-}
+    let vendor_id = order.0;
+    let amount = order.1;
+    let order_customer_id = order.2;
+    let already_released = order.3;
 
-use serde::{Deserialize, Serialize};
-use crate::models::VendorReport;
+    // Verify customer owns this order
+    if order_customer_id != customer_id {
+        return Err(sqlx::Error::RowNotFound);
+    }
 
-pub async fn create_vendor_report(
+    // Prevent double payment
+    if already_released {
+        return Ok(());
+    }
+
+    // Start transaction
+    let mut tx = pool.begin().await?;
+
+    // Mark order as verified; the payment_released guard makes a concurrent
+    // verification of the same order a no-op instead of a second credit
+    let marked = sqlx::query(
+        "UPDATE shipping_orders SET customer_verified = TRUE, payment_released = TRUE 
+         WHERE id = $1 AND payment_released = FALSE"
+    )
+    .bind(order_id)
+    .execute(&mut *tx)
+    .await?;
+    if marked.rows_affected() == 0 {
+        return Ok(());
+    }
+
+    // Vendors paid "after_order" can withdraw right away; "monthly" earnings wait for the payout sweep
+    sqlx::query(
+        r#"
+        UPDATE users SET
+            wallet_balance = wallet_balance + CASE WHEN payment_preference = 'monthly' THEN 0 ELSE $1 END,
+            pending_balance = pending_balance + CASE WHEN payment_preference = 'monthly' THEN $1 ELSE 0 END
+        WHERE id = $2
+        "#
+    )
+    .bind(amount)
+    .bind(vendor_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO wallet_ledger (user_id, entry_type, amount, reference)
+        SELECT id, 'order_release', $1, $3 FROM users
+        WHERE id = $2 AND payment_preference IS DISTINCT FROM 'monthly'
+        "#
+    )
+    .bind(amount)
+    .bind(vendor_id)
+    .bind(format!("order:{}", order_id))
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/**
+ * Get user's available and pending (awaiting monthly payout) balances
+ */
+pub async fn get_wallet_balances(
     pool: &PgPool,
-    customer_id: i32,
-    vendor_id: i32,
-    product_id: Option<i32>,
-    report_type: &str,
-    description: Option<&str>
-) -> Result<VendorReport, sqlx::Error> {
-    let row = sqlx::query(
+    user_id: i32,
+) -> Result<(f64, f64), sqlx::Error> {
+    sqlx::query_as("SELECT wallet_balance, pending_balance FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+}
+
+/**
+ * Record the payout sweep for a period. Returns false if it already ran.
+ * Runs in the sweep's transaction so a failed sweep leaves the period unclaimed.
+ */
+pub async fn claim_payout_period(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    period: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("INSERT INTO payout_sweeps (period) VALUES ($1) ON CONFLICT DO NOTHING")
+        .bind(period)
+        .execute(&mut **tx)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/**
+ * Move every pending balance into the withdrawable wallet balance.
+ * Returns (users paid, total amount moved).
+ */
+pub async fn sweep_pending_balances(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+) -> Result<(usize, f64), sqlx::Error> {
+    let moved: Vec<f64> = sqlx::query_scalar(
         r#"
-        INSERT INTO vendor_reports (customer_id, vendor_id, product_id, report_type, description)
-        VALUES ($1, $2, $3, $4, $5)
-        RETURNING id, customer_id, vendor_id, product_id, report_type, description, status, admin_notes,
-                  created_at, updated_at
+        WITH swept AS (
+            SELECT id, pending_balance FROM users WHERE pending_balance > 0 FOR UPDATE
+        ), paid AS (
+            UPDATE users u
+            SET wallet_balance = u.wallet_balance + swept.pending_balance, pending_balance = 0
+            FROM swept
+            WHERE u.id = swept.id
+            RETURNING u.id, swept.pending_balance
+        ), logged AS (
+            INSERT INTO wallet_ledger (user_id, entry_type, amount)
+            SELECT id, 'payout_sweep', pending_balance FROM paid
+        )
+        SELECT pending_balance FROM paid
         "#,
     )
-    .bind(customer_id)
-    .bind(vendor_id)
-    .bind(product_id)
-    .bind(report_type)
-    .bind(description)
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok((moved.len(), moved.iter().sum()))
+}
+
+/**
+ * Get user's wallet balance
+ */
+pub async fn get_wallet_balance(
+    pool: &PgPool,
+    user_id: i32,
+) -> Result<f64, sqlx::Error> {
+    let balance: (f64,) = sqlx::query_as(
+        "SELECT wallet_balance FROM users WHERE id = $1"
+    )
+    .bind(user_id)
     .fetch_one(pool)
     .await?;
 
-    // Get customer and vendor usernames, and product name if provided
-    let customer_username = sqlx::query_scalar(
-        "SELECT username FROM users WHERE id = $1"
+    Ok(balance.0)
+}
+
+/// Why a wallet withdrawal was refused
+#[derive(Debug)]
+pub enum WithdrawalError {
+    InsufficientFunds,
+    /// Today's withdrawals plus this one would pass the daily limit
+    DailyLimitExceeded { remaining: f64 },
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for WithdrawalError {
+    fn from(err: sqlx::Error) -> Self {
+        WithdrawalError::Database(err)
+    }
+}
+
+/**
+ * Withdraw from wallet to M-Pesa
+ *
+ * Locks the user's row so concurrent withdrawals are checked against the
+ * balance and the day's ledger total one at a time. Returns the new balance.
+ */
+pub async fn process_wallet_withdrawal(
+    pool: &PgPool,
+    user_id: i32,
+    amount: f64,
+    daily_limit: f64,
+    reference: &str,
+) -> Result<f64, WithdrawalError> {
+    let mut tx = pool.begin().await?;
+
+    let balance: f64 = sqlx::query_scalar("SELECT wallet_balance FROM users WHERE id = $1 FOR UPDATE")
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let withdrawn_today: f64 = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(SUM(-amount), 0) FROM wallet_ledger
+        WHERE user_id = $1 AND entry_type = 'withdrawal' AND created_at >= date_trunc('day', NOW())
+        "#
     )
-    .bind(customer_id)
-    .fetch_one(pool)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
     .await?;
 
-    let vendor_username = sqlx::query_scalar(
-        "SELECT username FROM users WHERE id = $1"
+    if withdrawn_today + amount > daily_limit {
+        return Err(WithdrawalError::DailyLimitExceeded {
+            remaining: (daily_limit - withdrawn_today).max(0.0),
+        });
+    }
+    if balance < amount {
+        return Err(WithdrawalError::InsufficientFunds);
+    }
+
+    let new_balance: f64 = sqlx::query_scalar(
+        "UPDATE users SET wallet_balance = wallet_balance - $1 WHERE id = $2 RETURNING wallet_balance"
     )
-    .bind(vendor_id)
-    .fetch_one(pool)
+    .bind(amount)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
     .await?;
 
-    let product_name = if let Some(pid) = product_id {
-        Some(sqlx::query_scalar("SELECT name FROM products WHERE id = $1")
-            .bind(pid)
-            .fetch_one(pool)
-            .await?)
-    } else {
-        None
-    };
+    sqlx::query("INSERT INTO wallet_ledger (user_id, entry_type, amount, reference) VALUES ($1, 'withdrawal', $2, $3)")
+        .bind(user_id)
+        .bind(-amount)
+        .bind(reference)
+        .execute(&mut *tx)
+        .await?;
 
-    Ok(VendorReport {
-        id: row.try_get("id")?,
-        customer_id: row.try_get("customer_id")?,
-        vendor_id: row.try_get("vendor_id")?,
-        product_id: row.try_get("product_id")?,
-        report_type: row.try_get("report_type")?,
-        description: row.try_get("description")?,
-        status: row.try_get("status")?,
-        admin_notes: row.try_get("admin_notes")?,
-        created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-        updated_at: row.try_get::<String, _>("updated_at").unwrap_or_else(|_| "?".to_string()),
-        customer_username,
-        vendor_username,
-        product_name,
-    })
+    tx.commit().await?;
+    Ok(new_balance)
 }
 
-pub async fn count_vendor_reports(pool: &PgPool, vendor_id: i32) -> Result<i32, sqlx::Error> {
-    let row: (i32,) = sqlx::query_as(
-        "SELECT COUNT(*) as report_count FROM vendor_reports WHERE vendor_id = $1"
+/// A user other users may look at: None if they don't exist, are banned or deleted.
+pub async fn get_visible_user(pool: &PgPool, user_id: i32) -> Result<Option<crate::models::User>, sqlx::Error> {
+    let visible: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND banned = FALSE AND deleted_at IS NULL)"
     )
-    .bind(vendor_id)
+    .bind(user_id)
     .fetch_one(pool)
     .await?;
-    Ok(row.0)
+    if !visible {
+        return Ok(None);
+    }
+    get_user_by_id(pool, user_id).await.map(Some)
 }
 
-pub async fn get_all_vendor_reports(pool: &PgPool) -> Result<Vec<VendorReport>, sqlx::Error> {
-    let rows = sqlx::query(
+/// Get user by ID with full profile information
+pub async fn get_user_by_id(pool: &PgPool, user_id: i32) -> Result<crate::models::User, sqlx::Error> {
+    let row = sqlx::query(
         r#"
-        SELECT
-            vr.id, vr.customer_id, vr.vendor_id, vr.product_id, vr.report_type,
-            vr.description, vr.status, vr.admin_notes, vr.created_at, vr.updated_at,
-            cu.username as customer_username, vu.username as vendor_username,
-            p.name as product_name
-        FROM vendor_reports vr
-        JOIN users cu ON vr.customer_id = cu.id
-        JOIN users vu ON vr.vendor_id = vu.id
-        LEFT JOIN products p ON vr.product_id = p.id
-        ORDER BY vr.created_at DESC
+        SELECT id, username, email, role, profile_image, verified, banned, secondary_email, mpesa_number, payment_preference, location_string, wallet_balance
+        FROM users
+        WHERE id = $1
         "#,
     )
-    .fetch_all(pool)
+    .bind(user_id)
+    .fetch_one(pool)
     .await?;
 
-    let mut reports = Vec::new();
-    for row in rows {
-        reports.push(VendorReport {
-            id: row.try_get("id")?,
-            customer_id: row.try_get("customer_id")?,
-            vendor_id: row.try_get("vendor_id")?,
-            product_id: row.try_get("product_id")?,
-            report_type: row.try_get("report_type")?,
-            description: row.try_get("description")?,
-            status: row.try_get("status")?,
-            admin_notes: row.try_get("admin_notes")?,
-            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-            updated_at: row.try_get::<String, _>("updated_at").unwrap_or_else(|_| "?".to_string()),
-            customer_username: row.try_get("customer_username")?,
-            vendor_username: row.try_get("vendor_username")?,
-            product_name: row.try_get("product_name")?,
-        });
-    }
+    let user = crate::models::User {
+        id: row.try_get(0)?,
+        username: row.try_get(1)?,
+        email: row.try_get(2)?,
+        role: match row.try_get::<String, _>(3)?.as_str() {
+            "Admin" => crate::models::Role::Admin,
+            "Customer" => crate::models::Role::Customer,
+            "Vendor" => crate::models::Role::Vendor,
+            _ => crate::models::Role::Customer,
+        },
+        profile_image: row.try_get(4)?,
+        verified: row.try_get(5)?,
+        banned: row.try_get(6)?,
+        verification_document: None,
+        verification_rejected_reason: None,
+        secondary_email: row.try_get(7)?,
+        mpesa_number: row.try_get(8)?,
+        payment_preference: row.try_get(9)?,
+        location_string: row.try_get(10)?,
+        wallet_balance: row.try_get(11).unwrap_or(0.0),
+    };
 
-    Ok(reports)
+    Ok(user)
 }
 
-pub async fn update_report_status(
+// Password Reset Functions
+
+/// Store a password reset verification code
+pub async fn store_password_reset_code(
     pool: &PgPool,
-    report_id: i32,
-    status: &str,
-    admin_notes: Option<&str>
-) -> Result<(), sqlx::Error> {
-    let query = if let Some(notes) = admin_notes {
-        sqlx::query(
-            "UPDATE vendor_reports SET status = $1, admin_notes = $2, updated_at = CURRENT_TIMESTAMP WHERE id = $3"
-        )
-        .bind(status)
-        .bind(notes)
-        .bind(report_id)
-    } else {
-        sqlx::query(
-            "UPDATE vendor_reports SET status = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2"
-        )
-        .bind(status)
-        .bind(report_id)
-    };
+    username: &str,
+    verification_code: &str,
+    expires_at: chrono::DateTime<chrono::Utc>,
+) -> Result<crate::models::PasswordResetCode, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO password_reset_codes (username, verification_code, expires_at)
+        VALUES ($1, $2, $3)
+        RETURNING id, username, verification_code, expires_at, used, created_at
+        "#,
+    )
+    .bind(username)
+    .bind(verification_code)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await?;
 
-    query.execute(pool).await?;
-    Ok(())
+    Ok(crate::models::PasswordResetCode {
+        id: row.try_get(0)?,
+        username: row.try_get(1)?,
+        verification_code: row.try_get(2)?,
+        expires_at: row.try_get::<chrono::DateTime<chrono::Utc>, _>(3)?.to_rfc3339(),
+        used: row.try_get(4)?,
+        created_at: row.try_get::<chrono::DateTime<chrono::Utc>, _>(5)?.to_rfc3339(),
+    })
 }
 
-pub async fn add_to_cart(pool: &PgPool, user_id: i32, product_id: i32, quantity: i32) -> Result<CartItem, sqlx::Error> {
-    // Check if item already exists in cart
-    let existing_row = sqlx::query(
-        "SELECT id, quantity FROM cart_items WHERE user_id = $1 AND product_id = $2"
+/// Verify password reset code and mark it as used
+pub async fn verify_password_reset_code(
+    pool: &PgPool,
+    username: &str,
+    verification_code: &str,
+) -> Result<bool, sqlx::Error> {
+    let now = chrono::Utc::now();
+    
+    // Check if code exists, is valid, and not expired
+    let row = sqlx::query(
+        r#"
+        SELECT id FROM password_reset_codes
+        WHERE username = $1 
+        AND verification_code = $2 
+        AND expires_at > $3 
+        AND used = FALSE
+        "#,
     )
-    .bind(user_id)
-    .bind(product_id)
+    .bind(username)
+    .bind(verification_code)
+    .bind(now)
     .fetch_optional(pool)
     .await?;
 
-    if let Some(row) = existing_row {
-        let existing_id: i32 = row.try_get(0)?;
-        let existing_quantity: i32 = row.try_get(1)?;
-        let new_quantity = existing_quantity + quantity;
-
-        // Update existing item
+    if let Some(row) = row {
+        let code_id: i32 = row.try_get(0)?;
+        
+        // Mark the code as used
         sqlx::query(
-            "UPDATE cart_items SET quantity = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2"
-        )
-        .bind(new_quantity)
-        .bind(existing_id)
-        .execute(pool)
-        .await?;
-
-        // Return updated cart item with product info - use manual construction to avoid compile-time validation
-        let row = sqlx::query(
-            r#"
-            SELECT
-                ci.id, ci.user_id, ci.product_id, ci.quantity,
-                p.id as p_id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
-            FROM cart_items ci
-            JOIN products p ON ci.product_id = p.id
-            WHERE ci.id = $1
-            "#,
-        )
-        .bind(existing_id)
-        .fetch_one(pool)
-        .await?;
-
-        let product = Product {
-            id: row.try_get::<i32, _>("p_id")? as u32,
-            name: row.try_get("name")?,
-            price: row.try_get::<f64, _>("price")?,
-            category: row.try_get("category")?,
-            description: row.try_get::<Option<String>, _>("description")?,
-            image: row.try_get::<Option<String>, _>("image")?,
-            quantity: row.try_get("quantity")?,
-            vendor_id: row.try_get::<i32, _>("vendor_id")? as u32,
-        };
-
-        return Ok(CartItem {
-            id: row.try_get("id")?,
-            user_id: row.try_get("user_id")?,
-            product_id: row.try_get("product_id")?,
-            quantity: row.try_get("quantity")?,
-            product,
-        });
-    } else {
-        // Insert new item
-        let row = sqlx::query(
-            r#"
-            INSERT INTO cart_items (user_id, product_id, quantity)
-            VALUES ($1, $2, $3)
-            RETURNING
-                id, user_id, product_id, quantity,
-                (SELECT name FROM products WHERE id = $2) as p_name,
-                (SELECT price FROM products WHERE id = $2) as p_price,
-                (SELECT category FROM products WHERE id = $2) as p_category,
-                (SELECT description FROM products WHERE id = $2) as p_description,
-                (SELECT image FROM products WHERE id = $2) as p_image,
-                (SELECT quantity FROM products WHERE id = $2) as p_quantity,
-                (SELECT vendor_id FROM products WHERE id = $2) as p_vendor_id
-            "#,
+            "UPDATE password_reset_codes SET used = TRUE WHERE id = $1"
         )
-        .bind(user_id)
-        .bind(product_id)
-        .bind(quantity)
-        .fetch_one(pool)
+        .bind(code_id)
+        .execute(pool)
         .await?;
-
-        let product = Product {
-            id: product_id as u32,
-            name: row.try_get("p_name")?,
-            price: row.try_get::<f64, _>("p_price")?,
-            category: row.try_get("p_category")?,
-            description: row.try_get::<Option<String>, _>("p_description")?,
-            image: row.try_get::<Option<String>, _>("p_image")?,
-            quantity: row.try_get("p_quantity")?,
-            vendor_id: row.try_get::<i32, _>("p_vendor_id")? as u32,
-        };
-
-        let cart_item = CartItem {
-            id: row.try_get("id")?,
-            user_id,
-            product_id,
-            quantity,
-            product,
-        };
-
-        Ok(cart_item)
+        
+        Ok(true)
+    } else {
+        Ok(false)
     }
 }
 
-pub async fn update_cart_item_quantity(pool: &PgPool, cart_item_id: i32, user_id: i32, quantity: i32) -> Result<CartItem, sqlx::Error> {
+/// Reset user password using username
+pub async fn reset_user_password_by_username(
+    pool: &PgPool,
+    username: &str,
+    new_password: &str,
+) -> Result<crate::models::User, sqlx::Error> {
+    let password_hash = bcrypt::hash(new_password, bcrypt::DEFAULT_COST)
+        .map_err(|_| sqlx::Error::RowNotFound)?;
+
     let row = sqlx::query(
         r#"
-        UPDATE cart_items SET quantity = $1, updated_at = CURRENT_TIMESTAMP
-        WHERE id = $2 AND user_id = $3
-        RETURNING
-            id, user_id, product_id, quantity,
-            (SELECT name FROM products WHERE id = product_id) as p_name,
-            (SELECT price FROM products WHERE id = product_id) as p_price,
-            (SELECT category FROM products WHERE id = product_id) as p_category,
-            (SELECT description FROM products WHERE id = product_id) as p_description,
-            (SELECT image FROM products WHERE id = product_id) as p_image,
-            (SELECT quantity FROM products WHERE id = product_id) as p_quantity,
-            (SELECT vendor_id FROM products WHERE id = product_id) as p_vendor_id
+        UPDATE users 
+        SET password_hash = $1
+        WHERE username = $2
+        RETURNING id, username, email, role, profile_image, verified, banned, secondary_email, mpesa_number, payment_preference, location_string, wallet_balance
         "#,
     )
-    .bind(quantity)
-    .bind(cart_item_id)
-    .bind(user_id)
+    .bind(password_hash)
+    .bind(username)
     .fetch_one(pool)
     .await?;
 
-    let product = Product {
-        id: row.try_get::<i32, _>("product_id")? as u32,
-        name: row.try_get("p_name")?,
-        price: row.try_get::<f64, _>("p_price")?,
-        category: row.try_get("p_category")?,
-        description: row.try_get::<Option<String>, _>("p_description")?,
-        image: row.try_get::<Option<String>, _>("p_image")?,
-        quantity: row.try_get("p_quantity")?,
-        vendor_id: row.try_get::<i32, _>("p_vendor_id")? as u32,
-    };
-
-    let cart_item = CartItem {
-        id: row.try_get("id")?,
-        user_id: row.try_get("user_id")?,
-        product_id: row.try_get("product_id")?,
-        quantity: row.try_get("quantity")?,
-        product,
-    };
-
-    Ok(cart_item)
+    Ok(crate::models::User {
+        id: row.try_get(0)?,
+        username: row.try_get(1)?,
+        email: row.try_get(2)?,
+        role: match row.try_get::<String, _>(3)?.as_str() {
+            "Admin" => crate::models::Role::Admin,
+            "Customer" => crate::models::Role::Customer,
+            "Vendor" => crate::models::Role::Vendor,
+            _ => crate::models::Role::Customer,
+        },
+        profile_image: row.try_get(4)?,
+        verified: row.try_get(5)?,
+        banned: row.try_get(6)?,
+        verification_document: None,
+        verification_rejected_reason: None,
+        secondary_email: row.try_get(7)?,
+        mpesa_number: row.try_get(8)?,
+        payment_preference: row.try_get(9)?,
+        location_string: row.try_get(10)?,
+        wallet_balance: row.try_get(11).unwrap_or(0.0),
+    })
 }
 
-pub async fn remove_from_cart_with_user(pool: &PgPool, cart_item_id: i32, user_id: i32) -> Result<(), sqlx::Error> {
-    sqlx::query("DELETE FROM cart_items WHERE id = $1 AND user_id = $2")
-        .bind(cart_item_id)
-        .bind(user_id)
-        .execute(pool)
-        .await?;
-    Ok(())
+/// Clean up expired password reset codes
+pub async fn cleanup_expired_reset_codes(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let now = chrono::Utc::now();
+    let result = sqlx::query(
+        "DELETE FROM password_reset_codes WHERE expires_at < $1"
+    )
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
 }
 
-// Admin user management functions
-pub async fn get_all_users(pool: &PgPool) -> Result<Vec<User>, sqlx::Error> {
-    let rows = sqlx::query(
+/// Find user by username (for password reset)
+pub async fn find_user_by_username(pool: &PgPool, username: &str) -> Result<Option<crate::models::User>, sqlx::Error> {
+    let row = sqlx::query(
         r#"
-        SELECT id, username, email, role, profile_image, verified, banned, secondary_email, mpesa_number, payment_preference, location_string
+        SELECT id, username, email, role, profile_image, verified, banned, secondary_email, mpesa_number, payment_preference, location_string, wallet_balance
         FROM users
-        ORDER BY id
+        WHERE username = $1 AND deleted_at IS NULL
         "#,
     )
-    .fetch_all(pool)
+    .bind(username)
+    .fetch_optional(pool)
     .await?;
 
-    let mut users = Vec::new();
-    for row in rows {
-        let user = User {
+    if let Some(row) = row {
+        Ok(Some(crate::models::User {
             id: row.try_get(0)?,
             username: row.try_get(1)?,
             email: row.try_get(2)?,
             role: match row.try_get::<String, _>(3)?.as_str() {
-                "Admin" => Role::Admin,
-                "Customer" => Role::Customer,
-                "Vendor" => Role::Vendor,
-                _ => Role::Customer,
+                "Admin" => crate::models::Role::Admin,
+                "Customer" => crate::models::Role::Customer,
+                "Vendor" => crate::models::Role::Vendor,
+                _ => crate::models::Role::Customer,
             },
             profile_image: row.try_get(4)?,
             verified: row.try_get(5)?,
@@ -860,257 +6066,308 @@ pub async fn get_all_users(pool: &PgPool) -> Result<Vec<User>, sqlx::Error> {
             mpesa_number: row.try_get(8)?,
             payment_preference: row.try_get(9)?,
             location_string: row.try_get(10)?,
-            wallet_balance: 0.0,
-        };
-        users.push(user);
+            wallet_balance: row.try_get(11).unwrap_or(0.0),
+        }))
+    } else {
+        Ok(None)
     }
-
-    Ok(users)
 }
+/// Load every stored runtime setting as `(key, value)` pairs.
+pub async fn get_all_settings(pool: &PgPool) -> Result<Vec<(String, String)>, sqlx::Error> {
+    let rows = sqlx::query("SELECT key, value FROM settings ORDER BY key")
+        .fetch_all(pool)
+        .await?;
 
-pub async fn get_pending_vendors(pool: &PgPool) -> Result<Vec<crate::models::VendorVerification>, sqlx::Error> {
-    let rows = sqlx::query(
-        r#"
-        SELECT id, username, email, profile_image, mpesa_number, payment_preference
-        FROM users
-        WHERE role = 'Vendor' AND verified = false AND profile_image IS NOT NULL
-        ORDER BY id
-        "#,
-    )
-    .fetch_all(pool)
-    .await?;
-
-    let mut vendors = Vec::new();
+    let mut settings = Vec::new();
     for row in rows {
-        let vendor = crate::models::VendorVerification {
-            id: row.try_get(0)?,
-            username: row.try_get(1)?,
-            email: row.try_get(2)?,
-            profile_image: row.try_get(3)?,
-            mpesa_number: row.try_get(4)?,
-            payment_preference: row.try_get(5)?,
-        };
-        vendors.push(vendor);
+        settings.push((row.try_get("key")?, row.try_get("value")?));
     }
-
-    Ok(vendors)
+    Ok(settings)
 }
 
-pub async fn update_user_role(pool: &PgPool, user_id: i32, new_role: &Role) -> Result<(), sqlx::Error> {
-    let role_str = match new_role {
-        Role::Admin => "Admin",
-        Role::Customer => "Customer",
-        Role::Vendor => "Vendor",
-    };
-
+/// Insert or overwrite a runtime setting.
+pub async fn upsert_setting(pool: &PgPool, key: &str, value: &str) -> Result<(), sqlx::Error> {
     sqlx::query(
-        "UPDATE users SET role = $1 WHERE id = $2",
+        r#"
+        INSERT INTO settings (key, value, updated_at)
+        VALUES ($1, $2, CURRENT_TIMESTAMP)
+        ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, updated_at = CURRENT_TIMESTAMP
+        "#,
     )
-    .bind(role_str)
-    .bind(user_id)
+    .bind(key)
+    .bind(value)
     .execute(pool)
     .await?;
-
     Ok(())
 }
 
-pub async fn update_user_verification(pool: &PgPool, user_id: i32, verified: bool) -> Result<(), sqlx::Error> {
-    if verified {
-        // User is being approved - clear any previous rejection reason
-        sqlx::query(
-            "UPDATE users SET verified = $1, verification_rejected_reason = NULL WHERE id = $2",
-        )
-        .bind(verified)
-        .bind(user_id)
-        .execute(pool)
-        .await?;
-    } else {
-        // User is being rejected - remove verification documents and set rejection reason
-        sqlx::query(
-            r#"
-            UPDATE users 
-            SET verified = $1, 
-                verification_document = NULL, 
-                verification_rejected_reason = 'Verification denied due to non-human image upload. Please upload a clear image of yourself.'
-            WHERE id = $2
-            "#,
-        )
-        .bind(verified)
+/// Return the user's M-Pesa number and whether it has been verified.
+pub async fn get_mpesa_verification(pool: &PgPool, user_id: i32) -> Result<(Option<String>, bool), sqlx::Error> {
+    sqlx::query_as("SELECT mpesa_number, mpesa_verified FROM users WHERE id = $1")
         .bind(user_id)
-        .execute(pool)
-        .await?;
-    }
-
-    Ok(())
-}
-
-pub async fn ban_user(pool: &PgPool, user_id: i32, banned: bool) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        "UPDATE users SET banned = $1 WHERE id = $2",
-    )
-    .bind(banned)
-    .bind(user_id)
-    .execute(pool)
-    .await?;
-
-    Ok(())
+        .fetch_one(pool)
+        .await
 }
 
-pub async fn update_user_profile_image(pool: &PgPool, user_id: i32, profile_image: &str) -> Result<(), sqlx::Error> {
-    // Check user role to determine verification status
-    let role_row = sqlx::query("SELECT role FROM users WHERE id = $1")
-        .bind(user_id)
-        .fetch_one(pool)
-        .await?;
-    
-    let role: String = role_row.try_get(0)?;
-    
-    // Vendors need admin verification when uploading profile image, Customers are auto-verified
-    if role == "Vendor" {
-        sqlx::query(
-            "UPDATE users SET profile_image = $1, verified = false WHERE id = $2",
-        )
-        .bind(profile_image)
-        .bind(user_id)
-        .execute(pool)
-        .await?;
-    } else {
-        // Customers remain verified or get auto-verified
-        sqlx::query(
-            "UPDATE users SET profile_image = $1, verified = true WHERE id = $2",
-        )
-        .bind(profile_image)
+/// Store an OTP for confirming `phone_number`, invalidating earlier unused codes.
+pub async fn store_phone_verification_code(
+    pool: &PgPool,
+    user_id: i32,
+    phone_number: &str,
+    verification_code: &str,
+    expires_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE phone_verification_codes SET used = TRUE WHERE user_id = $1 AND used = FALSE")
         .bind(user_id)
         .execute(pool)
         .await?;
-    }
+
+    sqlx::query(
+        "INSERT INTO phone_verification_codes (user_id, phone_number, verification_code, expires_at) VALUES ($1, $2, $3, $4)"
+    )
+    .bind(user_id)
+    .bind(phone_number)
+    .bind(verification_code)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
 
     Ok(())
 }
 
-pub async fn update_user_profile(pool: &PgPool, user_id: i32, new_username: Option<&str>, new_email: Option<&str>, secondary_email: Option<&str>, mpesa_number: Option<&str>, payment_preference: Option<&str>) -> Result<(), sqlx::Error> {
-    if let Some(username) = new_username {
+/// Wrong guesses allowed against one phone verification code before it's invalidated.
+pub const PHONE_CODE_MAX_ATTEMPTS: i32 = 5;
+
+/// Consume a valid OTP and mark the user's M-Pesa number verified.
+/// Returns false if the code is wrong, expired, or the number changed since it was sent.
+/// Each wrong guess counts against the user's current code, which stops working
+/// after `PHONE_CODE_MAX_ATTEMPTS` of them.
+pub async fn confirm_phone_verification_code(pool: &PgPool, user_id: i32, verification_code: &str) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let current: Option<(i32, String, String)> = sqlx::query_as(
+        r#"
+        SELECT id, verification_code, phone_number FROM phone_verification_codes
+        WHERE user_id = $1 AND used = FALSE AND expires_at > NOW()
+        ORDER BY created_at DESC
+        LIMIT 1
+        FOR UPDATE
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some((code_id, expected_code, phone_number)) = current else {
+        return Ok(false);
+    };
+
+    if expected_code != verification_code {
         sqlx::query(
-            "UPDATE users SET username = $1 WHERE id = $2",
+            "UPDATE phone_verification_codes
+             SET failed_attempts = failed_attempts + 1, used = failed_attempts + 1 >= $2
+             WHERE id = $1"
         )
-        .bind(username)
-        .bind(user_id)
-        .execute(pool)
+        .bind(code_id)
+        .bind(PHONE_CODE_MAX_ATTEMPTS)
+        .execute(&mut *tx)
         .await?;
+        tx.commit().await?;
+        return Ok(false);
     }
 
-    if let Some(email) = new_email {
-        sqlx::query(
-            "UPDATE users SET email = $1 WHERE id = $2",
-        )
-        .bind(email)
-        .bind(user_id)
-        .execute(pool)
+    sqlx::query("UPDATE phone_verification_codes SET used = TRUE WHERE id = $1")
+        .bind(code_id)
+        .execute(&mut *tx)
         .await?;
-    }
 
-    if let Some(sec_email) = secondary_email {
-        sqlx::query(
-            "UPDATE users SET secondary_email = $1 WHERE id = $2",
-        )
-        .bind(sec_email)
+    let updated = sqlx::query("UPDATE users SET mpesa_verified = TRUE WHERE id = $1 AND mpesa_number = $2")
         .bind(user_id)
-        .execute(pool)
+        .bind(&phone_number)
+        .execute(&mut *tx)
         .await?;
-    }
 
-    if let Some(mpesa) = mpesa_number {
+    tx.commit().await?;
+    Ok(updated.rows_affected() > 0)
+}
+
+/// Store a code unlocking the admin SQL console, invalidating earlier unused codes.
+pub async fn store_admin_query_code(
+    pool: &PgPool,
+    admin_id: i32,
+    verification_code: &str,
+    expires_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE admin_query_codes SET used = TRUE WHERE admin_id = $1 AND used = FALSE")
+        .bind(admin_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO admin_query_codes (admin_id, verification_code, expires_at) VALUES ($1, $2, $3)")
+        .bind(admin_id)
+        .bind(verification_code)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+/// Whether `verification_code` is the admin's current, unexpired console code.
+/// A code can be used for any number of queries until it expires. Each wrong
+/// guess counts against it, and it stops working after
+/// `admin_query::CODE_MAX_ATTEMPTS` of them.
+pub async fn admin_query_code_is_valid(pool: &PgPool, admin_id: i32, verification_code: &str) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let current: Option<(i32, String)> = sqlx::query_as(
+        r#"
+        SELECT id, verification_code FROM admin_query_codes
+        WHERE admin_id = $1 AND used = FALSE AND expires_at > NOW()
+        ORDER BY created_at DESC
+        LIMIT 1
+        FOR UPDATE
+        "#,
+    )
+    .bind(admin_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some((code_id, expected_code)) = current else {
+        return Ok(false);
+    };
+
+    if expected_code != verification_code {
         sqlx::query(
-            "UPDATE users SET mpesa_number = $1 WHERE id = $2",
+            "UPDATE admin_query_codes
+             SET failed_attempts = failed_attempts + 1, used = failed_attempts + 1 >= $2
+             WHERE id = $1"
         )
-        .bind(mpesa)
-        .bind(user_id)
-        .execute(pool)
+        .bind(code_id)
+        .bind(crate::admin_query::CODE_MAX_ATTEMPTS)
+        .execute(&mut *tx)
         .await?;
+        tx.commit().await?;
+        return Ok(false);
     }
 
-    if let Some(pref) = payment_preference {
-        sqlx::query(
-            "UPDATE users SET payment_preference = $1 WHERE id = $2",
-        )
-        .bind(pref)
-        .bind(user_id)
-        .execute(pool)
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Run an admin's validated SELECT in a read-only transaction with a
+/// statement timeout, returning at most `max_rows` rows. Values are converted
+/// to JSON by Postgres so every column type comes back as something readable.
+pub async fn run_read_only_query(
+    pool: &PgPool,
+    sql: &str,
+    params: &[QueryParam],
+    max_rows: usize,
+    timeout_ms: u64,
+) -> Result<crate::models::AdminQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION READ ONLY").execute(&mut *tx).await?;
+    sqlx::query(&format!("SET LOCAL ROLE {}", crate::admin_query::READER_ROLE))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(&format!("SET LOCAL statement_timeout = {}", timeout_ms))
+        .execute(&mut *tx)
         .await?;
+
+    // One extra row tells us whether the result was cut off. The wrapper's
+    // LIMIT saves work, but SQL that closes it early could lift it, so the
+    // cap is enforced again while reading rows.
+    let wrapped = format!(
+        "SELECT (SELECT json_agg(key) FROM json_each(r))::text, (SELECT json_agg(value) FROM json_each(r))::text
+         FROM (SELECT row_to_json(q) AS r FROM (\n{}\n) AS q LIMIT {}) AS limited",
+        sql,
+        max_rows + 1
+    );
+    let mut query = sqlx::query_as::<_, (Option<String>, Option<String>)>(&wrapped);
+    for param in params {
+        query = match param {
+            QueryParam::Text(text) => query.bind(text.clone()),
+            QueryParam::Int(n) => query.bind(*n),
+            QueryParam::Float(n) => query.bind(*n),
+            QueryParam::Bool(b) => query.bind(*b),
+            QueryParam::Null => query.bind(None::<String>),
+        };
     }
+    let mut fetched = Vec::new();
+    let mut stream = query.fetch(&mut *tx);
+    let read = loop {
+        match stream.try_next().await {
+            Ok(Some(row)) if fetched.len() <= max_rows => fetched.push(row),
+            Ok(_) => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+    drop(stream);
+    tx.rollback().await?;
+    read?;
 
-    Ok(())
+    let truncated = fetched.len() > max_rows;
+    fetched.truncate(max_rows);
+
+    let parse = |text: Option<String>| -> Vec<serde_json::Value> {
+        text.and_then(|text| serde_json::from_str(&text).ok()).unwrap_or_default()
+    };
+    let columns = fetched
+        .first()
+        .map(|(keys, _)| parse(keys.clone()).into_iter().filter_map(|key| key.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+    let rows = fetched.into_iter().map(|(_, values)| parse(values)).collect();
+    Ok(crate::models::AdminQueryResult { columns, rows, truncated })
 }
 
-pub async fn reset_user_password(pool: &PgPool, user_id: i32, new_password: &str) -> Result<(), sqlx::Error> {
-    let password_hash = hash(new_password, DEFAULT_COST).map_err(|_| sqlx::Error::RowNotFound)?;
+/// Audit an admin SQL console query and what came of it.
+pub async fn record_admin_query(pool: &PgPool, admin_id: i32, sql: &str, outcome: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO audit_log (actor_id, action, details) VALUES ($1, $2, $3)")
+        .bind(admin_id)
+        .bind("admin.query")
+        .bind(format!("{}; sql: {}", outcome, sql))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
 
+// Wishlist functions
+pub async fn add_to_wishlist(pool: &PgPool, user_id: i32, product_id: i32) -> Result<(), sqlx::Error> {
     sqlx::query(
-        "UPDATE users SET password_hash = $1 WHERE id = $2",
+        "INSERT INTO wishlist_items (user_id, product_id) VALUES ($1, $2) ON CONFLICT (user_id, product_id) DO NOTHING"
     )
-    .bind(password_hash)
     .bind(user_id)
+    .bind(product_id)
     .execute(pool)
     .await?;
     Ok(())
 }
 
-pub async fn delete_user(pool: &PgPool, user_id: i32) -> Result<(), sqlx::Error> {
-    sqlx::query("DELETE FROM users WHERE id = $1")
+pub async fn remove_from_wishlist(pool: &PgPool, user_id: i32, product_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM wishlist_items WHERE user_id = $1 AND product_id = $2")
         .bind(user_id)
+        .bind(product_id)
         .execute(pool)
         .await?;
     Ok(())
 }
 
-/// Fetch all products, optionally filtered by vendor ID or user location.
-/// Filters by matching location_string (e.g., "Nakuru" matches vendors with "Nakuru" in their location).
-pub async fn get_all_products(pool: &PgPool, vendor_filter: Option<i32>, user_location: Option<String>) -> Result<Vec<Product>, sqlx::Error> {
-    let rows = if let Some(vendor_id) = vendor_filter {
-        sqlx::query(
-            r#"
-            SELECT id, name, price, category, description, image, quantity, vendor_id
-            FROM products
-            WHERE vendor_id = $1
-            ORDER BY id
-            "#,
-        )
-        .bind(vendor_id)
-        .fetch_all(pool)
-        .await?
-    } else if let Some(location) = user_location {
-        // Filter products by vendors whose location contains the user's location
-        sqlx::query(
-            r#"
-            SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
-            FROM products p
-            JOIN users u ON p.vendor_id = u.id
-            WHERE u.verified = TRUE AND u.banned = FALSE
-            AND LOWER(u.location_string) LIKE LOWER($1)
-            ORDER BY p.id
-            "#,
-        )
-        .bind(format!("%{}%", location))
-        .fetch_all(pool)
-        .await?
-    } else {
-        // Return all products from verified vendors
-        sqlx::query(
-            r#"
-            SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
-            FROM products p
-            JOIN users u ON p.vendor_id = u.id
-            WHERE u.verified = TRUE AND u.banned = FALSE
-            ORDER BY p.id
-            "#,
-        )
-        .fetch_all(pool)
-        .await?
-    };
+pub async fn get_wishlist(pool: &PgPool, user_id: i32) -> Result<Vec<Product>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
+        FROM wishlist_items w
+        JOIN products p ON w.product_id = p.id
+        WHERE w.user_id = $1
+        ORDER BY w.created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
 
     let mut products = Vec::new();
     for row in rows {
-        let product = Product {
+        products.push(Product {
             id: row.try_get::<i32, _>(0)? as u32,
             name: row.try_get(1)?,
             price: row.try_get::<f64, _>(2)?,
@@ -1119,1475 +6376,2255 @@ pub async fn get_all_products(pool: &PgPool, vendor_filter: Option<i32>, user_lo
             image: row.try_get::<Option<String>, _>(5)?,
             quantity: row.try_get(6)?,
             vendor_id: row.try_get::<i32, _>(7)? as u32,
-        };
-        products.push(product);
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
+        });
     }
 
+    attach_tags(pool, &mut products).await?;
+    attach_gallery(pool, &mut products).await?;
+    attach_attributes(pool, &mut products).await?;
     Ok(products)
 }
 
-pub async fn create_product(pool: &PgPool, name: &str, price: f64, category: &str, description: &str, quantity: i32, image: Option<&str>, vendor_id: i32) -> Result<Product, sqlx::Error> {
-    let row = if let Some(img) = image {
-        sqlx::query(
-            r#"
-            INSERT INTO products (name, price, category, description, quantity, image, vendor_id)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
-            RETURNING id, name, price, category, description, quantity, image, vendor_id
-            "#,
-        )
-        .bind(name)
-        .bind(price)
-        .bind(category)
-        .bind(description)
-        .bind(quantity)
-        .bind(img)
-        .bind(vendor_id)
+pub async fn count_wishlist_items(pool: &PgPool, user_id: i32) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM wishlist_items WHERE user_id = $1")
+        .bind(user_id)
         .fetch_one(pool)
-        .await?
-    } else {
-        sqlx::query(
-            r#"
-            INSERT INTO products (name, price, category, description, quantity, vendor_id)
-            VALUES ($1, $2, $3, $4, $5, $6)
-            RETURNING id, name, price, category, description, quantity, image, vendor_id
-            "#,
+        .await
+}
+
+/// Number of recent orders included in the customer dashboard.
+const DASHBOARD_RECENT_ORDERS: usize = 5;
+
+/// Summary figures for the customer dashboard, built from the existing
+/// purchase report, follow, message, and shipping queries.
+pub async fn get_customer_dashboard(pool: &PgPool, customer_id: i32) -> Result<crate::models::CustomerDashboard, sqlx::Error> {
+    let report = get_customer_purchase_report(pool, customer_id).await?;
+    let follows = get_user_follows(pool, customer_id).await?;
+    let conversations = get_user_conversations(pool, customer_id).await?;
+    let active_orders = count_open_orders(pool, customer_id).await?;
+    let wishlist_size = count_wishlist_items(pool, customer_id).await?;
+
+    let mut recent_orders = get_customer_shipping_orders(pool, customer_id).await?;
+    recent_orders.truncate(DASHBOARD_RECENT_ORDERS);
+
+    Ok(crate::models::CustomerDashboard {
+        active_orders: active_orders as i32,
+        total_spent: report.total_spent,
+        total_orders: report.total_orders,
+        vendors_followed: follows.len() as i32,
+        unread_messages: conversations.iter().map(|c| c.unread_count).sum(),
+        wishlist_size: wishlist_size as i32,
+        recent_orders,
+    })
+}
+
+/// Find carts untouched for `stale_hours` whose owners accept email and have not
+/// been reminded within the same window, and record the reminder in one statement
+/// so concurrent runs never claim the same cart twice.
+/// Returns (user_id, username, email) for each claimed cart.
+pub async fn claim_abandoned_carts(pool: &PgPool, stale_hours: i32) -> Result<Vec<(i32, String, String)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        WITH stale AS (
+            SELECT ci.user_id
+            FROM cart_items ci
+            JOIN users u ON u.id = ci.user_id
+            WHERE u.deleted_at IS NULL AND u.email_notifications = TRUE
+            GROUP BY ci.user_id
+            HAVING MAX(COALESCE(ci.updated_at, ci.created_at)) < NOW() - make_interval(hours => $1)
+        ),
+        claimed AS (
+            INSERT INTO cart_reminders (user_id, last_reminded_at)
+            SELECT user_id, NOW() FROM stale
+            ON CONFLICT (user_id) DO UPDATE SET last_reminded_at = EXCLUDED.last_reminded_at
+            WHERE cart_reminders.last_reminded_at < NOW() - make_interval(hours => $1)
+            RETURNING user_id
         )
-        .bind(name)
-        .bind(price)
-        .bind(category)
-        .bind(description)
-        .bind(quantity)
-        .bind(vendor_id)
+        SELECT u.id, u.username, u.email
+        FROM claimed c
+        JOIN users u ON u.id = c.user_id
+        ORDER BY u.id
+        "#,
+    )
+    .bind(stale_hours)
+    .fetch_all(pool)
+    .await
+}
+
+/// Longest tag accepted, in characters.
+const MAX_TAG_LEN: usize = 50;
+
+/// Lowercase and collapse whitespace; `None` for blank or over-long tags.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LEN {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+/// Normalize a list of tags, dropping invalid ones and duplicates (first one wins).
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags.iter().filter_map(|t| normalize_tag(t)) {
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// Replace a product's tags. Returns the normalized tags that were stored.
+pub async fn set_product_tags(pool: &PgPool, product_id: i32, tags: &[String]) -> Result<Vec<String>, sqlx::Error> {
+    let tags = normalize_tags(tags);
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM product_tags WHERE product_id = $1")
+        .bind(product_id)
+        .execute(&mut *tx)
+        .await?;
+    for tag in &tags {
+        sqlx::query("INSERT INTO product_tags (product_id, tag) VALUES ($1, $2)")
+            .bind(product_id)
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(tags)
+}
+
+pub async fn get_product_tags(pool: &PgPool, product_id: i32) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT tag FROM product_tags WHERE product_id = $1 ORDER BY tag")
+        .bind(product_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Fill in `tags` for each product with a single query.
+pub async fn attach_tags(pool: &PgPool, products: &mut [Product]) -> Result<(), sqlx::Error> {
+    if products.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<i32> = products.iter().map(|p| p.id as i32).collect();
+    let rows: Vec<(i32, String)> = sqlx::query_as(
+        "SELECT product_id, tag FROM product_tags WHERE product_id = ANY($1) ORDER BY tag"
+    )
+    .bind(&ids)
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_product: std::collections::HashMap<i32, Vec<String>> = std::collections::HashMap::new();
+    for (product_id, tag) in rows {
+        by_product.entry(product_id).or_default().push(tag);
+    }
+    for product in products.iter_mut() {
+        product.tags = by_product.remove(&(product.id as i32)).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Structured details read from a `products` row.
+fn product_attributes_from_row(row: &sqlx::postgres::PgRow) -> Result<ProductAttributes, sqlx::Error> {
+    Ok(ProductAttributes {
+        unit: row.try_get("unit")?,
+        weight_grams: row.try_get("weight_grams")?,
+        origin: row.try_get("origin")?,
+        is_organic: row.try_get("is_organic")?,
+        harvest_date: row.try_get("harvest_date")?,
+    })
+}
+
+/// Fill in the structured details of each product.
+pub async fn attach_attributes(pool: &PgPool, products: &mut [Product]) -> Result<(), sqlx::Error> {
+    if products.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<i32> = products.iter().map(|p| p.id as i32).collect();
+    let rows = sqlx::query(
+        "SELECT id, unit, weight_grams, origin, is_organic, harvest_date FROM products WHERE id = ANY($1)"
+    )
+    .bind(&ids)
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_product = std::collections::HashMap::new();
+    for row in rows {
+        by_product.insert(row.try_get::<i32, _>("id")?, product_attributes_from_row(&row)?);
+    }
+    for product in products.iter_mut() {
+        product.attributes = by_product.remove(&(product.id as i32)).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// A product's structured details.
+pub async fn get_product_attributes(pool: &PgPool, product_id: i32) -> Result<ProductAttributes, sqlx::Error> {
+    let row = sqlx::query("SELECT unit, weight_grams, origin, is_organic, harvest_date FROM products WHERE id = $1")
+        .bind(product_id)
         .fetch_one(pool)
-        .await?
-    };
+        .await?;
+    product_attributes_from_row(&row)
+}
+
+/// Replace a product's structured details.
+pub async fn set_product_attributes(pool: &PgPool, product_id: i32, attributes: &ProductAttributes) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE products SET unit = $1, weight_grams = $2, origin = $3, is_organic = $4, harvest_date = $5 WHERE id = $6"
+    )
+    .bind(&attributes.unit)
+    .bind(attributes.weight_grams)
+    .bind(&attributes.origin)
+    .bind(attributes.is_organic)
+    .bind(attributes.harvest_date)
+    .bind(product_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A product's gallery images in display order.
+pub async fn get_product_images(pool: &PgPool, product_id: i32) -> Result<Vec<ProductImage>, sqlx::Error> {
+    let rows: Vec<(i32, String, i32)> = sqlx::query_as(
+        "SELECT id, image, position FROM product_images WHERE product_id = $1 ORDER BY position, id"
+    )
+    .bind(product_id)
+    .fetch_all(pool)
+    .await?;
 
-    let product = Product {
-        id: row.try_get::<i32, _>(0)? as u32,
-        name: row.try_get(1)?,
-        price: row.try_get::<f64, _>(2)?,
-        category: row.try_get(3)?,
-        description: row.try_get::<Option<String>, _>(4)?,
-        quantity: row.try_get(5)?,
-        image: row.try_get::<Option<String>, _>(6)?,
-        vendor_id: row.try_get::<i32, _>(7)? as u32,
-    };
+    Ok(rows
+        .into_iter()
+        .map(|(id, image, position)| ProductImage { id, image, position })
+        .collect())
+}
 
-    Ok(product)
+/// Fill in `gallery` for each product with a single query.
+pub async fn attach_gallery(pool: &PgPool, products: &mut [Product]) -> Result<(), sqlx::Error> {
+    if products.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<i32> = products.iter().map(|p| p.id as i32).collect();
+    let rows: Vec<(i32, i32, String, i32)> = sqlx::query_as(
+        "SELECT product_id, id, image, position FROM product_images WHERE product_id = ANY($1) ORDER BY position, id"
+    )
+    .bind(&ids)
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_product: std::collections::HashMap<i32, Vec<ProductImage>> = std::collections::HashMap::new();
+    for (product_id, id, image, position) in rows {
+        by_product.entry(product_id).or_default().push(ProductImage { id, image, position });
+    }
+    for product in products.iter_mut() {
+        product.gallery = by_product.remove(&(product.id as i32)).unwrap_or_default();
+    }
+    Ok(())
 }
 
-pub async fn update_product(pool: &PgPool, product_id: i32, name: &str, price: f64, category: &str, description: &str, quantity: i32, image: Option<&str>, vendor_id: i32) -> Result<Product, sqlx::Error> {
-    let row = if let Some(img) = image {
-        sqlx::query(
-            r#"
-            UPDATE products
-            SET name = $1, price = $2, category = $3, description = $4, quantity = $5, image = $6
-            WHERE id = $7 AND vendor_id = $8
-            RETURNING id, name, price, category, description, quantity, image, vendor_id
-            "#,
-        )
-        .bind(name)
-        .bind(price)
-        .bind(category)
-        .bind(description)
-        .bind(quantity)
-        .bind(img)
-        .bind(product_id)
-        .bind(vendor_id)
-        .fetch_one(pool)
-        .await?
-    } else {
-        sqlx::query(
-            r#"
-            UPDATE products
-            SET name = $1, price = $2, category = $3, description = $4, quantity = $5
-            WHERE id = $6 AND vendor_id = $7
-            RETURNING id, name, price, category, description, quantity, image, vendor_id
-            "#,
-        )
-        .bind(name)
-        .bind(price)
-        .bind(category)
-        .bind(description)
-        .bind(quantity)
+/// Append an image to a product's gallery unless it already has `max_images`.
+/// Returns None when the gallery is full.
+pub async fn add_product_image(
+    pool: &PgPool,
+    product_id: i32,
+    image: &str,
+    max_images: i64,
+) -> Result<Option<ProductImage>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    // Serialize concurrent uploads to the same product so the limit holds
+    sqlx::query("SELECT id FROM products WHERE id = $1 FOR UPDATE")
         .bind(product_id)
-        .bind(vendor_id)
-        .fetch_one(pool)
-        .await?
-    };
+        .fetch_one(&mut *tx)
+        .await?;
 
-    let product = Product {
-        id: row.try_get::<i32, _>(0)? as u32,
-        name: row.try_get(1)?,
-        price: row.try_get::<f64, _>(2)?,
-        category: row.try_get(3)?,
-        description: row.try_get::<Option<String>, _>(4)?,
-        quantity: row.try_get(5)?,
-        image: row.try_get::<Option<String>, _>(6)?,
-        vendor_id: row.try_get::<i32, _>(7)? as u32,
-    };
+    let (count, next_position): (i64, i32) = sqlx::query_as(
+        "SELECT COUNT(*), COALESCE(MAX(position) + 1, 0) FROM product_images WHERE product_id = $1"
+    )
+    .bind(product_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if count >= max_images {
+        return Ok(None);
+    }
 
-    Ok(product)
+    let (id,): (i32,) = sqlx::query_as(
+        "INSERT INTO product_images (product_id, image, position) VALUES ($1, $2, $3) RETURNING id"
+    )
+    .bind(product_id)
+    .bind(image)
+    .bind(next_position)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(ProductImage { id, image: image.to_string(), position: next_position }))
 }
 
-pub async fn delete_product(pool: &PgPool, product_id: i32, vendor_id: i32) -> Result<(), sqlx::Error> {
-    sqlx::query("DELETE FROM products WHERE id = $1 AND vendor_id = $2")
+/// Reorder a product's gallery. `image_ids` must list every image of the
+/// product exactly once; returns false (changing nothing) otherwise.
+pub async fn reorder_product_images(pool: &PgPool, product_id: i32, image_ids: &[i32]) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let mut current: Vec<i32> = sqlx::query_scalar("SELECT id FROM product_images WHERE product_id = $1 FOR UPDATE")
         .bind(product_id)
-        .bind(vendor_id)
-        .execute(pool)
+        .fetch_all(&mut *tx)
         .await?;
-    Ok(())
-}
+    let mut requested = image_ids.to_vec();
+    current.sort_unstable();
+    requested.sort_unstable();
+    if current != requested {
+        return Ok(false);
+    }
 
-pub async fn get_all_cart_items(pool: &PgPool) -> Result<Vec<CartItem>, sqlx::Error> {
-    let rows = sqlx::query(
+    sqlx::query(
         r#"
-        SELECT
-            ci.id, ci.user_id, ci.product_id, ci.quantity,
-            p.id as p_id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id,
-            u.username as user_name
-        FROM cart_items ci
-        JOIN products p ON ci.product_id = p.id
-        JOIN users u ON ci.user_id = u.id
-        ORDER BY ci.updated_at DESC
+        UPDATE product_images pi SET position = o.position - 1
+        FROM UNNEST($2::INTEGER[]) WITH ORDINALITY AS o(id, position)
+        WHERE pi.id = o.id AND pi.product_id = $1
         "#,
     )
-    .fetch_all(pool)
+    .bind(product_id)
+    .bind(image_ids)
+    .execute(&mut *tx)
     .await?;
 
-    let mut cart_items = Vec::new();
-    for row in rows {
-        let product = Product {
-            id: row.try_get::<i32, _>("p_id")? as u32,
-            name: row.try_get("name")?,
-            price: row.try_get::<f64, _>("price")?,
-            category: row.try_get("category")?,
-            description: row.try_get::<Option<String>, _>("description")?,
-            image: row.try_get::<Option<String>, _>("image")?,
-            quantity: row.try_get("quantity")?,
-            vendor_id: row.try_get::<i32, _>("vendor_id")? as u32,
-        };
+    tx.commit().await?;
+    Ok(true)
+}
 
-        let cart_item = CartItem {
-            id: row.try_get("id")?,
-            user_id: row.try_get("user_id")?,
-            product_id: row.try_get("product_id")?,
-            quantity: row.try_get("quantity")?,
-            product,
-        };
-        cart_items.push(cart_item);
-    }
+/// Remove an image from a product's gallery, closing the gap it leaves.
+pub async fn delete_product_image(pool: &PgPool, product_id: i32, image_id: i32) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let position: i32 = sqlx::query_scalar(
+        "DELETE FROM product_images WHERE id = $1 AND product_id = $2 RETURNING position"
+    )
+    .bind(image_id)
+    .bind(product_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(sqlx::Error::RowNotFound)?;
 
-    Ok(cart_items)
+    sqlx::query("UPDATE product_images SET position = position - 1 WHERE product_id = $1 AND position > $2")
+        .bind(product_id)
+        .bind(position)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
 }
 
-// Message functions
-pub async fn send_message(pool: &PgPool, sender_id: i32, receiver_id: i32, content: &str) -> Result<crate::models::Message, sqlx::Error> {
-    let row = sqlx::query(
+/// Most used tags across products from active, verified vendors.
+pub async fn get_popular_tags(pool: &PgPool, limit: i64) -> Result<Vec<crate::models::TagCount>, sqlx::Error> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
         r#"
-        INSERT INTO messages (sender_id, receiver_id, content)
-        VALUES ($1, $2, $3)
-        RETURNING id, sender_id, receiver_id, content, is_read, to_char(created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at
+        SELECT t.tag, COUNT(*) as product_count
+        FROM product_tags t
+        JOIN products p ON t.product_id = p.id
+        JOIN users u ON p.vendor_id = u.id
+        WHERE u.verified = TRUE AND u.banned = FALSE AND u.deleted_at IS NULL
+        GROUP BY t.tag
+        ORDER BY product_count DESC, t.tag
+        LIMIT $1
         "#,
     )
-    .bind(sender_id)
-    .bind(receiver_id)
-    .bind(content)
-    .fetch_one(pool)
+    .bind(limit)
+    .fetch_all(pool)
     .await?;
 
-    // Get usernames
-    let sender_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
-        .bind(sender_id)
-        .fetch_one(pool)
-        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(tag, product_count)| crate::models::TagCount { tag, product_count: product_count as i32 })
+        .collect())
+}
 
-    let receiver_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
-        .bind(receiver_id)
-        .fetch_one(pool)
-        .await?;
+/// Feature or unfeature a product. `days` bounds the promotion (None = until turned off);
+/// with `vendor_id`, only that vendor's product matches. RowNotFound if nothing matched.
+pub async fn set_product_featured(
+    pool: &PgPool,
+    product_id: i32,
+    featured: bool,
+    days: Option<i64>,
+    vendor_id: Option<i32>,
+) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE products
+        SET is_featured = $1,
+            featured_until = CASE WHEN $1 AND $2::bigint IS NOT NULL THEN NOW() + make_interval(days => $2::int) ELSE NULL END
+        WHERE id = $3 AND ($4::int IS NULL OR vendor_id = $4)
+        "#,
+    )
+    .bind(featured)
+    .bind(days)
+    .bind(product_id)
+    .bind(vendor_id)
+    .execute(pool)
+    .await?;
 
-    Ok(crate::models::Message {
-        id: row.try_get("id")?,
-        sender_id: row.try_get("sender_id")?,
-        receiver_id: row.try_get("receiver_id")?,
-        content: row.try_get("content")?,
-        is_read: row.try_get("is_read")?,
-        created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "2025-01-01T00:00:00Z".to_string()),
-        sender_username,
-        receiver_username,
-    })
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
 }
 
-pub async fn get_messages_between_users(pool: &PgPool, user1_id: i32, user2_id: i32) -> Result<Vec<crate::models::Message>, sqlx::Error> {
+/// Products whose promotion is active; expired ones drop out automatically.
+pub async fn get_featured_products(pool: &PgPool) -> Result<Vec<Product>, sqlx::Error> {
     let rows = sqlx::query(
         r#"
-        SELECT
-            m.id, m.sender_id, m.receiver_id, m.content, m.is_read, 
-            to_char(m.created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at,
-            su.username as sender_username, ru.username as receiver_username
-        FROM messages m
-        JOIN users su ON m.sender_id = su.id
-        JOIN users ru ON m.receiver_id = ru.id
-        WHERE (m.sender_id = $1 AND m.receiver_id = $2) OR (m.sender_id = $2 AND m.receiver_id = $1)
-        ORDER BY m.created_at ASC
+        SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
+        FROM products p
+        JOIN users u ON p.vendor_id = u.id
+        WHERE u.verified = TRUE AND u.banned = FALSE AND u.deleted_at IS NULL AND u.is_paused = FALSE AND p.removed_at IS NULL AND p.flagged_at IS NULL
+        AND p.is_featured AND (p.featured_until IS NULL OR p.featured_until > NOW())
+        ORDER BY p.featured_until NULLS FIRST, p.id
         "#,
     )
-    .bind(user1_id)
-    .bind(user2_id)
     .fetch_all(pool)
     .await?;
 
-    let mut messages = Vec::new();
+    let mut products = Vec::new();
     for row in rows {
-        messages.push(crate::models::Message {
-            id: row.try_get("id")?,
-            sender_id: row.try_get("sender_id")?,
-            receiver_id: row.try_get("receiver_id")?,
-            content: row.try_get("content")?,
-            is_read: row.try_get("is_read")?,
-            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "2025-01-01T00:00:00Z".to_string()),
-            sender_username: row.try_get("sender_username")?,
-            receiver_username: row.try_get("receiver_username")?,
+        products.push(Product {
+            id: row.try_get::<i32, _>(0)? as u32,
+            name: row.try_get(1)?,
+            price: row.try_get::<f64, _>(2)?,
+            category: row.try_get(3)?,
+            description: row.try_get::<Option<String>, _>(4)?,
+            image: row.try_get::<Option<String>, _>(5)?,
+            quantity: row.try_get(6)?,
+            vendor_id: row.try_get::<i32, _>(7)? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
         });
     }
 
-    Ok(messages)
+    drop_suspended_vendors(pool, &mut products).await?;
+    attach_tags(pool, &mut products).await?;
+    attach_gallery(pool, &mut products).await?;
+    attach_attributes(pool, &mut products).await?;
+    Ok(products)
 }
 
-pub async fn get_user_conversations(pool: &PgPool, user_id: i32) -> Result<Vec<crate::models::Conversation>, sqlx::Error> {
+/// In-stock products listed in the last `days` days, newest first, from
+/// vendors visible in the catalog.
+pub async fn get_new_arrivals(pool: &PgPool, days: i32) -> Result<Vec<crate::models::NewArrival>, sqlx::Error> {
     let rows = sqlx::query(
         r#"
-        WITH user_messages AS (
-            SELECT DISTINCT ON (
-                LEAST(sender_id, receiver_id),
-                GREATEST(sender_id, receiver_id)
-            )
-                *,
-                CASE
-                    WHEN sender_id = $1 THEN receiver_id
-                    ELSE sender_id
-                END as other_user_id
-            FROM messages
-            WHERE sender_id = $1 OR receiver_id = $1
-            ORDER BY LEAST(sender_id, receiver_id), GREATEST(sender_id, receiver_id), created_at DESC
-        ),
-        unread_counts AS (
-            SELECT
-                sender_id as other_user_id,
-                COUNT(*) as unread_count
-            FROM messages
-            WHERE receiver_id = $1 AND is_read = FALSE
-            GROUP BY sender_id
-        )
-        SELECT
-            um.other_user_id as id,
-            u.username,
-            u.profile_image,
-            um.content as last_message,
-            um.created_at as last_message_time,
-            COALESCE(uc.unread_count, 0) as unread_count
-        FROM user_messages um
-        JOIN users u ON u.id = um.other_user_id
-        LEFT JOIN unread_counts uc ON uc.other_user_id = um.other_user_id
-        ORDER BY um.created_at DESC
+        SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id, p.created_at
+        FROM products p
+        JOIN users u ON p.vendor_id = u.id
+        WHERE u.verified = TRUE AND u.banned = FALSE AND u.deleted_at IS NULL AND u.is_paused = FALSE AND p.removed_at IS NULL AND p.flagged_at IS NULL
+        AND p.quantity > 0
+        AND p.created_at >= NOW() - make_interval(days => $1)
+        ORDER BY p.created_at DESC, p.id DESC
         "#,
     )
-    .bind(user_id)
+    .bind(days)
     .fetch_all(pool)
     .await?;
 
-    let mut conversations = Vec::new();
+    let mut products = Vec::new();
+    let mut listed_at = std::collections::HashMap::new();
     for row in rows {
-        conversations.push(crate::models::Conversation {
-            id: row.try_get("id")?,
-            username: row.try_get("username")?,
-            profile_image: row.try_get("profile_image")?,
-            last_message: row.try_get("last_message")?,
-            last_message_time: row.try_get::<Option<String>, _>("last_message_time").unwrap_or(None),
-            unread_count: row.try_get::<i64, _>("unread_count")? as i32,
+        let id: i32 = row.try_get(0)?;
+        listed_at.insert(id, row.try_get::<chrono::DateTime<chrono::Utc>, _>(8)?);
+        products.push(Product {
+            id: id as u32,
+            name: row.try_get(1)?,
+            price: row.try_get::<f64, _>(2)?,
+            category: row.try_get(3)?,
+            description: row.try_get::<Option<String>, _>(4)?,
+            image: row.try_get::<Option<String>, _>(5)?,
+            quantity: row.try_get(6)?,
+            vendor_id: row.try_get::<i32, _>(7)? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
         });
     }
 
-    Ok(conversations)
+    drop_suspended_vendors(pool, &mut products).await?;
+    attach_tags(pool, &mut products).await?;
+    attach_gallery(pool, &mut products).await?;
+    attach_attributes(pool, &mut products).await?;
+    Ok(products
+        .into_iter()
+        .map(|product| crate::models::NewArrival {
+            created_at: listed_at[&(product.id as i32)],
+            product,
+        })
+        .collect())
 }
 
-pub async fn mark_messages_as_read(pool: &PgPool, user_id: i32, other_user_id: i32) -> Result<(), sqlx::Error> {
-    // Mark all messages sent by other_user_id to current user_id as read
-    sqlx::query(
-        "UPDATE messages SET is_read = TRUE WHERE sender_id = $1 AND receiver_id = $2 AND is_read = FALSE"
-    )
-    .bind(other_user_id)
-    .bind(user_id)
-    .execute(pool)
-    .await?;
-    Ok(())
+/// Read the LEFT JOINed `reply_*` columns of a review listing.
+fn review_reply_from_row(row: &sqlx::postgres::PgRow) -> Result<Option<crate::models::ReviewReply>, sqlx::Error> {
+    let Some(id) = row.try_get::<Option<i32>, _>("reply_id")? else {
+        return Ok(None);
+    };
+    Ok(Some(crate::models::ReviewReply {
+        id,
+        review_id: row.try_get("id")?,
+        vendor_id: row.try_get("reply_vendor_id")?,
+        content: row.try_get("reply_content")?,
+        created_at: row.try_get("reply_created_at")?,
+        updated_at: row.try_get("reply_updated_at")?,
+    }))
 }
 
-pub async fn edit_message(pool: &PgPool, message_id: i32, user_id: i32, new_content: &str) -> Result<crate::models::Message, sqlx::Error> {
-    let row = sqlx::query(
-        r#"
-        UPDATE messages 
-        SET content = $1, updated_at = NOW() 
-        WHERE id = $2 AND sender_id = $3
-        RETURNING id, sender_id, receiver_id, content, is_read, 
-                  COALESCE(updated_at, created_at) as created_at
-        "#
-    )
-    .bind(new_content)
-    .bind(message_id)
-    .bind(user_id)
-    .fetch_one(pool)
-    .await?;
+/// Vendor who owns the reviewed product, or RowNotFound.
+pub async fn get_review_vendor_id(pool: &PgPool, review_id: i32) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar("SELECT vendor_id FROM reviews WHERE id = $1")
+        .bind(review_id)
+        .fetch_one(pool)
+        .await
+}
 
-    // Get sender and receiver usernames
-    let user_row = sqlx::query(
+/// Create or edit the reply to a review. The bool is true when a new reply was created.
+pub async fn upsert_review_reply(
+    pool: &PgPool,
+    review_id: i32,
+    vendor_id: i32,
+    content: &str,
+) -> Result<(crate::models::ReviewReply, bool), sqlx::Error> {
+    let row = sqlx::query(
         r#"
-        SELECT 
-            s.username as sender_username,
-            r.username as receiver_username
-        FROM messages m
-        JOIN users s ON m.sender_id = s.id
-        JOIN users r ON m.receiver_id = r.id
-        WHERE m.id = $1
-        "#
+        INSERT INTO review_replies (review_id, vendor_id, content)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (review_id) DO UPDATE SET content = EXCLUDED.content, updated_at = CURRENT_TIMESTAMP
+        RETURNING id, review_id, vendor_id, content,
+            to_char(created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at,
+            to_char(updated_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as updated_at,
+            (xmax = 0) as inserted
+        "#,
     )
-    .bind(message_id)
+    .bind(review_id)
+    .bind(vendor_id)
+    .bind(content)
     .fetch_one(pool)
     .await?;
 
-    Ok(crate::models::Message {
+    let reply = crate::models::ReviewReply {
         id: row.try_get("id")?,
-        sender_id: row.try_get("sender_id")?,
-        receiver_id: row.try_get("receiver_id")?,
+        review_id: row.try_get("review_id")?,
+        vendor_id: row.try_get("vendor_id")?,
         content: row.try_get("content")?,
-        is_read: row.try_get("is_read")?,
         created_at: row.try_get("created_at")?,
-        sender_username: user_row.try_get("sender_username")?,
-        receiver_username: user_row.try_get("receiver_username")?,
-    })
+        updated_at: row.try_get("updated_at")?,
+    };
+    Ok((reply, row.try_get("inserted")?))
 }
 
-pub async fn delete_message(pool: &PgPool, message_id: i32, user_id: i32) -> Result<(), sqlx::Error> {
+/// Author of a review, or RowNotFound.
+pub async fn get_review_customer_id(pool: &PgPool, review_id: i32) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar("SELECT customer_id FROM reviews WHERE id = $1")
+        .bind(review_id)
+        .fetch_one(pool)
+        .await
+}
+
+/// Record or change a user's vote on a review; returns the review's new helpful count.
+pub async fn vote_on_review(pool: &PgPool, user_id: i32, review_id: i32, helpful: bool) -> Result<i64, sqlx::Error> {
     sqlx::query(
-        "DELETE FROM messages WHERE id = $1 AND sender_id = $2"
+        r#"
+        INSERT INTO review_votes (user_id, review_id, helpful)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, review_id) DO UPDATE SET helpful = EXCLUDED.helpful
+        "#,
     )
-    .bind(message_id)
     .bind(user_id)
+    .bind(review_id)
+    .bind(helpful)
     .execute(pool)
     .await?;
-    Ok(())
+
+    sqlx::query_scalar("SELECT COUNT(*) FROM review_votes WHERE review_id = $1 AND helpful")
+        .bind(review_id)
+        .fetch_one(pool)
+        .await
 }
 
-// Follow functions
-pub async fn follow_vendor(pool: &PgPool, follower_id: i32, vendor_id: i32) -> Result<crate::models::Follow, sqlx::Error> {
-    let row = sqlx::query(
+const APPEAL_COLUMNS: &str = r#"
+    a.id, a.vendor_id, u.username as vendor_username, a.message, a.status, a.admin_notes,
+    to_char(a.created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at,
+    to_char(a.resolved_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as resolved_at
+"#;
+
+fn appeal_from_row(row: &sqlx::postgres::PgRow) -> Result<crate::models::Appeal, sqlx::Error> {
+    Ok(crate::models::Appeal {
+        id: row.try_get("id")?,
+        vendor_id: row.try_get("vendor_id")?,
+        vendor_username: row.try_get("vendor_username")?,
+        message: row.try_get("message")?,
+        status: row.try_get("status")?,
+        admin_notes: row.try_get("admin_notes")?,
+        created_at: row.try_get("created_at")?,
+        resolved_at: row.try_get("resolved_at")?,
+    })
+}
+
+/// Record an appeal. Fails with a unique violation if the vendor already has one pending.
+pub async fn create_appeal(pool: &PgPool, vendor_id: i32, message: &str) -> Result<crate::models::Appeal, sqlx::Error> {
+    let id: i32 = sqlx::query_scalar("INSERT INTO appeals (vendor_id, message) VALUES ($1, $2) RETURNING id")
+        .bind(vendor_id)
+        .bind(message)
+        .fetch_one(pool)
+        .await?;
+    get_appeal(pool, id).await
+}
+
+pub async fn get_appeal(pool: &PgPool, appeal_id: i32) -> Result<crate::models::Appeal, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM appeals a JOIN users u ON a.vendor_id = u.id WHERE a.id = $1",
+        APPEAL_COLUMNS
+    ))
+    .bind(appeal_id)
+    .fetch_one(pool)
+    .await?;
+    appeal_from_row(&row)
+}
+
+/// All appeals, optionally filtered by status, oldest first.
+pub async fn get_appeals(pool: &PgPool, status: Option<&str>) -> Result<Vec<crate::models::Appeal>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM appeals a JOIN users u ON a.vendor_id = u.id WHERE ($1::text IS NULL OR a.status = $1) ORDER BY a.created_at, a.id",
+        APPEAL_COLUMNS
+    ))
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(appeal_from_row).collect()
+}
+
+/// Resolve a pending appeal. Approval lifts the vendor's ban and dismisses their
+/// outstanding reports in the same transaction. RowNotFound if not pending.
+pub async fn resolve_appeal(
+    pool: &PgPool,
+    appeal_id: i32,
+    approved: bool,
+    admin_notes: Option<&str>,
+) -> Result<crate::models::Appeal, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let vendor_id: i32 = sqlx::query_scalar(
         r#"
-        INSERT INTO follows (follower_id, vendor_id)
-        VALUES ($1, $2)
-        RETURNING id, follower_id, vendor_id, created_at
+        UPDATE appeals SET status = $1, admin_notes = $2, resolved_at = CURRENT_TIMESTAMP
+        WHERE id = $3 AND status = 'pending'
+        RETURNING vendor_id
         "#,
     )
-    .bind(follower_id)
-    .bind(vendor_id)
-    .fetch_one(pool)
+    .bind(if approved { "approved" } else { "rejected" })
+    .bind(admin_notes)
+    .bind(appeal_id)
+    .fetch_one(&mut *tx)
     .await?;
 
-    // Get usernames
-    let follower_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
-        .bind(follower_id)
-        .fetch_one(pool)
-        .await?;
-
-    let vendor_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+    if approved {
+        sqlx::query("UPDATE users SET banned = FALSE WHERE id = $1")
+            .bind(vendor_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            UPDATE vendor_reports
+            SET status = 'dismissed', admin_notes = COALESCE(admin_notes, 'Dismissed on appeal'), updated_at = CURRENT_TIMESTAMP
+            WHERE vendor_id = $1 AND status <> 'dismissed'
+            "#,
+        )
         .bind(vendor_id)
-        .fetch_one(pool)
+        .execute(&mut *tx)
         .await?;
+    }
 
-    Ok(crate::models::Follow {
+    tx.commit().await?;
+    get_appeal(pool, appeal_id).await
+}
+
+fn shipping_option_from_row(row: &sqlx::postgres::PgRow) -> Result<crate::models::ShippingOption, sqlx::Error> {
+    let method: String = row.try_get("method")?;
+    Ok(crate::models::ShippingOption {
         id: row.try_get("id")?,
-        follower_id: row.try_get("follower_id")?,
         vendor_id: row.try_get("vendor_id")?,
-        created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-        follower_username,
-        vendor_username,
+        name: row.try_get("name")?,
+        method: method.parse().map_err(|e: String| sqlx::Error::Decode(e.into()))?,
+        fee: row.try_get("fee")?,
+        free_threshold: row.try_get("free_threshold")?,
+        per_km_rate: row.try_get("per_km_rate")?,
     })
 }
 
-pub async fn unfollow_vendor(pool: &PgPool, follower_id: i32, vendor_id: i32) -> Result<(), sqlx::Error> {
-    sqlx::query("DELETE FROM follows WHERE follower_id = $1 AND vendor_id = $2")
-        .bind(follower_id)
+pub async fn create_shipping_option(
+    pool: &PgPool,
+    vendor_id: i32,
+    option: &crate::models::ShippingOptionRequest,
+) -> Result<crate::models::ShippingOption, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO shipping_options (vendor_id, name, method, fee, free_threshold, per_km_rate)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, vendor_id, name, method, fee, free_threshold, per_km_rate
+        "#,
+    )
+    .bind(vendor_id)
+    .bind(option.name.trim())
+    .bind(option.method.as_str())
+    .bind(option.fee)
+    .bind(option.free_threshold)
+    .bind(option.per_km_rate)
+    .fetch_one(pool)
+    .await?;
+    shipping_option_from_row(&row)
+}
+
+/// Deactivate one of a vendor's shipping options. RowNotFound if it isn't theirs.
+pub async fn deactivate_shipping_option(pool: &PgPool, vendor_id: i32, option_id: i32) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE shipping_options SET active = FALSE WHERE id = $1 AND vendor_id = $2 AND active")
+        .bind(option_id)
         .bind(vendor_id)
         .execute(pool)
         .await?;
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
     Ok(())
 }
 
-pub async fn is_following(pool: &PgPool, follower_id: i32, vendor_id: i32) -> Result<bool, sqlx::Error> {
-    let result: Option<(i32,)> = sqlx::query_as(
-        "SELECT id FROM follows WHERE follower_id = $1 AND vendor_id = $2"
+/// Active shipping options for the given vendors.
+pub async fn get_shipping_options(pool: &PgPool, vendor_ids: &[i32]) -> Result<Vec<crate::models::ShippingOption>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, vendor_id, name, method, fee, free_threshold, per_km_rate
+        FROM shipping_options
+        WHERE vendor_id = ANY($1) AND active
+        ORDER BY vendor_id, id
+        "#,
     )
-    .bind(follower_id)
-    .bind(vendor_id)
-    .fetch_optional(pool)
+    .bind(vendor_ids)
+    .fetch_all(pool)
     .await?;
-
-    Ok(result.is_some())
+    rows.iter().map(shipping_option_from_row).collect()
 }
 
-pub async fn get_user_follows(pool: &PgPool, user_id: i32) -> Result<Vec<crate::models::Follow>, sqlx::Error> {
-    let rows = sqlx::query(
-        r#"
-        SELECT
-            f.id, f.follower_id, f.vendor_id, f.created_at,
-            fu.username as follower_username, vu.username as vendor_username
-        FROM follows f
-        JOIN users fu ON f.follower_id = fu.id
-        JOIN users vu ON f.vendor_id = vu.id
-        WHERE f.follower_id = $1
-        ORDER BY f.created_at DESC
-        "#,
+/// Stored coordinates for the given users; users without a location are absent.
+pub async fn get_user_coordinates(pool: &PgPool, user_ids: &[i32]) -> Result<std::collections::HashMap<i32, (f64, f64)>, sqlx::Error> {
+    let rows: Vec<(i32, f64, f64)> = sqlx::query_as(
+        "SELECT id, latitude, longitude FROM users WHERE id = ANY($1) AND latitude IS NOT NULL AND longitude IS NOT NULL"
     )
-    .bind(user_id)
+    .bind(user_ids)
     .fetch_all(pool)
     .await?;
+    Ok(rows.into_iter().map(|(id, lat, lon)| (id, (lat, lon))).collect())
+}
 
-    let mut follows = Vec::new();
-    for row in rows {
-        follows.push(crate::models::Follow {
-            id: row.try_get("id")?,
-            follower_id: row.try_get("follower_id")?,
-            vendor_id: row.try_get("vendor_id")?,
-            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-            follower_username: row.try_get("follower_username")?,
-            vendor_username: row.try_get("vendor_username")?,
-        });
-    }
+pub async fn set_payment_shipping_charges(pool: &PgPool, checkout_request_id: &str, charges: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE payment_transactions SET shipping_charges = $1 WHERE checkout_request_id = $2")
+        .bind(charges)
+        .bind(checkout_request_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
 
-    Ok(follows)
+pub async fn get_payment_shipping_charges(pool: &PgPool, checkout_request_id: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT shipping_charges FROM payment_transactions WHERE checkout_request_id = $1")
+        .bind(checkout_request_id)
+        .fetch_one(pool)
+        .await
 }
 
-pub async fn get_vendor_followers(pool: &PgPool, vendor_id: i32) -> Result<Vec<crate::models::Follow>, sqlx::Error> {
-    let rows = sqlx::query(
-        r#"
-        SELECT
-            f.id, f.follower_id, f.vendor_id, f.created_at,
-            fu.username as follower_username, vu.username as vendor_username
-        FROM follows f
-        JOIN users fu ON f.follower_id = fu.id
-        JOIN users vu ON f.vendor_id = vu.id
-        WHERE f.vendor_id = $1
-        ORDER BY f.created_at DESC
-        "#,
-    )
+fn coupon_from_row(row: &sqlx::postgres::PgRow) -> Result<crate::models::Coupon, sqlx::Error> {
+    Ok(crate::models::Coupon {
+        id: row.try_get("id")?,
+        vendor_id: row.try_get("vendor_id")?,
+        code: row.try_get("code")?,
+        percent_off: row.try_get("percent_off")?,
+        max_uses: row.try_get("max_uses")?,
+        times_used: row.try_get("times_used")?,
+        expires_at: row.try_get("expires_at")?,
+        active: row.try_get("active")?,
+    })
+}
+
+const COUPON_COLUMNS: &str = "id, vendor_id, code, percent_off, max_uses, times_used, expires_at, active";
+
+/// Create a coupon for `vendor_id`. The code is stored uppercase; a code already
+/// in use by any vendor fails with a unique violation.
+pub async fn create_coupon(
+    pool: &PgPool,
+    vendor_id: i32,
+    coupon: &crate::models::CouponRequest,
+) -> Result<crate::models::Coupon, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "INSERT INTO coupons (vendor_id, code, percent_off, max_uses, expires_at) VALUES ($1, $2, $3, $4, $5) RETURNING {}",
+        COUPON_COLUMNS
+    ))
+    .bind(vendor_id)
+    .bind(crate::coupons::normalize_code(&coupon.code))
+    .bind(coupon.percent_off)
+    .bind(coupon.max_uses)
+    .bind(coupon.expires_at)
+    .fetch_one(pool)
+    .await?;
+    coupon_from_row(&row)
+}
+
+/// A vendor's coupons, active ones first.
+pub async fn get_vendor_coupons(pool: &PgPool, vendor_id: i32) -> Result<Vec<crate::models::Coupon>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM coupons WHERE vendor_id = $1 ORDER BY active DESC, id DESC",
+        COUPON_COLUMNS
+    ))
     .bind(vendor_id)
     .fetch_all(pool)
     .await?;
+    rows.iter().map(coupon_from_row).collect()
+}
 
-    let mut follows = Vec::new();
-    for row in rows {
-        follows.push(crate::models::Follow {
-            id: row.try_get("id")?,
-            follower_id: row.try_get("follower_id")?,
-            vendor_id: row.try_get("vendor_id")?,
-            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-            follower_username: row.try_get("follower_username")?,
-            vendor_username: row.try_get("vendor_username")?,
-        });
+/// Coupons with the given (normalized) codes, active or not.
+pub async fn get_coupons_by_code(pool: &PgPool, codes: &[String]) -> Result<Vec<crate::models::Coupon>, sqlx::Error> {
+    let rows = sqlx::query(&format!("SELECT {} FROM coupons WHERE code = ANY($1)", COUPON_COLUMNS))
+        .bind(codes)
+        .fetch_all(pool)
+        .await?;
+    rows.iter().map(coupon_from_row).collect()
+}
+
+/// Deactivate one of a vendor's coupons. RowNotFound if it isn't theirs.
+pub async fn deactivate_coupon(pool: &PgPool, vendor_id: i32, coupon_id: i32) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE coupons SET active = FALSE WHERE id = $1 AND vendor_id = $2 AND active")
+        .bind(coupon_id)
+        .bind(vendor_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
     }
+    Ok(())
+}
 
-    Ok(follows)
+/// Count one use of a coupon against its limit. Returns false, counting
+/// nothing, if the coupon was already used up.
+pub async fn redeem_coupon(pool: &PgPool, coupon_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE coupons SET times_used = times_used + 1 WHERE id = $1 AND (max_uses IS NULL OR times_used < max_uses)"
+    )
+    .bind(coupon_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
 }
 
-// Review functions
-pub async fn create_review(
-    pool: &PgPool,
-    customer_id: i32,
-    product_id: i32,
-    rating: i32,
-    comment: Option<&str>
-) -> Result<crate::models::Review, sqlx::Error> {
-    // Get vendor_id from product
-    let vendor_id: i32 = sqlx::query_scalar("SELECT vendor_id FROM products WHERE id = $1")
-        .bind(product_id)
+pub async fn set_payment_coupon_discounts(pool: &PgPool, checkout_request_id: &str, discounts: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE payment_transactions SET coupon_discounts = $1 WHERE checkout_request_id = $2")
+        .bind(discounts)
+        .bind(checkout_request_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_payment_coupon_discounts(pool: &PgPool, checkout_request_id: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT coupon_discounts FROM payment_transactions WHERE checkout_request_id = $1")
+        .bind(checkout_request_id)
         .fetch_one(pool)
+        .await
+}
+
+/// Take a coupon discount off a line item's total.
+pub async fn set_order_discount(pool: &PgPool, order_id: i32, coupon_id: i32, discount: f64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE shipping_orders SET coupon_id = $1, discount = $2, total_amount = total_amount - $2 WHERE id = $3")
+        .bind(coupon_id)
+        .bind(discount)
+        .bind(order_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn set_order_tax(pool: &PgPool, order_id: i32, tax: f64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE shipping_orders SET tax_amount = $1 WHERE id = $2")
+        .bind(tax)
+        .bind(order_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn set_order_shipping(pool: &PgPool, order_id: i32, option_id: Option<i32>, fee: f64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE shipping_orders SET shipping_option_id = $1, shipping_fee = $2 WHERE id = $3")
+        .bind(option_id)
+        .bind(fee)
+        .bind(order_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn set_order_payment_transaction(pool: &PgPool, order_id: i32, transaction_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE shipping_orders SET payment_transaction_id = $1 WHERE id = $2")
+        .bind(transaction_id)
+        .bind(order_id)
+        .execute(pool)
         .await?;
+    Ok(())
+}
 
+/// Everything printed on an order's invoice, with buyer/seller details and the
+/// receipt of the payment that created it.
+pub async fn get_order_invoice(pool: &PgPool, order_id: i32) -> Result<crate::models::OrderInvoice, sqlx::Error> {
     let row = sqlx::query(
         r#"
-        INSERT INTO reviews (customer_id, product_id, vendor_id, rating, comment)
-        VALUES ($1, $2, $3, $4, $5)
-        RETURNING id, customer_id, product_id, vendor_id, rating, comment, created_at
+        SELECT
+            so.id, to_char(so.created_at, 'YYYY-MM-DD') AS issued_on, so.shipping_status,
+            so.quantity, so.total_amount, so.tax_amount, so.shipping_fee, so.shipping_address,
+            p.name AS product_name,
+            cu.id AS customer_id, cu.username AS customer_username, cu.email AS customer_email,
+            cu.location_string AS customer_location,
+            vu.id AS vendor_id, vu.username AS vendor_username, vu.email AS vendor_email,
+            vu.location_string AS vendor_location,
+            pt.mpesa_receipt_number
+        FROM shipping_orders so
+        JOIN users cu ON so.customer_id = cu.id
+        JOIN users vu ON so.vendor_id = vu.id
+        JOIN products p ON so.product_id = p.id
+        LEFT JOIN payment_transactions pt ON so.payment_transaction_id = pt.id
+        WHERE so.id = $1
         "#,
     )
-    .bind(customer_id)
-    .bind(product_id)
-    .bind(vendor_id)
-    .bind(rating)
-    .bind(comment)
+    .bind(order_id)
     .fetch_one(pool)
     .await?;
 
-    // Get usernames and product name
-    let customer_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
-        .bind(customer_id)
-        .fetch_one(pool)
-        .await?;
-
-    let product_name = sqlx::query_scalar("SELECT name FROM products WHERE id = $1")
-        .bind(product_id)
-        .fetch_one(pool)
-        .await?;
-
-    Ok(crate::models::Review {
-        id: row.try_get("id")?,
+    Ok(crate::models::OrderInvoice {
+        order_id: row.try_get("id")?,
+        issued_on: row.try_get("issued_on")?,
+        shipping_status: row.try_get::<Option<String>, _>("shipping_status")?.unwrap_or_else(|| "pending".to_string()),
+        product_name: row.try_get("product_name")?,
+        quantity: row.try_get("quantity")?,
+        items_total: row.try_get("total_amount")?,
+        tax_amount: row.try_get("tax_amount")?,
+        shipping_fee: row.try_get("shipping_fee")?,
+        shipping_address: row.try_get("shipping_address")?,
         customer_id: row.try_get("customer_id")?,
-        product_id: row.try_get("product_id")?,
+        customer_username: row.try_get("customer_username")?,
+        customer_email: row.try_get("customer_email")?,
+        customer_location: row.try_get("customer_location")?,
         vendor_id: row.try_get("vendor_id")?,
-        rating: row.try_get("rating")?,
-        comment: row.try_get("comment")?,
-        created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-        customer_username,
-        product_name,
+        vendor_username: row.try_get("vendor_username")?,
+        vendor_email: row.try_get("vendor_email")?,
+        vendor_location: row.try_get("vendor_location")?,
+        mpesa_receipt_number: row.try_get("mpesa_receipt_number")?,
     })
 }
 
-pub async fn get_product_reviews(pool: &PgPool, product_id: i32) -> Result<Vec<crate::models::Review>, sqlx::Error> {
-    let rows = sqlx::query(
+/// An order's receipt with every line item, or None if the order doesn't exist.
+pub async fn get_order_receipt(pool: &PgPool, order_id: i32) -> Result<Option<crate::models::OrderReceipt>, sqlx::Error> {
+    use crate::models::round_cents;
+
+    let order = sqlx::query(
         r#"
-        SELECT
-            r.id, r.customer_id, r.product_id, r.vendor_id, r.rating, r.comment, r.created_at,
-            u.username as customer_username, p.name as product_name
-        FROM reviews r
-        JOIN users u ON r.customer_id = u.id
-        JOIN products p ON r.product_id = p.id
-        WHERE r.product_id = $1
-        ORDER BY r.created_at DESC
+        SELECT o.id, o.customer_id, cu.username AS customer_username, o.shipping_address,
+               to_char(o.created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS created_at,
+               pt.id AS payment_id, pt.merchant_request_id, pt.status AS payment_status,
+               pt.amount::float8 AS payment_amount, pt.phone_number, pt.mpesa_receipt_number,
+               to_char(pt.transaction_date AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS paid_at
+        FROM orders o
+        JOIN users cu ON cu.id = o.customer_id
+        LEFT JOIN payment_transactions pt ON pt.id = o.payment_transaction_id
+        WHERE o.id = $1
         "#,
     )
-    .bind(product_id)
-    .fetch_all(pool)
+    .bind(order_id)
+    .fetch_optional(pool)
     .await?;
+    let Some(order) = order else { return Ok(None) };
 
-    let mut reviews = Vec::new();
-    for row in rows {
-        reviews.push(crate::models::Review {
-            id: row.try_get("id")?,
-            customer_id: row.try_get("customer_id")?,
-            product_id: row.try_get("product_id")?,
-            vendor_id: row.try_get("vendor_id")?,
-            rating: row.try_get("rating")?,
-            comment: row.try_get("comment")?,
-            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-            customer_username: row.try_get("customer_username")?,
-            product_name: row.try_get("product_name")?,
-        });
-    }
-
-    Ok(reviews)
-}
-
-pub async fn get_customer_reviews(pool: &PgPool, customer_id: i32) -> Result<Vec<crate::models::Review>, sqlx::Error> {
     let rows = sqlx::query(
         r#"
-        SELECT
-            r.id, r.customer_id, r.product_id, r.vendor_id, r.rating, r.comment, r.created_at,
-            u.username as customer_username, p.name as product_name
-        FROM reviews r
-        JOIN users u ON r.customer_id = u.id
-        JOIN products p ON r.product_id = p.id
-        WHERE r.customer_id = $1
-        ORDER BY r.created_at DESC
+        SELECT so.id, so.product_id, p.name AS product_name, so.vendor_id, vu.username AS vendor_username,
+               so.quantity, so.total_amount, so.discount, so.tax_amount, so.shipping_fee, c.code AS coupon_code,
+               COALESCE(so.shipping_status, 'pending') AS shipping_status
+        FROM shipping_orders so
+        JOIN products p ON so.product_id = p.id
+        JOIN users vu ON so.vendor_id = vu.id
+        LEFT JOIN coupons c ON c.id = so.coupon_id
+        WHERE so.order_id = $1
+        ORDER BY so.id
         "#,
     )
-    .bind(customer_id)
+    .bind(order_id)
     .fetch_all(pool)
     .await?;
 
-    let mut reviews = Vec::new();
+    let mut items = Vec::new();
     for row in rows {
-        reviews.push(crate::models::Review {
+        let quantity: i32 = row.try_get("quantity")?;
+        let discount: f64 = row.try_get("discount")?;
+        let shipping_fee: f64 = row.try_get("shipping_fee")?;
+        // total_amount is already net of the discount
+        let subtotal = round_cents(row.try_get::<f64, _>("total_amount")? + discount);
+        items.push(crate::models::ReceiptLine {
             id: row.try_get("id")?,
-            customer_id: row.try_get("customer_id")?,
             product_id: row.try_get("product_id")?,
-            vendor_id: row.try_get("vendor_id")?,
-            rating: row.try_get("rating")?,
-            comment: row.try_get("comment")?,
-            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-            customer_username: row.try_get("customer_username")?,
             product_name: row.try_get("product_name")?,
+            vendor_id: row.try_get("vendor_id")?,
+            vendor_username: row.try_get("vendor_username")?,
+            quantity,
+            unit_price: round_cents(subtotal / quantity.max(1) as f64),
+            subtotal,
+            discount,
+            coupon_code: row.try_get("coupon_code")?,
+            tax: row.try_get("tax_amount")?,
+            shipping_fee,
+            total: round_cents(subtotal - discount + shipping_fee),
+            status: row.try_get("shipping_status")?,
         });
     }
 
-    Ok(reviews)
+    let payment = match order.try_get::<Option<i32>, _>("payment_id")? {
+        Some(_) => Some(crate::models::ReceiptPayment {
+            method: if order.try_get::<String, _>("merchant_request_id")? == "DEMO" { "demo" } else { "mpesa" }.to_string(),
+            status: order.try_get("payment_status")?,
+            amount: order.try_get("payment_amount")?,
+            phone_number: order.try_get("phone_number")?,
+            mpesa_receipt_number: order.try_get("mpesa_receipt_number")?,
+            paid_at: order.try_get("paid_at")?,
+        }),
+        None => None,
+    };
+
+    let mut receipt = crate::models::OrderReceipt {
+        order_id: order.try_get("id")?,
+        customer_id: order.try_get("customer_id")?,
+        customer_username: order.try_get("customer_username")?,
+        shipping_address: order.try_get("shipping_address")?,
+        created_at: order.try_get::<Option<String>, _>("created_at")?.unwrap_or_default(),
+        currency: "KES".to_string(),
+        items,
+        subtotal: 0.0,
+        discount_total: 0.0,
+        tax_total: 0.0,
+        shipping_total: 0.0,
+        total: 0.0,
+        payment,
+    };
+    receipt.update_totals();
+    Ok(Some(receipt))
 }
 
-// Shipping order functions
-pub async fn create_shipping_order(
-    pool: &PgPool,
-    customer_id: i32,
-    product_id: i32,
-    quantity: i32,
-    shipping_address: &str
-) -> Result<crate::models::ShippingOrder, sqlx::Error> {
-    // Get product details
-    let product_row: (i32, String, f64) = sqlx::query_as(
-        "SELECT vendor_id, name, price FROM products WHERE id = $1"
+/// Block `blocked_id` for `blocker_id` and drop any follow between them.
+pub async fn block_user(pool: &PgPool, blocker_id: i32, blocked_id: i32) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("INSERT INTO blocks (blocker_id, blocked_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(blocker_id)
+        .bind(blocked_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "DELETE FROM follows WHERE (follower_id = $1 AND vendor_id = $2) OR (follower_id = $2 AND vendor_id = $1)"
     )
-    .bind(product_id)
-    .fetch_one(pool)
+    .bind(blocker_id)
+    .bind(blocked_id)
+    .execute(&mut *tx)
     .await?;
+    tx.commit().await
+}
 
-    let vendor_id = product_row.0;
-    let product_name = product_row.1;
-    let price = product_row.2;
-    let total_amount = price * quantity as f64;
+/// Remove a block. Returns false if there was none.
+pub async fn unblock_user(pool: &PgPool, blocker_id: i32, blocked_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM blocks WHERE blocker_id = $1 AND blocked_id = $2")
+        .bind(blocker_id)
+        .bind(blocked_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
 
-    let row = sqlx::query(
+/// Whether either user has blocked the other.
+pub async fn is_blocked_between(pool: &PgPool, user_a: i32, user_b: i32) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
         r#"
-        INSERT INTO shipping_orders (customer_id, product_id, vendor_id, quantity, total_amount, shipping_address)
-        VALUES ($1, $2, $3, $4, $5, $6)
-        RETURNING id, customer_id, product_id, vendor_id, quantity, total_amount, shipping_status,
-                  tracking_number, shipping_address, created_at, updated_at
+        SELECT EXISTS (
+            SELECT 1 FROM blocks
+            WHERE (blocker_id = $1 AND blocked_id = $2) OR (blocker_id = $2 AND blocked_id = $1)
+        )
         "#,
     )
-    .bind(customer_id)
-    .bind(product_id)
-    .bind(vendor_id)
-    .bind(quantity)
-    .bind(total_amount)
-    .bind(shipping_address)
+    .bind(user_a)
+    .bind(user_b)
     .fetch_one(pool)
-    .await?;
-
-    // Get usernames
-    let customer_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
-        .bind(customer_id)
-        .fetch_one(pool)
-        .await?;
-
-    let vendor_username = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
-        .bind(vendor_id)
-        .fetch_one(pool)
-        .await?;
+    .await
+}
 
-    // Deduct inventory from product stock
-    if let Err(e) = deduct_product_inventory(pool, product_id, quantity).await {
-        eprintln!("Warning: Failed to deduct inventory for product {}: {:?}", product_id, e);
-    }
+/// Users hidden from `user_id`: those they blocked and those who blocked them.
+pub async fn get_blocked_user_ids(pool: &PgPool, user_id: i32) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT blocked_id FROM blocks WHERE blocker_id = $1
+        UNION
+        SELECT blocker_id FROM blocks WHERE blocked_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
 
-    Ok(crate::models::ShippingOrder {
-        id: row.try_get("id")?,
-        customer_id: row.try_get("customer_id")?,
-        product_id: row.try_get("product_id")?,
-        vendor_id: row.try_get("vendor_id")?,
-        quantity: row.try_get("quantity")?,
-        total_amount: row.try_get("total_amount")?,
-        shipping_status: row.try_get("shipping_status")?,
-        tracking_number: row.try_get("tracking_number")?,
-        shipping_address: row.try_get("shipping_address")?,
-        created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-        updated_at: row.try_get::<String, _>("updated_at").unwrap_or_else(|_| "?".to_string()),
-        customer_username,
-        vendor_username,
-        product_name,
-        customer_verified: false,
-        payment_released: false,
-        verification_requested_at: None,
-    })
+/// Users `blocker_id` has blocked, as (id, username, blocked at).
+pub async fn get_blocks(pool: &PgPool, blocker_id: i32) -> Result<Vec<(i32, String, String)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT u.id, u.username, to_char(b.created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"')
+        FROM blocks b
+        JOIN users u ON u.id = b.blocked_id
+        WHERE b.blocker_id = $1
+        ORDER BY b.created_at DESC
+        "#,
+    )
+    .bind(blocker_id)
+    .fetch_all(pool)
+    .await
 }
 
-/**
- * Deduct inventory quantity from a product
- * Updates the product's quantity after a purchase
- */
-pub async fn deduct_product_inventory(
+pub async fn record_audit_event(
     pool: &PgPool,
-    product_id: i32,
-    quantity_to_deduct: i32,
+    actor_id: Option<i32>,
+    impersonated_by: Option<i32>,
+    action: &str,
+    details: Option<&str>,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        "UPDATE products SET quantity = GREATEST(0, quantity - $1) WHERE id = $2"
-    )
-    .bind(quantity_to_deduct)
-    .bind(product_id)
-    .execute(pool)
-    .await?;
-    
+    sqlx::query("INSERT INTO audit_log (actor_id, impersonated_by, action, details) VALUES ($1, $2, $3, $4)")
+        .bind(actor_id)
+        .bind(impersonated_by)
+        .bind(action)
+        .bind(details)
+        .execute(pool)
+        .await?;
     Ok(())
 }
 
-pub async fn get_customer_shipping_orders(pool: &PgPool, customer_id: i32) -> Result<Vec<crate::models::ShippingOrder>, sqlx::Error> {
-    let rows = sqlx::query(
-        r#"
-        SELECT
-            so.id, so.customer_id, so.product_id, so.vendor_id, so.quantity, so.total_amount,
-            so.shipping_status, so.tracking_number, so.shipping_address, so.created_at, so.updated_at,
-            so.customer_verified, so.payment_released, so.verification_requested_at::text,
-            cu.username as customer_username, vu.username as vendor_username, p.name as product_name
-        FROM shipping_orders so
-        JOIN users cu ON so.customer_id = cu.id
-        JOIN users vu ON so.vendor_id = vu.id
-        JOIN products p ON so.product_id = p.id
-        WHERE so.customer_id = $1
-        ORDER BY so.created_at DESC
-        "#,
+/**
+ * Admin correction of a user's wallet balance by a signed `amount`.
+ * The balance change, its ledger entry and the audit entry commit together.
+ * Returns the new balance, or None if the adjustment would make it negative.
+ */
+pub async fn adjust_wallet_balance(
+    pool: &PgPool,
+    user_id: i32,
+    admin_id: i32,
+    amount: f64,
+    reason: &str,
+) -> Result<Option<f64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let balance: f64 = sqlx::query_scalar("SELECT wallet_balance FROM users WHERE id = $1 FOR UPDATE")
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if balance + amount < 0.0 {
+        return Ok(None);
+    }
+
+    let new_balance: f64 = sqlx::query_scalar(
+        "UPDATE users SET wallet_balance = wallet_balance + $1 WHERE id = $2 RETURNING wallet_balance"
     )
-    .bind(customer_id)
-    .fetch_all(pool)
+    .bind(amount)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
     .await?;
 
-    let mut orders = Vec::new();
-    for row in rows {
-        orders.push(crate::models::ShippingOrder {
-            id: row.try_get("id")?,
-            customer_id: row.try_get("customer_id")?,
-            product_id: row.try_get("product_id")?,
-            vendor_id: row.try_get("vendor_id")?,
-            quantity: row.try_get("quantity")?,
-            total_amount: row.try_get("total_amount")?,
-            shipping_status: row.try_get("shipping_status")?,
-            tracking_number: row.try_get("tracking_number")?,
-            shipping_address: row.try_get("shipping_address")?,
-            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-            updated_at: row.try_get::<String, _>("updated_at").unwrap_or_else(|_| "?".to_string()),
-            customer_username: row.try_get("customer_username")?,
-            vendor_username: row.try_get("vendor_username")?,
-            product_name: row.try_get("product_name")?,
-            customer_verified: row.try_get("customer_verified").unwrap_or(false),
-            payment_released: row.try_get("payment_released").unwrap_or(false),
-            verification_requested_at: row.try_get("verification_requested_at").ok(),
-        });
-    }
+    sqlx::query("INSERT INTO wallet_ledger (user_id, entry_type, amount, reference) VALUES ($1, 'admin_adjustment', $2, $3)")
+        .bind(user_id)
+        .bind(amount)
+        .bind(reason)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("INSERT INTO audit_log (actor_id, action, details) VALUES ($1, $2, $3)")
+        .bind(admin_id)
+        .bind(format!("wallet.adjust user {}", user_id))
+        .bind(format!("amount {:+.2}; reason: {}", amount, reason))
+        .execute(&mut *tx)
+        .await?;
 
-    Ok(orders)
+    tx.commit().await?;
+    Ok(Some(new_balance))
 }
 
-pub async fn get_vendor_shipping_orders(pool: &PgPool, vendor_id: i32) -> Result<Vec<crate::models::ShippingOrder>, sqlx::Error> {
+/// Most recent audit entries, optionally for one actor and/or impersonating admin.
+pub async fn get_audit_log(
+    pool: &PgPool,
+    actor_id: Option<i32>,
+    impersonated_by: Option<i32>,
+    limit: i64,
+) -> Result<Vec<crate::models::AuditLogEntry>, sqlx::Error> {
     let rows = sqlx::query(
         r#"
-        SELECT
-            so.id, so.customer_id, so.product_id, so.vendor_id, so.quantity, so.total_amount,
-            so.shipping_status, so.tracking_number, so.shipping_address, so.created_at, so.updated_at,
-            so.customer_verified, so.payment_released, so.verification_requested_at::text,
-            cu.username as customer_username, vu.username as vendor_username, p.name as product_name
-        FROM shipping_orders so
-        JOIN users cu ON so.customer_id = cu.id
-        JOIN users vu ON so.vendor_id = vu.id
-        JOIN products p ON so.product_id = p.id
-        WHERE so.vendor_id = $1
-        ORDER BY so.created_at DESC
+        SELECT id, actor_id, impersonated_by, action, details,
+               to_char(created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS created_at
+        FROM audit_log
+        WHERE ($1::int IS NULL OR actor_id = $1) AND ($2::int IS NULL OR impersonated_by = $2)
+        ORDER BY id DESC
+        LIMIT $3
         "#,
     )
-    .bind(vendor_id)
+    .bind(actor_id)
+    .bind(impersonated_by)
+    .bind(limit)
     .fetch_all(pool)
     .await?;
 
-    let mut orders = Vec::new();
-    for row in rows {
-        orders.push(crate::models::ShippingOrder {
-            id: row.try_get("id")?,
-            customer_id: row.try_get("customer_id")?,
-            product_id: row.try_get("product_id")?,
-            vendor_id: row.try_get("vendor_id")?,
-            quantity: row.try_get("quantity")?,
-            total_amount: row.try_get("total_amount")?,
-            shipping_status: row.try_get("shipping_status")?,
-            tracking_number: row.try_get("tracking_number")?,
-            shipping_address: row.try_get("shipping_address")?,
-            created_at: row.try_get::<String, _>("created_at").unwrap_or_else(|_| "?".to_string()),
-            updated_at: row.try_get::<String, _>("updated_at").unwrap_or_else(|_| "?".to_string()),
-            customer_username: row.try_get("customer_username")?,
-            vendor_username: row.try_get("vendor_username")?,
-            product_name: row.try_get("product_name")?,
-            customer_verified: row.try_get("customer_verified").unwrap_or(false),
-            payment_released: row.try_get("payment_released").unwrap_or(false),
-            verification_requested_at: row.try_get("verification_requested_at").ok(),
-        });
-    }
-
-    Ok(orders)
+    rows.iter()
+        .map(|row| {
+            Ok(crate::models::AuditLogEntry {
+                id: row.try_get("id")?,
+                actor_id: row.try_get("actor_id")?,
+                impersonated_by: row.try_get("impersonated_by")?,
+                action: row.try_get("action")?,
+                details: row.try_get("details")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
 }
 
-pub async fn update_shipping_status(
+pub async fn create_impersonation_session(
     pool: &PgPool,
-    order_id: i32,
-    shipping_status: &str,
-    tracking_number: Option<&str>
+    session_id: &str,
+    admin_id: i32,
+    user_id: i32,
+    expires_at: chrono::DateTime<chrono::Utc>,
 ) -> Result<(), sqlx::Error> {
-    let query = if let Some(tracking) = tracking_number {
-        sqlx::query(
-            "UPDATE shipping_orders SET shipping_status = $1, tracking_number = $2, updated_at = CURRENT_TIMESTAMP WHERE id = $3"
-        )
-        .bind(shipping_status)
-        .bind(tracking)
-        .bind(order_id)
-    } else {
-        sqlx::query(
-            "UPDATE shipping_orders SET shipping_status = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2"
-        )
-        .bind(shipping_status)
-        .bind(order_id)
-    };
-
-    query.execute(pool).await?;
+    sqlx::query("INSERT INTO impersonation_sessions (id, admin_id, user_id, expires_at) VALUES ($1, $2, $3, $4)")
+        .bind(session_id)
+        .bind(admin_id)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
     Ok(())
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct VendorProfile {
-    pub id: i32,
-    pub username: String,
-    pub email: String,
-    pub profile_image: Option<String>,
-    pub verified: bool,
-    pub total_purchases: i64,
-    pub total_revenue: f64,
-    pub follower_count: i64,
-}
-
-pub async fn get_vendor_profile(pool: &PgPool, vendor_id: i32) -> Result<VendorProfile, sqlx::Error> {
-    // Get vendor basic info
-    let vendor_row = sqlx::query(
-        "SELECT id, username, email, profile_image, verified FROM users WHERE id = $1 AND role = 'Vendor'"
+/// Start a session for `user_id` identified by the hash of its refresh token.
+pub async fn create_user_session(
+    pool: &PgPool,
+    user_id: i32,
+    refresh_token_hash: &str,
+    user_agent: Option<&str>,
+    ip_address: Option<&str>,
+    expires_at: chrono::DateTime<chrono::Utc>,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO user_sessions (user_id, refresh_token_hash, user_agent, ip_address, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        "#,
     )
-    .bind(vendor_id)
+    .bind(user_id)
+    .bind(refresh_token_hash)
+    .bind(user_agent)
+    .bind(ip_address)
+    .bind(expires_at)
     .fetch_one(pool)
-    .await?;
+    .await
+}
 
-    // Get total purchases (number of shipping orders)
-    let total_purchases: i64 = sqlx::query_scalar(
-        "SELECT COUNT(*) FROM shipping_orders WHERE vendor_id = $1"
+/// Swap an active session's refresh token for a new one and extend it to
+/// `expires_at`. Returns the session's user, or None if the old token is
+/// unknown, revoked, expired or belongs to a banned or deleted account.
+pub async fn rotate_user_session(
+    pool: &PgPool,
+    old_hash: &str,
+    new_hash: &str,
+    expires_at: chrono::DateTime<chrono::Utc>,
+) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        UPDATE user_sessions s
+        SET refresh_token_hash = $2, last_used_at = NOW(), expires_at = $3
+        FROM users u
+        WHERE s.refresh_token_hash = $1 AND s.revoked_at IS NULL AND s.expires_at > NOW()
+          AND u.id = s.user_id AND u.banned = FALSE AND u.deleted_at IS NULL
+        RETURNING s.user_id
+        "#,
     )
-    .bind(vendor_id)
-    .fetch_one(pool)
-    .await?;
+    .bind(old_hash)
+    .bind(new_hash)
+    .bind(expires_at)
+    .fetch_optional(pool)
+    .await
+}
 
-    // Get total revenue
-    let total_revenue: f64 = sqlx::query_scalar(
-        "SELECT COALESCE(SUM(total_amount), 0.0) FROM shipping_orders WHERE vendor_id = $1"
+/// A user's sessions that are neither revoked nor expired, most recently used first.
+pub async fn get_active_user_sessions(pool: &PgPool, user_id: i32) -> Result<Vec<crate::models::Session>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT s.id, s.user_agent, s.ip_address,
+               to_char(s.created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS created_at,
+               to_char(s.last_used_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS last_used_at,
+               to_char(s.expires_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS expires_at
+        FROM user_sessions s
+        WHERE s.user_id = $1 AND s.revoked_at IS NULL AND s.expires_at > NOW()
+        ORDER BY s.last_used_at DESC, s.id DESC
+        "#,
     )
-    .bind(vendor_id)
-    .fetch_one(pool)
+    .bind(user_id)
+    .fetch_all(pool)
     .await?;
 
-    // Get follower count
-    let follower_count: i64 = sqlx::query_scalar(
-        "SELECT COUNT(*) FROM follows WHERE vendor_id = $1"
+    rows.iter()
+        .map(|row| {
+            Ok(crate::models::Session {
+                id: row.try_get("id")?,
+                user_agent: row.try_get("user_agent")?,
+                ip_address: row.try_get("ip_address")?,
+                created_at: row.try_get::<Option<String>, _>("created_at")?.unwrap_or_default(),
+                last_used_at: row.try_get::<Option<String>, _>("last_used_at")?.unwrap_or_default(),
+                expires_at: row.try_get::<Option<String>, _>("expires_at")?.unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Revoke one of a user's active sessions; false if there's no such session.
+pub async fn revoke_user_session(pool: &PgPool, user_id: i32, session_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE user_sessions SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL"
     )
-    .bind(vendor_id)
-    .fetch_one(pool)
+    .bind(session_id)
+    .bind(user_id)
+    .execute(pool)
     .await?;
-
-    Ok(VendorProfile {
-        id: vendor_row.try_get("id")?,
-        username: vendor_row.try_get("username")?,
-        email: vendor_row.try_get("email")?,
-        profile_image: vendor_row.try_get("profile_image")?,
-        verified: vendor_row.try_get("verified")?,
-        total_purchases,
-        total_revenue,
-        follower_count,
-    })
+    Ok(result.rows_affected() > 0)
 }
 
-// Payment Transaction Functions
+/// Revoke every active session of a user. Returns how many were revoked.
+pub async fn revoke_all_user_sessions(pool: &PgPool, user_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("UPDATE user_sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
 
-pub async fn create_payment_transaction(
-    pool: &PgPool,
-    user_id: i32,
-    checkout_request_id: &str,
-    merchant_request_id: &str,
-    phone_number: &str,
-    amount: f64,
-    cart_item_ids: Option<&str>,
-) -> Result<i32, sqlx::Error> {
-    let row: (i32,) = sqlx::query_as(
-        "INSERT INTO payment_transactions (user_id, checkout_request_id, merchant_request_id, phone_number, amount, cart_item_ids)
-         VALUES ($1, $2, $3, $4, $5, $6) RETURNING id"
+/// Whether an impersonation session exists for this admin/user pair and is neither expired nor revoked.
+pub async fn is_impersonation_session_active(pool: &PgPool, session_id: &str, admin_id: i32, user_id: i32) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM impersonation_sessions
+            WHERE id = $1 AND admin_id = $2 AND user_id = $3
+              AND revoked_at IS NULL AND expires_at > NOW()
+        )
+        "#,
     )
+    .bind(session_id)
+    .bind(admin_id)
     .bind(user_id)
-    .bind(checkout_request_id)
-    .bind(merchant_request_id)
-    .bind(phone_number)
-    .bind(amount)
-    .bind(cart_item_ids)
     .fetch_one(pool)
-    .await?;
+    .await
+}
 
-    Ok(row.0)
+/// Revoke an active impersonation session. Returns (admin_id, user_id), or RowNotFound.
+pub async fn revoke_impersonation_session(pool: &PgPool, session_id: &str) -> Result<(i32, i32), sqlx::Error> {
+    sqlx::query_as(
+        "UPDATE impersonation_sessions SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL RETURNING admin_id, user_id"
+    )
+    .bind(session_id)
+    .fetch_one(pool)
+    .await
 }
 
-pub async fn update_payment_transaction(
+pub async fn get_product_vendor_id(pool: &PgPool, product_id: i32) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar("SELECT vendor_id FROM products WHERE id = $1")
+        .bind(product_id)
+        .fetch_one(pool)
+        .await
+}
+
+/// Record a product view unless the same viewer (user, or IP when anonymous)
+/// viewed it within the last `debounce_minutes`. Returns whether it was recorded.
+pub async fn record_product_view(
     pool: &PgPool,
-    checkout_request_id: &str,
-    status: &str,
-    mpesa_receipt_number: Option<&str>,
-    transaction_date: Option<&str>,
-) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        "UPDATE payment_transactions SET status = $1, mpesa_receipt_number = $2,
-         transaction_date = $3, updated_at = CURRENT_TIMESTAMP
-         WHERE checkout_request_id = $4"
+    product_id: i32,
+    viewer_id: Option<i32>,
+    viewer_ip: Option<&str>,
+    debounce_minutes: i32,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO product_views (product_id, viewer_id, viewer_ip)
+        SELECT $1, $2, $3
+        WHERE NOT EXISTS (
+            SELECT 1 FROM product_views
+            WHERE product_id = $1
+              AND viewed_at > NOW() - make_interval(mins => $4)
+              AND (
+                  ($2::int IS NOT NULL AND viewer_id = $2)
+                  OR ($2::int IS NULL AND viewer_id IS NULL AND viewer_ip IS NOT DISTINCT FROM $3)
+              )
+        )
+        "#,
     )
-    .bind(status)
-    .bind(mpesa_receipt_number)
-    .bind(transaction_date)
-    .bind(checkout_request_id)
+    .bind(product_id)
+    .bind(viewer_id)
+    .bind(viewer_ip)
+    .bind(debounce_minutes)
     .execute(pool)
     .await?;
-
-    Ok(())
+    Ok(result.rows_affected() == 1)
 }
 
-pub async fn get_payment_transaction_by_checkout_request_id(
-    pool: &PgPool,
-    checkout_request_id: &str,
-) -> Result<crate::models::PaymentTransaction, sqlx::Error> {
-    let row = sqlx::query(
-        "SELECT id, user_id, checkout_request_id, merchant_request_id, mpesa_receipt_number,
-         phone_number, amount, status, transaction_date, cart_item_ids,
-         created_at::text, updated_at::text
-         FROM payment_transactions WHERE checkout_request_id = $1"
+/// Move `product_id` to the front of the user's recently viewed list, keeping at most `keep` entries.
+pub async fn record_recently_viewed(pool: &PgPool, user_id: i32, product_id: i32, keep: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO recently_viewed (user_id, product_id) VALUES ($1, $2)
+        ON CONFLICT (user_id, product_id) DO UPDATE SET viewed_at = CURRENT_TIMESTAMP
+        "#,
     )
-    .bind(checkout_request_id)
-    .fetch_one(pool)
+    .bind(user_id)
+    .bind(product_id)
+    .execute(pool)
     .await?;
 
-    Ok(crate::models::PaymentTransaction {
-        id: row.try_get("id")?,
-        user_id: row.try_get("user_id")?,
-        checkout_request_id: row.try_get("checkout_request_id")?,
-        merchant_request_id: row.try_get("merchant_request_id")?,
-        mpesa_receipt_number: row.try_get("mpesa_receipt_number")?,
-        phone_number: row.try_get("phone_number")?,
-        amount: row.try_get("amount")?,
-        status: row.try_get("status")?,
-        transaction_date: row.try_get("transaction_date")?,
-        cart_item_ids: row.try_get("cart_item_ids")?,
-        created_at: row.try_get("created_at")?,
-        updated_at: row.try_get("updated_at")?,
-    })
-}
-
-pub async fn get_user_payment_transactions(
-    pool: &PgPool,
-    user_id: i32,
-) -> Result<Vec<crate::models::PaymentTransaction>, sqlx::Error> {
-    let rows = sqlx::query(
-        "SELECT id, user_id, checkout_request_id, merchant_request_id, mpesa_receipt_number,
-         phone_number, amount, status, transaction_date, cart_item_ids,
-         created_at::text, updated_at::text
-         FROM payment_transactions WHERE user_id = $1 ORDER BY created_at DESC"
+    sqlx::query(
+        r#"
+        DELETE FROM recently_viewed WHERE user_id = $1 AND product_id NOT IN (
+            SELECT product_id FROM recently_viewed WHERE user_id = $1 ORDER BY viewed_at DESC LIMIT $2
+        )
+        "#,
     )
     .bind(user_id)
-    .fetch_all(pool)
+    .bind(keep)
+    .execute(pool)
     .await?;
+    Ok(())
+}
 
-    let mut transactions = Vec::new();
-    for row in rows {
-        transactions.push(crate::models::PaymentTransaction {
-            id: row.try_get("id")?,
-            user_id: row.try_get("user_id")?,
-            checkout_request_id: row.try_get("checkout_request_id")?,
-            merchant_request_id: row.try_get("merchant_request_id")?,
-            mpesa_receipt_number: row.try_get("mpesa_receipt_number")?,
-            phone_number: row.try_get("phone_number")?,
-            amount: row.try_get("amount")?,
-            status: row.try_get("status")?,
-            transaction_date: row.try_get("transaction_date")?,
-            cart_item_ids: row.try_get("cart_item_ids")?,
-            created_at: row.try_get("created_at")?,
-            updated_at: row.try_get("updated_at")?,
+/// The user's most recently viewed products, newest first, skipping unavailable vendors.
+pub async fn get_recently_viewed(pool: &PgPool, user_id: i32, limit: i64) -> Result<Vec<Product>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
+        FROM recently_viewed rv
+        JOIN products p ON rv.product_id = p.id
+        JOIN users u ON p.vendor_id = u.id
+        WHERE rv.user_id = $1 AND u.banned = FALSE AND u.deleted_at IS NULL
+        ORDER BY rv.viewed_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    let mut products = Vec::new();
+    for row in rows {
+        products.push(Product {
+            id: row.try_get::<i32, _>(0)? as u32,
+            name: row.try_get(1)?,
+            price: row.try_get::<f64, _>(2)?,
+            category: row.try_get(3)?,
+            description: row.try_get::<Option<String>, _>(4)?,
+            image: row.try_get::<Option<String>, _>(5)?,
+            quantity: row.try_get(6)?,
+            vendor_id: row.try_get::<i32, _>(7)? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
         });
     }
 
-    Ok(transactions)
+    attach_tags(pool, &mut products).await?;
+    attach_gallery(pool, &mut products).await?;
+    attach_attributes(pool, &mut products).await?;
+    Ok(products)
 }
 
-// Get vendor sales report
-pub async fn get_vendor_sales_report(
-    pool: &PgPool,
-    vendor_id: i32,
-) -> Result<crate::models::VendorSalesReport, sqlx::Error> {
-    // Get total sales and order count
-    let summary = sqlx::query(
+/// Views per day across a vendor's products for the last `days` days, oldest first, including empty days.
+pub async fn get_vendor_daily_views(pool: &PgPool, vendor_id: i32, days: i32) -> Result<Vec<crate::models::DailyViews>, sqlx::Error> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
         r#"
-        SELECT 
-            COALESCE(SUM(total_amount), 0) as total_sales,
-            COUNT(*) as total_orders
-        FROM shipping_orders
-        WHERE vendor_id = $1 AND shipping_status != 'cancelled'
+        SELECT to_char(d.day, 'YYYY-MM-DD'), COUNT(pv.id)
+        FROM generate_series(CURRENT_DATE - ($2 - 1), CURRENT_DATE, INTERVAL '1 day') AS d(day)
+        LEFT JOIN product_views pv
+            ON pv.viewed_at >= d.day AND pv.viewed_at < d.day + INTERVAL '1 day'
+           AND pv.product_id IN (SELECT id FROM products WHERE vendor_id = $1)
+        GROUP BY d.day
+        ORDER BY d.day
         "#,
     )
     .bind(vendor_id)
-    .fetch_one(pool)
+    .bind(days)
+    .fetch_all(pool)
     .await?;
+    Ok(rows.into_iter().map(|(date, views)| crate::models::DailyViews { date, views }).collect())
+}
 
-    let total_sales: f64 = summary.try_get("total_sales")?;
-    let total_orders: i64 = summary.try_get("total_orders")?;
-
-    // Get sales by product
-    let product_rows = sqlx::query(
+/// View counts for each of a vendor's products.
+pub async fn get_vendor_product_analytics(pool: &PgPool, vendor_id: i32) -> Result<Vec<crate::models::ProductAnalytics>, sqlx::Error> {
+    let rows = sqlx::query(
         r#"
-        SELECT 
-            p.id as product_id,
-            p.name as product_name,
-            SUM(so.quantity) as quantity_sold,
-            SUM(so.total_amount) as total_revenue
-        FROM shipping_orders so
-        JOIN products p ON so.product_id = p.id
-        WHERE so.vendor_id = $1 AND so.shipping_status != 'cancelled'
+        SELECT
+            p.id, p.name,
+            COUNT(pv.id) AS total_views,
+            COUNT(pv.id) FILTER (WHERE pv.viewed_at > NOW() - INTERVAL '7 days') AS views_last_7_days,
+            COUNT(pv.id) FILTER (WHERE pv.viewed_at > NOW() - INTERVAL '30 days') AS views_last_30_days,
+            COALESCE((
+                SELECT SUM(so.quantity) FROM shipping_orders so
+                WHERE so.product_id = p.id AND so.shipping_status NOT IN ('cancelled', 'declined')
+            ), 0) AS quantity_sold
+        FROM products p
+        LEFT JOIN product_views pv ON pv.product_id = p.id
+        WHERE p.vendor_id = $1 AND p.removed_at IS NULL
         GROUP BY p.id, p.name
-        ORDER BY total_revenue DESC
+        ORDER BY total_views DESC, p.id
         "#,
     )
     .bind(vendor_id)
     .fetch_all(pool)
     .await?;
 
-    let mut sales_by_product = Vec::new();
-    for row in product_rows {
-        sales_by_product.push(crate::models::ProductSales {
-            product_id: row.try_get("product_id")?,
-            product_name: row.try_get("product_name")?,
-            quantity_sold: row.try_get::<i64, _>("quantity_sold")? as i32,
-            total_revenue: row.try_get("total_revenue")?,
-        });
-    }
-
-    Ok(crate::models::VendorSalesReport {
-        total_sales,
-        total_orders: total_orders as i32,
-        total_profit: total_sales, // For now, profit = sales
-        sales_by_product,
-    })
+    rows.iter()
+        .map(|row| {
+            Ok(crate::models::ProductAnalytics {
+                product_id: row.try_get("id")?,
+                product_name: row.try_get("name")?,
+                total_views: row.try_get("total_views")?,
+                views_last_7_days: row.try_get("views_last_7_days")?,
+                views_last_30_days: row.try_get("views_last_30_days")?,
+                quantity_sold: row.try_get("quantity_sold")?,
+            })
+        })
+        .collect()
 }
 
-// Get customer purchase report
-pub async fn get_customer_purchase_report(
+/// Stock, units sold in the last `window_days` and a stale flag (no sales in
+/// `stale_days`) for each of a vendor's products, best sellers first.
+pub async fn get_vendor_inventory_report(
     pool: &PgPool,
-    customer_id: i32,
-) -> Result<crate::models::CustomerPurchaseReport, sqlx::Error> {
-    // Get total spent and order count
-    let summary = sqlx::query(
+    vendor_id: i32,
+    window_days: i32,
+    stale_days: i32,
+) -> Result<crate::models::InventoryReport, sqlx::Error> {
+    let rows = sqlx::query(
         r#"
-        SELECT 
-            COALESCE(SUM(total_amount), 0) as total_spent,
-            COUNT(*) as total_orders
-        FROM shipping_orders
-        WHERE customer_id = $1
+        SELECT
+            p.id, p.name, p.quantity,
+            COALESCE(SUM(so.quantity) FILTER (WHERE so.created_at > NOW() - make_interval(days => $2)), 0)::int8 AS units_sold,
+            MAX(so.created_at)::text AS last_sold_at,
+            COALESCE(MAX(so.created_at) <= NOW() - make_interval(days => $3), TRUE) AS stale
+        FROM products p
+        LEFT JOIN shipping_orders so ON so.product_id = p.id AND so.shipping_status NOT IN ('cancelled', 'declined')
+        WHERE p.vendor_id = $1 AND p.removed_at IS NULL
+        GROUP BY p.id, p.name, p.quantity
+        ORDER BY units_sold DESC, p.id
         "#,
     )
-    .bind(customer_id)
-    .fetch_one(pool)
+    .bind(vendor_id)
+    .bind(window_days)
+    .bind(stale_days)
+    .fetch_all(pool)
     .await?;
 
-    let total_spent: f64 = summary.try_get("total_spent")?;
-    let total_orders: i64 = summary.try_get("total_orders")?;
+    let products = rows
+        .iter()
+        .map(|row| {
+            let stock: i32 = row.try_get("quantity")?;
+            let units_sold: i64 = row.try_get("units_sold")?;
+            let daily_sales = units_sold as f64 / window_days as f64;
+            Ok(crate::models::InventoryReportItem {
+                product_id: row.try_get("id")?,
+                product_name: row.try_get("name")?,
+                stock,
+                units_sold,
+                days_of_stock_remaining: (units_sold > 0).then(|| (stock as f64 / daily_sales * 10.0).round() / 10.0),
+                last_sold_at: row.try_get("last_sold_at")?,
+                stale: row.try_get("stale")?,
+            })
+        })
+        .collect::<Result<Vec<_>, sqlx::Error>>()?;
+
+    Ok(crate::models::InventoryReport { window_days, stale_after_days: stale_days, products })
+}
 
-    // Get purchases by category
-    let category_rows = sqlx::query(
+/// Escape LIKE wildcards so user input only matches literally.
+fn escape_like(input: &str) -> String {
+    input.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// In-stock products related to `product_id` from active, verified vendors.
+/// With `by_tags` false they share its category; otherwise they share at least one tag.
+/// Ranked by shared tags, a vendor in `location` (or the source vendor's location when
+/// None), average rating and units sold.
+pub async fn get_similar_products(
+    pool: &PgPool,
+    product_id: i32,
+    location: Option<&str>,
+    by_tags: bool,
+    limit: i64,
+) -> Result<Vec<Product>, sqlx::Error> {
+    let rows = sqlx::query(
         r#"
-        SELECT 
-            p.category,
-            SUM(so.total_amount) as total_spent,
-            SUM(so.quantity) as quantity
-        FROM shipping_orders so
-        JOIN products p ON so.product_id = p.id
-        WHERE so.customer_id = $1
-        GROUP BY p.category
-        ORDER BY total_spent DESC
+        WITH source AS (
+            SELECT p.id, p.category, sv.location_string
+            FROM products p JOIN users sv ON sv.id = p.vendor_id
+            WHERE p.id = $1
+        )
+        SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id
+        FROM products p
+        JOIN users u ON p.vendor_id = u.id
+        CROSS JOIN source s
+        WHERE p.id <> s.id AND p.quantity > 0 AND p.removed_at IS NULL AND p.flagged_at IS NULL
+          AND u.verified = TRUE AND u.banned = FALSE AND u.deleted_at IS NULL AND u.is_paused = FALSE
+          AND CASE WHEN $3
+              THEN EXISTS (
+                  SELECT 1 FROM product_tags t JOIN product_tags st ON st.tag = t.tag
+                  WHERE t.product_id = p.id AND st.product_id = s.id
+              )
+              ELSE LOWER(p.category) = LOWER(s.category)
+          END
+        ORDER BY
+            (SELECT COUNT(*) FROM product_tags t JOIN product_tags st ON st.tag = t.tag
+             WHERE t.product_id = p.id AND st.product_id = s.id) DESC,
+            (LOWER(u.location_string) = LOWER(COALESCE($2, s.location_string))) IS TRUE DESC,
+            (SELECT AVG(r.rating) FROM reviews r WHERE r.product_id = p.id AND r.removed_at IS NULL) DESC NULLS LAST,
+            (SELECT COALESCE(SUM(so.quantity), 0) FROM shipping_orders so
+             WHERE so.product_id = p.id AND so.shipping_status NOT IN ('cancelled', 'declined')) DESC,
+            p.id
+        LIMIT $4
         "#,
     )
-    .bind(customer_id)
+    .bind(product_id)
+    .bind(location)
+    .bind(by_tags)
+    .bind(limit)
     .fetch_all(pool)
     .await?;
 
-    let mut purchases_by_category = Vec::new();
-    for row in category_rows {
-        purchases_by_category.push(crate::models::CategoryPurchase {
-            category: row.try_get("category")?,
-            total_spent: row.try_get("total_spent")?,
-            quantity: row.try_get::<i64, _>("quantity")? as i32,
+    let mut products = Vec::new();
+    for row in rows {
+        products.push(Product {
+            id: row.try_get::<i32, _>(0)? as u32,
+            name: row.try_get(1)?,
+            price: row.try_get::<f64, _>(2)?,
+            category: row.try_get(3)?,
+            description: row.try_get::<Option<String>, _>(4)?,
+            image: row.try_get::<Option<String>, _>(5)?,
+            quantity: row.try_get(6)?,
+            vendor_id: row.try_get::<i32, _>(7)? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
         });
     }
 
-    // Get purchases by vendor
-    let vendor_rows = sqlx::query(
+    drop_suspended_vendors(pool, &mut products).await?;
+    attach_tags(pool, &mut products).await?;
+    attach_gallery(pool, &mut products).await?;
+    attach_attributes(pool, &mut products).await?;
+    Ok(products)
+}
+
+/// Products ranked by activity over the last `days` days: views, orders and
+/// wishlist adds, weighted by the given factors. Only in-stock products of
+/// active vendors with some recent activity are returned.
+pub async fn get_trending_products(
+    pool: &PgPool,
+    days: i32,
+    weights: (f64, f64, f64),
+    limit: i64,
+) -> Result<Vec<crate::models::TrendingProduct>, sqlx::Error> {
+    let (view_weight, order_weight, wishlist_weight) = weights;
+    let rows = sqlx::query(
         r#"
-        SELECT 
-            u.id as vendor_id,
-            u.username as vendor_name,
-            SUM(so.total_amount) as total_spent,
-            COUNT(*) as order_count
-        FROM shipping_orders so
-        JOIN users u ON so.vendor_id = u.id
-        WHERE so.customer_id = $1
-        GROUP BY u.id, u.username
-        ORDER BY total_spent DESC
+        WITH views AS (
+            SELECT product_id, COUNT(*) AS n FROM product_views
+            WHERE viewed_at > NOW() - make_interval(days => $1)
+            GROUP BY product_id
+        ), orders AS (
+            SELECT product_id, COUNT(*) AS n FROM shipping_orders
+            WHERE created_at > NOW() - make_interval(days => $1) AND shipping_status NOT IN ('cancelled', 'declined')
+            GROUP BY product_id
+        ), wishlist_adds AS (
+            SELECT product_id, COUNT(*) AS n FROM wishlist_items
+            WHERE created_at > NOW() - make_interval(days => $1)
+            GROUP BY product_id
+        ), scored AS (
+            SELECT p.id,
+                   COALESCE(v.n, 0) AS views,
+                   COALESCE(o.n, 0) AS orders,
+                   COALESCE(w.n, 0) AS wishlist_adds,
+                   COALESCE(v.n, 0) * $2 + COALESCE(o.n, 0) * $3 + COALESCE(w.n, 0) * $4 AS score
+            FROM products p
+            LEFT JOIN views v ON v.product_id = p.id
+            LEFT JOIN orders o ON o.product_id = p.id
+            LEFT JOIN wishlist_adds w ON w.product_id = p.id
+            WHERE p.removed_at IS NULL AND p.flagged_at IS NULL
+        )
+        SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id,
+               s.views, s.orders, s.wishlist_adds, s.score::FLOAT8,
+               (SELECT COUNT(*) FROM wishlist_items wi WHERE wi.product_id = p.id) AS favorites
+        FROM scored s
+        JOIN products p ON p.id = s.id
+        JOIN users u ON p.vendor_id = u.id
+        WHERE s.score > 0 AND p.quantity > 0
+          AND u.banned = FALSE AND u.deleted_at IS NULL AND u.is_paused = FALSE
+        ORDER BY s.score DESC, s.orders DESC, p.id
+        LIMIT $5
         "#,
     )
-    .bind(customer_id)
+    .bind(days)
+    .bind(view_weight)
+    .bind(order_weight)
+    .bind(wishlist_weight)
+    .bind(limit)
     .fetch_all(pool)
     .await?;
 
-    let mut purchases_by_vendor = Vec::new();
-    for row in vendor_rows {
-        purchases_by_vendor.push(crate::models::VendorPurchase {
-            vendor_id: row.try_get("vendor_id")?,
-            vendor_name: row.try_get("vendor_name")?,
-            total_spent: row.try_get("total_spent")?,
-            order_count: row.try_get::<i64, _>("order_count")? as i32,
+    let mut products = Vec::new();
+    let mut ranked = Vec::new();
+    for row in rows {
+        products.push(Product {
+            id: row.try_get::<i32, _>(0)? as u32,
+            name: row.try_get(1)?,
+            price: row.try_get::<f64, _>(2)?,
+            category: row.try_get(3)?,
+            description: row.try_get::<Option<String>, _>(4)?,
+            image: row.try_get::<Option<String>, _>(5)?,
+            quantity: row.try_get(6)?,
+            vendor_id: row.try_get::<i32, _>(7)? as u32,
+            tags: Vec::new(),
+            gallery: Vec::new(),
+            attributes: ProductAttributes::default(),
         });
+        ranked.push((
+            row.try_get::<i64, _>(8)?,
+            row.try_get::<i64, _>(9)?,
+            row.try_get::<i64, _>(10)?,
+            row.try_get::<f64, _>(11)?,
+            row.try_get::<i64, _>(12)?,
+        ));
     }
 
-    Ok(crate::models::CustomerPurchaseReport {
-        total_spent,
-        total_orders: total_orders as i32,
-        purchases_by_category,
-        purchases_by_vendor,
-         })
+    let suspended = suspended_vendors_of(pool, &products).await?;
+
+    attach_tags(pool, &mut products).await?;
+    attach_gallery(pool, &mut products).await?;
+    attach_attributes(pool, &mut products).await?;
+    Ok(products
+        .into_iter()
+        .zip(ranked)
+        .filter(|(product, _)| !suspended.contains(&(product.vendor_id as i32)))
+        .map(|(product, (recent_views, recent_orders, recent_wishlist_adds, score, favorites))| {
+            crate::models::TrendingProduct {
+                product,
+                recent_views,
+                recent_orders,
+                recent_wishlist_adds,
+                favorites,
+                score,
+            }
+        })
+        .collect())
 }
 
-/**
- * Mark order as delivered and request customer verification
- */
-pub async fn request_delivery_verification(
-    pool: &PgPool,
-    order_id: i32,
-) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        "UPDATE shipping_orders SET verification_requested_at = CURRENT_TIMESTAMP 
-         WHERE id = $1 AND shipping_status = 'delivered'"
+/// One product with its vendor and rating summary, or None if it doesn't exist
+/// or its vendor is unverified, banned, deleted or suspended.
+pub async fn get_product_detail(pool: &PgPool, product_id: i32) -> Result<Option<crate::models::ProductDetail>, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT p.id, p.name, p.price, p.category, p.description, p.image, p.quantity, p.vendor_id,
+               u.username, u.location_string,
+               AVG(r.rating)::FLOAT8 AS average_rating, COUNT(r.id) AS review_count
+        FROM products p
+        JOIN users u ON p.vendor_id = u.id
+        LEFT JOIN reviews r ON r.product_id = p.id AND r.removed_at IS NULL
+        WHERE p.id = $1 AND p.removed_at IS NULL AND p.flagged_at IS NULL AND u.verified = TRUE AND u.banned = FALSE AND u.deleted_at IS NULL
+        GROUP BY p.id, u.username, u.location_string
+        "#,
     )
-    .bind(order_id)
-    .execute(pool)
+    .bind(product_id)
+    .fetch_optional(pool)
     .await?;
-    
-    Ok(())
+
+    let Some(row) = row else { return Ok(None) };
+    let vendor_id: i32 = row.try_get("vendor_id")?;
+    if is_vendor_suspended(pool, vendor_id).await? {
+        return Ok(None);
+    }
+
+    let mut products = vec![Product {
+        id: row.try_get::<i32, _>("id")? as u32,
+        name: row.try_get("name")?,
+        price: row.try_get("price")?,
+        category: row.try_get("category")?,
+        description: row.try_get("description")?,
+        image: row.try_get("image")?,
+        quantity: row.try_get("quantity")?,
+        vendor_id: vendor_id as u32,
+        tags: Vec::new(),
+        gallery: Vec::new(),
+        attributes: ProductAttributes::default(),
+    }];
+    attach_tags(pool, &mut products).await?;
+    attach_gallery(pool, &mut products).await?;
+    attach_attributes(pool, &mut products).await?;
+    let product = products.remove(0);
+
+    Ok(Some(crate::models::ProductDetail {
+        in_stock: product.quantity > 0,
+        vendor: crate::models::ProductVendorSummary {
+            id: vendor_id,
+            username: row.try_get("username")?,
+            location: row.try_get("location_string")?,
+        },
+        average_rating: row.try_get("average_rating")?,
+        review_count: row.try_get("review_count")?,
+        product,
+    }))
 }
 
-/**
- * Customer verifies delivery and releases payment to vendor
- */
-pub async fn verify_delivery_and_release_payment(
+/// Comparison attributes for the given products, skipping ids that don't exist
+/// or belong to banned or deleted vendors. `distance_km` is left for the caller.
+pub async fn get_products_for_comparison(
     pool: &PgPool,
-    order_id: i32,
-    customer_id: i32,
-) -> Result<(), sqlx::Error> {
-    // Get order details
-    let order: (i32, f64, i32, bool) = sqlx::query_as(
-        "SELECT vendor_id, total_amount, customer_id, payment_released 
-         FROM shipping_orders WHERE id = $1"
+    product_ids: &[i32],
+) -> Result<Vec<crate::models::ProductComparison>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT p.id, p.name, p.price, p.category, p.quantity, p.vendor_id, u.username,
+               AVG(r.rating)::FLOAT8 AS average_rating, COUNT(r.id) AS review_count
+        FROM products p
+        JOIN users u ON p.vendor_id = u.id
+        LEFT JOIN reviews r ON r.product_id = p.id AND r.removed_at IS NULL
+        WHERE p.id = ANY($1) AND p.removed_at IS NULL AND p.flagged_at IS NULL AND u.banned = FALSE AND u.deleted_at IS NULL
+        GROUP BY p.id, u.username
+        "#,
     )
-    .bind(order_id)
-    .fetch_one(pool)
+    .bind(product_ids)
+    .fetch_all(pool)
     .await?;
 
-    let vendor_id = order.0;
-    let amount = order.1;
-    let order_customer_id = order.2;
-    let already_released = order.3;
-
-    // Verify customer owns this order
-    if order_customer_id != customer_id {
-        return Err(sqlx::Error::RowNotFound);
-    }
-
-    // Prevent double payment
-    if already_released {
-        return Ok(());
-    }
+    rows.iter()
+        .map(|row| {
+            Ok(crate::models::ProductComparison {
+                product_id: row.try_get("id")?,
+                name: row.try_get("name")?,
+                price: row.try_get("price")?,
+                category: row.try_get("category")?,
+                stock: row.try_get("quantity")?,
+                vendor_id: row.try_get("vendor_id")?,
+                vendor_name: row.try_get("username")?,
+                average_rating: row.try_get("average_rating")?,
+                review_count: row.try_get("review_count")?,
+                distance_km: None,
+            })
+        })
+        .collect()
+}
 
-    // Start transaction
-    let mut tx = pool.begin().await?;
+/// Search suggestions for a prefix: (product names, categories, tags), each at most `limit`
+/// long and ordered by popularity (units ordered for names, product counts otherwise).
+pub async fn get_search_suggestions(
+    pool: &PgPool,
+    prefix: &str,
+    limit: i64,
+) -> Result<(Vec<String>, Vec<String>, Vec<String>), sqlx::Error> {
+    let pattern = format!("{}%", escape_like(&prefix.to_lowercase()));
 
-    // Mark order as verified
-    sqlx::query(
-        "UPDATE shipping_orders SET customer_verified = TRUE, payment_released = TRUE 
-         WHERE id = $1"
+    let names: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT p.name
+        FROM products p
+        JOIN users u ON p.vendor_id = u.id
+        LEFT JOIN shipping_orders so ON so.product_id = p.id AND so.shipping_status NOT IN ('cancelled', 'declined')
+        WHERE lower(p.name) LIKE $1 AND p.removed_at IS NULL AND p.flagged_at IS NULL AND u.banned = FALSE AND u.deleted_at IS NULL AND u.is_paused = FALSE
+        GROUP BY p.name
+        ORDER BY COALESCE(SUM(so.quantity), 0) DESC, p.name
+        LIMIT $2
+        "#,
     )
-    .bind(order_id)
-    .execute(&mut *tx)
+    .bind(&pattern)
+    .bind(limit)
+    .fetch_all(pool)
     .await?;
 
-    // Add amount to vendor's wallet
-    sqlx::query(
-        "UPDATE users SET wallet_balance = wallet_balance + $1 WHERE id = $2"
+    let categories: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT category FROM products
+        WHERE lower(category) LIKE $1 AND removed_at IS NULL
+        GROUP BY category
+        ORDER BY COUNT(*) DESC, category
+        LIMIT $2
+        "#,
     )
-    .bind(amount)
-    .bind(vendor_id)
-    .execute(&mut *tx)
+    .bind(&pattern)
+    .bind(limit)
+    .fetch_all(pool)
     .await?;
 
-    tx.commit().await?;
-
-    Ok(())
-}
-
-/**
- * Get user's wallet balance
- */
-pub async fn get_wallet_balance(
-    pool: &PgPool,
-    user_id: i32,
-) -> Result<f64, sqlx::Error> {
-    let balance: (f64,) = sqlx::query_as(
-        "SELECT wallet_balance FROM users WHERE id = $1"
+    let tags: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT tag FROM product_tags
+        WHERE tag LIKE $1
+        GROUP BY tag
+        ORDER BY COUNT(*) DESC, tag
+        LIMIT $2
+        "#,
     )
-    .bind(user_id)
-    .fetch_one(pool)
+    .bind(&pattern)
+    .bind(limit)
+    .fetch_all(pool)
     .await?;
 
-    Ok(balance.0)
+    Ok((names, categories, tags))
+}
+
+/// Result of posting a vendor announcement
+pub enum AnnouncementOutcome {
+    /// Stored and delivered as a notification to `notified` followers
+    Posted { announcement: crate::models::Announcement, notified: u64 },
+    /// The vendor posted too recently
+    RateLimited { retry_after_seconds: i64 },
 }
 
 /**
- * Withdraw from wallet to M-Pesa
+ * Store an announcement and notify each of the vendor's followers, unless the
+ * vendor already posted within `min_interval_minutes`. Followers who blocked
+ * the vendor (or were blocked by them) are skipped.
  */
-pub async fn process_wallet_withdrawal(
+pub async fn create_announcement(
     pool: &PgPool,
-    user_id: i32,
-    amount: f64,
-) -> Result<f64, sqlx::Error> {
-    // Get current balance
-    let current_balance = get_wallet_balance(pool, user_id).await?;
+    vendor_id: i32,
+    title: &str,
+    body: &str,
+    min_interval_minutes: i32,
+) -> Result<AnnouncementOutcome, sqlx::Error> {
+    let mut tx = pool.begin().await?;
 
-    if current_balance < amount {
-        return Err(sqlx::Error::RowNotFound);
-    }
+    // Serialize posts by the same vendor so two rapid requests can't both pass the check
+    sqlx::query("SELECT id FROM users WHERE id = $1 FOR UPDATE")
+        .bind(vendor_id)
+        .execute(&mut *tx)
+        .await?;
 
-    // Deduct from wallet
-    sqlx::query(
-        "UPDATE users SET wallet_balance = wallet_balance - $1 WHERE id = $2"
+    let retry_after: Option<f64> = sqlx::query_scalar(
+        r#"
+        SELECT EXTRACT(EPOCH FROM MAX(created_at) + make_interval(mins => $2) - NOW())::FLOAT8
+        FROM announcements WHERE vendor_id = $1
+        "#,
     )
-    .bind(amount)
-    .bind(user_id)
-    .execute(pool)
+    .bind(vendor_id)
+    .bind(min_interval_minutes)
+    .fetch_one(&mut *tx)
     .await?;
+    if let Some(seconds) = retry_after.filter(|s| *s > 0.0) {
+        return Ok(AnnouncementOutcome::RateLimited { retry_after_seconds: seconds.ceil() as i64 });
+    }
 
-    // Return new balance
-    Ok(current_balance - amount)
-}
-
-/// Get user by ID with full profile information
-pub async fn get_user_by_id(pool: &PgPool, user_id: i32) -> Result<crate::models::User, sqlx::Error> {
     let row = sqlx::query(
         r#"
-        SELECT id, username, email, role, profile_image, verified, banned, secondary_email, mpesa_number, payment_preference, location_string, wallet_balance
-        FROM users
-        WHERE id = $1
+        INSERT INTO announcements (vendor_id, title, body) VALUES ($1, $2, $3)
+        RETURNING id, to_char(created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS created_at,
+                  (SELECT username FROM users WHERE id = $1) AS vendor_username
         "#,
     )
-    .bind(user_id)
-    .fetch_one(pool)
+    .bind(vendor_id)
+    .bind(title)
+    .bind(body)
+    .fetch_one(&mut *tx)
     .await?;
-
-    let user = crate::models::User {
-        id: row.try_get(0)?,
-        username: row.try_get(1)?,
-        email: row.try_get(2)?,
-        role: match row.try_get::<String, _>(3)?.as_str() {
-            "Admin" => crate::models::Role::Admin,
-            "Customer" => crate::models::Role::Customer,
-            "Vendor" => crate::models::Role::Vendor,
-            _ => crate::models::Role::Customer,
-        },
-        profile_image: row.try_get(4)?,
-        verified: row.try_get(5)?,
-        banned: row.try_get(6)?,
-        verification_document: None,
-        verification_rejected_reason: None,
-        secondary_email: row.try_get(7)?,
-        mpesa_number: row.try_get(8)?,
-        payment_preference: row.try_get(9)?,
-        location_string: row.try_get(10)?,
-        wallet_balance: row.try_get(11).unwrap_or(0.0),
+    let announcement = crate::models::Announcement {
+        id: row.try_get("id")?,
+        vendor_id,
+        vendor_username: row.try_get("vendor_username")?,
+        title: title.to_string(),
+        body: body.to_string(),
+        created_at: row.try_get("created_at")?,
     };
 
-    Ok(user)
+    let notified = sqlx::query(
+        r#"
+        INSERT INTO notifications (user_id, kind, title, body, reference)
+        SELECT f.follower_id, 'announcement', $2, $3, $4
+        FROM follows f
+        JOIN users u ON u.id = f.follower_id
+        WHERE f.vendor_id = $1 AND u.deleted_at IS NULL
+          AND NOT EXISTS (
+              SELECT 1 FROM blocks b
+              WHERE (b.blocker_id = f.follower_id AND b.blocked_id = $1)
+                 OR (b.blocker_id = $1 AND b.blocked_id = f.follower_id)
+          )
+        "#,
+    )
+    .bind(vendor_id)
+    .bind(format!("{}: {}", announcement.vendor_username, title))
+    .bind(body)
+    .bind(format!("announcement:{}", announcement.id))
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    tx.commit().await?;
+    Ok(AnnouncementOutcome::Posted { announcement, notified })
 }
 
-// Password Reset Functions
+/// (user id, email, username) of a vendor's followers who accept notification emails.
+pub async fn get_follower_email_recipients(pool: &PgPool, vendor_id: i32) -> Result<Vec<(i32, String, String)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT u.id, u.email, u.username
+        FROM follows f
+        JOIN users u ON u.id = f.follower_id
+        WHERE f.vendor_id = $1 AND u.deleted_at IS NULL AND u.email_notifications = TRUE
+          AND NOT EXISTS (
+              SELECT 1 FROM blocks b
+              WHERE (b.blocker_id = f.follower_id AND b.blocked_id = $1)
+                 OR (b.blocker_id = $1 AND b.blocked_id = f.follower_id)
+          )
+        "#,
+    )
+    .bind(vendor_id)
+    .fetch_all(pool)
+    .await
+}
 
-/// Store a password reset verification code
-pub async fn store_password_reset_code(
-    pool: &PgPool,
-    username: &str,
-    verification_code: &str,
-    expires_at: chrono::DateTime<chrono::Utc>,
-) -> Result<crate::models::PasswordResetCode, sqlx::Error> {
-    let row = sqlx::query(
+/// Announcements from the vendors a user follows, newest first.
+pub async fn get_announcement_feed(pool: &PgPool, user_id: i32, limit: i64) -> Result<Vec<crate::models::Announcement>, sqlx::Error> {
+    let rows = sqlx::query(
         r#"
-        INSERT INTO password_reset_codes (username, verification_code, expires_at)
-        VALUES ($1, $2, $3)
-        RETURNING id, username, verification_code, expires_at, used, created_at
+        SELECT a.id, a.vendor_id, u.username, a.title, a.body,
+               to_char(a.created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS created_at
+        FROM announcements a
+        JOIN follows f ON f.vendor_id = a.vendor_id AND f.follower_id = $1
+        JOIN users u ON u.id = a.vendor_id
+        WHERE u.deleted_at IS NULL
+        ORDER BY a.created_at DESC, a.id DESC
+        LIMIT $2
         "#,
     )
-    .bind(username)
-    .bind(verification_code)
-    .bind(expires_at)
-    .fetch_one(pool)
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
     .await?;
 
-    Ok(crate::models::PasswordResetCode {
-        id: row.try_get(0)?,
-        username: row.try_get(1)?,
-        verification_code: row.try_get(2)?,
-        expires_at: row.try_get::<chrono::DateTime<chrono::Utc>, _>(3)?.to_rfc3339(),
-        used: row.try_get(4)?,
-        created_at: row.try_get::<chrono::DateTime<chrono::Utc>, _>(5)?.to_rfc3339(),
-    })
+    rows.iter()
+        .map(|row| {
+            Ok(crate::models::Announcement {
+                id: row.try_get("id")?,
+                vendor_id: row.try_get("vendor_id")?,
+                vendor_username: row.try_get("username")?,
+                title: row.try_get("title")?,
+                body: row.try_get("body")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
 }
 
-/// Verify password reset code and mark it as used
-pub async fn verify_password_reset_code(
-    pool: &PgPool,
-    username: &str,
-    verification_code: &str,
-) -> Result<bool, sqlx::Error> {
-    let now = chrono::Utc::now();
-    
-    // Check if code exists, is valid, and not expired
-    let row = sqlx::query(
+/// A user's notifications, newest first.
+pub async fn get_notifications(pool: &PgPool, user_id: i32, unread_only: bool, limit: i64) -> Result<Vec<crate::models::Notification>, sqlx::Error> {
+    let rows = sqlx::query(
         r#"
-        SELECT id FROM password_reset_codes
-        WHERE username = $1 
-        AND verification_code = $2 
-        AND expires_at > $3 
-        AND used = FALSE
+        SELECT id, kind, title, body, reference, read,
+               to_char(created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS created_at
+        FROM notifications
+        WHERE user_id = $1 AND (NOT $2 OR read = FALSE)
+        ORDER BY created_at DESC, id DESC
+        LIMIT $3
         "#,
     )
-    .bind(username)
-    .bind(verification_code)
-    .bind(now)
-    .fetch_optional(pool)
+    .bind(user_id)
+    .bind(unread_only)
+    .bind(limit)
+    .fetch_all(pool)
     .await?;
 
-    if let Some(row) = row {
-        let code_id: i32 = row.try_get(0)?;
-        
-        // Mark the code as used
-        sqlx::query(
-            "UPDATE password_reset_codes SET used = TRUE WHERE id = $1"
-        )
-        .bind(code_id)
+    rows.iter()
+        .map(|row| {
+            Ok(crate::models::Notification {
+                id: row.try_get("id")?,
+                kind: row.try_get("kind")?,
+                title: row.try_get("title")?,
+                body: row.try_get("body")?,
+                reference: row.try_get("reference")?,
+                read: row.try_get("read")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+}
+
+/// Mark all of a user's notifications read. Returns how many were unread.
+pub async fn mark_notifications_read(pool: &PgPool, user_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("UPDATE notifications SET read = TRUE WHERE user_id = $1 AND read = FALSE")
+        .bind(user_id)
         .execute(pool)
         .await?;
-        
-        Ok(true)
-    } else {
-        Ok(false)
-    }
+    Ok(result.rows_affected())
 }
 
-/// Reset user password using username
-pub async fn reset_user_password_by_username(
-    pool: &PgPool,
-    username: &str,
-    new_password: &str,
-) -> Result<crate::models::User, sqlx::Error> {
-    let password_hash = bcrypt::hash(new_password, bcrypt::DEFAULT_COST)
-        .map_err(|_| sqlx::Error::RowNotFound)?;
+/// Cached place name for a geocoding grid cell.
+pub async fn get_cached_geocode(pool: &PgPool, lat_cell: i32, lon_cell: i32) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT location_string FROM geocode_cache WHERE lat_cell = $1 AND lon_cell = $2")
+        .bind(lat_cell)
+        .bind(lon_cell)
+        .fetch_optional(pool)
+        .await
+}
 
-    let row = sqlx::query(
+pub async fn cache_geocode(pool: &PgPool, lat_cell: i32, lon_cell: i32, location_string: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
         r#"
-        UPDATE users 
-        SET password_hash = $1
-        WHERE username = $2
-        RETURNING id, username, email, role, profile_image, verified, banned, secondary_email, mpesa_number, payment_preference, location_string, wallet_balance
+        INSERT INTO geocode_cache (lat_cell, lon_cell, location_string)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (lat_cell, lon_cell) DO UPDATE SET location_string = EXCLUDED.location_string, created_at = NOW()
         "#,
     )
-    .bind(password_hash)
-    .bind(username)
-    .fetch_one(pool)
+    .bind(lat_cell)
+    .bind(lon_cell)
+    .bind(location_string)
+    .execute(pool)
     .await?;
-
-    Ok(crate::models::User {
-        id: row.try_get(0)?,
-        username: row.try_get(1)?,
-        email: row.try_get(2)?,
-        role: match row.try_get::<String, _>(3)?.as_str() {
-            "Admin" => crate::models::Role::Admin,
-            "Customer" => crate::models::Role::Customer,
-            "Vendor" => crate::models::Role::Vendor,
-            _ => crate::models::Role::Customer,
-        },
-        profile_image: row.try_get(4)?,
-        verified: row.try_get(5)?,
-        banned: row.try_get(6)?,
-        verification_document: None,
-        verification_rejected_reason: None,
-        secondary_email: row.try_get(7)?,
-        mpesa_number: row.try_get(8)?,
-        payment_preference: row.try_get(9)?,
-        location_string: row.try_get(10)?,
-        wallet_balance: row.try_get(11).unwrap_or(0.0),
-    })
+    Ok(())
 }
 
-/// Clean up expired password reset codes
-pub async fn cleanup_expired_reset_codes(pool: &PgPool) -> Result<u64, sqlx::Error> {
-    let now = chrono::Utc::now();
-    let result = sqlx::query(
-        "DELETE FROM password_reset_codes WHERE expires_at < $1"
+/**
+ * Record a data export for the user unless they already exported within
+ * `min_interval_minutes`. Returns the seconds left to wait when refused.
+ */
+pub async fn record_data_export(pool: &PgPool, user_id: i32, min_interval_minutes: i32) -> Result<Option<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    // Serialize exports by the same user so two rapid requests can't both pass the check
+    sqlx::query("SELECT id FROM users WHERE id = $1 FOR UPDATE")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let retry_after: Option<f64> = sqlx::query_scalar(
+        r#"
+        SELECT EXTRACT(EPOCH FROM MAX(created_at) + make_interval(mins => $2) - NOW())::FLOAT8
+        FROM data_exports WHERE user_id = $1
+        "#,
     )
-    .bind(now)
-    .execute(pool)
+    .bind(user_id)
+    .bind(min_interval_minutes)
+    .fetch_one(&mut *tx)
     .await?;
+    if let Some(seconds) = retry_after.filter(|s| *s > 0.0) {
+        return Ok(Some(seconds.ceil() as i64));
+    }
 
-    Ok(result.rows_affected())
+    sqlx::query("INSERT INTO data_exports (user_id) VALUES ($1)")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(None)
+}
+
+/// Per-category VAT overrides as (category, rate), by category
+pub async fn get_vat_rates(pool: &PgPool) -> Result<Vec<(String, f64)>, sqlx::Error> {
+    sqlx::query_as("SELECT category, rate FROM vat_rates ORDER BY category")
+        .fetch_all(pool)
+        .await
 }
 
-/// Clear verification rejection reason when user uploads new verification document
-pub async fn clear_verification_rejection_reason(pool: &PgPool, user_id: i32) -> Result<(), sqlx::Error> {
+pub async fn set_vat_rate(pool: &PgPool, category: &str, rate: f64) -> Result<(), sqlx::Error> {
     sqlx::query(
-        "UPDATE users SET verification_rejected_reason = NULL WHERE id = $1",
+        r#"
+        INSERT INTO vat_rates (category, rate) VALUES ($1, $2)
+        ON CONFLICT (category) DO UPDATE SET rate = EXCLUDED.rate, updated_at = CURRENT_TIMESTAMP
+        "#,
     )
-    .bind(user_id)
+    .bind(category)
+    .bind(rate)
     .execute(pool)
     .await?;
-
     Ok(())
 }
 
-/// Find user by username (for password reset)
-pub async fn find_user_by_username(pool: &PgPool, username: &str) -> Result<Option<crate::models::User>, sqlx::Error> {
-    let row = sqlx::query(
+/// Remove a category's override so it falls back to the default rate. False if it had none.
+pub async fn delete_vat_rate(pool: &PgPool, category: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM vat_rates WHERE category = $1")
+        .bind(category)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Verified, active vendors with sales or reviews in the last `days` days,
+/// ranked by `sort`. Cancelled and declined orders don't count.
+pub async fn get_vendor_leaderboard(
+    pool: &PgPool,
+    days: i32,
+    sort: crate::models::LeaderboardSort,
+) -> Result<Vec<crate::models::VendorLeaderboardEntry>, sqlx::Error> {
+    use crate::models::LeaderboardSort;
+
+    let order_by = match sort {
+        LeaderboardSort::Revenue => "revenue DESC, orders DESC",
+        LeaderboardSort::Orders => "orders DESC, revenue DESC",
+        LeaderboardSort::Rating => "average_rating DESC NULLS LAST, review_count DESC",
+    };
+    let rows = sqlx::query(&format!(
         r#"
-        SELECT id, username, email, role, profile_image, verified, banned, secondary_email, mpesa_number, payment_preference, location_string, wallet_balance
-        FROM users
-        WHERE username = $1
+        WITH sales AS (
+            SELECT vendor_id, SUM(total_amount) AS revenue, COUNT(DISTINCT COALESCE(order_id, -id)) AS orders
+            FROM shipping_orders
+            WHERE created_at > NOW() - make_interval(days => $1) AND shipping_status NOT IN ('cancelled', 'declined')
+            GROUP BY vendor_id
+        ), ratings AS (
+            SELECT vendor_id, AVG(rating)::FLOAT8 AS average_rating, COUNT(*) AS review_count
+            FROM reviews
+            WHERE created_at > NOW() - make_interval(days => $1) AND removed_at IS NULL
+            GROUP BY vendor_id
+        )
+        SELECT u.id, u.username,
+               COALESCE(s.revenue, 0)::FLOAT8 AS revenue, COALESCE(s.orders, 0) AS orders,
+               r.average_rating, COALESCE(r.review_count, 0) AS review_count
+        FROM users u
+        LEFT JOIN sales s ON s.vendor_id = u.id
+        LEFT JOIN ratings r ON r.vendor_id = u.id
+        WHERE u.role = 'Vendor' AND u.verified = TRUE AND u.banned = FALSE AND u.deleted_at IS NULL
+          AND (s.vendor_id IS NOT NULL OR r.vendor_id IS NOT NULL)
+        ORDER BY {}, u.id
         "#,
-    )
-    .bind(username)
-    .fetch_optional(pool)
+        order_by
+    ))
+    .bind(days)
+    .fetch_all(pool)
     .await?;
 
-    if let Some(row) = row {
-        Ok(Some(crate::models::User {
-            id: row.try_get(0)?,
-            username: row.try_get(1)?,
-            email: row.try_get(2)?,
-            role: match row.try_get::<String, _>(3)?.as_str() {
-                "Admin" => crate::models::Role::Admin,
-                "Customer" => crate::models::Role::Customer,
-                "Vendor" => crate::models::Role::Vendor,
-                _ => crate::models::Role::Customer,
-            },
-            profile_image: row.try_get(4)?,
-            verified: row.try_get(5)?,
-            banned: row.try_get(6)?,
-            verification_document: None,
-            verification_rejected_reason: None,
-            secondary_email: row.try_get(7)?,
-            mpesa_number: row.try_get(8)?,
-            payment_preference: row.try_get(9)?,
-            location_string: row.try_get(10)?,
-            wallet_balance: row.try_get(11).unwrap_or(0.0),
-        }))
-    } else {
-        Ok(None)
+    let vendor_ids: Vec<i32> = rows.iter().map(|row| row.try_get("id")).collect::<Result<_, _>>()?;
+    let suspended = suspended_vendor_ids(pool, &vendor_ids).await?;
+
+    let mut entries = Vec::new();
+    for row in rows {
+        let vendor_id: i32 = row.try_get("id")?;
+        if suspended.contains(&vendor_id) {
+            continue;
+        }
+        entries.push(crate::models::VendorLeaderboardEntry {
+            rank: entries.len() as i64 + 1,
+            vendor_id,
+            username: row.try_get("username")?,
+            revenue: crate::models::round_cents(row.try_get("revenue")?),
+            orders: row.try_get("orders")?,
+            average_rating: row
+                .try_get::<Option<f64>, _>("average_rating")?
+                .map(|rating| (rating * 10.0).round() / 10.0),
+            review_count: row.try_get("review_count")?,
+        });
     }
-}
\ No newline at end of file
+    Ok(entries)
+}
@@ -0,0 +1,65 @@
+//! Notification digests. Users on `instant` get each notification email as it
+//! happens; `hourly` and `daily` users have a one-line summary queued instead,
+//! and a background task batches everything queued into a single email once
+//! their oldest item is an hour or a day old.
+
+use crate::{db, email};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Accepted values of a user's `notification_frequency`.
+pub const NOTIFICATION_FREQUENCIES: [&str; 3] = ["instant", "hourly", "daily"];
+
+/// How often the background task looks for due digests.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Queue `summary` for the user's digest unless they want emails instantly.
+/// Returns true when queued, meaning the caller shouldn't email them now;
+/// if queuing fails the caller falls back to emailing straight away.
+pub async fn queued_for_digest(pool: &PgPool, user_id: i32, summary: &str) -> bool {
+    match db::queue_digest_item(pool, user_id, summary).await {
+        Ok(queued) => queued,
+        Err(e) => {
+            eprintln!("Failed to queue digest item for user {}: {:?}", user_id, e);
+            false
+        }
+    }
+}
+
+/// Email every due digest. Returns the number of digests sent.
+/// Delivery failures are logged; the items stay marked sent, as with cart reminders.
+pub async fn send_due_digests(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let claimed = db::claim_due_digest_items(pool).await?;
+
+    // Items come grouped by user, oldest first
+    let mut digests: Vec<(i32, String, String, Vec<String>)> = Vec::new();
+    for (user_id, username, user_email, summary) in claimed {
+        match digests.last_mut() {
+            Some(digest) if digest.0 == user_id => digest.3.push(summary),
+            _ => digests.push((user_id, username, user_email, vec![summary])),
+        }
+    }
+
+    for (user_id, username, user_email, items) in &digests {
+        if let Err(e) = email::send_notification_digest_email(pool, user_email, username, items).await {
+            eprintln!("Failed to send notification digest to user {}: {}", user_id, e);
+        }
+    }
+
+    Ok(digests.len())
+}
+
+/// Run `send_due_digests` periodically for the life of the process.
+pub fn spawn_digest_task(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match send_due_digests(&pool).await {
+                Ok(0) => {}
+                Ok(count) => println!("📬 Sent {} notification digest(s)", count),
+                Err(e) => eprintln!("Notification digest run failed: {:?}", e),
+            }
+        }
+    });
+}
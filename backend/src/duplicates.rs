@@ -0,0 +1,60 @@
+//! Duplicate product detection. Names are compared the way pg_trgm does:
+//! lowercased words, padded, split into three-character pieces, and scored
+//! by the share of pieces the two names have in common. Case, punctuation
+//! and spacing make no difference, and a small typo or plural barely does.
+
+use crate::db;
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+/// Names at least this similar (0 to 1) count as the same product.
+pub const DUPLICATE_SIMILARITY: f64 = 0.7;
+
+/// A listed product whose name is close to a new one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PossibleDuplicate {
+    pub product_id: i32,
+    pub name: String,
+    pub similarity: f64,
+}
+
+/// Lowercase words of `name`, ignoring punctuation and extra spaces.
+pub fn normalize(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn trigrams(name: &str) -> HashSet<String> {
+    let mut trigrams = HashSet::new();
+    for word in normalize(name).split(' ').filter(|word| !word.is_empty()) {
+        let padded: Vec<char> = format!("  {} ", word).chars().collect();
+        for window in padded.windows(3) {
+            trigrams.insert(window.iter().collect());
+        }
+    }
+    trigrams
+}
+
+/// Trigram similarity of two names, from 0 (nothing shared) to 1 (same words).
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let shared = a.intersection(&b).count();
+    shared as f64 / (a.len() + b.len() - shared) as f64
+}
+
+/// The vendor's listed product most similar to `name`, if any is similar
+/// enough to be a likely duplicate.
+pub async fn find_duplicate(pool: &PgPool, vendor_id: i32, name: &str) -> Result<Option<PossibleDuplicate>, sqlx::Error> {
+    let products = db::get_vendor_product_names(pool, vendor_id).await?;
+    Ok(products
+        .into_iter()
+        .map(|(product_id, existing)| PossibleDuplicate { product_id, similarity: similarity(name, &existing), name: existing })
+        .filter(|candidate| candidate.similarity >= DUPLICATE_SIMILARITY)
+        .max_by(|a, b| a.similarity.total_cmp(&b.similarity)))
+}
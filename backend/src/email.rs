@@ -1,12 +1,20 @@
 //! Email service module for sending verification and notification emails.
-//! Uses SMTP with lettre for email delivery.
+//! Uses SMTP with lettre for email delivery. One pooled `Mailer` is built from
+//! the environment for the whole process; sends that fail transiently go to
+//! the retry queue in `email_queue`.
 
 use lettre::{
-    message::{header::ContentType, Mailbox},
-    transport::smtp::authentication::Credentials,
+    message::{Mailbox, MultiPart},
+    transport::smtp::{authentication::Credentials, PoolConfig},
     Message, SmtpTransport, Transport,
 };
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
 use std::env;
+use std::sync::OnceLock;
+
+use crate::email_queue;
+use crate::email_templates::{self, RenderedEmail};
 
 /// Error type for email operations
 #[derive(Debug)]
@@ -14,6 +22,20 @@ pub enum EmailError {
     InvalidConfig(String),
     MessageBuild(lettre::error::Error),
     SmtpError(lettre::transport::smtp::Error),
+    /// A failure worth retrying later, e.g. the mail server is briefly unreachable
+    Transient(String),
+}
+
+impl EmailError {
+    /// Whether sending again later might succeed. SMTP errors count unless the
+    /// server rejected the message permanently (5xx).
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            EmailError::SmtpError(err) => !err.is_permanent(),
+            EmailError::Transient(_) => true,
+            EmailError::InvalidConfig(_) | EmailError::MessageBuild(_) => false,
+        }
+    }
 }
 
 impl std::fmt::Display for EmailError {
@@ -22,6 +44,7 @@ impl std::fmt::Display for EmailError {
             EmailError::InvalidConfig(msg) => write!(f, "Email configuration error: {}", msg),
             EmailError::MessageBuild(err) => write!(f, "Email message build error: {}", err),
             EmailError::SmtpError(err) => write!(f, "SMTP error: {}", err),
+            EmailError::Transient(msg) => write!(f, "Temporary email failure: {}", msg),
         }
     }
 }
@@ -72,7 +95,10 @@ impl EmailConfig {
     }
 }
 
-/// Create an SMTP transport with the given configuration
+/// Most SMTP connections kept open for reuse between sends.
+const MAX_POOLED_CONNECTIONS: u32 = 4;
+
+/// Create a pooled SMTP transport with the given configuration
 fn create_mailer(config: &EmailConfig) -> Result<SmtpTransport, EmailError> {
     let creds = Credentials::new(config.smtp_username.clone(), config.smtp_password.clone());
 
@@ -80,45 +106,176 @@ fn create_mailer(config: &EmailConfig) -> Result<SmtpTransport, EmailError> {
         .map_err(EmailError::SmtpError)?
         .port(config.smtp_port)
         .credentials(creds)
+        .pool_config(PoolConfig::new().max_size(MAX_POOLED_CONNECTIONS))
         .build();
 
     Ok(mailer)
 }
 
+/// Build a multipart/alternative message: the plain-text body for clients
+/// that don't render HTML, and the HTML body for those that do
+pub fn build_message(from: Mailbox, to: Mailbox, email: &RenderedEmail) -> Result<Message, EmailError> {
+    Message::builder()
+        .from(from)
+        .to(to)
+        .subject(email.subject.clone())
+        .multipart(MultiPart::alternative_plain_html(email.body.clone(), email.html.clone()))
+        .map_err(EmailError::MessageBuild)
+}
+
+/// Delivers built messages: SMTP in the server, something else in tests.
+pub trait MailTransport: Send + Sync {
+    fn deliver(&self, message: &Message) -> Result<(), EmailError>;
+}
+
+impl MailTransport for SmtpTransport {
+    fn deliver(&self, message: &Message) -> Result<(), EmailError> {
+        self.send(message).map(|_| ()).map_err(EmailError::SmtpError)
+    }
+}
+
+/// Sends emails from the configured sender address over one transport
+pub struct Mailer {
+    transport: Box<dyn MailTransport>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(transport: impl MailTransport + 'static, from: Mailbox) -> Self {
+        Mailer { transport: Box::new(transport), from }
+    }
+
+    /// Pooled SMTP mailer from the `SMTP_*`, `FROM_EMAIL` and `FROM_NAME` variables
+    pub fn from_env() -> Result<Self, EmailError> {
+        let config = EmailConfig::from_env()?;
+        let transport = create_mailer(&config)?;
+
+        let from: Mailbox = format!("{} <{}>", config.from_name, config.from_email)
+            .parse()
+            .map_err(|_| EmailError::InvalidConfig("Invalid from email format".to_string()))?;
+
+        Ok(Mailer::new(transport, from))
+    }
+
+    /// Send a rendered email to one recipient
+    pub fn send(&self, user_email: &str, email: &RenderedEmail) -> Result<(), EmailError> {
+        let to_mailbox: Mailbox = user_email
+            .parse()
+            .map_err(|_| EmailError::InvalidConfig("Invalid recipient email format".to_string()))?;
+
+        let message = build_message(self.from.clone(), to_mailbox, email)?;
+        self.transport.deliver(&message)
+    }
+}
+
+static MAILER: OnceLock<Result<Mailer, String>> = OnceLock::new();
+
+/// The process-wide mailer, built from the environment on first use so its
+/// SMTP connections are shared by every send. `main` builds it at startup;
+/// when email isn't configured every send fails with `InvalidConfig`.
+pub fn mailer() -> Result<&'static Mailer, EmailError> {
+    MAILER
+        .get_or_init(|| Mailer::from_env().map_err(|e| e.to_string()))
+        .as_ref()
+        .map_err(|e| EmailError::InvalidConfig(e.clone()))
+}
+
+/// Send a rendered email to one recipient, queueing it for retry if the send fails transiently.
+/// Emails carrying a one-time code pass the code's `expires_at` so they aren't retried past it.
+async fn send_rendered(
+    pool: &PgPool,
+    user_email: &str,
+    email: &RenderedEmail,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(), EmailError> {
+    email_queue::deliver(pool, mailer()?, user_email, email, expires_at).await
+}
+
+/// Send a plain-text email, with an HTML version laid out from the same text
+async fn send_email(pool: &PgPool, user_email: &str, subject: &str, body: &str) -> Result<(), EmailError> {
+    send_rendered(pool, user_email, &email_templates::from_text(subject, body), None).await
+}
+
+/// Render an email template in the recipient's language
+fn render_template(template: &str, language: &str, vars: &[(&str, &str)]) -> Result<RenderedEmail, EmailError> {
+    email_templates::render_template(template, language, vars)
+        .ok_or_else(|| EmailError::InvalidConfig(format!("Email template {} not found", template)))
+}
+
+/// Render an email template in the recipient's language and send it
+async fn send_templated_email(
+    pool: &PgPool,
+    user_email: &str,
+    template: &str,
+    language: &str,
+    vars: &[(&str, &str)],
+) -> Result<(), EmailError> {
+    send_rendered(pool, user_email, &render_template(template, language, vars)?, None).await
+}
+
 /// Send a verification approval email to a user
 pub async fn send_verification_approval_email(
+    pool: &PgPool,
     user_email: &str,
     username: &str,
+    language: &str,
 ) -> Result<(), EmailError> {
-    let config = EmailConfig::from_env()?;
-    let mailer = create_mailer(&config)?;
+    send_templated_email(pool, user_email, email_templates::VERIFICATION_APPROVED, language, &[("username", username)]).await?;
 
-    let from_mailbox: Mailbox = format!("{} <{}>", config.from_name, config.from_email)
-        .parse()
-        .map_err(|_| EmailError::InvalidConfig("Invalid from email format".to_string()))?;
+    println!("✅ Verification approval email sent to {}", user_email);
+    Ok(())
+}
+
+/// Send a verification rejection email to a user
+pub async fn send_verification_rejection_email(
+    pool: &PgPool,
+    user_email: &str,
+    username: &str,
+    language: &str,
+) -> Result<(), EmailError> {
+    send_templated_email(pool, user_email, email_templates::VERIFICATION_REJECTED, language, &[("username", username)]).await?;
+
+    println!("📧 Verification rejection email sent to {}", user_email);
+    Ok(())
+}
+
+/// Send the code for resetting a forgotten password
+pub async fn send_password_reset_email(
+    pool: &PgPool,
+    user_email: &str,
+    username: &str,
+    code: &str,
+    expires_at: DateTime<Utc>,
+    language: &str,
+) -> Result<(), EmailError> {
+    let rendered = render_template(email_templates::PASSWORD_RESET, language, &[("username", username), ("code", code)])?;
+    send_rendered(pool, user_email, &rendered, Some(expires_at)).await?;
 
-    let to_mailbox: Mailbox = user_email
-        .parse()
-        .map_err(|_| EmailError::InvalidConfig("Invalid recipient email format".to_string()))?;
+    println!("📧 Password reset code sent to {}", user_email);
+    Ok(())
+}
 
-    let subject = "Account Verification Approved - Farmers Market Place";
+/// Send a one-time code confirming ownership of an M-Pesa number
+pub async fn send_phone_verification_email(
+    pool: &PgPool,
+    user_email: &str,
+    username: &str,
+    phone_number: &str,
+    code: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), EmailError> {
+    let subject = "Confirm your M-Pesa number - Farmers Market Place";
     let body = format!(
         r#"
 Dear {},
 
-Congratulations! Your account has been successfully verified on Farmers Market Place.
-
-You now have full access to all features of our platform, including:
-- Selling your farm products
-- Purchasing from other verified vendors
-- Using our secure payment system
-- Accessing premium vendor tools
+Use the code below to confirm that you own the M-Pesa number {}:
 
-You can now log in to your account and start using all the features available to verified users.
+    {}
 
-If you have any questions or need assistance, please don't hesitate to contact our support team.
+The code expires in 10 minutes. Withdrawals are only sent to a confirmed number.
 
-Welcome to the Farmers Market Place community!
+If you did not request this, please secure your account and contact our support team.
 
 Best regards,
 The Farmers Market Place Team
@@ -126,63 +283,110 @@ The Farmers Market Place Team
 ---
 This is an automated message. Please do not reply to this email.
 "#,
-        username
+        username, phone_number, code
     );
 
-    let email = Message::builder()
-        .from(from_mailbox)
-        .to(to_mailbox)
-        .subject(subject)
-        .header(ContentType::TEXT_PLAIN)
-        .body(body)
-        .map_err(EmailError::MessageBuild)?;
+    send_rendered(pool, user_email, &email_templates::from_text(subject, &body), Some(expires_at)).await?;
 
-    // Send the email
-    mailer.send(&email).map_err(EmailError::SmtpError)?;
-
-    println!("✅ Verification approval email sent to {}", user_email);
+    println!("📧 Phone verification code sent to {}", user_email);
     Ok(())
 }
 
-/// Send a verification rejection email to a user
-pub async fn send_verification_rejection_email(
+/// Send the one-time code that unlocks the admin SQL console
+pub async fn send_admin_query_code_email(
+    pool: &PgPool,
     user_email: &str,
     username: &str,
+    code: &str,
+    expires_at: DateTime<Utc>,
 ) -> Result<(), EmailError> {
-    let config = EmailConfig::from_env()?;
-    let mailer = create_mailer(&config)?;
+    let subject = "Your SQL console code - Farmers Market Place";
+    let body = format!(
+        r#"
+Dear {},
 
-    let from_mailbox: Mailbox = format!("{} <{}>", config.from_name, config.from_email)
-        .parse()
-        .map_err(|_| EmailError::InvalidConfig("Invalid from email format".to_string()))?;
+Use the code below to run read-only queries from the admin SQL console:
 
-    let to_mailbox: Mailbox = user_email
-        .parse()
-        .map_err(|_| EmailError::InvalidConfig("Invalid recipient email format".to_string()))?;
+    {}
+
+The code expires in 10 minutes. Every query you run is recorded in the audit log.
+
+If you did not request this, someone may have your admin password. Change it straight away.
+
+Best regards,
+The Farmers Market Place Team
+
+---
+This is an automated message. Please do not reply to this email.
+"#,
+        username, code
+    );
 
-    let subject = "Account Verification Status - Farmers Market Place";
+    send_rendered(pool, user_email, &email_templates::from_text(subject, &body), Some(expires_at)).await?;
+
+    println!("📧 Admin query code sent to {}", user_email);
+    Ok(())
+}
+
+/// Remind a customer about items left in their cart
+pub async fn send_abandoned_cart_email(
+    pool: &PgPool,
+    user_email: &str,
+    username: &str,
+    items_summary: &str,
+) -> Result<(), EmailError> {
+    let subject = "You left items in your cart - Farmers Market Place";
     let body = format!(
         r#"
 Dear {},
 
-Thank you for your interest in becoming a verified vendor on Farmers Market Place.
+You still have these items waiting in your cart:
+
+{}
+
+Fresh produce sells quickly, so log in and check out before it's gone.
+
+You can turn off these reminders from your profile settings.
 
-Unfortunately, we were unable to approve your verification request at this time. This could be due to:
-- Incomplete or unclear documentation
-- Information that doesn't meet our verification criteria
-- Technical issues with the submitted materials
+Best regards,
+The Farmers Market Place Team
+
+---
+This is an automated message. Please do not reply to this email.
+"#,
+        username, items_summary
+    );
 
-What you can do next:
-1. Review your submitted information and documentation
-2. Ensure all required fields are completed accurately
-3. Upload clear, high-quality images of required documents
-4. Resubmit your verification request with updated information
+    send_email(pool, user_email, subject, &body).await?;
 
-Our verification process helps maintain the quality and trustworthiness of our marketplace. We encourage you to review our vendor guidelines and try again.
+    println!("📧 Abandoned cart reminder sent to {}", user_email);
+    Ok(())
+}
 
-If you have questions about the verification process or need assistance with your application, please contact our support team.
+/// Tell a vendor how their suspension/ban appeal was decided
+pub async fn send_appeal_outcome_email(
+    pool: &PgPool,
+    user_email: &str,
+    username: &str,
+    approved: bool,
+    admin_notes: Option<&str>,
+) -> Result<(), EmailError> {
+    let subject = "Your appeal has been reviewed - Farmers Market Place";
+    let outcome = if approved {
+        "Good news: your appeal has been approved. Your account restrictions have been lifted and you can sell on the marketplace again."
+    } else {
+        "After careful review, your appeal has been rejected and the restrictions on your account remain in place."
+    };
+    let notes = admin_notes
+        .map(|n| format!("\nNotes from our team:\n{}\n", n))
+        .unwrap_or_default();
+    let body = format!(
+        r#"
+Dear {},
 
-Thank you for your understanding.
+{}
+{}
+If you have questions about this decision, please contact our support team.
 
 Best regards,
 The Farmers Market Place Team
@@ -190,22 +394,142 @@ The Farmers Market Place Team
 ---
 This is an automated message. Please do not reply to this email.
 "#,
-        username
+        username, outcome, notes
     );
 
-    let email = Message::builder()
-        .from(from_mailbox)
-        .to(to_mailbox)
-        .subject(subject)
-        .header(ContentType::TEXT_PLAIN)
-        .body(body)
-        .map_err(EmailError::MessageBuild)?;
+    send_email(pool, user_email, subject, &body).await?;
 
-    // Send the email
-    mailer.send(&email).map_err(EmailError::SmtpError)?;
+    println!("📧 Appeal outcome email sent to {}", user_email);
+    Ok(())
+}
 
-    println!("📧 Verification rejection email sent to {}", user_email);
+/// Tell a vendor that a customer cancelled an order before it shipped
+pub async fn send_order_cancelled_email(
+    pool: &PgPool,
+    user_email: &str,
+    username: &str,
+    order_id: i32,
+    product_name: &str,
+    quantity: i32,
+    language: &str,
+) -> Result<(), EmailError> {
+    let order_id = order_id.to_string();
+    let quantity = quantity.to_string();
+    send_templated_email(
+        pool,
+        user_email,
+        email_templates::ORDER_CANCELLED,
+        language,
+        &[("username", username), ("order_id", &order_id), ("quantity", &quantity), ("product_name", product_name)],
+    )
+    .await?;
+
+    println!("📧 Order cancellation email sent to {}", user_email);
+    Ok(())
+}
+
+/// Tell a customer their order was declined (by the vendor, or because it
+/// wasn't accepted in time) and what was refunded to their wallet
+#[allow(clippy::too_many_arguments)]
+pub async fn send_order_declined_email(
+    pool: &PgPool,
+    user_email: &str,
+    username: &str,
+    order_id: i32,
+    product_name: &str,
+    quantity: i32,
+    refunded: f64,
+    language: &str,
+) -> Result<(), EmailError> {
+    let order_id = order_id.to_string();
+    let quantity = quantity.to_string();
+    let refunded = format!("{:.2}", refunded);
+    send_templated_email(
+        pool,
+        user_email,
+        email_templates::ORDER_DECLINED,
+        language,
+        &[
+            ("username", username),
+            ("order_id", &order_id),
+            ("quantity", &quantity),
+            ("product_name", product_name),
+            ("refunded", &refunded),
+        ],
+    )
+    .await?;
+
+    println!("📧 Order declined email sent to {}", user_email);
+    Ok(())
+}
+
+/// Forward a vendor's announcement to one of their followers
+pub async fn send_announcement_email(
+    pool: &PgPool,
+    user_email: &str,
+    username: &str,
+    vendor_username: &str,
+    title: &str,
+    body: &str,
+) -> Result<(), EmailError> {
+    let subject = format!("{} from {} - Farmers Market Place", title, vendor_username);
+    let body = format!(
+        r#"
+Dear {},
+
+{}, a vendor you follow, posted an announcement:
+
+{}
+
+{}
+
+You can turn off these emails from your profile settings, or unfollow the vendor.
+
+Best regards,
+The Farmers Market Place Team
+
+---
+This is an automated message. Please do not reply to this email.
+"#,
+        username, vendor_username, title, body
+    );
+
+    send_email(pool, user_email, &subject, &body).await?;
+
+    println!("📧 Announcement email sent to {}", user_email);
     Ok(())
 }
 
+/// Several notifications batched into one email for users on an hourly or daily digest
+pub async fn send_notification_digest_email(
+    pool: &PgPool,
+    user_email: &str,
+    username: &str,
+    items: &[String],
+) -> Result<(), EmailError> {
+    let subject = format!("Your {} update(s) - Farmers Market Place", items.len());
+    let list = items.iter().map(|item| format!("- {}", item)).collect::<Vec<_>>().join("\n");
+    let body = format!(
+        r#"
+Dear {},
+
+Here is what happened since your last update:
+
+{}
+
+You can change how often you get these emails from your profile settings.
+
+Best regards,
+The Farmers Market Place Team
+
+---
+This is an automated message. Please do not reply to this email.
+"#,
+        username, list
+    );
+
+    send_email(pool, user_email, &subject, &body).await?;
 
+    println!("📧 Notification digest ({} items) sent to {}", items.len(), user_email);
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Email retry queue. A send that fails transiently (the SMTP server is
+//! unreachable or answers 4xx) is stored in `email_outbox` and tried again
+//! with exponential backoff. After `MAX_ATTEMPTS` the email is marked failed
+//! and listed for admins, who can queue it again. An email with an expiry,
+//! such as one carrying a one-time code, is dropped once that passes.
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::db;
+use crate::email::{self, EmailError, Mailer};
+use crate::email_templates::RenderedEmail;
+
+/// How often the background task looks for emails due another attempt.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Attempts, including the first send, before an email is marked failed.
+pub const MAX_ATTEMPTS: i32 = 6;
+
+/// Wait before the first retry; doubles after each further failure.
+const BASE_DELAY_SECONDS: i64 = 60;
+
+/// Most queued emails tried in one pass.
+const BATCH_SIZE: i64 = 50;
+
+/// Wait before the next attempt, after `attempts` failed ones.
+pub fn backoff(attempts: i32) -> chrono::Duration {
+    chrono::Duration::seconds(BASE_DELAY_SECONDS << (attempts - 1).clamp(0, 10))
+}
+
+/// Send an email now, queueing it for retry if that fails transiently.
+/// Only errors that retrying can't fix are returned. Retries stop at `expires_at`, if given.
+pub async fn deliver(
+    pool: &PgPool,
+    mailer: &Mailer,
+    user_email: &str,
+    email: &RenderedEmail,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(), EmailError> {
+    match mailer.send(user_email, email) {
+        Err(e) if e.is_retryable() => {
+            let error = e.to_string();
+            match db::queue_email(pool, user_email, email, &error, Utc::now() + backoff(1), expires_at).await {
+                Ok(id) => {
+                    eprintln!("📨 Email to {} queued for retry (#{}): {}", user_email, id, error);
+                    Ok(())
+                }
+                Err(db_err) => {
+                    eprintln!("Failed to queue email to {} for retry: {:?}", user_email, db_err);
+                    Err(e)
+                }
+            }
+        }
+        result => result,
+    }
+}
+
+/// What a pass over the queue did
+#[derive(Debug, Default, PartialEq)]
+pub struct RetryOutcome {
+    pub sent: usize,
+    pub rescheduled: usize,
+    /// Out of attempts, now listed for admins
+    pub failed: usize,
+    /// Past their expiry, dropped without another attempt
+    pub expired: usize,
+}
+
+/// Try every queued email that is due. Each failure pushes the next attempt
+/// further out; a permanent failure or the last attempt marks it failed.
+/// Expired emails are dropped first.
+pub async fn retry_due_emails(pool: &PgPool, mailer: &Mailer) -> Result<RetryOutcome, sqlx::Error> {
+    let mut outcome = RetryOutcome {
+        expired: db::expire_queued_emails(pool).await? as usize,
+        ..RetryOutcome::default()
+    };
+    for (id, recipient, email, attempts) in db::get_due_emails(pool, BATCH_SIZE).await? {
+        match mailer.send(&recipient, &email) {
+            Ok(()) => {
+                db::mark_email_sent(pool, id).await?;
+                outcome.sent += 1;
+            }
+            Err(e) => {
+                let attempts = attempts + 1;
+                let next_attempt_at = (e.is_retryable() && attempts < MAX_ATTEMPTS).then(|| Utc::now() + backoff(attempts));
+                db::record_email_failure(pool, id, &e.to_string(), next_attempt_at).await?;
+                if next_attempt_at.is_some() {
+                    outcome.rescheduled += 1;
+                } else {
+                    outcome.failed += 1;
+                }
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+/// Run `retry_due_emails` periodically for the life of the process.
+/// Does nothing when email isn't configured.
+pub fn spawn_email_retry_task(pool: PgPool) {
+    let Ok(mailer) = email::mailer() else { return };
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match retry_due_emails(&pool, mailer).await {
+                Ok(outcome) if outcome == RetryOutcome::default() => {}
+                Ok(outcome) => println!(
+                    "📨 Email retries: {} sent, {} rescheduled, {} failed, {} expired",
+                    outcome.sent, outcome.rescheduled, outcome.failed, outcome.expired
+                ),
+                Err(e) => eprintln!("Email retry pass failed: {:?}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,287 @@
+//! Email templates. Each template is a text file whose first line is
+//! `Subject: ...`, followed by a blank line and the body; `{{variable}}`
+//! placeholders in either part are filled in by `render_template`.
+//!
+//! The templates under `templates/email/<language>/` are built into the
+//! binary. Setting `EMAIL_TEMPLATES_DIR` to a directory with the same layout
+//! overrides (or adds) templates at startup, so wording can change without a
+//! recompile. A template missing in the user's language falls back to English.
+//!
+//! Every email also gets an HTML body: the plain text laid out as paragraphs
+//! and lists inside `layout.html`, unless the directory has a
+//! `<language>/<name>.html` template to use instead.
+
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::{env, fs};
+
+use crate::db;
+
+/// Language used when a user hasn't picked one, and the fallback for missing templates.
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+pub const VERIFICATION_APPROVED: &str = "verification_approved";
+pub const VERIFICATION_REJECTED: &str = "verification_rejected";
+pub const ORDER_CANCELLED: &str = "order_cancelled";
+pub const ORDER_DECLINED: &str = "order_declined";
+pub const PASSWORD_RESET: &str = "password_reset";
+
+/// Templates compiled into the binary as (language, name, source).
+const BUILT_IN: &[(&str, &str, &str)] = &[
+    ("en", VERIFICATION_APPROVED, include_str!("../templates/email/en/verification_approved.txt")),
+    ("en", VERIFICATION_REJECTED, include_str!("../templates/email/en/verification_rejected.txt")),
+    ("en", ORDER_CANCELLED, include_str!("../templates/email/en/order_cancelled.txt")),
+    ("en", ORDER_DECLINED, include_str!("../templates/email/en/order_declined.txt")),
+    ("en", PASSWORD_RESET, include_str!("../templates/email/en/password_reset.txt")),
+    ("sw", VERIFICATION_APPROVED, include_str!("../templates/email/sw/verification_approved.txt")),
+    ("sw", VERIFICATION_REJECTED, include_str!("../templates/email/sw/verification_rejected.txt")),
+    ("sw", ORDER_CANCELLED, include_str!("../templates/email/sw/order_cancelled.txt")),
+    ("sw", ORDER_DECLINED, include_str!("../templates/email/sw/order_declined.txt")),
+    ("sw", PASSWORD_RESET, include_str!("../templates/email/sw/password_reset.txt")),
+];
+
+/// Page the HTML body of every email is placed in; `{{subject}}` and `{{content}}` are filled in.
+const BUILT_IN_LAYOUT: &str = include_str!("../templates/email/layout.html");
+
+/// A rendered email, ready to send
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    /// Plain-text body, for clients that don't render HTML
+    pub body: String,
+    pub html: String,
+}
+
+struct TemplateSet {
+    /// Keyed by (language, file name without `.txt`); HTML templates keep their `.html`
+    templates: HashMap<(String, String), String>,
+    layout: String,
+}
+
+static TEMPLATES: OnceLock<TemplateSet> = OnceLock::new();
+
+fn templates() -> &'static TemplateSet {
+    TEMPLATES.get_or_init(load_templates)
+}
+
+/// Load the templates now rather than on the first email, so a bad
+/// `EMAIL_TEMPLATES_DIR` is reported at startup. Returns the number loaded.
+pub fn init() -> usize {
+    templates().templates.len()
+}
+
+fn load_templates() -> TemplateSet {
+    let mut set = TemplateSet {
+        templates: BUILT_IN
+            .iter()
+            .map(|(language, name, source)| ((language.to_string(), name.to_string()), source.to_string()))
+            .collect(),
+        layout: BUILT_IN_LAYOUT.to_string(),
+    };
+
+    let Ok(dir) = env::var("EMAIL_TEMPLATES_DIR") else {
+        return set;
+    };
+    if let Ok(layout) = fs::read_to_string(std::path::Path::new(&dir).join("layout.html")) {
+        set.layout = layout;
+    }
+    let languages = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("⚠️  Can't read EMAIL_TEMPLATES_DIR {}: {}", dir, e);
+            return set;
+        }
+    };
+    for language in languages.flatten().filter(|entry| entry.path().is_dir()) {
+        let language_name = language.file_name().to_string_lossy().to_string();
+        let Ok(files) = fs::read_dir(language.path()) else { continue };
+        for file in files.flatten() {
+            let path = file.path();
+            let Some(stem) = path.file_stem().map(|stem| stem.to_string_lossy().to_string()) else { continue };
+            let name = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("txt") => stem,
+                Some("html") => format!("{}.html", stem),
+                _ => continue,
+            };
+            match fs::read_to_string(&path) {
+                Ok(source) => {
+                    set.templates.insert((language_name.clone(), name), source);
+                }
+                Err(e) => eprintln!("⚠️  Can't read email template {}: {}", path.display(), e),
+            }
+        }
+    }
+    set
+}
+
+/// Languages that have at least one template
+pub fn languages() -> Vec<String> {
+    let mut languages: Vec<String> = templates().templates.keys().map(|(language, _)| language.clone()).collect();
+    languages.sort();
+    languages.dedup();
+    languages
+}
+
+pub fn is_supported_language(language: &str) -> bool {
+    templates().templates.keys().any(|(lang, _)| lang == language)
+}
+
+/// Replace each `{{name}}` (whitespace inside the braces is allowed) with its
+/// value. Placeholders without a value are left as they are.
+pub fn substitute(text: &str, vars: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let key = after[..end].trim();
+        match vars.iter().find(|(name, _)| *name == key) {
+            Some((_, value)) => out.push_str(value),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Render the named template in `language` (falling back to English) with
+/// `vars` substituted. None if the template doesn't exist.
+pub fn render_template(name: &str, language: &str, vars: &[(&str, &str)]) -> Option<RenderedEmail> {
+    let set = &templates().templates;
+    let language = if set.contains_key(&(language.to_string(), name.to_string())) {
+        language
+    } else {
+        DEFAULT_LANGUAGE
+    };
+    let source = set.get(&(language.to_string(), name.to_string()))?;
+
+    let (subject, body) = match source.strip_prefix("Subject:") {
+        Some(rest) => rest.split_once('\n').unwrap_or((rest, "")),
+        None => ("Farmers Market Place", source.as_str()),
+    };
+    let subject = substitute(subject.trim(), vars);
+    let body = substitute(body, vars);
+
+    // HTML templates get their values escaped; without one the text is laid out as HTML
+    let html = match set.get(&(language.to_string(), format!("{}.html", name))) {
+        Some(html) => {
+            let escaped: Vec<(&str, String)> = vars.iter().map(|(key, value)| (*key, escape_html(value))).collect();
+            let escaped: Vec<(&str, &str)> = escaped.iter().map(|(key, value)| (*key, value.as_str())).collect();
+            wrap_in_layout(&subject, &substitute(html, &escaped))
+        }
+        None => wrap_in_layout(&subject, &html_from_text(&body)),
+    };
+
+    Some(RenderedEmail { subject, body, html })
+}
+
+/// An email written directly as plain text, with an HTML version laid out from it
+pub fn from_text(subject: &str, body: &str) -> RenderedEmail {
+    RenderedEmail {
+        subject: subject.to_string(),
+        body: body.to_string(),
+        html: wrap_in_layout(subject, &html_from_text(body)),
+    }
+}
+
+fn wrap_in_layout(subject: &str, content: &str) -> String {
+    substitute(&templates().layout, &[("subject", &escape_html(subject)), ("content", content)])
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// How a line of a plain-text email is laid out in HTML
+#[derive(Clone, Copy, PartialEq)]
+enum LineKind {
+    Text,
+    /// `- item`
+    Bullet,
+    /// `1. step`
+    Numbered,
+    /// Indented, like a verification code
+    Code,
+    /// `---` above the footer
+    Rule,
+}
+
+fn line_kind(line: &str) -> LineKind {
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if line.trim() == "---" {
+        LineKind::Rule
+    } else if line.starts_with("- ") {
+        LineKind::Bullet
+    } else if digits > 0 && line[digits..].starts_with(". ") {
+        LineKind::Numbered
+    } else if line.starts_with("    ") {
+        LineKind::Code
+    } else {
+        LineKind::Text
+    }
+}
+
+/// Lay out a plain-text email as HTML: paragraphs, bullet and numbered lists,
+/// indented codes and the footer rule. All text is escaped.
+pub fn html_from_text(text: &str) -> String {
+    let mut html = String::new();
+    for block in text.trim().split("\n\n") {
+        let lines: Vec<&str> = block.lines().filter(|line| !line.trim().is_empty()).collect();
+        let mut start = 0;
+        while start < lines.len() {
+            let kind = line_kind(lines[start]);
+            let end = lines[start..]
+                .iter()
+                .position(|line| line_kind(line) != kind)
+                .map_or(lines.len(), |offset| start + offset);
+            let group = &lines[start..end];
+            let items = |skip: fn(&str) -> &str| {
+                group.iter().map(|line| format!("<li>{}</li>", escape_html(skip(line)))).collect::<String>()
+            };
+            match kind {
+                LineKind::Rule => html.push_str("<hr style=\"border:none;border-top:1px solid #dde3dc;\">\n"),
+                LineKind::Bullet => html.push_str(&format!("<ul>{}</ul>\n", items(|line| &line[2..]))),
+                LineKind::Numbered => html.push_str(&format!(
+                    "<ol>{}</ol>\n",
+                    items(|line| line.split_once(". ").map_or(line, |(_, rest)| rest))
+                )),
+                LineKind::Code => html.push_str(&format!(
+                    "<p style=\"font-size:22px;font-weight:bold;letter-spacing:4px;\">{}</p>\n",
+                    group.iter().map(|line| escape_html(line.trim())).collect::<Vec<_>>().join("<br>")
+                )),
+                LineKind::Text => html.push_str(&format!(
+                    "<p>{}</p>\n",
+                    group.iter().map(|line| escape_html(line)).collect::<Vec<_>>().join("<br>\n")
+                )),
+            }
+            start = end;
+        }
+    }
+    html
+}
+
+/// The language a user reads email in, defaulting to English
+pub async fn user_language(pool: &PgPool, user_id: i32) -> String {
+    db::get_user_language(pool, user_id)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string())
+}
@@ -1,12 +1,22 @@
-//! Gemini AI client for chatbot integration.
+//! Gemini AI client for chatbot integration and listing moderation.
 //! Handles communication with the Google Gemini API.
 
-use reqwest;
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::time::Duration;
 
 const GEMINI_API_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models/";
 
+const GEMINI_MODEL: &str = "gemini-2.5-flash:generateContent";
+
+/// Models endpoint; GEMINI_API_URL overrides it (e.g. for a local mock).
+fn api_base_url() -> String {
+    match env::var("GEMINI_API_URL") {
+        Ok(url) if !url.is_empty() => format!("{}/", url.trim_end_matches('/')),
+        _ => GEMINI_API_BASE_URL.to_string(),
+    }
+}
+
 #[derive(Serialize)]
 struct GeminiRequest {
     contents: Vec<Content>,
@@ -50,8 +60,7 @@ pub async fn get_gemini_response(prompt: &str) -> Result<String, Box<dyn std::er
             return Ok("I'm sorry, but the AI chatbot is currently unavailable. The administrator needs to configure the Gemini API key. Please try again later or contact support for assistance.".to_string());
         }
     };
-    let model = "gemini-2.5-flash:generateContent";
-    let url = format!("{}{}", GEMINI_API_BASE_URL, model);
+    let url = format!("{}{}", api_base_url(), GEMINI_MODEL);
 
     let client = reqwest::Client::new();
 
@@ -75,8 +84,8 @@ pub async fn get_gemini_response(prompt: &str) -> Result<String, Box<dyn std::er
     if res.status().is_success() {
         let gemini_response: GeminiResponse = res.json().await
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
-        if let Some(candidate) = gemini_response.candidates.get(0) {
-            if let Some(part) = candidate.content.parts.get(0) {
+        if let Some(candidate) = gemini_response.candidates.first() {
+            if let Some(part) = candidate.content.parts.first() {
                 return Ok(part.text.clone());
             }
         }
@@ -88,3 +97,42 @@ pub async fn get_gemini_response(prompt: &str) -> Result<String, Box<dyn std::er
         Ok(format!("Error: {}", error_body))
     }
 }
+
+/// Whether an API key is configured.
+pub fn is_configured() -> bool {
+    env::var("GEMINI_API_KEY").is_ok_and(|key| !key.is_empty())
+}
+
+/// The model's reply to `prompt`, for internal checks rather than the chatbot:
+/// a missing API key, a failed call, an error status or an empty reply are all
+/// errors, so the caller decides what to fall back to.
+pub async fn generate(prompt: &str, timeout: Duration) -> Result<String, Box<dyn std::error::Error>> {
+    let api_key = env::var("GEMINI_API_KEY").map_err(|_| "GEMINI_API_KEY is not set")?;
+    let url = format!("{}{}", api_base_url(), GEMINI_MODEL);
+    let client = reqwest::Client::builder().timeout(timeout).build()?;
+
+    let request_body = GeminiRequest {
+        contents: vec![Content {
+            parts: vec![Part {
+                text: prompt.to_string(),
+            }],
+        }],
+    };
+    let response: GeminiResponse = client
+        .post(&url)
+        .query(&[("key", &api_key)])
+        .json(&request_body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    response
+        .candidates
+        .into_iter()
+        .next()
+        .and_then(|candidate| candidate.content.parts.into_iter().next())
+        .map(|part| part.text)
+        .ok_or_else(|| "No response from Gemini".into())
+}
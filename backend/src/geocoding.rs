@@ -0,0 +1,107 @@
+//! Reverse geocoding for `/location/update`. When a user sends coordinates without a
+//! `location_string`, we ask a Nominatim-compatible provider (GEOCODING_URL) for the
+//! place name so the string-based location filters keep working. Lookups are cached
+//! per ~1 km grid cell in `geocode_cache`.
+
+use crate::db;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::env;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug)]
+struct ReverseResponse {
+    display_name: Option<String>,
+    #[serde(default)]
+    address: Address,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Address {
+    city: Option<String>,
+    town: Option<String>,
+    village: Option<String>,
+    suburb: Option<String>,
+    county: Option<String>,
+    country: Option<String>,
+}
+
+impl ReverseResponse {
+    /// "Town, Country", falling back to the provider's full display name.
+    fn place_name(self) -> Option<String> {
+        let address = self.address;
+        let locality = address.city.or(address.town).or(address.village).or(address.suburb).or(address.county);
+        match (locality, address.country) {
+            (Some(locality), Some(country)) => Some(format!("{}, {}", locality, country)),
+            (Some(locality), None) => Some(locality),
+            _ => self.display_name.filter(|name| !name.trim().is_empty()),
+        }
+    }
+}
+
+/// Client for a Nominatim-style `/reverse` endpoint.
+pub struct Geocoder {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl Geocoder {
+    pub fn new(base_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .user_agent("farmers-market-place")
+            .build()
+            .expect("Failed to create HTTP client");
+        Geocoder { base_url: base_url.trim_end_matches('/').to_string(), client }
+    }
+
+    /// The provider configured with GEOCODING_URL, if any.
+    pub fn from_env() -> Option<Self> {
+        env::var("GEOCODING_URL").ok().filter(|url| !url.is_empty()).map(|url| Geocoder::new(&url))
+    }
+
+    /// Ask the provider for the place at these coordinates.
+    pub async fn lookup(&self, latitude: f64, longitude: f64) -> Result<Option<String>, reqwest::Error> {
+        let response = self
+            .client
+            .get(format!("{}/reverse", self.base_url))
+            .query(&[
+                ("format", "jsonv2".to_string()),
+                ("lat", latitude.to_string()),
+                ("lon", longitude.to_string()),
+                ("zoom", "10".to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json::<ReverseResponse>().await?.place_name())
+    }
+}
+
+/// Grid cell (hundredths of a degree) used as the cache key.
+pub fn cache_cell(latitude: f64, longitude: f64) -> (i32, i32) {
+    ((latitude * 100.0).round() as i32, (longitude * 100.0).round() as i32)
+}
+
+/// Place name for the coordinates from the cache or the provider. Returns None when no
+/// provider is configured or it can't be reached, so callers store just the coordinates.
+pub async fn reverse_geocode(pool: &PgPool, geocoder: &Geocoder, latitude: f64, longitude: f64) -> Option<String> {
+    let (lat_cell, lon_cell) = cache_cell(latitude, longitude);
+    if let Ok(Some(cached)) = db::get_cached_geocode(pool, lat_cell, lon_cell).await {
+        return Some(cached);
+    }
+
+    match geocoder.lookup(latitude, longitude).await {
+        Ok(Some(place)) => {
+            if let Err(e) = db::cache_geocode(pool, lat_cell, lon_cell, &place).await {
+                eprintln!("Failed to cache geocode result: {}", e);
+            }
+            Some(place)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!("Reverse geocoding failed: {}", e);
+            None
+        }
+    }
+}
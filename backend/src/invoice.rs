@@ -0,0 +1,185 @@
+//! PDF invoices for orders. The layout is a single A4 page of plain text, so
+//! the document is written directly with the standard Helvetica fonts rather
+//! than pulling in a PDF rendering crate.
+
+use crate::models::OrderInvoice;
+
+const PAGE_WIDTH: f64 = 595.0;
+const PAGE_HEIGHT: f64 = 842.0;
+const MARGIN: f64 = 50.0;
+
+/// Standard font resources declared on the page.
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+/// Text drawing operations for one page's content stream.
+#[derive(Default)]
+struct Page {
+    ops: String,
+}
+
+impl Page {
+    fn text(&mut self, font: Font, size: f64, x: f64, y: f64, text: &str) {
+        self.ops.push_str(&format!(
+            "BT /{} {} Tf 1 0 0 1 {:.2} {:.2} Tm ({}) Tj ET\n",
+            font.resource(),
+            size,
+            x,
+            y,
+            escape(text)
+        ));
+    }
+
+    /// Right-align `text` so it ends at `right`. Uses an average glyph width,
+    /// which is close enough for the numeric columns it's used for.
+    fn text_right(&mut self, font: Font, size: f64, right: f64, y: f64, text: &str) {
+        let width = text.chars().count() as f64 * size * 0.55;
+        self.text(font, size, right - width, y, text);
+    }
+
+    fn line(&mut self, y: f64) {
+        self.ops.push_str(&format!("{:.2} {:.2} m {:.2} {:.2} l S\n", MARGIN, y, PAGE_WIDTH - MARGIN, y));
+    }
+}
+
+/// Escape a string for a PDF literal. Characters outside printable ASCII
+/// aren't in the base font encoding and are replaced with '?'.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+fn money(amount: f64) -> String {
+    format!("KSh {:.2}", amount)
+}
+
+/// Render an order invoice as a PDF document.
+pub fn render_pdf(invoice: &OrderInvoice) -> Vec<u8> {
+    let mut page = Page::default();
+    let right = PAGE_WIDTH - MARGIN;
+    let mut y = PAGE_HEIGHT - MARGIN - 10.0;
+
+    page.text(Font::Bold, 22.0, MARGIN, y, "INVOICE");
+    page.text_right(Font::Bold, 12.0, right, y, "Farmers Market Place");
+    y -= 28.0;
+    page.text(Font::Regular, 10.0, MARGIN, y, &format!("Invoice no: INV-{:06}", invoice.order_id));
+    y -= 14.0;
+    page.text(Font::Regular, 10.0, MARGIN, y, &format!("Date: {}", invoice.issued_on));
+    y -= 14.0;
+    page.text(Font::Regular, 10.0, MARGIN, y, &format!("Order status: {}", invoice.shipping_status));
+    y -= 30.0;
+
+    let seller = [
+        Some(invoice.vendor_username.as_str()),
+        Some(invoice.vendor_email.as_str()),
+        invoice.vendor_location.as_deref(),
+    ];
+    let buyer = [
+        Some(invoice.customer_username.as_str()),
+        Some(invoice.customer_email.as_str()),
+        invoice.shipping_address.as_deref().or(invoice.customer_location.as_deref()),
+    ];
+    let buyer_x = PAGE_WIDTH / 2.0;
+    page.text(Font::Bold, 11.0, MARGIN, y, "Sold by");
+    page.text(Font::Bold, 11.0, buyer_x, y, "Billed to");
+    for (seller_line, buyer_line) in seller.iter().zip(buyer.iter()) {
+        y -= 14.0;
+        if let Some(line) = seller_line {
+            page.text(Font::Regular, 10.0, MARGIN, y, line);
+        }
+        if let Some(line) = buyer_line {
+            page.text(Font::Regular, 10.0, buyer_x, y, line);
+        }
+    }
+    y -= 34.0;
+
+    let (qty_right, unit_right) = (330.0, 430.0);
+    page.text(Font::Bold, 10.0, MARGIN, y, "Item");
+    page.text_right(Font::Bold, 10.0, qty_right, y, "Qty");
+    page.text_right(Font::Bold, 10.0, unit_right, y, "Unit price");
+    page.text_right(Font::Bold, 10.0, right, y, "Amount");
+    y -= 6.0;
+    page.line(y);
+    y -= 16.0;
+
+    let unit_price = if invoice.quantity > 0 { invoice.items_total / invoice.quantity as f64 } else { invoice.items_total };
+    page.text(Font::Regular, 10.0, MARGIN, y, &invoice.product_name);
+    page.text_right(Font::Regular, 10.0, qty_right, y, &invoice.quantity.to_string());
+    page.text_right(Font::Regular, 10.0, unit_right, y, &money(unit_price));
+    page.text_right(Font::Regular, 10.0, right, y, &money(invoice.items_total));
+    y -= 16.0;
+    page.text(Font::Regular, 10.0, MARGIN, y, "Shipping");
+    page.text_right(Font::Regular, 10.0, right, y, &money(invoice.shipping_fee));
+    y -= 8.0;
+    page.line(y);
+    y -= 18.0;
+    page.text_right(Font::Bold, 12.0, unit_right, y, "Total");
+    page.text_right(Font::Bold, 12.0, right, y, &money(invoice.items_total + invoice.shipping_fee));
+    y -= 16.0;
+    page.text_right(Font::Regular, 10.0, unit_right, y, "Includes VAT");
+    page.text_right(Font::Regular, 10.0, right, y, &money(invoice.tax_amount));
+    y -= 36.0;
+
+    let receipt = invoice.mpesa_receipt_number.as_deref().unwrap_or("Not available");
+    page.text(Font::Regular, 10.0, MARGIN, y, &format!("M-Pesa receipt: {}", receipt));
+
+    page.text(Font::Regular, 8.0, MARGIN, MARGIN, "Generated by Farmers Market Place. Keep this invoice for your records.");
+
+    write_document(&page.ops)
+}
+
+/// Wrap a content stream in a one-page PDF with the two Helvetica fonts.
+fn write_document(content: &str) -> Vec<u8> {
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 4 0 R /F2 5 0 R >> >> /Contents 6 0 R >>",
+            PAGE_WIDTH, PAGE_HEIGHT
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_string(),
+        format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content),
+    ];
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+    }
+
+    let xref_offset = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref_offset
+    ));
+    pdf.into_bytes()
+}
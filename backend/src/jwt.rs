@@ -0,0 +1,102 @@
+//! Signing keys for auth tokens. HS256 with a shared secret remains the
+//! default; with `JWT_ALGORITHM=RS256` tokens are signed with an RSA private
+//! key and verified with its public key, so services that only verify tokens
+//! never need the key that mints them.
+
+use crate::models::Claims;
+use jsonwebtoken::errors::{Error, ErrorKind};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use std::env;
+use std::sync::OnceLock;
+
+/// Secret used when JWT_SECRET isn't set, kept so existing tokens stay valid.
+const DEFAULT_JWT_SECRET: &str = "your-secret-key";
+
+/// Keys tokens are signed and verified with.
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    /// None for verify-only RS256 deployments
+    encoding: Option<EncodingKey>,
+    decoding: DecodingKey,
+}
+
+impl JwtKeys {
+    pub fn hs256(secret: &[u8]) -> Self {
+        JwtKeys {
+            algorithm: Algorithm::HS256,
+            encoding: Some(EncodingKey::from_secret(secret)),
+            decoding: DecodingKey::from_secret(secret),
+        }
+    }
+
+    /// RS256 keys from PEM. Without a private key tokens can be verified but not issued.
+    pub fn rs256(private_pem: Option<&[u8]>, public_pem: &[u8]) -> Result<Self, Error> {
+        Ok(JwtKeys {
+            algorithm: Algorithm::RS256,
+            encoding: private_pem.map(EncodingKey::from_rsa_pem).transpose()?,
+            decoding: DecodingKey::from_rsa_pem(public_pem)?,
+        })
+    }
+
+    /// Keys configured by JWT_ALGORITHM (`HS256` by default, or `RS256`).
+    /// HS256 uses JWT_SECRET. RS256 reads PEM keys from JWT_PUBLIC_KEY and
+    /// JWT_PRIVATE_KEY, or from the files named by JWT_PUBLIC_KEY_FILE and
+    /// JWT_PRIVATE_KEY_FILE; the private key is optional.
+    pub fn from_env() -> Result<Self, String> {
+        let algorithm = env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string());
+        match algorithm.trim().to_uppercase().as_str() {
+            "" | "HS256" => {
+                let secret = env::var("JWT_SECRET")
+                    .ok()
+                    .filter(|secret| !secret.is_empty())
+                    .unwrap_or_else(|| DEFAULT_JWT_SECRET.to_string());
+                Ok(JwtKeys::hs256(secret.as_bytes()))
+            }
+            "RS256" => {
+                let public_pem = pem_from_env("JWT_PUBLIC_KEY")?
+                    .ok_or("JWT_ALGORITHM=RS256 needs JWT_PUBLIC_KEY or JWT_PUBLIC_KEY_FILE")?;
+                let private_pem = pem_from_env("JWT_PRIVATE_KEY")?;
+                JwtKeys::rs256(private_pem.as_deref(), &public_pem).map_err(|e| format!("Invalid RS256 key: {}", e))
+            }
+            other => Err(format!("Unsupported JWT_ALGORITHM: {} (expected HS256 or RS256)", other)),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn encode(&self, claims: &Claims) -> Result<String, Error> {
+        let key = self.encoding.as_ref().ok_or_else(|| Error::from(ErrorKind::InvalidRsaKey("no private key configured".to_string())))?;
+        encode(&Header::new(self.algorithm), claims, key)
+    }
+
+    /// Claims of a token signed with these keys; tokens using any other
+    /// algorithm are rejected.
+    pub fn decode(&self, token: &str) -> Result<Claims, Error> {
+        decode::<Claims>(token, &self.decoding, &Validation::new(self.algorithm)).map(|data| data.claims)
+    }
+}
+
+/// PEM text from `name`, or from the file named by `<name>_FILE`.
+fn pem_from_env(name: &str) -> Result<Option<Vec<u8>>, String> {
+    if let Some(pem) = env::var(name).ok().filter(|pem| !pem.trim().is_empty()) {
+        // Allow single-line values with escaped newlines, as some env files need
+        return Ok(Some(pem.replace("\\n", "\n").into_bytes()));
+    }
+    match env::var(format!("{}_FILE", name)) {
+        Ok(path) if !path.is_empty() => {
+            std::fs::read(&path).map(Some).map_err(|e| format!("Failed to read {}_FILE {}: {}", name, path, e))
+        }
+        _ => Ok(None),
+    }
+}
+
+static KEYS: OnceLock<JwtKeys> = OnceLock::new();
+
+/// The process-wide keys, loaded from the environment on first use.
+/// Panics if the configuration is invalid; `main` loads them at startup so
+/// that happens before the server accepts requests.
+pub fn keys() -> &'static JwtKeys {
+    KEYS.get_or_init(|| JwtKeys::from_env().unwrap_or_else(|e| panic!("Invalid JWT configuration: {}", e)))
+}
@@ -0,0 +1,42 @@
+//! Vendor leaderboard: verified vendors ranked by revenue, orders or rating
+//! over a recent window. The ranking aggregates every order and review in the
+//! window, so it is cached in memory per database, window and sort for a short
+//! time, and pages are cut from the cached ranking.
+
+use crate::db;
+use crate::models::{LeaderboardSort, VendorLeaderboardEntry};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+/// How long a computed ranking is served before it is recomputed.
+pub const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// (database name, window in days, sort)
+type CacheKey = (String, i32, LeaderboardSort);
+type LeaderboardCache = HashMap<CacheKey, (Instant, Vec<VendorLeaderboardEntry>)>;
+
+static CACHE: OnceLock<RwLock<LeaderboardCache>> = OnceLock::new();
+
+fn cache() -> &'static RwLock<LeaderboardCache> {
+    CACHE.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Every ranked vendor for the last `days` days, best first.
+pub async fn leaderboard(pool: &PgPool, days: i32, sort: LeaderboardSort) -> Result<Vec<VendorLeaderboardEntry>, sqlx::Error> {
+    let key = (
+        pool.connect_options().get_database().unwrap_or_default().to_string(),
+        days,
+        sort,
+    );
+    if let Some((computed_at, entries)) = cache().read().unwrap().get(&key) {
+        if computed_at.elapsed() < CACHE_TTL {
+            return Ok(entries.clone());
+        }
+    }
+
+    let entries = db::get_vendor_leaderboard(pool, days, sort).await?;
+    cache().write().unwrap().insert(key, (Instant::now(), entries.clone()));
+    Ok(entries)
+}
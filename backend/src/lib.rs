@@ -0,0 +1,38 @@
+//! Farmers Market Place backend library.
+//! Exposes the server modules so the binary and integration tests share them.
+
+pub mod acceptance;
+pub mod admin_query;
+pub mod audit;
+pub mod coupons;
+pub mod currency;
+pub mod data_export;
+pub mod db;
+pub mod digests;
+pub mod duplicates;
+pub mod models;
+pub mod moderation;
+pub mod routes;
+pub mod mpesa;
+pub mod gemini;
+pub mod email;
+pub mod email_queue;
+pub mod email_templates;
+pub mod geocoding;
+pub mod invoice;
+pub mod jwt;
+pub mod leaderboard;
+pub mod maintenance;
+pub mod password;
+pub mod settings;
+pub mod payouts;
+pub mod realtime;
+pub mod reminders;
+pub mod request_id;
+pub mod reservations;
+pub mod retention;
+pub mod shipping;
+pub mod tax;
+pub mod timestamps;
+pub mod trending;
+pub mod validation;
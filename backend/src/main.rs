@@ -1,25 +1,37 @@
 //! Farmers Market Place backend server.
 //! Provides REST API endpoints for products, users, messaging, and M-Pesa payments.
 
-use actix_web::{App, HttpServer, web};
+use actix_web::{middleware, App, HttpServer, web};
 use actix_cors::Cors;
 use std::io;
 
-mod db;
-mod models;
-mod routes;
-mod mpesa;
-mod gemini;
-mod email;
+use backend::{acceptance, audit, db, digests, email, email_queue, email_templates, jwt, maintenance, payouts, realtime, reminders, request_id, reservations, retention, routes, timestamps};
 
 /// Entry point: initializes database and starts HTTP server on port 8080.
 #[actix_web::main]
 async fn main() -> io::Result<()> {
     dotenv::dotenv().ok();
     println!("Starting Farmers Market Place Backend...");
+    // Fail fast on a bad JWT_ALGORITHM / key setup rather than on the first login
+    println!("🔑 Signing tokens with {:?}", jwt::keys().algorithm());
+    println!("📧 Loaded {} email templates", email_templates::init());
+    // One pooled SMTP mailer for the whole process
+    if let Err(e) = email::mailer() {
+        println!("⚠️  Email disabled: {}", e);
+    }
 
     let pool = db::init_db().await;
+    reminders::spawn_cart_reminder_task(pool.clone());
+    payouts::spawn_payout_task(pool.clone());
+    retention::spawn_retention_task(pool.clone());
+    reservations::spawn_reservation_task(pool.clone());
+    digests::spawn_digest_task(pool.clone());
+    acceptance::spawn_acceptance_task(pool.clone());
+    email_queue::spawn_email_retry_task(pool.clone());
     
+    // One hub for all workers so sockets on different workers can reach each other
+    let chat_hub = web::Data::new(realtime::ChatHub::default());
+
     println!("🚀 Starting HTTP server on http://127.0.0.1:8080");
 
     let server = HttpServer::new(move || {
@@ -27,8 +39,14 @@ async fn main() -> io::Result<()> {
         let cors = Cors::permissive();
         App::new()
             .app_data(web::Data::new(pool.clone()))
+            .app_data(chat_hub.clone())
+            .wrap(middleware::from_fn(timestamps::timestamp_middleware))
+            .wrap(middleware::from_fn(audit::impersonation_guard))
+            .wrap(middleware::from_fn(maintenance::maintenance_guard))
+            .wrap(middleware::from_fn(request_id::request_id_middleware))
             .wrap(cors)
             .configure(routes::init)
+            .default_service(web::to(routes::not_found))
     })
     .bind("127.0.0.1:8080")?;
     
@@ -0,0 +1,53 @@
+//! Maintenance mode: while the `maintenance_mode` setting is on, the API answers
+//! 503 with `{maintenance: true, message}`. Health checks, admin login and
+//! requests from admins still get through so the flag can be switched off again.
+
+use crate::models::verify_jwt;
+use crate::settings;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use serde_json::json;
+use sqlx::PgPool;
+
+/// Requests that are served even during maintenance. Login is let through so
+/// admins can sign in; the login route itself turns everyone else away.
+fn is_exempt(req: &ServiceRequest) -> bool {
+    if req.path() == "/health" || (req.method() == Method::POST && req.path() == "/login") {
+        return true;
+    }
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .and_then(|token| verify_jwt(token).ok())
+        .is_some_and(|claims| claims.role == "Admin" && claims.impersonated_by.is_none())
+}
+
+/// Middleware: refuse requests with 503 while maintenance mode is on.
+/// The flag comes from the settings cache, so this costs no query per request.
+pub async fn maintenance_guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(pool) = req.app_data::<web::Data<PgPool>>().cloned() else {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    };
+    if !settings::get_bool(&pool, settings::MAINTENANCE_MODE).await || is_exempt(&req) {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+
+    let response = unavailable(&pool).await;
+    Ok(req.into_response(response))
+}
+
+/// The 503 sent while in maintenance mode.
+pub async fn unavailable(pool: &PgPool) -> HttpResponse {
+    let message = settings::get_setting(pool, settings::MAINTENANCE_MESSAGE).await.unwrap_or_default();
+    HttpResponse::ServiceUnavailable()
+        .insert_header(("Retry-After", "300"))
+        .json(json!({ "maintenance": true, "message": message }))
+}
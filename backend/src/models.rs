@@ -2,7 +2,7 @@
 //! Defines structures for products, users, carts, messages, and authentication.
 
 use serde::{Deserialize, Serialize};
-use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey, errors::Error};
+use jsonwebtoken::errors::Error;
 use chrono::{Utc, Duration};
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -15,6 +15,87 @@ pub struct Product {
     pub image: Option<String>, // Base64 encoded image
     pub quantity: i32,
     pub vendor_id: u32,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Extra images after the primary `image`, in display order
+    #[serde(default)]
+    pub gallery: Vec<ProductImage>,
+    #[serde(flatten)]
+    pub attributes: ProductAttributes,
+}
+
+/// Structured details of a product: how it's sold, where it's from and when it was harvested
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct ProductAttributes {
+    /// Selling unit, e.g. "kg", "bunch" or "tray"
+    pub unit: Option<String>,
+    pub weight_grams: Option<i32>,
+    pub origin: Option<String>,
+    #[serde(default)]
+    pub is_organic: bool,
+    pub harvest_date: Option<chrono::NaiveDate>,
+}
+
+/// A product held by automated moderation, as shown to admins
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FlaggedProduct {
+    pub id: i32,
+    pub name: String,
+    pub category: String,
+    pub description: Option<String>,
+    pub vendor_id: i32,
+    pub vendor_username: String,
+    pub flag_reason: Option<String>,
+    pub flagged_at: chrono::DateTime<Utc>,
+}
+
+/// One image in a product's gallery
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProductImage {
+    pub id: i32,
+    pub image: String,
+    pub position: i32,
+}
+
+/// Body of a successful write: `{success: true}` with a human-readable
+/// `message`, the resulting `data`, or both.
+#[derive(Serialize, Deserialize)]
+pub struct ApiSuccess<T> {
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<T>,
+}
+
+/// One page of a listing, with enough metadata to fetch the next one.
+#[derive(Serialize, Deserialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    /// Whether items remain after this page
+    pub has_more: bool,
+}
+
+impl<T> Paginated<T> {
+    /// A page already cut from a listing of `total` items.
+    pub fn new(items: Vec<T>, total: i64, limit: i64, offset: i64) -> Self {
+        let has_more = offset + (items.len() as i64) < total;
+        Paginated { items, total, limit, offset, has_more }
+    }
+
+    /// Cut the page at `offset`/`limit` out of a complete listing.
+    pub fn from_all(all: Vec<T>, limit: i64, offset: i64) -> Self {
+        let total = all.len() as i64;
+        let items = all
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .collect();
+        Paginated::new(items, total, limit, offset)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -67,6 +148,24 @@ pub struct ProductRequest {
     pub description: String,
     pub quantity: i32,
     pub image: Option<String>, // Base64 encoded image
+    /// Replaces the product's tags when present; left unchanged when omitted on update
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    /// Create the product even if the vendor already lists one with a very similar name
+    #[serde(default)]
+    pub force: bool,
+    /// Structured details; each replaces the stored value when present and is
+    /// left unchanged when omitted on update
+    #[serde(default)]
+    pub unit: Option<String>,
+    #[serde(default)]
+    pub weight_grams: Option<i32>,
+    #[serde(default)]
+    pub origin: Option<String>,
+    #[serde(default)]
+    pub is_organic: Option<bool>,
+    #[serde(default)]
+    pub harvest_date: Option<chrono::NaiveDate>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -75,14 +174,38 @@ pub struct Claims {
     pub username: String,
     pub role: String,
     pub exp: usize, // expiration time
+    /// Admin acting as this user (support impersonation tokens only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonated_by: Option<i32>,
+    /// Impersonation session id, checked on every request so the token can be revoked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct LoginResponse {
     pub token: String,
+    /// Exchanged at POST /auth/refresh for a new token; valid for `REFRESH_TOKEN_TTL_DAYS`
+    pub refresh_token: String,
     pub user: User,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+/// A signed-in device, as listed by GET /account/sessions
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Session {
+    pub id: i32,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: String,
+    pub last_used_at: String,
+    pub expires_at: String,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CartItem {
     pub id: i32,
@@ -98,6 +221,21 @@ pub struct CartItemRequest {
     pub quantity: i32,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct CartBatchRequest {
+    pub items: Vec<CartItemRequest>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CartBatchResult {
+    pub product_id: i32,
+    pub quantity: i32,
+    pub success: bool,
+    pub error: Option<String>,
+    /// Machine-readable cause of `error`, as on POST /cart
+    pub reason: Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct UpdateCartItemRequest {
     pub quantity: i32,
@@ -113,6 +251,34 @@ pub struct UpdateUserVerificationRequest {
     pub verified: bool,
 }
 
+/// Moderator corrections to a product; unset fields are left alone
+#[derive(Serialize, Deserialize, Default)]
+pub struct AdminProductUpdate {
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub description: Option<String>,
+    /// Recorded in the audit log
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VerificationDocumentRetentionRequest {
+    pub retained: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BulkVerificationRequest {
+    pub user_ids: Vec<i32>,
+    pub verified: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BulkVerificationResult {
+    pub user_id: i32,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct UploadVerificationDocumentRequest {
     pub verification_document: String, // Base64 encoded image
@@ -123,6 +289,18 @@ pub struct CheckoutRequest {
     pub mpesa_number: String,
     pub total_amount: f64,
     pub selected_items: Option<Vec<i32>>, // Optional list of cart item IDs to checkout
+    /// Chosen shipping option per vendor; vendors left out get their cheapest option
+    #[serde(default)]
+    pub shipping_selections: Vec<ShippingSelection>,
+    /// Vendor coupon codes, at most one per vendor in the cart
+    #[serde(default)]
+    pub coupon_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct ShippingSelection {
+    pub vendor_id: i32,
+    pub option_id: i32,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -130,14 +308,21 @@ pub struct CheckoutResponse {
     pub transaction_id: String,
     pub message: String,
     pub status: String,
+    pub shipping_total: f64,
+    /// Total taken off by coupons
+    pub discount_total: f64,
+    /// VAT included in the items' prices (not added on top)
+    pub tax_total: f64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
     pub id: i32,
     pub sender_id: i32,
     pub receiver_id: i32,
     pub content: String,
+    /// Image shared with the message, as a base64 data URL
+    pub attachment: Option<String>,
     pub is_read: bool,
     pub created_at: String,
     pub sender_username: String,
@@ -167,7 +352,10 @@ pub struct Follow {
 #[derive(Serialize, Deserialize)]
 pub struct SendMessageRequest {
     pub receiver_id: i32,
+    #[serde(default)]
     pub content: String,
+    /// Optional image as a base64 data URL (`data:image/png;base64,...`)
+    pub attachment: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -186,6 +374,70 @@ pub struct Review {
     pub created_at: String,
     pub customer_username: String,
     pub product_name: String,
+    pub helpful_count: i32,
+    pub reply: Option<ReviewReply>,
+}
+
+/// Order of a product's reviews (`sort` on GET /reviews/product/{id})
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewSort {
+    #[default]
+    Newest,
+    Highest,
+    Lowest,
+    /// Most helpful votes first
+    Helpful,
+}
+
+impl std::str::FromStr for ReviewSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "newest" => Ok(ReviewSort::Newest),
+            "highest" => Ok(ReviewSort::Highest),
+            "lowest" => Ok(ReviewSort::Lowest),
+            "helpful" => Ok(ReviewSort::Helpful),
+            other => Err(format!("Unknown review sort: {}", other)),
+        }
+    }
+}
+
+/// Rating summary over all of a product's reviews
+#[derive(Serialize, Deserialize)]
+pub struct ReviewSummary {
+    /// None while the product has no reviews
+    pub average_rating: Option<f64>,
+    pub review_count: i64,
+}
+
+/// One page of a product's reviews with the summary of all of them
+#[derive(Serialize, Deserialize)]
+pub struct ReviewPage {
+    #[serde(flatten)]
+    pub page: Paginated<Review>,
+    pub summary: ReviewSummary,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReviewReply {
+    pub id: i32,
+    pub review_id: i32,
+    pub vendor_id: i32,
+    pub content: String,
+    pub created_at: String,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReviewVoteRequest {
+    pub helpful: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReviewReplyRequest {
+    pub content: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -207,6 +459,165 @@ pub struct ShippingOrder {
     pub customer_verified: bool,
     pub payment_released: bool,
     pub verification_requested_at: Option<String>,
+    pub shipping_fee: f64,
+    /// Coupon discount already taken off `total_amount`
+    pub discount: f64,
+    /// The order this is a line item of
+    pub order_id: Option<i32>,
+    /// Receipt of the payment for this order (the transaction id for demo payments)
+    pub mpesa_receipt_number: Option<String>,
+    pub transaction_date: Option<String>,
+}
+
+/// An order waiting for its vendor to accept or decline it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PendingAcceptanceOrder {
+    /// Shipping order id, as used by the accept and decline routes
+    pub id: i32,
+    pub order_id: Option<i32>,
+    pub product_id: i32,
+    pub product_name: String,
+    pub quantity: i32,
+    pub total_amount: f64,
+    pub customer_username: String,
+    pub created_at: String,
+    /// When the order is declined automatically if still not accepted
+    pub accept_by: String,
+}
+
+/// One line of an order: a product from one vendor, fulfilled independently
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OrderItem {
+    /// Id of the item's shipping order
+    pub id: i32,
+    pub product_id: i32,
+    pub product_name: String,
+    pub vendor_id: i32,
+    pub vendor_username: String,
+    pub quantity: i32,
+    pub total_amount: f64,
+    pub shipping_fee: f64,
+    /// Coupon discount already taken off `total_amount`
+    pub discount: f64,
+    pub shipping_status: String,
+    pub tracking_number: Option<String>,
+    pub updated_at: String,
+}
+
+/// An order with every line item (GET /orders/{id})
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Order {
+    pub id: i32,
+    pub customer_id: i32,
+    pub shipping_address: Option<String>,
+    pub payment_transaction_id: Option<i32>,
+    /// Receipt of the payment for this order (the transaction id for demo payments)
+    pub mpesa_receipt_number: Option<String>,
+    pub transaction_date: Option<String>,
+    pub created_at: String,
+    /// The items' shared status, or "partially_fulfilled" while they differ
+    pub status: String,
+    pub items_total: f64,
+    pub shipping_total: f64,
+    pub items: Vec<OrderItem>,
+}
+
+/// One line of an order receipt
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReceiptLine {
+    /// Id of the item's shipping order
+    pub id: i32,
+    pub product_id: i32,
+    pub product_name: String,
+    pub vendor_id: i32,
+    pub vendor_username: String,
+    pub quantity: i32,
+    pub unit_price: f64,
+    /// `unit_price` x `quantity`, before the discount
+    pub subtotal: f64,
+    pub discount: f64,
+    pub coupon_code: Option<String>,
+    /// VAT included in `subtotal` - `discount`
+    pub tax: f64,
+    pub shipping_fee: f64,
+    /// `subtotal` - `discount` + `shipping_fee`
+    pub total: f64,
+    pub status: String,
+}
+
+/// How an order was paid
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReceiptPayment {
+    /// "mpesa", or "demo" for payments simulated with DEMO_MODE
+    pub method: String,
+    pub status: String,
+    /// Amount charged for the whole order
+    pub amount: f64,
+    pub phone_number: String,
+    pub mpesa_receipt_number: Option<String>,
+    pub paid_at: Option<String>,
+}
+
+/// Machine-readable receipt for an order (GET /orders/{id}/receipt)
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OrderReceipt {
+    pub order_id: i32,
+    pub customer_id: i32,
+    pub customer_username: String,
+    pub shipping_address: Option<String>,
+    pub created_at: String,
+    pub currency: String,
+    pub items: Vec<ReceiptLine>,
+    pub subtotal: f64,
+    pub discount_total: f64,
+    /// VAT included in the items' prices; already part of `total`
+    pub tax_total: f64,
+    pub shipping_total: f64,
+    /// `subtotal` - `discount_total` + `shipping_total`
+    pub total: f64,
+    /// None for orders placed without a payment
+    pub payment: Option<ReceiptPayment>,
+}
+
+impl OrderReceipt {
+    /// Recompute the totals from `items`.
+    pub fn update_totals(&mut self) {
+        let sum = |amount: fn(&ReceiptLine) -> f64| round_cents(self.items.iter().map(amount).sum());
+        self.subtotal = sum(|item| item.subtotal);
+        self.discount_total = sum(|item| item.discount);
+        self.tax_total = sum(|item| item.tax);
+        self.shipping_total = sum(|item| item.shipping_fee);
+        self.total = round_cents(self.subtotal - self.discount_total + self.shipping_total);
+    }
+}
+
+/// Round a KSh amount to whole cents.
+pub fn round_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+/// Data printed on an order invoice (see `invoice::render_pdf`)
+#[derive(Serialize, Deserialize)]
+pub struct OrderInvoice {
+    pub order_id: i32,
+    pub issued_on: String,
+    pub shipping_status: String,
+    pub product_name: String,
+    pub quantity: i32,
+    pub items_total: f64,
+    /// VAT included in `items_total`
+    pub tax_amount: f64,
+    pub shipping_fee: f64,
+    pub shipping_address: Option<String>,
+    pub customer_id: i32,
+    pub customer_username: String,
+    pub customer_email: String,
+    pub customer_location: Option<String>,
+    pub vendor_id: i32,
+    pub vendor_username: String,
+    pub vendor_email: String,
+    pub vendor_location: Option<String>,
+    pub mpesa_receipt_number: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -229,6 +640,22 @@ pub struct UpdateShippingStatusRequest {
     pub tracking_number: Option<String>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct BulkShippingStatusRequest {
+    pub order_ids: Vec<i32>,
+    pub shipping_status: String,
+    /// Skip orders that belong to other vendors instead of rejecting the batch
+    #[serde(default)]
+    pub skip_foreign: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BulkShippingStatusResult {
+    pub order_id: i32,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct VerifyDeliveryRequest {
     pub order_id: i32,
@@ -249,8 +676,7 @@ pub struct WithdrawResponse {
     pub new_balance: f64,
 }
 
-// JWT utilities
-const JWT_SECRET: &str = "your-secret-key"; // In production, use env var
+// JWT utilities; signing keys are configured in `jwt`
 
 impl Claims {
     pub fn new(user: &User) -> Self {
@@ -268,6 +694,8 @@ impl Claims {
                 Role::Vendor => "Vendor".to_string(),
             },
             exp: expiration,
+            impersonated_by: None,
+            jti: None,
         }
     }
 }
@@ -297,6 +725,10 @@ pub struct VendorVerification {
     pub profile_image: Option<String>,
     pub mpesa_number: Option<String>,
     pub payment_preference: Option<String>,
+    /// A new document sent after a rejection, not a first submission
+    pub is_resubmission: bool,
+    /// When the current document was uploaded
+    pub submitted_at: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -308,9 +740,13 @@ pub struct PaymentTransaction {
     pub mpesa_receipt_number: Option<String>,
     pub phone_number: String,
     pub amount: f64,
-    pub status: String, // initiated, completed, failed, cancelled
+    pub status: crate::mpesa::PaymentStatus,
     pub transaction_date: Option<String>,
     pub cart_item_ids: Option<String>, // Comma-separated cart item IDs
+    /// X-Request-Id of the checkout that started it
+    pub request_id: Option<String>,
+    /// For a resent STK prompt, the checkout request id of the attempt's first prompt
+    pub attempt_id: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -320,7 +756,180 @@ pub struct VendorSalesReport {
     pub total_sales: f64,
     pub total_orders: i32,
     pub total_profit: f64, // Assuming profit = total_sales for now
+    /// VAT included in `total_sales`
+    pub total_tax: f64,
     pub sales_by_product: Vec<ProductSales>,
+    /// Product views over the last 30 days
+    pub total_views: i64,
+    pub views_by_day: Vec<DailyViews>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DailyViews {
+    pub date: String,
+    pub views: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProductAnalytics {
+    pub product_id: i32,
+    pub product_name: String,
+    pub total_views: i64,
+    pub views_last_7_days: i64,
+    pub views_last_30_days: i64,
+    pub quantity_sold: i64,
+}
+
+/// Stock and turnover of one product in a vendor's inventory report.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InventoryReportItem {
+    pub product_id: i32,
+    pub product_name: String,
+    pub stock: i32,
+    /// Units ordered (excluding cancellations) within the report window
+    pub units_sold: i64,
+    /// At the window's average daily sales; None without sales in the window
+    pub days_of_stock_remaining: Option<f64>,
+    pub last_sold_at: Option<String>,
+    /// No sales within the stale threshold
+    pub stale: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InventoryReport {
+    pub window_days: i32,
+    pub stale_after_days: i32,
+    pub products: Vec<InventoryReportItem>,
+}
+
+/// Rows returned by the admin SQL console.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AdminQueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// More rows matched than were returned
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProductImageRequest {
+    /// An http(s) URL or a base64 image data URL
+    pub image: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReorderProductImagesRequest {
+    /// Every gallery image id of the product, in the new order
+    pub image_ids: Vec<i32>,
+}
+
+/// A product ranked by GET /products/trending, with the activity behind its score
+#[derive(Serialize, Clone)]
+pub struct TrendingProduct {
+    #[serde(flatten)]
+    pub product: Product,
+    pub recent_views: i64,
+    pub recent_orders: i64,
+    pub recent_wishlist_adds: i64,
+    /// All-time number of wishlists the product is on
+    pub favorites: i64,
+    pub score: f64,
+}
+
+/// What GET /vendors/leaderboard ranks vendors by (`sort`)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardSort {
+    #[default]
+    Revenue,
+    Orders,
+    /// Average rating of reviews left in the window
+    Rating,
+}
+
+impl std::str::FromStr for LeaderboardSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "revenue" => Ok(LeaderboardSort::Revenue),
+            "orders" => Ok(LeaderboardSort::Orders),
+            "rating" => Ok(LeaderboardSort::Rating),
+            other => Err(format!("Unknown leaderboard sort: {}", other)),
+        }
+    }
+}
+
+/// A vendor's place on GET /vendors/leaderboard, with their figures for the window
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VendorLeaderboardEntry {
+    /// 1-based position in the whole ranking, not just the page
+    pub rank: i64,
+    pub vendor_id: i32,
+    pub username: String,
+    pub revenue: f64,
+    pub orders: i64,
+    /// None when no reviews were left in the window
+    pub average_rating: Option<f64>,
+    pub review_count: i64,
+}
+
+/// The vendor behind a product, as shown on its product page
+#[derive(Serialize, Clone)]
+pub struct ProductVendorSummary {
+    pub id: i32,
+    pub username: String,
+    pub location: Option<String>,
+}
+
+/// A product in the GET /products listing, with whether any is left to buy
+#[derive(Serialize, Clone)]
+pub struct ProductListing {
+    #[serde(flatten)]
+    pub product: Product,
+    pub in_stock: bool,
+}
+
+/// A recently listed product, as returned by GET /products/new
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NewArrival {
+    #[serde(flatten)]
+    pub product: Product,
+    /// When the product was listed
+    pub created_at: chrono::DateTime<Utc>,
+}
+
+/// A single product as returned by GET /products/{id}
+#[derive(Serialize, Clone)]
+pub struct ProductDetail {
+    #[serde(flatten)]
+    pub product: Product,
+    pub vendor: ProductVendorSummary,
+    pub average_rating: Option<f64>,
+    pub review_count: i64,
+    pub in_stock: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CompareProductsRequest {
+    pub ids: Vec<i32>,
+}
+
+/// One column of a product comparison
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProductComparison {
+    pub product_id: i32,
+    pub name: String,
+    pub price: f64,
+    pub category: String,
+    pub stock: i32,
+    pub vendor_id: i32,
+    pub vendor_name: String,
+    /// None until the product has been reviewed
+    pub average_rating: Option<f64>,
+    pub review_count: i64,
+    /// From the signed-in customer to the vendor, when both have coordinates
+    pub distance_km: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -354,14 +963,273 @@ pub struct VendorPurchase {
     pub order_count: i32,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct CustomerDashboard {
+    pub active_orders: i32,
+    pub total_spent: f64,
+    pub total_orders: i32,
+    pub vendors_followed: i32,
+    pub unread_messages: i32,
+    pub wishlist_size: i32,
+    pub recent_orders: Vec<ShippingOrder>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FeatureProductRequest {
+    pub featured: bool,
+    /// Length of the promotion; required for vendors, optional (indefinite) for admins
+    pub days: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Appeal {
+    pub id: i32,
+    pub vendor_id: i32,
+    pub vendor_username: String,
+    pub message: String,
+    pub status: String,
+    pub admin_notes: Option<String>,
+    pub created_at: String,
+    pub resolved_at: Option<String>,
+}
+
+/// An M-Pesa callback that was acknowledged but couldn't be applied
+#[derive(Serialize, Deserialize)]
+pub struct CallbackFailure {
+    pub id: i32,
+    pub checkout_request_id: String,
+    /// The callback body as received, JSON-encoded
+    pub payload: String,
+    /// What went wrong the last time it was processed
+    pub error: String,
+    pub retry_count: i32,
+    pub created_at: String,
+    pub last_retried_at: Option<String>,
+    pub resolved_at: Option<String>,
+}
+
+/// An email in the retry queue
+#[derive(Serialize, Deserialize)]
+pub struct QueuedEmail {
+    pub id: i32,
+    pub recipient: String,
+    pub subject: String,
+    /// 'pending', 'sent', 'failed' or 'expired'
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: String,
+    pub next_attempt_at: String,
+    pub created_at: String,
+    pub sent_at: Option<String>,
+}
+
+/// Banned vendors can't log in, so they may authenticate with credentials instead of a token
+#[derive(Serialize, Deserialize)]
+pub struct AppealRequest {
+    pub message: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResolveAppealRequest {
+    pub approved: bool,
+    pub admin_notes: Option<String>,
+}
+
+/// How a shipping option prices delivery
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShippingMethod {
+    /// Always `fee`
+    Flat,
+    /// `fee`, waived when the vendor subtotal reaches `free_threshold`
+    FreeOver,
+    /// `fee` plus `per_km_rate` for each km between vendor and customer
+    PerKm,
+}
+
+impl ShippingMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShippingMethod::Flat => "flat",
+            ShippingMethod::FreeOver => "free_over",
+            ShippingMethod::PerKm => "per_km",
+        }
+    }
+}
+
+impl std::str::FromStr for ShippingMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(ShippingMethod::Flat),
+            "free_over" => Ok(ShippingMethod::FreeOver),
+            "per_km" => Ok(ShippingMethod::PerKm),
+            other => Err(format!("Unknown shipping method: {}", other)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ShippingOption {
+    pub id: i32,
+    pub vendor_id: i32,
+    pub name: String,
+    pub method: ShippingMethod,
+    pub fee: f64,
+    pub free_threshold: Option<f64>,
+    pub per_km_rate: Option<f64>,
+}
+
+impl ShippingOption {
+    /// Delivery cost for a vendor subtotal, or `None` when a per-km option
+    /// has no distance to work with.
+    pub fn cost(&self, subtotal: f64, distance_km: Option<f64>) -> Option<f64> {
+        let cost = match self.method {
+            ShippingMethod::Flat => self.fee,
+            ShippingMethod::FreeOver => match self.free_threshold {
+                Some(threshold) if subtotal >= threshold => 0.0,
+                _ => self.fee,
+            },
+            ShippingMethod::PerKm => self.fee + self.per_km_rate.unwrap_or(0.0) * distance_km?,
+        };
+        Some((cost * 100.0).round() / 100.0)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShippingOptionRequest {
+    pub name: String,
+    pub method: ShippingMethod,
+    #[serde(default)]
+    pub fee: f64,
+    pub free_threshold: Option<f64>,
+    pub per_km_rate: Option<f64>,
+}
+
+/// A vendor's percentage discount code. It applies only to that vendor's products.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Coupon {
+    pub id: i32,
+    pub vendor_id: i32,
+    pub code: String,
+    pub percent_off: f64,
+    /// Checkouts the code can be used on; unlimited when `None`
+    pub max_uses: Option<i32>,
+    pub times_used: i32,
+    pub expires_at: Option<chrono::DateTime<Utc>>,
+    pub active: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CouponRequest {
+    pub code: String,
+    pub percent_off: f64,
+    pub max_uses: Option<i32>,
+    pub expires_at: Option<chrono::DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub product_count: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WishlistRequest {
+    pub product_id: i32,
+}
+
 pub fn create_jwt(user: &User) -> Result<String, Error> {
     let claims = Claims::new(user);
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(JWT_SECRET.as_ref()))
+    crate::jwt::keys().encode(&claims)
+}
+
+/// Short-lived token that acts as `user` on behalf of `admin_id`, tied to an
+/// impersonation session so it can be revoked before it expires.
+pub fn create_impersonation_jwt(user: &User, admin_id: i32, session_id: &str, expires_at: chrono::DateTime<Utc>) -> Result<String, Error> {
+    let claims = Claims {
+        exp: expires_at.timestamp() as usize,
+        impersonated_by: Some(admin_id),
+        jti: Some(session_id.to_string()),
+        ..Claims::new(user)
+    };
+    crate::jwt::keys().encode(&claims)
+}
+
+/// Days a refresh token (and so a session) stays usable without being refreshed.
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+/// A new random refresh token; only its `hash_refresh_token` is stored.
+pub fn generate_refresh_token() -> String {
+    format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+/// SHA-256 of a refresh token, as kept in the sessions table.
+pub fn hash_refresh_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(token.as_bytes()))
 }
 
 pub fn verify_jwt(token: &str) -> Result<Claims, Error> {
-    decode::<Claims>(token, &DecodingKey::from_secret(JWT_SECRET.as_ref()), &Validation::default())
-        .map(|data| data.claims)
+    crate::jwt::keys().decode(token)
+}
+
+/// Filters for the admin order search; unset fields match every order.
+/// `created_before` is exclusive.
+#[derive(Default)]
+pub struct OrderSearchFilter {
+    pub customer_id: Option<i32>,
+    pub vendor_id: Option<i32>,
+    pub status: Option<String>,
+    pub created_from: Option<chrono::NaiveDate>,
+    pub created_before: Option<chrono::NaiveDate>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    pub id: i32,
+    pub actor_id: Option<i32>,
+    pub impersonated_by: Option<i32>,
+    pub action: String,
+    pub details: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AnnouncementRequest {
+    pub title: String,
+    pub body: String,
+    /// Also email followers who haven't opted out of email notifications
+    #[serde(default)]
+    pub send_email: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Announcement {
+    pub id: i32,
+    pub vendor_id: i32,
+    pub vendor_username: String,
+    pub title: String,
+    pub body: String,
+    pub created_at: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Notification {
+    pub id: i32,
+    /// What the notification is about, e.g. "announcement"
+    pub kind: String,
+    pub title: String,
+    pub body: String,
+    /// Id of the related record, e.g. "announcement:12"
+    pub reference: Option<String>,
+    pub read: bool,
+    pub created_at: String,
 }
 
 // Password Reset Models
@@ -0,0 +1,64 @@
+//! Moderation of product listings. New and edited product names and
+//! descriptions are classified by the AI model; listings it flags are held
+//! for admin review instead of being published. The check fails open: if the
+//! model isn't configured or can't be reached, the listing goes live.
+
+use crate::gemini;
+use std::time::Duration;
+
+/// How long a listing may wait on the classifier before it is let through.
+const CLASSIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of checking a listing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allow,
+    /// Held for review, with the classifier's reason
+    Flag(String),
+}
+
+/// Instructions for the classifier; the listing follows them.
+const PROMPT: &str = "You moderate listings on a Kenyan farmers' market. Prohibited items include weapons, \
+ammunition, drugs and drug paraphernalia, alcohol sold without a licence, tobacco, live wild or endangered \
+animals and their products (ivory, bushmeat), stolen goods, counterfeit items, pesticides banned in Kenya, \
+and anything unrelated to farming or food that is illegal to sell. Reply with exactly one line: ALLOW if \
+the listing is acceptable, or FLAG: followed by a short reason if it is not.";
+
+fn prompt(name: &str, category: &str, description: &str) -> String {
+    format!(
+        "{}\n\nName: {}\nCategory: {}\nDescription: {}",
+        PROMPT, name, category, description
+    )
+}
+
+/// Read the classifier's reply. Anything that isn't a clear ALLOW or FLAG is None.
+pub fn parse_verdict(reply: &str) -> Option<Verdict> {
+    let reply = reply.trim().trim_matches('`').trim();
+    let upper = reply.to_ascii_uppercase();
+    if upper.starts_with("ALLOW") {
+        return Some(Verdict::Allow);
+    }
+    if upper.starts_with("FLAG") {
+        let reason = reply[4..].trim_start_matches([':', '-', ' ']).lines().next().unwrap_or("").trim();
+        let reason = if reason.is_empty() { "Flagged by automated moderation" } else { reason };
+        return Some(Verdict::Flag(reason.to_string()));
+    }
+    None
+}
+
+/// Classify a listing, allowing it whenever the classifier can't give an answer.
+pub async fn check_listing(name: &str, category: &str, description: &str) -> Verdict {
+    if !gemini::is_configured() {
+        return Verdict::Allow;
+    }
+    match gemini::generate(&prompt(name, category, description), CLASSIFY_TIMEOUT).await {
+        Ok(reply) => parse_verdict(&reply).unwrap_or_else(|| {
+            eprintln!("⚠️ Unclear moderation reply, allowing listing: {}", reply);
+            Verdict::Allow
+        }),
+        Err(e) => {
+            eprintln!("⚠️ Moderation unavailable, allowing listing: {}", e);
+            Verdict::Allow
+        }
+    }
+}
@@ -5,9 +5,6 @@
 use base64::Engine;
 use serde::{Deserialize, Serialize};
 use std::env;
-use reqwest;
-use base64;
-use chrono;
 
 // M-Pesa API Configuration
 #[derive(Clone)]
@@ -18,6 +15,65 @@ pub struct MpesaConfig {
     pub passkey: String,
     pub callback_url: String,
     pub environment: MpesaEnvironment,
+    /// Paybill or Buy Goods (MPESA_TRANSACTION_TYPE)
+    pub transaction_type: TransactionType,
+    /// Till that receives Buy Goods payments (MPESA_TILL_NUMBER); `shortcode` is then
+    /// the store number the till belongs to
+    pub till_number: Option<String>,
+    /// Overrides the Safaricom host (MPESA_BASE_URL), e.g. for a local mock.
+    pub base_url_override: Option<String>,
+    pub retry: RetryPolicy,
+}
+
+/// Retry behaviour for calls to the Daraja API.
+/// Configure via MPESA_RETRY_MAX_ATTEMPTS, MPESA_RETRY_BASE_DELAY_MS and MPESA_RETRY_MAX_TOTAL_MS.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled on each further retry.
+    pub base_delay: std::time::Duration,
+    /// Upper bound on any single delay (including Retry-After).
+    pub max_delay: std::time::Duration,
+    /// Give up once waiting again would exceed this much time since the first attempt.
+    pub max_total: std::time::Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_millis(500),
+            max_delay: std::time::Duration::from_secs(4),
+            max_total: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Load the retry policy from environment variables, falling back to defaults
+    pub fn from_env() -> Self {
+        let defaults = RetryPolicy::default();
+        let millis = |name: &str| env::var(name).ok().and_then(|v| v.parse::<u64>().ok()).map(std::time::Duration::from_millis);
+
+        RetryPolicy {
+            max_attempts: env::var("MPESA_RETRY_MAX_ATTEMPTS").ok().and_then(|v| v.parse().ok()).unwrap_or(defaults.max_attempts).max(1),
+            base_delay: millis("MPESA_RETRY_BASE_DELAY_MS").unwrap_or(defaults.base_delay),
+            max_delay: defaults.max_delay,
+            max_total: millis("MPESA_RETRY_MAX_TOTAL_MS").unwrap_or(defaults.max_total),
+        }
+    }
+
+    /// Backoff before retry number `retry` (1-based): exponential with up to 50% jitter,
+    /// or the server's Retry-After when given, capped at `max_delay`.
+    fn delay_for(&self, retry: u32, retry_after: Option<std::time::Duration>) -> std::time::Duration {
+        let delay = retry_after.unwrap_or_else(|| {
+            let exponential = self.base_delay.saturating_mul(1 << (retry - 1).min(16));
+            let jitter = exponential.mul_f64(rand::random::<f64>() * 0.5);
+            exponential + jitter
+        });
+        delay.min(self.max_delay)
+    }
 }
 
 #[derive(Clone)]
@@ -26,6 +82,37 @@ pub enum MpesaEnvironment {
     Production,
 }
 
+/// How customers pay the business: into a Paybill or a Buy Goods till.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransactionType {
+    PayBill,
+    BuyGoods,
+}
+
+impl TransactionType {
+    /// Parse MPESA_TRANSACTION_TYPE: `paybill`/`till`, or the Daraja names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "paybill" | "customerpaybillonline" => Some(TransactionType::PayBill),
+            "till" | "buygoods" | "customerbuygoodsonline" => Some(TransactionType::BuyGoods),
+            _ => None,
+        }
+    }
+
+    /// The `TransactionType` value sent to Daraja.
+    pub fn api_name(&self) -> &'static str {
+        match self {
+            TransactionType::PayBill => "CustomerPayBillOnline",
+            TransactionType::BuyGoods => "CustomerBuyGoodsOnline",
+        }
+    }
+}
+
+/// Whether `code` looks like an M-Pesa shortcode or till number (5-7 digits).
+fn is_valid_shortcode(code: &str) -> bool {
+    (5..=7).contains(&code.len()) && code.chars().all(|c| c.is_ascii_digit())
+}
+
 impl MpesaConfig {
     /// Load M-Pesa configuration from environment variables
     pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
@@ -44,18 +131,62 @@ impl MpesaConfig {
             _ => MpesaEnvironment::Sandbox,
         };
 
-        Ok(MpesaConfig {
+        let transaction_type = match env::var("MPESA_TRANSACTION_TYPE") {
+            Ok(value) => TransactionType::parse(&value)
+                .ok_or_else(|| format!("MPESA_TRANSACTION_TYPE must be paybill or till, got {:?}", value))?,
+            Err(_) => TransactionType::PayBill,
+        };
+        let till_number = env::var("MPESA_TILL_NUMBER").ok().filter(|till| !till.is_empty());
+
+        let base_url_override = env::var("MPESA_BASE_URL").ok().filter(|url| !url.is_empty());
+
+        let config = MpesaConfig {
             consumer_key,
             consumer_secret,
             shortcode,
             passkey,
             callback_url,
             environment,
-        })
+            transaction_type,
+            till_number,
+            base_url_override,
+            retry: RetryPolicy::from_env(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check the shortcodes suit the transaction type: Buy Goods needs a till
+    /// number, and a till number with Paybill is a misconfiguration.
+    pub fn validate(&self) -> Result<(), String> {
+        if !is_valid_shortcode(&self.shortcode) {
+            return Err(format!("MPESA_SHORTCODE must be 5-7 digits, got {:?}", self.shortcode));
+        }
+        match (self.transaction_type, &self.till_number) {
+            (TransactionType::BuyGoods, None) => Err("MPESA_TILL_NUMBER must be set when MPESA_TRANSACTION_TYPE is till".to_string()),
+            (TransactionType::BuyGoods, Some(till)) if !is_valid_shortcode(till) => {
+                Err(format!("MPESA_TILL_NUMBER must be 5-7 digits, got {:?}", till))
+            }
+            (TransactionType::PayBill, Some(_)) => {
+                Err("MPESA_TILL_NUMBER is only used with MPESA_TRANSACTION_TYPE=till".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Who receives the payment: the till for Buy Goods, otherwise the Paybill shortcode.
+    pub fn party_b(&self) -> &str {
+        match (self.transaction_type, &self.till_number) {
+            (TransactionType::BuyGoods, Some(till)) => till,
+            _ => &self.shortcode,
+        }
     }
 
     /// Get the base URL for M-Pesa API based on environment
     pub fn base_url(&self) -> &str {
+        if let Some(url) = &self.base_url_override {
+            return url.trim_end_matches('/');
+        }
         match self.environment {
             MpesaEnvironment::Sandbox => "https://sandbox.safaricom.co.ke",
             MpesaEnvironment::Production => "https://api.safaricom.co.ke",
@@ -83,7 +214,7 @@ pub struct StkPushRequest {
     pub transaction_type: String,
     pub amount: String,
     pub party_a: String, // Phone number paying
-    pub party_b: String, // Organization shortcode
+    pub party_b: String, // Paybill shortcode or till number
     pub phone_number: String,
     pub call_back_u_r_l: String,
     pub account_reference: String,
@@ -100,13 +231,31 @@ pub struct StkPushResponse {
     pub customer_message: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StkQueryRequest {
+    pub business_short_code: String,
+    pub password: String,
+    pub timestamp: String,
+    pub checkout_request_i_d: String,
+}
+
+/// Outcome of an earlier STK push. `result_code` "0" means the customer paid;
+/// a prompt still open on the phone is reported as `MpesaError::Duplicate`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StkQueryResponse {
+    pub result_code: String,
+    pub result_desc: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct StkCallbackBody {
     pub stk_callback: StkCallback,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct StkCallback {
     pub merchant_request_i_d: String,
@@ -116,13 +265,13 @@ pub struct StkCallback {
     pub callback_metadata: Option<CallbackMetadata>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct CallbackMetadata {
     pub item: Vec<CallbackItem>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct CallbackItem {
     pub name: String,
@@ -130,12 +279,16 @@ pub struct CallbackItem {
 }
 
 // Payment Status for our database
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum PaymentStatus {
     Initiated,
     Completed,
     Failed,
     Cancelled,
+    /// Any value not written by this code; never treated as paid or pending.
+    #[serde(other)]
+    Unknown,
 }
 
 impl std::fmt::Display for PaymentStatus {
@@ -145,6 +298,109 @@ impl std::fmt::Display for PaymentStatus {
             PaymentStatus::Completed => write!(f, "completed"),
             PaymentStatus::Failed => write!(f, "failed"),
             PaymentStatus::Cancelled => write!(f, "cancelled"),
+            PaymentStatus::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl std::str::FromStr for PaymentStatus {
+    type Err = std::convert::Infallible;
+
+    /// Parse a stored status; unrecognized values become `Unknown`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "initiated" => PaymentStatus::Initiated,
+            "completed" => PaymentStatus::Completed,
+            "failed" => PaymentStatus::Failed,
+            "cancelled" => PaymentStatus::Cancelled,
+            _ => PaymentStatus::Unknown,
+        })
+    }
+}
+
+/// Error type for M-Pesa API calls
+#[derive(Debug)]
+pub enum MpesaError {
+    /// Credentials rejected or access token invalid/expired
+    Auth(String),
+    /// Connection failure or unreadable response
+    Network(String),
+    InsufficientFunds,
+    InvalidPhone,
+    /// A request is already being processed for this subscriber
+    Duplicate,
+    /// Request or customer confirmation timed out
+    Timeout,
+    /// Any other Daraja error code (or HTTP status when no code was given)
+    Api { code: String, desc: String },
+}
+
+impl std::fmt::Display for MpesaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MpesaError::Auth(msg) => write!(f, "M-Pesa authentication failed: {}", msg),
+            MpesaError::Network(msg) => write!(f, "M-Pesa network error: {}", msg),
+            MpesaError::InsufficientFunds => write!(f, "Insufficient M-Pesa balance"),
+            MpesaError::InvalidPhone => write!(f, "Invalid M-Pesa phone number"),
+            MpesaError::Duplicate => write!(f, "A payment request is already in progress"),
+            MpesaError::Timeout => write!(f, "M-Pesa request timed out"),
+            MpesaError::Api { code, desc } => write!(f, "M-Pesa API error {}: {}", code, desc),
+        }
+    }
+}
+
+impl std::error::Error for MpesaError {}
+
+impl From<reqwest::Error> for MpesaError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            MpesaError::Timeout
+        } else {
+            MpesaError::Network(err.to_string())
+        }
+    }
+}
+
+/// Daraja error body, e.g. `{"requestId": "...", "errorCode": "400.002.02", "errorMessage": "..."}`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiErrorBody {
+    error_code: String,
+    error_message: String,
+}
+
+impl MpesaError {
+    /// Map a Safaricom error or result code to a variant.
+    pub fn from_api(code: &str, desc: &str) -> Self {
+        match code {
+            // STK result codes
+            "1" => MpesaError::InsufficientFunds,
+            "1001" => MpesaError::Duplicate,
+            "1019" | "1037" => MpesaError::Timeout,
+            "2001" => MpesaError::Auth(desc.to_string()),
+            // API error codes
+            "404.001.03" | "401.002.01" | "400.008.01" => MpesaError::Auth(desc.to_string()),
+            "500.001.1001" => MpesaError::Duplicate,
+            // "Invalid request payload" covers every field; only the phone is user-fixable
+            "400.002.02" if desc.contains("PhoneNumber") || desc.contains("PartyA") => MpesaError::InvalidPhone,
+            _ => MpesaError::Api { code: code.to_string(), desc: desc.to_string() },
+        }
+    }
+
+    /// Build an error from a non-success HTTP response body.
+    fn from_response(status: reqwest::StatusCode, body: &str) -> Self {
+        match serde_json::from_str::<ApiErrorBody>(body) {
+            Ok(err) => MpesaError::from_api(&err.error_code, &err.error_message),
+            Err(_) => MpesaError::Api { code: status.as_u16().to_string(), desc: body.to_string() },
+        }
+    }
+
+    /// Whether another attempt might succeed (transient transport or server-side failures).
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            MpesaError::Network(_) | MpesaError::Timeout => true,
+            MpesaError::Api { code, .. } => code == "429" || code.starts_with('5'),
+            _ => false,
         }
     }
 }
@@ -167,7 +423,7 @@ impl MpesaClient {
     }
 
     /// Get OAuth access token from M-Pesa API
-    pub async fn get_access_token(&self) -> Result<String, Box<dyn std::error::Error>> {
+    pub async fn get_access_token(&self) -> Result<String, MpesaError> {
         let auth_url = format!("{}/oauth/v1/generate?grant_type=client_credentials", self.config.base_url());
         
         // Create Basic Auth header
@@ -175,18 +431,75 @@ impl MpesaClient {
         let encoded_credentials = base64::engine::general_purpose::STANDARD.encode(credentials);
         let auth_header = format!("Basic {}", encoded_credentials);
 
-        let response = self.client
-            .get(&auth_url)
-            .header("Authorization", auth_header)
-            .send()
-            .await?;
+        let response = self.send_with_retry(|| {
+            self.client
+                .get(&auth_url)
+                .header("Authorization", auth_header.clone())
+        }, true).await?;
 
         if response.status().is_success() {
             let auth_response: AuthResponse = response.json().await?;
             Ok(auth_response.access_token)
         } else {
             let error_text = response.text().await?;
-            Err(format!("Failed to get access token: {}", error_text).into())
+            Err(MpesaError::Auth(format!("Failed to get access token: {}", error_text)))
+        }
+    }
+
+    /// Send a request, retrying retryable failures (network errors, timeouts,
+    /// 429 and 5xx without a non-retryable Daraja error code) according to the
+    /// configured `RetryPolicy`. Other responses, including 4xx, are returned
+    /// as-is for the caller to interpret.
+    ///
+    /// Requests that aren't `idempotent` (an STK push prompts the customer
+    /// again each time it's sent) are only retried when they can't have been
+    /// processed: the connection failed or Daraja answered 429. A timeout or
+    /// 5xx is returned straight away.
+    async fn send_with_retry<F>(&self, build: F, idempotent: bool) -> Result<reqwest::Response, MpesaError>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let policy = &self.config.retry;
+        let started = std::time::Instant::now();
+        let mut attempt = 1;
+
+        loop {
+            let (retry_after, last_error, unsent) = match build().send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    if !(status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS) {
+                        return Ok(response);
+                    }
+                    let retry_after = response
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.trim().parse::<u64>().ok())
+                        .map(std::time::Duration::from_secs);
+                    let body = response.text().await.unwrap_or_default();
+                    let unsent = status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+                    (retry_after, MpesaError::from_response(status, &body), unsent)
+                }
+                Err(e) => {
+                    let unsent = e.is_connect();
+                    (None, MpesaError::from(e), unsent)
+                }
+            };
+
+            if !last_error.is_retryable() || !(idempotent || unsent) || attempt >= policy.max_attempts {
+                return Err(last_error);
+            }
+            let delay = policy.delay_for(attempt, retry_after);
+            if started.elapsed() + delay > policy.max_total {
+                return Err(last_error);
+            }
+
+            eprintln!(
+                "[request {}] M-Pesa request failed (attempt {}/{}), retrying in {:?}: {}",
+                crate::request_id::current().unwrap_or_default(), attempt, policy.max_attempts, delay, last_error
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
     }
 
@@ -208,7 +521,7 @@ impl MpesaClient {
         amount: f64,
         account_reference: String,
         transaction_description: String,
-    ) -> Result<StkPushResponse, Box<dyn std::error::Error>> {
+    ) -> Result<StkPushResponse, MpesaError> {
         // Get access token
         let access_token = self.get_access_token().await?;
         
@@ -224,7 +537,7 @@ impl MpesaClient {
         } else if phone_number.starts_with("254") {
             phone_number
         } else {
-            return Err("Invalid phone number format".into());
+            return Err(MpesaError::InvalidPhone);
         };
 
         // Create STK push request
@@ -232,10 +545,10 @@ impl MpesaClient {
             business_short_code: self.config.shortcode.clone(),
             password,
             timestamp,
-            transaction_type: "CustomerPayBillOnline".to_string(),
+            transaction_type: self.config.transaction_type.api_name().to_string(),
             amount: amount.to_string(),
             party_a: formatted_phone.clone(),
-            party_b: self.config.shortcode.clone(),
+            party_b: self.config.party_b().to_string(),
             phone_number: formatted_phone,
             call_back_u_r_l: self.config.callback_url.clone(),
             account_reference,
@@ -245,20 +558,50 @@ impl MpesaClient {
         // Make API request
         let stk_url = format!("{}/mpesa/stkpush/v1/processrequest", self.config.base_url());
         
-        let response = self.client
-            .post(&stk_url)
-            .header("Authorization", format!("Bearer {}", access_token))
-            .header("Content-Type", "application/json")
-            .json(&stk_request)
-            .send()
-            .await?;
+        let response = self.send_with_retry(|| {
+            self.client
+                .post(&stk_url)
+                .header("Authorization", format!("Bearer {}", access_token))
+                .header("Content-Type", "application/json")
+                .json(&stk_request)
+        }, false).await?;
 
         if response.status().is_success() {
             let stk_response: StkPushResponse = response.json().await?;
             Ok(stk_response)
         } else {
+            let status = response.status();
+            let error_text = response.text().await?;
+            Err(MpesaError::from_response(status, &error_text))
+        }
+    }
+
+    /// Ask M-Pesa how an earlier STK push ended
+    pub async fn stk_query(&self, checkout_request_id: &str) -> Result<StkQueryResponse, MpesaError> {
+        let access_token = self.get_access_token().await?;
+        let timestamp = Self::generate_timestamp();
+        let query_request = StkQueryRequest {
+            business_short_code: self.config.shortcode.clone(),
+            password: self.generate_password(&timestamp),
+            timestamp,
+            checkout_request_i_d: checkout_request_id.to_string(),
+        };
+
+        let query_url = format!("{}/mpesa/stkpushquery/v1/query", self.config.base_url());
+        let response = self.send_with_retry(|| {
+            self.client
+                .post(&query_url)
+                .header("Authorization", format!("Bearer {}", access_token))
+                .header("Content-Type", "application/json")
+                .json(&query_request)
+        }, true).await?;
+
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            let status = response.status();
             let error_text = response.text().await?;
-            Err(format!("STK Push failed: {}", error_text).into())
+            Err(MpesaError::from_response(status, &error_text))
         }
     }
 }
@@ -291,3 +634,99 @@ pub fn extract_callback_data(callback: &StkCallback) -> Option<(String, String,
     }
     None
 }
+
+/// Safaricom's published Daraja callback source addresses.
+const SAFARICOM_CALLBACK_IPS: &str = "196.201.214.200,196.201.214.206,196.201.213.114,196.201.214.207,196.201.214.208,196.201.213.44,196.201.212.127,196.201.212.138,196.201.212.129,196.201.212.136,196.201.212.74,196.201.212.69";
+
+/// Decides whether an incoming STK callback really comes from Safaricom.
+/// Configure via MPESA_CALLBACK_ALLOWED_IPS (comma-separated IPs or IPv4 CIDRs,
+/// `*` to disable the IP check; defaults to Safaricom's ranges) and
+/// MPESA_CALLBACK_TOKEN (shared secret expected as `/mpesa/callback/{token}`).
+/// Set MPESA_CALLBACK_TRUST_PROXY=true when behind a reverse proxy so the
+/// X-Forwarded-For address is checked instead of the socket peer, and
+/// MPESA_CALLBACK_PROXY_HOPS to the number of proxies in front of the server
+/// (default 1).
+pub struct CallbackAuth {
+    allowed: Option<Vec<(std::net::IpAddr, u8)>>,
+    token: Option<String>,
+    trust_proxy: bool,
+    proxy_hops: usize,
+}
+
+impl CallbackAuth {
+    /// Load callback verification settings from environment variables
+    pub fn from_env() -> Self {
+        let allowlist = env::var("MPESA_CALLBACK_ALLOWED_IPS")
+            .unwrap_or_else(|_| SAFARICOM_CALLBACK_IPS.to_string());
+        let allowed = if allowlist.trim() == "*" {
+            None
+        } else {
+            Some(allowlist.split(',').filter_map(|entry| parse_ip_range(entry.trim())).collect())
+        };
+        let token = env::var("MPESA_CALLBACK_TOKEN").ok().filter(|t| !t.is_empty());
+        let trust_proxy = env::var("MPESA_CALLBACK_TRUST_PROXY").map(|v| v == "true").unwrap_or(false);
+        let proxy_hops = env::var("MPESA_CALLBACK_PROXY_HOPS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|hops| *hops > 0)
+            .unwrap_or(1);
+
+        CallbackAuth { allowed, token, trust_proxy, proxy_hops }
+    }
+
+    /// Address the callback came from. Behind trusted proxies that's the entry the
+    /// outermost one appended to X-Forwarded-For, counting `proxy_hops` from the
+    /// right; anything further left was sent by the client and can't be trusted.
+    /// Without a trusted proxy, or without the header, it's the socket peer.
+    pub fn source_ip(&self, peer: Option<std::net::IpAddr>, forwarded_for: Option<&str>) -> Option<std::net::IpAddr> {
+        match forwarded_for.filter(|_| self.trust_proxy) {
+            Some(header) => header.rsplit(',').nth(self.proxy_hops - 1)?.trim().parse().ok(),
+            None => peer,
+        }
+    }
+
+    /// Whether `ip` falls inside the configured allowlist.
+    pub fn is_allowed_ip(&self, ip: Option<std::net::IpAddr>) -> bool {
+        let Some(allowed) = &self.allowed else { return true };
+        let Some(ip) = ip else { return false };
+        allowed.iter().any(|(range, prefix)| ip_in_range(ip, *range, *prefix))
+    }
+
+    /// Verify both the source address and, when configured, the path token.
+    pub fn verify(&self, ip: Option<std::net::IpAddr>, token: Option<&str>) -> bool {
+        let token_ok = match &self.token {
+            Some(expected) => token.map(|t| constant_time_eq(t.as_bytes(), expected.as_bytes())).unwrap_or(false),
+            None => true,
+        };
+        token_ok && self.is_allowed_ip(ip)
+    }
+}
+
+fn parse_ip_range(entry: &str) -> Option<(std::net::IpAddr, u8)> {
+    match entry.split_once('/') {
+        Some((addr, prefix)) => Some((addr.parse().ok()?, prefix.parse().ok()?)),
+        None => {
+            let addr: std::net::IpAddr = entry.parse().ok()?;
+            Some((addr, if addr.is_ipv4() { 32 } else { 128 }))
+        }
+    }
+}
+
+fn ip_in_range(ip: std::net::IpAddr, range: std::net::IpAddr, prefix: u8) -> bool {
+    use std::net::IpAddr;
+    match (ip, range) {
+        (IpAddr::V4(ip), IpAddr::V4(range)) => {
+            let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix.min(32)) };
+            u32::from(ip) & mask == u32::from(range) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(range)) => {
+            let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix.min(128)) };
+            u128::from(ip) & mask == u128::from(range) & mask
+        }
+        _ => false,
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
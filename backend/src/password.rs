@@ -0,0 +1,139 @@
+//! Password policy shared by every path that sets a password. The minimum
+//! length and required character classes come from admin settings.
+
+use crate::settings;
+use serde::Serialize;
+use sqlx::PgPool;
+
+/// Characters that count as symbols for the `symbol` rule.
+pub const SYMBOLS: &str = "!@#$%^&*(),.?\":{}|<>";
+
+/// A password requirement, in the order they're checked.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordRule {
+    MinLength,
+    Uppercase,
+    Lowercase,
+    Digit,
+    Symbol,
+}
+
+/// The rules passwords are held to.
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            require_uppercase: true,
+            require_lowercase: true,
+            require_digit: true,
+            require_symbol: true,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// The policy configured in the settings table.
+    pub async fn from_settings(pool: &PgPool) -> Self {
+        PasswordPolicy {
+            min_length: settings::get_i64(pool, settings::PASSWORD_MIN_LENGTH).await.max(1) as usize,
+            require_uppercase: settings::get_bool(pool, settings::PASSWORD_REQUIRE_UPPERCASE).await,
+            require_lowercase: settings::get_bool(pool, settings::PASSWORD_REQUIRE_LOWERCASE).await,
+            require_digit: settings::get_bool(pool, settings::PASSWORD_REQUIRE_DIGIT).await,
+            require_symbol: settings::get_bool(pool, settings::PASSWORD_REQUIRE_SYMBOL).await,
+        }
+    }
+
+    /// Rules `password` doesn't meet, in check order.
+    pub fn unmet_rules(&self, password: &str) -> Vec<PasswordRule> {
+        let checks = [
+            (PasswordRule::MinLength, password.chars().count() >= self.min_length),
+            (PasswordRule::Uppercase, !self.require_uppercase || password.chars().any(|c| c.is_uppercase())),
+            (PasswordRule::Lowercase, !self.require_lowercase || password.chars().any(|c| c.is_lowercase())),
+            (PasswordRule::Digit, !self.require_digit || password.chars().any(|c| c.is_numeric())),
+            (PasswordRule::Symbol, !self.require_symbol || password.chars().any(|c| SYMBOLS.contains(c))),
+        ];
+        checks.into_iter().filter(|(_, met)| !met).map(|(rule, _)| rule).collect()
+    }
+
+    /// Message shown to users for an unmet rule.
+    pub fn message(&self, rule: PasswordRule) -> String {
+        match rule {
+            PasswordRule::MinLength => format!("Password must be at least {} characters", self.min_length),
+            PasswordRule::Uppercase => "Password must contain at least one uppercase letter".to_string(),
+            PasswordRule::Lowercase => "Password must contain at least one lowercase letter".to_string(),
+            PasswordRule::Digit => "Password must contain at least one number".to_string(),
+            PasswordRule::Symbol => "Password must contain at least one special character".to_string(),
+        }
+    }
+}
+
+/// Ok if `password` meets the policy, otherwise the message for the first unmet rule.
+pub fn validate_password(policy: &PasswordPolicy, password: &str) -> Result<(), String> {
+    match policy.unmet_rules(password).first() {
+        Some(rule) => Err(policy.message(*rule)),
+        None => Ok(()),
+    }
+}
+
+/// Rough strength from 0 (very weak) to 4 (strong): one point each for
+/// length, extra length, mixed case and a digit or symbol alongside letters.
+pub fn strength_score(password: &str) -> u8 {
+    let length = password.chars().count();
+    let has_upper = password.chars().any(|c| c.is_uppercase());
+    let has_lower = password.chars().any(|c| c.is_lowercase());
+    let has_digit = password.chars().any(|c| c.is_numeric());
+    let has_symbol = password.chars().any(|c| !c.is_alphanumeric());
+
+    let mut score = 0;
+    if length >= 8 {
+        score += 1;
+    }
+    if length >= 12 {
+        score += 1;
+    }
+    if has_upper && has_lower {
+        score += 1;
+    }
+    if (has_upper || has_lower) && has_digit && has_symbol {
+        score += 1;
+    }
+    // Short passwords are weak regardless of variety
+    if length < 8 {
+        score = score.min(1);
+    }
+    score
+}
+
+/// A random password that meets `policy`, for admin-initiated resets.
+pub fn generate_temporary_password(policy: &PasswordPolicy) -> String {
+    use rand::seq::SliceRandom;
+    use rand::Rng;
+
+    const LOWER: &[u8] = b"abcdefghijkmnpqrstuvwxyz";
+    const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+    const DIGITS: &[u8] = b"23456789";
+    let mut rng = rand::thread_rng();
+    // One of each class the policy may require, then fill up with letters and digits
+    let mut chars = vec![
+        *LOWER.choose(&mut rng).unwrap() as char,
+        *UPPER.choose(&mut rng).unwrap() as char,
+        *DIGITS.choose(&mut rng).unwrap() as char,
+        SYMBOLS.as_bytes()[rng.gen_range(0..SYMBOLS.len())] as char,
+    ];
+    let pool: Vec<u8> = [LOWER, UPPER, DIGITS].concat();
+    while chars.len() < policy.min_length.max(12) {
+        chars.push(*pool.choose(&mut rng).unwrap() as char);
+    }
+    chars.shuffle(&mut rng);
+    chars.into_iter().collect()
+}
@@ -0,0 +1,44 @@
+//! Vendor payout schedule. Vendors choose a `payment_preference`: "after_order"
+//! earnings are withdrawable as soon as the customer verifies delivery, while
+//! "monthly" earnings collect in `pending_balance` until the monthly sweep.
+
+use crate::db;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Accepted values for `users.payment_preference`.
+pub const PAYMENT_PREFERENCES: [&str; 2] = ["after_order", "monthly"];
+
+/// How often the background task checks whether this month's sweep has run.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Sweep pending balances once per calendar month (UTC). Returns `None` if this
+/// month was already swept, otherwise (vendors paid, amount moved). The month is
+/// claimed and swept in one transaction, so a failed sweep is retried next check.
+pub async fn run_monthly_payout(pool: &PgPool) -> Result<Option<(usize, f64)>, sqlx::Error> {
+    let period = chrono::Utc::now().format("%Y-%m").to_string();
+    let mut tx = pool.begin().await?;
+    if !db::claim_payout_period(&mut tx, &period).await? {
+        return Ok(None);
+    }
+    let swept = db::sweep_pending_balances(&mut tx).await?;
+    tx.commit().await?;
+    Ok(Some(swept))
+}
+
+/// Run `run_monthly_payout` periodically for the life of the process.
+pub fn spawn_payout_task(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match run_monthly_payout(&pool).await {
+                Ok(Some((vendors, amount))) => {
+                    println!("💸 Monthly payout: KSh {:.2} released to {} vendor(s)", amount, vendors)
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Monthly payout sweep failed: {:?}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,155 @@
+//! Live messaging over WebSocket. Each authenticated socket registers with the
+//! `ChatHub`, which fans out new messages and ephemeral presence frames
+//! (typing, delivered, read receipts) to every socket a user has open.
+//! Frames from a client are only relayed to users it may message, and only
+//! about messages it actually received.
+
+use crate::db;
+use crate::models::Message;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// Frames pushed from the server to a client socket.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// A new message addressed to this user
+    Message { message: Message },
+    /// `user_id` is typing a message to this user
+    Typing { user_id: i32 },
+    /// The recipient's socket received these messages
+    Delivered { user_id: i32, message_ids: Vec<i32> },
+    /// The recipient opened the conversation and read these messages
+    Read { user_id: i32, message_ids: Vec<i32> },
+}
+
+/// Control frames a client may send over its socket.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    /// Tell `to_user_id` that we're typing
+    Typing { to_user_id: i32 },
+    /// Acknowledge messages received from `sender_id`
+    Delivered { sender_id: i32, message_ids: Vec<i32> },
+}
+
+/// Open sockets for one user: (socket id, event sender).
+type UserSockets = Vec<(u64, UnboundedSender<ServerEvent>)>;
+
+/// Registry of open sockets by user id. Shared across workers via `web::Data`.
+#[derive(Default)]
+pub struct ChatHub {
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<i32, UserSockets>>,
+}
+
+/// A registered socket; events for its user arrive on `events`.
+pub struct Subscription {
+    pub id: u64,
+    pub user_id: i32,
+    pub events: UnboundedReceiver<ServerEvent>,
+}
+
+impl ChatHub {
+    pub fn subscribe(&self, user_id: i32) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = unbounded_channel();
+        self.connections
+            .lock()
+            .unwrap()
+            .entry(user_id)
+            .or_default()
+            .push((id, tx));
+        Subscription { id, user_id, events: rx }
+    }
+
+    pub fn unsubscribe(&self, subscription: &Subscription) {
+        let mut connections = self.connections.lock().unwrap();
+        if let Some(sockets) = connections.get_mut(&subscription.user_id) {
+            sockets.retain(|(id, _)| *id != subscription.id);
+            if sockets.is_empty() {
+                connections.remove(&subscription.user_id);
+            }
+        }
+    }
+
+    /// Push an event to all of a user's sockets. Users who are offline simply miss it.
+    pub fn send(&self, user_id: i32, event: ServerEvent) {
+        let mut connections = self.connections.lock().unwrap();
+        if let Some(sockets) = connections.get_mut(&user_id) {
+            sockets.retain(|(_, tx)| tx.send(event.clone()).is_ok());
+        }
+    }
+
+    /// Act on a frame from `user_id`'s socket. Typing goes nowhere if either user
+    /// blocked the other or the recipient wouldn't accept a message from `user_id`.
+    /// Delivery is recorded, and acknowledged to the sender, only for messages
+    /// `sender_id` sent `user_id` that weren't already marked delivered.
+    pub async fn handle_frame(&self, pool: &PgPool, user_id: i32, frame: ClientFrame) -> Result<(), sqlx::Error> {
+        match frame {
+            ClientFrame::Typing { to_user_id } => {
+                if !db::is_blocked_between(pool, user_id, to_user_id).await?
+                    && db::may_message(pool, user_id, to_user_id).await?
+                {
+                    self.send(to_user_id, ServerEvent::Typing { user_id });
+                }
+            }
+            ClientFrame::Delivered { sender_id, message_ids } => {
+                let message_ids = db::mark_messages_delivered(pool, user_id, sender_id, &message_ids).await?;
+                if !message_ids.is_empty() {
+                    self.send(sender_id, ServerEvent::Delivered { user_id, message_ids });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Drive one socket until either side closes it.
+pub async fn run_socket(
+    hub: actix_web::web::Data<ChatHub>,
+    pool: actix_web::web::Data<PgPool>,
+    mut subscription: Subscription,
+    mut session: actix_ws::Session,
+    mut stream: actix_ws::MessageStream,
+) {
+    loop {
+        tokio::select! {
+            event = subscription.events.recv() => {
+                let Some(event) = event else { break };
+                let Ok(json) = serde_json::to_string(&event) else { continue };
+                if session.text(json).await.is_err() {
+                    break;
+                }
+            }
+            frame = stream.recv() => {
+                match frame {
+                    Some(Ok(actix_ws::Message::Text(text))) => {
+                        match serde_json::from_str::<ClientFrame>(&text) {
+                            Ok(frame) => {
+                                if let Err(e) = hub.handle_frame(&pool, subscription.user_id, frame).await {
+                                    eprintln!("Failed to handle socket frame from user {}: {:?}", subscription.user_id, e);
+                                }
+                            }
+                            Err(_) => eprintln!("Ignoring malformed socket frame from user {}", subscription.user_id),
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+
+    hub.unsubscribe(&subscription);
+    let _ = session.close(None).await;
+}
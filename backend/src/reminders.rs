@@ -0,0 +1,56 @@
+//! Background reminders for customers who leave items in their cart.
+//! Carts untouched for `cart_reminder_hours` trigger at most one email per window.
+
+use crate::{db, email, settings};
+use crate::models::CartItem;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// How often the background task looks for abandoned carts.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Plain-text list of cart contents for the reminder email.
+pub fn cart_summary(items: &[CartItem]) -> String {
+    let mut lines: Vec<String> = items
+        .iter()
+        .map(|item| format!("- {} x {} (KES {:.2} each)", item.quantity, item.product.name, item.product.price))
+        .collect();
+    let total: f64 = items.iter().map(|item| item.product.price * item.quantity as f64).sum();
+    lines.push(format!("\nTotal: KES {:.2}", total));
+    lines.join("\n")
+}
+
+/// Email every owner of a newly stale cart once. Returns the number of carts reminded.
+/// Delivery failures are logged; the cart still counts as reminded so a broken
+/// mail server can't cause repeated sends once it recovers.
+pub async fn send_abandoned_cart_reminders(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let stale_hours = settings::get_i64(pool, settings::CART_REMINDER_HOURS).await.max(1) as i32;
+    let claimed = db::claim_abandoned_carts(pool, stale_hours).await?;
+
+    for (user_id, username, user_email) in &claimed {
+        let items = db::get_cart_items(pool, *user_id).await?;
+        if items.is_empty() {
+            continue;
+        }
+        if let Err(e) = email::send_abandoned_cart_email(pool, user_email, username, &cart_summary(&items)).await {
+            eprintln!("Failed to send cart reminder to user {}: {}", user_id, e);
+        }
+    }
+
+    Ok(claimed.len())
+}
+
+/// Run `send_abandoned_cart_reminders` periodically for the life of the process.
+pub fn spawn_cart_reminder_task(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match send_abandoned_cart_reminders(&pool).await {
+                Ok(0) => {}
+                Ok(count) => println!("🛒 Sent {} abandoned cart reminder(s)", count),
+                Err(e) => eprintln!("Abandoned cart reminder run failed: {:?}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,97 @@
+//! Request ids for correlating logs. Every request gets an `X-Request-Id`:
+//! the caller's if it sent a usable one, otherwise a new one. It is echoed on
+//! the response, added to JSON error objects, available to handlers while the
+//! request runs (`current`), and stored on payment transactions so an M-Pesa
+//! callback can be traced back to the checkout that started it.
+
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller-supplied id that is kept as is.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: String;
+}
+
+/// The request id, also stored in the request's extensions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+/// Id of the request being handled, if called while serving one.
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.clone()).ok()
+}
+
+/// Ids are passed on to logs and the database, so only accept short
+/// printable tokens from callers.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+fn request_id_for(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string())
+}
+
+/// Middleware: assign the request id, run the request with it as `current`,
+/// and put it on the response. Server errors are logged with the id.
+pub async fn request_id_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let request_id = request_id_for(&req);
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+    let (method, path) = (req.method().clone(), req.path().to_string());
+
+    let mut res = CURRENT.scope(request_id.clone(), next.call(req)).await?.map_into_boxed_body();
+    if res.status().is_server_error() {
+        eprintln!("[request {}] {} {} failed with {}", request_id, method, path, res.status());
+    }
+    if res.status().is_client_error() || res.status().is_server_error() {
+        res = with_request_id_in_body(res, &request_id).await?;
+    }
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(res)
+}
+
+/// Add `request_id` to a JSON object body. Other bodies are left untouched so
+/// clients expecting a plain message keep getting one.
+async fn with_request_id_in_body(res: ServiceResponse<BoxBody>, request_id: &str) -> Result<ServiceResponse<BoxBody>, Error> {
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    if !is_json {
+        return Ok(res);
+    }
+
+    let (req, res) = res.into_parts();
+    let (mut head, response_body) = res.into_parts();
+    let bytes = body::to_bytes(response_body).await.map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
+    let bytes = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut object)) => {
+            object.insert("request_id".to_string(), request_id.into());
+            // The length changes with the new field
+            head.headers_mut().remove(actix_web::http::header::CONTENT_LENGTH);
+            serde_json::to_vec(&object).map(Into::into).unwrap_or(bytes)
+        }
+        _ => bytes,
+    };
+    Ok(ServiceResponse::new(req, head.set_body(BoxBody::new(bytes))))
+}
@@ -0,0 +1,36 @@
+//! Stock reservations for checkouts awaiting payment. Checkout takes the
+//! items out of available stock for `stock_reservation_minutes`; a completed
+//! payment turns the hold into the sale, while a failed or cancelled one, or
+//! the hold running out, puts the stock back.
+
+use crate::{db, settings};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// How often the background task looks for expired reservations.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Minutes a new reservation is held for.
+pub async fn hold_minutes(pool: &PgPool) -> i64 {
+    settings::get_i64(pool, settings::STOCK_RESERVATION_MINUTES).await.max(1)
+}
+
+/// Put back the stock of every expired reservation. Returns the number released.
+pub async fn release_expired_reservations(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    db::release_expired_stock_reservations(pool).await
+}
+
+/// Run `release_expired_reservations` periodically for the life of the process.
+pub fn spawn_reservation_task(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match release_expired_reservations(&pool).await {
+                Ok(0) => {}
+                Ok(count) => println!("📦 Released {} expired stock reservation(s)", count),
+                Err(e) => eprintln!("Stock reservation release failed: {:?}", e),
+            }
+        }
+    });
+}
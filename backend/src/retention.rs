@@ -0,0 +1,32 @@
+//! Retention of vendor verification documents. Once a vendor is approved their
+//! ID or license is only kept for `verification_document_retention_days`,
+//! unless an admin marks it to be retained.
+
+use crate::{db, settings};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// How often the background task looks for expired documents.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Purge every verification document past its retention window. Returns the
+/// number of documents removed.
+pub async fn purge_expired_verification_documents(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let retention_days = settings::get_i64(pool, settings::VERIFICATION_DOCUMENT_RETENTION_DAYS).await.max(0) as i32;
+    db::purge_verification_documents(pool, retention_days).await.map(|purged| purged.len())
+}
+
+/// Run `purge_expired_verification_documents` periodically for the life of the process.
+pub fn spawn_retention_task(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge_expired_verification_documents(&pool).await {
+                Ok(0) => {}
+                Ok(count) => println!("🗑️ Purged {} verification document(s) past retention", count),
+                Err(e) => eprintln!("Verification document purge failed: {:?}", e),
+            }
+        }
+    });
+}
@@ -3,17 +3,44 @@
 
 use actix_web::{get, post, patch, put, delete, web, HttpResponse, Result as ActixResult};
 use sqlx::{PgPool, Row};
-use crate::models::{LoginRequest, SignupRequest, ProductRequest, Role, LoginResponse, create_jwt, verify_jwt, Claims, CartItemRequest, UpdateCartItemRequest, UpdateUserRoleRequest, UpdateUserVerificationRequest, UploadVerificationDocumentRequest, CheckoutRequest, CheckoutResponse, SendMessageRequest, FollowRequest, CreateReviewRequest, CreateShippingOrderRequest, UpdateShippingStatusRequest, VerifyDeliveryRequest, WithdrawRequest, WithdrawResponse, PasswordResetRequest, PasswordResetVerifyRequest, PasswordResetResponse};
+use crate::models::{ApiSuccess, create_impersonation_jwt, generate_refresh_token, hash_refresh_token, LoginRequest, RefreshRequest, REFRESH_TOKEN_TTL_DAYS, SignupRequest, ProductRequest, ProductAttributes, ProductListing, Role, LoginResponse, create_jwt, verify_jwt, Claims, CartItemRequest, CartBatchRequest, CartBatchResult, UpdateCartItemRequest, UpdateUserRoleRequest, UpdateUserVerificationRequest, BulkVerificationRequest, BulkVerificationResult, VerificationDocumentRetentionRequest, UploadVerificationDocumentRequest, CheckoutRequest, CheckoutResponse, SendMessageRequest, FollowRequest, CreateReviewRequest, ReviewReplyRequest, ReviewVoteRequest, CreateShippingOrderRequest, UpdateShippingStatusRequest, BulkShippingStatusRequest, BulkShippingStatusResult, VerifyDeliveryRequest, WithdrawRequest, WithdrawResponse, PasswordResetRequest, PasswordResetVerifyRequest, PasswordResetResponse, WishlistRequest, FeatureProductRequest, CompareProductsRequest, ProductImageRequest, ReorderProductImagesRequest, AnnouncementRequest, AppealRequest, ResolveAppealRequest, ShippingMethod, ShippingOptionRequest, CouponRequest, Paginated, ReviewPage, ReviewSort, AdminProductUpdate};
+use crate::acceptance;
+use crate::admin_query;
+use crate::audit;
+use crate::coupons;
+use crate::currency;
+use crate::data_export;
 use crate::db;
+use crate::digests;
+use crate::duplicates;
 use crate::email;  // Database helper functions
-use crate::mpesa::{MpesaClient, MpesaConfig, StkCallbackBody, extract_callback_data, PaymentStatus};
+use crate::email_templates;
+use crate::geocoding;
+use crate::password::{self, PasswordPolicy};
+use crate::mpesa::{MpesaClient, MpesaConfig, StkCallbackBody, extract_callback_data, PaymentStatus, CallbackAuth, MpesaError};
 use crate::gemini;
+use crate::invoice;
+use crate::leaderboard;
+use crate::request_id;
+use crate::reservations;
+use crate::maintenance;
+use crate::moderation::{self, Verdict};
+use crate::payouts;
+use crate::realtime::{self, ChatHub, ServerEvent};
+use crate::settings;
+use crate::shipping;
+use crate::tax;
+use crate::trending;
+use crate::validation;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::OnceLock;
-use rand;
 
 /// GET /products - Retrieve all products, optionally filtered by vendor or location.
+/// Each carries `in_stock`, and `?in_stock_only=true` leaves out sold-out ones;
+/// `?is_organic=true|false` filters on the organic flag.
+/// `?currency=USD` adds a `display_price` converted at the configured rate;
+/// `limit`/`offset` return a `Paginated` page instead of the full array.
 #[get("/products")]
 async fn get_products(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
     let vendor_filter = if let Ok(claims) = extract_auth(&req) {
@@ -28,15 +55,126 @@ async fn get_products(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> A
 
     // Extract location string for filtering (e.g., "Nakuru")
     let query_string = req.query_string();
-    let user_location = extract_query_param(&query_string, "location");
+    let user_location = extract_query_param(query_string, "location");
+    let tag = extract_query_param(query_string, "tag");
+    let featured_first = extract_query_param(query_string, "sort").as_deref() == Some("featured");
+    let in_stock_only = match parse_query_param::<bool>(query_string, "in_stock_only") {
+        Ok(value) => value.unwrap_or(false),
+        Err(response) => return Ok(response),
+    };
+    let is_organic = match parse_query_param::<bool>(query_string, "is_organic") {
+        Ok(value) => value,
+        Err(response) => return Ok(response),
+    };
 
-    match db::get_all_products(&pool, vendor_filter, user_location).await {
-        Ok(products) => Ok(HttpResponse::Ok().json(products)),
-        Err(e) => Ok(HttpResponse::InternalServerError().json(format!("Failed to fetch products: {:?}", e))),
+    // Optional display currency; prices stay (and are charged) in KSh
+    let currency = extract_query_param(query_string, "currency").filter(|c| !currency::is_base_currency(c));
+    let rates = match &currency {
+        Some(code) => {
+            let rates = currency::StaticRates::from_settings(&pool).await;
+            if currency::RateSource::kes_per_unit(&rates, code).is_none() {
+                return Ok(HttpResponse::BadRequest().json(format!("Unsupported currency: {}", code)));
+            }
+            Some(rates)
+        }
+        None => None,
+    };
+
+    let page = match page_params(query_string) {
+        Ok(page) => page,
+        Err(response) => return Ok(response),
+    };
+
+    let products: Vec<ProductListing> = match db::get_all_products(&pool, vendor_filter, user_location, tag.as_deref(), featured_first, in_stock_only, is_organic).await {
+        Ok(products) => products
+            .into_iter()
+            .map(|product| ProductListing { in_stock: product.quantity > 0, product })
+            .collect(),
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(format!("Failed to fetch products: {:?}", e))),
+    };
+
+    match (currency, rates) {
+        (Some(code), Some(rates)) => {
+            let products: Vec<serde_json::Value> = products
+                .into_iter()
+                .map(|product| {
+                    let display = currency::display_price(&rates, product.product.price, &code);
+                    let mut value = json!(product);
+                    value["display_price"] = json!(display);
+                    value
+                })
+                .collect();
+            Ok(listing_response(products, page))
+        }
+        _ => Ok(listing_response(products, page)),
+    }
+}
+
+/// Longest product description allowed by the `product_description_max_length` setting.
+async fn product_description_max(pool: &PgPool) -> usize {
+    settings::get_i64(pool, settings::PRODUCT_DESCRIPTION_MAX_LENGTH).await.max(1) as usize
+}
+
+/// Sanitized (name, category, description) of a product request.
+fn clean_product_text(product_req: &ProductRequest, description_max: usize) -> Result<(String, String, String), validation::FieldError> {
+    let name = validation::required_text("name", &product_req.name, validation::PRODUCT_NAME_MAX, false)?;
+    let category = validation::required_text("category", &product_req.category, validation::PRODUCT_CATEGORY_MAX, false)?;
+    let description = validation::optional_text("description", Some(&product_req.description), description_max, true)?
+        .unwrap_or_default();
+    Ok((name, category, description))
+}
+
+/// Heaviest single item a product may weigh: one tonne.
+const MAX_PRODUCT_WEIGHT_GRAMS: i32 = 1_000_000;
+
+/// `current` details with the ones given in a product request applied. A blank
+/// `unit` or `origin` clears it.
+fn product_attributes(product_req: &ProductRequest, current: ProductAttributes) -> Result<ProductAttributes, validation::FieldError> {
+    let mut attributes = current;
+    if let Some(unit) = &product_req.unit {
+        attributes.unit = validation::optional_text("unit", Some(unit), validation::PRODUCT_UNIT_MAX, false)?;
+    }
+    if let Some(origin) = &product_req.origin {
+        attributes.origin = validation::optional_text("origin", Some(origin), validation::PRODUCT_ORIGIN_MAX, false)?;
+    }
+    if let Some(weight) = product_req.weight_grams {
+        if !(1..=MAX_PRODUCT_WEIGHT_GRAMS).contains(&weight) {
+            return Err(validation::FieldError {
+                field: "weight_grams",
+                message: format!("weight_grams must be between 1 and {}", MAX_PRODUCT_WEIGHT_GRAMS),
+            });
+        }
+        attributes.weight_grams = Some(weight);
+    }
+    if let Some(is_organic) = product_req.is_organic {
+        attributes.is_organic = is_organic;
+    }
+    if let Some(harvest_date) = product_req.harvest_date {
+        if harvest_date > chrono::Utc::now().date_naive() {
+            return Err(validation::FieldError {
+                field: "harvest_date",
+                message: "harvest_date cannot be in the future".to_string(),
+            });
+        }
+        attributes.harvest_date = Some(harvest_date);
+    }
+    Ok(attributes)
+}
+
+/// A saved product as returned to its vendor, with `under_review` (and the
+/// `review_reason`) when automated moderation held it for admin review.
+fn moderated_product_json(product: &crate::models::Product, flag: Option<&str>) -> serde_json::Value {
+    let mut body = json!(product);
+    body["under_review"] = json!(flag.is_some());
+    if let Some(reason) = flag {
+        body["review_reason"] = json!(reason);
     }
+    body
 }
 
-/// POST /products - Create a new product (verified vendors only).
+/// POST /products - Create a new product (verified vendors only). A name very close
+/// to one the vendor already lists is refused with 409 unless `force` is set.
+/// Listings flagged by automated moderation are held for admin review.
 #[post("/products")]
 async fn create_product(req: actix_web::HttpRequest, pool: web::Data<PgPool>, product_req: web::Json<ProductRequest>) -> ActixResult<HttpResponse> {
     let vendor_id = match check_vendor_auth(&req) {
@@ -44,45 +182,74 @@ async fn create_product(req: actix_web::HttpRequest, pool: web::Data<PgPool>, pr
         Err(response) => return Ok(response),
     };
 
-    // Check if vendor is verified
-    let verified: (bool,) = match sqlx::query_as("SELECT verified FROM users WHERE id = $1")
-        .bind(vendor_id)
-        .fetch_one(pool.get_ref())
-        .await {
-        Ok(result) => result,
-        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to check verification status")),
+    let (name, category, description) = match clean_product_text(&product_req, product_description_max(&pool).await) {
+        Ok(text) => text,
+        Err(e) => return Ok(e.to_response()),
+    };
+    let attributes = match product_attributes(&product_req, ProductAttributes::default()) {
+        Ok(attributes) => attributes,
+        Err(e) => return Ok(e.to_response()),
     };
 
-    if !verified.0 {
-        return Ok(HttpResponse::Forbidden().json("Account not verified. Please wait for admin verification."));
+    if let Err(response) = check_vendor_can_sell(&pool, vendor_id).await {
+        return Ok(response);
     }
 
-    // Check report count
-    let report_count: i64 = match sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM vendor_reports WHERE vendor_id = $1")
-        .bind(vendor_id)
-        .fetch_one(pool.get_ref())
-        .await {
-        Ok(count) => count,
-        Err(e) => {
-            eprintln!("Database error checking report count: {:?}", e);
-            return Ok(HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to check report count",
-                "details": format!("{:?}", e)
-            })));
-        },
-    };
-
-    if report_count >= 5 {
-        return Ok(HttpResponse::Forbidden().json("Account suspended due to multiple reports."));
+    if !product_req.force {
+        match duplicates::find_duplicate(&pool, vendor_id, &name).await {
+            Ok(Some(existing)) => {
+                return Ok(HttpResponse::Conflict().json(json!({
+                    "error": "Possible duplicate product",
+                    "message": format!("You already list \"{}\". Send \"force\": true to create this product anyway.", existing.name),
+                    "existing_product_id": existing.product_id,
+                    "existing_product_name": existing.name,
+                    "similarity": existing.similarity
+                })));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Database error checking for duplicate products: {:?}", e);
+                return Ok(HttpResponse::InternalServerError().json("Failed to check for duplicate products"));
+            }
+        }
     }
 
-    match db::create_product(&pool, &product_req.name, product_req.price, &product_req.category, &product_req.description, product_req.quantity, product_req.image.as_deref(), vendor_id).await {
-        Ok(product) => Ok(HttpResponse::Created().json(product)),
+    let verdict = moderation::check_listing(&name, &category, &description).await;
+    let flag = match &verdict {
+        Verdict::Flag(reason) => Some(reason.as_str()),
+        Verdict::Allow => None,
+    };
+
+    match db::create_product(&pool, &name, product_req.price, &category, &description, product_req.quantity, product_req.image.as_deref(), vendor_id).await {
+        Ok(mut product) => {
+            if flag.is_some() {
+                if let Err(e) = db::set_product_flag(&pool, product.id as i32, flag).await {
+                    eprintln!("❌ Failed to hold product {} for review: {:?}", product.id, e);
+                    return Ok(HttpResponse::InternalServerError().json("Failed to create product"));
+                }
+            }
+            if let Some(tags) = &product_req.tags {
+                match db::set_product_tags(&pool, product.id as i32, tags).await {
+                    Ok(stored) => product.tags = stored,
+                    Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to save product tags")),
+                }
+            }
+            if attributes != ProductAttributes::default() {
+                if let Err(e) = db::set_product_attributes(&pool, product.id as i32, &attributes).await {
+                    eprintln!("❌ Failed to save details of product {}: {:?}", product.id, e);
+                    return Ok(HttpResponse::InternalServerError().json("Failed to save product details"));
+                }
+                product.attributes = attributes;
+            }
+            Ok(HttpResponse::Created().json(moderated_product_json(&product, flag)))
+        }
         Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create product")),
     }
 }
 
-/// PATCH /products/{product_id} - Update a product (owner only).
+/// PATCH /products/{product_id} - Update a product (owner only; verified and not
+/// suspended, as for creating). A changed name, category or description goes
+/// through automated moderation again.
 #[patch("/products/{product_id}")]
 async fn update_product(req: actix_web::HttpRequest, pool: web::Data<PgPool>, product_id: web::Path<i32>, product_req: web::Json<ProductRequest>) -> ActixResult<HttpResponse> {
     let vendor_id = match check_vendor_auth(&req) {
@@ -90,1949 +257,5730 @@ async fn update_product(req: actix_web::HttpRequest, pool: web::Data<PgPool>, pr
         Err(response) => return Ok(response),
     };
 
-    match db::update_product(&pool, *product_id, &product_req.name, product_req.price, &product_req.category, &product_req.description, product_req.quantity, product_req.image.as_deref(), vendor_id).await {
-        Ok(product) => Ok(HttpResponse::Ok().json(product)),
-        Err(_) => Ok(HttpResponse::BadRequest().json("Product not found or access denied")),
+    let (name, category, description) = match clean_product_text(&product_req, product_description_max(&pool).await) {
+        Ok(text) => text,
+        Err(e) => return Ok(e.to_response()),
+    };
+
+    if let Err(response) = check_vendor_can_sell(&pool, vendor_id).await {
+        return Ok(response);
     }
-}
 
-/// DELETE /products/{product_id} - Delete a product (owner only).
-#[delete("/products/{product_id}")]
-async fn delete_product(req: actix_web::HttpRequest, pool: web::Data<PgPool>, product_id: web::Path<i32>) -> ActixResult<HttpResponse> {
-    let vendor_id = match check_vendor_auth(&req) {
-        Ok(id) => id,
-        Err(response) => return Ok(response),
+    let attributes = match db::get_product_attributes(&pool, *product_id).await {
+        Ok(current) => match product_attributes(&product_req, current) {
+            Ok(attributes) => attributes,
+            Err(e) => return Ok(e.to_response()),
+        },
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::BadRequest().json("Product not found or access denied")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to update product")),
     };
 
-    match db::delete_product(&pool, *product_id, vendor_id).await {
-        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+    let current = match db::get_product_listing(&pool, *product_id).await {
+        Ok(current) => current,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to update product")),
+    };
+    let text_changed = current.as_ref().is_none_or(|(old_name, old_category, old_description, _)| {
+        (old_name.as_str(), old_category.as_str(), old_description.as_deref().unwrap_or("")) != (name.as_str(), category.as_str(), description.as_str())
+    });
+    let mut flag = current.and_then(|(_, _, _, flag)| flag);
+    let verdict = if text_changed { Some(moderation::check_listing(&name, &category, &description).await) } else { None };
+
+    match db::update_product(&pool, *product_id, &name, product_req.price, &category, &description, product_req.quantity, product_req.image.as_deref(), vendor_id).await {
+        Ok(mut product) => {
+            if let Some(verdict) = verdict {
+                flag = match verdict {
+                    Verdict::Flag(reason) => Some(reason),
+                    Verdict::Allow => None,
+                };
+                if let Err(e) = db::set_product_flag(&pool, *product_id, flag.as_deref()).await {
+                    eprintln!("❌ Failed to record moderation of product {}: {:?}", product_id, e);
+                    return Ok(HttpResponse::InternalServerError().json("Failed to update product"));
+                }
+            }
+            let tags = match &product_req.tags {
+                Some(tags) => db::set_product_tags(&pool, *product_id, tags).await,
+                None => db::get_product_tags(&pool, *product_id).await,
+            };
+            match tags {
+                Ok(tags) => product.tags = tags,
+                Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to save product tags")),
+            }
+            match db::get_product_images(&pool, *product_id).await {
+                Ok(gallery) => product.gallery = gallery,
+                Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch product images")),
+            }
+            if let Err(e) = db::set_product_attributes(&pool, *product_id, &attributes).await {
+                eprintln!("❌ Failed to save details of product {}: {:?}", product_id, e);
+                return Ok(HttpResponse::InternalServerError().json("Failed to save product details"));
+            }
+            product.attributes = attributes;
+            Ok(HttpResponse::Ok().json(moderated_product_json(&product, flag.as_deref())))
+        }
         Err(_) => Ok(HttpResponse::BadRequest().json("Product not found or access denied")),
     }
 }
 
-/**
- * POST /login - Authenticate user login
- *
- * Validates user credentials against the database and returns user information if valid.
- *
- * @param pool - PostgreSQL connection pool
- * @param req - JSON request with username and password
- * @returns JSON user object on success, 401 on invalid credentials
- */
-#[post("/login")]
-async fn login(pool: web::Data<PgPool>, req: web::Json<LoginRequest>) -> ActixResult<HttpResponse> {
-    // Attempt to authenticate the user with database
-    match db::authenticate_user(&pool, &req.username, &req.password).await {
-        Ok(user) => {
-            // Create JWT token
-            match create_jwt(&user) {
-                Ok(token) => {
-                    let response = LoginResponse { token, user };
-                    Ok(HttpResponse::Ok().json(response)) // 200 OK with token and user data
-                }
-                Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create token")),
-            }
-        }
-        Err(_) => Ok(HttpResponse::Unauthorized().json("Invalid credentials")), // 401 Unauthorized
+/// Most gallery images a product can have (besides its primary image).
+const MAX_PRODUCT_IMAGES: i64 = 8;
+
+/// Largest decoded gallery image accepted as a data URL.
+const MAX_PRODUCT_IMAGE_BYTES: usize = 2 * 1024 * 1024;
+
+/// Longest gallery image URL accepted.
+const MAX_PRODUCT_IMAGE_URL_LEN: usize = 2048;
+
+/// A gallery image must be an http(s) URL or a base64 image data URL.
+fn validate_gallery_image(image: &str) -> Result<(), String> {
+    if image.starts_with("data:") {
+        return validate_image_data_url(image, "Image", MAX_PRODUCT_IMAGE_BYTES);
+    }
+    if image.len() > MAX_PRODUCT_IMAGE_URL_LEN {
+        return Err(format!("Image URL must be at most {} characters", MAX_PRODUCT_IMAGE_URL_LEN));
+    }
+    match url::Url::parse(image) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.host().is_some() => Ok(()),
+        _ => Err("Image must be an http(s) URL or a base64 image data URL".to_string()),
     }
 }
 
-/**
- * POST /signup - Register a new user
- *
- * Creates a new user account with the specified role and profile information.
- * Handles duplicate username/email conflicts and returns appropriate errors.
- *
- * @param pool - PostgreSQL connection pool
- * @param req - JSON request with user registration details
- * @returns JSON user object on success, error status on failure
- */
-#[post("/signup")]
-async fn signup(pool: web::Data<PgPool>, req: web::Json<SignupRequest>) -> ActixResult<HttpResponse> {
-    // Validate phone number requirements
-    if req.mpesa_number.trim().is_empty() {
-        return Ok(HttpResponse::BadRequest().json("Phone number is required"));
+/// Confirm the signed-in vendor owns `product_id`, mapping failures to responses.
+async fn check_product_owner(req: &actix_web::HttpRequest, pool: &PgPool, product_id: i32) -> Result<(), HttpResponse> {
+    let vendor_id = check_vendor_auth(req)?;
+    match db::get_product_vendor_id(pool, product_id).await {
+        Ok(owner) if owner == vendor_id => Ok(()),
+        Ok(_) => Err(HttpResponse::Forbidden().json("You can only manage images of your own products")),
+        Err(sqlx::Error::RowNotFound) => Err(HttpResponse::NotFound().json("Product not found")),
+        Err(_) => Err(HttpResponse::InternalServerError().json("Failed to check product ownership")),
     }
-    if req.mpesa_number.len() < 10 || req.mpesa_number.len() > 15 {
-        return Ok(HttpResponse::BadRequest().json("Phone number must be between 10 and 15 digits"));
+}
+
+/// GET /products/{product_id}/images - A product's gallery in display order.
+#[get("/products/{product_id}/images")]
+async fn get_product_images(pool: web::Data<PgPool>, product_id: web::Path<i32>) -> ActixResult<HttpResponse> {
+    match db::get_product_vendor_id(&pool, *product_id).await {
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("Product not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch product images")),
     }
-    if !req.mpesa_number.chars().all(|c| c.is_numeric() || c == '+') {
-        return Ok(HttpResponse::BadRequest().json("Phone number must contain only numbers and optionally start with +"));
+    match db::get_product_images(&pool, *product_id).await {
+        Ok(images) => Ok(HttpResponse::Ok().json(images)),
+        Err(e) => {
+            eprintln!("Failed to fetch product images: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch product images"))
+        }
     }
+}
 
-    // Validate password requirements
-    if req.password.len() < 8 {
-        return Ok(HttpResponse::BadRequest().json("Password must be at least 8 characters"));
+/// POST /products/{product_id}/images - Append an image to the gallery (owning
+/// vendor only), up to MAX_PRODUCT_IMAGES per product.
+#[post("/products/{product_id}/images")]
+async fn add_product_image(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    product_id: web::Path<i32>,
+    image_req: web::Json<ProductImageRequest>,
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_product_owner(&req, &pool, *product_id).await {
+        return Ok(response);
     }
-    if !req.password.chars().any(|c| c.is_uppercase()) {
-        return Ok(HttpResponse::BadRequest().json("Password must contain at least one uppercase letter"));
+    let image = image_req.image.trim();
+    if let Err(reason) = validate_gallery_image(image) {
+        return Ok(HttpResponse::BadRequest().json(reason));
     }
-    if !req.password.chars().any(|c| c.is_lowercase()) {
-        return Ok(HttpResponse::BadRequest().json("Password must contain at least one lowercase letter"));
+
+    match db::add_product_image(&pool, *product_id, image, MAX_PRODUCT_IMAGES).await {
+        Ok(Some(image)) => Ok(HttpResponse::Created().json(image)),
+        Ok(None) => Ok(HttpResponse::BadRequest().json(format!(
+            "A product can have at most {} gallery images",
+            MAX_PRODUCT_IMAGES
+        ))),
+        Err(e) => {
+            eprintln!("Failed to add product image: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to add product image"))
+        }
     }
-    if !req.password.chars().any(|c| c.is_numeric()) {
-        return Ok(HttpResponse::BadRequest().json("Password must contain at least one number"));
+}
+
+/// PUT /products/{product_id}/images/order - Reorder the gallery (owning vendor
+/// only). `image_ids` must list every image of the product once.
+#[put("/products/{product_id}/images/order")]
+async fn reorder_product_images(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    product_id: web::Path<i32>,
+    order_req: web::Json<ReorderProductImagesRequest>,
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_product_owner(&req, &pool, *product_id).await {
+        return Ok(response);
     }
-    if !req.password.chars().any(|c| "!@#$%^&*(),.?\":{}|<>".contains(c)) {
-        return Ok(HttpResponse::BadRequest().json("Password must contain at least one special character"));
+
+    match db::reorder_product_images(&pool, *product_id, &order_req.image_ids).await {
+        Ok(true) => match db::get_product_images(&pool, *product_id).await {
+            Ok(images) => Ok(HttpResponse::Ok().json(images)),
+            Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch product images")),
+        },
+        Ok(false) => Ok(HttpResponse::BadRequest().json("image_ids must list every gallery image of the product exactly once")),
+        Err(e) => {
+            eprintln!("Failed to reorder product images: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to reorder product images"))
+        }
     }
+}
 
-    // Convert string role to enum, defaulting to Customer
-    let role = match req.role.as_deref().unwrap_or("Customer") {
-        "Vendor" => Role::Vendor,
-        _ => Role::Customer,
-    };
+/// DELETE /products/{product_id}/images/{image_id} - Remove a gallery image (owning vendor only).
+#[delete("/products/{product_id}/images/{image_id}")]
+async fn delete_product_image(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(i32, i32)>,
+) -> ActixResult<HttpResponse> {
+    let (product_id, image_id) = path.into_inner();
+    if let Err(response) = check_product_owner(&req, &pool, product_id).await {
+        return Ok(response);
+    }
 
-    // Attempt to create new user in database
-    match db::create_user(&pool, &req.username, &req.email, &req.password, &role, req.profile_image.as_deref(), req.location_string.as_deref(), Some(&req.mpesa_number)).await {
-        Ok(user) => Ok(HttpResponse::Created().json(user)),           // 201 Created with user data
-        // Handle unique constraint violations (duplicate username/email/phone)
-        Err(sqlx::Error::Database(db_err)) if db_err.constraint().is_some() => {
-            let constraint_name = db_err.constraint().unwrap_or("");
-            let error_message = match constraint_name {
-                name if name.contains("unique_mpesa_number") || name.contains("mpesa") => "Phone number is already registered to another account",
-                name if name.contains("email") => "Email address is already registered to another account", 
-                name if name.contains("username") => "Username is already taken",
-                _ => "Username, email, or phone number already exists"
-            };
-            Ok(HttpResponse::Conflict().json(error_message))  // 409 Conflict
+    match db::delete_product_image(&pool, product_id, image_id).await {
+        Ok(_) => Ok(ok_message("Image removed")),
+        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("Image not found")),
+        Err(e) => {
+            eprintln!("Failed to delete product image: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to delete product image"))
         }
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create user")),  // 500 Internal Error
     }
 }
 
-use actix_web::http::header::AUTHORIZATION;
+/// Longest promotion a vendor can give their own product, in days.
+const VENDOR_FEATURE_MAX_DAYS: i64 = 30;
+/// Longest timed promotion an admin can set; longer ones are left open-ended.
+const FEATURE_MAX_DAYS: i64 = 365;
 
+/// GET /products/featured - Products with an active promotion.
+#[get("/products/featured")]
+async fn get_featured_products(pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    match db::get_featured_products(&pool).await {
+        Ok(products) => Ok(HttpResponse::Ok().json(products)),
+        Err(e) => {
+            eprintln!("Failed to fetch featured products: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch featured products"))
+        }
+    }
+}
 
+/// Products per page of GET /products/new when no `limit` is given.
+const NEW_ARRIVALS_DEFAULT_LIMIT: i64 = 20;
 
-/**
- * GET /cart - Get user's cart items
- *
- * Retrieves all items in the authenticated customer's shopping cart.
- *
- * @param req - HTTP request for authentication
- * @param pool - Database connection pool
- * @returns JSON array of cart items with product details
- */
-#[get("/cart")]
-async fn get_cart(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
-    let user_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+/// GET /products/new?limit=&offset= - In-stock products listed within the
+/// `new_arrivals_days` setting, newest first, as a `Paginated` page.
+#[get("/products/new")]
+async fn get_new_arrivals(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let (limit, offset) = match page_params(req.query_string()) {
+        Ok(page) => page.unwrap_or((NEW_ARRIVALS_DEFAULT_LIMIT, 0)),
         Err(response) => return Ok(response),
     };
+    let days = settings::get_i64(&pool, settings::NEW_ARRIVALS_DAYS).await.clamp(1, i32::MAX as i64) as i32;
 
-    match db::get_cart_items(&pool, user_id).await {
-        Ok(cart_items) => Ok(HttpResponse::Ok().json(cart_items)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch cart items")),
+    match db::get_new_arrivals(&pool, days).await {
+        Ok(products) => Ok(HttpResponse::Ok().json(Paginated::from_all(products, limit, offset))),
+        Err(e) => {
+            eprintln!("Failed to fetch new arrivals: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch new arrivals"))
+        }
     }
 }
 
-/**
- * POST /cart - Add item to cart
- *
- * Adds a product to the authenticated customer's shopping cart.
- * If the product already exists in the cart, increases the quantity.
- *
- * @param req - HTTP request for authentication
- * @param pool - Database connection pool
- * @param cart_req - JSON request with product_id and quantity
- * @returns JSON of the added/updated cart item
- */
-#[post("/cart")]
-async fn add_to_cart_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>, cart_req: web::Json<CartItemRequest>) -> ActixResult<HttpResponse> {
-    let user_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
-        Err(response) => return Ok(response),
-    };
+/// Default and longest activity window for GET /products/trending, in days.
+const TRENDING_DEFAULT_DAYS: i32 = 7;
+const TRENDING_MAX_DAYS: i32 = 90;
+
+/// Most products GET /products/trending returns.
+const TRENDING_MAX_LIMIT: i64 = 50;
+
+/// GET /products/trending?days=&limit= - Products ranked by recent views, orders
+/// and wishlist adds over the last `days` days (default 7), top `limit` first
+/// (default 10). Results are cached briefly.
+#[get("/products/trending")]
+async fn get_trending_products(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let days = extract_query_param(req.query_string(), "days")
+        .and_then(|v| v.parse::<i32>().ok())
+        .unwrap_or(TRENDING_DEFAULT_DAYS)
+        .clamp(1, TRENDING_MAX_DAYS);
+    let limit = extract_query_param(req.query_string(), "limit")
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(10)
+        .clamp(1, TRENDING_MAX_LIMIT);
+
+    match trending::trending_products(&pool, days, limit).await {
+        Ok(products) => Ok(HttpResponse::Ok().json(products)),
+        Err(e) => {
+            eprintln!("Failed to fetch trending products: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch trending products"))
+        }
+    }
+}
 
-    match db::add_to_cart(&pool, user_id, cart_req.product_id, cart_req.quantity).await {
-        Ok(cart_item) => Ok(HttpResponse::Created().json(cart_item)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to add item to cart")),
+/// GET /products/{product_id} - One product with its vendor, rating summary,
+/// gallery and stock. Products of vendors hidden from the catalog are not found.
+#[get("/products/{product_id}")]
+async fn get_product(pool: web::Data<PgPool>, product_id: web::Path<i32>) -> ActixResult<HttpResponse> {
+    match db::get_product_detail(&pool, *product_id).await {
+        Ok(Some(product)) => Ok(HttpResponse::Ok().json(product)),
+        Ok(None) => Ok(HttpResponse::NotFound().json("Product not found")),
+        Err(e) => {
+            eprintln!("Failed to fetch product: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch product"))
+        }
     }
 }
 
-/**
- * PUT /cart/{item_id} - Update cart item quantity
- *
- * Updates the quantity of a specific item in the authenticated customer's cart.
- * User can only update their own cart items.
- *
- * @param req - HTTP request for authentication
- * @param pool - Database connection pool
- * @param item_id - Cart item ID from URL path
- * @param update_req - JSON request with new quantity
- * @returns JSON of the updated cart item
- */
-#[patch("/cart/{item_id}")]
-async fn update_cart_item(req: actix_web::HttpRequest, pool: web::Data<PgPool>, item_id: web::Path<i32>, update_req: web::Json<UpdateCartItemRequest>) -> ActixResult<HttpResponse> {
-    let user_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
-        Err(response) => return Ok(response),
+/// Repeat views of a product by the same viewer within this window count once.
+const PRODUCT_VIEW_DEBOUNCE_MINUTES: i32 = 30;
+
+/// Products kept in each user's recently viewed history.
+const RECENTLY_VIEWED_HISTORY: i64 = 50;
+
+/// POST /products/{product_id}/view - Record a product page view. Authentication
+/// is optional; anonymous views are debounced by IP. Vendors viewing their own
+/// products aren't counted. Signed-in viewers also get the product added to
+/// their recently viewed list.
+#[post("/products/{product_id}/view")]
+async fn record_product_view(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    product_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let viewer_id = extract_auth(&req).ok().map(|claims| claims.sub);
+
+    let vendor_id = match db::get_product_vendor_id(&pool, *product_id).await {
+        Ok(id) => id,
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("Product not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to record view")),
     };
+    if let Some(user_id) = viewer_id {
+        if let Err(e) = db::record_recently_viewed(&pool, user_id, *product_id, RECENTLY_VIEWED_HISTORY).await {
+            eprintln!("Failed to update recently viewed for user {}: {:?}", user_id, e);
+        }
+    }
+    if viewer_id == Some(vendor_id) {
+        return Ok(ok(json!({ "recorded": false })));
+    }
 
-    match db::update_cart_item_quantity(&pool, *item_id, user_id, update_req.quantity).await {
-        Ok(cart_item) => Ok(HttpResponse::Ok().json(cart_item)),
-        Err(_) => Ok(HttpResponse::BadRequest().json("Cart item not found or access denied")),
+    let viewer_ip = req.connection_info().realip_remote_addr().map(str::to_string);
+    match db::record_product_view(&pool, *product_id, viewer_id, viewer_ip.as_deref(), PRODUCT_VIEW_DEBOUNCE_MINUTES).await {
+        Ok(recorded) => Ok(ok(json!({ "recorded": recorded }))),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to record view")),
     }
 }
 
-/**
- * DELETE /cart/{item_id} - Remove item from cart
- *
- * Removes a specific item from the authenticated customer's shopping cart.
- * User can only remove their own cart items.
- *
- * @param req - HTTP request for authentication
- * @param pool - Database connection pool
- * @param item_id - Cart item ID from URL path
- * @returns Empty response on success
- */
-#[delete("/cart/{item_id}")]
-async fn remove_from_cart_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>, item_id: web::Path<i32>) -> ActixResult<HttpResponse> {
-    let user_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+/// GET /products/recently-viewed?limit= - The caller's recently viewed products,
+/// newest first (default 20, at most the stored history).
+#[get("/products/recently-viewed")]
+async fn get_recently_viewed(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
         Err(response) => return Ok(response),
     };
+    let limit = extract_query_param(req.query_string(), "limit")
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(20)
+        .clamp(1, RECENTLY_VIEWED_HISTORY);
 
-    match db::remove_from_cart_with_user(&pool, *item_id, user_id).await {
-        Ok(_) => Ok(HttpResponse::NoContent().finish()),
-        Err(_) => Ok(HttpResponse::BadRequest().json("Cart item not found or access denied")),
-    }
-}
-
-// Global M-Pesa client instance
-static MPESA_CLIENT: OnceLock<Option<MpesaClient>> = OnceLock::new();
-
-fn get_mpesa_client() -> Option<&'static MpesaClient> {
-    MPESA_CLIENT.get_or_init(|| {
-        match MpesaConfig::from_env() {
-            Ok(config) => Some(MpesaClient::new(config)),
-            Err(e) => {
-                eprintln!("Failed to initialize M-Pesa client: {}", e);
-                None
-            }
+    match db::get_recently_viewed(&pool, claims.sub, limit).await {
+        Ok(products) => Ok(HttpResponse::Ok().json(products)),
+        Err(e) => {
+            eprintln!("Failed to fetch recently viewed products: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch recently viewed products"))
         }
-    }).as_ref()
+    }
 }
 
-/**
- * POST /checkout - Process M-Pesa payment using Daraja API
- *
- * Initiates M-Pesa STK Push payment for the authenticated customer's cart items.
- * Integrates with Safaricom's M-Pesa Daraja API for real payments.
- *
- * @param req - HTTP request for authentication
- * @param pool - Database connection pool
- * @param checkout_req - JSON request with M-Pesa number and total amount
- * @returns JSON response with transaction details
- */
-#[post("/checkout")]
-async fn checkout(req: actix_web::HttpRequest, pool: web::Data<PgPool>, checkout_req: web::Json<CheckoutRequest>) -> ActixResult<HttpResponse> {
-    let user_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+/// PATCH /products/{product_id}/featured - Feature or unfeature a product.
+/// Admins may feature any product for 1 to FEATURE_MAX_DAYS days, or indefinitely
+/// if `days` is omitted; vendors may only promote their own products for 1 to
+/// VENDOR_FEATURE_MAX_DAYS days.
+#[patch("/products/{product_id}/featured")]
+async fn set_product_featured(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    product_id: web::Path<i32>,
+    feature_req: web::Json<FeatureProductRequest>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(claims) => claims,
         Err(response) => return Ok(response),
     };
 
-    // Validate M-Pesa phone number format
-    let phone_number = &checkout_req.mpesa_number;
-    if !is_valid_kenyan_phone(phone_number) {
-        return Ok(HttpResponse::BadRequest().json(json!({
-            "error": "Invalid phone number",
-            "message": "Please enter a valid Kenyan phone number (07XXXXXXXX, 254XXXXXXXXX, or +254XXXXXXXXX)"
-        })));
-    }
+    let vendor_scope = match claims.role.as_str() {
+        "Admin" => None,
+        "Vendor" => Some(claims.sub),
+        _ => return Ok(HttpResponse::Forbidden().json("Only admins and vendors can feature products")),
+    };
 
-    // Validate minimum amount (M-Pesa minimum is KSh 1)
-    if checkout_req.total_amount < 1.0 {
-        return Ok(HttpResponse::BadRequest().json(json!({
-            "error": "Invalid amount", 
-            "message": "Amount must be at least KSh 1"
-        })));
+    if let Some(days) = feature_req.days {
+        if !(1..=FEATURE_MAX_DAYS).contains(&days) {
+            return Ok(HttpResponse::BadRequest().json(format!("days must be between 1 and {}", FEATURE_MAX_DAYS)));
+        }
     }
-
-    println!("🔄 Checkout initiated for phone: {} | Amount: KSh {:.2}", phone_number, checkout_req.total_amount);
-
-    // Get user's cart items to verify they have items
-    match db::get_cart_items(&pool, user_id).await {
-        Ok(all_cart_items) => {
-            if all_cart_items.is_empty() {
-                return Ok(HttpResponse::BadRequest().json("Cart is empty"));
-            }
-
-            // Filter cart items based on selected_items if provided
-            let cart_items: Vec<_> = if let Some(selected) = &checkout_req.selected_items {
-                all_cart_items.into_iter()
-                    .filter(|item| selected.contains(&item.id))
-                    .collect()
-            } else {
-                all_cart_items
-            };
-
-            if cart_items.is_empty() {
-                return Ok(HttpResponse::BadRequest().json("No valid items selected for checkout"));
-            }
-
-            // Calculate total from selected cart items for reference
-            let calculated_total: f64 = cart_items.iter()
-                .map(|item| item.product.price * item.quantity as f64)
-                .sum();
-            
-            // Round to 2 decimal places to match frontend
-            let calculated_total = (calculated_total * 100.0).round() / 100.0;
-
-            // Allow custom amounts - no longer enforce cart total match
-            // Users can pay any amount they want (as low as 1 KSh)
-            // This allows flexible payments, partial payments, tips, etc.
-            println!("💳 Payment request: KSh {:.2} (Cart total: KSh {:.2})", checkout_req.total_amount, calculated_total);
-            
-            // Only validate that amount is reasonable (>= 1 KSh)
-            if checkout_req.total_amount < 1.0 {
-                return Ok(HttpResponse::BadRequest().json(json!({
-                    "error": "Invalid amount",
-                    "message": "Minimum payment amount is KSh 1",
-                    "requested_total": checkout_req.total_amount
-                })));
+    if vendor_scope.is_some() && feature_req.featured {
+        match feature_req.days {
+            Some(days) if days <= VENDOR_FEATURE_MAX_DAYS => {}
+            _ => {
+                return Ok(HttpResponse::BadRequest().json(format!(
+                    "Vendors must feature products for 1 to {} days",
+                    VENDOR_FEATURE_MAX_DAYS
+                )))
             }
+        }
+    }
 
-            // Get M-Pesa client
-            let mpesa_client = match get_mpesa_client() {
-                Some(client) => client,
-                None => {
-                    // Fallback to demo mode if M-Pesa is not configured
-                    println!("M-Pesa not configured, using demo mode");
-                    return demo_checkout(pool, user_id, &cart_items, &checkout_req).await;
-                }
-            };
-
-            // Prepare STK Push parameters
-            let formatted_phone = format_kenyan_phone(phone_number);
-            let account_reference = format!("FM_{}", user_id); // Farmers Market + user ID
-            let transaction_desc = "Farmers Market Purchase";
+    match db::set_product_featured(&pool, *product_id, feature_req.featured, feature_req.days, vendor_scope).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(if feature_req.featured { "Product featured" } else { "Product unfeatured" })),
+        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("Product not found or access denied")),
+        Err(e) => {
+            eprintln!("Failed to update featured status: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to update featured status"))
+        }
+    }
+}
 
-            println!("📱 Initiating STK Push to: {}", formatted_phone);
-            println!("💰 Amount: KSh {:.2}", checkout_req.total_amount);
+/// Number of tags returned by GET /tags.
+const POPULAR_TAGS_LIMIT: i64 = 20;
 
-            // Initiate STK Push
-            match mpesa_client.stk_push(
-                formatted_phone.clone(),
-                checkout_req.total_amount,
-                account_reference,
-                transaction_desc.to_string(),
-            ).await {
-                Ok(stk_response) => {
-                    println!("✅ STK Push initiated successfully");
-                    println!("📋 Transaction ID: {}", stk_response.checkout_request_i_d);
+/// GET /tags - Most used product tags with their product counts.
+#[get("/tags")]
+async fn get_popular_tags(pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    match db::get_popular_tags(&pool, POPULAR_TAGS_LIMIT).await {
+        Ok(tags) => Ok(HttpResponse::Ok().json(tags)),
+        Err(e) => {
+            eprintln!("Failed to fetch popular tags: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch tags"))
+        }
+    }
+}
 
-                    // Convert selected cart item IDs to comma-separated string
-                    let cart_item_ids_str = if let Some(selected) = &checkout_req.selected_items {
-                        Some(selected.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(","))
-                    } else {
-                        // If no items selected, get all cart item IDs
-                        let all_ids = cart_items.iter().map(|item| item.id.to_string()).collect::<Vec<_>>().join(",");
-                        Some(all_ids)
-                    };
+/// Maximum suggestions of each kind returned by GET /products/suggest.
+const SEARCH_SUGGESTIONS_LIMIT: i64 = 10;
+
+/// GET /products/suggest?q= - Autocomplete for the search box: product names,
+/// categories and tags starting with `q`, most popular first. Queries shorter
+/// than two characters return nothing.
+#[get("/products/suggest")]
+async fn suggest_products(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let query = extract_query_param(req.query_string(), "q").unwrap_or_default();
+    let prefix = query.trim();
+    if prefix.chars().count() < 2 {
+        return Ok(HttpResponse::Ok().json(json!({ "names": [], "categories": [], "tags": [] })));
+    }
+    // A prefix longer than any product name can't match anything
+    let prefix: String = prefix.chars().take(100).collect();
+
+    match db::get_search_suggestions(&pool, &prefix, SEARCH_SUGGESTIONS_LIMIT).await {
+        Ok((names, categories, tags)) => Ok(HttpResponse::Ok().json(json!({
+            "names": names,
+            "categories": categories,
+            "tags": tags
+        }))),
+        Err(e) => {
+            eprintln!("Failed to fetch search suggestions: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch suggestions"))
+        }
+    }
+}
 
-                    // Store payment transaction in database with selected cart items
-                    match db::create_payment_transaction(
-                        &pool,
-                        user_id,
-                        &stk_response.checkout_request_i_d,
-                        &stk_response.merchant_request_i_d,
-                        &formatted_phone,
-                        checkout_req.total_amount,
-                        cart_item_ids_str.as_deref(),
-                    ).await {
-                        Ok(transaction_id) => {
-                            println!("💾 Payment transaction stored with ID: {}", transaction_id);
-                        }
-                        Err(e) => {
-                            eprintln!("❌ Failed to store payment transaction: {:?}", e);
-                            // Continue anyway - payment can still succeed
-                        }
-                    }
+/// Most products GET /products/{id}/similar returns.
+const SIMILAR_PRODUCTS_LIMIT: i64 = 8;
 
-                    // Log M-Pesa response details
-                    println!("📊 M-Pesa Response Code: {}", stk_response.response_code);
-                    println!("📝 M-Pesa Description: {}", stk_response.response_description);
+/// GET /products/{product_id}/similar - In-stock alternatives in the same category,
+/// best rated and best selling first, preferring vendors near the signed-in user.
+/// When nothing else shares the category, products sharing a tag are suggested
+/// instead; `match` says which ("category", "tags" or "none").
+#[get("/products/{product_id}/similar")]
+async fn get_similar_products(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    product_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    match db::get_product_vendor_id(&pool, *product_id).await {
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("Product not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch similar products")),
+    }
 
-                    // Return success response to frontend
-                    let response = CheckoutResponse {
-                        transaction_id: stk_response.checkout_request_i_d,
-                        message: if !stk_response.customer_message.is_empty() {
-                            stk_response.customer_message
-                        } else {
-                            format!("Payment request sent to {}. Check your phone and enter your M-Pesa PIN to complete the payment.", phone_number)
-                        },
-                        status: if stk_response.response_code == "0" {
-                            "initiated".to_string()
-                        } else {
-                            "pending".to_string()
-                        },
-                    };
+    let location = match extract_auth(&req) {
+        Ok(claims) => db::get_user_by_id(&pool, claims.sub).await.ok().and_then(|user| user.location_string),
+        Err(_) => None,
+    };
 
-                    Ok(HttpResponse::Ok().json(response))
-                }
-                Err(e) => {
-                    eprintln!("❌ STK Push failed: {:?}", e);
-                    
-                    // Return user-friendly error message based on error type
-                    let error_message = if e.to_string().contains("insufficient") {
-                        "Insufficient balance. Please top up your M-Pesa account and try again."
-                    } else if e.to_string().contains("timeout") {
-                        "Request timeout. Please check your network connection and try again."
-                    } else if e.to_string().contains("invalid") {
-                        "Invalid phone number. Please check and try again."
-                    } else if e.to_string().contains("duplicate") {
-                        "A payment request is already pending for this transaction. Please wait a moment and try again."
-                    } else {
-                        "Payment service temporarily unavailable. Please try again in a few minutes."
-                    };
+    let mut matched = "category";
+    let mut products = db::get_similar_products(&pool, *product_id, location.as_deref(), false, SIMILAR_PRODUCTS_LIMIT).await;
+    if matches!(&products, Ok(found) if found.is_empty()) {
+        matched = "tags";
+        products = db::get_similar_products(&pool, *product_id, location.as_deref(), true, SIMILAR_PRODUCTS_LIMIT).await;
+    }
 
-                    Ok(HttpResponse::ServiceUnavailable().json(json!({
-                        "error": "Payment failed",
-                        "message": error_message,
-                        "retry": true,
-                        "phone_number": phone_number
-                    })))
-                }
-            }
+    match products {
+        Ok(products) => Ok(HttpResponse::Ok().json(json!({
+            "match": if products.is_empty() { "none" } else { matched },
+            "products": products
+        }))),
+        Err(e) => {
+            eprintln!("Failed to fetch similar products: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch similar products"))
         }
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch cart items")),
     }
 }
 
+/// Most products POST /products/compare accepts at once.
+const MAX_COMPARED_PRODUCTS: usize = 5;
+
 /**
- * Fallback demo checkout when M-Pesa is not configured
+ * POST /products/compare - Compare products side by side
+ *
+ * Returns price, rating, vendor, stock, category and (for signed-in users
+ * with a location) distance to the vendor for each product, in request
+ * order. Unknown or unavailable ids are listed under `missing_ids`.
+ *
+ * @param req - HTTP request; auth is optional and only used for distance
+ * @param pool - Database connection pool
+ * @param compare_req - JSON body with the product ids to compare
+ * @returns JSON with the compared attributes, products and missing ids
  */
-async fn demo_checkout(
+#[post("/products/compare")]
+async fn compare_products(
+    req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    user_id: i32,
-    cart_items: &[crate::models::CartItem],
-    checkout_req: &CheckoutRequest,
+    compare_req: web::Json<CompareProductsRequest>,
 ) -> ActixResult<HttpResponse> {
-    // Generate transaction ID (demo mode)
-    let transaction_id = format!("DEMO_TXN_{}_{}", user_id, chrono::Utc::now().timestamp());
+    let mut ids: Vec<i32> = Vec::new();
+    for id in &compare_req.ids {
+        if !ids.contains(id) {
+            ids.push(*id);
+        }
+    }
+    if ids.is_empty() {
+        return Ok(HttpResponse::BadRequest().json("Provide at least one product id"));
+    }
+    if ids.len() > MAX_COMPARED_PRODUCTS {
+        return Ok(HttpResponse::BadRequest().json(format!("At most {} products can be compared", MAX_COMPARED_PRODUCTS)));
+    }
 
-    // Create shipping orders for each cart item
-    for item in cart_items {
-        match db::create_shipping_order(&pool, user_id, item.product_id, item.quantity, "Default shipping address - please update in your orders").await {
-            Ok(_) => {},
-            Err(e) => {
-                eprintln!("Failed to create shipping order for product {}: {:?}", item.product_id, e);
+    let mut found = match db::get_products_for_comparison(&pool, &ids).await {
+        Ok(products) => products,
+        Err(e) => {
+            eprintln!("Failed to load products for comparison: {:?}", e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to compare products"));
+        }
+    };
+
+    if let Ok(claims) = extract_auth(&req) {
+        let mut user_ids: Vec<i32> = found.iter().map(|p| p.vendor_id).collect();
+        user_ids.push(claims.sub);
+        if let Ok(coordinates) = db::get_user_coordinates(&pool, &user_ids).await {
+            if let Some(&origin) = coordinates.get(&claims.sub) {
+                for product in found.iter_mut() {
+                    product.distance_km = coordinates
+                        .get(&product.vendor_id)
+                        .map(|&vendor| (shipping::distance_km(origin, vendor) * 10.0).round() / 10.0);
+                }
             }
         }
     }
 
-    // Clear the cart after successful checkout (demo mode)
-    for item in cart_items {
-        match db::remove_from_cart_with_user(&pool, item.id, user_id).await {
-            Ok(_) => {},
-            Err(e) => eprintln!("Failed to remove cart item {}: {:?}", item.id, e),
+    let mut products = Vec::with_capacity(ids.len());
+    let mut missing_ids = Vec::new();
+    for id in &ids {
+        match found.iter().position(|p| p.product_id == *id) {
+            Some(index) => products.push(found.swap_remove(index)),
+            None => missing_ids.push(*id),
         }
     }
 
-    let response = CheckoutResponse {
-        transaction_id: transaction_id.clone(),
-        message: "DEMO MODE: Payment simulated successfully. Your orders have been created.".to_string(),
-        status: "completed".to_string(),
-    };
-
-    println!("Demo payment completed - User: {}, Phone: {}, Amount: {:.2}, Transaction: {}",
-            user_id, checkout_req.mpesa_number, checkout_req.total_amount, transaction_id);
-
-    Ok(HttpResponse::Ok().json(response))
+    let mut body = json!({
+        "attributes": ["price", "average_rating", "vendor_name", "stock", "category", "distance_km"],
+        "products": products,
+        "missing_ids": missing_ids
+    });
+    if !missing_ids.is_empty() {
+        body["note"] = json!("Some products were skipped because they no longer exist or are unavailable");
+    }
+    Ok(HttpResponse::Ok().json(body))
 }
 
-// Helper functions for M-Pesa phone number validation and formatting
-fn is_valid_kenyan_phone(phone: &str) -> bool {
-    // Remove spaces and common separators
-    let clean_phone = phone.replace(&[' ', '-', '(', ')'][..], "");
-    
-    // Check various Kenyan phone number formats
-    if clean_phone.starts_with("07") && clean_phone.len() == 10 {
-        // 07XXXXXXXX format
-        clean_phone.chars().all(|c| c.is_ascii_digit())
-    } else if clean_phone.starts_with("254") && clean_phone.len() == 12 {
-        // 254XXXXXXXXX format
-        clean_phone.chars().all(|c| c.is_ascii_digit())
-    } else if clean_phone.starts_with("+254") && clean_phone.len() == 13 {
-        // +254XXXXXXXXX format
-        clean_phone[1..].chars().all(|c| c.is_ascii_digit())
-    } else {
-        false
-    }
-}
-
-fn format_kenyan_phone(phone: &str) -> String {
-    let clean_phone = phone.replace(&[' ', '-', '(', ')'][..], "");
-    
-    if clean_phone.starts_with("07") {
-        format!("254{}", &clean_phone[1..])
-    } else if clean_phone.starts_with("+254") {
-        clean_phone[1..].to_string()
-    } else if clean_phone.starts_with("254") {
-        clean_phone
-    } else {
-        // Default fallback - assume it's a 9-digit number without country code
-        if clean_phone.len() == 9 && clean_phone.chars().all(|c| c.is_ascii_digit()) {
-            format!("254{}", clean_phone)
-        } else {
-            format!("254{}", clean_phone)
-        }
-    }
-}
-
-// Auth helpers
-fn extract_auth(req: &actix_web::HttpRequest) -> Result<Claims, HttpResponse> {
-    let auth_header = req.headers().get(AUTHORIZATION);
-    if auth_header.is_none() {
-        return Err(HttpResponse::Unauthorized().json("Authorization header missing"));
-    }
-
-    let auth_header_value = auth_header.unwrap();
-    let auth_str = match auth_header_value.to_str() {
-        Ok(s) => s,
-        Err(_) => return Err(HttpResponse::BadRequest().json("Invalid authorization header encoding")),
+/// DELETE /products/{product_id} - Delete a product (owner only).
+#[delete("/products/{product_id}")]
+async fn delete_product(req: actix_web::HttpRequest, pool: web::Data<PgPool>, product_id: web::Path<i32>) -> ActixResult<HttpResponse> {
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
     };
 
-    if !auth_str.starts_with("Bearer ") {
-        return Err(HttpResponse::Unauthorized().json("Invalid authorization format"));
-    }
-
-    let token = &auth_str[7..]; // Remove "Bearer " prefix
-
-    match verify_jwt(token) {
-        Ok(claims) => Ok(claims),
-        Err(_) => Err(HttpResponse::Unauthorized().json("Invalid token")),
+    match db::delete_product(&pool, *product_id, vendor_id).await {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(_) => Ok(HttpResponse::BadRequest().json("Product not found or access denied")),
     }
 }
 
-fn check_admin_auth(req: &actix_web::HttpRequest) -> Result<(), HttpResponse> {
-    let claims = extract_auth(req)?;
-    if claims.role != "Admin" {
-        return Err(HttpResponse::Forbidden().json("Admin privileges required"));
-    }
-    Ok(())
+/// GET /health - Liveness check; answered even in maintenance mode.
+#[get("/health")]
+async fn health() -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(json!({ "status": "ok" })))
 }
 
-fn check_vendor_auth(req: &actix_web::HttpRequest) -> Result<i32, HttpResponse> {
-    let claims = extract_auth(req)?;
-    if claims.role != "Vendor" {
-        return Err(HttpResponse::Forbidden().json("Vendor privileges required"));
+/**
+ * POST /login - Authenticate user login
+ *
+ * Validates user credentials against the database and returns user information if valid.
+ *
+ * @param pool - PostgreSQL connection pool
+ * @param req - JSON request with username and password
+ * @returns JSON user object on success, 401 on invalid credentials
+ */
+#[post("/login")]
+async fn login(http_req: actix_web::HttpRequest, pool: web::Data<PgPool>, req: web::Json<LoginRequest>) -> ActixResult<HttpResponse> {
+    // Attempt to authenticate the user with database
+    match db::authenticate_user(&pool, &req.username, &req.password).await {
+        Ok(user) => {
+            // Only admins can sign in during maintenance, so they can turn it off
+            if !matches!(user.role, Role::Admin) && settings::get_bool(&pool, settings::MAINTENANCE_MODE).await {
+                return Ok(maintenance::unavailable(&pool).await);
+            }
+            // Create JWT token and a session for this device
+            let token = match create_jwt(&user) {
+                Ok(token) => token,
+                Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to create token")),
+            };
+            let refresh_token = match start_session(&pool, &http_req, user.id).await {
+                Ok(refresh_token) => refresh_token,
+                Err(e) => {
+                    eprintln!("Failed to start session for user {}: {:?}", user.id, e);
+                    return Ok(HttpResponse::InternalServerError().json("Failed to create token"));
+                }
+            };
+            let response = LoginResponse { token, refresh_token, user };
+            Ok(HttpResponse::Ok().json(response)) // 200 OK with token and user data
+        }
+        Err(_) => Ok(HttpResponse::Unauthorized().json("Invalid credentials")), // 401 Unauthorized
     }
-    Ok(claims.sub)
 }
 
-fn check_customer_auth(req: &actix_web::HttpRequest) -> Result<i32, HttpResponse> {
-    let claims = extract_auth(req)?;
-    if claims.role != "Customer" {
-        return Err(HttpResponse::Forbidden().json("Customer privileges required"));
-    }
-    Ok(claims.sub)
+/// Record a new session for `user_id` from the requesting device; returns its refresh token.
+async fn start_session(pool: &PgPool, req: &actix_web::HttpRequest, user_id: i32) -> Result<String, sqlx::Error> {
+    let refresh_token = generate_refresh_token();
+    let user_agent = req
+        .headers()
+        .get(actix_web::http::header::USER_AGENT)
+        .and_then(|ua| ua.to_str().ok())
+        .map(|ua| ua.chars().take(255).collect::<String>());
+    let ip_address = req.connection_info().realip_remote_addr().map(str::to_string);
+    let expires_at = chrono::Utc::now() + chrono::Duration::days(REFRESH_TOKEN_TTL_DAYS);
+    db::create_user_session(pool, user_id, &hash_refresh_token(&refresh_token), user_agent.as_deref(), ip_address.as_deref(), expires_at).await?;
+    Ok(refresh_token)
 }
 
-fn extract_query_param(query_string: &str, param_name: &str) -> Option<String> {
-    let params = query_string.trim_start_matches('?');
-    for pair in params.split('&') {
-        let mut parts = pair.split('=');
-        if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
-            if key == param_name {
-                return Some(value.to_string());
-            }
+/// POST /auth/refresh - Exchange a refresh token for a new token and refresh token.
+/// The old refresh token stops working; revoked or expired sessions get 401.
+#[post("/auth/refresh")]
+async fn refresh_token_route(pool: web::Data<PgPool>, req: web::Json<RefreshRequest>) -> ActixResult<HttpResponse> {
+    let refresh_token = generate_refresh_token();
+    let expires_at = chrono::Utc::now() + chrono::Duration::days(REFRESH_TOKEN_TTL_DAYS);
+    let user_id = match db::rotate_user_session(&pool, &hash_refresh_token(&req.refresh_token), &hash_refresh_token(&refresh_token), expires_at).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return Ok(HttpResponse::Unauthorized().json("Session expired or revoked")),
+        Err(e) => {
+            eprintln!("Failed to refresh session: {:?}", e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to refresh token"));
         }
+    };
+
+    let user = match db::get_user_by_id(&pool, user_id).await {
+        Ok(user) => user,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to refresh token")),
+    };
+    match create_jwt(&user) {
+        Ok(token) => Ok(HttpResponse::Ok().json(json!({ "token": token, "refresh_token": refresh_token }))),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create token")),
     }
-    None
 }
 
-// ADMIN ROUTES
-#[get("/api/admin/users")]
-async fn get_all_users(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
-    }
+/// GET /account/sessions - The caller's active sessions (device, IP, created and last used).
+#[get("/account/sessions")]
+async fn get_sessions_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
 
-    match db::get_all_users(&pool).await {
-        Ok(users) => Ok(HttpResponse::Ok().json(users)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch users")),
+    match db::get_active_user_sessions(&pool, claims.sub).await {
+        Ok(sessions) => Ok(HttpResponse::Ok().json(sessions)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch sessions")),
     }
 }
 
-/// GET /users - Get all users for messaging (public, but requires authentication)
-#[get("/users")]
-async fn get_all_users_for_messaging(
+/// DELETE /account/sessions/{session_id} - Sign one of the caller's devices out;
+/// its refresh token stops working immediately.
+#[delete("/account/sessions/{session_id}")]
+async fn revoke_session_route(
     req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
+    pool: web::Data<PgPool>,
+    session_id: web::Path<i32>,
 ) -> ActixResult<HttpResponse> {
-    // Verify user is authenticated
-    let current_user_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
         Err(response) => return Ok(response),
     };
 
-    match db::get_all_users(&pool).await {
-        Ok(users) => {
-            // Check if current users are following each other (mutual friends)
-            let following_ids: Vec<i32> = match sqlx::query_scalar(
-                "SELECT vendor_id FROM follows WHERE follower_id = $1"
-            )
-            .bind(current_user_id)
-            .fetch_all(pool.get_ref())
-            .await {
-                Ok(ids) => ids,
-                Err(_) => Vec::new(),
-            };
-
-            let followers_ids: Vec<i32> = match sqlx::query_scalar(
-                "SELECT follower_id FROM follows WHERE vendor_id = $1"
-            )
-            .bind(current_user_id)
-            .fetch_all(pool.get_ref())
-            .await {
-                Ok(ids) => ids,
-                Err(_) => Vec::new(),
-            };
-
-            // Filter out the current user and admins, then return enriched data
-            let filtered_users: Vec<_> = users
-                .into_iter()
-                .filter(|u| u.id != current_user_id && !matches!(u.role, Role::Admin))
-                .map(|u| {
-                    let is_followed = following_ids.contains(&u.id);
-                    let is_following_back = followers_ids.contains(&u.id);
-                    let is_mutual_friend = is_followed && is_following_back;
-
-                    serde_json::json!({
-                        "id": u.id,
-                        "username": u.username,
-                        "role": u.role,
-                        "profile_image": u.profile_image,
-                        // Only show personal info if mutual friends
-                        "email": if is_mutual_friend { &u.secondary_email } else { &None },
-                        "phone": if is_mutual_friend { &u.mpesa_number } else { &None },
-                        "location": if is_mutual_friend { &u.location_string } else { &None },
-                        // Follow status for UI
-                        "is_followed": is_followed,
-                        "is_following_back": is_following_back,
-                        "is_mutual_friend": is_mutual_friend
-                    })
-                })
-                .collect();
-            Ok(HttpResponse::Ok().json(filtered_users))
-        },
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch users")),
+    match db::revoke_user_session(&pool, claims.sub, *session_id).await {
+        Ok(true) => Ok(ok_message("Session revoked")),
+        Ok(false) => Ok(HttpResponse::NotFound().json("Session not found or already revoked")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to revoke session")),
     }
 }
 
-#[get("/api/admin/pending-vendors")]
-async fn get_pending_vendors(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
+/**
+ * POST /signup - Register a new user
+ *
+ * Creates a new user account with the specified role and profile information.
+ * Handles duplicate username/email conflicts and returns appropriate errors.
+ *
+ * @param pool - PostgreSQL connection pool
+ * @param req - JSON request with user registration details
+ * @returns JSON user object on success, error status on failure
+ */
+#[post("/signup")]
+async fn signup(pool: web::Data<PgPool>, req: web::Json<SignupRequest>) -> ActixResult<HttpResponse> {
+    // Validate phone number requirements
+    if req.mpesa_number.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json("Phone number is required"));
     }
-
-    match db::get_pending_vendors(&pool).await {
-        Ok(vendors) => Ok(HttpResponse::Ok().json(vendors)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch pending vendors")),
+    if req.mpesa_number.len() < 10 || req.mpesa_number.len() > 15 {
+        return Ok(HttpResponse::BadRequest().json("Phone number must be between 10 and 15 digits"));
+    }
+    if !req.mpesa_number.chars().all(|c| c.is_numeric() || c == '+') {
+        return Ok(HttpResponse::BadRequest().json("Phone number must contain only numbers and optionally start with +"));
     }
-}
 
-#[patch("/api/admin/users/{user_id}")]
-async fn update_user_role(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    user_id: web::Path<i32>,
-    request: web::Json<UpdateUserRoleRequest>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
+    // Validate password requirements
+    if let Err(reason) = password::validate_password(&PasswordPolicy::from_settings(&pool).await, &req.password) {
+        return Ok(HttpResponse::BadRequest().json(reason));
     }
 
-    let role = match request.role.as_str() {
-        "Admin" => Role::Admin,
+    // Convert string role to enum, defaulting to Customer
+    let role = match req.role.as_deref().unwrap_or("Customer") {
         "Vendor" => Role::Vendor,
-        "Customer" => Role::Customer,
-        _ => return Ok(HttpResponse::BadRequest().json("Invalid role")),
+        _ => Role::Customer,
     };
 
-    match db::update_user_role(&pool, *user_id, &role).await {
-        Ok(_) => Ok(HttpResponse::Ok().json("Role updated successfully")),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update role")),
+    // Attempt to create new user in database
+    match db::create_user(&pool, &req.username, &req.email, &req.password, &role, req.profile_image.as_deref(), req.location_string.as_deref(), Some(&req.mpesa_number)).await {
+        Ok(user) => Ok(HttpResponse::Created().json(user)),           // 201 Created with user data
+        // Handle unique constraint violations (duplicate username/email/phone)
+        Err(sqlx::Error::Database(db_err)) if db_err.constraint().is_some() => {
+            let error_message = unique_violation_message(db_err.constraint().unwrap_or(""));
+            Ok(HttpResponse::Conflict().json(error_message))  // 409 Conflict
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create user")),  // 500 Internal Error
     }
 }
 
-#[patch("/api/admin/users/{user_id}/verify")]
-async fn update_user_verification(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    user_id: web::Path<i32>,
-    request: web::Json<UpdateUserVerificationRequest>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
+/// User-facing message for a unique constraint violation on the users table.
+fn unique_violation_message(constraint_name: &str) -> &'static str {
+    match constraint_name {
+        name if name.contains("unique_mpesa_number") || name.contains("mpesa") => "Phone number is already registered to another account",
+        name if name.contains("email") => "Email address is already registered to another account",
+        name if name.contains("username") => "Username is already taken",
+        _ => "Username, email, or phone number already exists"
     }
+}
+
+use actix_web::http::header::AUTHORIZATION;
 
-    // Get user details before updating verification status
-    let user = match db::get_user_by_id(&pool, *user_id).await {
-        Ok(user) => user,
-        Err(_) => return Ok(HttpResponse::NotFound().json("User not found")),
-    };
 
-    match db::update_user_verification(&pool, *user_id, request.verified).await {
-        Ok(_) => {
-            // Send email notification based on verification status
-            if request.verified {
-                if let Err(e) = email::send_verification_approval_email(&user.email, &user.username).await {
-                    eprintln!("Failed to send approval email to {}: {:?}", user.email, e);
-                }
-            } else {
-                if let Err(e) = email::send_verification_rejection_email(&user.email, &user.username).await {
-                    eprintln!("Failed to send rejection email to {}: {:?}", user.email, e);
-                }
-            }
-            
-            let status = if request.verified { "approved" } else { "rejected" };
-            Ok(HttpResponse::Ok().json(format!("Vendor {} and email notification sent", status)))
-        },
-        Err(e) => Ok(HttpResponse::InternalServerError().json(format!("Failed to update verification: {:?}", e))),
-    }
-}
 
 /**
- * POST /vendor/upload-verification - Upload vendor verification document
+ * GET /cart - Get user's cart items
  *
- * Allows vendors to upload documents (ID, business license, etc.) for verification.
- * The document image is stored as Base64 encoded data.
+ * Retrieves all items in the authenticated customer's shopping cart.
  *
  * @param req - HTTP request for authentication
- * @param pool - PostgreSQL connection pool
- * @param request - JSON request body with verification_document (Base64 encoded)
- * @returns JSON confirmation message
+ * @param pool - Database connection pool
+ * @returns JSON array of cart items with product details
  */
-#[post("/vendor/upload-verification")]
-async fn upload_verification_document(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    request: web::Json<UploadVerificationDocumentRequest>
-) -> ActixResult<HttpResponse> {
-    let vendor_id = match check_vendor_auth(&req) {
-        Ok(id) => id,
+#[get("/cart")]
+async fn get_cart(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    // Check that document is not empty
-    if request.verification_document.is_empty() {
-        return Ok(HttpResponse::BadRequest().json("Verification document cannot be empty"));
+    match db::get_cart_items(&pool, user_id).await {
+        Ok(cart_items) => Ok(HttpResponse::Ok().json(cart_items)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch cart items")),
     }
+}
 
-    // Check document size (limit to ~5MB of Base64 encoded data)
-    if request.verification_document.len() > 6_500_000 {
-        return Ok(HttpResponse::BadRequest().json("Document is too large. Maximum size is 5MB"));
-    }
+/**
+ * GET /cart/summary - Cart totals grouped by vendor, including shipping
+ *
+ * Each vendor group lists the vendor's shipping options priced for that group
+ * and the option checkout would pick by default (the cheapest available).
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @returns JSON with vendor groups, items_total, shipping_total and grand_total
+ */
+#[get("/cart/summary")]
+async fn get_cart_summary(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
 
-    match db::upload_verification_document(&pool, vendor_id, &request.verification_document).await {
-        Ok(_) => {
-            // Clear any previous verification rejection reason since they're uploading a new document
-            let _ = db::clear_verification_rejection_reason(&pool, vendor_id).await;
-            
+    let cart_items = match db::get_cart_items(&pool, user_id).await {
+        Ok(items) => items,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch cart items")),
+    };
+
+    match shipping::quote(&pool, user_id, &cart_items, &[]).await {
+        Ok(vendors) => {
+            let items_total: f64 = vendors.iter().map(|v| v.subtotal).sum();
+            let shipping_total: f64 = vendors.iter().map(|v| v.shipping_fee).sum();
             Ok(HttpResponse::Ok().json(json!({
-                "success": true,
-                "message": "Verification document submitted successfully. An administrator will review your submission."
+                "vendors": vendors,
+                "items_total": (items_total * 100.0).round() / 100.0,
+                "shipping_total": (shipping_total * 100.0).round() / 100.0,
+                "grand_total": ((items_total + shipping_total) * 100.0).round() / 100.0
             })))
-        },
-        Err(e) => Ok(HttpResponse::InternalServerError().json(format!("Failed to upload verification document: {:?}", e))),
-    }
-}
-
-#[delete("/api/admin/users/{user_id}")]
-async fn delete_user(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    user_id: web::Path<i32>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
-    }
-
-    match db::delete_user(&pool, *user_id).await {
-        Ok(_) => Ok(HttpResponse::NoContent().finish()),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to delete user")),
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to calculate shipping")),
     }
 }
 
-#[derive(Deserialize)]
-struct BanUserRequest {
-    banned: bool,
-}
+/**
+ * POST /cart - Add item to cart
+ *
+ * Adds a product to the authenticated customer's shopping cart.
+ * If the product already exists in the cart, increases the quantity.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param cart_req - JSON request with product_id and quantity
+ * @returns JSON of the added/updated cart item
+ */
+#[post("/cart")]
+async fn add_to_cart_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>, cart_req: web::Json<CartItemRequest>) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
 
-#[patch("/api/admin/users/{user_id}/ban")]
-async fn ban_user_route(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    user_id: web::Path<i32>,
-    request: web::Json<BanUserRequest>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
+    if cart_req.quantity < 1 {
+        return Ok(HttpResponse::BadRequest().json("Quantity must be at least 1"));
     }
 
-    match db::ban_user(&pool, *user_id, request.banned).await {
-        Ok(_) => Ok(HttpResponse::Ok().json(if request.banned { "User banned successfully" } else { "User unbanned successfully" })),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update ban status")),
+    let max_items = settings::get_i64(&pool, settings::MAX_CART_ITEMS).await;
+    let max_quantity = settings::get_i64(&pool, settings::MAX_CART_ITEM_QUANTITY).await;
+    let (existing_quantity, distinct_items) = match db::get_cart_usage(&pool, user_id, cart_req.product_id).await {
+        Ok(usage) => usage,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to add item to cart")),
+    };
+    if existing_quantity.is_none() && distinct_items >= max_items {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("A cart can hold at most {} different products", max_items),
+            "reason": "max_cart_items"
+        })));
     }
-}
-
-#[patch("/api/admin/users/{user_id}/reset-password")]
-async fn reset_user_password_route(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    user_id: web::Path<i32>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
+    if i64::from(existing_quantity.unwrap_or(0)) + i64::from(cart_req.quantity) > max_quantity {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("At most {} of a product can be in the cart", max_quantity),
+            "reason": "max_cart_item_quantity"
+        })));
     }
 
-    // Generate a random temporary password (8 characters)
-    let temp_password = format!("temp_{}", rand::random::<u32>());
-
-    match db::reset_user_password(&pool, *user_id, &temp_password).await {
-        Ok(_) => {
-            // In a real application, send email here with temp_password
-            // For now, we'll return a generic success message
-            // TODO: Integrate with email service (SendGrid, Mailgun, etc.)
-            println!("TEMP PASSWORD FOR USER {}: {}", user_id, temp_password);
-            Ok(HttpResponse::Ok().json(json!({
-                "message": "Password reset successfully. User has been emailed their new password."
-            })))
+    match db::is_product_vendor_paused(&pool, cart_req.product_id).await {
+        Ok(true) => {
+            return Ok(HttpResponse::Conflict().json(json!({
+                "error": "Vendor paused",
+                "message": "This vendor is away and isn't taking orders right now"
+            })));
         }
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to reset password")),
-    }
-}
-
-#[get("/api/admin/cart")]
-async fn get_all_cart_items(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
+        Ok(false) => {}
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to add item to cart")),
     }
 
-    match db::get_all_cart_items(&pool).await {
-        Ok(cart_items) => Ok(HttpResponse::Ok().json(cart_items)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch cart items")),
+    match db::add_to_cart(&pool, user_id, cart_req.product_id, cart_req.quantity).await {
+        Ok(cart_item) => Ok(HttpResponse::Created().json(cart_item)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to add item to cart")),
     }
 }
 
-#[derive(Deserialize)]
-struct CreateVendorReportRequest {
-    vendor_id: i32,
-    product_id: Option<i32>,
-    report_type: String,
-    description: Option<String>,
-}
+/// Most items POST /cart/batch accepts at once.
+const MAX_CART_BATCH_ITEMS: usize = 100;
 
-#[post("/reports")]
-async fn create_vendor_report_route(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    report_req: web::Json<CreateVendorReportRequest>
-) -> ActixResult<HttpResponse> {
-    let customer_id = match extract_auth(&req) {
+/**
+ * POST /cart/batch - Add several items to the cart at once
+ *
+ * Items are added in one transaction with the same limits as POST /cart, plus
+ * a check that the cart doesn't hold more than the product has in stock. An
+ * item that fails is reported and skipped; the rest are still added.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param batch_req - JSON request with a list of product_id and quantity
+ * @returns JSON with the number of items added and per-item results
+ */
+#[post("/cart/batch")]
+async fn add_to_cart_batch_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>, batch_req: web::Json<CartBatchRequest>) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
         Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    match db::create_vendor_report(&pool, customer_id, report_req.vendor_id, report_req.product_id, &report_req.report_type, report_req.description.as_deref()).await {
-        Ok(report) => Ok(HttpResponse::Created().json(report)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create report")),
+    if batch_req.items.is_empty() {
+        return Ok(HttpResponse::BadRequest().json("items must not be empty"));
+    }
+    if batch_req.items.len() > MAX_CART_BATCH_ITEMS {
+        return Ok(HttpResponse::BadRequest().json(format!("At most {} items can be added at once", MAX_CART_BATCH_ITEMS)));
     }
+
+    let max_items = settings::get_i64(&pool, settings::MAX_CART_ITEMS).await;
+    let max_quantity = settings::get_i64(&pool, settings::MAX_CART_ITEM_QUANTITY).await;
+    let items: Vec<(i32, i32)> = batch_req.items.iter().map(|item| (item.product_id, item.quantity)).collect();
+    let outcomes = match db::add_to_cart_batch(&pool, user_id, &items, max_items, max_quantity).await {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            eprintln!("Cart batch failed: {:?}", e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to add items to cart"));
+        }
+    };
+
+    let results: Vec<CartBatchResult> = items
+        .iter()
+        .zip(outcomes)
+        .map(|(&(product_id, quantity), outcome)| {
+            let (error, reason) = match outcome {
+                Ok(()) => (None, None),
+                Err(db::CartBatchRejection::InvalidQuantity) => (Some("Quantity must be at least 1".to_string()), Some("invalid_quantity")),
+                Err(db::CartBatchRejection::ProductNotFound) => (Some("Product not found".to_string()), Some("product_not_found")),
+                Err(db::CartBatchRejection::VendorPaused) => {
+                    (Some("This vendor is away and isn't taking orders right now".to_string()), Some("vendor_paused"))
+                }
+                Err(db::CartBatchRejection::MaxCartItems) => {
+                    (Some(format!("A cart can hold at most {} different products", max_items)), Some("max_cart_items"))
+                }
+                Err(db::CartBatchRejection::MaxItemQuantity) => {
+                    (Some(format!("At most {} of a product can be in the cart", max_quantity)), Some("max_cart_item_quantity"))
+                }
+                Err(db::CartBatchRejection::OutOfStock { available }) => {
+                    (Some(format!("Only {} in stock", available)), Some("out_of_stock"))
+                }
+            };
+            CartBatchResult { product_id, quantity, success: error.is_none(), error, reason: reason.map(str::to_string) }
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "added": results.iter().filter(|r| r.success).count(),
+        "results": results
+    })))
 }
 
-#[get("/vendor/reports/count")]
-async fn get_vendor_report_count(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
-    let claims = match extract_auth(&req) {
-        Ok(c) => c,
+/**
+ * PUT /cart/{item_id} - Update cart item quantity
+ *
+ * Updates the quantity of a specific item in the authenticated customer's cart.
+ * User can only update their own cart items.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param item_id - Cart item ID from URL path
+ * @param update_req - JSON request with new quantity
+ * @returns JSON of the updated cart item
+ */
+#[patch("/cart/{item_id}")]
+async fn update_cart_item(req: actix_web::HttpRequest, pool: web::Data<PgPool>, item_id: web::Path<i32>, update_req: web::Json<UpdateCartItemRequest>) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    match db::count_vendor_reports(&pool, claims.sub).await {
-        Ok(count) => Ok(HttpResponse::Ok().json(json!({ "report_count": count }))),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to count reports")),
+    if update_req.quantity < 1 {
+        return Ok(HttpResponse::BadRequest().json("Quantity must be at least 1"));
     }
-}
-
-#[get("/api/admin/reports")]
-async fn get_all_vendor_reports_route(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
+    let max_quantity = settings::get_i64(&pool, settings::MAX_CART_ITEM_QUANTITY).await;
+    if i64::from(update_req.quantity) > max_quantity {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("At most {} of a product can be in the cart", max_quantity),
+            "reason": "max_cart_item_quantity"
+        })));
     }
 
-    match db::get_all_vendor_reports(&pool).await {
-        Ok(reports) => Ok(HttpResponse::Ok().json(reports)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch reports")),
+    match db::update_cart_item_quantity(&pool, *item_id, user_id, update_req.quantity).await {
+        Ok(cart_item) => Ok(HttpResponse::Ok().json(cart_item)),
+        Err(_) => Ok(HttpResponse::BadRequest().json("Cart item not found or access denied")),
     }
 }
 
-#[derive(Deserialize)]
-struct UpdateReportStatusRequest {
-    status: String,
-    admin_notes: Option<String>,
-}
-
-#[patch("/api/admin/reports/{report_id}")]
-async fn update_vendor_report_status_route(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    report_id: web::Path<i32>,
-    update_req: web::Json<UpdateReportStatusRequest>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
-    }
+/**
+ * DELETE /cart/{item_id} - Remove item from cart
+ *
+ * Removes a specific item from the authenticated customer's shopping cart.
+ * User can only remove their own cart items.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param item_id - Cart item ID from URL path
+ * @returns Empty response on success
+ */
+#[delete("/cart/{item_id}")]
+async fn remove_from_cart_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>, item_id: web::Path<i32>) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
 
-    match db::update_report_status(&pool, *report_id, &update_req.status, update_req.admin_notes.as_deref()).await {
-        Ok(_) => Ok(HttpResponse::Ok().json("Report status updated successfully")),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update report status")),
+    match db::remove_from_cart_with_user(&pool, *item_id, user_id).await {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(_) => Ok(HttpResponse::BadRequest().json("Cart item not found or access denied")),
     }
 }
 
-#[derive(Serialize)]
-struct DatabaseInfo {
-    name: String,
-    owner: String,
-    encoding: String,
-}
+// Global M-Pesa client instance
+static MPESA_CLIENT: OnceLock<Option<MpesaClient>> = OnceLock::new();
 
-#[get("/api/admin/databases")]
-async fn get_databases(
-    req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
-) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
-    }
+fn get_mpesa_client() -> Option<&'static MpesaClient> {
+    MPESA_CLIENT.get_or_init(|| {
+        match MpesaConfig::from_env() {
+            Ok(config) => Some(MpesaClient::new(config)),
+            Err(e) => {
+                eprintln!("Failed to initialize M-Pesa client: {}", e);
+                None
+            }
+        }
+    }).as_ref()
+}
+
+/**
+ * POST /checkout - Process M-Pesa payment using Daraja API
+ *
+ * Initiates M-Pesa STK Push payment for the authenticated customer's cart items.
+ * Integrates with Safaricom's M-Pesa Daraja API for real payments.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param checkout_req - JSON request with M-Pesa number and total amount
+ * @returns JSON response with transaction details
+ */
+#[post("/checkout")]
+async fn checkout(req: actix_web::HttpRequest, pool: web::Data<PgPool>, checkout_req: web::Json<CheckoutRequest>) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+
+    // Validate M-Pesa phone number format
+    let phone_number = &checkout_req.mpesa_number;
+    if !is_valid_kenyan_phone(phone_number) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "Invalid phone number",
+            "message": "Please enter a valid Kenyan phone number (07XXXXXXXX, 254XXXXXXXXX, or +254XXXXXXXXX)"
+        })));
+    }
+
+    // Validate minimum amount (M-Pesa minimum is KSh 1)
+    if checkout_req.total_amount < 1.0 {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "Invalid amount", 
+            "message": "Amount must be at least KSh 1"
+        })));
+    }
+
+    println!("🔄 Checkout initiated for phone: {} | Amount: KSh {:.2}", phone_number, checkout_req.total_amount);
+
+    // Get user's cart items to verify they have items
+    match db::get_cart_items(&pool, user_id).await {
+        Ok(all_cart_items) => {
+            if all_cart_items.is_empty() {
+                return Ok(HttpResponse::BadRequest().json("Cart is empty"));
+            }
+
+            // Filter cart items based on selected_items if provided
+            let cart_items: Vec<_> = if let Some(selected) = &checkout_req.selected_items {
+                all_cart_items.into_iter()
+                    .filter(|item| selected.contains(&item.id))
+                    .collect()
+            } else {
+                all_cart_items
+            };
+
+            if cart_items.is_empty() {
+                return Ok(HttpResponse::BadRequest().json("No valid items selected for checkout"));
+            }
+
+            // Calculate total from selected cart items for reference
+            let calculated_total: f64 = cart_items.iter()
+                .map(|item| item.product.price * item.quantity as f64)
+                .sum();
+            
+            // Round to 2 decimal places to match frontend
+            let calculated_total = (calculated_total * 100.0).round() / 100.0;
+
+            // Items added before their vendor paused stay in the cart but can't be bought
+            let mut cart_vendor_ids: Vec<i32> = cart_items.iter().map(|item| item.product.vendor_id as i32).collect();
+            cart_vendor_ids.sort_unstable();
+            cart_vendor_ids.dedup();
+            match db::paused_vendor_ids(&pool, &cart_vendor_ids).await {
+                Ok(paused) => {
+                    if let Some(vendor_id) = paused.into_iter().min() {
+                        return Ok(HttpResponse::BadRequest().json(json!({
+                            "error": "Vendor paused",
+                            "message": format!("Vendor {} is away and isn't taking orders right now. Remove their items to check out.", vendor_id),
+                            "vendor_id": vendor_id
+                        })));
+                    }
+                }
+                Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to check vendors")),
+            }
+
+            // Price shipping per vendor group; a selection must be one of that vendor's options
+            let shipping_quotes = match shipping::quote(&pool, user_id, &cart_items, &checkout_req.shipping_selections).await {
+                Ok(quotes) => quotes,
+                Err(shipping::QuoteError::Database(e)) => {
+                    eprintln!("❌ Failed to quote shipping: {:?}", e);
+                    return Ok(HttpResponse::InternalServerError().json("Failed to calculate shipping"));
+                }
+                Err(e) => {
+                    return Ok(HttpResponse::BadRequest().json(json!({
+                        "error": "Invalid shipping option",
+                        "message": e.to_string()
+                    })));
+                }
+            };
+            if let Some(quote) = shipping_quotes.iter().find(|q| q.is_unshippable()) {
+                return Ok(HttpResponse::BadRequest().json(json!({
+                    "error": "Shipping unavailable",
+                    "message": format!("None of vendor {}'s shipping options can deliver to you. Add your location to your profile and try again.", quote.vendor_id),
+                    "vendor_id": quote.vendor_id
+                })));
+            }
+            if let Some(quote) = shipping_quotes.iter().find(|q| q.shortfall().is_some()) {
+                let shortfall = quote.shortfall().unwrap_or_default();
+                return Ok(HttpResponse::BadRequest().json(json!({
+                    "error": "Below vendor minimum order",
+                    "message": format!(
+                        "{} only accepts orders of KSh {:.2} or more. Add KSh {:.2} of their products to check out.",
+                        quote.vendor_username, quote.min_order_value, shortfall
+                    ),
+                    "vendor_id": quote.vendor_id,
+                    "vendor_username": quote.vendor_username,
+                    "min_order_value": quote.min_order_value,
+                    "subtotal": quote.subtotal,
+                    "shortfall": shortfall
+                })));
+            }
+            let shipping_charges = shipping::charges(&shipping_quotes);
+            let shipping_total: f64 = shipping_charges.iter().map(|c| c.fee).sum();
+            let shipping_total = (shipping_total * 100.0).round() / 100.0;
+
+            // Vendor coupons, each discounting only its own vendor's items
+            let coupon_discounts = match coupons::apply(&pool, &cart_items, &checkout_req.coupon_codes).await {
+                Ok(discounts) => discounts,
+                Err(coupons::CouponError::Database(e)) => {
+                    eprintln!("❌ Failed to apply coupons: {:?}", e);
+                    return Ok(HttpResponse::InternalServerError().json("Failed to apply coupons"));
+                }
+                Err(e) => {
+                    return Ok(HttpResponse::BadRequest().json(json!({
+                        "error": "Invalid coupon",
+                        "message": e.to_string()
+                    })));
+                }
+            };
+            let discount_total: f64 = coupon_discounts.iter().map(|d| d.amount).sum();
+            let discount_total = (discount_total * 100.0).round() / 100.0;
+
+            // Prices include VAT, so this is only reported, not added to the total
+            let tax_total = match tax::TaxRates::load(&pool).await {
+                Ok(rates) => tax::cart_tax(&rates, &cart_items, &coupon_discounts),
+                Err(e) => {
+                    eprintln!("❌ Failed to load VAT rates: {:?}", e);
+                    return Ok(HttpResponse::InternalServerError().json("Failed to calculate VAT"));
+                }
+            };
+
+            // Allow custom amounts - no longer enforce cart total match
+            // Users can pay any amount they want (as low as 1 KSh)
+            // This allows flexible payments, partial payments, tips, etc.
+            println!("💳 Payment request: KSh {:.2} (Cart total: KSh {:.2} + shipping KSh {:.2} - discounts KSh {:.2})", checkout_req.total_amount, calculated_total, shipping_total, discount_total);
+            
+            // Only validate that amount is reasonable (>= 1 KSh)
+            if checkout_req.total_amount < 1.0 {
+                return Ok(HttpResponse::BadRequest().json(json!({
+                    "error": "Invalid amount",
+                    "message": "Minimum payment amount is KSh 1",
+                    "requested_total": checkout_req.total_amount
+                })));
+            }
+
+            // Hold the stock while the customer pays so concurrent checkouts can't oversell it
+            let reservation = format!("HOLD_{}", uuid::Uuid::new_v4().simple());
+            let reserved_items: Vec<(i32, i32)> = cart_items.iter().map(|item| (item.product_id, item.quantity)).collect();
+            match db::reserve_stock(&pool, user_id, &reservation, &reserved_items, reservations::hold_minutes(&pool).await).await {
+                Ok(()) => {}
+                Err(db::ReservationError::OutOfStock { product_id, name, available }) => {
+                    return Ok(HttpResponse::Conflict().json(json!({
+                        "error": "Insufficient stock",
+                        "message": format!("Only {} of {} left in stock", available, name),
+                        "product_id": product_id,
+                        "available": available
+                    })));
+                }
+                Err(db::ReservationError::Database(e)) => {
+                    eprintln!("❌ Failed to reserve stock: {:?}", e);
+                    return Ok(HttpResponse::InternalServerError().json("Failed to reserve stock"));
+                }
+            }
+
+            if is_demo_mode() {
+                println!("DEMO_MODE enabled, simulating payment");
+                return demo_checkout(pool, user_id, &cart_items, &shipping_charges, &coupon_discounts, tax_total, &checkout_req, &reservation).await;
+            }
+
+            // Get M-Pesa client
+            let mpesa_client = match get_mpesa_client() {
+                Some(client) => client,
+                None => {
+                    release_reservation(&pool, &reservation).await;
+                    return Ok(HttpResponse::ServiceUnavailable().json(json!({
+                        "error": "Payment failed",
+                        "message": "M-Pesa payments are not configured on this server.",
+                        "retry": false
+                    })));
+                }
+            };
+
+            // Prepare STK Push parameters
+            let formatted_phone = format_kenyan_phone(phone_number);
+            let account_reference = format!("FM_{}", user_id); // Farmers Market + user ID
+            let transaction_desc = "Farmers Market Purchase";
+
+            println!("📱 Initiating STK Push to: {} (request {})", formatted_phone, request_id::current().unwrap_or_default());
+            println!("💰 Amount: KSh {:.2}", checkout_req.total_amount);
+
+            // Initiate STK Push
+            match mpesa_client.stk_push(
+                formatted_phone.clone(),
+                checkout_req.total_amount,
+                account_reference,
+                transaction_desc.to_string(),
+            ).await {
+                Ok(stk_response) => {
+                    println!("✅ STK Push initiated successfully");
+                    println!("📋 Transaction ID: {}", stk_response.checkout_request_i_d);
+                    if let Err(e) = db::attach_stock_reservation(&pool, &reservation, &stk_response.checkout_request_i_d).await {
+                        eprintln!("❌ Failed to attach stock reservation {}: {:?}", reservation, e);
+                    }
+
+                    // Convert selected cart item IDs to comma-separated string
+                    let cart_item_ids_str = if let Some(selected) = &checkout_req.selected_items {
+                        Some(selected.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(","))
+                    } else {
+                        // If no items selected, get all cart item IDs
+                        let all_ids = cart_items.iter().map(|item| item.id.to_string()).collect::<Vec<_>>().join(",");
+                        Some(all_ids)
+                    };
+
+                    // Store payment transaction in database with selected cart items
+                    match db::create_payment_transaction(
+                        &pool,
+                        user_id,
+                        &stk_response.checkout_request_i_d,
+                        &stk_response.merchant_request_i_d,
+                        &formatted_phone,
+                        checkout_req.total_amount,
+                        cart_item_ids_str.as_deref(),
+                        request_id::current().as_deref(),
+                    ).await {
+                        Ok(transaction_id) => {
+                            println!("💾 Payment transaction stored with ID: {}", transaction_id);
+                            store_shipping_charges(&pool, &stk_response.checkout_request_i_d, &shipping_charges).await;
+                            store_coupon_discounts(&pool, &stk_response.checkout_request_i_d, &coupon_discounts).await;
+                        }
+                        Err(e) => {
+                            eprintln!("❌ Failed to store payment transaction: {:?}", e);
+                            // Continue anyway - payment can still succeed
+                        }
+                    }
+
+                    // Log M-Pesa response details
+                    println!("📊 M-Pesa Response Code: {}", stk_response.response_code);
+                    println!("📝 M-Pesa Description: {}", stk_response.response_description);
+
+                    // Return success response to frontend
+                    let response = CheckoutResponse {
+                        transaction_id: stk_response.checkout_request_i_d,
+                        message: if !stk_response.customer_message.is_empty() {
+                            stk_response.customer_message
+                        } else {
+                            format!("Payment request sent to {}. Check your phone and enter your M-Pesa PIN to complete the payment.", phone_number)
+                        },
+                        status: if stk_response.response_code == "0" {
+                            "initiated".to_string()
+                        } else {
+                            "pending".to_string()
+                        },
+                        shipping_total,
+                        discount_total,
+                        tax_total,
+                    };
+
+                    Ok(HttpResponse::Ok().json(response))
+                }
+                Err(e) => {
+                    eprintln!("❌ STK Push failed: {:?}", e);
+                    release_reservation(&pool, &reservation).await;
+                    
+                    Ok(HttpResponse::ServiceUnavailable().json(json!({
+                        "error": "Payment failed",
+                        "message": stk_error_message(&e),
+                        "retry": true,
+                        "phone_number": phone_number
+                    })))
+                }
+            }
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch cart items")),
+    }
+}
+
+/// User-friendly explanation of a failed STK push.
+fn stk_error_message(error: &MpesaError) -> &'static str {
+    match error {
+        MpesaError::InsufficientFunds => "Insufficient balance. Please top up your M-Pesa account and try again.",
+        MpesaError::Timeout | MpesaError::Network(_) => "Request timeout. Please check your network connection and try again.",
+        MpesaError::InvalidPhone => "Invalid phone number. Please check and try again.",
+        MpesaError::Duplicate => "A payment request is already pending for this transaction. Please wait a moment and try again.",
+        MpesaError::Auth(_) | MpesaError::Api { .. } => "Payment service temporarily unavailable. Please try again in a few minutes.",
+    }
+}
+
+/// Whether checkout should simulate payments instead of calling M-Pesa (`DEMO_MODE=true`).
+fn is_demo_mode() -> bool {
+    matches!(std::env::var("DEMO_MODE").as_deref(), Ok("true") | Ok("1"))
+}
+
+/**
+ * Demo checkout (DEMO_MODE=true)
+ *
+ * Records a payment transaction exactly like an STK push would, marks it completed
+ * as the M-Pesa callback would, and finalizes it through the same shared path.
+ */
+#[allow(clippy::too_many_arguments)]
+async fn demo_checkout(
+    pool: web::Data<PgPool>,
+    user_id: i32,
+    cart_items: &[crate::models::CartItem],
+    shipping_charges: &[shipping::ShippingCharge],
+    coupon_discounts: &[coupons::CouponDiscount],
+    tax_total: f64,
+    checkout_req: &CheckoutRequest,
+    reservation: &str,
+) -> ActixResult<HttpResponse> {
+    // Generate transaction ID (demo mode)
+    let transaction_id = format!("DEMO_TXN_{}_{}", user_id, uuid::Uuid::new_v4().simple());
+    if let Err(e) = db::attach_stock_reservation(&pool, reservation, &transaction_id).await {
+        eprintln!("❌ Failed to attach stock reservation {}: {:?}", reservation, e);
+    }
+    let cart_item_ids = cart_items.iter().map(|item| item.id.to_string()).collect::<Vec<_>>().join(",");
+    let formatted_phone = format_kenyan_phone(&checkout_req.mpesa_number);
+
+    if let Err(e) = db::create_payment_transaction(
+        &pool,
+        user_id,
+        &transaction_id,
+        "DEMO",
+        &formatted_phone,
+        checkout_req.total_amount,
+        Some(&cart_item_ids),
+        request_id::current().as_deref(),
+    ).await {
+        eprintln!("❌ Failed to store demo payment transaction: {:?}", e);
+        return Ok(HttpResponse::InternalServerError().json("Failed to record payment"));
+    }
+    store_shipping_charges(&pool, &transaction_id, shipping_charges).await;
+    store_coupon_discounts(&pool, &transaction_id, coupon_discounts).await;
+
+    let transaction_date = chrono::Utc::now().format("%Y%m%d%H%M%S").to_string();
+    if let Err(e) = db::update_payment_transaction(
+        &pool,
+        &transaction_id,
+        &PaymentStatus::Completed.to_string(),
+        Some(&transaction_id),
+        Some(&transaction_date),
+    ).await {
+        eprintln!("❌ Failed to complete demo payment transaction: {:?}", e);
+        return Ok(HttpResponse::InternalServerError().json("Failed to record payment"));
+    }
+
+    let transaction = match db::get_payment_transaction_by_checkout_request_id(&pool, &transaction_id).await {
+        Ok(t) => t,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to record payment")),
+    };
+    finalize_successful_payment(&pool, &transaction).await;
+
+    let response = CheckoutResponse {
+        transaction_id: transaction_id.clone(),
+        message: "DEMO MODE: Payment simulated successfully. Your orders have been created.".to_string(),
+        status: PaymentStatus::Completed.to_string(),
+        shipping_total: shipping_charges.iter().map(|c| c.fee).sum(),
+        discount_total: coupon_discounts.iter().map(|d| d.amount).sum(),
+        tax_total,
+    };
+
+    println!("Demo payment completed - User: {}, Phone: {}, Amount: {:.2}, Transaction: {}",
+            user_id, checkout_req.mpesa_number, checkout_req.total_amount, transaction_id);
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Put a checkout's held stock back, logging rather than failing on errors.
+async fn release_reservation(pool: &PgPool, checkout_request_id: &str) {
+    if let Err(e) = db::release_stock_reservation(pool, checkout_request_id).await {
+        eprintln!("❌ Failed to release stock reserved for {}: {:?}", checkout_request_id, e);
+    }
+}
+
+/// Record the shipping quoted at checkout so finalization can put it on the orders.
+async fn store_shipping_charges(pool: &PgPool, checkout_request_id: &str, charges: &[shipping::ShippingCharge]) {
+    let charges = match serde_json::to_string(charges) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("❌ Failed to encode shipping charges: {:?}", e);
+            return;
+        }
+    };
+    if let Err(e) = db::set_payment_shipping_charges(pool, checkout_request_id, &charges).await {
+        eprintln!("❌ Failed to store shipping charges for {}: {:?}", checkout_request_id, e);
+    }
+}
+
+/// Record the coupon discounts applied at checkout so finalization can put them on the orders.
+async fn store_coupon_discounts(pool: &PgPool, checkout_request_id: &str, discounts: &[coupons::CouponDiscount]) {
+    if discounts.is_empty() {
+        return;
+    }
+    let discounts = match serde_json::to_string(discounts) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("❌ Failed to encode coupon discounts: {:?}", e);
+            return;
+        }
+    };
+    if let Err(e) = db::set_payment_coupon_discounts(pool, checkout_request_id, &discounts).await {
+        eprintln!("❌ Failed to store coupon discounts for {}: {:?}", checkout_request_id, e);
+    }
+}
+
+/// Turn a completed payment into an order: pick the cart items recorded on the
+/// transaction (all items for older records), add a shipping order for each as
+/// a line item and remove it from the cart. Items that fail stay in the cart, so
+/// running this again for the same payment adds just those to the payment's
+/// existing order. Shared by the M-Pesa callback, callback retries, manual
+/// reprocessing and demo checkout. Returns (orders created, error messages).
+async fn finalize_successful_payment(
+    pool: &PgPool,
+    transaction: &crate::models::PaymentTransaction,
+) -> (usize, Vec<String>) {
+    let mut orders_created = 0;
+    let mut errors = Vec::new();
+
+    let all_cart_items = match db::get_cart_items(pool, transaction.user_id).await {
+        Ok(items) => items,
+        Err(e) => {
+            let error_msg = format!("Failed to get cart items: {:?}", e);
+            eprintln!("❌ {}", error_msg);
+            return (0, vec![error_msg]);
+        }
+    };
+
+    // Filter cart items based on what was actually selected for this payment
+    let items_to_process = if let Some(cart_item_ids_str) = &transaction.cart_item_ids {
+        let selected_ids: Vec<i32> = cart_item_ids_str
+            .split(',')
+            .filter_map(|id| id.trim().parse().ok())
+            .collect();
+
+        all_cart_items.into_iter()
+            .filter(|item| selected_ids.contains(&item.id))
+            .collect::<Vec<_>>()
+    } else {
+        all_cart_items
+    };
+
+    // Each vendor group's shipping fee goes on the first order created for that vendor
+    let mut shipping_charges: Vec<shipping::ShippingCharge> =
+        match db::get_payment_shipping_charges(pool, &transaction.checkout_request_id).await {
+            Ok(Some(json)) => serde_json::from_str(&json).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+
+    // Coupons discount each of their vendor's line items, and count once per payment
+    let coupon_discounts: Vec<coupons::CouponDiscount> =
+        match db::get_payment_coupon_discounts(pool, &transaction.checkout_request_id).await {
+            Ok(Some(json)) => serde_json::from_str(&json).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+
+    if items_to_process.is_empty() {
+        return (0, errors);
+    }
+
+    let tax_rates = match tax::TaxRates::load(pool).await {
+        Ok(rates) => rates,
+        Err(e) => {
+            eprintln!("❌ Failed to load VAT rates: {:?}", e);
+            tax::TaxRates::default()
+        }
+    };
+
+    // Everything paid for together becomes one order, with a line item per cart item.
+    // A payment processed before already has its order, shipping and coupon uses.
+    let existing_order = match db::get_payment_order(pool, transaction.id).await {
+        Ok(existing) => existing,
+        Err(e) => {
+            let error_msg = format!("Failed to look up order for payment: {:?}", e);
+            eprintln!("❌ {}", error_msg);
+            return (0, vec![error_msg]);
+        }
+    };
+    let first_pass = existing_order.is_none();
+    let order_id = match existing_order {
+        Some((order_id, ordered_vendors)) => {
+            shipping_charges.retain(|charge| !ordered_vendors.contains(&charge.vendor_id));
+            order_id
+        }
+        None => match db::create_order(pool, transaction.user_id, "Default shipping address - please update in your orders", Some(transaction.id)).await {
+            Ok(id) => id,
+            Err(e) => {
+                let error_msg = format!("Failed to create order: {:?}", e);
+                eprintln!("❌ {}", error_msg);
+                return (0, vec![error_msg]);
+            }
+        },
+    };
+
+    // Stock held at checkout is already deducted; anything else (e.g. a hold that expired) is deducted now
+    let reserved_products = match db::convert_stock_reservation(pool, &transaction.checkout_request_id).await {
+        Ok(products) => products,
+        Err(e) => {
+            eprintln!("❌ Failed to convert stock reservation for {}: {:?}", transaction.checkout_request_id, e);
+            Vec::new()
+        }
+    };
+
+    let mut ordered_items = Vec::new();
+    for item in &items_to_process {
+        let added = if reserved_products.contains(&item.product_id) {
+            db::add_reserved_order_item(pool, order_id, item.product_id, item.quantity).await
+        } else {
+            db::add_order_item(pool, order_id, item.product_id, item.quantity).await
+        };
+        match added {
+            Ok(order) => {
+                println!("✅ Shipping order created for product {} (qty: {})", item.product_id, item.quantity);
+                orders_created += 1;
+                ordered_items.push(item.id);
+
+                if let Err(e) = db::set_order_payment_transaction(pool, order.id, transaction.id).await {
+                    eprintln!("❌ Failed to link order {} to payment {}: {:?}", order.id, transaction.id, e);
+                }
+
+                if let Some(pos) = shipping_charges.iter().position(|c| c.vendor_id == order.vendor_id) {
+                    let charge = shipping_charges.remove(pos);
+                    if let Err(e) = db::set_order_shipping(pool, order.id, charge.option_id, charge.fee).await {
+                        let error_msg = format!("Failed to record shipping on order {}: {:?}", order.id, e);
+                        eprintln!("❌ {}", error_msg);
+                        errors.push(error_msg);
+                    }
+                }
+
+                let mut discount = 0.0;
+                if let Some(coupon) = coupon_discounts.iter().find(|d| d.vendor_id == order.vendor_id) {
+                    discount = coupons::line_discount(order.total_amount, coupon.percent_off);
+                    if let Err(e) = db::set_order_discount(pool, order.id, coupon.coupon_id, discount).await {
+                        let error_msg = format!("Failed to record coupon discount on order {}: {:?}", order.id, e);
+                        eprintln!("❌ {}", error_msg);
+                        errors.push(error_msg);
+                    }
+                }
+
+                let tax = tax::inclusive_tax(order.total_amount - discount, tax_rates.rate_for(&item.product.category));
+                if let Err(e) = db::set_order_tax(pool, order.id, tax).await {
+                    let error_msg = format!("Failed to record VAT on order {}: {:?}", order.id, e);
+                    eprintln!("❌ {}", error_msg);
+                    errors.push(error_msg);
+                }
+            }
+            Err(e) => {
+                let error_msg = format!("Failed to create shipping order for product {}: {:?}", item.product_id, e);
+                eprintln!("❌ {}", error_msg);
+                errors.push(error_msg);
+            }
+        }
+    }
+
+    if first_pass {
+        for coupon in &coupon_discounts {
+            match db::redeem_coupon(pool, coupon.coupon_id).await {
+                Ok(true) => {}
+                // Another checkout took the last use while this payment was pending
+                Ok(false) => {
+                    let error_msg = format!(
+                        "{} before payment {} completed; its discount was already charged",
+                        coupons::CouponError::UsedUp(coupon.code.clone()), transaction.checkout_request_id
+                    );
+                    eprintln!("❌ {}", error_msg);
+                    errors.push(error_msg);
+                }
+                Err(e) => eprintln!("❌ Failed to redeem coupon {}: {:?}", coupon.code, e),
+            }
+        }
+    }
+
+    // Clear only the items that made it onto the order; failed ones are picked up by a retry
+    for item_id in ordered_items {
+        if let Err(e) = db::remove_from_cart_with_user(pool, item_id, transaction.user_id).await {
+            eprintln!("❌ Failed to remove cart item {}: {:?}", item_id, e);
+        }
+    }
+
+    (orders_created, errors)
+}
+
+// Helper functions for M-Pesa phone number validation and formatting
+fn is_valid_kenyan_phone(phone: &str) -> bool {
+    // Remove spaces and common separators
+    let clean_phone = phone.replace(&[' ', '-', '(', ')'][..], "");
+    
+    // Check various Kenyan phone number formats
+    if clean_phone.starts_with("07") && clean_phone.len() == 10 {
+        // 07XXXXXXXX format
+        clean_phone.chars().all(|c| c.is_ascii_digit())
+    } else if clean_phone.starts_with("254") && clean_phone.len() == 12 {
+        // 254XXXXXXXXX format
+        clean_phone.chars().all(|c| c.is_ascii_digit())
+    } else if clean_phone.starts_with("+254") && clean_phone.len() == 13 {
+        // +254XXXXXXXXX format
+        clean_phone[1..].chars().all(|c| c.is_ascii_digit())
+    } else {
+        false
+    }
+}
+
+fn format_kenyan_phone(phone: &str) -> String {
+    let clean_phone = phone.replace(&[' ', '-', '(', ')'][..], "");
+    
+    if clean_phone.starts_with("07") {
+        format!("254{}", &clean_phone[1..])
+    } else if clean_phone.starts_with("+254") {
+        clean_phone[1..].to_string()
+    } else if clean_phone.starts_with("254") {
+        clean_phone
+    } else {
+        // Default fallback - assume it's a 9-digit number without country code
+        format!("254{}", clean_phone)
+    }
+}
+
+// Auth helpers
+fn extract_auth(req: &actix_web::HttpRequest) -> Result<Claims, HttpResponse> {
+    let auth_header = req.headers().get(AUTHORIZATION);
+    if auth_header.is_none() {
+        return Err(HttpResponse::Unauthorized().json("Authorization header missing"));
+    }
+
+    let auth_header_value = auth_header.unwrap();
+    let auth_str = match auth_header_value.to_str() {
+        Ok(s) => s,
+        Err(_) => return Err(HttpResponse::BadRequest().json("Invalid authorization header encoding")),
+    };
+
+    if !auth_str.starts_with("Bearer ") {
+        return Err(HttpResponse::Unauthorized().json("Invalid authorization format"));
+    }
+
+    let token = &auth_str[7..]; // Remove "Bearer " prefix
+
+    match verify_jwt(token) {
+        Ok(claims) => Ok(claims),
+        Err(_) => Err(HttpResponse::Unauthorized().json("Invalid token")),
+    }
+}
+
+fn check_admin_auth(req: &actix_web::HttpRequest) -> Result<(), HttpResponse> {
+    let claims = extract_auth(req)?;
+    if claims.role != "Admin" {
+        return Err(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+    Ok(())
+}
+
+fn check_vendor_auth(req: &actix_web::HttpRequest) -> Result<i32, HttpResponse> {
+    let claims = extract_auth(req)?;
+    if claims.role != "Vendor" {
+        return Err(HttpResponse::Forbidden().json("Vendor privileges required"));
+    }
+    Ok(claims.sub)
+}
+
+/// Only verified vendors can list or edit products, and not while suspended
+/// because their weighted report score reached the threshold.
+async fn check_vendor_can_sell(pool: &PgPool, vendor_id: i32) -> Result<(), HttpResponse> {
+    let verified: bool = match sqlx::query_scalar("SELECT verified FROM users WHERE id = $1")
+        .bind(vendor_id)
+        .fetch_one(pool)
+        .await {
+        Ok(verified) => verified,
+        Err(_) => return Err(HttpResponse::InternalServerError().json("Failed to check verification status")),
+    };
+    if !verified {
+        return Err(HttpResponse::Forbidden().json("Account not verified. Please wait for admin verification."));
+    }
+
+    match db::is_vendor_suspended(pool, vendor_id).await {
+        Ok(true) => Err(HttpResponse::Forbidden().json("Account suspended due to multiple reports.")),
+        Ok(false) => Ok(()),
+        Err(e) => {
+            eprintln!("Database error checking suspension: {:?}", e);
+            Err(HttpResponse::InternalServerError().json("Failed to check report count"))
+        }
+    }
+}
+
+fn check_customer_auth(req: &actix_web::HttpRequest) -> Result<i32, HttpResponse> {
+    let claims = extract_auth(req)?;
+    if claims.role != "Customer" {
+        return Err(HttpResponse::Forbidden().json("Customer privileges required"));
+    }
+    Ok(claims.sub)
+}
+
+fn extract_query_param(query_string: &str, param_name: &str) -> Option<String> {
+    let params = query_string.trim_start_matches('?');
+    url::form_urlencoded::parse(params.as_bytes())
+        .find(|(key, _)| key == param_name)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Default and largest `limit` for paginated listings.
+const DEFAULT_PAGE_LIMIT: i64 = 50;
+const MAX_PAGE_LIMIT: i64 = 200;
+
+/// `limit`/`offset` of a listing request, or None when neither is given and
+/// the caller expects the whole listing as a bare array.
+fn page_params(query: &str) -> Result<Option<(i64, i64)>, HttpResponse> {
+    let limit = parse_query_param::<i64>(query, "limit")?;
+    let offset = parse_query_param::<i64>(query, "offset")?;
+    if limit.is_none() && offset.is_none() {
+        return Ok(None);
+    }
+    Ok(Some((
+        limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT),
+        offset.unwrap_or(0).max(0),
+    )))
+}
+
+/// Respond with `items` as a `Paginated` envelope when paging was requested,
+/// otherwise as a bare array.
+fn listing_response<T: Serialize>(items: Vec<T>, page: Option<(i64, i64)>) -> HttpResponse {
+    match page {
+        Some((limit, offset)) => HttpResponse::Ok().json(Paginated::from_all(items, limit, offset)),
+        None => HttpResponse::Ok().json(items),
+    }
+}
+
+/// 200 with `{success: true, data}`.
+fn ok<T: Serialize>(data: T) -> HttpResponse {
+    HttpResponse::Ok().json(ApiSuccess { success: true, message: None, data: Some(data) })
+}
+
+/// 200 with `{success: true, message}`, for writes with nothing to return.
+fn ok_message(message: impl Into<String>) -> HttpResponse {
+    HttpResponse::Ok().json(ApiSuccess::<()> { success: true, message: Some(message.into()), data: None })
+}
+
+/// 200 with `{success: true, message, data}`.
+fn ok_with_message<T: Serialize>(message: impl Into<String>, data: T) -> HttpResponse {
+    HttpResponse::Ok().json(ApiSuccess { success: true, message: Some(message.into()), data: Some(data) })
+}
+
+// ADMIN ROUTES
+#[get("/api/admin/users")]
+async fn get_all_users(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+    let page = match page_params(req.query_string()) {
+        Ok(page) => page,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_all_users(&pool).await {
+        Ok(users) => Ok(listing_response(users, page)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch users")),
+    }
+}
+
+/// GET /users - Get all users for messaging (public, but requires authentication)
+#[get("/users")]
+async fn get_all_users_for_messaging(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    // Verify user is authenticated
+    let current_user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+    let page = match page_params(req.query_string()) {
+        Ok(page) => page,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_all_users(&pool).await {
+        Ok(users) => {
+            // Check if current users are following each other (mutual friends)
+            let following_ids: Vec<i32> = sqlx::query_scalar(
+                "SELECT vendor_id FROM follows WHERE follower_id = $1"
+            )
+            .bind(current_user_id)
+            .fetch_all(pool.get_ref())
+            .await
+            .unwrap_or_default();
+
+            let followers_ids: Vec<i32> = sqlx::query_scalar(
+                "SELECT follower_id FROM follows WHERE vendor_id = $1"
+            )
+            .bind(current_user_id)
+            .fetch_all(pool.get_ref())
+            .await
+            .unwrap_or_default();
+
+            let blocked_ids = match db::get_blocked_user_ids(&pool, current_user_id).await {
+                Ok(ids) => ids,
+                Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch users")),
+            };
+
+            // Filter out the current user, admins and blocked users, then return enriched data
+            let filtered_users: Vec<_> = users
+                .into_iter()
+                .filter(|u| u.id != current_user_id && !matches!(u.role, Role::Admin) && !blocked_ids.contains(&u.id))
+                .map(|u| public_user_json(&u, following_ids.contains(&u.id), followers_ids.contains(&u.id)))
+                .collect();
+            Ok(listing_response(filtered_users, page))
+        },
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch users")),
+    }
+}
+
+/// A user as other users see them. Contact details are only shared between
+/// mutual followers.
+fn public_user_json(u: &crate::models::User, is_followed: bool, is_following_back: bool) -> serde_json::Value {
+    let is_mutual_friend = is_followed && is_following_back;
+    serde_json::json!({
+        "id": u.id,
+        "username": u.username,
+        "role": u.role,
+        "profile_image": u.profile_image,
+        // Only show personal info if mutual friends
+        "email": if is_mutual_friend { &u.secondary_email } else { &None },
+        "phone": if is_mutual_friend { &u.mpesa_number } else { &None },
+        "location": if is_mutual_friend { &u.location_string } else { &None },
+        // Follow status for UI
+        "is_followed": is_followed,
+        "is_following_back": is_following_back,
+        "is_mutual_friend": is_mutual_friend
+    })
+}
+
+/**
+ * GET /users/{user_id}/profile - A user's public profile
+ *
+ * Public fields, follow status and `follower_count` / `following_count`.
+ * Contact details (email, phone, location) are only included for mutual
+ * followers. Banned, deleted and blocked users are 404.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param user_id - User ID from URL path
+ * @returns JSON of the profile
+ */
+#[get("/users/{user_id}/profile")]
+async fn get_user_profile_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let viewer_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+    let user_id = user_id.into_inner();
+
+    let user = match db::get_visible_user(&pool, user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Ok(HttpResponse::NotFound().json("User not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch user")),
+    };
+    match db::is_blocked_between(&pool, viewer_id, user_id).await {
+        Ok(false) => {}
+        Ok(true) => return Ok(HttpResponse::NotFound().json("User not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch user")),
+    }
+
+    let relations = async {
+        let is_followed = db::is_following(&pool, viewer_id, user_id).await?;
+        let is_following_back = db::is_following(&pool, user_id, viewer_id).await?;
+        let (follower_count, following_count) = db::get_follow_counts(&pool, user_id).await?;
+        Ok::<_, sqlx::Error>((is_followed, is_following_back, follower_count, following_count))
+    };
+    let (is_followed, is_following_back, follower_count, following_count) = match relations.await {
+        Ok(relations) => relations,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch user")),
+    };
+
+    // You always see your own contact details
+    let mut profile = if viewer_id == user_id {
+        public_user_json(&user, true, true)
+    } else {
+        public_user_json(&user, is_followed, is_following_back)
+    };
+    profile["is_followed"] = json!(is_followed);
+    profile["is_following_back"] = json!(is_following_back);
+    profile["is_mutual_friend"] = json!(is_followed && is_following_back);
+    profile["follower_count"] = json!(follower_count);
+    profile["following_count"] = json!(following_count);
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+#[get("/api/admin/pending-vendors")]
+async fn get_pending_vendors(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::get_pending_vendors(&pool).await {
+        Ok(vendors) => Ok(HttpResponse::Ok().json(vendors)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch pending vendors")),
+    }
+}
+
+#[patch("/api/admin/users/{user_id}")]
+async fn update_user_role(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>,
+    request: web::Json<UpdateUserRoleRequest>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    let role = match request.role.as_str() {
+        "Admin" => Role::Admin,
+        "Vendor" => Role::Vendor,
+        "Customer" => Role::Customer,
+        _ => return Ok(HttpResponse::BadRequest().json("Invalid role")),
+    };
+
+    match db::update_user_role(&pool, *user_id, &role).await {
+        Ok(_) => Ok(ok_message("Role updated successfully")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update role")),
+    }
+}
+
+#[patch("/api/admin/users/{user_id}/verify")]
+async fn update_user_verification(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>,
+    request: web::Json<UpdateUserVerificationRequest>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    // Get user details before updating verification status
+    let user = match db::get_user_by_id(&pool, *user_id).await {
+        Ok(user) => user,
+        Err(_) => return Ok(HttpResponse::NotFound().json("User not found")),
+    };
+
+    match db::update_user_verification(&pool, *user_id, request.verified).await {
+        Ok(_) => {
+            // Send email notification based on verification status
+            let language = email_templates::user_language(&pool, user.id).await;
+            if request.verified {
+                if let Err(e) = email::send_verification_approval_email(&pool, &user.email, &user.username, &language).await {
+                    eprintln!("Failed to send approval email to {}: {:?}", user.email, e);
+                }
+            } else {
+                if let Err(e) = email::send_verification_rejection_email(&pool, &user.email, &user.username, &language).await {
+                    eprintln!("Failed to send rejection email to {}: {:?}", user.email, e);
+                }
+            }
+            
+            let status = if request.verified { "approved" } else { "rejected" };
+            Ok(ok_message(format!("Vendor {} and email notification sent", status)))
+        },
+        Err(e) => Ok(HttpResponse::InternalServerError().json(format!("Failed to update verification: {:?}", e))),
+    }
+}
+
+/// Most users PATCH /api/admin/users/verify-bulk accepts at once.
+const MAX_BULK_VERIFICATIONS: usize = 100;
+
+/**
+ * PATCH /api/admin/users/verify-bulk - Approve or reject several vendors at once
+ *
+ * Updates run in one transaction and each change is written to the audit log.
+ * Ids that don't belong to an active vendor are skipped and reported. Approved
+ * or rejected vendors are emailed as with single verification.
+ *
+ * @param req - HTTP request for admin authentication
+ * @param pool - Database connection pool
+ * @param bulk_req - JSON request with user_ids and verified
+ * @returns JSON with per-user results
+ */
+#[patch("/api/admin/users/verify-bulk")]
+async fn bulk_update_user_verification(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    bulk_req: web::Json<BulkVerificationRequest>
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(claims) => claims,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    let mut user_ids = bulk_req.user_ids.clone();
+    user_ids.sort_unstable();
+    user_ids.dedup();
+    if user_ids.is_empty() {
+        return Ok(HttpResponse::BadRequest().json("user_ids must not be empty"));
+    }
+    if user_ids.len() > MAX_BULK_VERIFICATIONS {
+        return Ok(HttpResponse::BadRequest().json(format!("At most {} users can be verified at once", MAX_BULK_VERIFICATIONS)));
+    }
+
+    let (roles, updated) = match db::bulk_update_user_verification(&pool, claims.sub, &user_ids, bulk_req.verified).await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            eprintln!("Bulk verification failed: {:?}", e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to update verification"));
+        }
+    };
+
+    for (id, email, username) in &updated {
+        let language = email_templates::user_language(&pool, *id).await;
+        let sent = if bulk_req.verified {
+            email::send_verification_approval_email(&pool, email, username, &language).await
+        } else {
+            email::send_verification_rejection_email(&pool, email, username, &language).await
+        };
+        if let Err(e) = sent {
+            eprintln!("Failed to send verification email to {}: {:?}", email, e);
+        }
+    }
+
+    let results: Vec<BulkVerificationResult> = user_ids
+        .iter()
+        .map(|&user_id| {
+            let error = match roles.get(&user_id) {
+                None => Some("User not found".to_string()),
+                Some(role) if role != "Vendor" => Some(format!("Skipped: user is a {}, not a vendor", role)),
+                Some(_) => None,
+            };
+            BulkVerificationResult { user_id, success: error.is_none(), error }
+        })
+        .collect();
+
+    Ok(ok(json!({
+        "updated": updated.len(),
+        "results": results
+    })))
+}
+
+/// Largest decoded verification document accepted.
+const MAX_VERIFICATION_DOCUMENT_BYTES: usize = 5 * 1024 * 1024;
+
+/// MIME type of a JPEG, PNG or PDF file, recognized by its leading bytes.
+fn detect_document_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        Some("image/png")
+    } else if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else {
+        None
+    }
+}
+
+/// Decode a verification document (a base64 data URL or bare base64) and return
+/// its detected MIME type. A data URL's declared type must match the contents.
+fn validate_verification_document(document: &str) -> Result<&'static str, String> {
+    use base64::Engine;
+
+    let (declared, data) = match document.strip_prefix("data:").and_then(|rest| rest.split_once(";base64,")) {
+        Some((declared, data)) => (Some(declared), data),
+        None => (None, document),
+    };
+    // Reject oversized payloads before spending time decoding them
+    if data.len() / 4 * 3 > MAX_VERIFICATION_DOCUMENT_BYTES + 3 {
+        return Err("Document is too large. Maximum size is 5MB".to_string());
+    }
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|_| "Verification document is not valid base64".to_string())?;
+    if bytes.len() > MAX_VERIFICATION_DOCUMENT_BYTES {
+        return Err("Document is too large. Maximum size is 5MB".to_string());
+    }
+    let content_type = detect_document_type(&bytes)
+        .ok_or_else(|| "Verification document must be a JPEG, PNG or PDF file".to_string())?;
+    if declared.is_some_and(|declared| !declared.eq_ignore_ascii_case(content_type)) {
+        return Err(format!("Verification document is a {} file, not {}", content_type, declared.unwrap_or_default()));
+    }
+    Ok(content_type)
+}
+
+/**
+ * POST /vendor/upload-verification - Upload vendor verification document
+ *
+ * Allows vendors to upload documents (ID, business license, etc.) for verification.
+ * The document is stored as Base64 encoded data; only JPEG, PNG and PDF files
+ * are accepted, and the detected type is stored alongside it.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - PostgreSQL connection pool
+ * @param request - JSON request body with verification_document (Base64 encoded)
+ * @returns JSON confirmation with the detected content type and whether it is a resubmission
+ */
+#[post("/vendor/upload-verification")]
+async fn upload_verification_document(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    request: web::Json<UploadVerificationDocumentRequest>
+) -> ActixResult<HttpResponse> {
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+
+    // Check that document is not empty
+    if request.verification_document.is_empty() {
+        return Ok(HttpResponse::BadRequest().json("Verification document cannot be empty"));
+    }
+
+    let content_type = match validate_verification_document(&request.verification_document) {
+        Ok(content_type) => content_type,
+        Err(reason) => return Ok(HttpResponse::BadRequest().json(reason)),
+    };
+
+    match db::upload_verification_document(&pool, vendor_id, &request.verification_document, content_type).await {
+        Ok(resubmission) => {
+            Ok(ok_with_message(
+                "Verification document submitted successfully. An administrator will review your submission.",
+                json!({ "content_type": content_type, "resubmission": resubmission }),
+            ))
+        },
+        Err(e) => Ok(HttpResponse::InternalServerError().json(format!("Failed to upload verification document: {:?}", e))),
+    }
+}
+
+/// GET /api/admin/users/{user_id}/verification-document - A vendor's submitted
+/// document with its `content_type` so it can be displayed appropriately.
+#[get("/api/admin/users/{user_id}/verification-document")]
+async fn get_verification_document(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::get_user_verification_document(&pool, *user_id).await {
+        Ok(Some((document, content_type))) => Ok(HttpResponse::Ok().json(json!({
+            "document": document,
+            "content_type": content_type
+        }))),
+        Ok(None) => Ok(HttpResponse::NotFound().json("No verification document submitted")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch verification document")),
+    }
+}
+
+/// PATCH /api/admin/users/{user_id}/verification-document/retention - Keep a
+/// vendor's verification document past the retention window (`retained: true`)
+/// or let it be purged as usual. Audited.
+#[patch("/api/admin/users/{user_id}/verification-document/retention")]
+async fn set_verification_document_retention(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>,
+    retention_req: web::Json<VerificationDocumentRetentionRequest>
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(claims) => claims,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    match db::set_verification_document_retained(&pool, claims.sub, *user_id, retention_req.retained).await {
+        Ok(true) => Ok(ok(json!({ "user_id": *user_id, "retained": retention_req.retained }))),
+        Ok(false) => Ok(HttpResponse::NotFound().json("No verification document submitted")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update document retention")),
+    }
+}
+
+#[delete("/api/admin/users/{user_id}")]
+async fn delete_user(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::delete_user(&pool, *user_id).await {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("User not found or already deleted")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to delete user")),
+    }
+}
+
+/// GET /api/admin/users/deleted - List soft-deleted accounts.
+#[get("/api/admin/users/deleted")]
+async fn get_deleted_users(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::get_deleted_users(&pool).await {
+        Ok(users) => Ok(HttpResponse::Ok().json(users.into_iter().map(|(id, username, role, deleted_at)| json!({
+            "id": id,
+            "username": username,
+            "role": role,
+            "deleted_at": deleted_at
+        })).collect::<Vec<_>>())),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch deleted users")),
+    }
+}
+
+/// PATCH /api/admin/users/{user_id}/reactivate - Restore a soft-deleted account.
+/// Anonymized details stay anonymized; reset the password to hand the account back.
+#[patch("/api/admin/users/{user_id}/reactivate")]
+async fn reactivate_user_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::reactivate_user(&pool, *user_id).await {
+        Ok(_) => Ok(ok_message("User reactivated successfully")),
+        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("User not found or not deleted")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to reactivate user")),
+    }
+}
+
+/**
+ * POST /api/admin/users/{user_id}/impersonate - Act as a user for support
+ *
+ * Returns a token for the user that expires after `IMPERSONATION_TTL_MINUTES`
+ * and carries an `impersonated_by` claim. Every request made with it is
+ * recorded in the audit log under both ids. Admins can't be impersonated.
+ *
+ * @param req - HTTP request for admin authentication
+ * @param pool - Database connection pool
+ * @param user_id - User to impersonate
+ * @returns JSON with token, session_id and expires_at
+ */
+#[post("/api/admin/users/{user_id}/impersonate")]
+async fn impersonate_user_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    let user = match db::get_user_by_id(&pool, *user_id).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("User not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to load user")),
+    };
+    if matches!(user.role, Role::Admin) {
+        return Ok(HttpResponse::Forbidden().json("Admins can't be impersonated"));
+    }
+
+    let session_id = uuid::Uuid::new_v4().simple().to_string();
+    let expires_at = chrono::Utc::now() + chrono::Duration::minutes(audit::IMPERSONATION_TTL_MINUTES);
+    if db::create_impersonation_session(&pool, &session_id, claims.sub, user.id, expires_at).await.is_err() {
+        return Ok(HttpResponse::InternalServerError().json("Failed to start impersonation"));
+    }
+    let token = match create_impersonation_jwt(&user, claims.sub, &session_id, expires_at) {
+        Ok(token) => token,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to start impersonation")),
+    };
+
+    let details = format!("session {}", session_id);
+    if let Err(e) = db::record_audit_event(&pool, Some(claims.sub), None, &format!("impersonation.start user {}", user.id), Some(&details)).await {
+        eprintln!("❌ Failed to audit impersonation start: {:?}", e);
+    }
+
+    Ok(ok(json!({
+        "token": token,
+        "session_id": session_id,
+        "expires_at": expires_at.to_rfc3339(),
+        "user_id": user.id,
+        "username": user.username
+    })))
+}
+
+/// DELETE /api/admin/impersonations/{session_id} - End an impersonation session early
+#[delete("/api/admin/impersonations/{session_id}")]
+async fn revoke_impersonation_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    session_id: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    match db::revoke_impersonation_session(&pool, &session_id).await {
+        Ok((admin_id, user_id)) => {
+            let details = format!("session {}; started by admin {}", session_id, admin_id);
+            if let Err(e) = db::record_audit_event(&pool, Some(claims.sub), None, &format!("impersonation.revoke user {}", user_id), Some(&details)).await {
+                eprintln!("❌ Failed to audit impersonation revoke: {:?}", e);
+            }
+            Ok(ok_message("Impersonation session revoked"))
+        }
+        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("Session not found or already revoked")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to revoke session")),
+    }
+}
+
+/// GET /api/admin/users/{user_id}/sessions - A user's active sessions
+#[get("/api/admin/users/{user_id}/sessions")]
+async fn get_user_sessions_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::get_active_user_sessions(&pool, *user_id).await {
+        Ok(sessions) => Ok(HttpResponse::Ok().json(sessions)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch sessions")),
+    }
+}
+
+/// DELETE /api/admin/users/{user_id}/sessions - Sign a user out everywhere, e.g.
+/// after their account is compromised. Existing access tokens last until they expire.
+#[delete("/api/admin/users/{user_id}/sessions")]
+async fn revoke_user_sessions_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    match db::revoke_all_user_sessions(&pool, *user_id).await {
+        Ok(revoked) => {
+            let details = format!("{} sessions", revoked);
+            if let Err(e) = db::record_audit_event(&pool, Some(claims.sub), None, &format!("sessions.revoke_all user {}", user_id), Some(&details)).await {
+                eprintln!("❌ Failed to audit session revocation: {:?}", e);
+            }
+            Ok(ok(json!({ "revoked": revoked })))
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to revoke sessions")),
+    }
+}
+
+/// GET /api/admin/audit-log - Recent audit entries (`?actor_id=`, `?impersonated_by=`, `?limit=`)
+#[get("/api/admin/audit-log")]
+async fn get_audit_log_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    let query = req.query_string();
+    let actor_id = extract_query_param(query, "actor_id").and_then(|v| v.parse().ok());
+    let impersonated_by = extract_query_param(query, "impersonated_by").and_then(|v| v.parse().ok());
+    let limit = extract_query_param(query, "limit")
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(100)
+        .clamp(1, 500);
+
+    match db::get_audit_log(&pool, actor_id, impersonated_by, limit).await {
+        Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch audit log")),
+    }
+}
+
+/// Default and largest page size for GET /api/admin/orders.
+const ADMIN_ORDERS_PAGE_SIZE: i64 = 50;
+const ADMIN_ORDERS_MAX_PAGE_SIZE: i64 = 200;
+
+/// Optional typed query parameter; present but unparsable values are a 400.
+fn parse_query_param<T: std::str::FromStr>(query: &str, name: &str) -> Result<Option<T>, HttpResponse> {
+    match extract_query_param(query, name).filter(|v| !v.trim().is_empty()) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| HttpResponse::BadRequest().json(format!("Invalid {}", name))),
+        None => Ok(None),
+    }
+}
+
+/// Filter, page and page size of an admin order search.
+fn order_search_params(query: &str) -> Result<(crate::models::OrderSearchFilter, i64, i64), HttpResponse> {
+    let filter = crate::models::OrderSearchFilter {
+        customer_id: parse_query_param(query, "customer_id")?,
+        vendor_id: parse_query_param(query, "vendor_id")?,
+        status: parse_query_param(query, "status")?,
+        created_from: parse_query_param(query, "from")?,
+        // `to` is inclusive, the filter's bound isn't
+        created_before: parse_query_param::<chrono::NaiveDate>(query, "to")?.and_then(|to| to.succ_opt()),
+        min_amount: parse_query_param(query, "min_amount")?,
+        max_amount: parse_query_param(query, "max_amount")?,
+    };
+    let page = parse_query_param::<i64>(query, "page")?.unwrap_or(1).max(1);
+    let per_page = parse_query_param::<i64>(query, "per_page")?
+        .unwrap_or(ADMIN_ORDERS_PAGE_SIZE)
+        .clamp(1, ADMIN_ORDERS_MAX_PAGE_SIZE);
+    Ok((filter, page, per_page))
+}
+
+/// GET /api/admin/orders - Search orders across the platform. Filters:
+/// `customer_id`, `vendor_id`, `status`, `from`/`to` (YYYY-MM-DD, inclusive),
+/// `min_amount`/`max_amount`; paged with `page` (from 1) and `per_page`.
+#[get("/api/admin/orders")]
+async fn search_orders_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    let (filter, page, per_page) = match order_search_params(req.query_string()) {
+        Ok(params) => params,
+        Err(response) => return Ok(response),
+    };
+
+    match db::search_orders(&pool, &filter, per_page, (page - 1).saturating_mul(per_page)).await {
+        Ok((orders, total)) => Ok(HttpResponse::Ok().json(json!({
+            "orders": orders,
+            "total": total,
+            "page": page,
+            "per_page": per_page
+        }))),
+        Err(e) => {
+            eprintln!("Failed to search orders: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to search orders"))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AdjustWalletRequest {
+    amount: f64,
+    reason: String,
+}
+
+/**
+ * POST /api/admin/users/{user_id}/wallet/adjust - Manually correct a wallet balance
+ *
+ * Credits (positive `amount`) or debits (negative) the user's withdrawable
+ * balance, recording the change in the wallet ledger and the audit log.
+ * Refuses adjustments that would leave the balance negative.
+ *
+ * @param req - HTTP request for admin authentication
+ * @param pool - Database connection pool
+ * @param user_id - User whose wallet is adjusted
+ * @param request - Signed amount and the reason for the adjustment
+ * @returns JSON with the new balance
+ */
+#[post("/api/admin/users/{user_id}/wallet/adjust")]
+async fn adjust_wallet_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>,
+    request: web::Json<AdjustWalletRequest>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    if !request.amount.is_finite() || request.amount == 0.0 {
+        return Ok(HttpResponse::BadRequest().json("Amount must be a non-zero number"));
+    }
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        return Ok(HttpResponse::BadRequest().json("A reason is required"));
+    }
+
+    match db::adjust_wallet_balance(&pool, *user_id, claims.sub, request.amount, reason).await {
+        Ok(Some(new_balance)) => Ok(ok(json!({
+            "user_id": *user_id,
+            "amount": request.amount,
+            "new_balance": new_balance
+        }))),
+        Ok(None) => Ok(HttpResponse::BadRequest().json("Adjustment would make the balance negative")),
+        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("User not found")),
+        Err(e) => {
+            eprintln!("❌ Wallet adjustment failed for user {}: {:?}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to adjust wallet"))
+        }
+    }
+}
+
+/// DELETE /api/admin/products/{product_id} - Remove any product for moderation
+///
+/// Soft-deletes the product whoever owns it: existing orders keep it, but it
+/// leaves listings, carts and wishlists. An optional `?reason=` is kept in
+/// the audit log.
+#[delete("/api/admin/products/{product_id}")]
+async fn admin_remove_product_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    product_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+    let reason = extract_query_param(req.query_string(), "reason");
+
+    match db::remove_product_as_admin(&pool, claims.sub, *product_id, reason.as_deref()).await {
+        Ok(true) => Ok(ok_message("Product removed")),
+        Ok(false) => Ok(HttpResponse::NotFound().json("Product not found")),
+        Err(e) => {
+            eprintln!("❌ Failed to remove product {}: {:?}", product_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to remove product"))
+        }
+    }
+}
+
+/// PATCH /api/admin/products/{product_id} - Correct a product's `name`,
+/// `category` or `description` (e.g. a mislabeled category), with an optional
+/// `reason`. Each change is audited.
+#[patch("/api/admin/products/{product_id}")]
+async fn admin_update_product_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    product_id: web::Path<i32>,
+    update: web::Json<AdminProductUpdate>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    // Given fields must be valid and, except the description, non-blank
+    let name = match update.name.as_deref().map(|name| validation::required_text("name", name, validation::PRODUCT_NAME_MAX, false)).transpose() {
+        Ok(name) => name,
+        Err(e) => return Ok(e.to_response()),
+    };
+    let category = match update.category.as_deref().map(|category| validation::required_text("category", category, validation::PRODUCT_CATEGORY_MAX, false)).transpose() {
+        Ok(category) => category,
+        Err(e) => return Ok(e.to_response()),
+    };
+    let description = match validation::optional_text("description", update.description.as_deref(), product_description_max(&pool).await, true) {
+        Ok(description) => description,
+        Err(e) => return Ok(e.to_response()),
+    };
+    let changes = AdminProductUpdate {
+        name,
+        category,
+        description,
+        reason: update.reason.as_deref().map(str::trim).filter(|r| !r.is_empty()).map(str::to_string),
+    };
+    if changes.name.is_none() && changes.category.is_none() && changes.description.is_none() {
+        return Ok(HttpResponse::BadRequest().json("Nothing to update"));
+    }
+
+    match db::update_product_as_admin(&pool, claims.sub, *product_id, &changes).await {
+        Ok(Some(product)) => Ok(HttpResponse::Ok().json(product)),
+        Ok(None) => Ok(HttpResponse::NotFound().json("Product not found")),
+        Err(e) => {
+            eprintln!("❌ Failed to update product {}: {:?}", product_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to update product"))
+        }
+    }
+}
+
+/// GET /api/admin/products/flagged - Listings held back by automated moderation,
+/// oldest first. Approve them below or remove them with DELETE.
+#[get("/api/admin/products/flagged")]
+async fn admin_flagged_products_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    match db::get_flagged_products(&pool).await {
+        Ok(products) => Ok(HttpResponse::Ok().json(products)),
+        Err(e) => {
+            eprintln!("❌ Failed to fetch flagged products: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch flagged products"))
+        }
+    }
+}
+
+/// POST /api/admin/products/{product_id}/approve - Publish a listing held by
+/// automated moderation. Audited.
+#[post("/api/admin/products/{product_id}/approve")]
+async fn admin_approve_product_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    product_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    match db::approve_flagged_product(&pool, claims.sub, *product_id).await {
+        Ok(true) => Ok(ok_message("Product approved")),
+        Ok(false) => Ok(HttpResponse::NotFound().json("No flagged product with that id")),
+        Err(e) => {
+            eprintln!("❌ Failed to approve product {}: {:?}", product_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to approve product"))
+        }
+    }
+}
+
+/// DELETE /api/admin/reviews/{review_id} - Remove any review for moderation
+///
+/// The review is hidden from product pages and no longer counts towards
+/// ratings. An optional `?reason=` is kept in the audit log.
+#[delete("/api/admin/reviews/{review_id}")]
+async fn admin_remove_review_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    review_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+    let reason = extract_query_param(req.query_string(), "reason");
+
+    match db::remove_review_as_admin(&pool, claims.sub, *review_id, reason.as_deref()).await {
+        Ok(true) => Ok(ok_message("Review removed")),
+        Ok(false) => Ok(HttpResponse::NotFound().json("Review not found")),
+        Err(e) => {
+            eprintln!("❌ Failed to remove review {}: {:?}", review_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to remove review"))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BanUserRequest {
+    banned: bool,
+}
+
+#[patch("/api/admin/users/{user_id}/ban")]
+async fn ban_user_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>,
+    request: web::Json<BanUserRequest>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::ban_user(&pool, *user_id, request.banned).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(if request.banned { "User banned successfully" } else { "User unbanned successfully" })),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update ban status")),
+    }
+}
+
+#[patch("/api/admin/users/{user_id}/reset-password")]
+async fn reset_user_password_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    // Generate a random temporary password that satisfies the policy
+    let temp_password = password::generate_temporary_password(&PasswordPolicy::from_settings(&pool).await);
+
+    match db::reset_user_password(&pool, *user_id, &temp_password).await {
+        Ok(_) => {
+            // In a real application, send email here with temp_password
+            // For now, we'll return a generic success message
+            // TODO: Integrate with email service (SendGrid, Mailgun, etc.)
+            println!("TEMP PASSWORD FOR USER {}: {}", user_id, temp_password);
+            Ok(ok_message("Password reset successfully. User has been emailed their new password."))
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to reset password")),
+    }
+}
+
+/// GET /api/admin/settings - List runtime settings (defaults overlaid with stored values).
+#[get("/api/admin/settings")]
+async fn get_settings_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match settings::all_settings(&pool).await {
+        Ok(all) => Ok(HttpResponse::Ok().json(all)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch settings")),
+    }
+}
+
+/// PATCH /api/admin/settings - Update one or more settings from a JSON object of key/value pairs.
+/// All values are validated before any are written.
+#[patch("/api/admin/settings")]
+async fn update_settings_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    request: web::Json<serde_json::Map<String, serde_json::Value>>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    let mut updates = Vec::new();
+    for (key, value) in request.iter() {
+        let raw = match value {
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Number(n) => n.to_string(),
+            serde_json::Value::Bool(b) => b.to_string(),
+            _ => return Ok(HttpResponse::BadRequest().json(json!({
+                "error": format!("Setting '{}' must be a string, number or boolean", key),
+                "key": key
+            }))),
+        };
+        match settings::validate(key, &raw) {
+            Ok(normalized) => updates.push((key.clone(), normalized)),
+            Err(e) => return Ok(HttpResponse::BadRequest().json(json!({
+                "error": e.to_string(),
+                "key": key
+            }))),
+        }
+    }
+
+    for (key, value) in &updates {
+        if let Err(e) = settings::set_setting(&pool, key, value).await {
+            eprintln!("Failed to update setting {}: {}", key, e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to update settings"));
+        }
+    }
+
+    match settings::all_settings(&pool).await {
+        Ok(all) => Ok(HttpResponse::Ok().json(all)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch settings")),
+    }
+}
+
+/// JSON of the default VAT rate and each category override, as the admin VAT routes return it
+async fn vat_rates_json(pool: &PgPool) -> Result<serde_json::Value, sqlx::Error> {
+    let default_rate = settings::get_f64(pool, settings::VAT_RATE).await;
+    let categories: Vec<serde_json::Value> = db::get_vat_rates(pool)
+        .await?
+        .into_iter()
+        .map(|(category, rate)| json!({ "category": category, "rate": rate, "exempt": rate == 0.0 }))
+        .collect();
+    Ok(json!({ "default_rate": default_rate, "categories": categories }))
+}
+
+/// GET /api/admin/vat-rates - The default VAT rate (`vat_rate` setting) and per-category overrides.
+#[get("/api/admin/vat-rates")]
+async fn get_vat_rates_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match vat_rates_json(&pool).await {
+        Ok(rates) => Ok(HttpResponse::Ok().json(rates)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch VAT rates")),
+    }
+}
+
+#[derive(Deserialize)]
+struct VatRateRequest {
+    rate: f64,
+}
+
+/// PUT /api/admin/vat-rates/{category} - Set a category's VAT rate (0.0 - 1.0); 0 makes it exempt.
+#[put("/api/admin/vat-rates/{category}")]
+async fn set_vat_rate_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    rate_req: web::Json<VatRateRequest>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    let category = tax::normalize_category(&path.into_inner());
+    if category.is_empty() {
+        return Ok(HttpResponse::BadRequest().json("Category is required"));
+    }
+    if !(0.0..=1.0).contains(&rate_req.rate) {
+        return Ok(HttpResponse::BadRequest().json("VAT rate must be between 0 and 1"));
+    }
+
+    if let Err(e) = db::set_vat_rate(&pool, &category, rate_req.rate).await {
+        eprintln!("Failed to set VAT rate for {}: {:?}", category, e);
+        return Ok(HttpResponse::InternalServerError().json("Failed to set VAT rate"));
+    }
+    match vat_rates_json(&pool).await {
+        Ok(rates) => Ok(HttpResponse::Ok().json(rates)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch VAT rates")),
+    }
+}
+
+/// DELETE /api/admin/vat-rates/{category} - Drop a category's override so the default rate applies.
+#[delete("/api/admin/vat-rates/{category}")]
+async fn delete_vat_rate_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<String>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::delete_vat_rate(&pool, &tax::normalize_category(&path.into_inner())).await {
+        Ok(true) => match vat_rates_json(&pool).await {
+            Ok(rates) => Ok(HttpResponse::Ok().json(rates)),
+            Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch VAT rates")),
+        },
+        Ok(false) => Ok(HttpResponse::NotFound().json("No VAT rate set for that category")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to delete VAT rate")),
+    }
+}
+
+#[get("/api/admin/cart")]
+async fn get_all_cart_items(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::get_all_cart_items(&pool).await {
+        Ok(cart_items) => Ok(HttpResponse::Ok().json(cart_items)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch cart items")),
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateVendorReportRequest {
+    vendor_id: i32,
+    product_id: Option<i32>,
+    report_type: String,
+    description: Option<String>,
+}
+
+#[post("/reports")]
+async fn create_vendor_report_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    report_req: web::Json<CreateVendorReportRequest>
+) -> ActixResult<HttpResponse> {
+    let customer_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+    if !db::is_report_type(&report_req.report_type) {
+        return Ok(HttpResponse::BadRequest().json(format!(
+            "report_type must be one of: {}",
+            db::report_types().join(", ")
+        )));
+    }
+
+    match db::create_vendor_report(&pool, customer_id, report_req.vendor_id, report_req.product_id, &report_req.report_type, report_req.description.as_deref()).await {
+        Ok(report) => Ok(HttpResponse::Created().json(report)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create report")),
+    }
+}
+
+#[get("/vendor/reports/count")]
+async fn get_vendor_report_count(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+
+    let count = match db::count_vendor_reports(&pool, claims.sub).await {
+        Ok(count) => count,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to count reports")),
+    };
+    let score = match db::vendor_report_scores(&pool, &[claims.sub]).await {
+        Ok(scores) => scores.get(&claims.sub).copied().unwrap_or(0.0),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to count reports")),
+    };
+    let threshold = settings::get_f64(&pool, settings::REPORT_SUSPENSION_THRESHOLD).await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "report_count": count,
+        "report_score": score,
+        "suspension_threshold": threshold,
+        "suspended": score >= threshold
+    })))
+}
+
+#[get("/api/admin/reports")]
+async fn get_all_vendor_reports_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::get_all_vendor_reports(&pool).await {
+        Ok(reports) => Ok(HttpResponse::Ok().json(reports)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch reports")),
+    }
+}
+
+#[derive(Deserialize)]
+struct UpdateReportStatusRequest {
+    status: String,
+    admin_notes: Option<String>,
+}
+
+#[patch("/api/admin/reports/{report_id}")]
+async fn update_vendor_report_status_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    report_id: web::Path<i32>,
+    update_req: web::Json<UpdateReportStatusRequest>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::update_report_status(&pool, *report_id, &update_req.status, update_req.admin_notes.as_deref()).await {
+        Ok(_) => Ok(ok_message("Report status updated successfully")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update report status")),
+    }
+}
+
+/// POST /vendor/appeal - Appeal a ban or report-based suspension.
+/// Suspended vendors use their token; banned vendors can't log in, so they
+/// authenticate with `username` and `password` in the body instead.
+#[post("/vendor/appeal")]
+async fn create_appeal_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    appeal_req: web::Json<AppealRequest>
+) -> ActixResult<HttpResponse> {
+    let vendor = if req.headers().contains_key(AUTHORIZATION) {
+        let vendor_id = match check_vendor_auth(&req) {
+            Ok(id) => id,
+            Err(response) => return Ok(response),
+        };
+        match db::get_user_by_id(&pool, vendor_id).await {
+            Ok(user) => user,
+            Err(_) => return Ok(HttpResponse::NotFound().json("User not found")),
+        }
+    } else {
+        let (Some(username), Some(password)) = (&appeal_req.username, &appeal_req.password) else {
+            return Ok(HttpResponse::Unauthorized().json("Authorization header or credentials required"));
+        };
+        let user = match db::find_user_by_username(&pool, username).await {
+            Ok(Some(user)) => user,
+            Ok(None) => return Ok(HttpResponse::Unauthorized().json("Invalid credentials")),
+            Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to submit appeal")),
+        };
+        match db::verify_user_password(&pool, user.id, password).await {
+            Ok(true) => {}
+            Ok(false) => return Ok(HttpResponse::Unauthorized().json("Invalid credentials")),
+            Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to submit appeal")),
+        }
+        if !matches!(user.role, Role::Vendor) {
+            return Ok(HttpResponse::Forbidden().json("Vendor privileges required"));
+        }
+        user
+    };
+
+    let message = match validation::required_text("message", &appeal_req.message, validation::APPEAL_MESSAGE_MAX, true) {
+        Ok(message) => message,
+        Err(e) => return Ok(e.to_response()),
+    };
+
+    let suspended = match db::is_vendor_suspended(&pool, vendor.id).await {
+        Ok(suspended) => suspended,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to submit appeal")),
+    };
+    if !vendor.banned && !suspended {
+        return Ok(HttpResponse::BadRequest().json("Account is not suspended or banned"));
+    }
+
+    match db::create_appeal(&pool, vendor.id, &message).await {
+        Ok(appeal) => Ok(HttpResponse::Created().json(appeal)),
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            Ok(HttpResponse::Conflict().json("You already have an appeal under review"))
+        }
+        Err(e) => {
+            eprintln!("Failed to create appeal: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to submit appeal"))
+        }
+    }
+}
+
+/// GET /api/admin/appeals - List appeals, optionally `?status=pending|approved|rejected`.
+#[get("/api/admin/appeals")]
+async fn get_appeals_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    let status = extract_query_param(req.query_string(), "status");
+    match db::get_appeals(&pool, status.as_deref()).await {
+        Ok(appeals) => Ok(HttpResponse::Ok().json(appeals)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch appeals")),
+    }
+}
+
+/// PATCH /api/admin/appeals/{appeal_id} - Approve or reject a pending appeal.
+/// Approval lifts the ban and dismisses outstanding reports; the vendor is emailed either way.
+#[patch("/api/admin/appeals/{appeal_id}")]
+async fn resolve_appeal_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    appeal_id: web::Path<i32>,
+    resolve_req: web::Json<ResolveAppealRequest>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    let appeal = match db::resolve_appeal(&pool, *appeal_id, resolve_req.approved, resolve_req.admin_notes.as_deref()).await {
+        Ok(appeal) => appeal,
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("Pending appeal not found")),
+        Err(e) => {
+            eprintln!("Failed to resolve appeal: {:?}", e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to resolve appeal"));
+        }
+    };
+
+    if let Ok(vendor) = db::get_user_by_id(&pool, appeal.vendor_id).await {
+        if let Err(e) = email::send_appeal_outcome_email(&pool, &vendor.email, &vendor.username, resolve_req.approved, resolve_req.admin_notes.as_deref()).await {
+            eprintln!("Failed to send appeal outcome email to {}: {:?}", vendor.email, e);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(appeal))
+}
+
+#[derive(Serialize)]
+struct DatabaseInfo {
+    name: String,
+    owner: String,
+    encoding: String,
+}
+
+#[get("/api/admin/databases")]
+async fn get_databases(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    // Query to get database information
+    let rows = match sqlx::query(
+        "SELECT datname, usename as owner, encoding, datcollate
+         FROM pg_database d
+         JOIN pg_user u ON d.datdba = u.usesysid
+         WHERE datistemplate = false
+         ORDER BY datname"
+    )
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => rows,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch databases")),
+    };
+
+    let mut databases = Vec::new();
+    for row in rows {
+        let name: String = match row.try_get("datname") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let owner: String = match row.try_get("owner") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let encoding: String = match row.try_get("encoding") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        databases.push(DatabaseInfo { name, owner, encoding });
+    }
+
+    Ok(HttpResponse::Ok().json(databases))
+}
+
+#[derive(Serialize)]
+struct TableInfo {
+    name: String,
+    schema: String,
+    table_type: String,
+    owner: String,
+}
+
+#[get("/api/admin/tables")]
+async fn get_tables(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    // Query to get table information
+    let rows = match sqlx::query(
+        "SELECT schemaname, tablename, tableowner, 'table' as table_type
+         FROM pg_tables
+         WHERE schemaname NOT IN ('pg_catalog', 'information_schema')
+         ORDER BY schemaname, tablename"
+    )
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => rows,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch tables")),
+    };
+
+    let mut tables = Vec::new();
+    for row in rows {
+        let schema: String = match row.try_get("schemaname") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let name: String = match row.try_get("tablename") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let owner: String = match row.try_get("tableowner") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let table_type: String = match row.try_get("table_type") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        tables.push(TableInfo { name, schema, table_type, owner });
+    }
+
+    Ok(HttpResponse::Ok().json(tables))
+}
+
+#[derive(Serialize)]
+struct ColumnInfo {
+    name: String,
+    data_type: String,
+    is_nullable: String,
+    default_value: Option<String>,
+}
+
+#[get("/api/admin/tables/{table_name}/columns")]
+async fn get_table_columns(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    table_name: web::Path<String>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    // Query to get column information for a specific table
+    let rows = match sqlx::query(
+        "SELECT column_name, data_type, is_nullable, column_default
+         FROM information_schema.columns
+         WHERE table_name = $1 AND table_schema = 'public'
+         ORDER BY ordinal_position"
+    )
+    .bind(&*table_name)
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => rows,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch table columns")),
+    };
+
+    let mut columns = Vec::new();
+    for row in rows {
+        let name: String = match row.try_get("column_name") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let data_type: String = match row.try_get("data_type") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let is_nullable: String = match row.try_get("is_nullable") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let default_value: Option<String> = match row.try_get("column_default") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        columns.push(ColumnInfo { name, data_type, is_nullable, default_value });
+    }
+
+    Ok(HttpResponse::Ok().json(columns))
+}
+
+/// One cell of the admin table viewer as JSON. Timestamps come back as RFC 3339
+/// strings, numerics as exact decimal strings, json/jsonb as-is and arrays as
+/// JSON arrays. Any other type is named rather than shown.
+fn cell_to_json(row: &sqlx::postgres::PgRow, i: usize) -> serde_json::Value {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+    use rust_decimal::Decimal;
+
+    fn get<'r, T>(row: &'r sqlx::postgres::PgRow, i: usize) -> Option<Option<T>>
+    where
+        T: sqlx::Decode<'r, sqlx::Postgres> + sqlx::Type<sqlx::Postgres>,
+    {
+        row.try_get::<Option<T>, _>(i).ok()
+    }
+    fn array<T>(items: Option<Vec<Option<T>>>, to_json: impl Fn(T) -> serde_json::Value) -> serde_json::Value {
+        items.map_or(serde_json::Value::Null, |items| {
+            items.into_iter().map(|item| item.map_or(serde_json::Value::Null, &to_json)).collect()
+        })
+    }
+
+    if let Some(v) = get::<String>(row, i) {
+        return json!(v);
+    }
+    if let Some(v) = get::<i16>(row, i) {
+        return json!(v);
+    }
+    if let Some(v) = get::<i32>(row, i) {
+        return json!(v);
+    }
+    if let Some(v) = get::<i64>(row, i) {
+        return json!(v);
+    }
+    if let Some(v) = get::<f32>(row, i) {
+        return json!(v);
+    }
+    if let Some(v) = get::<f64>(row, i) {
+        return json!(v);
+    }
+    if let Some(v) = get::<bool>(row, i) {
+        return json!(v);
+    }
+    if let Some(v) = get::<Decimal>(row, i) {
+        return json!(v.map(|d| d.to_string()));
+    }
+    if let Some(v) = get::<DateTime<Utc>>(row, i) {
+        return json!(v.map(|t| t.to_rfc3339()));
+    }
+    if let Some(v) = get::<NaiveDateTime>(row, i) {
+        return json!(v.map(|t| t.and_utc().to_rfc3339()));
+    }
+    if let Some(v) = get::<NaiveDate>(row, i) {
+        return json!(v.map(|d| d.to_string()));
+    }
+    if let Some(v) = get::<NaiveTime>(row, i) {
+        return json!(v.map(|t| t.to_string()));
+    }
+    if let Some(v) = get::<uuid::Uuid>(row, i) {
+        return json!(v.map(|u| u.to_string()));
+    }
+    if let Some(v) = get::<serde_json::Value>(row, i) {
+        return v.unwrap_or(serde_json::Value::Null);
+    }
+    if let Some(v) = get::<Vec<Option<String>>>(row, i) {
+        return array(v, |s| json!(s));
+    }
+    if let Some(v) = get::<Vec<Option<i16>>>(row, i) {
+        return array(v, |n| json!(n));
+    }
+    if let Some(v) = get::<Vec<Option<i32>>>(row, i) {
+        return array(v, |n| json!(n));
+    }
+    if let Some(v) = get::<Vec<Option<i64>>>(row, i) {
+        return array(v, |n| json!(n));
+    }
+    if let Some(v) = get::<Vec<Option<f64>>>(row, i) {
+        return array(v, |n| json!(n));
+    }
+    if let Some(v) = get::<Vec<Option<bool>>>(row, i) {
+        return array(v, |b| json!(b));
+    }
+    if let Some(v) = get::<Vec<Option<Decimal>>>(row, i) {
+        return array(v, |d| json!(d.to_string()));
+    }
+    if let Some(v) = get::<Vec<Option<DateTime<Utc>>>>(row, i) {
+        return array(v, |t| json!(t.to_rfc3339()));
+    }
+    if let Some(v) = get::<Vec<Option<uuid::Uuid>>>(row, i) {
+        return array(v, |u| json!(u.to_string()));
+    }
+    if let Some(v) = get::<Vec<Option<serde_json::Value>>>(row, i) {
+        return array(v, |value| value);
+    }
+    match row.try_get_raw(i) {
+        Ok(raw) => {
+            use sqlx::{TypeInfo, ValueRef};
+            json!(format!("Unsupported type: {}", raw.type_info().name()))
+        }
+        Err(_) => serde_json::Value::Null,
+    }
+}
+
+#[derive(Serialize)]
+struct TableData {
+    columns: Vec<String>,
+    rows: Vec<Vec<serde_json::Value>>,
+}
+
+#[get("/api/admin/tables/{table_name}/data")]
+async fn get_table_data(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    table_name: web::Path<String>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    // First get column names
+    let column_rows = match sqlx::query(
+        "SELECT column_name
+         FROM information_schema.columns
+         WHERE table_name = $1 AND table_schema = 'public'
+         ORDER BY ordinal_position"
+    )
+    .bind(&*table_name)
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => rows,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch column names")),
+    };
+
+    let mut columns = Vec::new();
+    for row in &column_rows {
+        let column_name: String = match row.try_get("column_name") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        columns.push(column_name);
+    }
+
+    // Build dynamic SELECT query
+    if columns.is_empty() {
+        return Ok(HttpResponse::Ok().json(TableData { columns: vec![], rows: vec![] }));
+    }
+
+    let select_clause = columns.join(", ");
+    let query_str = format!("SELECT {} FROM {} LIMIT 100", select_clause, table_name);
+
+    // Execute the dynamic query
+    let data_rows = match sqlx::query(&query_str)
+        .fetch_all(pool.get_ref())
+        .await {
+        Ok(rows) => rows,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch table data")),
+    };
+
+    let mut rows = Vec::new();
+    for data_row in data_rows {
+        let mut row_data = Vec::new();
+        for i in 0..columns.len() {
+            row_data.push(cell_to_json(&data_row, i));
+        }
+        rows.push(row_data);
+    }
+
+    Ok(HttpResponse::Ok().json(TableData { columns, rows }))
+}
+
+/// POST /api/admin/query/code - Email the admin a one-time code for the SQL console.
+/// The code is the second factor for `POST /api/admin/query` and lasts 10 minutes.
+#[post("/api/admin/query/code")]
+async fn request_admin_query_code(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    let verification_code = format!("{:06}", rand::random::<u32>() % 1000000);
+    let expires_at = chrono::Utc::now() + chrono::Duration::minutes(admin_query::CODE_VALID_MINUTES);
+    if db::store_admin_query_code(&pool, claims.sub, &verification_code, expires_at).await.is_err() {
+        return Ok(HttpResponse::InternalServerError().json("Failed to create verification code"));
+    }
+
+    let admin = match db::get_user_by_id(&pool, claims.sub).await {
+        Ok(admin) => admin,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to load user")),
+    };
+    if let Err(e) = email::send_admin_query_code_email(&pool, &admin.email, &admin.username, &verification_code, expires_at).await {
+        // Development fallback, same as password reset codes
+        eprintln!("Failed to email admin query code: {}", e);
+        println!("🔐 DEVELOPMENT MODE - SQL console code for {}: {}", admin.username, verification_code);
+    }
+
+    Ok(ok_with_message("Verification code sent", json!({ "expires_at": expires_at.to_rfc3339() })))
+}
+
+#[derive(Deserialize)]
+struct AdminQueryRequest {
+    sql: String,
+    #[serde(default)]
+    params: Vec<serde_json::Value>,
+    /// Code from POST /api/admin/query/code
+    code: String,
+}
+
+/// POST /api/admin/query - Run a parameterized, read-only SELECT.
+/// Needs an admin token and a current code from `/api/admin/query/code`.
+/// Anything but a single SELECT is refused; the statement runs in a read-only
+/// transaction as a role that can only read the app's tables, with a 5 second
+/// timeout, and returns at most 500 rows.
+/// Every query, refused or not, is written to the audit log.
+#[post("/api/admin/query")]
+async fn admin_query_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    request: web::Json<AdminQueryRequest>
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    let audit = |outcome: String| {
+        let pool = pool.clone();
+        let sql = request.sql.clone();
+        async move { db::record_admin_query(&pool, claims.sub, &sql, &outcome).await }
+    };
+
+    match db::admin_query_code_is_valid(&pool, claims.sub, request.code.trim()).await {
+        Ok(true) => {}
+        Ok(false) => {
+            let _ = audit("denied: invalid or expired code".to_string()).await;
+            return Ok(HttpResponse::Forbidden().json(json!({
+                "error": "Verification required",
+                "message": "Request a code from /api/admin/query/code and send it as `code`"
+            })));
+        }
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to check verification code")),
+    }
+
+    let refuse = |message: String| HttpResponse::BadRequest().json(json!({ "error": "Query rejected", "message": message }));
+    let sql = match admin_query::validate(&request.sql) {
+        Ok(sql) => sql,
+        Err(message) => {
+            let _ = audit(format!("rejected: {}", message)).await;
+            return Ok(refuse(message));
+        }
+    };
+    let params: Result<Vec<_>, _> = request.params.iter().map(admin_query::QueryParam::try_from).collect();
+    let params = match params {
+        Ok(params) => params,
+        Err(message) => {
+            let _ = audit(format!("rejected: {}", message)).await;
+            return Ok(refuse(message));
+        }
+    };
+
+    let result = db::run_read_only_query(&pool, sql, &params, admin_query::MAX_ROWS, admin_query::STATEMENT_TIMEOUT_MS).await;
+    let outcome = match &result {
+        Ok(result) => format!("ok: {} rows{}", result.rows.len(), if result.truncated { " (truncated)" } else { "" }),
+        Err(e) => format!("failed: {}", e),
+    };
+    // A query that can't be audited doesn't get its results back
+    if let Err(e) = audit(outcome).await {
+        eprintln!("❌ Failed to audit admin query: {:?}", e);
+        return Ok(HttpResponse::InternalServerError().json("Failed to record query in the audit log"));
+    }
+
+    match result {
+        Ok(result) => Ok(HttpResponse::Ok().json(json!({
+            "columns": result.columns,
+            "rows": result.rows,
+            "row_count": result.rows.len(),
+            "truncated": result.truncated,
+            "max_rows": admin_query::MAX_ROWS
+        }))),
+        Err(sqlx::Error::Database(e)) => {
+            let error = match e.code().as_deref() {
+                // read_only_sql_transaction, or insufficient_privilege for the reader role
+                Some("25006") | Some("42501") => "Query rejected",
+                // query_canceled, raised by the statement timeout
+                Some("57014") => "Query timed out",
+                _ => "Query failed",
+            };
+            Ok(HttpResponse::BadRequest().json(json!({ "error": error, "message": e.message() })))
+        }
+        Err(e) => {
+            eprintln!("❌ Admin query failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to run query"))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UpdateProfileImageRequest {
+    profile_image: String,
+}
+
+#[derive(Deserialize)]
+struct UpdateProfileRequest {
+    username: Option<String>,
+    email: Option<String>,
+    secondary_email: Option<String>,
+    mpesa_number: Option<String>,
+    payment_preference: Option<String>,
+    location_string: Option<String>,
+    profile_image: Option<String>,
+    current_password: Option<String>,
+    new_password: Option<String>,
+    email_notifications: Option<bool>,
+    notification_frequency: Option<String>,
+    /// Language of the user's emails, e.g. "en" or "sw"
+    language: Option<String>,
+    /// Vendors only: smallest subtotal of their items accepted at checkout (0 for none)
+    min_order_value: Option<f64>,
+    /// Vendors only: take new conversations only from customers who have ordered from them
+    buyers_only_messages: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct UpdateAdminCredentialsRequest {
+    current_password: String,
+    new_username: Option<String>,
+    new_password: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UpdateLocationRequest {
+    latitude: f64,
+    longitude: f64,
+    location_string: Option<String>,
+}
+
+/**
+ * POST /location/update - Update user location
+ *
+ * Allows authenticated users to update their location coordinates. Without a
+ * location_string the place name is reverse-geocoded (GEOCODING_URL) when possible.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param location_req - JSON request with latitude, longitude, and optional location_string
+ * @returns Success message
+ */
+#[post("/location/update")]
+async fn update_location(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    location_req: web::Json<UpdateLocationRequest>,
+) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+
+    let location_string = match location_req.location_string.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        Some(location) => Some(location.to_string()),
+        None => match geocoding::Geocoder::from_env() {
+            Some(geocoder) => geocoding::reverse_geocode(&pool, &geocoder, location_req.latitude, location_req.longitude).await,
+            None => None,
+        },
+    };
+
+    match sqlx::query(
+        "UPDATE users SET latitude = $1, longitude = $2, location_string = $3 WHERE id = $4"
+    )
+    .bind(location_req.latitude)
+    .bind(location_req.longitude)
+    .bind(&location_string)
+    .bind(user_id)
+    .execute(pool.get_ref())
+    .await {
+        Ok(_) => Ok(ok_with_message("Location updated successfully", json!({ "location_string": location_string }))),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update location")),
+    }
+}
+
+// Profile image update endpoint for users to update their own profile images
+#[patch("/profile/image")]
+async fn update_profile_image(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    request: web::Json<UpdateProfileImageRequest>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+
+    match db::update_user_profile_image(&pool, claims.sub, &request.profile_image).await {
+        Ok(_) => Ok(ok_message("Profile image updated successfully")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update profile image")),
+    }
+}
+
+/// 400 response when a profile update names a payout schedule we don't run.
+fn reject_unknown_payment_preference(request: &UpdateProfileRequest) -> Option<HttpResponse> {
+    let preference = request.payment_preference.as_deref()?;
+    if payouts::PAYMENT_PREFERENCES.contains(&preference) {
+        return None;
+    }
+    Some(HttpResponse::BadRequest().json(json!({
+        "error": "Invalid payment preference",
+        "allowed": payouts::PAYMENT_PREFERENCES
+    })))
+}
+
+/// 400 response when a profile update names a notification frequency we don't support.
+fn reject_unknown_notification_frequency(request: &UpdateProfileRequest) -> Option<HttpResponse> {
+    let frequency = request.notification_frequency.as_deref()?;
+    if digests::NOTIFICATION_FREQUENCIES.contains(&frequency) {
+        return None;
+    }
+    Some(HttpResponse::BadRequest().json(json!({
+        "error": "Invalid notification frequency",
+        "allowed": digests::NOTIFICATION_FREQUENCIES
+    })))
+}
+
+/// 400 response when a profile update names a language we have no email templates for.
+fn reject_unknown_language(request: &UpdateProfileRequest) -> Option<HttpResponse> {
+    let language = request.language.as_deref()?;
+    if email_templates::is_supported_language(language) {
+        return None;
+    }
+    Some(HttpResponse::BadRequest().json(json!({
+        "error": "Invalid language",
+        "allowed": email_templates::languages()
+    })))
+}
+
+/// 400 response when a profile update sets a minimum order value it can't have.
+fn reject_invalid_min_order_value(request: &UpdateProfileRequest, role: &str) -> Option<HttpResponse> {
+    let minimum = request.min_order_value?;
+    if role != "Vendor" {
+        return Some(HttpResponse::BadRequest().json(json!({
+            "error": "Invalid minimum order value",
+            "message": "Only vendors can set a minimum order value"
+        })));
+    }
+    if !minimum.is_finite() || minimum < 0.0 {
+        return Some(HttpResponse::BadRequest().json(json!({
+            "error": "Invalid minimum order value",
+            "message": "Minimum order value must be zero or more"
+        })));
+    }
+    None
+}
+
+/// 400 response when someone other than a vendor sets buyers-only messaging.
+fn reject_buyers_only_messages(request: &UpdateProfileRequest, role: &str) -> Option<HttpResponse> {
+    if request.buyers_only_messages.is_some() && role != "Vendor" {
+        return Some(HttpResponse::BadRequest().json(json!({
+            "error": "Invalid messaging setting",
+            "message": "Only vendors can limit messages to their buyers"
+        })));
+    }
+    None
+}
 
-    // Query to get database information
-    let rows = match sqlx::query(
-        "SELECT datname, usename as owner, encoding, datcollate
-         FROM pg_database d
-         JOIN pg_user u ON d.datdba = u.usesysid
-         WHERE datistemplate = false
-         ORDER BY datname"
-    )
-    .fetch_all(pool.get_ref())
-    .await {
-        Ok(rows) => rows,
-        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch databases")),
+// Profile update endpoint for users to update their own username and email
+#[patch("/profile")]
+async fn update_profile(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    request: web::Json<UpdateProfileRequest>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
     };
 
-    let mut databases = Vec::new();
-    for row in rows {
-        let name: String = match row.try_get("datname") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        let owner: String = match row.try_get("owner") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        let encoding: String = match row.try_get("encoding") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        databases.push(DatabaseInfo { name, owner, encoding });
+    if let Some(response) = reject_unknown_payment_preference(&request) {
+        return Ok(response);
+    }
+    if let Some(response) = reject_unknown_notification_frequency(&request) {
+        return Ok(response);
+    }
+    if let Some(response) = reject_unknown_language(&request) {
+        return Ok(response);
+    }
+    if let Some(response) = reject_invalid_min_order_value(&request, &claims.role) {
+        return Ok(response);
+    }
+    if let Some(response) = reject_buyers_only_messages(&request, &claims.role) {
+        return Ok(response);
     }
 
-    Ok(HttpResponse::Ok().json(databases))
+    if let Some(enabled) = request.email_notifications {
+        if db::set_email_notifications(&pool, claims.sub, enabled).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update notification preference"));
+        }
+    }
+    if let Some(frequency) = &request.notification_frequency {
+        if db::set_notification_frequency(&pool, claims.sub, frequency).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update notification preference"));
+        }
+    }
+    if let Some(language) = &request.language {
+        if db::set_user_language(&pool, claims.sub, language).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update language"));
+        }
+    }
+    if let Some(minimum) = request.min_order_value {
+        if db::set_min_order_value(&pool, claims.sub, (minimum * 100.0).round() / 100.0).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update minimum order value"));
+        }
+    }
+    if let Some(enabled) = request.buyers_only_messages {
+        if db::set_buyers_only_messages(&pool, claims.sub, enabled).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update messaging setting"));
+        }
+    }
+
+    match db::update_user_profile(&pool, claims.sub, request.username.as_deref(), request.email.as_deref(), request.secondary_email.as_deref(), request.mpesa_number.as_deref(), request.payment_preference.as_deref()).await {
+        Ok(_) => {
+            // Return a success message with the updated username (if changed)
+            Ok(ok_with_message("Profile updated successfully", json!({ "new_username": request.username })))
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.constraint().is_some() => {
+            Ok(HttpResponse::Conflict().json(unique_violation_message(db_err.constraint().unwrap_or(""))))
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update profile")),
+    }
 }
 
-#[derive(Serialize)]
-struct TableInfo {
-    name: String,
-    schema: String,
-    table_type: String,
-    owner: String,
+// Comprehensive profile update endpoint - handles all profile fields including password
+#[actix_web::put("/user/profile")]
+async fn update_user_profile_comprehensive(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    request: web::Json<UpdateProfileRequest>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+
+    if let Some(response) = reject_unknown_payment_preference(&request) {
+        return Ok(response);
+    }
+    if let Some(response) = reject_unknown_notification_frequency(&request) {
+        return Ok(response);
+    }
+    if let Some(response) = reject_unknown_language(&request) {
+        return Ok(response);
+    }
+    if let Some(response) = reject_invalid_min_order_value(&request, &claims.role) {
+        return Ok(response);
+    }
+    if let Some(response) = reject_buyers_only_messages(&request, &claims.role) {
+        return Ok(response);
+    }
+
+    // If password change is requested, verify current password first
+    if let (Some(current_pwd), Some(new_pwd)) = (&request.current_password, &request.new_password) {
+        if let Err(reason) = password::validate_password(&PasswordPolicy::from_settings(&pool).await, new_pwd) {
+            return Ok(HttpResponse::BadRequest().json(reason));
+        }
+
+        // Verify current password
+        let row = match sqlx::query("SELECT password_hash FROM users WHERE id = $1")
+            .bind(claims.sub)
+            .fetch_one(pool.get_ref())
+            .await {
+            Ok(r) => r,
+            Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to verify password")),
+        };
+
+        let stored_hash: String = row.try_get(0).unwrap();
+        let is_valid = bcrypt::verify(current_pwd, &stored_hash).unwrap_or(false);
+
+        if !is_valid {
+            return Ok(HttpResponse::Unauthorized().json("Current password is incorrect"));
+        }
+
+        // Update password
+        if db::reset_user_password(&pool, claims.sub, new_pwd).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update password"));
+        }
+    }
+
+    // Update profile image if provided
+    if let Some(profile_img) = &request.profile_image {
+        if db::update_user_profile_image(&pool, claims.sub, profile_img).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update profile image"));
+        }
+    }
+
+    // Update location if provided
+    if let Some(location) = &request.location_string {
+        if sqlx::query("UPDATE users SET location_string = $1 WHERE id = $2")
+            .bind(location)
+            .bind(claims.sub)
+            .execute(pool.get_ref())
+            .await
+            .is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update location"));
+        }
+    }
+
+    // Update other profile fields
+    if let Some(enabled) = request.email_notifications {
+        if db::set_email_notifications(&pool, claims.sub, enabled).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update notification preference"));
+        }
+    }
+    if let Some(frequency) = &request.notification_frequency {
+        if db::set_notification_frequency(&pool, claims.sub, frequency).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update notification preference"));
+        }
+    }
+    if let Some(language) = &request.language {
+        if db::set_user_language(&pool, claims.sub, language).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update language"));
+        }
+    }
+    if let Some(minimum) = request.min_order_value {
+        if db::set_min_order_value(&pool, claims.sub, (minimum * 100.0).round() / 100.0).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update minimum order value"));
+        }
+    }
+    if let Some(enabled) = request.buyers_only_messages {
+        if db::set_buyers_only_messages(&pool, claims.sub, enabled).await.is_err() {
+            return Ok(HttpResponse::InternalServerError().json("Failed to update messaging setting"));
+        }
+    }
+
+    match db::update_user_profile(&pool, claims.sub, request.username.as_deref(), request.email.as_deref(), request.secondary_email.as_deref(), request.mpesa_number.as_deref(), request.payment_preference.as_deref()).await {
+        Ok(_) => {
+            // Return updated user data
+            let user = json!({
+                "username": request.username.as_ref().unwrap_or(&claims.username),
+                "email": request.email.as_ref().unwrap_or(&"".to_string()),
+                "location_string": request.location_string.as_ref().unwrap_or(&"".to_string())
+            });
+            Ok(ok_with_message("Profile updated successfully", user))
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.constraint().is_some() => {
+            Ok(HttpResponse::Conflict().json(unique_violation_message(db_err.constraint().unwrap_or(""))))
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update profile")),
+    }
 }
 
-#[get("/api/admin/tables")]
-async fn get_tables(
+/// GET /account/export - Download everything the platform holds about the caller
+/// as one JSON bundle, streamed as it is read. Limited to one export an hour.
+#[get("/account/export")]
+async fn export_account_data(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>
 ) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
+    let claims = match extract_auth(&req) {
+        Ok(claims) => claims,
+        Err(response) => return Ok(response),
+    };
+
+    match db::record_data_export(&pool, claims.sub, data_export::EXPORT_INTERVAL_MINUTES).await {
+        Ok(None) => {}
+        Ok(Some(retry_after_seconds)) => {
+            return Ok(HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after_seconds.to_string()))
+                .json(json!({
+                    "error": "You can export your data once an hour",
+                    "retry_after_seconds": retry_after_seconds
+                })));
+        }
+        Err(e) => {
+            eprintln!("Failed to record data export: {:?}", e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to start export"));
+        }
     }
 
-    // Query to get table information
-    let rows = match sqlx::query(
-        "SELECT schemaname, tablename, tableowner, 'table' as table_type
-         FROM pg_tables
-         WHERE schemaname NOT IN ('pg_catalog', 'information_schema')
-         ORDER BY schemaname, tablename"
-    )
-    .fetch_all(pool.get_ref())
-    .await {
-        Ok(rows) => rows,
-        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch tables")),
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(("Content-Disposition", "attachment; filename=\"account-export.json\""))
+        .streaming(data_export::export_stream(pool.get_ref().clone(), claims.sub)))
+}
+
+#[derive(Deserialize)]
+struct DeleteAccountRequest {
+    current_password: String,
+}
+
+/// DELETE /account - Delete the authenticated user's own account.
+/// Requires the current password. Refused while the user has open orders or,
+/// for vendors, an unwithdrawn wallet balance. Removes cart and messages and
+/// anonymizes the profile; order history is kept.
+#[delete("/account")]
+async fn delete_own_account(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    request: web::Json<DeleteAccountRequest>
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(claims) => claims,
+        Err(response) => return Ok(response),
+    };
+
+    if claims.role == "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin accounts cannot be self-deleted"));
+    }
+
+    match db::verify_user_password(&pool, claims.sub, &request.current_password).await {
+        Ok(true) => {}
+        Ok(false) => return Ok(HttpResponse::Unauthorized().json("Current password is incorrect")),
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("Account not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to verify password")),
+    }
+
+    if claims.role == "Vendor" {
+        match db::get_wallet_balances(&pool, claims.sub).await {
+            Ok((balance, pending)) if balance > 0.0 || pending > 0.0 => {
+                return Ok(HttpResponse::Conflict().json(json!({
+                    "error": "Withdraw your wallet balance before deleting your account",
+                    "reason": "wallet_balance",
+                    "wallet_balance": balance,
+                    "pending_balance": pending
+                })));
+            }
+            Ok(_) => {}
+            Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to check wallet balance")),
+        }
+    }
+
+    match db::count_open_orders(&pool, claims.sub).await {
+        Ok(0) => {}
+        Ok(open_orders) => {
+            return Ok(HttpResponse::Conflict().json(json!({
+                "error": "Complete or cancel your open orders before deleting your account",
+                "reason": "open_orders",
+                "open_orders": open_orders
+            })));
+        }
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to check open orders")),
+    }
+
+    match db::delete_own_account(&pool, claims.sub).await {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to delete account")),
+    }
+}
+
+// Admin credentials update endpoint - allows admin to change their username and password
+#[patch("/admin/credentials")]
+async fn update_admin_credentials(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    request: web::Json<UpdateAdminCredentialsRequest>,
+) -> ActixResult<HttpResponse> {
+    // Only allow admin users
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+
+    if claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    }
+
+    if let Some(new_password) = &request.new_password {
+        if let Err(reason) = password::validate_password(&PasswordPolicy::from_settings(&pool).await, new_password) {
+            return Ok(HttpResponse::BadRequest().json(reason));
+        }
+    }
+
+    // Verify current password
+    let current_user = match db::authenticate_user(&pool, &claims.username, &request.current_password).await {
+        Ok(user) => user,
+        Err(_) => return Ok(HttpResponse::Unauthorized().json("Current password is incorrect")),
     };
 
-    let mut tables = Vec::new();
-    for row in rows {
-        let schema: String = match row.try_get("schemaname") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        let name: String = match row.try_get("tablename") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        let owner: String = match row.try_get("tableowner") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        let table_type: String = match row.try_get("table_type") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        tables.push(TableInfo { name, schema, table_type, owner });
+    // Update username if provided
+    if let Some(new_username) = &request.new_username {
+        if new_username != &current_user.username {
+            match db::update_user_profile(&pool, claims.sub, Some(new_username), None, None, None, None).await {
+                Ok(_) => {},
+                Err(sqlx::Error::Database(db_err)) if db_err.constraint().is_some() => {
+                    return Ok(HttpResponse::Conflict().json("Username already exists"));
+                }
+                Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to update username")),
+            }
+        }
+    }
+
+    // Update password if provided
+    if let Some(new_password) = &request.new_password {
+        match db::reset_user_password(&pool, claims.sub, new_password).await {
+            Ok(_) => {},
+            Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to update password")),
+        }
     }
 
-    Ok(HttpResponse::Ok().json(tables))
+    Ok(ok_message("Admin credentials updated successfully"))
 }
 
-#[derive(Serialize)]
-struct ColumnInfo {
-    name: String,
-    data_type: String,
-    is_nullable: String,
-    default_value: Option<String>,
+/// Largest decoded image accepted as a message attachment.
+const MAX_MESSAGE_ATTACHMENT_BYTES: usize = 1024 * 1024;
+
+/// Image types accepted in message attachments and product galleries.
+const ATTACHMENT_IMAGE_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// Check that an attachment is a base64 image data URL within the size limit.
+fn validate_image_attachment(attachment: &str) -> Result<(), String> {
+    validate_image_data_url(attachment, "Attachment", MAX_MESSAGE_ATTACHMENT_BYTES)
 }
 
-#[get("/api/admin/tables/{table_name}/columns")]
-async fn get_table_columns(
+/// Check that `value` is a base64 data URL of an accepted image type decoding
+/// to at most `max_bytes`; errors name the value as `label`.
+fn validate_image_data_url(value: &str, label: &str, max_bytes: usize) -> Result<(), String> {
+    use base64::Engine;
+
+    let (header, data) = value
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .ok_or_else(|| format!("{} must be a base64 image data URL", label))?;
+    if !ATTACHMENT_IMAGE_TYPES.contains(&header) {
+        return Err(format!("{} type must be one of: {}", label, ATTACHMENT_IMAGE_TYPES.join(", ")));
+    }
+    // Reject oversized payloads before spending time decoding them
+    if data.len() / 4 * 3 > max_bytes + 3 {
+        return Err(format!("{} must be at most {} KB", label, max_bytes / 1024));
+    }
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|_| format!("{} is not valid base64", label))?;
+    if bytes.is_empty() {
+        return Err(format!("{} is empty", label));
+    }
+    if bytes.len() > max_bytes {
+        return Err(format!("{} must be at most {} KB", label, max_bytes / 1024));
+    }
+    Ok(())
+}
+
+/**
+ * POST /messages - Send a message
+ *
+ * Allows authenticated users to send messages to other users, optionally
+ * with an image attachment (PNG, JPEG, GIF or WebP data URL, up to 1 MB).
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param message_req - JSON request with receiver_id, content and optional attachment
+ * @returns JSON of the sent message
+ */
+#[post("/messages")]
+async fn send_message_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    table_name: web::Path<String>
+    hub: web::Data<ChatHub>,
+    message_req: web::Json<SendMessageRequest>
 ) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
+    let claims = match extract_auth(&req) {
+        Ok(claims) => claims,
+        Err(response) => return Ok(response),
+    };
+    let sender_id = claims.sub;
+
+    match db::is_user_active(&pool, message_req.receiver_id).await {
+        Ok(true) => {}
+        Ok(false) => return Ok(HttpResponse::NotFound().json("Recipient not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to send message")),
     }
 
-    // Query to get column information for a specific table
-    let rows = match sqlx::query(
-        "SELECT column_name, data_type, is_nullable, column_default
-         FROM information_schema.columns
-         WHERE table_name = $1 AND table_schema = 'public'
-         ORDER BY ordinal_position"
-    )
-    .bind(&*table_name)
-    .fetch_all(pool.get_ref())
-    .await {
-        Ok(rows) => rows,
-        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch table columns")),
-    };
+    // Admins can always reach users; everyone else is stopped by a block in either direction
+    // and by vendors who only hear from their buyers
+    if claims.role != "Admin" {
+        match db::is_blocked_between(&pool, sender_id, message_req.receiver_id).await {
+            Ok(false) => {}
+            Ok(true) => return Ok(HttpResponse::Forbidden().json("You can't message this user")),
+            Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to send message")),
+        }
+        match db::may_message(&pool, sender_id, message_req.receiver_id).await {
+            Ok(true) => {}
+            Ok(false) => return Ok(HttpResponse::Forbidden().json(json!({
+                "error": "Buyers only",
+                "message": "This vendor only accepts messages from customers who have ordered from them"
+            }))),
+            Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to send message")),
+        }
+    }
 
-    let mut columns = Vec::new();
-    for row in rows {
-        let name: String = match row.try_get("column_name") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        let data_type: String = match row.try_get("data_type") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        let is_nullable: String = match row.try_get("is_nullable") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        let default_value: Option<String> = match row.try_get("column_default") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        columns.push(ColumnInfo { name, data_type, is_nullable, default_value });
+    if let Some(attachment) = &message_req.attachment {
+        if let Err(reason) = validate_image_attachment(attachment) {
+            return Ok(HttpResponse::BadRequest().json(reason));
+        }
     }
 
-    Ok(HttpResponse::Ok().json(columns))
+    // A message needs text unless it carries an attachment
+    let content = match message_req.attachment {
+        Some(_) => validation::optional_text("content", Some(&message_req.content), validation::MESSAGE_CONTENT_MAX, true)
+            .map(Option::unwrap_or_default),
+        None => validation::required_text("content", &message_req.content, validation::MESSAGE_CONTENT_MAX, true),
+    };
+    let content = match content {
+        Ok(content) => content,
+        Err(e) => return Ok(e.to_response()),
+    };
+
+    match db::send_message_with_attachment(&pool, sender_id, message_req.receiver_id, &content, message_req.attachment.as_deref()).await {
+        Ok(message) => {
+            hub.send(message.receiver_id, ServerEvent::Message { message: message.clone() });
+            Ok(HttpResponse::Created().json(message))
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to send message")),
+    }
 }
 
-#[derive(Serialize)]
-struct TableData {
-    columns: Vec<String>,
-    rows: Vec<Vec<serde_json::Value>>,
+/**
+ * GET /ws/messages - Live messaging socket
+ *
+ * Pushes new messages plus typing, delivered and read frames as JSON
+ * (`{"type": "message" | "typing" | "delivered" | "read", ...}`). Clients send
+ * `{"type": "typing", "to_user_id"}` and acknowledge received messages with
+ * `{"type": "delivered", "sender_id", "message_ids"}`. Browsers can't set
+ * headers on a WebSocket, so the JWT may also be passed as `?token=`.
+ *
+ * @param req - HTTP request for authentication
+ * @param body - Upgrade request payload
+ * @param hub - Registry of open sockets
+ * @returns 101 Switching Protocols
+ */
+#[get("/ws/messages")]
+async fn messages_socket(
+    req: actix_web::HttpRequest,
+    body: web::Payload,
+    pool: web::Data<PgPool>,
+    hub: web::Data<ChatHub>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_query_param(req.query_string(), "token") {
+        Some(token) => match verify_jwt(&token) {
+            Ok(claims) => claims,
+            Err(_) => return Ok(HttpResponse::Unauthorized().json("Invalid token")),
+        },
+        None => match extract_auth(&req) {
+            Ok(claims) => claims,
+            Err(response) => return Ok(response),
+        },
+    };
+
+    let (response, session, stream) = actix_ws::handle(&req, body)?;
+    let subscription = hub.subscribe(claims.sub);
+    actix_web::rt::spawn(realtime::run_socket(hub, pool, subscription, session, stream));
+    Ok(response)
 }
 
-#[get("/api/admin/tables/{table_name}/data")]
-async fn get_table_data(
+/**
+ * GET /messages/{user_id} - Get messages between current user and another user
+ *
+ * Retrieves all messages between the authenticated user and the specified user.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param other_user_id - User ID from URL path
+ * @returns JSON array of messages
+ */
+#[get("/messages/{user_id}")]
+async fn get_messages_between_users_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    table_name: web::Path<String>
+    other_user_id: web::Path<i32>
 ) -> ActixResult<HttpResponse> {
-    if let Err(response) = check_admin_auth(&req) {
-        return Ok(response);
-    }
+    let current_user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
 
-    // First get column names
-    let column_rows = match sqlx::query(
-        "SELECT column_name
-         FROM information_schema.columns
-         WHERE table_name = $1 AND table_schema = 'public'
-         ORDER BY ordinal_position"
-    )
-    .bind(&*table_name)
-    .fetch_all(pool.get_ref())
-    .await {
-        Ok(rows) => rows,
-        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch column names")),
+    let page = match page_params(req.query_string()) {
+        Ok(page) => page,
+        Err(response) => return Ok(response),
     };
 
-    let mut columns = Vec::new();
-    for row in &column_rows {
-        let column_name: String = match row.try_get("column_name") {
-            Ok(val) => val,
-            Err(_) => continue,
-        };
-        columns.push(column_name);
+    match db::get_messages_between_users(&pool, current_user_id, *other_user_id).await {
+        Ok(messages) => Ok(listing_response(messages, page)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch messages")),
     }
+}
 
-    // Build dynamic SELECT query
-    if columns.is_empty() {
-        return Ok(HttpResponse::Ok().json(TableData { columns: vec![], rows: vec![] }));
-    }
+/**
+ * GET /messages - Get user's conversations
+ *
+ * Retrieves a list of recent conversations for the authenticated user.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @returns JSON array of conversation previews
+ */
+#[get("/messages")]
+async fn get_user_conversations_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
 
-    let select_clause = columns.join(", ");
-    let query_str = format!("SELECT {} FROM {} LIMIT 100", select_clause, table_name);
+    let page = match page_params(req.query_string()) {
+        Ok(page) => page,
+        Err(response) => return Ok(response),
+    };
 
-    // Execute the dynamic query
-    let data_rows = match sqlx::query(&query_str)
-        .fetch_all(pool.get_ref())
-        .await {
-        Ok(rows) => rows,
-        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch table data")),
+    match db::get_user_conversations(&pool, user_id).await {
+        Ok(conversations) => Ok(listing_response(conversations, page)),
+        Err(e) => {
+            eprintln!("Failed to fetch conversations for user {}: {:?}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch conversations"))
+        },
+    }
+}
+
+/**
+ * PATCH /messages/{user_id}/read - Mark messages as read
+ *
+ * Marks all messages from the specified user as read for the authenticated user.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param other_user_id - User ID from URL path
+ * @returns Success message
+ */
+#[patch("/messages/{user_id}/read")]
+async fn mark_messages_as_read_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    hub: web::Data<ChatHub>,
+    other_user_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    let current_user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
     };
 
-    let mut rows = Vec::new();
-    for data_row in data_rows {
-        let mut row_data = Vec::new();
-        for (i, _col) in columns.iter().enumerate() {
-            // Convert each value to JSON
-            let value: serde_json::Value = match data_row.try_get_raw(i) {
-                Ok(_raw_value) => {
-                    // Try to convert to appropriate JSON type
-                    if let Ok(val) = data_row.try_get::<Option<String>, _>(i) {
-                        val.map_or(serde_json::Value::Null, |s| serde_json::Value::String(s))
-                    } else if let Ok(val) = data_row.try_get::<Option<i32>, _>(i) {
-                        val.map_or(serde_json::Value::Null, |n| serde_json::Value::Number(n.into()))
-                    } else if let Ok(val) = data_row.try_get::<Option<i64>, _>(i) {
-                        val.map_or(serde_json::Value::Null, |n| serde_json::Value::Number(n.into()))
-                    } else if let Ok(val) = data_row.try_get::<Option<f64>, _>(i) {
-                        val.map_or(serde_json::Value::Null, |n| serde_json::Value::Number(serde_json::Number::from_f64(n).unwrap_or(serde_json::Number::from(0))))
-                    } else if let Ok(val) = data_row.try_get::<Option<bool>, _>(i) {
-                        val.map_or(serde_json::Value::Null, |b| serde_json::Value::Bool(b))
-                    } else {
-                        serde_json::Value::String("Unsupported type".to_string())
-                    }
-                }
-                Err(_) => serde_json::Value::Null,
-            };
-            row_data.push(value);
+    match db::mark_messages_as_read(&pool, current_user_id, *other_user_id).await {
+        Ok(message_ids) => {
+            if !message_ids.is_empty() {
+                hub.send(*other_user_id, ServerEvent::Read { user_id: current_user_id, message_ids });
+            }
+            Ok(ok_message("Messages marked as read"))
         }
-        rows.push(row_data);
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to mark messages as read")),
     }
-
-    Ok(HttpResponse::Ok().json(TableData { columns, rows }))
 }
 
-#[derive(Deserialize)]
-struct UpdateProfileImageRequest {
-    profile_image: String,
-}
+/**
+ * PUT /messages/{message_id} - Edit a message
+ *
+ * Allows users to edit their own messages.
+ *
+ * @param message_id - The ID of the message to edit
+ * @body content - The new message content
+ * @returns The updated message object
+ */
+#[put("/messages/{message_id}")]
+async fn edit_message_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    message_id: web::Path<i32>,
+    body: web::Json<serde_json::Value>
+) -> ActixResult<HttpResponse> {
+    let current_user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+
+    let content = match body.get("content").and_then(|c| c.as_str()) {
+        Some(content) => content,
+        None => return Ok(HttpResponse::BadRequest().json("Content is required")),
+    };
+    // Same clean-up and limit as a new message
+    let content = match validation::required_text("content", content, validation::MESSAGE_CONTENT_MAX, true) {
+        Ok(content) => content,
+        Err(e) => return Ok(e.to_response()),
+    };
 
-#[derive(Deserialize)]
-struct UpdateProfileRequest {
-    username: Option<String>,
-    email: Option<String>,
-    secondary_email: Option<String>,
-    mpesa_number: Option<String>,
-    payment_preference: Option<String>,
-    location_string: Option<String>,
-    profile_image: Option<String>,
-    current_password: Option<String>,
-    new_password: Option<String>,
+    match db::edit_message(&pool, *message_id, current_user_id, &content).await {
+        Ok(message) => Ok(HttpResponse::Ok().json(message)),
+        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("Message not found or you don't have permission to edit it")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to edit message")),
+    }
 }
 
-#[derive(Deserialize)]
-struct UpdateAdminCredentialsRequest {
-    current_password: String,
-    new_username: Option<String>,
-    new_password: Option<String>,
-}
+/**
+ * DELETE /messages/{message_id} - Delete a message
+ *
+ * Allows users to delete their own messages.
+ *
+ * @param message_id - The ID of the message to delete
+ * @returns Success message
+ */
+#[delete("/messages/{message_id}")]
+async fn delete_message_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    message_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    let current_user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
 
-#[derive(Deserialize)]
-struct UpdateLocationRequest {
-    latitude: f64,
-    longitude: f64,
-    location_string: Option<String>,
+    match db::delete_message(&pool, *message_id, current_user_id).await {
+        Ok(_) => Ok(ok_message("Message deleted successfully")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to delete message or message not found")),
+    }
 }
 
 /**
- * POST /location/update - Update user location
+ * DELETE /messages/conversations/{user_id} - Archive a conversation
  *
- * Allows authenticated users to update their location coordinates.
+ * Hides the conversation from the caller's conversation list without deleting
+ * any messages; the other user still sees it. It reappears when a new message
+ * is exchanged.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param location_req - JSON request with latitude, longitude, and optional location_string
+ * @param other_user_id - The other participant
  * @returns Success message
  */
-#[post("/location/update")]
-async fn update_location(
+#[delete("/messages/conversations/{user_id}")]
+async fn archive_conversation_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    location_req: web::Json<UpdateLocationRequest>,
+    other_user_id: web::Path<i32>
 ) -> ActixResult<HttpResponse> {
-    let user_id = match extract_auth(&req) {
+    let current_user_id = match extract_auth(&req) {
         Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    match sqlx::query(
-        "UPDATE users SET latitude = $1, longitude = $2, location_string = $3 WHERE id = $4"
-    )
-    .bind(location_req.latitude)
-    .bind(location_req.longitude)
-    .bind(&location_req.location_string)
-    .bind(user_id)
-    .execute(pool.get_ref())
-    .await {
-        Ok(_) => Ok(HttpResponse::Ok().json("Location updated successfully")),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update location")),
+    match db::archive_conversation(&pool, current_user_id, *other_user_id).await {
+        Ok(true) => Ok(ok_message("Conversation archived")),
+        Ok(false) => Ok(HttpResponse::NotFound().json("Conversation not found")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to archive conversation")),
     }
 }
 
-// Profile image update endpoint for users to update their own profile images
-#[patch("/profile/image")]
-async fn update_profile_image(
+/**
+ * POST /block/{user_id} - Block a user
+ *
+ * Stops messages in both directions, hides each user from the other's contact
+ * list and conversations, and removes any follow between them. Admins can't
+ * block or be blocked.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param user_id - User to block
+ * @returns Success message
+ */
+#[post("/block/{user_id}")]
+async fn block_user_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    request: web::Json<UpdateProfileImageRequest>,
+    user_id: web::Path<i32>,
 ) -> ActixResult<HttpResponse> {
     let claims = match extract_auth(&req) {
         Ok(c) => c,
         Err(response) => return Ok(response),
     };
+    let blocked_id = user_id.into_inner();
 
-    match db::update_user_profile_image(&pool, claims.sub, &request.profile_image).await {
-        Ok(_) => Ok(HttpResponse::Ok().json("Profile image updated successfully")),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update profile image")),
+    if blocked_id == claims.sub {
+        return Ok(HttpResponse::BadRequest().json("You can't block yourself"));
+    }
+    let target = match db::get_user_by_id(&pool, blocked_id).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("User not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to block user")),
+    };
+    if claims.role == "Admin" || matches!(target.role, Role::Admin) {
+        return Ok(HttpResponse::BadRequest().json("Blocking doesn't apply to admins"));
+    }
+
+    match db::block_user(&pool, claims.sub, blocked_id).await {
+        Ok(()) => Ok(ok_message("User blocked")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to block user")),
     }
 }
 
-// Profile update endpoint for users to update their own username and email
-#[patch("/profile")]
-async fn update_profile(
+/// DELETE /block/{user_id} - Unblock a user
+#[delete("/block/{user_id}")]
+async fn unblock_user_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    request: web::Json<UpdateProfileRequest>,
+    user_id: web::Path<i32>,
 ) -> ActixResult<HttpResponse> {
-    let claims = match extract_auth(&req) {
-        Ok(c) => c,
+    let blocker_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    match db::update_user_profile(&pool, claims.sub, request.username.as_deref(), request.email.as_deref(), request.secondary_email.as_deref(), request.mpesa_number.as_deref(), request.payment_preference.as_deref()).await {
-        Ok(_) => {
-            // Return a success message with the updated username (if changed)
-            let response = json!({
-                "message": "Profile updated successfully",
-                "new_username": request.username
-            });
-            Ok(HttpResponse::Ok().json(response))
-        }
-        Err(sqlx::Error::Database(db_err)) if db_err.constraint().is_some() => {
-            Ok(HttpResponse::Conflict().json("Username or email already exists"))
-        }
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update profile")),
+    match db::unblock_user(&pool, blocker_id, *user_id).await {
+        Ok(true) => Ok(ok_message("User unblocked")),
+        Ok(false) => Ok(HttpResponse::NotFound().json("User is not blocked")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to unblock user")),
     }
 }
 
-// Comprehensive profile update endpoint - handles all profile fields including password
-#[actix_web::put("/user/profile")]
-async fn update_user_profile_comprehensive(
+/// GET /blocks - Users the authenticated user has blocked
+#[get("/blocks")]
+async fn get_blocks_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let blocker_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_blocks(&pool, blocker_id).await {
+        Ok(blocks) => Ok(HttpResponse::Ok().json(
+            blocks
+                .into_iter()
+                .map(|(id, username, blocked_at)| json!({ "id": id, "username": username, "blocked_at": blocked_at }))
+                .collect::<Vec<_>>(),
+        )),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch blocked users")),
+    }
+}
+
+/**
+ * POST /follow - Follow a vendor
+ *
+ * Allows customers to follow vendors. Idempotent: a new follow is 201 Created,
+ * following again returns the existing relationship with 200.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param follow_req - JSON request with vendor_id
+ * @returns JSON of the follow relationship
+ */
+#[post("/follow")]
+async fn follow_vendor_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    request: web::Json<UpdateProfileRequest>,
+    follow_req: web::Json<FollowRequest>
 ) -> ActixResult<HttpResponse> {
-    let claims = match extract_auth(&req) {
-        Ok(c) => c,
+    let follower_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    // If password change is requested, verify current password first
-    if let (Some(current_pwd), Some(new_pwd)) = (&request.current_password, &request.new_password) {
-        // Verify current password
-        let row = match sqlx::query("SELECT password_hash FROM users WHERE id = $1")
-            .bind(claims.sub)
-            .fetch_one(pool.get_ref())
-            .await {
-            Ok(r) => r,
-            Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to verify password")),
-        };
+    match db::is_blocked_between(&pool, follower_id, follow_req.vendor_id).await {
+        Ok(false) => {}
+        Ok(true) => return Ok(HttpResponse::Forbidden().json("You can't follow this vendor")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to follow vendor")),
+    }
 
-        let stored_hash: String = row.try_get(0).unwrap();
-        let is_valid = bcrypt::verify(current_pwd, &stored_hash).unwrap_or(false);
+    match db::follow_vendor(&pool, follower_id, follow_req.vendor_id).await {
+        Ok((follow, true)) => Ok(HttpResponse::Created().json(follow)),
+        Ok((follow, false)) => Ok(HttpResponse::Ok().json(follow)),
+        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("Vendor not found")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to follow vendor")),
+    }
+}
 
-        if !is_valid {
-            return Ok(HttpResponse::Unauthorized().json("Current password is incorrect"));
-        }
+/**
+ * DELETE /follow/{vendor_id} - Unfollow a vendor
+ *
+ * Allows customers to unfollow vendors. Idempotent: unfollowing a vendor you
+ * don't follow also succeeds, with `unfollowed: false`.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param vendor_id - Vendor ID from URL path
+ * @returns JSON with whether a follow was removed and the vendor's follower count
+ */
+#[delete("/follow/{vendor_id}")]
+async fn unfollow_vendor_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    vendor_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    let follower_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
 
-        // Update password
-        if let Err(_) = db::reset_user_password(&pool, claims.sub, new_pwd).await {
-            return Ok(HttpResponse::InternalServerError().json("Failed to update password"));
-        }
+    let unfollowed = match db::unfollow_vendor(&pool, follower_id, *vendor_id).await {
+        Ok(unfollowed) => unfollowed,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to unfollow vendor")),
+    };
+    match db::get_follower_count(&pool, *vendor_id).await {
+        Ok(follower_count) => Ok(ok_with_message(
+            if unfollowed { "Successfully unfollowed vendor" } else { "You were not following this vendor" },
+            json!({ "unfollowed": unfollowed, "follower_count": follower_count }),
+        )),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to unfollow vendor")),
     }
+}
 
-    // Update profile image if provided
-    if let Some(profile_img) = &request.profile_image {
-        if let Err(_) = db::update_user_profile_image(&pool, claims.sub, profile_img).await {
-            return Ok(HttpResponse::InternalServerError().json("Failed to update profile image"));
-        }
-    }
+/**
+ * GET /follow/{vendor_id} - Check if following a vendor
+ *
+ * Checks if the authenticated user is following the specified vendor.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param vendor_id - Vendor ID from URL path
+ * @returns JSON with following status
+ */
+#[get("/follow/{vendor_id}")]
+async fn is_following_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    vendor_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    let follower_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
 
-    // Update location if provided
-    if let Some(location) = &request.location_string {
-        if let Err(_) = sqlx::query("UPDATE users SET location_string = $1 WHERE id = $2")
-            .bind(location)
-            .bind(claims.sub)
-            .execute(pool.get_ref())
-            .await {
-            return Ok(HttpResponse::InternalServerError().json("Failed to update location"));
-        }
+    match db::is_following(&pool, follower_id, *vendor_id).await {
+        Ok(is_following) => Ok(HttpResponse::Ok().json(json!({ "is_following": is_following }))),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to check follow status")),
     }
+}
 
-    // Update other profile fields
-    match db::update_user_profile(&pool, claims.sub, request.username.as_deref(), request.email.as_deref(), request.secondary_email.as_deref(), request.mpesa_number.as_deref(), request.payment_preference.as_deref()).await {
-        Ok(_) => {
-            // Return updated user data
-            let response = json!({
-                "message": "Profile updated successfully",
-                "username": request.username.as_ref().unwrap_or(&claims.username),
-                "email": request.email.as_ref().unwrap_or(&"".to_string()),
-                "location_string": request.location_string.as_ref().unwrap_or(&"".to_string())
-            });
-            Ok(HttpResponse::Ok().json(response))
-        }
-        Err(sqlx::Error::Database(db_err)) if db_err.constraint().is_some() => {
-            Ok(HttpResponse::Conflict().json("Username or email already exists"))
-        }
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update profile")),
+/**
+ * GET /follow - Get user's follows
+ *
+ * Retrieves all vendors that the authenticated user is following.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @returns JSON array of follows
+ */
+#[get("/follow")]
+async fn get_user_follows_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_user_follows(&pool, user_id).await {
+        Ok(follows) => Ok(HttpResponse::Ok().json(follows)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch follows")),
     }
 }
 
-// Admin credentials update endpoint - allows admin to change their username and password
-#[patch("/admin/credentials")]
-async fn update_admin_credentials(
+/**
+ * GET /followers/{vendor_id} - Get vendor's followers
+ *
+ * Retrieves all followers of the specified vendor.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param vendor_id - Vendor ID from URL path
+ * @returns JSON array of followers
+ */
+#[get("/followers/{vendor_id}")]
+async fn get_vendor_followers_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    request: web::Json<UpdateAdminCredentialsRequest>,
+    vendor_id: web::Path<i32>
 ) -> ActixResult<HttpResponse> {
-    // Only allow admin users
+    // Only allow vendors to see their own followers, or admins
     let claims = match extract_auth(&req) {
         Ok(c) => c,
         Err(response) => return Ok(response),
     };
 
-    if claims.role != "Admin" {
-        return Ok(HttpResponse::Forbidden().json("Admin privileges required"));
+    if claims.role != "Admin" && claims.role != "Vendor" {
+        return Ok(HttpResponse::Forbidden().json("Access denied"));
     }
 
-    // Verify current password
-    let current_user = match db::authenticate_user(&pool, &claims.username, &request.current_password).await {
-        Ok(user) => user,
-        Err(_) => return Ok(HttpResponse::Unauthorized().json("Current password is incorrect")),
-    };
-
-    // Update username if provided
-    if let Some(new_username) = &request.new_username {
-        if new_username != &current_user.username {
-            match db::update_user_profile(&pool, claims.sub, Some(new_username), None, None, None, None).await {
-                Ok(_) => {},
-                Err(sqlx::Error::Database(db_err)) if db_err.constraint().is_some() => {
-                    return Ok(HttpResponse::Conflict().json("Username already exists"));
-                }
-                Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to update username")),
-            }
-        }
+    if claims.role == "Vendor" && claims.sub != *vendor_id {
+        return Ok(HttpResponse::Forbidden().json("Can only view your own followers"));
     }
 
-    // Update password if provided
-    if let Some(new_password) = &request.new_password {
-        match db::reset_user_password(&pool, claims.sub, new_password).await {
-            Ok(_) => {},
-            Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to update password")),
-        }
+    match db::get_vendor_followers(&pool, *vendor_id).await {
+        Ok(followers) => Ok(HttpResponse::Ok().json(followers)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch followers")),
     }
-
-    Ok(HttpResponse::Ok().json(json!({
-        "message": "Admin credentials updated successfully"
-    })))
 }
 
+/// Minimum time between two announcements from the same vendor.
+const ANNOUNCEMENT_INTERVAL_MINUTES: i32 = 60;
+
 /**
- * POST /messages - Send a message
+ * POST /vendor/announcements - Broadcast an announcement to followers
  *
- * Allows authenticated users to send messages to other users.
+ * Stores the announcement and adds an in-app notification for every follower;
+ * with `send_email` followers who accept notification emails are also emailed.
+ * Vendors may post once per `ANNOUNCEMENT_INTERVAL_MINUTES`.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param message_req - JSON request with receiver_id and content
- * @returns JSON of the sent message
+ * @param announcement_req - JSON with title, body and optional send_email
+ * @returns JSON with the announcement and how many followers were notified
  */
-#[post("/messages")]
-async fn send_message_route(
+#[post("/vendor/announcements")]
+async fn create_announcement_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    message_req: web::Json<SendMessageRequest>
+    announcement_req: web::Json<AnnouncementRequest>,
 ) -> ActixResult<HttpResponse> {
-    let sender_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
         Err(response) => return Ok(response),
     };
 
-    match db::send_message(&pool, sender_id, message_req.receiver_id, &message_req.content).await {
-        Ok(message) => Ok(HttpResponse::Created().json(message)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to send message")),
+    let title = match validation::required_text("title", &announcement_req.title, validation::ANNOUNCEMENT_TITLE_MAX, false) {
+        Ok(title) => title,
+        Err(e) => return Ok(e.to_response()),
+    };
+    let body = match validation::required_text("body", &announcement_req.body, validation::ANNOUNCEMENT_BODY_MAX, true) {
+        Ok(body) => body,
+        Err(e) => return Ok(e.to_response()),
+    };
+
+    let (announcement, notified) = match db::create_announcement(&pool, vendor_id, &title, &body, ANNOUNCEMENT_INTERVAL_MINUTES).await {
+        Ok(db::AnnouncementOutcome::Posted { announcement, notified }) => (announcement, notified),
+        Ok(db::AnnouncementOutcome::RateLimited { retry_after_seconds }) => {
+            return Ok(HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after_seconds.to_string()))
+                .json(json!({
+                    "error": "You can post one announcement per hour",
+                    "retry_after_seconds": retry_after_seconds
+                })));
+        }
+        Err(e) => {
+            eprintln!("Failed to create announcement: {:?}", e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to post announcement"));
+        }
+    };
+
+    if announcement_req.send_email {
+        let pool = pool.get_ref().clone();
+        let announcement = announcement.clone();
+        tokio::spawn(async move {
+            let recipients = match db::get_follower_email_recipients(&pool, announcement.vendor_id).await {
+                Ok(recipients) => recipients,
+                Err(e) => {
+                    eprintln!("Failed to load announcement recipients: {:?}", e);
+                    return;
+                }
+            };
+            let summary = format!("{} posted an announcement: {}", announcement.vendor_username, announcement.title);
+            for (user_id, email_address, username) in recipients {
+                if digests::queued_for_digest(&pool, user_id, &summary).await {
+                    continue;
+                }
+                if let Err(e) = email::send_announcement_email(&pool, &email_address, &username, &announcement.vendor_username, &announcement.title, &announcement.body).await {
+                    eprintln!("Failed to send announcement email to {}: {:?}", email_address, e);
+                }
+            }
+        });
+    }
+
+    Ok(HttpResponse::Created().json(json!({
+        "announcement": announcement,
+        "notified": notified
+    })))
+}
+
+/// GET /announcements - Announcements from vendors the caller follows, newest first (`?limit=`, default 50)
+#[get("/announcements")]
+async fn get_announcements_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    let limit = extract_query_param(req.query_string(), "limit")
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(50)
+        .clamp(1, 200);
+
+    match db::get_announcement_feed(&pool, claims.sub, limit).await {
+        Ok(feed) => Ok(HttpResponse::Ok().json(feed)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch announcements")),
+    }
+}
+
+/// GET /notifications - The caller's notifications, newest first (`?unread=true`, `?limit=`)
+#[get("/notifications")]
+async fn get_notifications_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+    let query = req.query_string();
+    let unread_only = extract_query_param(query, "unread").as_deref() == Some("true");
+    let limit = extract_query_param(query, "limit")
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(50)
+        .clamp(1, 200);
+
+    match db::get_notifications(&pool, claims.sub, unread_only, limit).await {
+        Ok(notifications) => Ok(HttpResponse::Ok().json(notifications)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch notifications")),
+    }
+}
+
+/// PATCH /notifications/read - Mark all of the caller's notifications read
+#[patch("/notifications/read")]
+async fn mark_notifications_read_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+
+    match db::mark_notifications_read(&pool, claims.sub).await {
+        Ok(count) => Ok(ok(json!({ "marked_read": count }))),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update notifications")),
     }
 }
 
 /**
- * GET /messages/{user_id} - Get messages between current user and another user
+ * POST /reviews - Create a product review
  *
- * Retrieves all messages between the authenticated user and the specified user.
+ * Allows customers to leave reviews for products they've purchased.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param other_user_id - User ID from URL path
- * @returns JSON array of messages
+ * @param review_req - JSON request with product_id, rating, and comment
+ * @returns JSON of the created review, or 409 with the existing `review` and its `review_id`
  */
-#[get("/messages/{user_id}")]
-async fn get_messages_between_users_route(
+#[post("/reviews")]
+async fn create_review_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    other_user_id: web::Path<i32>
+    review_req: web::Json<CreateReviewRequest>
 ) -> ActixResult<HttpResponse> {
-    let current_user_id = match extract_auth(&req) {
+    let customer_id = match extract_auth(&req) {
         Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    match db::get_messages_between_users(&pool, current_user_id, *other_user_id).await {
-        Ok(messages) => Ok(HttpResponse::Ok().json(messages)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch messages")),
+    let comment = match validation::optional_text("comment", review_req.comment.as_deref(), validation::REVIEW_COMMENT_MAX, true) {
+        Ok(comment) => comment,
+        Err(e) => return Ok(e.to_response()),
+    };
+
+    match db::create_review(&pool, customer_id, review_req.product_id, review_req.rating, comment.as_deref()).await {
+        Ok(review) => Ok(HttpResponse::Created().json(review)),
+        // One review per customer and product; point them at the one they wrote
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            let existing = match db::get_customer_reviews(&pool, customer_id).await {
+                Ok(reviews) => reviews.into_iter().find(|review| review.product_id == review_req.product_id),
+                Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to create review")),
+            };
+            Ok(HttpResponse::Conflict().json(json!({
+                "message": "You have already reviewed this product. Edit your existing review instead.",
+                "review_id": existing.as_ref().map(|review| review.id),
+                "review": existing
+            })))
+        }
+        Err(e) => {
+            eprintln!("Failed to create review: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to create review"))
+        }
     }
 }
 
 /**
- * GET /messages - Get user's conversations
+ * GET /reviews/product/{product_id} - Get reviews for a product
  *
- * Retrieves a list of recent conversations for the authenticated user.
+ * Retrieves the reviews for the specified product, ordered by `sort`
+ * (`newest` by default, `highest`, `lowest` or `helpful`). With `limit` or
+ * `offset` only that page is returned, along with the total and a rating summary.
  *
- * @param req - HTTP request for authentication
+ * @param req - HTTP request for query parameters
  * @param pool - Database connection pool
- * @returns JSON array of conversation previews
+ * @param product_id - Product ID from URL path
+ * @returns JSON array of reviews, or a page of them with `summary`
  */
-#[get("/messages")]
-async fn get_user_conversations_route(
+#[get("/reviews/product/{product_id}")]
+async fn get_product_reviews_route(
     req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
+    pool: web::Data<PgPool>,
+    product_id: web::Path<i32>
 ) -> ActixResult<HttpResponse> {
-    let user_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+    // Allow anyone to view reviews
+    let sort = match parse_query_param::<ReviewSort>(req.query_string(), "sort") {
+        Ok(sort) => sort.unwrap_or_default(),
+        Err(response) => return Ok(response),
+    };
+    let page = match page_params(req.query_string()) {
+        Ok(page) => page,
         Err(response) => return Ok(response),
     };
 
-    match db::get_user_conversations(&pool, user_id).await {
-        Ok(conversations) => Ok(HttpResponse::Ok().json(conversations)),
-        Err(e) => {
-            eprintln!("Failed to fetch conversations for user {}: {:?}", user_id, e);
-            Ok(HttpResponse::InternalServerError().json("Failed to fetch conversations"))
-        },
+    let reviews = match db::get_product_reviews(&pool, *product_id, sort, page).await {
+        Ok(reviews) => reviews,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to fetch reviews")),
+    };
+    let Some((limit, offset)) = page else {
+        return Ok(HttpResponse::Ok().json(reviews));
+    };
+    match db::get_product_review_summary(&pool, *product_id).await {
+        Ok(summary) => Ok(HttpResponse::Ok().json(ReviewPage {
+            page: Paginated::new(reviews, summary.review_count, limit, offset),
+            summary,
+        })),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch reviews")),
     }
 }
 
 /**
- * PATCH /messages/{user_id}/read - Mark messages as read
+ * POST /reviews/{review_id}/vote - Vote on whether a review is helpful
  *
- * Marks all messages from the specified user as read for the authenticated user.
+ * Each user has one vote per review; voting again replaces it.
+ * Authors cannot vote on their own reviews.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param other_user_id - User ID from URL path
- * @returns Success message
+ * @param review_id - Review ID from URL path
+ * @param vote_req - JSON request with helpful flag
+ * @returns JSON with the updated helpful_count
  */
-#[patch("/messages/{user_id}/read")]
-async fn mark_messages_as_read_route(
+#[post("/reviews/{review_id}/vote")]
+async fn vote_on_review_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    other_user_id: web::Path<i32>
+    review_id: web::Path<i32>,
+    vote_req: web::Json<ReviewVoteRequest>
 ) -> ActixResult<HttpResponse> {
-    let current_user_id = match extract_auth(&req) {
+    let user_id = match extract_auth(&req) {
         Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    match db::mark_messages_as_read(&pool, current_user_id, *other_user_id).await {
-        Ok(_) => Ok(HttpResponse::Ok().json("Messages marked as read")),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to mark messages as read")),
+    match db::get_review_customer_id(&pool, *review_id).await {
+        Ok(author_id) if author_id == user_id => {
+            return Ok(HttpResponse::Forbidden().json("You cannot vote on your own review"));
+        }
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("Review not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to record vote")),
+    }
+
+    match db::vote_on_review(&pool, user_id, *review_id, vote_req.helpful).await {
+        Ok(helpful_count) => Ok(ok(json!({ "helpful_count": helpful_count }))),
+        Err(e) => {
+            eprintln!("Failed to record review vote: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to record vote"))
+        }
     }
 }
 
 /**
- * PUT /messages/{message_id} - Edit a message
+ * POST /reviews/{review_id}/reply - Reply to a review
  *
- * Allows users to edit their own messages.
+ * Lets the vendor who owns the reviewed product post a public response.
+ * Each review has at most one reply; posting again edits it.
  *
- * @param message_id - The ID of the message to edit
- * @body content - The new message content
- * @returns The updated message object
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param review_id - Review ID from URL path
+ * @param reply_req - JSON request with the reply content
+ * @returns JSON of the reply (201 when created, 200 when edited)
  */
-#[put("/messages/{message_id}")]
-async fn edit_message_route(
+#[post("/reviews/{review_id}/reply")]
+async fn reply_to_review_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    message_id: web::Path<i32>,
-    body: web::Json<serde_json::Value>
+    review_id: web::Path<i32>,
+    reply_req: web::Json<ReviewReplyRequest>
 ) -> ActixResult<HttpResponse> {
-    let current_user_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
         Err(response) => return Ok(response),
     };
 
-    let content = match body.get("content").and_then(|c| c.as_str()) {
-        Some(content) => content.trim(),
-        None => return Ok(HttpResponse::BadRequest().json("Content is required")),
+    let content = match validation::required_text("content", &reply_req.content, validation::REVIEW_COMMENT_MAX, true) {
+        Ok(content) => content,
+        Err(e) => return Ok(e.to_response()),
     };
 
-    if content.is_empty() {
-        return Ok(HttpResponse::BadRequest().json("Message content cannot be empty"));
-    }
-
-    if content.len() > 500 {
-        return Ok(HttpResponse::BadRequest().json("Message content too long (max 500 characters)"));
+    match db::get_review_vendor_id(&pool, *review_id).await {
+        Ok(owner_id) if owner_id == vendor_id => {}
+        Ok(_) => return Ok(HttpResponse::Forbidden().json("Only the vendor of the reviewed product can reply")),
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("Review not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to save reply")),
     }
 
-    match db::edit_message(&pool, *message_id, current_user_id, content).await {
-        Ok(message) => Ok(HttpResponse::Ok().json(message)),
-        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("Message not found or you don't have permission to edit it")),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to edit message")),
+    match db::upsert_review_reply(&pool, *review_id, vendor_id, &content).await {
+        Ok((reply, true)) => Ok(HttpResponse::Created().json(reply)),
+        Ok((reply, false)) => Ok(HttpResponse::Ok().json(reply)),
+        Err(e) => {
+            eprintln!("Failed to save review reply: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to save reply"))
+        }
     }
 }
 
 /**
- * DELETE /messages/{message_id} - Delete a message
+ * GET /reviews - Get customer's reviews
  *
- * Allows users to delete their own messages.
+ * Retrieves all reviews written by the authenticated customer.
  *
- * @param message_id - The ID of the message to delete
- * @returns Success message
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @returns JSON array of customer's reviews
  */
-#[delete("/messages/{message_id}")]
-async fn delete_message_route(
+#[get("/reviews")]
+async fn get_customer_reviews_route(
     req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    message_id: web::Path<i32>
+    pool: web::Data<PgPool>
 ) -> ActixResult<HttpResponse> {
-    let current_user_id = match extract_auth(&req) {
+    let customer_id = match extract_auth(&req) {
         Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    match db::delete_message(&pool, *message_id, current_user_id).await {
-        Ok(_) => Ok(HttpResponse::Ok().json("Message deleted successfully")),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to delete message or message not found")),
+    match db::get_customer_reviews(&pool, customer_id).await {
+        Ok(reviews) => Ok(HttpResponse::Ok().json(reviews)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch reviews")),
     }
 }
 
 /**
- * POST /follow - Follow a vendor
+ * POST /shipping - Create a shipping order
  *
- * Allows customers to follow vendors.
+ * Allows customers to create shipping orders for products.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param follow_req - JSON request with vendor_id
- * @returns JSON of the follow relationship
+ * @param shipping_req - JSON request with product_id, quantity, and shipping_address
+ * @returns JSON of the created shipping order
  */
-#[post("/follow")]
-async fn follow_vendor_route(
+#[post("/shipping")]
+async fn create_shipping_order_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    follow_req: web::Json<FollowRequest>
+    shipping_req: web::Json<CreateShippingOrderRequest>
 ) -> ActixResult<HttpResponse> {
-    let follower_id = match extract_auth(&req) {
+    let customer_id = match extract_auth(&req) {
         Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    match db::follow_vendor(&pool, follower_id, follow_req.vendor_id).await {
-        Ok(follow) => Ok(HttpResponse::Created().json(follow)),
-        Err(sqlx::Error::Database(db_err)) if db_err.constraint().is_some() => {
-            Ok(HttpResponse::Conflict().json("Already following this vendor"))
-        }
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to follow vendor")),
+    match db::create_shipping_order(&pool, customer_id, shipping_req.product_id, shipping_req.quantity, &shipping_req.shipping_address).await {
+        Ok(order) => Ok(HttpResponse::Created().json(order)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create shipping order")),
     }
 }
 
 /**
- * DELETE /follow/{vendor_id} - Unfollow a vendor
+ * GET /shipping - Get customer's shipping orders
  *
- * Allows customers to unfollow vendors.
+ * Retrieves all shipping orders for the authenticated customer.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param vendor_id - Vendor ID from URL path
- * @returns Success message
+ * @returns JSON array of customer's shipping orders
  */
-#[delete("/follow/{vendor_id}")]
-async fn unfollow_vendor_route(
+#[get("/shipping")]
+async fn get_customer_shipping_orders_route(
     req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    vendor_id: web::Path<i32>
+    pool: web::Data<PgPool>
 ) -> ActixResult<HttpResponse> {
-    let follower_id = match extract_auth(&req) {
+    let customer_id = match extract_auth(&req) {
         Ok(claims) => claims.sub,
         Err(response) => return Ok(response),
     };
 
-    match db::unfollow_vendor(&pool, follower_id, *vendor_id).await {
-        Ok(_) => Ok(HttpResponse::Ok().json("Successfully unfollowed vendor")),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to unfollow vendor")),
+    let page = match page_params(req.query_string()) {
+        Ok(page) => page,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_customer_shipping_orders(&pool, customer_id).await {
+        Ok(orders) => Ok(listing_response(orders, page)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch shipping orders")),
     }
 }
 
 /**
- * GET /follow/{vendor_id} - Check if following a vendor
+ * GET /shipping/vendor - Get vendor's shipping orders
  *
- * Checks if the authenticated user is following the specified vendor.
+ * Retrieves all shipping orders for the authenticated vendor.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param vendor_id - Vendor ID from URL path
- * @returns JSON with following status
+ * @returns JSON array of vendor's shipping orders
  */
-#[get("/follow/{vendor_id}")]
-async fn is_following_route(
+#[get("/shipping/vendor")]
+async fn get_vendor_shipping_orders_route(
     req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    vendor_id: web::Path<i32>
+    pool: web::Data<PgPool>
 ) -> ActixResult<HttpResponse> {
-    let follower_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
         Err(response) => return Ok(response),
     };
 
-    match db::is_following(&pool, follower_id, *vendor_id).await {
-        Ok(is_following) => Ok(HttpResponse::Ok().json(json!({ "is_following": is_following }))),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to check follow status")),
+    let page = match page_params(req.query_string()) {
+        Ok(page) => page,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_vendor_shipping_orders(&pool, vendor_id).await {
+        Ok(orders) => Ok(listing_response(orders, page)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch shipping orders")),
     }
 }
 
 /**
- * GET /follow - Get user's follows
+ * PATCH /shipping/{order_id}/status - Update shipping order status
  *
- * Retrieves all vendors that the authenticated user is following.
+ * Allows vendors to update the shipping status of their orders to one of
+ * `processing`, `shipped`, `delivered` or `cancelled`; anything else is a 400.
+ * An order must be accepted before it can be marked shipped or delivered.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @returns JSON array of follows
+ * @param order_id - Order ID from URL path
+ * @param status_req - JSON request with shipping_status and optional tracking_number
+ * @returns Success message
  */
-#[get("/follow")]
-async fn get_user_follows_route(
+#[patch("/shipping/{order_id}/status")]
+async fn update_shipping_status_route(
     req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
+    pool: web::Data<PgPool>,
+    order_id: web::Path<i32>,
+    status_req: web::Json<UpdateShippingStatusRequest>
 ) -> ActixResult<HttpResponse> {
-    let user_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+    let shipping_status = match parse_shipping_status(&status_req.shipping_status) {
+        Ok(status) => status,
         Err(response) => return Ok(response),
     };
 
-    match db::get_user_follows(&pool, user_id).await {
-        Ok(follows) => Ok(HttpResponse::Ok().json(follows)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch follows")),
+    // Verify the order belongs to this vendor
+    let (order_vendor_id, current_status): (i32, String) = match sqlx::query_as(
+        "SELECT vendor_id, COALESCE(shipping_status, 'pending') FROM shipping_orders WHERE id = $1"
+    )
+        .bind(*order_id)
+        .fetch_one(pool.get_ref())
+        .await {
+        Ok(order) => order,
+        Err(_) => return Ok(HttpResponse::NotFound().json("Order not found")),
+    };
+
+    if order_vendor_id != vendor_id {
+        return Ok(HttpResponse::Forbidden().json("Can only update your own orders"));
+    }
+    if let Err(message) = check_acceptance(&current_status, shipping_status) {
+        return Ok(HttpResponse::Conflict().json(message));
+    }
+
+    match db::update_shipping_status(&pool, *order_id, shipping_status, status_req.tracking_number.as_deref()).await {
+        Ok(_) => {
+            // If status is "delivered", request customer verification
+            if shipping_status == "delivered" {
+                let _ = db::request_delivery_verification(&pool, *order_id).await;
+                println!("📦 Order {} marked as delivered - verification requested from customer", order_id);
+            }
+            Ok(ok_message("Shipping status updated successfully"))
+        },
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update shipping status")),
     }
 }
 
 /**
- * GET /followers/{vendor_id} - Get vendor's followers
+ * POST /shipping/{order_id}/cancel - Customer cancels an order before it ships
  *
- * Retrieves all followers of the specified vendor.
+ * Only the order's customer may cancel, and only while it is `pending`.
+ * The ordered quantity goes back into stock, a paid order is refunded to the
+ * customer's wallet, and the vendor is emailed.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param vendor_id - Vendor ID from URL path
- * @returns JSON array of followers
+ * @param order_id - Order ID from URL path
+ * @returns JSON with the refunded amount
  */
-#[get("/followers/{vendor_id}")]
-async fn get_vendor_followers_route(
+#[post("/shipping/{order_id}/cancel")]
+async fn cancel_order_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    vendor_id: web::Path<i32>
+    order_id: web::Path<i32>,
 ) -> ActixResult<HttpResponse> {
-    // Only allow vendors to see their own followers, or admins
     let claims = match extract_auth(&req) {
         Ok(c) => c,
         Err(response) => return Ok(response),
     };
 
-    if claims.role != "Admin" && claims.role != "Vendor" {
-        return Ok(HttpResponse::Forbidden().json("Access denied"));
+    let order: (i32, i32, i32, String) = match sqlx::query_as(
+        r#"
+        SELECT so.customer_id, so.vendor_id, so.quantity, p.name
+        FROM shipping_orders so JOIN products p ON so.product_id = p.id
+        WHERE so.id = $1
+        "#,
+    )
+    .bind(*order_id)
+    .fetch_one(pool.get_ref())
+    .await
+    {
+        Ok(order) => order,
+        Err(_) => return Ok(HttpResponse::NotFound().json("Order not found")),
+    };
+    let (customer_id, vendor_id, quantity, product_name) = order;
+    if customer_id != claims.sub {
+        return Ok(HttpResponse::Forbidden().json("Can only cancel your own orders"));
     }
 
-    if claims.role == "Vendor" && claims.sub != *vendor_id {
-        return Ok(HttpResponse::Forbidden().json("Can only view your own followers"));
+    match db::cancel_pending_order(&pool, *order_id).await {
+        Ok(Some(refunded)) => {
+            let summary = format!("Order #{} ({} x {}) was cancelled by the customer", *order_id, quantity, product_name);
+            // Vendors on a digest get this with their next one instead
+            if !digests::queued_for_digest(&pool, vendor_id, &summary).await {
+                if let Ok(vendor) = db::get_user_by_id(&pool, vendor_id).await {
+                    let language = email_templates::user_language(&pool, vendor_id).await;
+                    if let Err(e) = email::send_order_cancelled_email(&pool, &vendor.email, &vendor.username, *order_id, &product_name, quantity, &language).await {
+                        eprintln!("Failed to send cancellation email to {}: {:?}", vendor.email, e);
+                    }
+                }
+            }
+            Ok(ok_with_message("Order cancelled", json!({ "order_id": *order_id, "refunded": refunded })))
+        }
+        Ok(None) => Ok(HttpResponse::Conflict().json("Only pending orders can be cancelled")),
+        Err(e) => {
+            eprintln!("Failed to cancel order {}: {:?}", order_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to cancel order"))
+        }
     }
+}
 
-    match db::get_vendor_followers(&pool, *vendor_id).await {
-        Ok(followers) => Ok(HttpResponse::Ok().json(followers)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch followers")),
+/// Shipping statuses a vendor can set; `pending` and `declined` are only reached
+/// through placing and declining an order.
+const VENDOR_SHIPPING_STATUSES: [&str; 4] = ["processing", "shipped", "delivered", "cancelled"];
+
+/// Match a requested shipping status case-insensitively against
+/// `VENDOR_SHIPPING_STATUSES`, or a 400 naming the allowed values.
+fn parse_shipping_status(shipping_status: &str) -> Result<&'static str, HttpResponse> {
+    let requested = shipping_status.trim();
+    VENDOR_SHIPPING_STATUSES
+        .iter()
+        .find(|status| status.eq_ignore_ascii_case(requested))
+        .copied()
+        .ok_or_else(|| {
+            HttpResponse::BadRequest().json(format!(
+                "shipping_status must be one of: {}",
+                VENDOR_SHIPPING_STATUSES.join(", ")
+            ))
+        })
+}
+
+/// Refuse to move an order its vendor hasn't accepted yet (still `pending`)
+/// on to `shipped` or `delivered`. Shared by the single and bulk status routes.
+fn check_acceptance(current_status: &str, shipping_status: &str) -> Result<(), &'static str> {
+    let needs_acceptance = ["shipped", "delivered"].contains(&shipping_status.to_lowercase().as_str());
+    if needs_acceptance && current_status == "pending" {
+        Err("Order must be accepted first")
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that `order_id` exists and belongs to `vendor_id`.
+async fn check_order_vendor(pool: &PgPool, order_id: i32, vendor_id: i32) -> Result<(), HttpResponse> {
+    match db::get_shipping_order_summary(pool, order_id).await {
+        Ok(Some((_, order_vendor_id, _, _))) if order_vendor_id == vendor_id => Ok(()),
+        Ok(Some(_)) => Err(HttpResponse::Forbidden().json("Can only update your own orders")),
+        Ok(None) => Err(HttpResponse::NotFound().json("Order not found")),
+        Err(_) => Err(HttpResponse::InternalServerError().json("Failed to look up order")),
     }
 }
 
 /**
- * POST /reviews - Create a product review
+ * GET /shipping/vendor/pending-acceptance - Orders waiting for the vendor to accept them
  *
- * Allows customers to leave reviews for products they've purchased.
+ * Oldest first, each with `accept_by`: the time it is declined automatically
+ * if still not accepted (`order_acceptance_hours` after it was placed).
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param review_req - JSON request with product_id, rating, and comment
- * @returns JSON of the created review
+ * @returns JSON with the count, the window in hours and the orders
  */
-#[post("/reviews")]
-async fn create_review_route(
+#[get("/shipping/vendor/pending-acceptance")]
+async fn get_pending_acceptance_orders_route(
     req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>,
-    review_req: web::Json<CreateReviewRequest>
+    pool: web::Data<PgPool>
 ) -> ActixResult<HttpResponse> {
-    let customer_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
         Err(response) => return Ok(response),
     };
 
-    match db::create_review(&pool, customer_id, review_req.product_id, review_req.rating, review_req.comment.as_deref()).await {
-        Ok(review) => Ok(HttpResponse::Created().json(review)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create review")),
+    let window_hours = acceptance::window_hours(&pool).await;
+    match db::get_pending_acceptance_orders(&pool, vendor_id, window_hours).await {
+        Ok(orders) => Ok(HttpResponse::Ok().json(json!({
+            "count": orders.len(),
+            "acceptance_window_hours": window_hours,
+            "orders": orders
+        }))),
+        Err(e) => {
+            eprintln!("Failed to fetch orders pending acceptance: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch shipping orders"))
+        }
     }
 }
 
 /**
- * GET /reviews/product/{product_id} - Get reviews for a product
+ * POST /shipping/{order_id}/accept - Vendor accepts a new order
  *
- * Retrieves all reviews for the specified product.
+ * Moves a `pending` order to `processing`, after which it can be shipped.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param product_id - Product ID from URL path
- * @returns JSON array of reviews
+ * @param order_id - Order ID from URL path
+ * @returns Success message
  */
-#[get("/reviews/product/{product_id}")]
-async fn get_product_reviews_route(
-    _req: actix_web::HttpRequest,
+#[post("/shipping/{order_id}/accept")]
+async fn accept_order_route(
+    req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    product_id: web::Path<i32>
+    order_id: web::Path<i32>,
 ) -> ActixResult<HttpResponse> {
-    // Allow anyone to view reviews
-    match db::get_product_reviews(&pool, *product_id).await {
-        Ok(reviews) => Ok(HttpResponse::Ok().json(reviews)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch reviews")),
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+    if let Err(response) = check_order_vendor(&pool, *order_id, vendor_id).await {
+        return Ok(response);
+    }
+
+    match db::accept_pending_order(&pool, *order_id).await {
+        Ok(true) => Ok(ok_with_message("Order accepted", json!({ "order_id": *order_id, "shipping_status": "processing" }))),
+        Ok(false) => Ok(HttpResponse::Conflict().json("Only pending orders can be accepted")),
+        Err(e) => {
+            eprintln!("Failed to accept order {}: {:?}", order_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to accept order"))
+        }
     }
 }
 
 /**
- * GET /reviews - Get customer's reviews
+ * POST /shipping/{order_id}/decline - Vendor declines an order they can't fulfill
  *
- * Retrieves all reviews written by the authenticated customer.
+ * Only while the order is `pending`. The quantity goes back into stock, a paid
+ * order is refunded to the customer's wallet, and the customer is emailed.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @returns JSON array of customer's reviews
+ * @param order_id - Order ID from URL path
+ * @returns JSON with the refunded amount
  */
-#[get("/reviews")]
-async fn get_customer_reviews_route(
+#[post("/shipping/{order_id}/decline")]
+async fn decline_order_route(
     req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
+    pool: web::Data<PgPool>,
+    order_id: web::Path<i32>,
 ) -> ActixResult<HttpResponse> {
-    let customer_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
         Err(response) => return Ok(response),
     };
+    if let Err(response) = check_order_vendor(&pool, *order_id, vendor_id).await {
+        return Ok(response);
+    }
 
-    match db::get_customer_reviews(&pool, customer_id).await {
-        Ok(reviews) => Ok(HttpResponse::Ok().json(reviews)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch reviews")),
+    match acceptance::decline_order(&pool, *order_id).await {
+        Ok(Some(refunded)) => Ok(ok_with_message("Order declined", json!({ "order_id": *order_id, "refunded": refunded }))),
+        Ok(None) => Ok(HttpResponse::Conflict().json("Only pending orders can be declined")),
+        Err(e) => {
+            eprintln!("Failed to decline order {}: {:?}", order_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to decline order"))
+        }
     }
 }
 
 /**
- * POST /shipping - Create a shipping order
+ * PATCH /shipping/bulk-status - Update the status of several orders at once
  *
- * Allows customers to create shipping orders for products.
+ * Every order must belong to the authenticated vendor; otherwise the whole batch
+ * is rejected unless `skip_foreign` is set, in which case those orders are
+ * reported as failures and the rest are updated. Updates run in one transaction.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @param shipping_req - JSON request with product_id, quantity, and shipping_address
- * @returns JSON of the created shipping order
+ * @param bulk_req - JSON request with order_ids, shipping_status and skip_foreign
+ * @returns JSON with per-order results
  */
-#[post("/shipping")]
-async fn create_shipping_order_route(
+#[patch("/shipping/bulk-status")]
+async fn bulk_update_shipping_status_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    shipping_req: web::Json<CreateShippingOrderRequest>
+    bulk_req: web::Json<BulkShippingStatusRequest>
 ) -> ActixResult<HttpResponse> {
-    let customer_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
         Err(response) => return Ok(response),
     };
 
-    match db::create_shipping_order(&pool, customer_id, shipping_req.product_id, shipping_req.quantity, &shipping_req.shipping_address).await {
-        Ok(order) => Ok(HttpResponse::Created().json(order)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create shipping order")),
+    let shipping_status = match parse_shipping_status(&bulk_req.shipping_status) {
+        Ok(status) => status,
+        Err(response) => return Ok(response),
+    };
+
+    let mut order_ids = bulk_req.order_ids.clone();
+    order_ids.sort_unstable();
+    order_ids.dedup();
+    if order_ids.is_empty() {
+        return Ok(HttpResponse::BadRequest().json("order_ids must not be empty"));
+    }
+
+    let orders = match db::get_order_vendors_and_statuses(&pool, &order_ids).await {
+        Ok(orders) => orders,
+        Err(e) => {
+            eprintln!("Failed to look up orders for bulk update: {:?}", e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to update shipping status"));
+        }
+    };
+
+    let rejected: Vec<i32> = order_ids
+        .iter()
+        .copied()
+        .filter(|id| orders.get(id).map(|(owner, _)| *owner) != Some(vendor_id))
+        .collect();
+    if !rejected.is_empty() && !bulk_req.skip_foreign {
+        return Ok(HttpResponse::Forbidden().json(json!({
+            "error": "Can only update your own orders",
+            "order_ids": rejected
+        })));
+    }
+
+    // Orders the vendor hasn't accepted yet can't move on to shipping
+    let unaccepted: std::collections::HashMap<i32, &str> = orders
+        .iter()
+        .filter_map(|(&id, (_, current_status))| {
+            check_acceptance(current_status, shipping_status).err().map(|message| (id, message))
+        })
+        .collect();
+
+    let owned: Vec<i32> = order_ids
+        .iter()
+        .copied()
+        .filter(|id| !rejected.contains(id) && !unaccepted.contains_key(id))
+        .collect();
+    let updated = match db::bulk_update_shipping_status(&pool, vendor_id, &owned, shipping_status).await {
+        Ok(updated) => updated,
+        Err(e) => {
+            eprintln!("Bulk shipping status update failed: {:?}", e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to update shipping status"));
+        }
+    };
+
+    let results: Vec<BulkShippingStatusResult> = order_ids
+        .iter()
+        .map(|&order_id| {
+            let error = if updated.contains(&order_id) {
+                None
+            } else if !orders.contains_key(&order_id) {
+                Some("Order not found".to_string())
+            } else if rejected.contains(&order_id) {
+                Some("Order belongs to another vendor".to_string())
+            } else if let Some(message) = unaccepted.get(&order_id) {
+                Some(message.to_string())
+            } else {
+                Some("Order was not updated".to_string())
+            };
+            BulkShippingStatusResult { order_id, success: error.is_none(), error }
+        })
+        .collect();
+
+    if shipping_status == "delivered" && !updated.is_empty() {
+        println!("📦 {} orders marked as delivered - verification requested from customers", updated.len());
+    }
+
+    Ok(ok(json!({
+        "updated": updated.len(),
+        "results": results
+    })))
+}
+
+/// Default and longest window for GET /vendors/leaderboard, in days.
+const LEADERBOARD_DEFAULT_DAYS: i32 = 30;
+const LEADERBOARD_MAX_DAYS: i32 = 365;
+
+/// Vendors per page of GET /vendors/leaderboard when no `limit` is given.
+const LEADERBOARD_DEFAULT_LIMIT: i64 = 10;
+
+/// GET /vendors/leaderboard?sort=&days=&limit=&offset= - Verified vendors ranked
+/// by `revenue` (default), `orders` or `rating` over the last `days` days
+/// (default 30), as a `Paginated` page. Results are cached briefly.
+#[get("/vendors/leaderboard")]
+async fn get_vendor_leaderboard(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let sort = match parse_query_param::<crate::models::LeaderboardSort>(req.query_string(), "sort") {
+        Ok(sort) => sort.unwrap_or_default(),
+        Err(response) => return Ok(response),
+    };
+    let days = match parse_query_param::<i32>(req.query_string(), "days") {
+        Ok(days) => days.unwrap_or(LEADERBOARD_DEFAULT_DAYS).clamp(1, LEADERBOARD_MAX_DAYS),
+        Err(response) => return Ok(response),
+    };
+    let (limit, offset) = match page_params(req.query_string()) {
+        Ok(page) => page.unwrap_or((LEADERBOARD_DEFAULT_LIMIT, 0)),
+        Err(response) => return Ok(response),
+    };
+
+    match leaderboard::leaderboard(&pool, days, sort).await {
+        Ok(entries) => Ok(HttpResponse::Ok().json(Paginated::from_all(entries, limit, offset))),
+        Err(e) => {
+            eprintln!("Failed to build vendor leaderboard: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch vendor leaderboard"))
+        }
     }
 }
 
 /**
- * GET /shipping - Get customer's shipping orders
+ * GET /vendors/{vendor_id}/profile - Get vendor profile information
  *
- * Retrieves all shipping orders for the authenticated customer.
+ * Retrieves vendor profile information including total purchases, revenue, and follower count.
+ * Available to all authenticated users; `distance_km` is included when both the viewer and
+ * the vendor have coordinates.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @returns JSON array of customer's shipping orders
+ * @param vendor_id - Vendor ID from URL path
+ * @returns JSON of vendor profile information
  */
-#[get("/shipping")]
-async fn get_customer_shipping_orders_route(
+#[get("/vendors/{vendor_id}/profile")]
+async fn get_vendor_profile_route(
     req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
+    pool: web::Data<PgPool>,
+    vendor_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    // Require authentication
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_vendor_profile(&pool, *vendor_id).await {
+        Ok(mut profile) => {
+            if let Ok(coordinates) = db::get_user_coordinates(&pool, &[claims.sub, profile.id]).await {
+                if let (Some(&viewer), Some(&vendor)) = (coordinates.get(&claims.sub), coordinates.get(&profile.id)) {
+                    profile.distance_km = Some((shipping::distance_km(viewer, vendor) * 10.0).round() / 10.0);
+                }
+            }
+            Ok(HttpResponse::Ok().json(profile))
+        }
+        Err(_) => Ok(HttpResponse::NotFound().json("Vendor not found")),
+    }
+}
+
+/// GET /vendors/{vendor_id}/products - A vendor's storefront (public). Takes the
+/// `tag`, `sort=featured` and `limit`/`offset` options of GET /products, plus `q`
+/// to search names and descriptions.
+#[get("/vendors/{vendor_id}/products")]
+async fn get_vendor_products(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    vendor_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let query_string = req.query_string();
+    let tag = extract_query_param(query_string, "tag");
+    let search = extract_query_param(query_string, "q");
+    let featured_first = extract_query_param(query_string, "sort").as_deref() == Some("featured");
+    let page = match page_params(query_string) {
+        Ok(page) => page,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_vendor_storefront_products(&pool, *vendor_id, tag.as_deref(), search.as_deref(), featured_first).await {
+        Ok(Some(products)) => Ok(listing_response(products, page)),
+        Ok(None) => Ok(HttpResponse::NotFound().json("Vendor not found")),
+        Err(e) => {
+            eprintln!("Failed to fetch vendor products: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch products"))
+        }
+    }
+}
+
+/// GET /vendors/{vendor_id}/shipping-options - A vendor's active shipping options
+#[get("/vendors/{vendor_id}/shipping-options")]
+async fn get_vendor_shipping_options_route(
+    pool: web::Data<PgPool>,
+    vendor_id: web::Path<i32>,
 ) -> ActixResult<HttpResponse> {
-    let customer_id = match extract_auth(&req) {
-        Ok(claims) => claims.sub,
+    match db::get_shipping_options(&pool, &[*vendor_id]).await {
+        Ok(options) => Ok(HttpResponse::Ok().json(options)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch shipping options")),
+    }
+}
+
+/// GET /vendor/shipping-options - The authenticated vendor's active shipping options
+#[get("/vendor/shipping-options")]
+async fn get_own_shipping_options_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
         Err(response) => return Ok(response),
     };
 
-    match db::get_customer_shipping_orders(&pool, customer_id).await {
-        Ok(orders) => Ok(HttpResponse::Ok().json(orders)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch shipping orders")),
+    match db::get_shipping_options(&pool, &[vendor_id]).await {
+        Ok(options) => Ok(HttpResponse::Ok().json(options)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch shipping options")),
     }
 }
 
 /**
- * GET /shipping/vendor - Get vendor's shipping orders
+ * POST /vendor/shipping-options - Add a shipping option
  *
- * Retrieves all shipping orders for the authenticated vendor.
+ * Methods: `flat` (always `fee`), `free_over` (`fee`, waived once the vendor
+ * subtotal reaches `free_threshold`) and `per_km` (`fee` plus `per_km_rate`
+ * per km between vendor and customer).
  *
- * @param req - HTTP request for authentication
+ * @param req - HTTP request for vendor authentication
  * @param pool - Database connection pool
- * @returns JSON array of vendor's shipping orders
+ * @param option_req - JSON with name, method, fee, free_threshold, per_km_rate
+ * @returns JSON of the created option
  */
-#[get("/shipping/vendor")]
-async fn get_vendor_shipping_orders_route(
+#[post("/vendor/shipping-options")]
+async fn create_shipping_option_route(
     req: actix_web::HttpRequest,
-    pool: web::Data<PgPool>
+    pool: web::Data<PgPool>,
+    option_req: web::Json<ShippingOptionRequest>,
 ) -> ActixResult<HttpResponse> {
     let vendor_id = match check_vendor_auth(&req) {
         Ok(id) => id,
         Err(response) => return Ok(response),
     };
 
-    match db::get_vendor_shipping_orders(&pool, vendor_id).await {
-        Ok(orders) => Ok(HttpResponse::Ok().json(orders)),
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch shipping orders")),
+    let name = option_req.name.trim();
+    if name.is_empty() || name.len() > 100 {
+        return Ok(HttpResponse::BadRequest().json("Name must be between 1 and 100 characters"));
+    }
+    let non_negative = |v: Option<f64>| v.is_none_or(|v| v.is_finite() && v >= 0.0);
+    if !non_negative(Some(option_req.fee)) || !non_negative(option_req.free_threshold) || !non_negative(option_req.per_km_rate) {
+        return Ok(HttpResponse::BadRequest().json("Fees must be zero or more"));
+    }
+    match option_req.method {
+        ShippingMethod::FreeOver if option_req.free_threshold.is_none() => {
+            return Ok(HttpResponse::BadRequest().json("free_over options need a free_threshold"));
+        }
+        ShippingMethod::PerKm if option_req.per_km_rate.is_none() => {
+            return Ok(HttpResponse::BadRequest().json("per_km options need a per_km_rate"));
+        }
+        _ => {}
+    }
+
+    match db::create_shipping_option(&pool, vendor_id, &option_req).await {
+        Ok(option) => Ok(HttpResponse::Created().json(option)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create shipping option")),
     }
 }
 
-/**
- * PATCH /shipping/{order_id}/status - Update shipping order status
- *
- * Allows vendors to update the shipping status of their orders.
- *
- * @param req - HTTP request for authentication
- * @param pool - Database connection pool
- * @param order_id - Order ID from URL path
- * @param status_req - JSON request with shipping_status and optional tracking_number
- * @returns Success message
- */
-#[patch("/shipping/{order_id}/status")]
-async fn update_shipping_status_route(
+/// DELETE /vendor/shipping-options/{id} - Withdraw a shipping option (kept for past orders)
+#[delete("/vendor/shipping-options/{id}")]
+async fn delete_shipping_option_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    order_id: web::Path<i32>,
-    status_req: web::Json<UpdateShippingStatusRequest>
+    option_id: web::Path<i32>,
 ) -> ActixResult<HttpResponse> {
     let vendor_id = match check_vendor_auth(&req) {
         Ok(id) => id,
         Err(response) => return Ok(response),
     };
 
-    // Verify the order belongs to this vendor
-    let order_vendor_id: i32 = match sqlx::query_scalar("SELECT vendor_id FROM shipping_orders WHERE id = $1")
-        .bind(*order_id)
-        .fetch_one(pool.get_ref())
-        .await {
+    match db::deactivate_shipping_option(&pool, vendor_id, *option_id).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("Shipping option not found")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to remove shipping option")),
+    }
+}
+
+/// POST /vendor/pause - Go on holiday: hide your products from listings and stop
+/// new cart additions and checkouts. Existing orders are unaffected.
+#[post("/vendor/pause")]
+async fn pause_vendor_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    set_vendor_paused(&req, &pool, true).await
+}
+
+/// POST /vendor/resume - Come back from holiday and start selling again
+#[post("/vendor/resume")]
+async fn resume_vendor_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    set_vendor_paused(&req, &pool, false).await
+}
+
+async fn set_vendor_paused(req: &actix_web::HttpRequest, pool: &PgPool, paused: bool) -> ActixResult<HttpResponse> {
+    let vendor_id = match check_vendor_auth(req) {
         Ok(id) => id,
-        Err(_) => return Ok(HttpResponse::NotFound().json("Order not found")),
+        Err(response) => return Ok(response),
     };
 
-    if order_vendor_id != vendor_id {
-        return Ok(HttpResponse::Forbidden().json("Can only update your own orders"));
+    match db::set_vendor_paused(pool, vendor_id, paused).await {
+        Ok(()) => Ok(ok(json!({ "is_paused": paused }))),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update pause mode")),
     }
+}
 
-    match db::update_shipping_status(&pool, *order_id, &status_req.shipping_status, status_req.tracking_number.as_deref()).await {
-        Ok(_) => {
-            // If status is "delivered", request customer verification
-            if status_req.shipping_status.to_lowercase() == "delivered" {
-                let _ = db::request_delivery_verification(&pool, *order_id).await;
-                println!("📦 Order {} marked as delivered - verification requested from customer", order_id);
-            }
-            Ok(HttpResponse::Ok().json("Shipping status updated successfully"))
-        },
-        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to update shipping status")),
+/// GET /vendor/coupons - The authenticated vendor's coupons, withdrawn ones included
+#[get("/vendor/coupons")]
+async fn get_own_coupons_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_vendor_coupons(&pool, vendor_id).await {
+        Ok(coupons) => Ok(HttpResponse::Ok().json(coupons)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch coupons")),
     }
 }
 
 /**
- * GET /vendors/{vendor_id}/profile - Get vendor profile information
+ * POST /vendor/coupons - Add a coupon
  *
- * Retrieves vendor profile information including total purchases, revenue, and follower count.
- * Available to all authenticated users.
+ * The coupon always belongs to the authenticated vendor and only discounts
+ * their own products, so it can't be used on other vendors' items.
  *
- * @param req - HTTP request for authentication
+ * @param req - HTTP request for vendor authentication
  * @param pool - Database connection pool
- * @param vendor_id - Vendor ID from URL path
- * @returns JSON of vendor profile information
+ * @param coupon_req - JSON with code, percent_off, max_uses, expires_at
+ * @returns JSON of the created coupon
  */
-#[get("/vendors/{vendor_id}/profile")]
-async fn get_vendor_profile_route(
+#[post("/vendor/coupons")]
+async fn create_coupon_route(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
-    vendor_id: web::Path<i32>
+    coupon_req: web::Json<CouponRequest>,
 ) -> ActixResult<HttpResponse> {
-    // Require authentication
-    let _claims = match extract_auth(&req) {
-        Ok(c) => c,
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
         Err(response) => return Ok(response),
     };
 
-    match db::get_vendor_profile(&pool, *vendor_id).await {
-        Ok(profile) => Ok(HttpResponse::Ok().json(profile)),
-        Err(_) => Ok(HttpResponse::NotFound().json("Vendor not found")),
+    let code = coupons::normalize_code(&coupon_req.code);
+    if code.len() < 3 || code.len() > 32 || !code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Ok(HttpResponse::BadRequest().json("Code must be 3 to 32 letters, digits, dashes or underscores"));
+    }
+    if !coupon_req.percent_off.is_finite() || coupon_req.percent_off <= 0.0 || coupon_req.percent_off > 100.0 {
+        return Ok(HttpResponse::BadRequest().json("percent_off must be more than 0 and at most 100"));
+    }
+    if coupon_req.max_uses.is_some_and(|max| max < 1) {
+        return Ok(HttpResponse::BadRequest().json("max_uses must be at least 1"));
+    }
+    if coupon_req.expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now()) {
+        return Ok(HttpResponse::BadRequest().json("expires_at must be in the future"));
+    }
+
+    match db::create_coupon(&pool, vendor_id, &coupon_req).await {
+        Ok(coupon) => Ok(HttpResponse::Created().json(coupon)),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            Ok(HttpResponse::Conflict().json("That coupon code is already taken"))
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to create coupon")),
+    }
+}
+
+/// DELETE /vendor/coupons/{id} - Withdraw a coupon (kept for past orders)
+#[delete("/vendor/coupons/{id}")]
+async fn delete_coupon_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    coupon_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+
+    match db::deactivate_coupon(&pool, vendor_id, *coupon_id).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("Coupon not found")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to remove coupon")),
     }
 }
 
@@ -2048,11 +5996,62 @@ async fn get_vendor_profile_route(
  */
 #[post("/mpesa/callback")]
 async fn mpesa_callback(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    callback_data: web::Json<StkCallbackBody>
+) -> ActixResult<HttpResponse> {
+    handle_mpesa_callback(&req, pool, callback_data, None).await
+}
+
+/// POST /mpesa/callback/{token} - Same as above with the MPESA_CALLBACK_TOKEN shared secret in the path.
+#[post("/mpesa/callback/{token}")]
+async fn mpesa_callback_with_token(
+    req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
+    token: web::Path<String>,
     callback_data: web::Json<StkCallbackBody>
 ) -> ActixResult<HttpResponse> {
+    handle_mpesa_callback(&req, pool, callback_data, Some(&token)).await
+}
+
+async fn handle_mpesa_callback(
+    req: &actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    callback_data: web::Json<StkCallbackBody>,
+    token: Option<&str>,
+) -> ActixResult<HttpResponse> {
+    // Reject forged callbacks before touching any transaction state
+    let auth = CallbackAuth::from_env();
+    let forwarded_for = req.headers().get("X-Forwarded-For").and_then(|v| v.to_str().ok());
+    let source_ip = auth.source_ip(req.peer_addr().map(|addr| addr.ip()), forwarded_for);
+    if !auth.verify(source_ip, token) {
+        eprintln!("⚠️ Rejected M-Pesa callback from unverified source {:?}", source_ip);
+        return Ok(HttpResponse::Unauthorized().json("Unauthorized"));
+    }
+
     println!("M-Pesa callback received: {:?}", callback_data);
 
+    if let Err(error) = process_mpesa_callback(&pool, &callback_data).await {
+        let checkout_request_id = &callback_data.stk_callback.checkout_request_i_d;
+        eprintln!("❌ M-Pesa callback for {} failed: {}", checkout_request_id, error);
+        let payload = serde_json::to_string(&*callback_data).unwrap_or_default();
+        if let Err(e) = db::record_callback_failure(&pool, checkout_request_id, &payload, &error).await {
+            eprintln!("Failed to record callback failure for {}: {:?}", checkout_request_id, e);
+        }
+    }
+
+    // Safaricom needs ResultCode 0 whatever happened here, or it keeps resending;
+    // failures are kept in callback_failures for admins to retry instead
+    Ok(HttpResponse::Ok().json(json!({
+        "ResultCode": 0,
+        "ResultDesc": "Accepted"
+    })))
+}
+
+/// Apply an STK callback to its payment: record the outcome and, for a
+/// successful payment, create its orders. Shared by the callback routes and
+/// admin retries. Returns the new payment status, or what went wrong.
+async fn process_mpesa_callback(pool: &PgPool, callback_data: &StkCallbackBody) -> Result<String, String> {
     let callback = &callback_data.stk_callback;
     let checkout_request_id = &callback.checkout_request_i_d;
     
@@ -2061,14 +6060,47 @@ async fn mpesa_callback(
              callback.merchant_request_i_d, checkout_request_id);
 
     // Get payment transaction from database
-    let transaction = match db::get_payment_transaction_by_checkout_request_id(&pool, checkout_request_id).await {
+    let transaction = match db::get_payment_transaction_by_checkout_request_id(pool, checkout_request_id).await {
         Ok(t) => t,
-        Err(_) => {
-            eprintln!("Transaction not found for checkout_request_id: {}", checkout_request_id);
-            return Ok(HttpResponse::Ok().json(json!({"ResultCode": 0, "ResultDesc": "Accepted"})));
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(format!("Transaction not found for checkout_request_id: {}", checkout_request_id));
         }
+        Err(e) => return Err(format!("Failed to look up transaction: {:?}", e)),
     };
+    // Tie the callback back to the checkout request that started the payment
+    println!("🔗 Callback for {} belongs to checkout request {}",
+             checkout_request_id, transaction.request_id.as_deref().unwrap_or("unknown"));
+
+    // A payment already completed keeps that outcome: a repeated or retried
+    // success callback only finishes any orders still missing, and a late
+    // failure callback changes nothing
+    let already_completed = transaction.status == PaymentStatus::Completed;
+    if already_completed && callback.result_code != 0 {
+        println!("Ignoring failure callback for already completed payment {}", checkout_request_id);
+        return Ok(PaymentStatus::Completed.to_string());
+    }
+
+    // The customer can still pay a prompt that a resend replaced. If no other
+    // prompt of the attempt was paid it takes the order back; otherwise the
+    // second payment is refunded to their wallet
+    let mut transaction = transaction;
+    if callback.result_code == 0 && transaction.status == PaymentStatus::Cancelled {
+        match db::settle_superseded_payment(pool, &transaction).await {
+            Ok(Some(db::SupersededPayment::Reclaimed)) => {
+                println!("Superseded prompt {} was paid; it replaces the later prompt", checkout_request_id);
+                transaction.status = PaymentStatus::Initiated;
+            }
+            Ok(Some(db::SupersededPayment::Refunded(amount))) => {
+                println!("Superseded prompt {} was paid after another prompt; refunded KSh {:.2} to the wallet",
+                         checkout_request_id, amount);
+                return Ok(PaymentStatus::Cancelled.to_string());
+            }
+            Ok(None) => {}
+            Err(e) => return Err(format!("Failed to settle superseded payment: {:?}", e)),
+        }
+    }
 
+    let mut errors = Vec::new();
     let status = if callback.result_code == 0 {
         // Payment successful
         println!("Payment successful for checkout_request_id: {} - {}", checkout_request_id, callback.result_desc);
@@ -2080,64 +6112,22 @@ async fn mpesa_callback(
         };
 
         // Update payment transaction
-        if let Err(e) = db::update_payment_transaction(
-            &pool,
-            checkout_request_id,
-            &PaymentStatus::Completed.to_string(),
-            mpesa_receipt.as_deref(),
-            transaction_date.as_deref(),
-        ).await {
-            eprintln!("Failed to update payment transaction: {:?}", e);
-        }
-
-        // Get user's cart items
-        match db::get_cart_items(&pool, transaction.user_id).await {
-            Ok(all_cart_items) => {
-                // Filter cart items based on what was actually selected for this payment
-                let items_to_process = if let Some(cart_item_ids_str) = &transaction.cart_item_ids {
-                    // Parse the comma-separated cart item IDs
-                    let selected_ids: Vec<i32> = cart_item_ids_str
-                        .split(',')
-                        .filter_map(|id| id.trim().parse().ok())
-                        .collect();
-                    
-                    // Only process the selected cart items
-                    all_cart_items.into_iter()
-                        .filter(|item| selected_ids.contains(&item.id))
-                        .collect::<Vec<_>>()
-                } else {
-                    // If no specific items were stored, process all cart items (backward compatibility)
-                    all_cart_items
-                };
-
-                println!("📦 Processing {} cart items for shipping orders", items_to_process.len());
-
-                // Create shipping orders only for selected items
-                for item in &items_to_process {
-                    match db::create_shipping_order(
-                        &pool,
-                        transaction.user_id,
-                        item.product_id,
-                        item.quantity,
-                        "Default shipping address - please update in your orders"
-                    ).await {
-                        Ok(_) => println!("✅ Shipping order created for product {} (qty: {})", item.product_id, item.quantity),
-                        Err(e) => eprintln!("❌ Failed to create shipping order: {:?}", e),
-                    }
-                }
-
-                // Clear only the selected items from the cart
-                for item in &items_to_process {
-                    if let Err(e) = db::remove_from_cart_with_user(&pool, item.id, transaction.user_id).await {
-                        eprintln!("❌ Failed to remove cart item {}: {:?}", item.id, e);
-                    } else {
-                        println!("🗑️ Removed cart item {} from user {}'s cart", item.id, transaction.user_id);
-                    }
-                }
+        if !already_completed {
+            if let Err(e) = db::update_payment_transaction(
+                pool,
+                checkout_request_id,
+                &PaymentStatus::Completed.to_string(),
+                mpesa_receipt.as_deref(),
+                transaction_date.as_deref(),
+            ).await {
+                errors.push(format!("Failed to update payment transaction: {:?}", e));
             }
-            Err(e) => eprintln!("❌ Failed to get cart items: {:?}", e),
         }
 
+        let (orders_created, mut order_errors) = finalize_successful_payment(pool, &transaction).await;
+        println!("📦 Created {} shipping orders for {} ({} errors)", orders_created, checkout_request_id, order_errors.len());
+        errors.append(&mut order_errors);
+
         PaymentStatus::Completed.to_string()
     } else {
         // Payment failed or cancelled
@@ -2152,25 +6142,263 @@ async fn mpesa_callback(
 
         // Update payment transaction status
         if let Err(e) = db::update_payment_transaction(
-            &pool,
+            pool,
             checkout_request_id,
             &status,
             None,
             None,
         ).await {
-            eprintln!("Failed to update payment transaction: {:?}", e);
+            errors.push(format!("Failed to update payment transaction: {:?}", e));
         }
+        release_reservation(pool, checkout_request_id).await;
 
         status
     };
 
     println!("Payment status updated to: {} for transaction: {}", status, checkout_request_id);
+    if errors.is_empty() {
+        Ok(status)
+    } else {
+        Err(errors.join("; "))
+    }
+}
 
-    // Respond to M-Pesa with acknowledgment
-    Ok(HttpResponse::Ok().json(json!({
-        "ResultCode": 0,
-        "ResultDesc": "Accepted"
-    })))
+/// GET /api/admin/callback-failures - M-Pesa callbacks that couldn't be applied,
+/// oldest first, optionally `?status=open|resolved`.
+#[get("/api/admin/callback-failures")]
+async fn get_callback_failures_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    let resolved = match extract_query_param(req.query_string(), "status").as_deref() {
+        None => None,
+        Some("open") => Some(false),
+        Some("resolved") => Some(true),
+        Some(_) => return Ok(HttpResponse::BadRequest().json("status must be open or resolved")),
+    };
+    match db::get_callback_failures(&pool, resolved).await {
+        Ok(failures) => Ok(HttpResponse::Ok().json(failures)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch callback failures")),
+    }
+}
+
+/// POST /api/admin/callback-failures/{failure_id}/retry - Process a failed callback
+/// again from its stored payload. Resolves it on success; otherwise it stays open
+/// with the new error.
+#[post("/api/admin/callback-failures/{failure_id}/retry")]
+async fn retry_callback_failure_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    failure_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    let failure = match db::get_callback_failure(&pool, *failure_id).await {
+        Ok(Some(failure)) => failure,
+        Ok(None) => return Ok(HttpResponse::NotFound().json("Callback failure not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to retry callback")),
+    };
+    if failure.resolved_at.is_some() {
+        return Ok(HttpResponse::Conflict().json("Callback was already processed"));
+    }
+    let callback_data: StkCallbackBody = match serde_json::from_str(&failure.payload) {
+        Ok(callback_data) => callback_data,
+        Err(e) => return Ok(HttpResponse::UnprocessableEntity().json(format!("Stored payload is not a valid callback: {}", e))),
+    };
+
+    let outcome = process_mpesa_callback(&pool, &callback_data).await;
+    if let Err(e) = db::record_callback_retry(&pool, *failure_id, outcome.as_ref().err().map(String::as_str)).await {
+        eprintln!("Failed to record retry of callback failure {}: {:?}", failure_id, e);
+        return Ok(HttpResponse::InternalServerError().json("Failed to retry callback"));
+    }
+
+    Ok(HttpResponse::Ok().json(match outcome {
+        Ok(status) => json!({ "resolved": true, "payment_status": status }),
+        Err(error) => json!({ "resolved": false, "error": error }),
+    }))
+}
+
+/// GET /api/admin/emails - Emails in the retry queue, newest first. `?status=` picks
+/// `failed` (out of attempts; the default), `pending`, `sent` or `expired`.
+#[get("/api/admin/emails")]
+async fn get_queued_emails_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    let status = extract_query_param(req.query_string(), "status").unwrap_or_else(|| "failed".to_string());
+    if !["pending", "sent", "failed", "expired"].contains(&status.as_str()) {
+        return Ok(HttpResponse::BadRequest().json("status must be pending, sent, failed or expired"));
+    }
+    match db::get_queued_emails(&pool, &status).await {
+        Ok(emails) => Ok(HttpResponse::Ok().json(emails)),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch queued emails")),
+    }
+}
+
+/// POST /api/admin/emails/{email_id}/retry - Queue a failed email again with a
+/// fresh set of attempts; the retry task sends it on its next pass.
+#[post("/api/admin/emails/{email_id}/retry")]
+async fn retry_queued_email_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    email_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = check_admin_auth(&req) {
+        return Ok(response);
+    }
+
+    match db::requeue_failed_email(&pool, *email_id).await {
+        Ok(true) => Ok(ok_message("Email queued for retry")),
+        Ok(false) => Ok(HttpResponse::NotFound().json("No failed email with that id")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to queue email")),
+    }
+}
+
+/// Most STK prompts that can be resent for one payment attempt.
+const MAX_STK_RESENDS: i64 = 3;
+/// Shortest wait between prompts of one payment attempt.
+const STK_RESEND_COOLDOWN_SECONDS: f64 = 60.0;
+
+/// POST /payments/{checkout_request_id}/resend - Send a fresh STK prompt for the caller's
+/// pending payment, for the same phone and amount. Allowed while the payment's stock is
+/// still held, up to `MAX_STK_RESENDS` times and once a minute, and only once M-Pesa reports
+/// the old prompt ended unpaid. The new prompt replaces the old one (cancelled) and shares
+/// its `attempt_id`.
+#[post("/payments/{checkout_request_id}/resend")]
+async fn resend_payment_prompt(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    checkout_request_id: web::Path<String>
+) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+
+    let transaction = match db::get_payment_transaction_by_checkout_request_id(&pool, &checkout_request_id).await {
+        Ok(transaction) if transaction.user_id == user_id => transaction,
+        Ok(_) | Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("Payment not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to resend payment prompt")),
+    };
+    if transaction.status != PaymentStatus::Initiated {
+        return Ok(HttpResponse::Conflict().json(json!({
+            "error": format!("Payment is already {} and can't be resent", transaction.status),
+            "status": transaction.status
+        })));
+    }
+
+    let attempt_id = transaction.attempt_id.clone().unwrap_or_else(|| transaction.checkout_request_id.clone());
+    let (resends, seconds_since_last) = match db::get_payment_attempt_activity(&pool, &attempt_id).await {
+        Ok(activity) => activity,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to resend payment prompt")),
+    };
+    if seconds_since_last > reservations::hold_minutes(&pool).await as f64 * 60.0 {
+        return Ok(HttpResponse::Conflict().json(json!({
+            "error": "Payment request has expired. Please check out again.",
+            "status": transaction.status
+        })));
+    }
+    if resends >= MAX_STK_RESENDS {
+        return Ok(HttpResponse::TooManyRequests().json(json!({
+            "error": format!("A payment prompt can be resent at most {} times. Please check out again.", MAX_STK_RESENDS)
+        })));
+    }
+    if seconds_since_last < STK_RESEND_COOLDOWN_SECONDS {
+        let retry_after = (STK_RESEND_COOLDOWN_SECONDS - seconds_since_last).ceil() as i64;
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after.to_string()))
+            .json(json!({
+                "error": "Please wait before resending the payment prompt",
+                "retry_after_seconds": retry_after
+            })));
+    }
+
+    let Some(mpesa_client) = get_mpesa_client() else {
+        return Ok(HttpResponse::ServiceUnavailable().json(json!({
+            "error": "Payment failed",
+            "message": "M-Pesa payments are not configured on this server.",
+            "retry": false
+        })));
+    };
+
+    // The earlier prompt may still be open on the phone or already paid; only
+    // replace it once M-Pesa reports it ended without payment
+    match mpesa_client.stk_query(&transaction.checkout_request_id).await {
+        Ok(result) if result.result_code == "0" => {
+            return Ok(HttpResponse::Conflict().json(json!({
+                "error": "Your payment went through and is being confirmed",
+                "status": transaction.status
+            })));
+        }
+        Ok(_) => {}
+        Err(MpesaError::Duplicate) => {
+            return Ok(HttpResponse::Conflict().json(json!({
+                "error": "The previous payment prompt is still open on your phone. Complete or dismiss it first.",
+                "status": transaction.status
+            })));
+        }
+        Err(e) => {
+            eprintln!("❌ STK Push query failed: {:?}", e);
+            return Ok(HttpResponse::ServiceUnavailable().json(json!({
+                "error": "Payment failed",
+                "message": stk_error_message(&e),
+                "retry": true
+            })));
+        }
+    }
+
+    println!("📱 Resending STK Push for {} (attempt {})", transaction.checkout_request_id, attempt_id);
+    let stk_response = match mpesa_client.stk_push(
+        transaction.phone_number.clone(),
+        transaction.amount,
+        format!("FM_{}", user_id),
+        "Farmers Market Purchase".to_string(),
+    ).await {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("❌ STK Push resend failed: {:?}", e);
+            return Ok(HttpResponse::ServiceUnavailable().json(json!({
+                "error": "Payment failed",
+                "message": stk_error_message(&e),
+                "retry": true
+            })));
+        }
+    };
+
+    match db::record_stk_resend(
+        &pool,
+        &transaction,
+        &stk_response.checkout_request_i_d,
+        &stk_response.merchant_request_i_d,
+        request_id::current().as_deref(),
+    ).await {
+        Ok(true) => Ok(ok_with_message(
+            format!("Payment request sent again to {}. Check your phone and enter your M-Pesa PIN.", transaction.phone_number),
+            json!({
+                "transaction_id": stk_response.checkout_request_i_d,
+                "attempt_id": attempt_id,
+                "status": PaymentStatus::Initiated
+            }),
+        )),
+        // Completed or cancelled while the prompt was being sent
+        Ok(false) => Ok(HttpResponse::Conflict().json(json!({
+            "error": "Payment is no longer pending and can't be resent"
+        }))),
+        Err(e) => {
+            eprintln!("❌ Failed to record resent payment prompt: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to resend payment prompt"))
+        }
+    }
 }
 
 /**
@@ -2198,6 +6426,39 @@ async fn get_payment_history(
     }
 }
 
+/// POST /payments/{checkout_request_id}/cancel - Cancel the caller's pending STK push payment.
+/// Completed, failed or already-cancelled payments are left untouched (409).
+#[post("/payments/{checkout_request_id}/cancel")]
+async fn cancel_payment(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    checkout_request_id: web::Path<String>
+) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+
+    match db::cancel_payment_transaction(&pool, &checkout_request_id, user_id).await {
+        Ok(true) => {
+            release_reservation(&pool, &checkout_request_id).await;
+            return Ok(ok_with_message("Payment cancelled", json!({ "status": PaymentStatus::Cancelled })));
+        }
+        Ok(false) => {}
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to cancel payment")),
+    }
+
+    // Nothing updated: explain why without revealing other users' transactions
+    match db::get_payment_transaction_by_checkout_request_id(&pool, &checkout_request_id).await {
+        Ok(transaction) if transaction.user_id == user_id => Ok(HttpResponse::Conflict().json(json!({
+            "error": format!("Payment is already {} and can no longer be cancelled", transaction.status),
+            "status": transaction.status
+        }))),
+        Ok(_) | Err(sqlx::Error::RowNotFound) => Ok(HttpResponse::NotFound().json("Payment not found")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to cancel payment")),
+    }
+}
+
 /**
  * POST /payments/process-completed - Manually process completed payments
  *
@@ -2229,74 +6490,24 @@ async fn process_completed_payments(
 
             for transaction in transactions {
                 // Only process completed payments
-                if transaction.status != "completed" {
+                if transaction.status != PaymentStatus::Completed {
                     continue;
                 }
 
-                // Check if shipping orders already exist for this transaction
-                // (You may need to add a field to track this or check for existing orders)
-                
-                // Get user's cart items at time of payment
-                match db::get_cart_items(&pool, user_id).await {
-                    Ok(all_cart_items) => {
-                        let items_to_process = if let Some(cart_item_ids_str) = &transaction.cart_item_ids {
-                            let selected_ids: Vec<i32> = cart_item_ids_str
-                                .split(',')
-                                .filter_map(|id| id.trim().parse().ok())
-                                .collect();
-                            
-                            all_cart_items.into_iter()
-                                .filter(|item| selected_ids.contains(&item.id))
-                                .collect::<Vec<_>>()
-                        } else {
-                            all_cart_items
-                        };
-
-                        if items_to_process.is_empty() {
-                            println!("⚠️ No cart items found for transaction {}", transaction.id);
-                            continue;
-                        }
-
-                        processed += 1;
-                        
-                        // Create shipping orders
-                        for item in &items_to_process {
-                            match db::create_shipping_order(
-                                &pool,
-                                user_id,
-                                item.product_id,
-                                item.quantity,
-                                "Default shipping address - please update in your orders"
-                            ).await {
-                                Ok(_) => {
-                                    println!("✅ Shipping order created for product {} (qty: {})", item.product_id, item.quantity);
-                                    orders_created += 1;
-                                },
-                                Err(e) => {
-                                    let error_msg = format!("Failed to create shipping order for product {}: {:?}", item.product_id, e);
-                                    eprintln!("❌ {}", error_msg);
-                                    errors.push(error_msg);
-                                }
-                            }
-                        }
-
-                        // Clear items from cart
-                        for item in &items_to_process {
-                            if let Err(e) = db::remove_from_cart_with_user(&pool, item.id, user_id).await {
-                                eprintln!("❌ Failed to remove cart item {}: {:?}", item.id, e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        let error_msg = format!("Failed to get cart items: {:?}", e);
-                        eprintln!("❌ {}", error_msg);
-                        errors.push(error_msg);
-                    }
+                // Cart items are removed once ordered, so an already-finalized
+                // payment has nothing left to process.
+                let (created, mut item_errors) = finalize_successful_payment(&pool, &transaction).await;
+                if created == 0 && item_errors.is_empty() {
+                    println!("⚠️ No cart items found for transaction {}", transaction.id);
+                    continue;
                 }
+
+                processed += 1;
+                orders_created += created;
+                errors.append(&mut item_errors);
             }
 
-            Ok(HttpResponse::Ok().json(json!({
-                "success": true,
+            Ok(ok(json!({
                 "payments_processed": processed,
                 "orders_created": orders_created,
                 "errors": errors
@@ -2340,6 +6551,52 @@ async fn get_vendor_sales_report_route(
     }
 }
 
+/// GET /vendor/analytics/products - View counts and units sold for each of the vendor's products
+#[get("/vendor/analytics/products")]
+async fn get_vendor_product_analytics_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_vendor_product_analytics(&pool, vendor_id).await {
+        Ok(analytics) => Ok(HttpResponse::Ok().json(analytics)),
+        Err(e) => {
+            eprintln!("Failed to fetch product analytics: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch product analytics"))
+        }
+    }
+}
+
+/// Default and longest sales window / stale threshold for the inventory report, in days.
+const INVENTORY_REPORT_DEFAULT_DAYS: i32 = 30;
+const INVENTORY_REPORT_MAX_DAYS: i32 = 365;
+
+/// GET /reports/vendor/inventory?days=&stale_days= - Stock, units sold over the last
+/// `days` days, estimated days of stock left and a stale flag for products without
+/// sales in `stale_days` days, for each of the vendor's products. Both default to 30.
+#[get("/reports/vendor/inventory")]
+async fn get_vendor_inventory_report_route(req: actix_web::HttpRequest, pool: web::Data<PgPool>) -> ActixResult<HttpResponse> {
+    let vendor_id = match check_vendor_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+    let days_param = |name| {
+        extract_query_param(req.query_string(), name)
+            .and_then(|v| v.parse::<i32>().ok())
+            .unwrap_or(INVENTORY_REPORT_DEFAULT_DAYS)
+            .clamp(1, INVENTORY_REPORT_MAX_DAYS)
+    };
+
+    match db::get_vendor_inventory_report(&pool, vendor_id, days_param("days"), days_param("stale_days")).await {
+        Ok(report) => Ok(HttpResponse::Ok().json(report)),
+        Err(e) => {
+            eprintln!("Failed to fetch inventory report: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch inventory report"))
+        }
+    }
+}
+
 /**
  * GET /reports/customer/purchases - Get customer purchase report
  *
@@ -2374,6 +6631,238 @@ async fn get_customer_purchase_report_route(
     }
 }
 
+/**
+ * GET /customer/dashboard - Customer dashboard summary
+ *
+ * Returns active order count, total spent, vendors followed, unread messages,
+ * wishlist size, and the most recent orders for the authenticated customer.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @returns JSON with dashboard figures
+ */
+#[get("/customer/dashboard")]
+async fn get_customer_dashboard_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    let customer_id = match check_customer_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_customer_dashboard(&pool, customer_id).await {
+        Ok(dashboard) => Ok(HttpResponse::Ok().json(dashboard)),
+        Err(e) => {
+            eprintln!("Failed to fetch customer dashboard: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch dashboard"))
+        }
+    }
+}
+
+/**
+ * GET /wishlist - List the customer's saved products
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @returns JSON array of products
+ */
+#[get("/wishlist")]
+async fn get_wishlist_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    let customer_id = match check_customer_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_wishlist(&pool, customer_id).await {
+        Ok(products) => Ok(HttpResponse::Ok().json(products)),
+        Err(e) => {
+            eprintln!("Failed to fetch wishlist: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to fetch wishlist"))
+        }
+    }
+}
+
+/**
+ * POST /wishlist - Save a product to the customer's wishlist
+ *
+ * Adding a product that is already saved is a no-op.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param wishlist_req - JSON request with product_id
+ * @returns Success message
+ */
+#[post("/wishlist")]
+async fn add_to_wishlist_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    wishlist_req: web::Json<WishlistRequest>
+) -> ActixResult<HttpResponse> {
+    let customer_id = match check_customer_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+
+    match db::add_to_wishlist(&pool, customer_id, wishlist_req.product_id).await {
+        Ok(_) => Ok(HttpResponse::Created().json("Product added to wishlist")),
+        Err(sqlx::Error::Database(db_err)) if db_err.is_foreign_key_violation() => {
+            Ok(HttpResponse::NotFound().json("Product not found"))
+        }
+        Err(e) => {
+            eprintln!("Failed to add to wishlist: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json("Failed to add to wishlist"))
+        }
+    }
+}
+
+/**
+ * DELETE /wishlist/{product_id} - Remove a product from the wishlist
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param product_id - Product ID from URL path
+ * @returns Success message
+ */
+#[delete("/wishlist/{product_id}")]
+async fn remove_from_wishlist_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    product_id: web::Path<i32>
+) -> ActixResult<HttpResponse> {
+    let customer_id = match check_customer_auth(&req) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+
+    match db::remove_from_wishlist(&pool, customer_id, *product_id).await {
+        Ok(_) => Ok(ok_message("Product removed from wishlist")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to remove from wishlist")),
+    }
+}
+
+/**
+ * GET /orders/{id} - Get an order with all its line items
+ *
+ * Each item is one shipping order with its own status and tracking, so a
+ * multi-vendor order shows how far along every part of it is. Available to
+ * the order's customer and admins.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param order_id - Order ID from URL path
+ * @returns JSON of the order and its items
+ */
+#[get("/orders/{order_id}")]
+async fn get_order_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    order_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+
+    match db::get_order(&pool, *order_id).await {
+        Ok(Some(order)) if order.customer_id == claims.sub || claims.role == "Admin" => Ok(HttpResponse::Ok().json(order)),
+        Ok(Some(_)) => Ok(HttpResponse::Forbidden().json("You can only view your own orders")),
+        Ok(None) => Ok(HttpResponse::NotFound().json("Order not found")),
+        Err(e) => {
+            eprintln!("Failed to load order {}: {:?}", order_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to load order"))
+        }
+    }
+}
+
+/**
+ * GET /orders/{id}/invoice.pdf - Download an order invoice
+ *
+ * Renders the order's line items, totals, buyer/seller details and M-Pesa
+ * receipt as a PDF. Available to the order's customer and vendor, and admins.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param order_id - Order ID from URL path
+ * @returns application/pdf attachment
+ */
+#[get("/orders/{order_id}/invoice.pdf")]
+async fn get_order_invoice_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    order_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+
+    let invoice = match db::get_order_invoice(&pool, *order_id).await {
+        Ok(invoice) => invoice,
+        Err(sqlx::Error::RowNotFound) => return Ok(HttpResponse::NotFound().json("Order not found")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to load order")),
+    };
+
+    if claims.sub != invoice.customer_id && claims.sub != invoice.vendor_id && claims.role != "Admin" {
+        return Ok(HttpResponse::Forbidden().json("You can only view invoices for your own orders"));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header((
+            actix_web::http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"invoice-{}.pdf\"", invoice.order_id),
+        ))
+        .body(invoice::render_pdf(&invoice)))
+}
+
+/**
+ * GET /orders/{id}/receipt - Structured receipt for an order
+ *
+ * Line items with unit and line prices, discounts and coupon codes, shipping
+ * fees, the order totals and how it was paid (method, M-Pesa receipt number
+ * and time). Available to the order's customer and admins; a vendor with
+ * items in the order sees a receipt of just their own items.
+ *
+ * @param req - HTTP request for authentication
+ * @param pool - Database connection pool
+ * @param order_id - Order ID from URL path
+ * @returns JSON receipt
+ */
+#[get("/orders/{order_id}/receipt")]
+async fn get_order_receipt_route(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    order_id: web::Path<i32>,
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(c) => c,
+        Err(response) => return Ok(response),
+    };
+
+    let mut receipt = match db::get_order_receipt(&pool, *order_id).await {
+        Ok(Some(receipt)) => receipt,
+        Ok(None) => return Ok(HttpResponse::NotFound().json("Order not found")),
+        Err(e) => {
+            eprintln!("Failed to load receipt for order {}: {:?}", order_id, e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to load receipt"));
+        }
+    };
+
+    if receipt.customer_id != claims.sub && claims.role != "Admin" {
+        if !receipt.items.iter().any(|item| item.vendor_id == claims.sub) {
+            return Ok(HttpResponse::Forbidden().json("You can only view receipts for your own orders"));
+        }
+        receipt.items.retain(|item| item.vendor_id == claims.sub);
+        receipt.update_totals();
+    }
+
+    Ok(HttpResponse::Ok().json(receipt))
+}
+
 /**
  * POST /shipping/{order_id}/verify - Customer verifies delivery
  *
@@ -2401,28 +6890,25 @@ async fn verify_delivery_route(
         match db::verify_delivery_and_release_payment(&pool, *order_id, customer_id).await {
             Ok(_) => {
                 println!("✅ Order {} verified by customer {} - payment released to vendor", order_id, customer_id);
-                Ok(HttpResponse::Ok().json(json!({
-                    "message": "Delivery verified successfully. Payment has been released to the vendor's wallet."
-                })))
+                Ok(ok_message("Delivery verified successfully. Payment has been released to the vendor's wallet."))
             },
             Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to verify delivery")),
         }
     } else {
         // Customer disputes delivery - could trigger admin review
-        Ok(HttpResponse::Ok().json(json!({
-            "message": "Delivery dispute recorded. Please contact support for assistance."
-        })))
+        Ok(ok_message("Delivery dispute recorded. Please contact support for assistance."))
     }
 }
 
 /**
  * GET /wallet/balance - Get user's wallet balance
  *
- * Returns the current wallet balance for vendors.
+ * Returns the withdrawable balance and, for vendors paid monthly, the
+ * earnings waiting for the next payout sweep.
  *
  * @param req - HTTP request for authentication
  * @param pool - Database connection pool
- * @returns JSON with wallet balance
+ * @returns JSON with balance (available), pending_balance and currency
  */
 #[get("/wallet/balance")]
 async fn get_wallet_balance_route(
@@ -2434,9 +6920,10 @@ async fn get_wallet_balance_route(
         Err(response) => return Ok(response),
     };
 
-    match db::get_wallet_balance(&pool, claims.sub).await {
-        Ok(balance) => Ok(HttpResponse::Ok().json(json!({
+    match db::get_wallet_balances(&pool, claims.sub).await {
+        Ok((balance, pending)) => Ok(HttpResponse::Ok().json(json!({
             "balance": balance,
+            "pending_balance": pending,
             "currency": "KSh"
         }))),
         Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to fetch wallet balance")),
@@ -2464,10 +6951,17 @@ async fn withdraw_wallet_route(
         Err(response) => return Ok(response),
     };
 
-    // Validate minimum withdrawal amount
-    if withdraw_req.amount < 10.0 {
+    // Validate withdrawal amount against the configured per-transaction bounds
+    let min_amount = settings::get_f64(&pool, settings::MIN_WITHDRAWAL_AMOUNT).await;
+    let max_amount = settings::get_f64(&pool, settings::MAX_WITHDRAWAL_AMOUNT).await;
+    if withdraw_req.amount < min_amount {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Minimum withdrawal amount is KSh {}", min_amount)
+        })));
+    }
+    if withdraw_req.amount > max_amount {
         return Ok(HttpResponse::BadRequest().json(json!({
-            "error": "Minimum withdrawal amount is KSh 10"
+            "error": format!("Maximum withdrawal amount is KSh {}", max_amount)
         })));
     }
 
@@ -2478,8 +6972,27 @@ async fn withdraw_wallet_route(
         })));
     }
 
+    // Payouts only go to the vendor's own, OTP-verified M-Pesa number
+    match db::get_mpesa_verification(&pool, user_id).await {
+        Ok((Some(number), true)) if format_kenyan_phone(&number) == format_kenyan_phone(&withdraw_req.mpesa_number) => {}
+        Ok((_, true)) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": "Withdrawals can only be sent to your verified M-Pesa number"
+            })));
+        }
+        Ok((_, false)) => {
+            return Ok(HttpResponse::Forbidden().json(json!({
+                "error": "Verify your M-Pesa number before withdrawing",
+                "reason": "mpesa_unverified"
+            })));
+        }
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to check M-Pesa verification")),
+    }
+
     // Process withdrawal from wallet
-    match db::process_wallet_withdrawal(&pool, user_id, withdraw_req.amount).await {
+    let daily_limit = settings::get_f64(&pool, settings::DAILY_WITHDRAWAL_LIMIT).await;
+    let transaction_id = format!("WD_{}_{}", user_id, chrono::Utc::now().timestamp());
+    match db::process_wallet_withdrawal(&pool, user_id, withdraw_req.amount, daily_limit, &transaction_id).await {
         Ok(new_balance) => {
             // TODO: Integrate with M-Pesa B2C API to send money to vendor's phone
             println!("💰 Withdrawal processed: User {} withdrew KSh {:.2} to {}", 
@@ -2490,15 +7003,90 @@ async fn withdraw_wallet_route(
                 success: true,
                 message: format!("Withdrawal of KSh {:.2} initiated to {}. Funds will be sent shortly.", 
                                 withdraw_req.amount, withdraw_req.mpesa_number),
-                transaction_id: Some(format!("WD_{}_{}", user_id, chrono::Utc::now().timestamp())),
+                transaction_id: Some(transaction_id),
                 new_balance,
             };
 
             Ok(HttpResponse::Ok().json(response))
         },
-        Err(_) => Ok(HttpResponse::BadRequest().json(json!({
-            "error": "Insufficient wallet balance or withdrawal failed"
+        Err(db::WithdrawalError::InsufficientFunds) => Ok(HttpResponse::BadRequest().json(json!({
+            "error": "Insufficient wallet balance"
         }))),
+        Err(db::WithdrawalError::DailyLimitExceeded { remaining }) => Ok(HttpResponse::TooManyRequests().json(json!({
+            "error": format!("Daily withdrawal limit of KSh {} reached", daily_limit),
+            "reason": "daily_limit_exceeded",
+            "remaining_allowance": remaining
+        }))),
+        Err(db::WithdrawalError::Database(e)) => {
+            eprintln!("❌ Withdrawal failed for user {}: {:?}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "Withdrawal failed"
+            })))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfirmMpesaNumberRequest {
+    code: String,
+}
+
+/// POST /profile/mpesa/verify - Send a one-time code confirming the user's M-Pesa number.
+/// The code is emailed when SMTP is configured and printed to the console otherwise.
+#[post("/profile/mpesa/verify")]
+async fn request_mpesa_verification(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>
+) -> ActixResult<HttpResponse> {
+    let claims = match extract_auth(&req) {
+        Ok(claims) => claims,
+        Err(response) => return Ok(response),
+    };
+
+    let mpesa_number = match db::get_mpesa_verification(&pool, claims.sub).await {
+        Ok((Some(number), false)) => number,
+        Ok((Some(_), true)) => return Ok(ok_with_message("M-Pesa number already verified", json!({ "verified": true }))),
+        Ok((None, _)) => return Ok(HttpResponse::BadRequest().json("Add an M-Pesa number to your profile first")),
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to load M-Pesa number")),
+    };
+
+    let verification_code = format!("{:06}", rand::random::<u32>() % 1000000);
+    let expires_at = chrono::Utc::now() + chrono::Duration::minutes(10);
+
+    if db::store_phone_verification_code(&pool, claims.sub, &mpesa_number, &verification_code, expires_at).await.is_err() {
+        return Ok(HttpResponse::InternalServerError().json("Failed to create verification code"));
+    }
+
+    let user = match db::get_user_by_id(&pool, claims.sub).await {
+        Ok(user) => user,
+        Err(_) => return Ok(HttpResponse::InternalServerError().json("Failed to load user")),
+    };
+    if let Err(e) = email::send_phone_verification_email(&pool, &user.email, &user.username, &mpesa_number, &verification_code, expires_at).await {
+        // Development fallback, same as password reset codes
+        eprintln!("Failed to email phone verification code: {}", e);
+        println!("📱 DEVELOPMENT MODE - M-Pesa verification code for {} ({}): {}", user.username, mpesa_number, verification_code);
+    }
+
+    Ok(ok_with_message("Verification code sent", json!({ "expires_at": expires_at.to_rfc3339() })))
+}
+
+/// POST /profile/mpesa/verify/confirm - Confirm the M-Pesa number with the emailed code.
+/// The code is invalidated after `db::PHONE_CODE_MAX_ATTEMPTS` wrong guesses.
+#[post("/profile/mpesa/verify/confirm")]
+async fn confirm_mpesa_verification(
+    req: actix_web::HttpRequest,
+    pool: web::Data<PgPool>,
+    request: web::Json<ConfirmMpesaNumberRequest>
+) -> ActixResult<HttpResponse> {
+    let user_id = match extract_auth(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return Ok(response),
+    };
+
+    match db::confirm_phone_verification_code(&pool, user_id, request.code.trim()).await {
+        Ok(true) => Ok(ok_with_message("M-Pesa number verified", json!({ "verified": true }))),
+        Ok(false) => Ok(HttpResponse::BadRequest().json("Invalid or expired verification code")),
+        Err(_) => Ok(HttpResponse::InternalServerError().json("Failed to verify code")),
     }
 }
 
@@ -2534,7 +7122,7 @@ async fn password_reset_request(
 
     // Check if user exists with this username
     match db::find_user_by_username(&pool, username).await {
-        Ok(Some(_user)) => {
+        Ok(Some(user)) => {
             // User exists, proceed with password reset
             let verification_code = format!("{:06}", rand::random::<u32>() % 1000000); // Generate 6-digit code
             let expires_at = chrono::Utc::now() + chrono::Duration::minutes(10); // 10 minutes expiry
@@ -2542,13 +7130,17 @@ async fn password_reset_request(
             // Store the verification code
             match db::store_password_reset_code(&pool, username, &verification_code, expires_at).await {
                 Ok(_) => {
-                    // Development: Print verification code to console instead of sending SMS
-                    println!("
+                    let language = email_templates::user_language(&pool, user.id).await;
+                    if let Err(e) = email::send_password_reset_email(&pool, &user.email, &user.username, &verification_code, expires_at, &language).await {
+                        // Development fallback: print the code when email isn't configured
+                        eprintln!("Failed to email password reset code: {}", e);
+                        println!("
 🔐 DEVELOPMENT MODE - PASSWORD RESET CODE");
-                    println!("👤 Username: {}", username);
-                    println!("🔢 Verification Code: {}", verification_code);
-                    println!("⏰ Expires At: {}", expires_at.format("%Y-%m-%d %H:%M:%S UTC"));
-                    println!("──────────────────────────────────────\n");
+                        println!("👤 Username: {}", username);
+                        println!("🔢 Verification Code: {}", verification_code);
+                        println!("⏰ Expires At: {}", expires_at.format("%Y-%m-%d %H:%M:%S UTC"));
+                        println!("──────────────────────────────────────\n");
+                    }
                     
                     let response = PasswordResetResponse {
                         message: format!("Verification code generated for '{}'. Check the backend console for the code (Development Mode).", username),
@@ -2579,6 +7171,29 @@ async fn password_reset_request(
     }
 }
 
+#[derive(Deserialize)]
+struct PasswordStrengthRequest {
+    password: String,
+}
+
+/// POST /password/strength - Live feedback for password fields: a 0-4 `score`,
+/// the policy rules not yet met and their messages, and whether it's `valid`.
+#[post("/password/strength")]
+async fn password_strength(
+    pool: web::Data<PgPool>,
+    request: web::Json<PasswordStrengthRequest>,
+) -> ActixResult<HttpResponse> {
+    let policy = PasswordPolicy::from_settings(&pool).await;
+    let unmet = policy.unmet_rules(&request.password);
+    let messages: Vec<String> = unmet.iter().map(|rule| policy.message(*rule)).collect();
+    Ok(HttpResponse::Ok().json(json!({
+        "score": password::strength_score(&request.password),
+        "valid": unmet.is_empty(),
+        "unmet_rules": unmet,
+        "messages": messages
+    })))
+}
+
 /// POST /auth/password-reset/verify - Verify code and reset password
 #[post("/auth/password-reset/verify")]
 async fn password_reset_verify(
@@ -2590,20 +7205,8 @@ async fn password_reset_verify(
     let new_password = &request.new_password;
 
     // Validate new password requirements (same as signup)
-    if new_password.len() < 8 {
-        return Ok(HttpResponse::BadRequest().json("Password must be at least 8 characters"));
-    }
-    if !new_password.chars().any(|c| c.is_uppercase()) {
-        return Ok(HttpResponse::BadRequest().json("Password must contain at least one uppercase letter"));
-    }
-    if !new_password.chars().any(|c| c.is_lowercase()) {
-        return Ok(HttpResponse::BadRequest().json("Password must contain at least one lowercase letter"));
-    }
-    if !new_password.chars().any(|c| c.is_numeric()) {
-        return Ok(HttpResponse::BadRequest().json("Password must contain at least one number"));
-    }
-    if !new_password.chars().any(|c| "!@#$%^&*(),.?\":{}|<>".contains(c)) {
-        return Ok(HttpResponse::BadRequest().json("Password must contain at least one special character"));
+    if let Err(reason) = password::validate_password(&PasswordPolicy::from_settings(&pool).await, new_password) {
+        return Ok(HttpResponse::BadRequest().json(reason));
     }
 
     // Verify the code
@@ -2624,10 +7227,10 @@ async fn password_reset_verify(
                     // Clean up expired codes
                     let _ = db::cleanup_expired_reset_codes(&pool).await;
 
-                    Ok(HttpResponse::Ok().json(json!({
-                        "message": "Password reset successful. You can now log in with your new password.",
-                        "username": user.username
-                    })))
+                    Ok(ok_with_message(
+                        "Password reset successful. You can now log in with your new password.",
+                        json!({ "username": user.username }),
+                    ))
                 },
                 Err(e) => {
                     println!("🔒 Failed to reset password: {:?}", e);
@@ -2645,6 +7248,27 @@ async fn password_reset_verify(
     }
 }
 
+/**
+ * Default service for requests no route handles
+ *
+ * Answers in JSON like every other endpoint: 405 when the path exists but not
+ * for this method, 404 otherwise. Register it with `App::default_service`.
+ *
+ * @param req - The unmatched request
+ * @returns JSON `{error, path}` (plus `method` on 405)
+ */
+pub async fn not_found(req: actix_web::HttpRequest) -> HttpResponse {
+    let path = req.path().to_string();
+    if req.resource_map().has_resource(&path) {
+        return HttpResponse::MethodNotAllowed().json(json!({
+            "error": "Method not allowed",
+            "path": path,
+            "method": req.method().as_str()
+        }));
+    }
+    HttpResponse::NotFound().json(json!({ "error": "Not found", "path": path }))
+}
+
 /**
  * Initialize route configuration
  *
@@ -2658,27 +7282,60 @@ pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(create_product);     // POST /products (vendors only)
     cfg.service(update_product);     // PATCH /products/{product_id} (vendors only)
     cfg.service(delete_product);     // DELETE /products/{product_id} (vendors only)
+    cfg.service(record_product_view); // POST /products/{product_id}/view (public)
+    cfg.service(get_recently_viewed); // GET /products/recently-viewed (authenticated)
+    cfg.service(suggest_products);   // GET /products/suggest (public)
+    cfg.service(compare_products);   // POST /products/compare (public)
+    cfg.service(get_similar_products); // GET /products/{product_id}/similar (public)
+    cfg.service(get_product_images);   // GET /products/{product_id}/images (public)
+    cfg.service(add_product_image);    // POST /products/{product_id}/images (owning vendor)
+    cfg.service(reorder_product_images); // PUT /products/{product_id}/images/order (owning vendor)
+    cfg.service(delete_product_image); // DELETE /products/{product_id}/images/{image_id} (owning vendor)
+    cfg.service(get_popular_tags);   // GET /tags (public)
+    cfg.service(get_featured_products); // GET /products/featured (public)
+    cfg.service(get_trending_products); // GET /products/trending (public)
+    cfg.service(get_new_arrivals); // GET /products/new (public)
+    cfg.service(get_product);        // GET /products/{product_id} (public; after the fixed /products/* paths)
+    cfg.service(set_product_featured);  // PATCH /products/{product_id}/featured (admins, owning vendor)
+    cfg.service(health);             // GET /health (public)
     cfg.service(login);              // POST /login
+    cfg.service(refresh_token_route); // POST /auth/refresh
+    cfg.service(get_sessions_route); // GET /account/sessions (authenticated)
+    cfg.service(revoke_session_route); // DELETE /account/sessions/{session_id} (authenticated)
     cfg.service(signup);             // POST /signup
     cfg.service(password_reset_request); // POST /auth/password-reset
     cfg.service(password_reset_verify);  // POST /auth/password-reset/verify
+    cfg.service(password_strength);      // POST /password/strength
     cfg.service(update_profile_image); // PATCH /profile/image
     cfg.service(update_profile);     // PATCH /profile
     cfg.service(update_user_profile_comprehensive); // PUT /user/profile (comprehensive update)
     cfg.service(update_location);    // POST /location/update
     cfg.service(update_admin_credentials); // PATCH /admin/credentials
+    cfg.service(export_account_data); // GET /account/export
+    cfg.service(delete_own_account); // DELETE /account
+    cfg.service(get_vendor_leaderboard); // GET /vendors/leaderboard (public)
     cfg.service(get_vendor_profile_route); // GET /vendors/{vendor_id}/profile
+    cfg.service(get_vendor_products);  // GET /vendors/{vendor_id}/products (public)
 
     // Cart routes - currently without authentication for testing
     cfg.service(get_cart)
+        .service(get_cart_summary)
         .service(add_to_cart_route)
+        .service(add_to_cart_batch_route)
         .service(update_cart_item)
         .service(remove_from_cart_route)
         .service(checkout);
 
     // M-Pesa payment routes
     cfg.service(mpesa_callback)
+        .service(mpesa_callback_with_token)
+        .service(get_callback_failures_route)
+        .service(retry_callback_failure_route)
+        .service(get_queued_emails_route)
+        .service(retry_queued_email_route)
         .service(get_payment_history)
+        .service(cancel_payment)
+        .service(resend_payment_prompt)
         .service(process_completed_payments);
 
     // Message routes
@@ -2686,8 +7343,15 @@ pub fn init(cfg: &mut web::ServiceConfig) {
         .service(get_messages_between_users_route)
         .service(get_user_conversations_route)
         .service(mark_messages_as_read_route)
+        .service(messages_socket)
         .service(edit_message_route)
-        .service(delete_message_route);
+        .service(delete_message_route)
+        .service(archive_conversation_route);
+
+    // Block routes
+    cfg.service(block_user_route)
+        .service(unblock_user_route)
+        .service(get_blocks_route);
 
     // Follow routes
     cfg.service(follow_vendor_route)
@@ -2696,52 +7360,118 @@ pub fn init(cfg: &mut web::ServiceConfig) {
         .service(get_user_follows_route)
         .service(get_vendor_followers_route);
 
+    // Announcement and notification routes
+    cfg.service(create_announcement_route)
+        .service(get_announcements_route)
+        .service(get_notifications_route)
+        .service(mark_notifications_read_route);
+
     // Review routes
     cfg.service(create_review_route)
         .service(get_product_reviews_route)
-        .service(get_customer_reviews_route);
+        .service(get_customer_reviews_route)
+        .service(reply_to_review_route)
+        .service(vote_on_review_route);
 
     // Shipping routes
     cfg.service(create_shipping_order_route)
         .service(get_customer_shipping_orders_route)
         .service(get_vendor_shipping_orders_route)
+        .service(get_pending_acceptance_orders_route)
+        .service(bulk_update_shipping_status_route)
         .service(update_shipping_status_route)
-        .service(verify_delivery_route);
+        .service(cancel_order_route)
+        .service(accept_order_route)
+        .service(decline_order_route)
+        .service(verify_delivery_route)
+        .service(get_order_route)
+        .service(get_order_invoice_route)
+        .service(get_order_receipt_route);
+
+    // Shipping option routes
+    cfg.service(get_vendor_shipping_options_route)
+        .service(get_own_shipping_options_route)
+        .service(create_shipping_option_route)
+        .service(delete_shipping_option_route);
+
+    // Vendor pause (holiday) mode
+    cfg.service(pause_vendor_route)
+        .service(resume_vendor_route);
+
+    // Vendor coupon routes
+    cfg.service(get_own_coupons_route)
+        .service(create_coupon_route)
+        .service(delete_coupon_route);
 
     // Wallet routes
     cfg.service(get_wallet_balance_route)
-        .service(withdraw_wallet_route);
+        .service(withdraw_wallet_route)
+        .service(request_mpesa_verification)
+        .service(confirm_mpesa_verification);
 
     // Analytics/Reports routes
     cfg.service(get_vendor_sales_report_route)
-        .service(get_customer_purchase_report_route);
+        .service(get_vendor_product_analytics_route)
+        .service(get_vendor_inventory_report_route)
+        .service(get_customer_purchase_report_route)
+        .service(get_customer_dashboard_route);
 
-    // Analytics/Reports routes
-    cfg.service(get_vendor_sales_report_route)
-        .service(get_customer_purchase_report_route);
+    // Wishlist routes
+    cfg.service(get_wishlist_route)
+        .service(add_to_wishlist_route)
+        .service(remove_from_wishlist_route);
 
     // Vendor report count route
     cfg.service(get_vendor_report_count);
 
     // Public messaging route
-    cfg.service(get_all_users_for_messaging);
+    cfg.service(get_all_users_for_messaging)
+        .service(get_user_profile_route);
 
     // Admin routes - authentication checked in route handlers
     cfg.service(get_all_users)
         .service(get_pending_vendors)
+        // Before update_user_role so "verify-bulk" isn't taken for a user id
+        .service(bulk_update_user_verification)
         .service(update_user_role)
         .service(update_user_verification)
         .service(upload_verification_document)
+        .service(get_verification_document)
+        .service(set_verification_document_retention)
+        .service(get_deleted_users)
         .service(delete_user)
+        .service(reactivate_user_route)
         .service(ban_user_route)
+        .service(impersonate_user_route)
+        .service(revoke_impersonation_route)
+        .service(get_user_sessions_route)
+        .service(revoke_user_sessions_route)
+        .service(get_audit_log_route)
+        .service(search_orders_route)
+        .service(adjust_wallet_route)
+        .service(admin_flagged_products_route)
+        .service(admin_approve_product_route)
+        .service(admin_remove_product_route)
+        .service(admin_update_product_route)
+        .service(admin_remove_review_route)
         .service(reset_user_password_route)
         .service(get_all_cart_items)
+        .service(get_settings_route)
+        .service(update_settings_route)
+        .service(get_vat_rates_route)
+        .service(set_vat_rate_route)
+        .service(delete_vat_rate_route)
         .service(create_vendor_report_route)
+        .service(create_appeal_route)
+        .service(get_appeals_route)
+        .service(resolve_appeal_route)
         .service(get_all_vendor_reports_route)
         .service(update_vendor_report_status_route)
         .service(get_databases)
         .service(get_tables)
         .service(get_table_columns)
         .service(get_table_data)
+        .service(request_admin_query_code)
+        .service(admin_query_route)
         .service(chatbot_handler);
 }
@@ -0,0 +1,243 @@
+//! Runtime-configurable settings backed by the `settings` table.
+//! Values are cached in memory per database so hot paths don't hit Postgres;
+//! `set_setting` writes through and refreshes the cache immediately.
+
+use crate::db;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+/// When true, the API answers 503 to everyone but admins (see `maintenance`).
+pub const MAINTENANCE_MODE: &str = "maintenance_mode";
+/// Message returned alongside the 503 while in maintenance mode.
+pub const MAINTENANCE_MESSAGE: &str = "maintenance_message";
+/// Hours a cart must sit untouched before its owner is emailed a reminder.
+pub const CART_REMINDER_HOURS: &str = "cart_reminder_hours";
+/// Weighted report score at which a vendor is suspended from selling.
+pub const REPORT_SUSPENSION_THRESHOLD: &str = "report_suspension_threshold";
+/// Smallest amount (KES) a single wallet withdrawal may be.
+pub const MIN_WITHDRAWAL_AMOUNT: &str = "min_withdrawal_amount";
+/// Largest amount (KES) a single wallet withdrawal may be.
+pub const MAX_WITHDRAWAL_AMOUNT: &str = "max_withdrawal_amount";
+/// Total amount (KES) a user may withdraw per calendar day.
+pub const DAILY_WITHDRAWAL_LIMIT: &str = "daily_withdrawal_limit";
+/// Maximum number of distinct products in a cart.
+pub const MAX_CART_ITEMS: &str = "max_cart_items";
+/// Maximum quantity of a single product in a cart.
+pub const MAX_CART_ITEM_QUANTITY: &str = "max_cart_item_quantity";
+/// KSh per US dollar, used only to display converted prices.
+pub const USD_EXCHANGE_RATE: &str = "usd_exchange_rate";
+/// Shortest password accepted when a password is set.
+pub const PASSWORD_MIN_LENGTH: &str = "password_min_length";
+/// Whether new passwords need an uppercase letter.
+pub const PASSWORD_REQUIRE_UPPERCASE: &str = "password_require_uppercase";
+/// Whether new passwords need a lowercase letter.
+pub const PASSWORD_REQUIRE_LOWERCASE: &str = "password_require_lowercase";
+/// Whether new passwords need a digit.
+pub const PASSWORD_REQUIRE_DIGIT: &str = "password_require_digit";
+/// Whether new passwords need a special character.
+pub const PASSWORD_REQUIRE_SYMBOL: &str = "password_require_symbol";
+/// Days after approval that a vendor's verification document is kept.
+pub const VERIFICATION_DOCUMENT_RETENTION_DAYS: &str = "verification_document_retention_days";
+/// Minutes stock stays reserved for a checkout awaiting payment.
+pub const STOCK_RESERVATION_MINUTES: &str = "stock_reservation_minutes";
+/// Hours a vendor has to accept a new order before it is declined automatically.
+pub const ORDER_ACCEPTANCE_HOURS: &str = "order_acceptance_hours";
+/// VAT rate (fraction, 0.0 - 1.0) included in prices, unless the product's category has its own rate.
+pub const VAT_RATE: &str = "vat_rate";
+/// Longest product description (characters) vendors and admins may save.
+pub const PRODUCT_DESCRIPTION_MAX_LENGTH: &str = "product_description_max_length";
+/// Days a product counts as a new arrival after it is listed.
+pub const NEW_ARRIVALS_DAYS: &str = "new_arrivals_days";
+
+/// Value type and allowed range of a known setting, used for validation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingKind {
+    Bool,
+    /// Whole number of at least 1 (counts, lengths, hours, minutes, days)
+    PositiveInteger,
+    /// Whole number of at least 0
+    NonNegativeInteger,
+    /// Number above 0 (amounts, rates, thresholds)
+    PositiveNumber,
+    /// Number from 0.0 to 1.0 (tax rates)
+    Fraction,
+    Text,
+}
+
+impl SettingKind {
+    fn name(&self) -> &'static str {
+        match self {
+            SettingKind::Bool => "boolean",
+            SettingKind::PositiveInteger => "whole number of at least 1",
+            SettingKind::NonNegativeInteger => "whole number of at least 0",
+            SettingKind::PositiveNumber => "number above 0",
+            SettingKind::Fraction => "number from 0 to 1",
+            SettingKind::Text => "string",
+        }
+    }
+
+    fn accepts(&self, value: &str) -> bool {
+        let integer = || value.parse::<i64>().ok();
+        let number = || value.parse::<f64>().ok().filter(|v| v.is_finite());
+        match self {
+            SettingKind::Bool => value == "true" || value == "false",
+            SettingKind::PositiveInteger => integer().is_some_and(|v| v >= 1),
+            SettingKind::NonNegativeInteger => integer().is_some_and(|v| v >= 0),
+            SettingKind::PositiveNumber => number().is_some_and(|v| v > 0.0),
+            SettingKind::Fraction => number().is_some_and(|v| (0.0..=1.0).contains(&v)),
+            SettingKind::Text => true,
+        }
+    }
+}
+
+/// Known settings: (key, kind, default value).
+const KNOWN_SETTINGS: &[(&str, SettingKind, &str)] = &[
+    (MAINTENANCE_MODE, SettingKind::Bool, "false"),
+    (MAINTENANCE_MESSAGE, SettingKind::Text, "The marketplace is down for maintenance. Please try again shortly."),
+    (CART_REMINDER_HOURS, SettingKind::PositiveInteger, "24"),
+    (REPORT_SUSPENSION_THRESHOLD, SettingKind::PositiveNumber, "5.0"),
+    (MIN_WITHDRAWAL_AMOUNT, SettingKind::PositiveNumber, "10"),
+    (MAX_WITHDRAWAL_AMOUNT, SettingKind::PositiveNumber, "150000"),
+    (DAILY_WITHDRAWAL_LIMIT, SettingKind::PositiveNumber, "300000"),
+    (USD_EXCHANGE_RATE, SettingKind::PositiveNumber, "129.0"),
+    (MAX_CART_ITEMS, SettingKind::PositiveInteger, "50"),
+    (MAX_CART_ITEM_QUANTITY, SettingKind::PositiveInteger, "100"),
+    (PASSWORD_MIN_LENGTH, SettingKind::PositiveInteger, "8"),
+    (PASSWORD_REQUIRE_UPPERCASE, SettingKind::Bool, "true"),
+    (PASSWORD_REQUIRE_LOWERCASE, SettingKind::Bool, "true"),
+    (PASSWORD_REQUIRE_DIGIT, SettingKind::Bool, "true"),
+    (PASSWORD_REQUIRE_SYMBOL, SettingKind::Bool, "true"),
+    (VERIFICATION_DOCUMENT_RETENTION_DAYS, SettingKind::NonNegativeInteger, "90"),
+    (STOCK_RESERVATION_MINUTES, SettingKind::PositiveInteger, "15"),
+    (ORDER_ACCEPTANCE_HOURS, SettingKind::PositiveInteger, "48"),
+    (VAT_RATE, SettingKind::Fraction, "0.16"),
+    (PRODUCT_DESCRIPTION_MAX_LENGTH, SettingKind::PositiveInteger, "2000"),
+    (NEW_ARRIVALS_DAYS, SettingKind::PositiveInteger, "14"),
+];
+
+/// Error type for settings operations
+#[derive(Debug)]
+pub enum SettingError {
+    InvalidValue { key: String, expected: &'static str },
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for SettingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingError::InvalidValue { key, expected } => write!(f, "Setting '{}' must be a {}", key, expected),
+            SettingError::Database(err) => write!(f, "Settings database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SettingError {}
+
+type SettingsMap = HashMap<String, String>;
+
+/// Cache keyed by database name so separate databases never share values.
+static CACHE: OnceLock<RwLock<HashMap<String, SettingsMap>>> = OnceLock::new();
+
+fn cache() -> &'static RwLock<HashMap<String, SettingsMap>> {
+    CACHE.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn cache_key(pool: &PgPool) -> String {
+    pool.connect_options().get_database().unwrap_or_default().to_string()
+}
+
+fn known_setting(key: &str) -> Option<(SettingKind, &'static str)> {
+    KNOWN_SETTINGS
+        .iter()
+        .find(|(name, _, _)| *name == key)
+        .map(|(_, kind, default)| (*kind, *default))
+}
+
+/// Normalize and type-check a value for `key`. Unknown keys are stored as text.
+pub fn validate(key: &str, value: &str) -> Result<String, SettingError> {
+    let value = value.trim();
+    match known_setting(key) {
+        Some((kind, _)) if !kind.accepts(value) => Err(SettingError::InvalidValue {
+            key: key.to_string(),
+            expected: kind.name(),
+        }),
+        _ => Ok(value.to_string()),
+    }
+}
+
+/// Load the stored settings for this pool's database into the cache if needed.
+async fn ensure_loaded(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let key = cache_key(pool);
+    if cache().read().unwrap().contains_key(&key) {
+        return Ok(());
+    }
+
+    let stored: SettingsMap = db::get_all_settings(pool).await?.into_iter().collect();
+    cache().write().unwrap().entry(key).or_insert(stored);
+    Ok(())
+}
+
+/// Get a setting value, falling back to the built-in default for known keys.
+pub async fn get_setting(pool: &PgPool, key: &str) -> Option<String> {
+    if let Err(e) = ensure_loaded(pool).await {
+        eprintln!("Failed to load settings: {:?}", e);
+    }
+
+    let cached = cache()
+        .read()
+        .unwrap()
+        .get(&cache_key(pool))
+        .and_then(|settings| settings.get(key).cloned());
+
+    cached.or_else(|| known_setting(key).map(|(_, default)| default.to_string()))
+}
+
+/// Validate and persist a setting, refreshing the cache on success.
+pub async fn set_setting(pool: &PgPool, key: &str, value: &str) -> Result<String, SettingError> {
+    let value = validate(key, value)?;
+    db::upsert_setting(pool, key, &value).await.map_err(SettingError::Database)?;
+
+    cache()
+        .write()
+        .unwrap()
+        .entry(cache_key(pool))
+        .or_default()
+        .insert(key.to_string(), value.clone());
+
+    Ok(value)
+}
+
+/// Drop cached values for this pool's database; the next read reloads them.
+pub fn invalidate(pool: &PgPool) {
+    cache().write().unwrap().remove(&cache_key(pool));
+}
+
+/// All settings: defaults for known keys overlaid with stored values.
+pub async fn all_settings(pool: &PgPool) -> Result<SettingsMap, sqlx::Error> {
+    ensure_loaded(pool).await?;
+
+    let mut settings: SettingsMap = KNOWN_SETTINGS
+        .iter()
+        .map(|(key, _, default)| (key.to_string(), default.to_string()))
+        .collect();
+    if let Some(stored) = cache().read().unwrap().get(&cache_key(pool)) {
+        settings.extend(stored.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    Ok(settings)
+}
+
+/// Typed accessor for float settings.
+pub async fn get_f64(pool: &PgPool, key: &str) -> f64 {
+    get_setting(pool, key).await.and_then(|v| v.parse().ok()).unwrap_or(0.0)
+}
+
+/// Typed accessor for integer settings.
+pub async fn get_i64(pool: &PgPool, key: &str) -> i64 {
+    get_setting(pool, key).await.and_then(|v| v.parse().ok()).unwrap_or(0)
+}
+
+/// Typed accessor for boolean settings.
+pub async fn get_bool(pool: &PgPool, key: &str) -> bool {
+    get_setting(pool, key).await.map(|v| v == "true").unwrap_or(false)
+}
@@ -0,0 +1,164 @@
+//! Shipping quotes for a cart: items are grouped by vendor and each group is
+//! priced with one of that vendor's shipping options. Each group also carries
+//! the vendor's minimum order value, which checkout enforces.
+
+use crate::db;
+use crate::models::{CartItem, ShippingOption, ShippingSelection};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+
+/// Mean Earth radius used for great-circle distances.
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Great-circle distance between two (latitude, longitude) points in km.
+pub fn distance_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+#[derive(Serialize)]
+pub struct OptionQuote {
+    #[serde(flatten)]
+    pub option: ShippingOption,
+    /// `None` when the option can't be priced (per-km without both locations)
+    pub cost: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct VendorShippingQuote {
+    pub vendor_id: i32,
+    pub vendor_username: String,
+    pub subtotal: f64,
+    /// The vendor's minimum order value; 0 when they have none
+    pub min_order_value: f64,
+    pub options: Vec<OptionQuote>,
+    pub selected_option_id: Option<i32>,
+    pub shipping_fee: f64,
+}
+
+impl VendorShippingQuote {
+    /// A vendor that offers shipping options but none of them can be used.
+    pub fn is_unshippable(&self) -> bool {
+        !self.options.is_empty() && self.selected_option_id.is_none()
+    }
+
+    /// How far the subtotal falls short of the vendor's minimum order value, if it does.
+    pub fn shortfall(&self) -> Option<f64> {
+        let shortfall = ((self.min_order_value - self.subtotal) * 100.0).round() / 100.0;
+        (shortfall > 0.0).then_some(shortfall)
+    }
+}
+
+/// Shipping charged to one vendor group, stored on the payment transaction at checkout.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct ShippingCharge {
+    pub vendor_id: i32,
+    pub option_id: Option<i32>,
+    pub fee: f64,
+}
+
+/// Error for an explicit selection that can't be honoured.
+#[derive(Debug)]
+pub enum QuoteError {
+    /// The option doesn't exist, is inactive, or belongs to another vendor
+    WrongVendor { vendor_id: i32, option_id: i32 },
+    /// A per-km option was chosen but a location is missing
+    NoDistance { option_id: i32 },
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for QuoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuoteError::WrongVendor { vendor_id, option_id } => {
+                write!(f, "Shipping option {} is not offered by vendor {}", option_id, vendor_id)
+            }
+            QuoteError::NoDistance { option_id } => {
+                write!(f, "Shipping option {} needs both your and the vendor's location", option_id)
+            }
+            QuoteError::Database(err) => write!(f, "Shipping database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for QuoteError {}
+
+impl From<sqlx::Error> for QuoteError {
+    fn from(err: sqlx::Error) -> Self {
+        QuoteError::Database(err)
+    }
+}
+
+/// Quote shipping for each vendor in `items`. Explicit `selections` must name an
+/// option of that vendor; other vendors get their cheapest usable option.
+/// Vendors without options ship free.
+pub async fn quote(
+    pool: &PgPool,
+    customer_id: i32,
+    items: &[CartItem],
+    selections: &[ShippingSelection],
+) -> Result<Vec<VendorShippingQuote>, QuoteError> {
+    let mut subtotals: BTreeMap<i32, f64> = BTreeMap::new();
+    for item in items {
+        *subtotals.entry(item.product.vendor_id as i32).or_insert(0.0) += item.product.price * item.quantity as f64;
+    }
+
+    let vendor_ids: Vec<i32> = subtotals.keys().copied().collect();
+    let options = db::get_shipping_options(pool, &vendor_ids).await?;
+    let minimums = db::get_vendor_order_minimums(pool, &vendor_ids).await?;
+    let mut user_ids = vendor_ids.clone();
+    user_ids.push(customer_id);
+    let coordinates = db::get_user_coordinates(pool, &user_ids).await?;
+
+    let mut quotes = Vec::new();
+    for (vendor_id, subtotal) in subtotals {
+        let distance = match (coordinates.get(&customer_id), coordinates.get(&vendor_id)) {
+            (Some(customer), Some(vendor)) => Some(distance_km(*customer, *vendor)),
+            _ => None,
+        };
+        let vendor_options: Vec<OptionQuote> = options
+            .iter()
+            .filter(|o| o.vendor_id == vendor_id)
+            .map(|o| OptionQuote { option: o.clone(), cost: o.cost(subtotal, distance) })
+            .collect();
+
+        let chosen = match selections.iter().find(|s| s.vendor_id == vendor_id) {
+            Some(selection) => {
+                let quote = vendor_options
+                    .iter()
+                    .find(|q| q.option.id == selection.option_id)
+                    .ok_or(QuoteError::WrongVendor { vendor_id, option_id: selection.option_id })?;
+                let cost = quote.cost.ok_or(QuoteError::NoDistance { option_id: selection.option_id })?;
+                Some((quote.option.id, cost))
+            }
+            None => vendor_options
+                .iter()
+                .filter_map(|q| q.cost.map(|cost| (q.option.id, cost)))
+                .min_by(|a, b| a.1.total_cmp(&b.1)),
+        };
+
+        let (vendor_username, min_order_value) = minimums.get(&vendor_id).cloned().unwrap_or_default();
+        quotes.push(VendorShippingQuote {
+            vendor_id,
+            vendor_username,
+            subtotal: (subtotal * 100.0).round() / 100.0,
+            min_order_value,
+            options: vendor_options,
+            selected_option_id: chosen.map(|(id, _)| id),
+            shipping_fee: chosen.map(|(_, cost)| cost).unwrap_or(0.0),
+        });
+    }
+
+    Ok(quotes)
+}
+
+/// Charges to record for a set of quotes.
+pub fn charges(quotes: &[VendorShippingQuote]) -> Vec<ShippingCharge> {
+    quotes
+        .iter()
+        .map(|q| ShippingCharge { vendor_id: q.vendor_id, option_id: q.selected_option_id, fee: q.shipping_fee })
+        .collect()
+}
@@ -0,0 +1,62 @@
+//! VAT. Listed prices include VAT, so tax never changes what a customer pays;
+//! it is worked out from each line's total (net of any coupon discount) so
+//! invoices and reports can show it. The `vat_rate` setting is the default and
+//! product categories can override it in `vat_rates`, with a rate of 0 making
+//! a category exempt (e.g. unprocessed produce).
+
+use crate::coupons::{self, CouponDiscount};
+use crate::db;
+use crate::models::{round_cents, CartItem};
+use crate::settings;
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// The default VAT rate and per-category overrides in effect.
+#[derive(Debug, Clone, Default)]
+pub struct TaxRates {
+    pub default_rate: f64,
+    /// Keyed by normalized category
+    pub categories: HashMap<String, f64>,
+}
+
+impl TaxRates {
+    pub async fn load(pool: &PgPool) -> Result<Self, sqlx::Error> {
+        Ok(TaxRates {
+            default_rate: settings::get_f64(pool, settings::VAT_RATE).await.clamp(0.0, 1.0),
+            categories: db::get_vat_rates(pool).await?.into_iter().collect(),
+        })
+    }
+
+    pub fn rate_for(&self, category: &str) -> f64 {
+        self.categories.get(&normalize_category(category)).copied().unwrap_or(self.default_rate)
+    }
+}
+
+/// Categories are matched without regard to case or surrounding spaces.
+pub fn normalize_category(category: &str) -> String {
+    category.trim().to_lowercase()
+}
+
+/// VAT contained in a VAT-inclusive amount, rounded to the cent.
+pub fn inclusive_tax(amount: f64, rate: f64) -> f64 {
+    if rate <= 0.0 {
+        return 0.0;
+    }
+    round_cents(amount * rate / (1.0 + rate))
+}
+
+/// VAT included in a cart's items after coupon discounts; shipping is not taxed.
+pub fn cart_tax(rates: &TaxRates, items: &[CartItem], discounts: &[CouponDiscount]) -> f64 {
+    let tax: f64 = items
+        .iter()
+        .map(|item| {
+            let line_total = item.product.price * item.quantity as f64;
+            let discount = discounts
+                .iter()
+                .find(|discount| discount.vendor_id == item.product.vendor_id as i32)
+                .map_or(0.0, |discount| coupons::line_discount(line_total, discount.percent_off));
+            inclusive_tax(line_total - discount, rates.rate_for(&item.product.category))
+        })
+        .sum();
+    round_cents(tax)
+}
@@ -0,0 +1,163 @@
+//! Timestamp rendering. The database session runs in UTC, and every timestamp
+//! field in a JSON response (`*_at`, `*_until`, `*_time`, `transaction_date`)
+//! is rewritten as RFC 3339 with an explicit offset. Clients pick the zone
+//! with `?tz=`: an IANA name such as `Africa/Nairobi`, a fixed offset such as
+//! `+03:00`, or `UTC` (the default).
+
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+use chrono::{DateTime, FixedOffset, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// Zone timestamps are rendered in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayZone {
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl Default for DisplayZone {
+    fn default() -> Self {
+        DisplayZone::Named(Tz::UTC)
+    }
+}
+
+impl FromStr for DisplayZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("utc") || s == "Z" {
+            return Ok(DisplayZone::default());
+        }
+        if s.starts_with(['+', '-']) {
+            return parse_offset(s).map(DisplayZone::Fixed).ok_or_else(|| format!("Invalid offset: {}", s));
+        }
+        s.parse::<Tz>().map(DisplayZone::Named).map_err(|_| format!("Unknown time zone: {}", s))
+    }
+}
+
+/// `+03:00`, `+0300` or `+03`, up to 14 hours either way.
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let sign = if s.starts_with('-') { -1 } else { 1 };
+    let digits: String = s[1..].chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits[..2].parse::<i32>().ok()?, digits[2..].parse::<i32>().ok()?),
+        _ => return None,
+    };
+    if hours > 14 || minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parse a timestamp as Postgres prints it (`2025-01-31 09:15:00.5+00`), as
+/// RFC 3339, or without an offset, which is taken to be UTC.
+pub fn parse(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(s) {
+        return Some(ts.with_timezone(&Utc));
+    }
+    if let Ok(ts) = DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f%#z") {
+        return Some(ts.with_timezone(&Utc));
+    }
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .map(|naive| Utc.from_utc_datetime(&naive))
+}
+
+/// RFC 3339 in `zone`, e.g. `2025-01-31T12:15:00+03:00`.
+pub fn format(ts: DateTime<Utc>, zone: DisplayZone) -> String {
+    match zone {
+        DisplayZone::Named(tz) => ts.with_timezone(&tz).to_rfc3339_opts(SecondsFormat::AutoSi, false),
+        DisplayZone::Fixed(offset) => ts.with_timezone(&offset).to_rfc3339_opts(SecondsFormat::AutoSi, false),
+    }
+}
+
+fn is_timestamp_field(key: &str) -> bool {
+    key.ends_with("_at") || key.ends_with("_until") || key.ends_with("_time") || key == "transaction_date"
+}
+
+/// Rewrite every timestamp field in `value`, at any depth, in `zone`.
+/// Fields that don't hold a timestamp are left as they are.
+pub fn render_timestamps(value: &mut Value, zone: DisplayZone) {
+    match value {
+        Value::Object(object) => {
+            for (key, field) in object.iter_mut() {
+                match field {
+                    Value::String(text) if is_timestamp_field(key) => {
+                        if let Some(ts) = parse(text) {
+                            *text = format(ts, zone);
+                        }
+                    }
+                    _ => render_timestamps(field, zone),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| render_timestamps(item, zone)),
+        _ => {}
+    }
+}
+
+/// The `tz` query parameter. A `+` that wasn't percent-encoded arrives as a
+/// space, so a leading space is read as one.
+fn requested_zone(query: &str) -> Result<DisplayZone, String> {
+    let tz = url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "tz")
+        .map(|(_, value)| value.into_owned());
+    match tz {
+        Some(tz) if tz.starts_with(' ') => format!("+{}", tz.trim()).parse(),
+        Some(tz) if !tz.trim().is_empty() => tz.parse(),
+        _ => Ok(DisplayZone::default()),
+    }
+}
+
+/// Middleware: render the timestamps of JSON responses in the requested zone.
+/// An unknown `tz` is refused with 400.
+pub async fn timestamp_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let zone = match requested_zone(req.query_string()) {
+        Ok(zone) => zone,
+        Err(message) => {
+            let response = HttpResponse::BadRequest().json(json!({ "error": "Invalid tz", "message": message }));
+            return Ok(req.into_response(response));
+        }
+    };
+
+    let res = next.call(req).await?.map_into_boxed_body();
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    // Streamed bodies are passed through as they are; buffering them here
+    // would defeat the streaming
+    let is_streamed = matches!(res.response().body().size(), actix_web::body::BodySize::Stream);
+    if !is_json || is_streamed {
+        return Ok(res);
+    }
+
+    let (req, res) = res.into_parts();
+    let (mut head, response_body) = res.into_parts();
+    let bytes = body::to_bytes(response_body).await.map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
+    let bytes = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(mut value) => {
+            render_timestamps(&mut value, zone);
+            head.headers_mut().remove(actix_web::http::header::CONTENT_LENGTH);
+            serde_json::to_vec(&value).map(Into::into).unwrap_or(bytes)
+        }
+        Err(_) => bytes,
+    };
+    Ok(ServiceResponse::new(req, head.set_body(BoxBody::new(bytes))))
+}
@@ -0,0 +1,47 @@
+//! Trending products: a blend of recent views, orders and wishlist adds.
+//! Ranking scans several activity tables, so results are cached in memory per
+//! database and window for a short time.
+
+use crate::db;
+use crate::models::TrendingProduct;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+/// Score contributed by one view, one order and one wishlist add.
+pub const VIEW_WEIGHT: f64 = 1.0;
+pub const ORDER_WEIGHT: f64 = 5.0;
+pub const WISHLIST_WEIGHT: f64 = 3.0;
+
+/// How long a computed ranking is served before it is recomputed.
+pub const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// (database name, window in days, limit)
+type CacheKey = (String, i32, i64);
+type TrendingCache = HashMap<CacheKey, (Instant, Vec<TrendingProduct>)>;
+
+static CACHE: OnceLock<RwLock<TrendingCache>> = OnceLock::new();
+
+fn cache() -> &'static RwLock<TrendingCache> {
+    CACHE.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// The top `limit` products by activity over the last `days` days.
+pub async fn trending_products(pool: &PgPool, days: i32, limit: i64) -> Result<Vec<TrendingProduct>, sqlx::Error> {
+    let key = (
+        pool.connect_options().get_database().unwrap_or_default().to_string(),
+        days,
+        limit,
+    );
+    if let Some((computed_at, products)) = cache().read().unwrap().get(&key) {
+        if computed_at.elapsed() < CACHE_TTL {
+            return Ok(products.clone());
+        }
+    }
+
+    let products =
+        db::get_trending_products(pool, days, (VIEW_WEIGHT, ORDER_WEIGHT, WISHLIST_WEIGHT), limit).await?;
+    cache().write().unwrap().insert(key, (Instant::now(), products.clone()));
+    Ok(products)
+}
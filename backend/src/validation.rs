@@ -0,0 +1,82 @@
+//! Clean-up and length limits for user-supplied free text. Values are trimmed
+//! and stripped of control characters and HTML markup before they're stored;
+//! anything empty or too long is rejected with a 400 naming the field.
+
+use actix_web::HttpResponse;
+use serde_json::json;
+
+pub const PRODUCT_NAME_MAX: usize = 100;
+pub const PRODUCT_CATEGORY_MAX: usize = 50;
+pub const PRODUCT_UNIT_MAX: usize = 20;
+pub const PRODUCT_ORIGIN_MAX: usize = 100;
+pub const MESSAGE_CONTENT_MAX: usize = 2000;
+pub const REVIEW_COMMENT_MAX: usize = 1000;
+pub const ANNOUNCEMENT_TITLE_MAX: usize = 120;
+pub const ANNOUNCEMENT_BODY_MAX: usize = 2000;
+pub const APPEAL_MESSAGE_MAX: usize = 2000;
+
+/// A free-text field that failed validation
+#[derive(Debug)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn to_response(&self) -> HttpResponse {
+        HttpResponse::BadRequest().json(json!({
+            "error": self.message,
+            "field": self.field
+        }))
+    }
+}
+
+/// Remove HTML tags (and the contents of script/style elements), keeping the
+/// text as plain characters. Repeats until stable so entity-encoded markup
+/// such as `&lt;script&gt;` can't turn back into a tag once decoded.
+pub fn strip_html(value: &str) -> String {
+    let mut text = value.to_string();
+    for _ in 0..4 {
+        let cleaned = ammonia::Builder::empty().clean(&text).to_string();
+        let decoded = cleaned
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&nbsp;", "\u{a0}")
+            .replace("&amp;", "&");
+        if decoded == text {
+            return text;
+        }
+        text = decoded;
+    }
+    // Still changing after several rounds (deeply nested entities): keep it escaped
+    ammonia::Builder::empty().clean(&text).to_string()
+}
+
+/// Strip markup, trim and drop control characters; multi-line fields keep newlines and tabs.
+pub fn sanitize(value: &str, multiline: bool) -> String {
+    strip_html(value)
+        .chars()
+        .filter(|c| !c.is_control() || (multiline && matches!(c, '\n' | '\t')))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Sanitized `value`, which must be non-empty and at most `max` characters.
+pub fn required_text(field: &'static str, value: &str, max: usize, multiline: bool) -> Result<String, FieldError> {
+    match optional_text(field, Some(value), max, multiline)? {
+        Some(text) => Ok(text),
+        None => Err(FieldError { field, message: format!("{} cannot be empty", field) }),
+    }
+}
+
+/// Sanitized `value` of at most `max` characters, or None when missing or blank.
+pub fn optional_text(field: &'static str, value: Option<&str>, max: usize, multiline: bool) -> Result<Option<String>, FieldError> {
+    let Some(text) = value.map(|v| sanitize(v, multiline)).filter(|t| !t.is_empty()) else {
+        return Ok(None);
+    };
+    if text.chars().count() > max {
+        return Err(FieldError { field, message: format!("{} must be at most {} characters", field, max) });
+    }
+    Ok(Some(text))
+}
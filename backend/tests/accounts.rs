@@ -0,0 +1,162 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn deleted_customer_orders_keep_anonymized_username() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "acct_admin", Role::Admin).await;
+    let vendor = common::create_user(&pool, "acct_vendor", Role::Vendor).await;
+    let customer = common::create_user(&pool, "acct_customer", Role::Customer).await;
+
+    let product = db::create_product(&pool, "Maize", 50.0, "Grains", "Dry maize", 10, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_shipping_order(&pool, customer.id, product.id as i32, 2, "Nakuru")
+        .await
+        .unwrap();
+
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/admin/users/{}", customer.id))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+
+    let orders = db::get_vendor_shipping_orders(&pool, vendor.id).await.unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].customer_username, format!("deleted_user_{}", customer.id));
+
+    // Deleted accounts can't log in and disappear from user listings
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "username": "acct_customer", "password": "password123" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    let users = db::get_all_users(&pool).await.unwrap();
+    assert!(users.iter().all(|u| u.id != customer.id));
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/admin/users/{}/reactivate", customer.id))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert!(db::is_user_active(&pool, customer.id).await.unwrap());
+}
+
+#[actix_web::test]
+async fn customer_can_delete_own_account() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_user(&pool, "self_vendor", Role::Vendor).await;
+    let customer = common::create_user(&pool, "self_customer", Role::Customer).await;
+
+    let product = db::create_product(&pool, "Beans", 120.0, "Legumes", "Dry beans", 5, None, vendor.id)
+        .await
+        .unwrap();
+    db::add_to_cart(&pool, customer.id, product.id as i32, 1).await.unwrap();
+    db::send_message(&pool, customer.id, vendor.id, "Hello").await.unwrap();
+
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::delete()
+        .uri("/account")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "current_password": "wrong" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let req = test::TestRequest::delete()
+        .uri("/account")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "current_password": "password123" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+
+    assert!(!db::is_user_active(&pool, customer.id).await.unwrap());
+    assert!(db::get_cart_items(&pool, customer.id).await.unwrap().is_empty());
+    assert!(db::get_messages_between_users(&pool, customer.id, vendor.id).await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn vendor_with_wallet_balance_cannot_delete_account() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_user(&pool, "balance_vendor", Role::Vendor).await;
+    sqlx::query("UPDATE users SET wallet_balance = 250.0 WHERE id = $1")
+        .bind(vendor.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::delete()
+        .uri("/account")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "current_password": "password123" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["reason"], "wallet_balance");
+    assert!(db::is_user_active(&pool, vendor.id).await.unwrap());
+}
+
+#[actix_web::test]
+async fn export_contains_only_the_callers_own_data() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "export_vendor").await;
+    let other_vendor = common::create_verified_vendor(&pool, "export_other_vendor").await;
+    let customer = common::create_user(&pool, "export_customer", Role::Customer).await;
+    let other_customer = common::create_user(&pool, "export_other_customer", Role::Customer).await;
+
+    let kale = db::create_product(&pool, "Kale", 30.0, "Vegetables", "Fresh kale", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let eggs = db::create_product(&pool, "Eggs", 15.0, "Dairy", "Farm eggs", 30, None, other_vendor.id)
+        .await
+        .unwrap();
+    let own_order = db::create_shipping_order(&pool, customer.id, kale.id as i32, 2, "Kisumu").await.unwrap();
+    db::create_shipping_order(&pool, other_customer.id, eggs.id as i32, 6, "Eldoret").await.unwrap();
+    db::send_message(&pool, customer.id, vendor.id, "Is the kale organic?").await.unwrap();
+    db::send_message(&pool, other_customer.id, other_vendor.id, "Not for you").await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let export = |user| test::TestRequest::get().uri("/account/export").insert_header(common::bearer(user)).to_request();
+
+    let resp = test::call_service(&app, export(&customer)).await;
+    assert_eq!(resp.status(), 200);
+    let bundle: Value = test::read_body_json(resp).await;
+    assert_eq!(bundle["profile"]["username"], "export_customer");
+    assert!(bundle["profile"].get("password_hash").is_none());
+    let orders = bundle["orders"].as_array().unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0]["id"], own_order.id);
+    assert_eq!(orders[0]["vendor"], "export_vendor");
+    let messages = bundle["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["direction"], "sent");
+    assert_eq!(messages[0]["counterpart"], "export_vendor");
+    assert!(!bundle.to_string().contains(&vendor.email));
+
+    // Exports are expensive, so there's one per hour
+    let resp = test::call_service(&app, export(&customer)).await;
+    assert_eq!(resp.status(), 429);
+    assert!(resp.headers().contains_key("Retry-After"));
+
+    let resp = test::call_service(&app, export(&vendor)).await;
+    assert_eq!(resp.status(), 200);
+    let bundle: Value = test::read_body_json(resp).await;
+    let products: Vec<&str> = bundle["products"].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+    assert_eq!(products, vec!["Kale"]);
+    let sales = bundle["sales"].as_array().unwrap();
+    assert_eq!(sales.len(), 1);
+    assert!(sales[0].get("shipping_address").is_none());
+    assert!(!bundle["sales"].to_string().contains("export_customer"));
+    assert!(!bundle.to_string().contains(&customer.email));
+    assert_eq!(bundle["messages"][0]["direction"], "received");
+    assert_eq!(bundle["messages"][0]["counterpart"], "export_customer");
+}
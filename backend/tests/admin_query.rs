@@ -0,0 +1,145 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn admin_queries_need_a_code_and_only_select_runs() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "sql_admin", Role::Admin).await;
+    let customer = common::create_user(&pool, "sql_customer", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    let query = |user, body: Value| {
+        test::TestRequest::post()
+            .uri("/api/admin/query")
+            .insert_header(common::bearer(user))
+            .set_json(body)
+            .to_request()
+    };
+
+    let req = query(&customer, json!({ "sql": "SELECT 1", "code": "000000" }));
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+    let req = query(&admin, json!({ "sql": "SELECT 1", "code": "not-a-code" }));
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/query/code")
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let code: String = sqlx::query_scalar("SELECT verification_code FROM admin_query_codes WHERE admin_id = $1 AND used = FALSE")
+        .bind(admin.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    for sql in [
+        "DELETE FROM users",
+        "SELECT 1; DROP TABLE users",
+        "WITH gone AS (DELETE FROM users RETURNING id) SELECT * FROM gone",
+        "SELECT * INTO users_copy FROM users",
+        "SELECT * FROM users FOR UPDATE",
+    ] {
+        let resp = test::call_service(&app, query(&admin, json!({ "sql": sql, "code": code }))).await;
+        assert_eq!(resp.status(), 400, "{}", sql);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "Query rejected", "{}", sql);
+    }
+
+    // Writes the keyword check can't see are stopped by the read-only transaction
+    let resp = test::call_service(&app, query(&admin, json!({ "sql": "SELECT nextval('users_id_seq')", "code": code }))).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Query rejected");
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&pool).await.unwrap();
+    assert_eq!(users, 2);
+
+    // Server functions hidden from the keyword check by an escape string and a
+    // comment are still out of reach of the reader role
+    for sql in [
+        "select E'\\'', pg_read_file('/etc/passwd') --'",
+        "SELECT /* */ pg_ls_dir('.')",
+    ] {
+        let resp = test::call_service(&app, query(&admin, json!({ "sql": sql, "code": code }))).await;
+        assert_eq!(resp.status(), 400, "{}", sql);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "Query rejected", "{}", sql);
+    }
+    let resp = test::call_service(&app, query(&admin, json!({ "sql": "SELECT pg_catalog.pg_stat_file('/etc/passwd')", "code": code }))).await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Query rejected");
+
+    // Parameters are bound, not interpolated
+    let resp = test::call_service(
+        &app,
+        query(&admin, json!({ "sql": "SELECT id, username FROM users WHERE id = $1;", "params": [customer.id], "code": code })),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["columns"], json!(["id", "username"]));
+    assert_eq!(body["rows"], json!([[customer.id, "sql_customer"]]));
+    assert_eq!(body["truncated"], false);
+
+    let entries = db::get_audit_log(&pool, Some(admin.id), None, 20).await.unwrap();
+    assert_eq!(entries.iter().filter(|entry| entry.action == "admin.query").count(), 11);
+}
+
+#[actix_web::test]
+async fn admin_select_results_are_capped() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "cap_admin", Role::Admin).await;
+    db::store_admin_query_code(&pool, admin.id, "123456", chrono::Utc::now() + chrono::Duration::minutes(10))
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/query")
+        .insert_header(common::bearer(&admin))
+        .set_json(json!({ "sql": "SELECT n FROM generate_series(1, 2000) AS n ORDER BY n", "code": "123456" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["row_count"], 500);
+    assert_eq!(body["rows"].as_array().unwrap().len(), 500);
+    assert_eq!(body["rows"][499], json!([500]));
+    assert_eq!(body["truncated"], true);
+
+    let entries = db::get_audit_log(&pool, Some(admin.id), None, 10).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, "admin.query");
+    assert!(entries[0].details.as_deref().unwrap_or_default().starts_with("ok: 500 rows (truncated)"));
+}
+
+#[actix_web::test]
+async fn admin_query_code_stops_working_after_too_many_wrong_guesses() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "guess_admin", Role::Admin).await;
+    db::store_admin_query_code(&pool, admin.id, "123456", chrono::Utc::now() + chrono::Duration::minutes(10))
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let query = |code: &str| {
+        test::TestRequest::post()
+            .uri("/api/admin/query")
+            .insert_header(common::bearer(&admin))
+            .set_json(json!({ "sql": "SELECT 1", "code": code }))
+            .to_request()
+    };
+    for guess in 0..backend::admin_query::CODE_MAX_ATTEMPTS - 1 {
+        let wrong = format!("{:06}", guess);
+        assert_eq!(test::call_service(&app, query(&wrong)).await.status(), 403);
+    }
+    // Still one guess to spare, so the right code works
+    assert_eq!(test::call_service(&app, query("123456")).await.status(), 200);
+
+    assert_eq!(test::call_service(&app, query("999999")).await.status(), 403);
+    // The code is spent; even the right one is refused now
+    assert_eq!(test::call_service(&app, query("123456")).await.status(), 403);
+}
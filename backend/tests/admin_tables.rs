@@ -0,0 +1,71 @@
+mod common;
+
+use actix_web::test;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn table_viewer_serializes_timestamps_json_and_other_types() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "tables_admin", Role::Admin).await;
+    sqlx::query(
+        "CREATE TABLE viewer_samples (
+            id SERIAL PRIMARY KEY,
+            seen_at TIMESTAMPTZ,
+            logged_at TIMESTAMP,
+            payload JSONB,
+            amount NUMERIC(12, 2),
+            token UUID,
+            scores INTEGER[],
+            labels TEXT[],
+            raw BYTEA
+        )",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO viewer_samples (seen_at, logged_at, payload, amount, token, scores, labels, raw) VALUES
+         ('2026-03-04T05:06:07Z', '2026-03-04 05:06:07', '{\"crop\": \"maize\", \"bags\": [1, 2]}', 1234.50,
+          '6f1c2a9e-3b4d-4e5f-8a9b-0c1d2e3f4a5b', '{3, NULL, 5}', '{a, b}', '\\x00ff'),
+         (NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/tables/viewer_samples/data")
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["columns"],
+        json!(["id", "seen_at", "logged_at", "payload", "amount", "token", "scores", "labels", "raw"])
+    );
+    assert_eq!(
+        body["rows"][0],
+        json!([
+            1,
+            "2026-03-04T05:06:07+00:00",
+            "2026-03-04T05:06:07+00:00",
+            { "crop": "maize", "bags": [1, 2] },
+            "1234.50",
+            "6f1c2a9e-3b4d-4e5f-8a9b-0c1d2e3f4a5b",
+            [3, null, 5],
+            ["a", "b"],
+            "Unsupported type: BYTEA"
+        ])
+    );
+    assert_eq!(body["rows"][1], json!([2, null, null, null, null, null, null, null, null]));
+
+    let customer = common::create_user(&pool, "tables_customer", Role::Customer).await;
+    let req = test::TestRequest::get()
+        .uri("/api/admin/tables/viewer_samples/data")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
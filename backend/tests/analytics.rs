@@ -0,0 +1,162 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::Value;
+
+#[actix_web::test]
+async fn product_views_show_in_vendor_analytics() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "views_vendor").await;
+    let alice = common::create_user(&pool, "views_alice", Role::Customer).await;
+    let bob = common::create_user(&pool, "views_bob", Role::Customer).await;
+    let kale = db::create_product(&pool, "Kale", 30.0, "Vegetables", "Curly kale", 40, None, vendor.id)
+        .await
+        .unwrap();
+    let leeks = db::create_product(&pool, "Leeks", 45.0, "Vegetables", "Fresh leeks", 40, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let view = |user: Option<&backend::models::User>| {
+        let mut req = test::TestRequest::post().uri(&format!("/products/{}/view", kale.id));
+        if let Some(user) = user {
+            req = req.insert_header(common::bearer(user));
+        }
+        req.to_request()
+    };
+
+    // Alice's rapid repeat view is debounced; the vendor's own view isn't counted
+    let expected = [(Some(&alice), true), (Some(&alice), false), (Some(&bob), true), (None, true), (Some(&vendor), false)];
+    for (user, recorded) in expected {
+        let body: Value = test::call_and_read_body_json(&app, view(user)).await;
+        assert_eq!(body["data"]["recorded"], recorded);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/vendor/analytics/products")
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    let analytics: Value = test::call_and_read_body_json(&app, req).await;
+    let analytics = analytics.as_array().unwrap();
+    assert_eq!(analytics.len(), 2);
+    assert_eq!(analytics[0]["product_id"], kale.id);
+    assert_eq!(analytics[0]["total_views"], 3);
+    assert_eq!(analytics[0]["views_last_7_days"], 3);
+    assert_eq!(analytics[1]["product_id"], leeks.id);
+    assert_eq!(analytics[1]["total_views"], 0);
+
+    let req = test::TestRequest::get()
+        .uri("/reports/vendor/sales")
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    let report: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(report["total_views"], 3);
+    let days = report["views_by_day"].as_array().unwrap();
+    assert_eq!(days.len(), 30);
+    assert_eq!(days.last().unwrap()["views"], 3);
+
+    let req = test::TestRequest::post().uri("/products/999999/view").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn recently_viewed_is_newest_first_without_duplicates() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "recent_vendor").await;
+    let carol = common::create_user(&pool, "recent_carol", Role::Customer).await;
+    let mut ids = Vec::new();
+    for name in ["Beans", "Peas", "Lentils"] {
+        let product = db::create_product(&pool, name, 80.0, "Legumes", "Dry", 10, None, vendor.id)
+            .await
+            .unwrap();
+        ids.push(product.id);
+    }
+    let app = common::init_app(&pool).await;
+
+    for id in [ids[0], ids[1], ids[2], ids[1]] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/products/{}/view", id))
+            .insert_header(common::bearer(&carol))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/products/recently-viewed")
+        .insert_header(common::bearer(&carol))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let names: Vec<&str> = body.as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["Peas", "Lentils", "Beans"]);
+
+    // Deleted products drop out of the history
+    db::delete_product(&pool, ids[2] as i32, vendor.id).await.unwrap();
+    let req = test::TestRequest::get()
+        .uri("/products/recently-viewed")
+        .insert_header(common::bearer(&carol))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body.as_array().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn inventory_report_separates_moving_and_stale_stock() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "inventory_vendor").await;
+    let customer = common::create_user(&pool, "inventory_customer", Role::Customer).await;
+    let onions = db::create_product(&pool, "Onions", 20.0, "Vegetables", "Red onions", 20, None, vendor.id)
+        .await
+        .unwrap();
+    let yams = db::create_product(&pool, "Yams", 80.0, "Vegetables", "Purple yams", 15, None, vendor.id)
+        .await
+        .unwrap();
+    let beets = db::create_product(&pool, "Beets", 40.0, "Vegetables", "Beetroot", 8, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_shipping_order(&pool, customer.id, onions.id as i32, 4, "Nairobi").await.unwrap();
+    db::create_shipping_order(&pool, customer.id, onions.id as i32, 2, "Nairobi").await.unwrap();
+    // Beets last sold well before the window
+    let old = db::create_shipping_order(&pool, customer.id, beets.id as i32, 2, "Nairobi").await.unwrap();
+    sqlx::query("UPDATE shipping_orders SET created_at = NOW() - INTERVAL '45 days' WHERE id = $1")
+        .bind(old.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get()
+        .uri("/reports/vendor/inventory?days=30&stale_days=30")
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    let report: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(report["window_days"], 30);
+    let products = report["products"].as_array().unwrap();
+    let item = |id: u32| products.iter().find(|p| p["product_id"] == id).unwrap();
+
+    let moving = item(onions.id);
+    assert_eq!(moving["stock"], 14);
+    assert_eq!(moving["units_sold"], 6);
+    // 6 sold over 30 days is 0.2 a day, so 14 left lasts 70 days
+    assert_eq!(moving["days_of_stock_remaining"], 70.0);
+    assert_eq!(moving["stale"], false);
+
+    let unsold = item(yams.id);
+    assert_eq!(unsold["units_sold"], 0);
+    assert_eq!(unsold["days_of_stock_remaining"], Value::Null);
+    assert_eq!(unsold["last_sold_at"], Value::Null);
+    assert_eq!(unsold["stale"], true);
+
+    let lapsed = item(beets.id);
+    assert_eq!(lapsed["units_sold"], 0);
+    assert!(lapsed["last_sold_at"].is_string());
+    assert_eq!(lapsed["stale"], true);
+    assert_eq!(products[0]["product_id"], onions.id);
+
+    let req = test::TestRequest::get()
+        .uri("/reports/vendor/inventory")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn announcement_notifies_followers_and_is_rate_limited() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "harvest_vendor").await;
+    let alice = common::create_user(&pool, "harvest_alice", Role::Customer).await;
+    let bob = common::create_user(&pool, "harvest_bob", Role::Customer).await;
+    let carol = common::create_user(&pool, "harvest_carol", Role::Customer).await;
+    db::follow_vendor(&pool, alice.id, vendor.id).await.unwrap();
+    db::follow_vendor(&pool, bob.id, vendor.id).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let post = || {
+        test::TestRequest::post()
+            .uri("/vendor/announcements")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "title": "Mango harvest", "body": "Fresh Apple mangoes from Thursday" }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, post()).await;
+    assert_eq!(resp.status(), 201);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["notified"], 2);
+
+    for (user, expected) in [(&alice, 1), (&bob, 1), (&carol, 0)] {
+        let req = test::TestRequest::get()
+            .uri("/notifications?unread=true")
+            .insert_header(common::bearer(user))
+            .to_request();
+        let notifications: Value = test::call_and_read_body_json(&app, req).await;
+        let notifications = notifications.as_array().unwrap();
+        assert_eq!(notifications.len(), expected, "{}", user.username);
+        if expected == 1 {
+            assert_eq!(notifications[0]["kind"], "announcement");
+            assert_eq!(notifications[0]["body"], "Fresh Apple mangoes from Thursday");
+        }
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/announcements")
+        .insert_header(common::bearer(&alice))
+        .to_request();
+    let feed: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(feed[0]["title"], "Mango harvest");
+    assert_eq!(feed[0]["vendor_username"], "harvest_vendor");
+
+    // A rapid second post is refused and nobody is notified again
+    let resp = test::call_service(&app, post()).await;
+    assert_eq!(resp.status(), 429);
+    assert!(resp.headers().contains_key("retry-after"));
+    let unread = db::get_notifications(&pool, alice.id, true, 50).await.unwrap();
+    assert_eq!(unread.len(), 1);
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn approved_appeal_restores_product_creation() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "appeal_admin", Role::Admin).await;
+    let vendor = common::create_verified_vendor(&pool, "appeal_vendor").await;
+    let customer = common::create_user(&pool, "appeal_customer", Role::Customer).await;
+
+    for _ in 0..3 {
+        db::create_vendor_report(&pool, customer.id, vendor.id, None, "non_delivery", None).await.unwrap();
+    }
+    assert!(db::is_vendor_suspended(&pool, vendor.id).await.unwrap());
+
+    let app = common::init_app(&pool).await;
+    let new_product = || {
+        test::TestRequest::post()
+            .uri("/products")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({
+                "name": "Sorghum", "price": 70.0, "category": "Grains",
+                "description": "White", "quantity": 30
+            }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, new_product()).await.status(), 403);
+
+    let appeal_with = |message: String| {
+        test::TestRequest::post()
+            .uri("/vendor/appeal")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "message": message }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, appeal_with("<script>alert(1)</script>".to_string())).await.status(), 400);
+    let resp = test::call_service(&app, appeal_with("x".repeat(2001))).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["field"], "message");
+
+    let appeal_req = || {
+        test::TestRequest::post()
+            .uri("/vendor/appeal")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "message": "These reports came from a competitor." }))
+            .to_request()
+    };
+    let resp = test::call_service(&app, appeal_req()).await;
+    assert_eq!(resp.status(), 201);
+    let appeal: Value = test::read_body_json(resp).await;
+    assert_eq!(appeal["message"], "These reports came from a competitor.");
+
+    // Only one open appeal at a time
+    assert_eq!(test::call_service(&app, appeal_req()).await.status(), 409);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/appeals?status=pending")
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    let pending: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(pending.as_array().unwrap().len(), 1);
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/admin/appeals/{}", appeal["id"]))
+        .insert_header(common::bearer(&admin))
+        .set_json(json!({ "approved": true, "admin_notes": "Reports were unfounded" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let resolved: Value = test::read_body_json(resp).await;
+    assert_eq!(resolved["status"], "approved");
+
+    assert!(!db::is_vendor_suspended(&pool, vendor.id).await.unwrap());
+    assert_eq!(test::call_service(&app, new_product()).await.status(), 201);
+}
+
+#[actix_web::test]
+async fn banned_vendor_appeals_with_credentials() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "ban_admin", Role::Admin).await;
+    let vendor = common::create_verified_vendor(&pool, "ban_vendor").await;
+    db::ban_user(&pool, vendor.id, true).await.unwrap();
+
+    let app = common::init_app(&pool).await;
+    let req = test::TestRequest::post()
+        .uri("/vendor/appeal")
+        .set_json(json!({ "message": "Please reconsider", "username": "ban_vendor", "password": "wrong" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let req = test::TestRequest::post()
+        .uri("/vendor/appeal")
+        .set_json(json!({ "message": "Please reconsider", "username": "ban_vendor", "password": "password123" }))
+        .to_request();
+    let appeal: Value = test::read_body_json(test::call_service(&app, req).await).await;
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/admin/appeals/{}", appeal["id"]))
+        .insert_header(common::bearer(&admin))
+        .set_json(json!({ "approved": true }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "username": "ban_vendor", "password": "password123" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+}
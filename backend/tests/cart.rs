@@ -0,0 +1,122 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use backend::settings;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn cart_limits_distinct_items() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "limits_vendor").await;
+    let customer = common::create_user(&pool, "limits_customer", Role::Customer).await;
+    settings::set_setting(&pool, settings::MAX_CART_ITEMS, "2").await.unwrap();
+    let mut ids = Vec::new();
+    for name in ["Millet", "Sorghum", "Cassava"] {
+        let product = db::create_product(&pool, name, 60.0, "Grains", "Dry", 500, None, vendor.id)
+            .await
+            .unwrap();
+        ids.push(product.id);
+    }
+    let app = common::init_app(&pool).await;
+
+    let add = |product_id: u32| {
+        test::TestRequest::post()
+            .uri("/cart")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "product_id": product_id, "quantity": 1 }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, add(ids[0])).await.status(), 201);
+    assert_eq!(test::call_service(&app, add(ids[1])).await.status(), 201);
+
+    let resp = test::call_service(&app, add(ids[2])).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["reason"], "max_cart_items");
+
+    // Topping up a product already in the cart is still fine
+    assert_eq!(test::call_service(&app, add(ids[0])).await.status(), 201);
+}
+
+#[actix_web::test]
+async fn cart_limits_quantity_per_item() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "qty_vendor").await;
+    let customer = common::create_user(&pool, "qty_customer", Role::Customer).await;
+    settings::set_setting(&pool, settings::MAX_CART_ITEM_QUANTITY, "10").await.unwrap();
+    let rice = db::create_product(&pool, "Rice", 120.0, "Grains", "Pishori", 500, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let add = |quantity: i32| {
+        test::TestRequest::post()
+            .uri("/cart")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "product_id": rice.id, "quantity": quantity }))
+            .to_request()
+    };
+    let resp = test::call_service(&app, add(8)).await;
+    assert_eq!(resp.status(), 201);
+    let item: Value = test::read_body_json(resp).await;
+
+    // 8 + 3 would pass the cap
+    let resp = test::call_service(&app, add(3)).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["reason"], "max_cart_item_quantity");
+
+    let update = |quantity: i32| {
+        test::TestRequest::patch()
+            .uri(&format!("/cart/{}", item["id"]))
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "quantity": quantity }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, update(11)).await.status(), 400);
+    assert_eq!(test::call_service(&app, update(10)).await.status(), 200);
+}
+
+#[actix_web::test]
+async fn cart_batch_reports_out_of_stock_items_and_adds_the_rest() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "batch_vendor").await;
+    let customer = common::create_user(&pool, "batch_customer", Role::Customer).await;
+    let beans = db::create_product(&pool, "Beans", 150.0, "Grains", "Rosecoco", 40, None, vendor.id)
+        .await
+        .unwrap();
+    let flour = db::create_product(&pool, "Flour", 200.0, "Grains", "Maize", 2, None, vendor.id)
+        .await
+        .unwrap();
+    let oil = db::create_product(&pool, "Oil", 300.0, "Pantry", "Sunflower", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/cart/batch")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "items": [
+            { "product_id": beans.id, "quantity": 3 },
+            { "product_id": flour.id, "quantity": 5 },
+            { "product_id": oil.id, "quantity": 1 }
+        ] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["added"], 2);
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results[0]["success"], true);
+    assert_eq!(results[1]["success"], false);
+    assert_eq!(results[1]["reason"], "out_of_stock");
+    assert_eq!(results[1]["error"], "Only 2 in stock");
+    assert_eq!(results[2]["success"], true);
+
+    let cart = db::get_cart_items(&pool, customer.id).await.unwrap();
+    let mut in_cart: Vec<(i32, i32)> = cart.iter().map(|item| (item.product_id, item.quantity)).collect();
+    in_cart.sort_unstable();
+    assert_eq!(in_cart, vec![(beans.id as i32, 3), (oil.id as i32, 1)]);
+}
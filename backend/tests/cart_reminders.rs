@@ -0,0 +1,48 @@
+mod common;
+
+use backend::models::Role;
+use backend::{db, reminders};
+
+#[actix_web::test]
+async fn stale_cart_is_reminded_exactly_once() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "cart_vendor").await;
+    let stale = common::create_user(&pool, "cart_stale", Role::Customer).await;
+    let fresh = common::create_user(&pool, "cart_fresh", Role::Customer).await;
+    let opted_out = common::create_user(&pool, "cart_opted_out", Role::Customer).await;
+
+    let product = db::create_product(&pool, "Kale", 30.0, "Vegetables", "Sukuma wiki", 50, None, vendor.id)
+        .await
+        .unwrap();
+    for user in [&stale, &fresh, &opted_out] {
+        db::add_to_cart(&pool, user.id, product.id as i32, 2).await.unwrap();
+    }
+    db::set_email_notifications(&pool, opted_out.id, false).await.unwrap();
+
+    sqlx::query("UPDATE cart_items SET updated_at = NOW() - INTERVAL '2 days' WHERE user_id = ANY($1)")
+        .bind(vec![stale.id, opted_out.id])
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let claimed = db::claim_abandoned_carts(&pool, 24).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].0, stale.id);
+
+    // Already reminded within the window
+    assert_eq!(reminders::send_abandoned_cart_reminders(&pool).await.unwrap(), 0);
+}
+
+#[actix_web::test]
+async fn fresh_cart_is_not_reminded() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "cart_vendor").await;
+    let customer = common::create_user(&pool, "cart_customer", Role::Customer).await;
+
+    let product = db::create_product(&pool, "Kale", 30.0, "Vegetables", "Sukuma wiki", 50, None, vendor.id)
+        .await
+        .unwrap();
+    db::add_to_cart(&pool, customer.id, product.id as i32, 1).await.unwrap();
+
+    assert_eq!(reminders::send_abandoned_cart_reminders(&pool).await.unwrap(), 0);
+}
@@ -0,0 +1,133 @@
+//! Shared helpers for integration tests.
+//! Each test gets its own throwaway database created from TEST_DATABASE_URL,
+//! dropped again when the test ends; when that variable is unset the
+//! database-backed tests are skipped.
+
+#![allow(dead_code)]
+
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::{middleware, test, web, App};
+use backend::models::{create_jwt, Role, User};
+use backend::realtime::ChatHub;
+use backend::{audit, db, maintenance, request_id, routes, timestamps};
+use sqlx::{postgres::PgPoolOptions, Connection, Executor, PgConnection, PgPool};
+
+/// A test's database. Dropping it (at the end of the test, even a failed one)
+/// removes the database, closing any connections still open to it.
+pub struct TestDb {
+    base: String,
+    name: String,
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let base = self.base.clone();
+        let name = self.name.clone();
+        // Drop runs inside the test's runtime, so the cleanup gets its own
+        let result = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+            runtime.block_on(async {
+                let mut admin = PgConnection::connect(&base).await?;
+                admin
+                    .execute(format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", name).as_str())
+                    .await?;
+                admin.close().await?;
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+            })
+        })
+        .join();
+        if let Ok(Err(e)) = result {
+            eprintln!("Failed to drop test database {}: {}", self.name, e);
+        }
+    }
+}
+
+/// Create a fresh database with the full schema, or `None` if TEST_DATABASE_URL
+/// is unset. Keep the `TestDb` alive for the whole test; dropping it removes the database.
+pub async fn test_pool() -> Option<(PgPool, TestDb)> {
+    let base = match std::env::var("TEST_DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("TEST_DATABASE_URL not set; skipping database test");
+            return None;
+        }
+    };
+
+    let admin = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&base)
+        .await
+        .expect("Failed to connect to TEST_DATABASE_URL");
+    let name = format!("fmp_test_{}", uuid::Uuid::new_v4().simple());
+    admin
+        .execute(format!("CREATE DATABASE {}", name).as_str())
+        .await
+        .expect("Failed to create test database");
+    admin.close().await;
+    let test_db = TestDb { base: base.clone(), name: name.clone() };
+
+    let mut url = url::Url::parse(&base).expect("Invalid TEST_DATABASE_URL");
+    url.set_path(&format!("/{}", name));
+    let pool = db::pool_options()
+        .max_connections(5)
+        .connect(url.as_str())
+        .await
+        .expect("Failed to connect to test database");
+
+    db::init_schema(&pool).await;
+    Some((pool, test_db))
+}
+
+/// Create a user with a predictable email and password `password123`.
+pub async fn create_user(pool: &PgPool, username: &str, role: Role) -> User {
+    db::create_user(pool, username, &format!("{}@example.com", username), "password123", &role, None, None, None)
+        .await
+        .expect("Failed to create test user")
+}
+
+/// Bearer header value for `user`.
+pub fn bearer(user: &User) -> (String, String) {
+    let token = create_jwt(user).expect("Failed to create JWT");
+    ("Authorization".to_string(), format!("Bearer {}", token))
+}
+
+/// Create a vendor that has already passed admin verification.
+pub async fn create_verified_vendor(pool: &PgPool, username: &str) -> User {
+    let mut vendor = create_user(pool, username, Role::Vendor).await;
+    db::update_user_verification(pool, vendor.id, true)
+        .await
+        .expect("Failed to verify test vendor");
+    vendor.verified = true;
+    vendor
+}
+
+/// Build the full application (all routes) on top of `pool`.
+pub async fn init_app(
+    pool: &PgPool,
+) -> impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    init_app_with_hub(pool, web::Data::new(ChatHub::default())).await
+}
+
+/// Like `init_app`, sharing `hub` so a test can subscribe to live events.
+pub async fn init_app_with_hub(
+    pool: &PgPool,
+    hub: web::Data<ChatHub>,
+) -> impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(hub)
+            .wrap(middleware::from_fn(timestamps::timestamp_middleware))
+            .wrap(middleware::from_fn(audit::impersonation_guard))
+            .wrap(middleware::from_fn(maintenance::maintenance_guard))
+            .wrap(middleware::from_fn(request_id::request_id_middleware))
+            .configure(routes::init)
+            .default_service(web::to(routes::not_found)),
+    )
+    .await
+}
+
+/// Route checkout through the simulated (DEMO_MODE) payment path.
+pub fn enable_demo_mode() {
+    std::env::set_var("DEMO_MODE", "true");
+}
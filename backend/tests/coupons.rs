@@ -0,0 +1,113 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn vendor_coupon_discounts_only_that_vendors_items() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "coupon_vendor").await;
+    let other_vendor = common::create_verified_vendor(&pool, "coupon_other").await;
+    let customer = common::create_user(&pool, "coupon_customer", Role::Customer).await;
+    let honey = db::create_product(&pool, "Honey", 200.0, "Pantry", "Raw honey", 50, None, vendor.id)
+        .await
+        .unwrap();
+    let jam = db::create_product(&pool, "Jam", 150.0, "Pantry", "Plum jam", 50, None, vendor.id)
+        .await
+        .unwrap();
+    let milk = db::create_product(&pool, "Milk", 60.0, "Dairy", "Fresh milk", 50, None, other_vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let create = |user, body: Value| {
+        test::TestRequest::post()
+            .uri("/vendor/coupons")
+            .insert_header(common::bearer(user))
+            .set_json(body)
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, create(&customer, json!({ "code": "NOPE", "percent_off": 10.0 }))).await.status(), 403);
+    assert_eq!(test::call_service(&app, create(&vendor, json!({ "code": "HONEY", "percent_off": 150.0 }))).await.status(), 400);
+    let resp = test::call_service(&app, create(&vendor, json!({ "code": "honey10", "percent_off": 10.0 }))).await;
+    assert_eq!(resp.status(), 201);
+    let coupon: Value = test::read_body_json(resp).await;
+    assert_eq!(coupon["code"], "HONEY10");
+    assert_eq!(coupon["vendor_id"], vendor.id);
+    // Codes are unique across vendors, so another vendor can't claim it
+    assert_eq!(test::call_service(&app, create(&other_vendor, json!({ "code": "HONEY10", "percent_off": 50.0 }))).await.status(), 409);
+
+    let checkout = |codes: Value| {
+        test::TestRequest::post()
+            .uri("/checkout")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "mpesa_number": "0712345678", "total_amount": 500.0, "coupon_codes": codes }))
+            .to_request()
+    };
+
+    // Only the other vendor's milk in the cart: the coupon doesn't apply
+    db::add_to_cart(&pool, customer.id, milk.id as i32, 2).await.unwrap();
+    let resp = test::call_service(&app, checkout(json!(["HONEY10"]))).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Invalid coupon");
+    assert_eq!(test::call_service(&app, checkout(json!(["NOSUCHCODE"]))).await.status(), 400);
+    assert!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().is_empty());
+
+    db::add_to_cart(&pool, customer.id, honey.id as i32, 1).await.unwrap();
+    db::add_to_cart(&pool, customer.id, jam.id as i32, 2).await.unwrap();
+    let resp = test::call_service(&app, checkout(json!([" honey10 "]))).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["discount_total"].as_f64().unwrap(), 50.0);
+
+    let orders = db::get_customer_shipping_orders(&pool, customer.id).await.unwrap();
+    assert_eq!(orders.len(), 3);
+    for order in &orders {
+        let (total, discount) = match order.product_id {
+            id if id == honey.id as i32 => (180.0, 20.0),
+            id if id == jam.id as i32 => (270.0, 30.0),
+            _ => (120.0, 0.0),
+        };
+        assert_eq!(order.total_amount, total, "{}", order.product_name);
+        assert_eq!(order.discount, discount, "{}", order.product_name);
+    }
+
+    let coupons = db::get_vendor_coupons(&pool, vendor.id).await.unwrap();
+    assert_eq!(coupons[0].times_used, 1);
+
+    // Withdrawn coupons stop working; only the owner can withdraw them
+    let delete = |user| {
+        test::TestRequest::delete()
+            .uri(&format!("/vendor/coupons/{}", coupon["id"]))
+            .insert_header(common::bearer(user))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, delete(&other_vendor)).await.status(), 404);
+    assert_eq!(test::call_service(&app, delete(&vendor)).await.status(), 204);
+    db::add_to_cart(&pool, customer.id, honey.id as i32, 1).await.unwrap();
+    assert_eq!(test::call_service(&app, checkout(json!(["HONEY10"]))).await.status(), 400);
+}
+
+#[actix_web::test]
+async fn redeeming_a_used_up_coupon_counts_nothing() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "coupon_limit_vendor").await;
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/vendor/coupons")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "code": "ONCE", "percent_off": 10.0, "max_uses": 1 }))
+        .to_request();
+    let coupon: Value = test::call_and_read_body_json(&app, req).await;
+    let coupon_id = coupon["id"].as_i64().unwrap() as i32;
+
+    assert!(db::redeem_coupon(&pool, coupon_id).await.unwrap());
+    assert!(!db::redeem_coupon(&pool, coupon_id).await.unwrap());
+    let coupons = db::get_vendor_coupons(&pool, vendor.id).await.unwrap();
+    assert_eq!(coupons[0].times_used, 1);
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use actix_web::test::{call_and_read_body_json, call_service, TestRequest};
+use backend::currency::{self, StaticRates};
+use backend::db;
+use backend::settings;
+use serde_json::Value;
+
+#[test]
+fn conversion_uses_the_given_rate() {
+    let rates = StaticRates::default().with_rate("usd", 130.0);
+    let price = currency::display_price(&rates, 650.0, "USD").unwrap();
+    assert_eq!(price.amount, 5.0);
+    assert_eq!(price.rate, 130.0);
+    assert_eq!(price.currency, "USD");
+    // Rounded to cents
+    assert_eq!(currency::convert(100.0, 129.0), 0.78);
+    assert!(currency::display_price(&rates, 650.0, "EUR").is_none());
+}
+
+#[actix_web::test]
+async fn products_show_converted_prices_alongside_ksh() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "fx_vendor").await;
+    db::create_product(&pool, "Macadamia", 1250.0, "Nuts", "Roasted", 10, None, vendor.id)
+        .await
+        .unwrap();
+    settings::set_setting(&pool, settings::USD_EXCHANGE_RATE, "125").await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = TestRequest::get().uri("/products?currency=usd").to_request();
+    let body: Value = call_and_read_body_json(&app, req).await;
+    let product = &body[0];
+    assert_eq!(product["price"], 1250.0);
+    assert_eq!(product["display_price"]["currency"], "USD");
+    assert_eq!(product["display_price"]["amount"], 10.0);
+    assert_eq!(product["display_price"]["rate"], 125.0);
+
+    let req = TestRequest::get().uri("/products?currency=XYZ").to_request();
+    assert_eq!(call_service(&app, req).await.status(), 400);
+}
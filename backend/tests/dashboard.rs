@@ -0,0 +1,67 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn customer_dashboard_reports_seeded_figures() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "dash_vendor").await;
+    let other_vendor = common::create_verified_vendor(&pool, "dash_vendor2").await;
+    let customer = common::create_user(&pool, "dash_customer", Role::Customer).await;
+
+    let maize = db::create_product(&pool, "Maize", 50.0, "Grains", "Dry maize", 100, None, vendor.id)
+        .await
+        .unwrap();
+    let beans = db::create_product(&pool, "Beans", 80.0, "Legumes", "Rosecoco", 100, None, other_vendor.id)
+        .await
+        .unwrap();
+
+    // Two open orders and one that has been delivered and settled
+    db::create_shipping_order(&pool, customer.id, maize.id as i32, 2, "Nakuru").await.unwrap();
+    db::create_shipping_order(&pool, customer.id, beans.id as i32, 1, "Nakuru").await.unwrap();
+    let settled = db::create_shipping_order(&pool, customer.id, maize.id as i32, 1, "Nakuru").await.unwrap();
+    db::verify_delivery_and_release_payment(&pool, settled.id, customer.id).await.unwrap();
+
+    db::follow_vendor(&pool, customer.id, vendor.id).await.unwrap();
+    db::follow_vendor(&pool, customer.id, other_vendor.id).await.unwrap();
+
+    db::send_message(&pool, vendor.id, customer.id, "Your maize is packed").await.unwrap();
+    db::send_message(&pool, vendor.id, customer.id, "Dispatching today").await.unwrap();
+    db::send_message(&pool, other_vendor.id, customer.id, "Thanks for ordering").await.unwrap();
+    db::send_message(&pool, customer.id, vendor.id, "Great, thanks").await.unwrap();
+
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/wishlist")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "product_id": beans.id }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+    let req = test::TestRequest::get()
+        .uri("/customer/dashboard")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+
+    assert_eq!(body["active_orders"], 2);
+    assert_eq!(body["total_orders"], 3);
+    assert_eq!(body["total_spent"].as_f64().unwrap(), 230.0);
+    assert_eq!(body["vendors_followed"], 2);
+    assert_eq!(body["unread_messages"], 3);
+    assert_eq!(body["wishlist_size"], 1);
+    assert_eq!(body["recent_orders"].as_array().unwrap().len(), 3);
+
+    // Vendors don't have a customer dashboard
+    let req = test::TestRequest::get()
+        .uri("/customer/dashboard")
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use actix_web::test;
+use backend::models::Role;
+use backend::{db, digests};
+use serde_json::json;
+use sqlx::PgPool;
+
+async fn pending_items(pool: &PgPool, user_id: i32) -> Vec<String> {
+    sqlx::query_scalar("SELECT summary FROM email_digest_items WHERE user_id = $1 AND sent_at IS NULL ORDER BY id")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn daily_user_gets_one_digest_for_two_events() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "digest_vendor").await;
+    let instant_vendor = common::create_verified_vendor(&pool, "digest_instant").await;
+    let customer = common::create_user(&pool, "digest_customer", Role::Customer).await;
+    let beans = db::create_product(&pool, "Beans", 120.0, "Legumes", "Rosecoco", 20, None, vendor.id)
+        .await
+        .unwrap();
+    let peas = db::create_product(&pool, "Peas", 90.0, "Legumes", "Green peas", 20, None, instant_vendor.id)
+        .await
+        .unwrap();
+    let first = db::create_shipping_order(&pool, customer.id, beans.id as i32, 1, "Nyeri").await.unwrap();
+    let second = db::create_shipping_order(&pool, customer.id, beans.id as i32, 2, "Nyeri").await.unwrap();
+    let other = db::create_shipping_order(&pool, customer.id, peas.id as i32, 1, "Nyeri").await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let set_frequency = |frequency: &str| {
+        test::TestRequest::patch()
+            .uri("/profile")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "notification_frequency": frequency }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, set_frequency("weekly")).await.status(), 400);
+    assert_eq!(test::call_service(&app, set_frequency("daily")).await.status(), 200);
+
+    for order in [&first, &second, &other] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/shipping/{}/cancel", order.id))
+            .insert_header(common::bearer(&customer))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+    }
+
+    // Both cancellations wait for the daily digest; the instant vendor was emailed directly
+    assert_eq!(pending_items(&pool, vendor.id).await, vec![
+        format!("Order #{} (1 x Beans) was cancelled by the customer", first.id),
+        format!("Order #{} (2 x Beans) was cancelled by the customer", second.id),
+    ]);
+    assert!(pending_items(&pool, instant_vendor.id).await.is_empty());
+    assert_eq!(digests::send_due_digests(&pool).await.unwrap(), 0);
+
+    sqlx::query("UPDATE email_digest_items SET created_at = NOW() - INTERVAL '25 hours'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(digests::send_due_digests(&pool).await.unwrap(), 1);
+    assert!(pending_items(&pool, vendor.id).await.is_empty());
+    assert_eq!(digests::send_due_digests(&pool).await.unwrap(), 0);
+}
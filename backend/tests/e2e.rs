@@ -0,0 +1,76 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use serde_json::json;
+
+#[actix_web::test]
+async fn signup_login_add_product_and_demo_checkout() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "e2e_vendor").await;
+    let app = common::init_app(&pool).await;
+
+    // Vendor lists a product
+    let req = test::TestRequest::post()
+        .uri("/products")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({
+            "name": "Tomatoes",
+            "price": 50.0,
+            "category": "Vegetables",
+            "description": "Fresh tomatoes",
+            "quantity": 20
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let product: serde_json::Value = test::read_body_json(resp).await;
+
+    // Customer signs up and logs in
+    let req = test::TestRequest::post()
+        .uri("/signup")
+        .set_json(json!({
+            "username": "e2e_customer",
+            "email": "e2e_customer@example.com",
+            "password": "Secret#123",
+            "mpesa_number": "0712345678",
+            "role": "Customer"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success(), "signup failed: {}", resp.status());
+
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "username": "e2e_customer", "password": "Secret#123" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let login: serde_json::Value = test::read_body_json(resp).await;
+    let auth = format!("Bearer {}", login["token"].as_str().unwrap());
+    let customer_id = login["user"]["id"].as_i64().unwrap() as i32;
+
+    // Add to cart and check out in demo mode
+    let req = test::TestRequest::post()
+        .uri("/cart")
+        .insert_header(("Authorization", auth.clone()))
+        .set_json(json!({ "product_id": product["id"], "quantity": 2 }))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let req = test::TestRequest::post()
+        .uri("/checkout")
+        .insert_header(("Authorization", auth.clone()))
+        .set_json(json!({ "mpesa_number": "0712345678", "total_amount": 100.0 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let checkout: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(checkout["status"], "completed");
+
+    let orders = db::get_customer_shipping_orders(&pool, customer_id).await.unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].quantity, 2);
+    assert!(db::get_cart_items(&pool, customer_id).await.unwrap().is_empty());
+}
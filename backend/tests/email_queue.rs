@@ -0,0 +1,143 @@
+mod common;
+
+use actix_web::test;
+use backend::email::{EmailError, MailTransport, Mailer};
+use backend::email_queue::{self, RetryOutcome, MAX_ATTEMPTS};
+use backend::email_templates;
+use backend::models::{QueuedEmail, Role};
+use lettre::Message;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Fails the first `failures` sends as if the server were unreachable, then delivers
+struct FlakyTransport {
+    failures: usize,
+    attempts: Arc<AtomicUsize>,
+}
+
+impl MailTransport for FlakyTransport {
+    fn deliver(&self, _message: &Message) -> Result<(), EmailError> {
+        if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+            Err(EmailError::Transient("connection refused".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn flaky_mailer(failures: usize) -> (Mailer, Arc<AtomicUsize>) {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let transport = FlakyTransport { failures, attempts: attempts.clone() };
+    (Mailer::new(transport, "Farmers Market Place <noreply@example.com>".parse().unwrap()), attempts)
+}
+
+async fn make_due(pool: &PgPool) {
+    sqlx::query("UPDATE email_outbox SET next_attempt_at = NOW()")
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+async fn outbox_row(pool: &PgPool) -> (String, i32, bool) {
+    sqlx::query_as("SELECT status, attempts, sent_at IS NOT NULL FROM email_outbox")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn transient_failure_is_retried_until_sent() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let (mailer, attempts) = flaky_mailer(2);
+    let email = email_templates::from_text("Hello", "Dear wanjiru,\n\nYour order shipped.");
+
+    // The first send fails and is queued rather than lost
+    email_queue::deliver(&pool, &mailer, "wanjiru@example.com", &email, None).await.unwrap();
+    assert_eq!(outbox_row(&pool).await, ("pending".to_string(), 1, false));
+
+    // Not due yet: the backoff holds it back
+    assert_eq!(email_queue::retry_due_emails(&pool, &mailer).await.unwrap(), RetryOutcome::default());
+
+    make_due(&pool).await;
+    let outcome = email_queue::retry_due_emails(&pool, &mailer).await.unwrap();
+    assert_eq!(outcome, RetryOutcome { rescheduled: 1, ..Default::default() });
+    assert_eq!(outbox_row(&pool).await, ("pending".to_string(), 2, false));
+
+    make_due(&pool).await;
+    let outcome = email_queue::retry_due_emails(&pool, &mailer).await.unwrap();
+    assert_eq!(outcome, RetryOutcome { sent: 1, ..Default::default() });
+    assert_eq!(outbox_row(&pool).await, ("sent".to_string(), 3, true));
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+    // Sent emails are left alone
+    make_due(&pool).await;
+    assert_eq!(email_queue::retry_due_emails(&pool, &mailer).await.unwrap(), RetryOutcome::default());
+}
+
+#[actix_web::test]
+async fn email_out_of_attempts_is_listed_for_admins() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "outbox_admin", Role::Admin).await;
+    let app = common::init_app(&pool).await;
+    let (mailer, _) = flaky_mailer(usize::MAX);
+    let email = email_templates::from_text("Hello", "Dear kamau,");
+
+    email_queue::deliver(&pool, &mailer, "kamau@example.com", &email, None).await.unwrap();
+    sqlx::query("UPDATE email_outbox SET attempts = $1, next_attempt_at = NOW()")
+        .bind(MAX_ATTEMPTS - 1)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let outcome = email_queue::retry_due_emails(&pool, &mailer).await.unwrap();
+    assert_eq!(outcome, RetryOutcome { failed: 1, ..Default::default() });
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/emails")
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    let failed: Vec<QueuedEmail> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].recipient, "kamau@example.com");
+    assert_eq!(failed[0].attempts, MAX_ATTEMPTS);
+    assert!(failed[0].last_error.contains("connection refused"));
+
+    let retry = |id: i32| {
+        test::TestRequest::post()
+            .uri(&format!("/api/admin/emails/{}/retry", id))
+            .insert_header(common::bearer(&admin))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, retry(failed[0].id)).await.status(), 200);
+    // Only failed emails can be queued again
+    assert_eq!(test::call_service(&app, retry(failed[0].id)).await.status(), 404);
+
+    let (delivering, _) = flaky_mailer(0);
+    let outcome = email_queue::retry_due_emails(&pool, &delivering).await.unwrap();
+    assert_eq!(outcome, RetryOutcome { sent: 1, ..Default::default() });
+}
+
+#[actix_web::test]
+async fn code_email_is_not_retried_after_the_code_expires() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let (mailer, attempts) = flaky_mailer(1);
+    let email = email_templates::from_text("Your code", "Dear achieng,\n\n123456");
+    let expires_at = chrono::Utc::now() + chrono::Duration::minutes(10);
+
+    email_queue::deliver(&pool, &mailer, "achieng@example.com", &email, Some(expires_at)).await.unwrap();
+    assert_eq!(outbox_row(&pool).await, ("pending".to_string(), 1, false));
+
+    sqlx::query("UPDATE email_outbox SET expires_at = NOW() - INTERVAL '1 minute'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    make_due(&pool).await;
+    let outcome = email_queue::retry_due_emails(&pool, &mailer).await.unwrap();
+    assert_eq!(outcome, RetryOutcome { expired: 1, ..Default::default() });
+    assert_eq!(outbox_row(&pool).await, ("expired".to_string(), 1, false));
+    // The stale code was never sent again
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+    make_due(&pool).await;
+    assert_eq!(email_queue::retry_due_emails(&pool, &mailer).await.unwrap(), RetryOutcome::default());
+}
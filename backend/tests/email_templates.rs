@@ -0,0 +1,86 @@
+mod common;
+
+use actix_web::test::{call_service, TestRequest};
+use backend::email;
+use backend::email_templates::{self, render_template, substitute};
+use backend::models::Role;
+use serde_json::json;
+
+#[test]
+fn template_renders_with_substituted_variables() {
+    let email = render_template(
+        email_templates::ORDER_DECLINED,
+        "en",
+        &[("username", "wanjiru"), ("order_id", "42"), ("quantity", "3"), ("product_name", "Kales"), ("refunded", "150.00")],
+    )
+    .unwrap();
+
+    assert_eq!(email.subject, "Order #42 was declined - Farmers Market Place");
+    assert!(email.body.contains("Dear wanjiru,"));
+    assert!(email.body.contains("order #42 (3 x Kales)"));
+    assert!(email.body.contains("KSh 150.00 has been refunded"));
+    assert!(!email.body.contains("{{"));
+
+    // Swahili has its own wording; an unknown language falls back to English
+    let swahili = render_template(email_templates::PASSWORD_RESET, "sw", &[("username", "wanjiru"), ("code", "123456")]).unwrap();
+    assert!(swahili.body.contains("Mpendwa wanjiru,"));
+    assert!(swahili.body.contains("123456"));
+    let fallback = render_template(email_templates::PASSWORD_RESET, "fr", &[("username", "wanjiru"), ("code", "123456")]).unwrap();
+    assert!(fallback.body.contains("Dear wanjiru,"));
+
+    assert!(render_template("no_such_template", "en", &[]).is_none());
+}
+
+#[test]
+fn unknown_placeholders_are_left_alone() {
+    assert_eq!(substitute("Hi {{ name }}, {{other}} {{", &[("name", "Otieno")]), "Hi Otieno, {{other}} {{");
+}
+
+#[test]
+fn built_message_has_text_and_html_parts() {
+    let rendered = render_template(
+        email_templates::VERIFICATION_REJECTED,
+        "en",
+        &[("username", "<b>kamau</b>")],
+    )
+    .unwrap();
+    // Values are escaped in the HTML body and the text's lists become HTML lists
+    assert!(rendered.html.contains("Dear &lt;b&gt;kamau&lt;/b&gt;,"));
+    assert!(rendered.html.contains("<ul><li>Incomplete or unclear documentation</li>"));
+    assert!(rendered.html.contains("<ol><li>Review your submitted information and documentation</li>"));
+
+    let message = email::build_message(
+        "Farmers Market Place <noreply@example.com>".parse().unwrap(),
+        "kamau@example.com".parse().unwrap(),
+        &rendered,
+    )
+    .unwrap();
+    let formatted = String::from_utf8(message.formatted()).unwrap();
+
+    assert!(formatted.contains("Content-Type: multipart/alternative"));
+    let text_part = formatted.find("Content-Type: text/plain").expect("text part");
+    let html_part = formatted.find("Content-Type: text/html").expect("HTML part");
+    // Clients pick the last alternative they can show, so HTML goes after the text fallback
+    assert!(text_part < html_part);
+}
+
+#[actix_web::test]
+async fn profile_language_must_have_templates() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let user = common::create_user(&pool, "language_user", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    assert_eq!(email_templates::user_language(&pool, user.id).await, "en");
+
+    let set_language = |language: &str| {
+        TestRequest::patch()
+            .uri("/profile")
+            .insert_header(common::bearer(&user))
+            .set_json(json!({ "language": language }))
+            .to_request()
+    };
+    assert_eq!(call_service(&app, set_language("xx")).await.status(), 400);
+    assert_eq!(call_service(&app, set_language("sw")).await.status(), 200);
+
+    assert_eq!(email_templates::user_language(&pool, user.id).await, "sw");
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use actix_web::test;
+use serde_json::Value;
+
+#[actix_web::test]
+async fn unknown_paths_get_a_json_404() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get().uri("/no/such/route?x=1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Not found");
+    assert_eq!(body["path"], "/no/such/route");
+
+    // Real routes are untouched
+    let req = test::TestRequest::get().uri("/products").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+}
+
+#[actix_web::test]
+async fn wrong_methods_on_known_paths_get_a_json_405() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::put().uri("/products/featured").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 405);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Method not allowed");
+    assert_eq!(body["path"], "/products/featured");
+    assert_eq!(body["method"], "PUT");
+}
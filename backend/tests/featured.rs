@@ -0,0 +1,78 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn expired_feature_leaves_featured_list() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "feat_admin", Role::Admin).await;
+    let vendor = common::create_verified_vendor(&pool, "feat_vendor").await;
+    let other_vendor = common::create_verified_vendor(&pool, "feat_other").await;
+
+    let milk = db::create_product(&pool, "Milk", 60.0, "Dairy", "Fresh", 20, None, vendor.id).await.unwrap();
+    let eggs = db::create_product(&pool, "Eggs", 15.0, "Poultry", "Free range", 90, None, vendor.id).await.unwrap();
+    let honey = db::create_product(&pool, "Honey", 500.0, "Pantry", "Raw", 5, None, vendor.id).await.unwrap();
+
+    let app = common::init_app(&pool).await;
+
+    // Admin features honey indefinitely, vendor promotes eggs for a week
+    let req = test::TestRequest::patch()
+        .uri(&format!("/products/{}/featured", honey.id))
+        .insert_header(common::bearer(&admin))
+        .set_json(json!({ "featured": true }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    // A duration past a year is refused rather than overflowing the interval
+    let req = test::TestRequest::patch()
+        .uri(&format!("/products/{}/featured", milk.id))
+        .insert_header(common::bearer(&admin))
+        .set_json(json!({ "featured": true, "days": 3_000_000_000i64 }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+    let req = test::TestRequest::patch()
+        .uri(&format!("/products/{}/featured", eggs.id))
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "featured": true, "days": 7 }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    // Vendors can't promote someone else's product or skip the duration
+    let req = test::TestRequest::patch()
+        .uri(&format!("/products/{}/featured", milk.id))
+        .insert_header(common::bearer(&other_vendor))
+        .set_json(json!({ "featured": true, "days": 7 }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+    let req = test::TestRequest::patch()
+        .uri(&format!("/products/{}/featured", milk.id))
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "featured": true }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let req = test::TestRequest::get().uri("/products/featured").to_request();
+    let featured: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(featured.as_array().unwrap().len(), 2);
+
+    // The eggs promotion runs out
+    sqlx::query("UPDATE products SET featured_until = NOW() - INTERVAL '1 minute' WHERE id = $1")
+        .bind(eggs.id as i32)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let req = test::TestRequest::get().uri("/products/featured").to_request();
+    let featured: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let featured = featured.as_array().unwrap();
+    assert_eq!(featured.len(), 1);
+    assert_eq!(featured[0]["id"], honey.id);
+
+    // sort=featured boosts the active feature to the top of the listing
+    let req = test::TestRequest::get().uri("/products?sort=featured").to_request();
+    let products: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let ids: Vec<u64> = products.as_array().unwrap().iter().map(|p| p["id"].as_u64().unwrap()).collect();
+    assert_eq!(ids, vec![honey.id as u64, milk.id as u64, eggs.id as u64]);
+}
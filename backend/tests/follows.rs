@@ -0,0 +1,145 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn following_twice_returns_the_existing_follow() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "follow_vendor").await;
+    let customer = common::create_user(&pool, "follow_customer", Role::Customer).await;
+    let other = common::create_user(&pool, "follow_other", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    let follow = |vendor_id: i32| {
+        test::TestRequest::post()
+            .uri("/follow")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "vendor_id": vendor_id }))
+            .to_request()
+    };
+    let resp = test::call_service(&app, follow(vendor.id)).await;
+    assert_eq!(resp.status(), 201);
+    let first: Value = test::read_body_json(resp).await;
+
+    let resp = test::call_service(&app, follow(vendor.id)).await;
+    assert_eq!(resp.status(), 200);
+    let second: Value = test::read_body_json(resp).await;
+    assert_eq!(second["id"], first["id"]);
+    assert_eq!(second["vendor_username"], "follow_vendor");
+
+    // Only vendors can be followed
+    assert_eq!(test::call_service(&app, follow(other.id)).await.status(), 404);
+
+    let profile = db::get_vendor_profile(&pool, vendor.id).await.unwrap();
+    assert_eq!(profile.follower_count, 1);
+}
+
+#[actix_web::test]
+async fn unfollow_reports_whether_a_follow_was_removed() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "unfollow_vendor").await;
+    let customer = common::create_user(&pool, "unfollow_customer", Role::Customer).await;
+    let other = common::create_user(&pool, "unfollow_other", Role::Customer).await;
+    db::follow_vendor(&pool, customer.id, vendor.id).await.unwrap();
+    db::follow_vendor(&pool, other.id, vendor.id).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let unfollow = || {
+        test::TestRequest::delete()
+            .uri(&format!("/follow/{}", vendor.id))
+            .insert_header(common::bearer(&customer))
+            .to_request()
+    };
+    let resp = test::call_service(&app, unfollow()).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["unfollowed"], true);
+    assert_eq!(body["data"]["follower_count"], 1);
+
+    let resp = test::call_service(&app, unfollow()).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["unfollowed"], false);
+    assert_eq!(body["data"]["follower_count"], 1);
+}
+
+#[actix_web::test]
+async fn follower_count_survives_rapid_toggles() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "toggle_vendor").await;
+    let customer = common::create_user(&pool, "toggle_customer", Role::Customer).await;
+
+    let toggles = (0..20).map(|i| {
+        let pool = pool.clone();
+        let (customer_id, vendor_id) = (customer.id, vendor.id);
+        tokio::spawn(async move {
+            if i % 2 == 0 {
+                db::follow_vendor(&pool, customer_id, vendor_id).await.map(|_| ())
+            } else {
+                db::unfollow_vendor(&pool, customer_id, vendor_id).await.map(|_| ())
+            }
+        })
+    });
+    for toggle in futures_util::future::join_all(toggles).await {
+        toggle.unwrap().unwrap();
+    }
+
+    // Whatever order they ran in, there is at most one follow and the count agrees
+    let following = db::is_following(&pool, customer.id, vendor.id).await.unwrap();
+    let profile = db::get_vendor_profile(&pool, vendor.id).await.unwrap();
+    assert_eq!(profile.follower_count, following as i64);
+
+    let (_, created) = db::follow_vendor(&pool, customer.id, vendor.id).await.unwrap();
+    assert_eq!(created, !following);
+    assert_eq!(db::get_vendor_profile(&pool, vendor.id).await.unwrap().follower_count, 1);
+}
+
+#[actix_web::test]
+async fn user_profile_hides_contact_details_from_non_mutual_followers() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "profile_vendor").await;
+    let partner = common::create_verified_vendor(&pool, "profile_partner").await;
+    let customer = common::create_user(&pool, "profile_customer", Role::Customer).await;
+    let banned = common::create_user(&pool, "profile_banned", Role::Customer).await;
+    sqlx::query("UPDATE users SET secondary_email = 'farm@example.com', mpesa_number = '254712345678', location_string = 'Nakuru' WHERE id = $1")
+        .bind(vendor.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    db::ban_user(&pool, banned.id, true).await.unwrap();
+    db::follow_vendor(&pool, customer.id, vendor.id).await.unwrap();
+    db::follow_vendor(&pool, partner.id, vendor.id).await.unwrap();
+    db::follow_vendor(&pool, vendor.id, partner.id).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let profile = |viewer, user_id: i32| {
+        test::TestRequest::get()
+            .uri(&format!("/users/{}/profile", user_id))
+            .insert_header(common::bearer(viewer))
+            .to_request()
+    };
+
+    // A follower the vendor doesn't follow back sees counts but no contact details
+    let resp = test::call_service(&app, profile(&customer, vendor.id)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["username"], "profile_vendor");
+    assert_eq!(body["follower_count"], 2);
+    assert_eq!(body["following_count"], 1);
+    assert_eq!(body["is_followed"], true);
+    assert_eq!(body["is_mutual_friend"], false);
+    assert!(body["email"].is_null());
+    assert!(body["phone"].is_null());
+    assert!(body["location"].is_null());
+
+    let body: Value = test::call_and_read_body_json(&app, profile(&partner, vendor.id)).await;
+    assert_eq!(body["is_mutual_friend"], true);
+    assert_eq!(body["email"], "farm@example.com");
+    assert_eq!(body["phone"], "254712345678");
+
+    assert_eq!(test::call_service(&app, profile(&customer, banned.id)).await.status(), 404);
+    assert_eq!(test::call_service(&app, profile(&customer, 999_999)).await.status(), 404);
+}
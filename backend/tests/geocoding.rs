@@ -0,0 +1,80 @@
+mod common;
+
+use actix_web::{test, web, App, HttpResponse, HttpServer};
+use backend::geocoding::{self, Geocoder};
+use backend::models::Role;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Mock Nominatim `/reverse` endpoint that counts lookups.
+fn start_mock_geocoder() -> (String, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+
+    let server = HttpServer::new(move || {
+        let counter = counter.clone();
+        App::new().route("/reverse", web::get().to(move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                HttpResponse::Ok().json(json!({
+                    "display_name": "Nakuru, Nakuru County, Kenya",
+                    "address": { "city": "Nakuru", "county": "Nakuru County", "country": "Kenya" }
+                }))
+            }
+        }))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+
+    (format!("http://{}", addr), calls)
+}
+
+#[actix_web::test]
+async fn coordinates_are_reverse_geocoded_and_cached() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let (base_url, calls) = start_mock_geocoder();
+    std::env::set_var("GEOCODING_URL", &base_url);
+    let user = common::create_user(&pool, "geo_customer", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    let update = |latitude: f64, longitude: f64| {
+        test::TestRequest::post()
+            .uri("/location/update")
+            .insert_header(common::bearer(&user))
+            .set_json(json!({ "latitude": latitude, "longitude": longitude }))
+            .to_request()
+    };
+
+    let body: Value = test::call_and_read_body_json(&app, update(-0.3031, 36.0800)).await;
+    assert_eq!(body["data"]["location_string"], "Nakuru, Kenya");
+    let stored = backend::db::get_user_by_id(&pool, user.id).await.unwrap();
+    assert_eq!(stored.location_string.as_deref(), Some("Nakuru, Kenya"));
+
+    // A nearby point falls in the same cached cell
+    let body: Value = test::call_and_read_body_json(&app, update(-0.3029, 36.0802)).await;
+    assert_eq!(body["data"]["location_string"], "Nakuru, Kenya");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // An explicit string wins over the provider
+    let req = test::TestRequest::post()
+        .uri("/location/update")
+        .insert_header(common::bearer(&user))
+        .set_json(json!({ "latitude": -0.3031, "longitude": 36.0800, "location_string": "Lanet" }))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["location_string"], "Lanet");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[actix_web::test]
+async fn unreachable_provider_falls_back_to_coordinates_only() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    // Nothing listens on port 9 locally
+    let geocoder = Geocoder::new("http://127.0.0.1:9");
+    assert_eq!(geocoding::reverse_geocode(&pool, &geocoder, 1.2921, 36.8219).await, None);
+}
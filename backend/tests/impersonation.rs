@@ -0,0 +1,67 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn impersonated_actions_are_attributed_to_the_admin() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "support_admin", Role::Admin).await;
+    let other_admin = common::create_user(&pool, "other_admin", Role::Admin).await;
+    let customer = common::create_user(&pool, "confused_customer", Role::Customer).await;
+    let vendor = common::create_verified_vendor(&pool, "imp_vendor").await;
+    let tomatoes = db::create_product(&pool, "Tomatoes", 50.0, "Vegetables", "Ripe", 20, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/admin/users/{}/impersonate", other_admin.id))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/admin/users/{}/impersonate", customer.id))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let session: Value = test::read_body_json(resp).await;
+    let token = format!("Bearer {}", session["data"]["token"].as_str().unwrap());
+
+    // Act as the customer
+    let req = test::TestRequest::post()
+        .uri("/cart")
+        .insert_header(("Authorization", token.as_str()))
+        .set_json(json!({ "product_id": tomatoes.id, "quantity": 2 }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+    assert_eq!(db::get_cart_items(&pool, customer.id).await.unwrap().len(), 1);
+
+    let entries = db::get_audit_log(&pool, Some(customer.id), None, 10).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, "POST /cart");
+    assert_eq!(entries[0].impersonated_by, Some(admin.id));
+
+    // Revoked tokens stop working immediately
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/admin/impersonations/{}", session["data"]["session_id"].as_str().unwrap()))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let req = test::TestRequest::get()
+        .uri("/cart")
+        .insert_header(("Authorization", token.as_str()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    // The customer's own requests aren't audited as impersonated
+    let req = test::TestRequest::get().uri("/cart").insert_header(common::bearer(&customer)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let impersonated = db::get_audit_log(&pool, None, Some(admin.id), 10).await.unwrap();
+    assert_eq!(impersonated.len(), 1);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn invoice_is_a_pdf_for_order_parties_only() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "invoice_vendor").await;
+    let customer = common::create_user(&pool, "invoice_customer", Role::Customer).await;
+    let stranger = common::create_user(&pool, "invoice_stranger", Role::Customer).await;
+    let admin = common::create_user(&pool, "invoice_admin", Role::Admin).await;
+    let mangoes = db::create_product(&pool, "Mangoes", 25.0, "Fruits", "Apple mangoes", 100, None, vendor.id)
+        .await
+        .unwrap();
+    db::add_to_cart(&pool, customer.id, mangoes.id as i32, 4).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/checkout")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "mpesa_number": "0712345678", "total_amount": 100.0 }))
+        .to_request();
+    let checkout: Value = test::call_and_read_body_json(&app, req).await;
+    let orders = db::get_customer_shipping_orders(&pool, customer.id).await.unwrap();
+    let uri = format!("/orders/{}/invoice.pdf", orders[0].id);
+
+    for user in [&customer, &vendor, &admin] {
+        let req = test::TestRequest::get().uri(&uri).insert_header(common::bearer(user)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/pdf");
+        let disposition = resp.headers().get("content-disposition").unwrap().to_str().unwrap().to_string();
+        assert!(disposition.contains(&format!("invoice-{}.pdf", orders[0].id)));
+
+        let body = test::read_body(resp).await;
+        assert!(body.starts_with(b"%PDF-"));
+        let text = String::from_utf8_lossy(&body);
+        assert!(text.contains("Mangoes"));
+        assert!(text.contains(checkout["transaction_id"].as_str().unwrap()));
+    }
+
+    let req = test::TestRequest::get().uri(&uri).insert_header(common::bearer(&stranger)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::get()
+        .uri("/orders/999999/invoice.pdf")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
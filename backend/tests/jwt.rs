@@ -0,0 +1,48 @@
+use backend::jwt::JwtKeys;
+use backend::models::Claims;
+
+const PRIVATE_KEY: &[u8] = include_bytes!("fixtures/jwt_rs256_private.pem");
+const PUBLIC_KEY: &[u8] = include_bytes!("fixtures/jwt_rs256_public.pem");
+/// Public half of an unrelated key pair
+const OTHER_PUBLIC_KEY: &[u8] = include_bytes!("fixtures/jwt_rs256_other_public.pem");
+
+fn claims() -> Claims {
+    Claims {
+        sub: 7,
+        username: "rs256_user".to_string(),
+        role: "Admin".to_string(),
+        exp: (chrono::Utc::now() + chrono::Duration::hours(1)).timestamp() as usize,
+        impersonated_by: None,
+        jti: None,
+    }
+}
+
+#[test]
+fn rs256_tokens_verify_only_with_the_matching_public_key() {
+    let signer = JwtKeys::rs256(Some(PRIVATE_KEY), PUBLIC_KEY).unwrap();
+    let token = signer.encode(&claims()).unwrap();
+
+    // A verify-only deployment holds just the public key
+    let verifier = JwtKeys::rs256(None, PUBLIC_KEY).unwrap();
+    let decoded = verifier.decode(&token).unwrap();
+    assert_eq!(decoded.sub, 7);
+    assert_eq!(decoded.role, "Admin");
+    assert!(verifier.encode(&claims()).is_err());
+
+    let wrong = JwtKeys::rs256(None, OTHER_PUBLIC_KEY).unwrap();
+    assert!(wrong.decode(&token).is_err());
+}
+
+#[test]
+fn algorithms_are_not_interchangeable() {
+    let rs256 = JwtKeys::rs256(Some(PRIVATE_KEY), PUBLIC_KEY).unwrap();
+    let hs256 = JwtKeys::hs256(b"your-secret-key");
+
+    let hs_token = hs256.encode(&claims()).unwrap();
+    assert_eq!(hs256.decode(&hs_token).unwrap().username, "rs256_user");
+    assert!(rs256.decode(&hs_token).is_err());
+    // Nor can the public key be used as an HMAC secret to forge tokens
+    let forged = JwtKeys::hs256(PUBLIC_KEY).encode(&claims()).unwrap();
+    assert!(rs256.decode(&forged).is_err());
+    assert!(hs256.decode(&rs256.encode(&claims()).unwrap()).is_err());
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::{Paginated, Role, VendorLeaderboardEntry};
+
+#[actix_web::test]
+async fn highest_windowed_revenue_ranks_first() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let big = common::create_verified_vendor(&pool, "board_big").await;
+    let busy = common::create_verified_vendor(&pool, "board_busy").await;
+    let past = common::create_verified_vendor(&pool, "board_past").await;
+    let banned = common::create_verified_vendor(&pool, "board_banned").await;
+    let customer = common::create_user(&pool, "board_customer", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    let product = |vendor_id: i32, price: f64| {
+        let pool = pool.clone();
+        async move {
+            db::create_product(&pool, "Produce", price, "Vegetables", "Fresh", 100, None, vendor_id)
+                .await
+                .unwrap()
+                .id as i32
+        }
+    };
+    let (big_product, busy_product) = (product(big.id, 1000.0).await, product(busy.id, 50.0).await);
+    let (past_product, banned_product) = (product(past.id, 5000.0).await, product(banned.id, 9000.0).await);
+
+    db::create_shipping_order(&pool, customer.id, big_product, 1, "Nakuru").await.unwrap();
+    for _ in 0..3 {
+        db::create_shipping_order(&pool, customer.id, busy_product, 1, "Nakuru").await.unwrap();
+    }
+    db::create_review(&pool, customer.id, busy_product, 5, None).await.unwrap();
+    // Outside the default 30-day window
+    let old = db::create_shipping_order(&pool, customer.id, past_product, 1, "Nakuru").await.unwrap();
+    sqlx::query("UPDATE shipping_orders SET created_at = NOW() - INTERVAL '60 days' WHERE id = $1")
+        .bind(old.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    db::create_shipping_order(&pool, customer.id, banned_product, 1, "Nakuru").await.unwrap();
+    sqlx::query("UPDATE users SET banned = TRUE WHERE id = $1")
+        .bind(banned.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let board = |query: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/vendors/leaderboard{}", query))
+            .to_request()
+    };
+    let page: Paginated<VendorLeaderboardEntry> = test::call_and_read_body_json(&app, board("")).await;
+    let ranked: Vec<i32> = page.items.iter().map(|entry| entry.vendor_id).collect();
+    assert_eq!(ranked, vec![big.id, busy.id]);
+    assert_eq!((page.items[0].rank, page.items[0].revenue), (1, 1000.0));
+    assert_eq!((page.items[1].orders, page.items[1].average_rating), (3, Some(5.0)));
+
+    let by_orders: Paginated<VendorLeaderboardEntry> = test::call_and_read_body_json(&app, board("?sort=orders")).await;
+    assert_eq!(by_orders.items[0].vendor_id, busy.id);
+
+    // A longer window takes in the older sale, and pages keep the overall rank
+    let second: Paginated<VendorLeaderboardEntry> =
+        test::call_and_read_body_json(&app, board("?days=90&limit=1&offset=1")).await;
+    assert_eq!((second.total, second.has_more), (3, true));
+    assert_eq!((second.items[0].vendor_id, second.items[0].rank), (big.id, 2));
+
+    assert_eq!(test::call_service(&app, board("?sort=loudest")).await.status(), 400);
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use actix_web::test;
+use backend::models::Role;
+use backend::settings;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn maintenance_mode_blocks_everything_but_health_and_admins() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "maintenance_admin", Role::Admin).await;
+    common::create_user(&pool, "maintenance_customer", Role::Customer).await;
+    settings::set_setting(&pool, settings::MAINTENANCE_MODE, "true").await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/products").to_request()).await;
+    assert_eq!(resp.status(), 503);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["maintenance"], true);
+    assert_eq!(body["message"], "The marketplace is down for maintenance. Please try again shortly.");
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/health").to_request()).await;
+    assert_eq!(resp.status(), 200);
+
+    // Only admins may sign in
+    let login = |username: &str| {
+        test::TestRequest::post()
+            .uri("/login")
+            .set_json(json!({ "username": username, "password": "password123" }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, login("maintenance_customer")).await.status(), 503);
+    assert_eq!(test::call_service(&app, login("maintenance_admin")).await.status(), 200);
+
+    let req = test::TestRequest::patch()
+        .uri("/api/admin/settings")
+        .insert_header(common::bearer(&admin))
+        .set_json(json!({ "maintenance_mode": false }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/products").to_request()).await;
+    assert_eq!(resp.status(), 200);
+}
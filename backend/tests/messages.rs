@@ -0,0 +1,272 @@
+mod common;
+
+use actix_web::test;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+/// 1x1 transparent PNG
+const PIXEL_PNG: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+
+#[actix_web::test]
+async fn message_attachment_round_trips() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let customer = common::create_user(&pool, "attach_customer", Role::Customer).await;
+    let vendor = common::create_verified_vendor(&pool, "attach_vendor").await;
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/messages")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "receiver_id": vendor.id, "content": "The crate arrived cracked", "attachment": PIXEL_PNG }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let sent: Value = test::read_body_json(resp).await;
+    assert_eq!(sent["attachment"], PIXEL_PNG);
+
+    // Text-only messages are unchanged
+    let req = test::TestRequest::post()
+        .uri("/messages")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "receiver_id": customer.id, "content": "Sorry, sending a replacement" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/messages/{}", customer.id))
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    let history: Value = test::call_and_read_body_json(&app, req).await;
+    let history = history.as_array().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0]["attachment"], PIXEL_PNG);
+    assert_eq!(history[0]["content"], "The crate arrived cracked");
+    assert!(history[1]["attachment"].is_null());
+
+    // Non-images and oversized images are rejected
+    let oversized = format!("data:image/png;base64,{}", "A".repeat(1_500_000));
+    for attachment in ["data:text/plain;base64,aGVsbG8=", "data:image/png;base64,not base64!", oversized.as_str()] {
+        let req = test::TestRequest::post()
+            .uri("/messages")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "receiver_id": vendor.id, "content": "photo", "attachment": attachment }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+}
+
+#[actix_web::test]
+async fn blocked_users_cannot_message_or_see_each_other() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let customer = common::create_user(&pool, "block_customer", Role::Customer).await;
+    let vendor = common::create_verified_vendor(&pool, "block_vendor").await;
+    let bystander = common::create_user(&pool, "block_bystander", Role::Customer).await;
+    let admin = common::create_user(&pool, "block_admin", Role::Admin).await;
+    backend::db::follow_vendor(&pool, customer.id, vendor.id).await.unwrap();
+    backend::db::send_message(&pool, vendor.id, customer.id, "Buy more!").await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/block/{}", vendor.id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert!(!backend::db::is_following(&pool, customer.id, vendor.id).await.unwrap());
+
+    // Neither side can message the other
+    for (sender, receiver) in [(&vendor, &customer), (&customer, &vendor)] {
+        let req = test::TestRequest::post()
+            .uri("/messages")
+            .insert_header(common::bearer(sender))
+            .set_json(json!({ "receiver_id": receiver.id, "content": "hello?" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 403);
+    }
+
+    // ...and they drop out of each other's contact list and conversations
+    let contact_ids = |body: Value| -> Vec<i64> {
+        body.as_array().unwrap().iter().map(|u| u["id"].as_i64().unwrap()).collect()
+    };
+    let req = test::TestRequest::get().uri("/users").insert_header(common::bearer(&customer)).to_request();
+    let contacts = contact_ids(test::call_and_read_body_json(&app, req).await);
+    assert!(!contacts.contains(&(vendor.id as i64)));
+    assert!(contacts.contains(&(bystander.id as i64)));
+
+    let req = test::TestRequest::get().uri("/users").insert_header(common::bearer(&vendor)).to_request();
+    let contacts = contact_ids(test::call_and_read_body_json(&app, req).await);
+    assert!(!contacts.contains(&(customer.id as i64)));
+
+    let conversations = backend::db::get_user_conversations(&pool, vendor.id).await.unwrap();
+    assert!(conversations.is_empty());
+
+    // Admins are exempt
+    let req = test::TestRequest::post()
+        .uri(&format!("/block/{}", admin.id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    // Unblocking restores messaging
+    let req = test::TestRequest::delete()
+        .uri(&format!("/block/{}", vendor.id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let req = test::TestRequest::post()
+        .uri("/messages")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "receiver_id": customer.id, "content": "Thanks for unblocking" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+}
+
+#[actix_web::test]
+async fn archived_conversation_returns_on_new_message() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let alice = common::create_user(&pool, "archive_alice", Role::Customer).await;
+    let bob = common::create_user(&pool, "archive_bob", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    let send = |from: &backend::models::User, to: &backend::models::User, content: &str| {
+        test::TestRequest::post()
+            .uri("/messages")
+            .insert_header(common::bearer(from))
+            .set_json(json!({ "receiver_id": to.id, "content": content }))
+            .to_request()
+    };
+    let conversations = |user: &backend::models::User| {
+        test::TestRequest::get()
+            .uri("/messages")
+            .insert_header(common::bearer(user))
+            .to_request()
+    };
+
+    assert_eq!(test::call_service(&app, send(&bob, &alice, "Do you have eggs?")).await.status(), 201);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/messages/conversations/{}", bob.id))
+        .insert_header(common::bearer(&alice))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let list: Value = test::call_and_read_body_json(&app, conversations(&alice)).await;
+    assert!(list.as_array().unwrap().is_empty());
+    // Bob still sees it
+    let list: Value = test::call_and_read_body_json(&app, conversations(&bob)).await;
+    assert_eq!(list.as_array().unwrap().len(), 1);
+
+    assert_eq!(test::call_service(&app, send(&bob, &alice, "Hello again")).await.status(), 201);
+    let list: Value = test::call_and_read_body_json(&app, conversations(&alice)).await;
+    assert_eq!(list.as_array().unwrap().len(), 1);
+    assert_eq!(list[0]["last_message"], "Hello again");
+
+    // Nothing to archive with someone you've never messaged
+    let carol = common::create_user(&pool, "archive_carol", Role::Customer).await;
+    let req = test::TestRequest::delete()
+        .uri(&format!("/messages/conversations/{}", carol.id))
+        .insert_header(common::bearer(&alice))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn conversations_report_last_message_and_unread_count() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let alice = common::create_user(&pool, "unread_alice", Role::Customer).await;
+    let bob = common::create_user(&pool, "unread_bob", Role::Customer).await;
+    let carol = common::create_user(&pool, "unread_carol", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    let send = |from: &backend::models::User, to: &backend::models::User, content: &str| {
+        test::TestRequest::post()
+            .uri("/messages")
+            .insert_header(common::bearer(from))
+            .set_json(json!({ "receiver_id": to.id, "content": content }))
+            .to_request()
+    };
+    let conversations = || {
+        test::TestRequest::get()
+            .uri("/messages")
+            .insert_header(common::bearer(&alice))
+            .to_request()
+    };
+
+    assert_eq!(test::call_service(&app, send(&bob, &alice, "Are the mangoes ripe?")).await.status(), 201);
+    assert_eq!(test::call_service(&app, send(&bob, &alice, "Need 5kg")).await.status(), 201);
+    assert_eq!(test::call_service(&app, send(&alice, &carol, "Hi Carol")).await.status(), 201);
+    assert_eq!(test::call_service(&app, send(&carol, &alice, "Hi!")).await.status(), 201);
+
+    let list: Value = test::call_and_read_body_json(&app, conversations()).await;
+    let list = list.as_array().unwrap();
+    assert_eq!(list.len(), 2);
+    // Most recent activity first
+    assert_eq!(list[0]["id"], carol.id);
+    assert_eq!(list[0]["username"], "unread_carol");
+    assert_eq!(list[0]["last_message"], "Hi!");
+    assert_eq!(list[0]["unread_count"], 1);
+    assert!(list[0]["last_message_time"].is_string());
+    assert_eq!(list[1]["id"], bob.id);
+    assert_eq!(list[1]["last_message"], "Need 5kg");
+    assert_eq!(list[1]["unread_count"], 2);
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/messages/{}/read", bob.id))
+        .insert_header(common::bearer(&alice))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let list: Value = test::call_and_read_body_json(&app, conversations()).await;
+    assert_eq!(list[1]["unread_count"], 0);
+    assert_eq!(list[0]["unread_count"], 1);
+}
+
+#[actix_web::test]
+async fn buyers_only_vendor_refuses_non_purchasers() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "buyers_vendor").await;
+    let buyer = common::create_user(&pool, "buyers_buyer", Role::Customer).await;
+    let stranger = common::create_user(&pool, "buyers_stranger", Role::Customer).await;
+    // Only vendors can be followed, so a mutual follower is another vendor
+    let friend = common::create_verified_vendor(&pool, "buyers_friend").await;
+    let admin = common::create_user(&pool, "buyers_admin", Role::Admin).await;
+    let product = backend::db::create_product(&pool, "Eggs", 15.0, "Poultry", "Tray of eggs", 30, None, vendor.id)
+        .await
+        .unwrap();
+    backend::db::create_shipping_order(&pool, buyer.id, product.id as i32, 1, "Kisumu").await.unwrap();
+    backend::db::follow_vendor(&pool, friend.id, vendor.id).await.unwrap();
+    backend::db::follow_vendor(&pool, vendor.id, friend.id).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let set_buyers_only = |enabled: bool| {
+        test::TestRequest::patch()
+            .uri("/profile")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "buyers_only_messages": enabled }))
+            .to_request()
+    };
+    let message = |sender| {
+        test::TestRequest::post()
+            .uri("/messages")
+            .insert_header(common::bearer(sender))
+            .set_json(json!({ "receiver_id": vendor.id, "content": "Do you deliver to Kisumu?" }))
+            .to_request()
+    };
+
+    assert_eq!(test::call_service(&app, set_buyers_only(true)).await.status(), 200);
+    assert_eq!(test::call_service(&app, message(&stranger)).await.status(), 403);
+    assert_eq!(test::call_service(&app, message(&buyer)).await.status(), 201);
+    // Mutual followers and admins get through too
+    assert_eq!(test::call_service(&app, message(&friend)).await.status(), 201);
+    assert_eq!(test::call_service(&app, message(&admin)).await.status(), 201);
+
+    assert_eq!(test::call_service(&app, set_buyers_only(false)).await.status(), 200);
+    assert_eq!(test::call_service(&app, message(&stranger)).await.status(), 201);
+
+    // Only vendors have the setting
+    let req = test::TestRequest::patch()
+        .uri("/profile")
+        .insert_header(common::bearer(&buyer))
+        .set_json(json!({ "buyers_only_messages": true }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use actix_web::{test, web, App, HttpResponse, HttpServer};
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+/// Mock Gemini models endpoint that flags any listing mentioning a rifle.
+fn start_mock_classifier() -> String {
+    let server = HttpServer::new(|| {
+        App::new().route("/{model}", web::post().to(|body: String| async move {
+            let verdict = if body.to_lowercase().contains("rifle") { "FLAG: weapons" } else { "ALLOW" };
+            HttpResponse::Ok().json(json!({ "candidates": [{ "content": { "parts": [{ "text": verdict }] } }] }))
+        }))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    format!("http://{}", addr)
+}
+
+#[actix_web::test]
+async fn flagged_listings_wait_for_admin_review() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    std::env::set_var("GEMINI_API_KEY", "test-key");
+    std::env::set_var("GEMINI_API_URL", start_mock_classifier());
+    let vendor = common::create_verified_vendor(&pool, "mod_vendor").await;
+    let admin = common::create_user(&pool, "mod_admin", Role::Admin).await;
+    let app = common::init_app(&pool).await;
+
+    let create = |name: &str, description: &str| {
+        test::TestRequest::post()
+            .uri("/products")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({
+                "name": name, "price": 100.0, "category": "Tools",
+                "description": description, "quantity": 3
+            }))
+            .to_request()
+    };
+    let public_ids = |body: Value| -> Vec<i64> {
+        body.as_array().unwrap().iter().map(|p| p["id"].as_i64().unwrap()).collect()
+    };
+
+    let resp = test::call_service(&app, create("Hand hoe", "Forged steel hoe")).await;
+    assert_eq!(resp.status(), 201);
+    let hoe: Value = test::read_body_json(resp).await;
+    assert_eq!(hoe["under_review"], false);
+
+    let resp = test::call_service(&app, create("Hunting rifle", "Barely used")).await;
+    assert_eq!(resp.status(), 201);
+    let rifle: Value = test::read_body_json(resp).await;
+    assert_eq!(rifle["under_review"], true);
+    assert_eq!(rifle["review_reason"], "weapons");
+    let rifle_id = rifle["id"].as_i64().unwrap();
+
+    let products: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/products").to_request()).await;
+    let ids = public_ids(products);
+    assert!(ids.contains(&hoe["id"].as_i64().unwrap()));
+    assert!(!ids.contains(&rifle_id));
+    let resp = test::call_service(&app, test::TestRequest::get().uri(&format!("/products/{}", rifle_id)).to_request()).await;
+    assert_eq!(resp.status(), 404);
+
+    // Editing a listing to mention a flagged term holds it too
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::patch()
+            .uri(&format!("/products/{}", hoe["id"]))
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({
+                "name": "Hand hoe", "price": 100.0, "category": "Tools",
+                "description": "Comes with a free air rifle", "quantity": 3
+            }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let edited: Value = test::read_body_json(resp).await;
+    assert_eq!(edited["under_review"], true);
+
+    let flagged_req = |user| {
+        test::TestRequest::get().uri("/api/admin/products/flagged").insert_header(common::bearer(user)).to_request()
+    };
+    assert_eq!(test::call_service(&app, flagged_req(&vendor)).await.status(), 403);
+    let flagged: Value = test::call_and_read_body_json(&app, flagged_req(&admin)).await;
+    let flagged = flagged.as_array().unwrap();
+    assert_eq!(flagged.len(), 2);
+    assert_eq!(flagged[0]["id"], rifle_id);
+    assert_eq!(flagged[0]["flag_reason"], "weapons");
+    assert_eq!(flagged[0]["vendor_username"], "mod_vendor");
+
+    let approve = |id: i64| {
+        test::TestRequest::post()
+            .uri(&format!("/api/admin/products/{}/approve", id))
+            .insert_header(common::bearer(&admin))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, approve(rifle_id)).await.status(), 200);
+    assert_eq!(test::call_service(&app, approve(rifle_id)).await.status(), 404);
+    let products: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/products").to_request()).await;
+    assert!(public_ids(products).contains(&rifle_id));
+    let entries = db::get_audit_log(&pool, Some(admin.id), None, 10).await.unwrap();
+    assert!(entries.iter().any(|entry| entry.action == format!("moderation.product_approve product {}", rifle_id)));
+
+    // The classifier being unreachable lets listings through
+    std::env::set_var("GEMINI_API_URL", "http://127.0.0.1:9");
+    let resp = test::call_service(&app, create("Air rifle pellets", "Tin of 500")).await;
+    assert_eq!(resp.status(), 201);
+    let pellets: Value = test::read_body_json(resp).await;
+    assert_eq!(pellets["under_review"], false);
+    let products: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/products").to_request()).await;
+    assert!(public_ids(products).contains(&pellets["id"].as_i64().unwrap()));
+}
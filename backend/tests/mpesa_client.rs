@@ -0,0 +1,210 @@
+use actix_web::{web, App, HttpResponse, HttpServer};
+use backend::mpesa::{MpesaClient, MpesaConfig, MpesaEnvironment, RetryPolicy, TransactionType};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Mock Daraja API whose OAuth and STK endpoints each fail `failures` times with 503.
+fn start_mock_daraja(failures: usize) -> (String, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+    let token_calls = Arc::new(AtomicUsize::new(0));
+    let stk_calls = Arc::new(AtomicUsize::new(0));
+    let (tc, sc) = (token_calls.clone(), stk_calls.clone());
+
+    let server = HttpServer::new(move || {
+        let (tc, sc) = (tc.clone(), sc.clone());
+        App::new()
+            .route("/oauth/v1/generate", web::get().to(move || {
+                let tc = tc.clone();
+                async move {
+                    if tc.fetch_add(1, Ordering::SeqCst) < failures {
+                        return HttpResponse::ServiceUnavailable().insert_header(("Retry-After", "0")).finish();
+                    }
+                    HttpResponse::Ok().json(json!({ "access_token": "mock-token", "expires_in": "3599" }))
+                }
+            }))
+            .route("/mpesa/stkpush/v1/processrequest", web::post().to(move || {
+                let sc = sc.clone();
+                async move {
+                    if sc.fetch_add(1, Ordering::SeqCst) < failures {
+                        return HttpResponse::BadGateway().finish();
+                    }
+                    HttpResponse::Ok().json(json!({
+                        "MerchantRequestID": "29115-34620561-1",
+                        "CheckoutRequestID": "ws_CO_191220191020363925",
+                        "ResponseCode": "0",
+                        "ResponseDescription": "Success. Request accepted for processing",
+                        "CustomerMessage": "Success. Request accepted for processing"
+                    }))
+                }
+            }))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+
+    (format!("http://{}", addr), token_calls, stk_calls)
+}
+
+fn mock_config(base_url: String, retry: RetryPolicy) -> MpesaConfig {
+    MpesaConfig {
+        consumer_key: "key".to_string(),
+        consumer_secret: "secret".to_string(),
+        shortcode: "174379".to_string(),
+        passkey: "passkey".to_string(),
+        callback_url: "https://example.com/mpesa/callback".to_string(),
+        environment: MpesaEnvironment::Sandbox,
+        transaction_type: TransactionType::PayBill,
+        till_number: None,
+        base_url_override: Some(base_url),
+        retry,
+    }
+}
+
+fn mock_client(base_url: String, retry: RetryPolicy) -> MpesaClient {
+    MpesaClient::new(mock_config(base_url, retry))
+}
+
+/// Mock Daraja API that records the body of every STK push it receives.
+fn start_recording_daraja() -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+
+    let server = HttpServer::new(move || {
+        let recorded = recorded.clone();
+        App::new()
+            .route("/oauth/v1/generate", web::get().to(|| async {
+                HttpResponse::Ok().json(json!({ "access_token": "mock-token", "expires_in": "3599" }))
+            }))
+            .route("/mpesa/stkpush/v1/processrequest", web::post().to(move |body: web::Json<serde_json::Value>| {
+                let recorded = recorded.clone();
+                async move {
+                    recorded.lock().unwrap().push(body.into_inner());
+                    HttpResponse::Ok().json(json!({
+                        "MerchantRequestID": "29115-34620561-1",
+                        "CheckoutRequestID": "ws_CO_191220191020363925",
+                        "ResponseCode": "0",
+                        "ResponseDescription": "Success. Request accepted for processing",
+                        "CustomerMessage": "Success. Request accepted for processing"
+                    }))
+                }
+            }))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+
+    (format!("http://{}", addr), requests)
+}
+
+#[actix_web::test]
+async fn token_requests_are_retried_after_transient_failures() {
+    let (base_url, token_calls, stk_calls) = start_mock_daraja(2);
+    let client = mock_client(base_url, RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(50),
+        max_total: Duration::from_secs(5),
+    });
+
+    assert_eq!(client.get_access_token().await.expect("token on the third attempt"), "mock-token");
+    assert_eq!(token_calls.load(Ordering::SeqCst), 3);
+    assert_eq!(stk_calls.load(Ordering::SeqCst), 0);
+}
+
+#[actix_web::test]
+async fn stk_push_is_not_resent_after_a_server_error() {
+    // Daraja may already have prompted the customer, so a 5xx isn't retried
+    let (base_url, _, stk_calls) = start_mock_daraja(1);
+    let client = mock_client(base_url, RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(50),
+        max_total: Duration::from_secs(5),
+    });
+
+    assert!(client
+        .stk_push("0712345678".to_string(), 10.0, "FM_1".to_string(), "Test".to_string())
+        .await
+        .is_err());
+    assert_eq!(stk_calls.load(Ordering::SeqCst), 1);
+}
+
+#[actix_web::test]
+async fn retries_stop_at_max_attempts() {
+    let (base_url, token_calls, _) = start_mock_daraja(5);
+    let client = mock_client(base_url, RetryPolicy {
+        max_attempts: 2,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(50),
+        max_total: Duration::from_secs(5),
+    });
+
+    assert!(client.get_access_token().await.is_err());
+    assert_eq!(token_calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn safaricom_codes_map_to_error_variants() {
+    use backend::mpesa::MpesaError;
+
+    assert!(matches!(MpesaError::from_api("1", "The balance is insufficient for the transaction."), MpesaError::InsufficientFunds));
+    assert!(matches!(MpesaError::from_api("1037", "DS timeout user cannot be reached"), MpesaError::Timeout));
+    assert!(matches!(MpesaError::from_api("500.001.1001", "Unable to lock subscriber, a transaction is already in process for the current subscriber"), MpesaError::Duplicate));
+    assert!(matches!(MpesaError::from_api("404.001.03", "Invalid Access Token"), MpesaError::Auth(_)));
+    assert!(matches!(MpesaError::from_api("400.002.02", "Bad Request - Invalid PhoneNumber"), MpesaError::InvalidPhone));
+    assert!(matches!(
+        MpesaError::from_api("400.002.02", "Bad Request - Invalid Amount"),
+        MpesaError::Api { ref code, .. } if code == "400.002.02"
+    ));
+    assert!(!MpesaError::Duplicate.is_retryable());
+    assert!(MpesaError::Timeout.is_retryable());
+}
+
+#[actix_web::test]
+async fn stk_push_sends_the_configured_transaction_type() {
+    let (base_url, requests) = start_recording_daraja();
+    let paybill = mock_client(base_url.clone(), RetryPolicy::default());
+    let till = MpesaClient::new(MpesaConfig {
+        transaction_type: TransactionType::BuyGoods,
+        till_number: Some("5566778".to_string()),
+        ..mock_config(base_url, RetryPolicy::default())
+    });
+
+    for client in [&paybill, &till] {
+        client
+            .stk_push("0712345678".to_string(), 10.0, "FM_1".to_string(), "Test".to_string())
+            .await
+            .expect("STK push should succeed");
+    }
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests[0]["TransactionType"], "CustomerPayBillOnline");
+    assert_eq!(requests[0]["PartyB"], "174379");
+    assert_eq!(requests[1]["TransactionType"], "CustomerBuyGoodsOnline");
+    assert_eq!(requests[1]["BusinessShortCode"], "174379");
+    assert_eq!(requests[1]["PartyB"], "5566778");
+}
+
+#[test]
+fn transaction_type_must_match_the_configured_shortcodes() {
+    let config = |transaction_type, till_number: Option<&str>| MpesaConfig {
+        transaction_type,
+        till_number: till_number.map(str::to_string),
+        ..mock_config("http://127.0.0.1".to_string(), RetryPolicy::default())
+    };
+
+    assert_eq!(TransactionType::parse("Till"), Some(TransactionType::BuyGoods));
+    assert_eq!(TransactionType::parse("CustomerPayBillOnline"), Some(TransactionType::PayBill));
+    assert_eq!(TransactionType::parse("cash"), None);
+
+    assert!(config(TransactionType::PayBill, None).validate().is_ok());
+    assert!(config(TransactionType::BuyGoods, Some("5566778")).validate().is_ok());
+    assert!(config(TransactionType::BuyGoods, None).validate().is_err());
+    assert!(config(TransactionType::BuyGoods, Some("till-1")).validate().is_err());
+    assert!(config(TransactionType::PayBill, Some("5566778")).validate().is_err());
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::json;
+
+#[actix_web::test]
+async fn duplicate_mpesa_number_is_rejected() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let app = common::init_app(&pool).await;
+
+    for (username, expected) in [("phone_one", 201), ("phone_two", 409)] {
+        let req = test::TestRequest::post()
+            .uri("/signup")
+            .set_json(json!({
+                "username": username,
+                "email": format!("{}@example.com", username),
+                "password": "Secret#123",
+                "mpesa_number": "0711000111"
+            }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), expected);
+    }
+
+    // Taking someone else's number through a profile update is refused too
+    let other = common::create_user(&pool, "phone_three", Role::Customer).await;
+    let req = test::TestRequest::patch()
+        .uri("/profile")
+        .insert_header(common::bearer(&other))
+        .set_json(json!({ "mpesa_number": "0711000111" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 409);
+}
+
+#[actix_web::test]
+async fn withdraw_is_blocked_until_number_verified() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "otp_vendor").await;
+    sqlx::query("UPDATE users SET wallet_balance = 500.0, mpesa_number = '0722000222' WHERE id = $1")
+        .bind(vendor.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let withdraw = || {
+        test::TestRequest::post()
+            .uri("/wallet/withdraw")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "amount": 100.0, "mpesa_number": "0722000222" }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, withdraw()).await.status(), 403);
+
+    let req = test::TestRequest::post()
+        .uri("/profile/mpesa/verify")
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let code: String = sqlx::query_scalar("SELECT verification_code FROM phone_verification_codes WHERE user_id = $1 AND used = FALSE")
+        .bind(vendor.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/profile/mpesa/verify/confirm")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "code": code }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    assert_eq!(test::call_service(&app, withdraw()).await.status(), 200);
+    assert_eq!(db::get_wallet_balance(&pool, vendor.id).await.unwrap(), 400.0);
+
+    // Changing the number drops verification again
+    db::update_user_profile(&pool, vendor.id, None, None, None, Some("0733000333"), None)
+        .await
+        .unwrap();
+    assert!(!db::get_mpesa_verification(&pool, vendor.id).await.unwrap().1);
+}
+
+#[actix_web::test]
+async fn verification_code_stops_working_after_repeated_wrong_guesses() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "otp_guesser").await;
+    sqlx::query("UPDATE users SET mpesa_number = '0744000444' WHERE id = $1")
+        .bind(vendor.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/profile/mpesa/verify")
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let code: String = sqlx::query_scalar("SELECT verification_code FROM phone_verification_codes WHERE user_id = $1 AND used = FALSE")
+        .bind(vendor.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+
+    let confirm = |guess: &str| {
+        test::TestRequest::post()
+            .uri("/profile/mpesa/verify/confirm")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "code": guess }))
+            .to_request()
+    };
+    for _ in 0..db::PHONE_CODE_MAX_ATTEMPTS {
+        assert_eq!(test::call_service(&app, confirm(wrong)).await.status(), 400);
+    }
+
+    // The right code no longer works once the guesses are used up
+    assert_eq!(test::call_service(&app, confirm(&code)).await.status(), 400);
+    assert!(!db::get_mpesa_verification(&pool, vendor.id).await.unwrap().1);
+}
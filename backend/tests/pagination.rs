@@ -0,0 +1,70 @@
+mod common;
+
+use actix_web::test::{call_and_read_body_json, call_service, TestRequest};
+use backend::db;
+use backend::models::{Paginated, Role};
+use serde_json::Value;
+
+#[test]
+fn has_more_stops_at_the_last_page() {
+    let all: Vec<i32> = (1..=5).collect();
+
+    let page = Paginated::from_all(all.clone(), 2, 2);
+    assert_eq!(page.items, vec![3, 4]);
+    assert!(page.has_more);
+
+    // Exactly reaching the end leaves nothing more
+    let page = Paginated::from_all(all.clone(), 2, 3);
+    assert_eq!(page.items, vec![4, 5]);
+    assert!(!page.has_more);
+
+    let page = Paginated::from_all(all.clone(), 5, 0);
+    assert_eq!(page.total, 5);
+    assert!(!page.has_more);
+
+    let page = Paginated::from_all(all, 2, 10);
+    assert!(page.items.is_empty());
+    assert!(!page.has_more);
+}
+
+#[actix_web::test]
+async fn product_listing_pages_when_asked() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "page_vendor").await;
+    let customer = common::create_user(&pool, "page_customer", Role::Customer).await;
+    for name in ["Apples", "Beets", "Chard"] {
+        db::create_product(&pool, name, 10.0, "Produce", "Fresh", 5, None, vendor.id)
+            .await
+            .unwrap();
+    }
+    let app = common::init_app(&pool).await;
+
+    // Without paging parameters the bare array is kept
+    let req = TestRequest::get().uri("/products").to_request();
+    let body: Value = call_and_read_body_json(&app, req).await;
+    assert_eq!(body.as_array().unwrap().len(), 3);
+
+    let req = TestRequest::get().uri("/products?limit=2").to_request();
+    let body: Value = call_and_read_body_json(&app, req).await;
+    assert_eq!(body["items"].as_array().unwrap().len(), 2);
+    assert_eq!(body["total"], 3);
+    assert_eq!(body["has_more"], true);
+
+    let req = TestRequest::get().uri("/products?limit=2&offset=1").to_request();
+    let body: Value = call_and_read_body_json(&app, req).await;
+    assert_eq!(body["items"][1]["name"], "Chard");
+    assert_eq!(body["offset"], 1);
+    assert_eq!(body["has_more"], false);
+
+    let req = TestRequest::get()
+        .uri("/users?offset=0")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let body: Value = call_and_read_body_json(&app, req).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["limit"], 50);
+    assert_eq!(body["has_more"], false);
+
+    let req = TestRequest::get().uri("/products?limit=many").to_request();
+    assert_eq!(call_service(&app, req).await.status(), 400);
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use actix_web::test;
+use backend::models::Role;
+use backend::{db, settings};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn every_password_path_enforces_the_policy() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let customer = common::create_user(&pool, "policy_customer", Role::Customer).await;
+    let admin = common::create_user(&pool, "policy_admin", Role::Admin).await;
+    db::store_password_reset_code(&pool, "policy_customer", "123456", chrono::Utc::now() + chrono::Duration::minutes(10))
+        .await
+        .unwrap();
+    settings::set_setting(&pool, settings::PASSWORD_MIN_LENGTH, "12").await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    // Meets every class but is shorter than the configured minimum
+    let weak = "Secret#123";
+    let expected = json!("Password must be at least 12 characters");
+    let attempts = [
+        test::TestRequest::post().uri("/signup").set_json(json!({
+            "username": "policy_signup",
+            "email": "policy_signup@example.com",
+            "password": weak,
+            "mpesa_number": "0711000999"
+        })),
+        test::TestRequest::post().uri("/auth/password-reset/verify").set_json(json!({
+            "username": "policy_customer",
+            "verification_code": "123456",
+            "new_password": weak
+        })),
+        test::TestRequest::put()
+            .uri("/user/profile")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "current_password": "password123", "new_password": weak })),
+        test::TestRequest::patch()
+            .uri("/admin/credentials")
+            .insert_header(common::bearer(&admin))
+            .set_json(json!({ "current_password": "password123", "new_password": weak })),
+    ];
+    for attempt in attempts {
+        let resp = test::call_service(&app, attempt.to_request()).await;
+        assert_eq!(resp.status(), 400);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body, expected);
+    }
+    // Nothing was changed by the rejected attempts
+    assert!(db::verify_user_password(&pool, customer.id, "password123").await.unwrap());
+    assert!(db::verify_user_password(&pool, admin.id, "password123").await.unwrap());
+
+    let req = test::TestRequest::put()
+        .uri("/user/profile")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "current_password": "password123", "new_password": "Longer#Secret123" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert!(db::verify_user_password(&pool, customer.id, "Longer#Secret123").await.unwrap());
+}
+
+#[actix_web::test]
+async fn strength_lists_unmet_rules() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/password/strength")
+        .set_json(json!({ "password": "short" }))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["valid"], false);
+    assert_eq!(body["unmet_rules"], json!(["min_length", "uppercase", "digit", "symbol"]));
+    assert_eq!(body["messages"][0], "Password must be at least 8 characters");
+    assert!(body["score"].as_u64().unwrap() <= 1);
+
+    let req = test::TestRequest::post()
+        .uri("/password/strength")
+        .set_json(json!({ "password": "Correct#Horse42" }))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["valid"], true);
+    assert_eq!(body["unmet_rules"], json!([]));
+    assert_eq!(body["score"], 4);
+}
@@ -0,0 +1,13 @@
+use backend::mpesa::PaymentStatus;
+
+#[test]
+fn unknown_payment_status_parses_to_fallback() {
+    assert_eq!("completed".parse::<PaymentStatus>().unwrap(), PaymentStatus::Completed);
+    assert_eq!("Cancelled".parse::<PaymentStatus>().unwrap(), PaymentStatus::Cancelled);
+    assert_eq!("refunded?".parse::<PaymentStatus>().unwrap(), PaymentStatus::Unknown);
+    assert_eq!("".parse::<PaymentStatus>().unwrap(), PaymentStatus::Unknown);
+
+    // JSON stays the lowercase strings the frontend already expects
+    assert_eq!(serde_json::to_value(PaymentStatus::Completed).unwrap(), "completed");
+    assert_eq!(serde_json::from_value::<PaymentStatus>("pending".into()).unwrap(), PaymentStatus::Unknown);
+}
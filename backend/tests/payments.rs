@@ -0,0 +1,400 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::json;
+
+#[actix_web::test]
+async fn demo_checkout_records_payment_and_creates_orders() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "pay_vendor").await;
+    let customer = common::create_user(&pool, "pay_customer", Role::Customer).await;
+    let kale = db::create_product(&pool, "Kale", 45.0, "Vegetables", "Fresh kale", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let eggs = db::create_product(&pool, "Eggs", 15.0, "Poultry", "Tray of eggs", 30, None, vendor.id)
+        .await
+        .unwrap();
+    db::add_to_cart(&pool, customer.id, kale.id as i32, 2).await.unwrap();
+    db::add_to_cart(&pool, customer.id, eggs.id as i32, 3).await.unwrap();
+
+    let app = common::init_app(&pool).await;
+    let req = test::TestRequest::post()
+        .uri("/checkout")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "mpesa_number": "0712345678", "total_amount": 135.0 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let checkout: serde_json::Value = test::read_body_json(resp).await;
+
+    let req = test::TestRequest::get()
+        .uri("/payments/history")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let history: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let history = history.as_array().unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0]["checkout_request_id"], checkout["transaction_id"]);
+    assert_eq!(history[0]["status"], "completed");
+    assert_eq!(history[0]["amount"], 135.0);
+
+    let orders = db::get_customer_shipping_orders(&pool, customer.id).await.unwrap();
+    assert_eq!(orders.len(), 2);
+    assert!(db::get_cart_items(&pool, customer.id).await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn only_pending_payments_can_be_cancelled() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let customer = common::create_user(&pool, "cancel_customer", Role::Customer).await;
+    let other = common::create_user(&pool, "cancel_other", Role::Customer).await;
+    db::create_payment_transaction(&pool, customer.id, "ws_CO_pending", "m1", "254712345678", 100.0, None, None)
+        .await
+        .unwrap();
+    db::create_payment_transaction(&pool, customer.id, "ws_CO_done", "m2", "254712345678", 100.0, None, None)
+        .await
+        .unwrap();
+    db::update_payment_transaction(&pool, "ws_CO_done", "completed", Some("RCPT1"), Some("20250101120000"))
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let cancel = |id: &str, user| {
+        test::TestRequest::post()
+            .uri(&format!("/payments/{}/cancel", id))
+            .insert_header(common::bearer(user))
+            .to_request()
+    };
+
+    assert_eq!(test::call_service(&app, cancel("ws_CO_pending", &other)).await.status(), 404);
+    assert_eq!(test::call_service(&app, cancel("ws_CO_done", &customer)).await.status(), 409);
+    assert_eq!(test::call_service(&app, cancel("ws_CO_pending", &customer)).await.status(), 200);
+
+    let done = db::get_payment_transaction_by_checkout_request_id(&pool, "ws_CO_done").await.unwrap();
+    assert_eq!(done.status.to_string(), "completed");
+    let pending = db::get_payment_transaction_by_checkout_request_id(&pool, "ws_CO_pending").await.unwrap();
+    assert_eq!(pending.status.to_string(), "cancelled");
+}
+
+fn success_callback(checkout_request_id: &str) -> serde_json::Value {
+    json!({
+        "StkCallback": {
+            "MerchantRequestID": "m-forged",
+            "CheckoutRequestID": checkout_request_id,
+            "ResultCode": 0,
+            "ResultDesc": "The service request is processed successfully.",
+            "CallbackMetadata": { "Item": [
+                { "Name": "Amount", "Value": 90.0 },
+                { "Name": "MpesaReceiptNumber", "Value": "QWE123RTY" },
+                { "Name": "TransactionDate", "Value": "20250101120000" }
+            ]}
+        }
+    })
+}
+
+#[actix_web::test]
+async fn callback_from_unlisted_source_is_rejected() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "cb_vendor").await;
+    let customer = common::create_user(&pool, "cb_customer", Role::Customer).await;
+    let product = db::create_product(&pool, "Honey", 45.0, "Pantry", "Raw honey", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let item = db::add_to_cart(&pool, customer.id, product.id as i32, 2).await.unwrap();
+    db::create_payment_transaction(&pool, customer.id, "ws_CO_cb", "m-cb", "254712345678", 90.0, Some(&item.id.to_string()), None)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/mpesa/callback")
+        .peer_addr("10.0.0.5:4000".parse().unwrap())
+        .set_json(success_callback("ws_CO_cb"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    assert!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().is_empty());
+    let transaction = db::get_payment_transaction_by_checkout_request_id(&pool, "ws_CO_cb").await.unwrap();
+    assert_eq!(transaction.status.to_string(), "initiated");
+
+    // The same callback from a Safaricom address is processed
+    let req = test::TestRequest::post()
+        .uri("/mpesa/callback")
+        .peer_addr("196.201.214.200:4000".parse().unwrap())
+        .set_json(success_callback("ws_CO_cb"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert_eq!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn spoofed_forwarded_for_entry_is_not_trusted() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    // Other callback tests send no X-Forwarded-For, so they still see the socket peer
+    std::env::set_var("MPESA_CALLBACK_TRUST_PROXY", "true");
+    let vendor = common::create_verified_vendor(&pool, "xff_vendor").await;
+    let customer = common::create_user(&pool, "xff_customer", Role::Customer).await;
+    let product = db::create_product(&pool, "Honey", 45.0, "Pantry", "Raw honey", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let item = db::add_to_cart(&pool, customer.id, product.id as i32, 2).await.unwrap();
+    db::create_payment_transaction(&pool, customer.id, "ws_CO_xff", "m-xff", "254712345678", 90.0, Some(&item.id.to_string()), None)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let callback = |forwarded_for: &str| {
+        test::TestRequest::post()
+            .uri("/mpesa/callback")
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded_for.to_string()))
+            .set_json(success_callback("ws_CO_xff"))
+            .to_request()
+    };
+    // The client put a Safaricom address first; the proxy appended the real one
+    assert_eq!(test::call_service(&app, callback("196.201.214.200, 10.0.0.5")).await.status(), 401);
+    assert!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().is_empty());
+
+    // The proxy saw the request come from Safaricom
+    assert_eq!(test::call_service(&app, callback("203.0.113.9, 196.201.214.200")).await.status(), 200);
+    assert_eq!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn only_recent_pending_payments_can_have_their_prompt_resent() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let customer = common::create_user(&pool, "resend_customer", Role::Customer).await;
+    let other = common::create_user(&pool, "resend_other", Role::Customer).await;
+    for (checkout_request_id, attempt_id) in [
+        ("ws_CO_paid", None),
+        ("ws_CO_fresh", None),
+        ("ws_CO_stale", None),
+        ("ws_CO_first", None),
+        ("ws_CO_again1", Some("ws_CO_first")),
+        ("ws_CO_again2", Some("ws_CO_first")),
+        ("ws_CO_again3", Some("ws_CO_first")),
+    ] {
+        db::create_payment_transaction(&pool, customer.id, checkout_request_id, "m1", "254712345678", 100.0, None, None)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE payment_transactions SET attempt_id = $2, created_at = NOW() - INTERVAL '2 minutes' WHERE checkout_request_id = $1")
+            .bind(checkout_request_id)
+            .bind(attempt_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+    db::update_payment_transaction(&pool, "ws_CO_paid", "completed", Some("RCPT2"), Some("20250101120000"))
+        .await
+        .unwrap();
+    sqlx::query("UPDATE payment_transactions SET created_at = NOW() WHERE checkout_request_id = 'ws_CO_fresh'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE payment_transactions SET created_at = NOW() - INTERVAL '1 hour' WHERE checkout_request_id = 'ws_CO_stale'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let resend = |id: &str, user| {
+        test::TestRequest::post()
+            .uri(&format!("/payments/{}/resend", id))
+            .insert_header(common::bearer(user))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, resend("ws_CO_paid", &customer)).await;
+    assert_eq!(resp.status(), 409);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "completed");
+
+    assert_eq!(test::call_service(&app, resend("ws_CO_paid", &other)).await.status(), 404);
+    assert_eq!(test::call_service(&app, resend("ws_CO_stale", &customer)).await.status(), 409);
+
+    // Rate limited: too soon after the last prompt, or too many prompts already
+    let resp = test::call_service(&app, resend("ws_CO_fresh", &customer)).await;
+    assert_eq!(resp.status(), 429);
+    assert!(resp.headers().contains_key("retry-after"));
+    assert_eq!(test::call_service(&app, resend("ws_CO_again3", &customer)).await.status(), 429);
+
+    let paid = db::get_payment_transaction_by_checkout_request_id(&pool, "ws_CO_paid").await.unwrap();
+    assert_eq!(paid.status.to_string(), "completed");
+}
+
+#[actix_web::test]
+async fn paying_a_superseded_prompt_completes_once_and_refunds_the_second() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "superseded_vendor").await;
+    let customer = common::create_user(&pool, "superseded_customer", Role::Customer).await;
+    let product = db::create_product(&pool, "Honey", 45.0, "Pantry", "Raw honey", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let item = db::add_to_cart(&pool, customer.id, product.id as i32, 2).await.unwrap();
+    db::create_payment_transaction(&pool, customer.id, "ws_CO_old", "m-old", "254712345678", 90.0, Some(&item.id.to_string()), None)
+        .await
+        .unwrap();
+    let old = db::get_payment_transaction_by_checkout_request_id(&pool, "ws_CO_old").await.unwrap();
+    assert!(db::record_stk_resend(&pool, &old, "ws_CO_new", "m-new", None).await.unwrap());
+    let app = common::init_app(&pool).await;
+
+    let callback = |id: &str| {
+        test::TestRequest::post()
+            .uri("/mpesa/callback")
+            .peer_addr("196.201.214.200:4000".parse().unwrap())
+            .set_json(success_callback(id))
+            .to_request()
+    };
+
+    // The customer pays the replaced prompt: it takes the order back from the live one
+    assert_eq!(test::call_service(&app, callback("ws_CO_old")).await.status(), 200);
+    let old = db::get_payment_transaction_by_checkout_request_id(&pool, "ws_CO_old").await.unwrap();
+    assert_eq!(old.status.to_string(), "completed");
+    let new = db::get_payment_transaction_by_checkout_request_id(&pool, "ws_CO_new").await.unwrap();
+    assert_eq!(new.status.to_string(), "cancelled");
+    assert_eq!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().len(), 1);
+
+    // Paying the other prompt too is refunded to the wallet, once
+    for _ in 0..2 {
+        assert_eq!(test::call_service(&app, callback("ws_CO_new")).await.status(), 200);
+    }
+    assert_eq!(db::get_wallet_balance(&pool, customer.id).await.unwrap(), 90.0);
+    assert_eq!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().len(), 1);
+    let new = db::get_payment_transaction_by_checkout_request_id(&pool, "ws_CO_new").await.unwrap();
+    assert_eq!(new.status.to_string(), "cancelled");
+}
+
+#[actix_web::test]
+async fn unmatched_callback_is_kept_for_retry() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "cbf_admin", Role::Admin).await;
+    let vendor = common::create_verified_vendor(&pool, "cbf_vendor").await;
+    let customer = common::create_user(&pool, "cbf_customer", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    // Still acknowledged to Safaricom, but recorded
+    let req = test::TestRequest::post()
+        .uri("/mpesa/callback")
+        .peer_addr("196.201.214.200:4000".parse().unwrap())
+        .set_json(success_callback("ws_CO_unmatched"))
+        .to_request();
+    let ack: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(ack["ResultCode"], 0);
+
+    let list = || {
+        test::TestRequest::get()
+            .uri("/api/admin/callback-failures?status=open")
+            .insert_header(common::bearer(&admin))
+            .to_request()
+    };
+    let failures: serde_json::Value = test::call_and_read_body_json(&app, list()).await;
+    let failures = failures.as_array().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0]["checkout_request_id"], "ws_CO_unmatched");
+    assert!(failures[0]["error"].as_str().unwrap().contains("Transaction not found"));
+    let payload: serde_json::Value = serde_json::from_str(failures[0]["payload"].as_str().unwrap()).unwrap();
+    assert_eq!(payload, success_callback("ws_CO_unmatched"));
+    let failure_id = failures[0]["id"].as_i64().unwrap();
+
+    let retry = || {
+        test::TestRequest::post()
+            .uri(&format!("/api/admin/callback-failures/{}/retry", failure_id))
+            .insert_header(common::bearer(&admin))
+            .to_request()
+    };
+    let outcome: serde_json::Value = test::call_and_read_body_json(&app, retry()).await;
+    assert_eq!(outcome["resolved"], false);
+
+    // Once the payment it belongs to is known, a retry applies it
+    let product = db::create_product(&pool, "Honey", 45.0, "Pantry", "Raw honey", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let item = db::add_to_cart(&pool, customer.id, product.id as i32, 2).await.unwrap();
+    db::create_payment_transaction(&pool, customer.id, "ws_CO_unmatched", "m-forged", "254712345678", 90.0, Some(&item.id.to_string()), None)
+        .await
+        .unwrap();
+    let outcome: serde_json::Value = test::call_and_read_body_json(&app, retry()).await;
+    assert_eq!(outcome["resolved"], true);
+    assert_eq!(outcome["payment_status"], "completed");
+    assert_eq!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().len(), 1);
+
+    let open: serde_json::Value = test::call_and_read_body_json(&app, list()).await;
+    assert!(open.as_array().unwrap().is_empty());
+    assert_eq!(test::call_service(&app, retry()).await.status(), 409);
+}
+
+#[actix_web::test]
+async fn retrying_a_partly_failed_callback_adds_only_the_missing_orders() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "partial_admin", Role::Admin).await;
+    let vendor = common::create_verified_vendor(&pool, "partial_vendor").await;
+    let customer = common::create_user(&pool, "partial_customer", Role::Customer).await;
+    let honey = db::create_product(&pool, "Honey", 30.0, "Pantry", "Raw honey", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let jam = db::create_product(&pool, "Jam", 30.0, "Pantry", "Plum jam", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let honey_item = db::add_to_cart(&pool, customer.id, honey.id as i32, 1).await.unwrap();
+    let jam_item = db::add_to_cart(&pool, customer.id, jam.id as i32, 2).await.unwrap();
+    let items = format!("{},{}", honey_item.id, jam_item.id);
+    db::create_payment_transaction(&pool, customer.id, "ws_CO_partial", "m-forged", "254712345678", 90.0, Some(&items), None)
+        .await
+        .unwrap();
+    // Make the jam's line item fail to insert
+    sqlx::query(&format!(
+        "CREATE FUNCTION fail_jam() RETURNS trigger AS $$ BEGIN IF NEW.product_id = {} THEN RAISE EXCEPTION 'jam unavailable'; END IF; RETURN NEW; END $$ LANGUAGE plpgsql",
+        jam.id
+    ))
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query("CREATE TRIGGER fail_jam BEFORE INSERT ON shipping_orders FOR EACH ROW EXECUTE FUNCTION fail_jam()")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let callback = |body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri("/mpesa/callback")
+            .peer_addr("196.201.214.200:4000".parse().unwrap())
+            .set_json(body)
+            .to_request()
+    };
+    test::call_service(&app, callback(success_callback("ws_CO_partial"))).await;
+    let orders = db::get_customer_shipping_orders(&pool, customer.id).await.unwrap();
+    assert_eq!(orders.len(), 1);
+    // The failed line stays in the cart for the retry
+    let cart = db::get_cart_items(&pool, customer.id).await.unwrap();
+    assert_eq!(cart.iter().map(|item| item.id).collect::<Vec<_>>(), vec![jam_item.id]);
+
+    sqlx::query("DROP TRIGGER fail_jam ON shipping_orders").execute(&pool).await.unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/admin/callback-failures?status=open")
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    let failures: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/admin/callback-failures/{}/retry", failures[0]["id"]))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    let outcome: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(outcome["resolved"], true);
+
+    let orders = db::get_customer_shipping_orders(&pool, customer.id).await.unwrap();
+    assert_eq!(orders.len(), 2);
+    assert_eq!(orders[0].order_id, orders[1].order_id);
+    assert!(db::get_cart_items(&pool, customer.id).await.unwrap().is_empty());
+
+    // A repeated success callback, or a late failure one, changes nothing
+    test::call_service(&app, callback(success_callback("ws_CO_partial"))).await;
+    let mut failed = success_callback("ws_CO_partial");
+    failed["StkCallback"]["ResultCode"] = json!(1032);
+    test::call_service(&app, callback(failed)).await;
+    assert_eq!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().len(), 2);
+    let payment = db::get_payment_transaction_by_checkout_request_id(&pool, "ws_CO_partial").await.unwrap();
+    assert_eq!(payment.status.to_string(), "completed");
+}
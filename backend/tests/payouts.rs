@@ -0,0 +1,59 @@
+mod common;
+
+use actix_web::test;
+use backend::models::Role;
+use backend::{db, payouts};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn monthly_vendor_earnings_stay_pending_until_sweep() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let monthly = common::create_verified_vendor(&pool, "payout_monthly").await;
+    let instant = common::create_verified_vendor(&pool, "payout_instant").await;
+    let customer = common::create_user(&pool, "payout_customer", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    for (vendor, preference) in [(&monthly, "monthly"), (&instant, "after_order")] {
+        let req = test::TestRequest::patch()
+            .uri("/profile")
+            .insert_header(common::bearer(vendor))
+            .set_json(json!({ "payment_preference": preference }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+    }
+
+    let req = test::TestRequest::patch()
+        .uri("/profile")
+        .insert_header(common::bearer(&monthly))
+        .set_json(json!({ "payment_preference": "weekly" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let potatoes = db::create_product(&pool, "Potatoes", 120.0, "Vegetables", "Shangi", 40, None, monthly.id)
+        .await
+        .unwrap();
+    let onions = db::create_product(&pool, "Onions", 90.0, "Vegetables", "Red onions", 40, None, instant.id)
+        .await
+        .unwrap();
+    let first = db::create_shipping_order(&pool, customer.id, potatoes.id as i32, 2, "Eldoret").await.unwrap();
+    let second = db::create_shipping_order(&pool, customer.id, onions.id as i32, 1, "Eldoret").await.unwrap();
+    db::verify_delivery_and_release_payment(&pool, first.id, customer.id).await.unwrap();
+    db::verify_delivery_and_release_payment(&pool, second.id, customer.id).await.unwrap();
+
+    assert_eq!(db::get_wallet_balances(&pool, monthly.id).await.unwrap(), (0.0, 240.0));
+    assert_eq!(db::get_wallet_balances(&pool, instant.id).await.unwrap(), (90.0, 0.0));
+
+    let req = test::TestRequest::get()
+        .uri("/wallet/balance")
+        .insert_header(common::bearer(&monthly))
+        .to_request();
+    let wallet: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(wallet["balance"].as_f64().unwrap(), 0.0);
+    assert_eq!(wallet["pending_balance"].as_f64().unwrap(), 240.0);
+
+    // The sweep releases pending earnings once per month
+    assert_eq!(payouts::run_monthly_payout(&pool).await.unwrap(), Some((1, 240.0)));
+    assert_eq!(payouts::run_monthly_payout(&pool).await.unwrap(), None);
+    assert_eq!(db::get_wallet_balances(&pool, monthly.id).await.unwrap(), (240.0, 0.0));
+    assert_eq!(db::get_wallet_balances(&pool, instant.id).await.unwrap(), (90.0, 0.0));
+}
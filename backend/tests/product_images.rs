@@ -0,0 +1,103 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn gallery_images_follow_the_configured_order() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "gallery_vendor").await;
+    let other = common::create_verified_vendor(&pool, "gallery_other").await;
+    let product = db::create_product(&pool, "Pumpkin", 150.0, "Vegetables", "Orange", 5, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+    let images_uri = format!("/products/{}/images", product.id);
+
+    let mut ids = Vec::new();
+    for image in [
+        "https://cdn.example.com/pumpkin-front.jpg",
+        "data:image/png;base64,iVBORw0KGgo=",
+        "https://cdn.example.com/pumpkin-side.jpg",
+    ] {
+        let req = test::TestRequest::post()
+            .uri(&images_uri)
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "image": image }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        let body: Value = test::read_body_json(resp).await;
+        ids.push(body["id"].as_i64().unwrap());
+    }
+
+    // Invalid images and other vendors are refused
+    for (user, image, status) in [
+        (&vendor, "ftp://cdn.example.com/pumpkin.jpg", 400),
+        (&vendor, "data:text/html;base64,PGI+", 400),
+        (&other, "https://cdn.example.com/other.jpg", 403),
+    ] {
+        let req = test::TestRequest::post()
+            .uri(&images_uri)
+            .insert_header(common::bearer(user))
+            .set_json(json!({ "image": image }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), status);
+    }
+
+    let req = test::TestRequest::put()
+        .uri(&format!("{}/order", images_uri))
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "image_ids": [ids[2], ids[0], ids[1]] }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    // The order must name every image exactly once
+    let req = test::TestRequest::put()
+        .uri(&format!("{}/order", images_uri))
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "image_ids": [ids[0], ids[1]] }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let req = test::TestRequest::get().uri("/products").to_request();
+    let products: Value = test::call_and_read_body_json(&app, req).await;
+    let gallery = products[0]["gallery"].as_array().unwrap();
+    let gallery_ids: Vec<i64> = gallery.iter().map(|image| image["id"].as_i64().unwrap()).collect();
+    assert_eq!(gallery_ids, vec![ids[2], ids[0], ids[1]]);
+    assert_eq!(gallery[0]["image"], "https://cdn.example.com/pumpkin-side.jpg");
+
+    // Deleting closes the gap
+    let req = test::TestRequest::delete()
+        .uri(&format!("{}/{}", images_uri, ids[0]))
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let req = test::TestRequest::get().uri(&images_uri).to_request();
+    let gallery: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(gallery, json!([
+        { "id": ids[2], "image": "https://cdn.example.com/pumpkin-side.jpg", "position": 0 },
+        { "id": ids[1], "image": "data:image/png;base64,iVBORw0KGgo=", "position": 1 }
+    ]));
+}
+
+#[actix_web::test]
+async fn gallery_is_capped_per_product() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "gallery_cap_vendor").await;
+    let product = db::create_product(&pool, "Melon", 200.0, "Fruit", "Sweet", 5, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    for n in 0..9 {
+        let req = test::TestRequest::post()
+            .uri(&format!("/products/{}/images", product.id))
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "image": format!("https://cdn.example.com/melon-{}.jpg", n) }))
+            .to_request();
+        let expected = if n < 8 { 201 } else { 400 };
+        assert_eq!(test::call_service(&app, req).await.status(), expected);
+    }
+}
@@ -0,0 +1,230 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::{NewArrival, Paginated, Role};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn single_product_includes_vendor_rating_and_stock() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "detail_vendor").await;
+    let customer = common::create_user(&pool, "detail_customer", Role::Customer).await;
+    let cabbage = db::create_product(&pool, "Cabbage", 60.0, "Vegetables", "Green", 0, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_review(&pool, customer.id, cabbage.id as i32, 4, None).await.unwrap();
+    db::add_product_image(&pool, cabbage.id as i32, "https://cdn.example.com/cabbage.jpg", 8).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get().uri(&format!("/products/{}", cabbage.id)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["name"], "Cabbage");
+    assert_eq!(body["vendor"]["id"], vendor.id);
+    assert_eq!(body["vendor"]["username"], "detail_vendor");
+    assert_eq!(body["average_rating"], 4.0);
+    assert_eq!(body["review_count"], 1);
+    assert_eq!(body["in_stock"], false);
+    assert_eq!(body["gallery"][0]["image"], "https://cdn.example.com/cabbage.jpg");
+
+    // Fixed paths under /products still win over the id
+    let req = test::TestRequest::get().uri("/products/featured").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let removed = db::create_product(&pool, "Leeks", 40.0, "Vegetables", "Long", 3, None, vendor.id)
+        .await
+        .unwrap();
+    db::delete_product(&pool, removed.id as i32, vendor.id).await.unwrap();
+    for uri in [format!("/products/{}", removed.id), "/products/999999".to_string()] {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+    }
+}
+
+#[actix_web::test]
+async fn in_stock_only_hides_sold_out_products() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "stock_filter_vendor").await;
+    let eggs = db::create_product(&pool, "Eggs", 15.0, "Poultry", "Free range", 30, None, vendor.id)
+        .await
+        .unwrap();
+    let ducks = db::create_product(&pool, "Duck Eggs", 40.0, "Poultry", "Sold out", 0, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let listing = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+    let find = |products: &Value, id: u32| products.as_array().unwrap().iter().find(|p| p["id"] == id).cloned();
+
+    // By default sold-out products are listed, marked as such
+    let all: Value = test::call_and_read_body_json(&app, listing("/products")).await;
+    assert_eq!(find(&all, eggs.id).unwrap()["in_stock"], true);
+    assert_eq!(find(&all, ducks.id).unwrap()["in_stock"], false);
+
+    let in_stock: Value = test::call_and_read_body_json(&app, listing("/products?in_stock_only=true")).await;
+    assert!(find(&in_stock, eggs.id).is_some());
+    assert!(find(&in_stock, ducks.id).is_none());
+    assert!(in_stock.as_array().unwrap().iter().all(|p| p["quantity"].as_i64().unwrap() > 0));
+
+    assert_eq!(test::call_service(&app, listing("/products?in_stock_only=maybe")).await.status(), 400);
+}
+
+#[actix_web::test]
+async fn products_carry_details_and_filter_by_organic() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "details_vendor").await;
+    let app = common::init_app(&pool).await;
+
+    let create = |body: Value| {
+        test::TestRequest::post()
+            .uri("/products")
+            .insert_header(common::bearer(&vendor))
+            .set_json(body)
+            .to_request()
+    };
+    let organic: Value = test::call_and_read_body_json(&app, create(json!({
+        "name": "Organic Spinach", "price": 40.0, "category": "Vegetables", "description": "Leafy",
+        "quantity": 10, "unit": "bunch", "weight_grams": 250, "origin": "Limuru",
+        "is_organic": true, "harvest_date": "2024-03-01"
+    })))
+    .await;
+    assert_eq!(organic["unit"], "bunch");
+    assert_eq!(organic["weight_grams"], 250);
+    assert_eq!(organic["harvest_date"], "2024-03-01");
+    let plain: Value = test::call_and_read_body_json(&app, create(json!({
+        "name": "Cabbage Head", "price": 60.0, "category": "Vegetables", "description": "Round", "quantity": 10
+    })))
+    .await;
+    assert_eq!(plain["is_organic"], false);
+    assert_eq!(plain["origin"], Value::Null);
+
+    for invalid in [json!({ "weight_grams": 0 }), json!({ "harvest_date": "2999-01-01" }), json!({ "unit": "x".repeat(21) })] {
+        let mut body = json!({ "name": "Bad Details", "price": 10.0, "category": "Vegetables", "description": "", "quantity": 1 });
+        body.as_object_mut().unwrap().extend(invalid.as_object().unwrap().clone());
+        assert_eq!(test::call_service(&app, create(body)).await.status(), 400);
+    }
+
+    let listing = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+    let products: Value = test::call_and_read_body_json(&app, listing("/products?is_organic=true")).await;
+    let products = products.as_array().unwrap();
+    assert!(products.iter().all(|p| p["is_organic"] == true));
+    assert!(products.iter().any(|p| p["id"] == organic["id"] && p["origin"] == "Limuru"));
+    assert!(!products.iter().any(|p| p["id"] == plain["id"]));
+
+    // Details left out of an update are kept
+    let req = test::TestRequest::patch()
+        .uri(&format!("/products/{}", organic["id"]))
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "name": "Organic Spinach", "price": 45.0, "category": "Vegetables", "description": "Leafy", "quantity": 8, "origin": "Kiambu" }))
+        .to_request();
+    let updated: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((updated["origin"].as_str(), updated["unit"].as_str(), updated["is_organic"].as_bool()), (Some("Kiambu"), Some("bunch"), Some(true)));
+}
+
+#[actix_web::test]
+async fn new_arrivals_list_recent_in_stock_products_newest_first() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "arrivals_vendor").await;
+    let fresh = db::create_product(&pool, "Fresh Peas", 90.0, "Vegetables", "Just listed", 12, None, vendor.id)
+        .await
+        .unwrap();
+    let old = db::create_product(&pool, "Old Beans", 70.0, "Vegetables", "Listed long ago", 12, None, vendor.id)
+        .await
+        .unwrap();
+    let sold_out = db::create_product(&pool, "New Okra", 50.0, "Vegetables", "None left", 0, None, vendor.id)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE products SET created_at = NOW() - INTERVAL '90 days' WHERE id = $1")
+        .bind(old.id as i32)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get().uri("/products/new?limit=200").to_request();
+    let page: Paginated<NewArrival> = test::call_and_read_body_json(&app, req).await;
+    let ids: Vec<u32> = page.items.iter().map(|arrival| arrival.product.id).collect();
+    assert!(ids.contains(&fresh.id));
+    assert!(!ids.contains(&old.id));
+    assert!(!ids.contains(&sold_out.id));
+    assert!(page.items.windows(2).all(|pair| pair[0].created_at >= pair[1].created_at));
+}
+
+#[actix_web::test]
+async fn customers_can_browse_a_vendor_storefront() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "storefront_vendor").await;
+    let other = common::create_verified_vendor(&pool, "storefront_other").await;
+    let pending = common::create_user(&pool, "storefront_pending", Role::Vendor).await;
+    let customer = common::create_user(&pool, "storefront_customer", Role::Customer).await;
+    for (name, description) in [("Sweet Potatoes", "Orange flesh"), ("Arrowroots", "Boiled or fried"), ("Yams", "Sweet and starchy")] {
+        db::create_product(&pool, name, 80.0, "Tubers", description, 10, None, vendor.id).await.unwrap();
+    }
+    db::create_product(&pool, "Cassava", 50.0, "Tubers", "Not this vendor", 10, None, other.id).await.unwrap();
+    db::create_product(&pool, "Taro", 50.0, "Tubers", "Pending vendor", 10, None, pending.id).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/vendors/{}/products", vendor.id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let products: Value = test::call_and_read_body_json(&app, req).await;
+    let names: Vec<&str> = products.as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["Sweet Potatoes", "Arrowroots", "Yams"]);
+
+    // Search covers descriptions, and paging returns the envelope
+    let req = test::TestRequest::get()
+        .uri(&format!("/vendors/{}/products?q=sweet&limit=1", vendor.id))
+        .to_request();
+    let page: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["has_more"], true);
+    assert_eq!(page["items"][0]["name"], "Sweet Potatoes");
+
+    for vendor_id in [pending.id, customer.id, 999_999] {
+        let req = test::TestRequest::get().uri(&format!("/vendors/{}/products", vendor_id)).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+    }
+}
+
+#[actix_web::test]
+async fn near_identical_product_names_are_flagged_unless_forced() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "dup_vendor").await;
+    let other_vendor = common::create_verified_vendor(&pool, "dup_other_vendor").await;
+    let app = common::init_app(&pool).await;
+
+    let create = |user, name: &str, force: bool| {
+        test::TestRequest::post()
+            .uri("/products")
+            .insert_header(common::bearer(user))
+            .set_json(json!({
+                "name": name, "price": 80.0, "category": "Vegetables",
+                "description": "Vine ripened", "quantity": 10, "force": force
+            }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, create(&vendor, "Fresh Tomatoes", false)).await;
+    assert_eq!(resp.status(), 201);
+    let original: Value = test::read_body_json(resp).await;
+
+    let resp = test::call_service(&app, create(&vendor, "fresh  tomatoes", false)).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["existing_product_id"], original["id"]);
+    assert_eq!(body["existing_product_name"], "Fresh Tomatoes");
+    assert_eq!(body["similarity"], 1.0);
+
+    // Clearly different products and other vendors' catalogs don't count
+    let resp = test::call_service(&app, create(&vendor, "Cherry Tomatoes", false)).await;
+    assert_eq!(resp.status(), 201);
+    let resp = test::call_service(&app, create(&other_vendor, "Fresh Tomatoes", false)).await;
+    assert_eq!(resp.status(), 201);
+
+    let resp = test::call_service(&app, create(&vendor, "fresh  tomatoes", true)).await;
+    assert_eq!(resp.status(), 201);
+    assert_eq!(db::get_vendor_product_names(&pool, vendor.id).await.unwrap().len(), 3);
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use actix_web::{test, web};
+use backend::db;
+use backend::models::Role;
+use backend::realtime::{ChatHub, ClientFrame, ServerEvent};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn reading_messages_sends_read_receipt_to_sender() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "live_vendor").await;
+    let customer = common::create_user(&pool, "live_customer", Role::Customer).await;
+    let hub = web::Data::new(ChatHub::default());
+    let app = common::init_app_with_hub(&pool, hub.clone()).await;
+
+    let mut vendor_socket = hub.subscribe(vendor.id);
+    let mut customer_socket = hub.subscribe(customer.id);
+
+    hub.handle_frame(&pool, vendor.id, ClientFrame::Typing { to_user_id: customer.id }).await.unwrap();
+    match customer_socket.events.try_recv().unwrap() {
+        ServerEvent::Typing { user_id } => assert_eq!(user_id, vendor.id),
+        other => panic!("expected typing, got {:?}", other),
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/messages")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "receiver_id": customer.id, "content": "Your order is ready" }))
+        .to_request();
+    let sent: Value = test::call_and_read_body_json(&app, req).await;
+    let message_id = sent["id"].as_i64().unwrap() as i32;
+
+    // The recipient's socket gets the message and acknowledges it
+    match customer_socket.events.try_recv().unwrap() {
+        ServerEvent::Message { message } => assert_eq!(message.id, message_id),
+        other => panic!("expected message, got {:?}", other),
+    }
+    hub.handle_frame(&pool, customer.id, ClientFrame::Delivered { sender_id: vendor.id, message_ids: vec![message_id] })
+        .await
+        .unwrap();
+    match vendor_socket.events.try_recv().unwrap() {
+        ServerEvent::Delivered { user_id, message_ids } => {
+            assert_eq!(user_id, customer.id);
+            assert_eq!(message_ids, vec![message_id]);
+        }
+        other => panic!("expected delivered, got {:?}", other),
+    }
+
+    // Opening the conversation pushes a read receipt to the sender
+    let req = test::TestRequest::patch()
+        .uri(&format!("/messages/{}/read", vendor.id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    match vendor_socket.events.try_recv().unwrap() {
+        ServerEvent::Read { user_id, message_ids } => {
+            assert_eq!(user_id, customer.id);
+            assert_eq!(message_ids, vec![message_id]);
+        }
+        other => panic!("expected read receipt, got {:?}", other),
+    }
+
+    // Nothing new to read, so no further receipt
+    let req = test::TestRequest::patch()
+        .uri(&format!("/messages/{}/read", vendor.id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert!(vendor_socket.events.try_recv().is_err());
+
+    hub.unsubscribe(&vendor_socket);
+    hub.unsubscribe(&customer_socket);
+}
+
+#[actix_web::test]
+async fn typing_is_not_relayed_between_blocked_users() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "quiet_vendor").await;
+    let customer = common::create_user(&pool, "quiet_customer", Role::Customer).await;
+    let hub = ChatHub::default();
+    let mut vendor_socket = hub.subscribe(vendor.id);
+    let mut customer_socket = hub.subscribe(customer.id);
+
+    db::block_user(&pool, vendor.id, customer.id).await.unwrap();
+    hub.handle_frame(&pool, customer.id, ClientFrame::Typing { to_user_id: vendor.id }).await.unwrap();
+    assert!(vendor_socket.events.try_recv().is_err());
+    // A block in either direction stops it
+    hub.handle_frame(&pool, vendor.id, ClientFrame::Typing { to_user_id: customer.id }).await.unwrap();
+    assert!(customer_socket.events.try_recv().is_err());
+
+    db::unblock_user(&pool, vendor.id, customer.id).await.unwrap();
+    hub.handle_frame(&pool, customer.id, ClientFrame::Typing { to_user_id: vendor.id }).await.unwrap();
+    assert!(matches!(vendor_socket.events.try_recv().unwrap(), ServerEvent::Typing { user_id } if user_id == customer.id));
+
+    hub.unsubscribe(&vendor_socket);
+    hub.unsubscribe(&customer_socket);
+}
+
+#[actix_web::test]
+async fn delivery_receipts_only_cover_messages_the_acknowledger_received() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "ack_vendor").await;
+    let customer = common::create_user(&pool, "ack_customer", Role::Customer).await;
+    let outsider = common::create_user(&pool, "ack_outsider", Role::Customer).await;
+    let hub = ChatHub::default();
+    let mut vendor_socket = hub.subscribe(vendor.id);
+
+    let to_customer = db::send_message(&pool, vendor.id, customer.id, "Ready for pickup").await.unwrap();
+    let to_outsider = db::send_message(&pool, vendor.id, outsider.id, "Out of stock").await.unwrap();
+    let delivered_at = |id: i32| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query_scalar::<_, bool>("SELECT delivered_at IS NOT NULL FROM messages WHERE id = $1")
+                .bind(id)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        }
+    };
+
+    // The outsider can't acknowledge someone else's message, or one that doesn't exist
+    let forged = ClientFrame::Delivered { sender_id: vendor.id, message_ids: vec![to_customer.id, i32::MAX] };
+    hub.handle_frame(&pool, outsider.id, forged).await.unwrap();
+    assert!(vendor_socket.events.try_recv().is_err());
+    assert!(!delivered_at(to_customer.id).await);
+
+    // Naming the wrong sender doesn't help either
+    let forged = ClientFrame::Delivered { sender_id: outsider.id, message_ids: vec![to_customer.id] };
+    hub.handle_frame(&pool, customer.id, forged).await.unwrap();
+    assert!(!delivered_at(to_customer.id).await);
+
+    // Mixed in with a real one, only the real one is acknowledged and recorded
+    let frame = ClientFrame::Delivered { sender_id: vendor.id, message_ids: vec![to_customer.id, to_outsider.id] };
+    hub.handle_frame(&pool, customer.id, frame).await.unwrap();
+    match vendor_socket.events.try_recv().unwrap() {
+        ServerEvent::Delivered { user_id, message_ids } => {
+            assert_eq!(user_id, customer.id);
+            assert_eq!(message_ids, vec![to_customer.id]);
+        }
+        other => panic!("expected delivered, got {:?}", other),
+    }
+    assert!(delivered_at(to_customer.id).await);
+    assert!(!delivered_at(to_outsider.id).await);
+
+    hub.unsubscribe(&vendor_socket);
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::{OrderReceipt, Role};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn receipt_totals_reconcile_with_line_items() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "receipt_vendor").await;
+    let other_vendor = common::create_verified_vendor(&pool, "receipt_other").await;
+    let customer = common::create_user(&pool, "receipt_customer", Role::Customer).await;
+    let stranger = common::create_user(&pool, "receipt_stranger", Role::Customer).await;
+    let admin = common::create_user(&pool, "receipt_admin", Role::Admin).await;
+    let honey = db::create_product(&pool, "Honey", 200.0, "Pantry", "Raw honey", 50, None, vendor.id)
+        .await
+        .unwrap();
+    let jam = db::create_product(&pool, "Jam", 150.0, "Pantry", "Plum jam", 50, None, vendor.id)
+        .await
+        .unwrap();
+    let milk = db::create_product(&pool, "Milk", 60.0, "Dairy", "Fresh milk", 50, None, other_vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/vendor/coupons")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "code": "RECEIPT10", "percent_off": 10.0 }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+    let req = test::TestRequest::post()
+        .uri("/vendor/shipping-options")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "name": "Courier", "method": "flat", "fee": 80.0 }))
+        .to_request();
+    let option: Value = test::call_and_read_body_json(&app, req).await;
+
+    db::add_to_cart(&pool, customer.id, honey.id as i32, 1).await.unwrap();
+    db::add_to_cart(&pool, customer.id, jam.id as i32, 3).await.unwrap();
+    db::add_to_cart(&pool, customer.id, milk.id as i32, 2).await.unwrap();
+    // 650 of items less 65 off, plus 80 shipping and 120 of milk
+    let req = test::TestRequest::post()
+        .uri("/checkout")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({
+            "mpesa_number": "0712345678",
+            "total_amount": 785.0,
+            "coupon_codes": ["RECEIPT10"],
+            "shipping_selections": [{ "vendor_id": vendor.id, "option_id": option["id"] }]
+        }))
+        .to_request();
+    let checkout: Value = test::call_and_read_body_json(&app, req).await;
+    let order_id = db::get_customer_shipping_orders(&pool, customer.id).await.unwrap()[0].order_id.unwrap();
+
+    let receipt = |user| {
+        test::TestRequest::get()
+            .uri(&format!("/orders/{}/receipt", order_id))
+            .insert_header(common::bearer(user))
+            .to_request()
+    };
+    let full: OrderReceipt = test::call_and_read_body_json(&app, receipt(&customer)).await;
+    assert_eq!(full.items.len(), 3);
+
+    let items_subtotal: f64 = full.items.iter().map(|item| item.subtotal).sum();
+    let shipping: f64 = full.items.iter().map(|item| item.shipping_fee).sum();
+    let discount: f64 = full.items.iter().map(|item| item.discount).sum();
+    assert_eq!(full.subtotal, items_subtotal);
+    assert_eq!(full.subtotal, 770.0);
+    assert_eq!(full.discount_total, 65.0);
+    assert_eq!(full.shipping_total, 80.0);
+    assert_eq!(full.total, items_subtotal + shipping - discount);
+    assert_eq!(full.total, full.items.iter().map(|item| item.total).sum::<f64>());
+    for item in &full.items {
+        assert_eq!(item.subtotal, item.unit_price * item.quantity as f64, "{}", item.product_name);
+    }
+    let jam_line = full.items.iter().find(|item| item.product_id == jam.id as i32).unwrap();
+    assert_eq!((jam_line.unit_price, jam_line.discount), (150.0, 45.0));
+    assert_eq!(jam_line.coupon_code.as_deref(), Some("RECEIPT10"));
+
+    let payment = full.payment.as_ref().unwrap();
+    assert_eq!(payment.method, "demo");
+    assert_eq!(payment.amount, full.total);
+    assert_eq!(payment.mpesa_receipt_number.as_deref(), checkout["transaction_id"].as_str());
+    assert!(payment.paid_at.is_some());
+
+    let as_admin: OrderReceipt = test::call_and_read_body_json(&app, receipt(&admin)).await;
+    assert_eq!(as_admin.total, full.total);
+
+    // A vendor sees only their own lines, totalled on their own
+    let as_vendor: OrderReceipt = test::call_and_read_body_json(&app, receipt(&other_vendor)).await;
+    assert_eq!(as_vendor.items.len(), 1);
+    assert_eq!(as_vendor.total, 120.0);
+
+    assert_eq!(test::call_service(&app, receipt(&stranger)).await.status(), 403);
+    let req = test::TestRequest::get()
+        .uri("/orders/999999/receipt")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use actix_web::test;
+use backend::models::Role;
+use backend::{db, settings};
+use serde_json::{json, Value};
+
+async fn create_product_status(
+    app: &impl actix_web::dev::Service<actix_http::Request, Response = actix_web::dev::ServiceResponse, Error = actix_web::Error>,
+    vendor: &backend::models::User,
+) -> u16 {
+    let req = test::TestRequest::post()
+        .uri("/products")
+        .insert_header(common::bearer(vendor))
+        .set_json(json!({
+            "name": "Cabbage", "price": 35.0, "category": "Vegetables",
+            "description": "Green", "quantity": 12, "force": true
+        }))
+        .to_request();
+    test::call_service(app, req).await.status().as_u16()
+}
+
+#[actix_web::test]
+async fn dismissed_reports_do_not_count_toward_suspension() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "rep_vendor").await;
+    let customer = common::create_user(&pool, "rep_customer", Role::Customer).await;
+
+    let mut report_ids = Vec::new();
+    for _ in 0..5 {
+        let report = db::create_vendor_report(&pool, customer.id, vendor.id, None, "other", Some("Late"))
+            .await
+            .unwrap();
+        report_ids.push(report.id);
+    }
+    for id in &report_ids[..2] {
+        db::update_report_status(&pool, *id, "dismissed", Some("Frivolous")).await.unwrap();
+    }
+
+    let app = common::init_app(&pool).await;
+    assert!(!db::is_vendor_suspended(&pool, vendor.id).await.unwrap());
+    assert_eq!(create_product_status(&app, &vendor).await, 201);
+
+    // Non-delivery weighs more: one such report pushes 3.0 to 5.0
+    db::create_vendor_report(&pool, customer.id, vendor.id, None, "non_delivery", None).await.unwrap();
+    assert!(db::is_vendor_suspended(&pool, vendor.id).await.unwrap());
+    assert_eq!(create_product_status(&app, &vendor).await, 403);
+
+    // Suspended vendors' products disappear from the storefront
+    let req = test::TestRequest::get().uri("/products").to_request();
+    let products: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert!(products.as_array().unwrap().is_empty());
+
+    // The threshold is configurable
+    settings::set_setting(&pool, settings::REPORT_SUSPENSION_THRESHOLD, "10").await.unwrap();
+    assert_eq!(create_product_status(&app, &vendor).await, 201);
+}
+
+#[actix_web::test]
+async fn suspended_vendors_cannot_edit_products() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "rep_edit_vendor").await;
+    let customer = common::create_user(&pool, "rep_edit_customer", Role::Customer).await;
+    let product = db::create_product(&pool, "Onions", 50.0, "Vegetables", "Red onions", 30, None, vendor.id)
+        .await
+        .unwrap();
+    db::set_product_featured(&pool, product.id as i32, true, None, None).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let update = |price: f64| {
+        test::TestRequest::patch()
+            .uri(&format!("/products/{}", product.id))
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({
+                "name": "Onions", "price": price, "category": "Vegetables",
+                "description": "Red onions", "quantity": 30
+            }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, update(55.0)).await.status(), 200);
+
+    for _ in 0..3 {
+        db::create_vendor_report(&pool, customer.id, vendor.id, Some(product.id as i32), "non_delivery", None).await.unwrap();
+    }
+    let resp = test::call_service(&app, update(20.0)).await;
+    assert_eq!(resp.status(), 403);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, "Account suspended due to multiple reports.");
+    let price: f64 = sqlx::query_scalar("SELECT price FROM products WHERE id = $1")
+        .bind(product.id as i32)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(price, 55.0);
+
+    // Featured listings drop the newly suspended vendor's products too
+    let req = test::TestRequest::get().uri("/products/featured").to_request();
+    let featured: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert!(featured.as_array().unwrap().is_empty());
+
+    // Unverified vendors can't edit either
+    let unverified = common::create_user(&pool, "rep_edit_unverified", Role::Vendor).await;
+    let theirs = db::create_product(&pool, "Leeks", 40.0, "Vegetables", "Fresh", 10, None, unverified.id)
+        .await
+        .unwrap();
+    let req = test::TestRequest::patch()
+        .uri(&format!("/products/{}", theirs.id))
+        .insert_header(common::bearer(&unverified))
+        .set_json(json!({ "name": "Leeks", "price": 1.0, "category": "Vegetables", "description": "Fresh", "quantity": 10 }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
+
+#[actix_web::test]
+async fn reports_only_accept_the_report_form_types() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "rep_type_vendor").await;
+    let customer = common::create_user(&pool, "rep_type_customer", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    let report = |report_type: &str| {
+        test::TestRequest::post()
+            .uri("/reports")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "vendor_id": vendor.id, "report_type": report_type, "description": "Never arrived" }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, report("fraud")).await.status(), 400);
+    assert_eq!(test::call_service(&app, report("non_delivery")).await.status(), 201);
+    assert_eq!(db::count_vendor_reports(&pool, vendor.id).await.unwrap(), 1);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use actix_web::test;
+use backend::models::Role;
+use backend::{db, settings};
+use serde_json::{json, Value};
+
+fn request_id(resp: &actix_web::dev::ServiceResponse) -> String {
+    resp.headers().get("x-request-id").unwrap().to_str().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn request_ids_are_echoed_or_generated() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get()
+        .uri("/health")
+        .insert_header(("X-Request-Id", "checkout-debug-42"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(request_id(&resp), "checkout-debug-42");
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/health").to_request()).await;
+    let generated = request_id(&resp);
+    assert_eq!(generated.len(), 32);
+    assert!(generated.chars().all(|c| c.is_ascii_hexdigit()));
+
+    // Unusable ids are replaced rather than passed on to the logs
+    let req = test::TestRequest::get()
+        .uri("/health")
+        .insert_header(("X-Request-Id", "bad id\twith spaces"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_ne!(request_id(&resp), "bad id\twith spaces");
+
+    // JSON error objects carry it too
+    settings::set_setting(&pool, settings::MAINTENANCE_MODE, "true").await.unwrap();
+    let req = test::TestRequest::get()
+        .uri("/products")
+        .insert_header(("X-Request-Id", "during-maintenance"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+    assert_eq!(request_id(&resp), "during-maintenance");
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["maintenance"], true);
+    assert_eq!(body["request_id"], "during-maintenance");
+}
+
+#[actix_web::test]
+async fn checkout_request_id_is_stored_on_the_payment() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "rid_vendor").await;
+    let customer = common::create_user(&pool, "rid_customer", Role::Customer).await;
+    let beans = db::create_product(&pool, "Beans", 120.0, "Legumes", "Rosecoco", 10, None, vendor.id)
+        .await
+        .unwrap();
+    db::add_to_cart(&pool, customer.id, beans.id as i32, 1).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/checkout")
+        .insert_header(common::bearer(&customer))
+        .insert_header(("X-Request-Id", "rid-checkout-1"))
+        .set_json(json!({ "mpesa_number": "0712345678", "total_amount": 120.0 }))
+        .to_request();
+    let checkout: Value = test::call_and_read_body_json(&app, req).await;
+
+    let transaction = db::get_payment_transaction_by_checkout_request_id(&pool, checkout["transaction_id"].as_str().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(transaction.request_id.as_deref(), Some("rid-checkout-1"));
+}
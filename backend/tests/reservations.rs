@@ -0,0 +1,142 @@
+mod common;
+
+use actix_web::test;
+use backend::{db, reservations};
+use backend::models::Role;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+async fn stock(pool: &PgPool, product_id: i32) -> i32 {
+    sqlx::query_scalar("SELECT quantity FROM products WHERE id = $1")
+        .bind(product_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+async fn reservation_statuses(pool: &PgPool) -> Vec<String> {
+    sqlx::query_scalar("SELECT status FROM stock_reservations ORDER BY id")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn concurrent_checkouts_for_the_last_unit_reserve_it_once() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "last_unit_vendor").await;
+    let honey = db::create_product(&pool, "Honey", 350.0, "Pantry", "Last jar", 1, None, vendor.id)
+        .await
+        .unwrap();
+    let honey_id = honey.id as i32;
+    let first_customer = common::create_user(&pool, "last_unit_first", Role::Customer).await;
+    let second_customer = common::create_user(&pool, "last_unit_second", Role::Customer).await;
+
+    let last_jar = [(honey_id, 1)];
+    let (first, second) = tokio::join!(
+        db::reserve_stock(&pool, first_customer.id, "ws_CO_first", &last_jar, 15),
+        db::reserve_stock(&pool, second_customer.id, "ws_CO_second", &last_jar, 15),
+    );
+    assert_eq!([first.is_ok(), second.is_ok()].iter().filter(|ok| **ok).count(), 1);
+    let refused = if first.is_ok() { second } else { first };
+    assert!(matches!(refused, Err(db::ReservationError::OutOfStock { available: 0, .. })));
+
+    assert_eq!(stock(&pool, honey_id).await, 0);
+    assert_eq!(reservation_statuses(&pool).await, vec!["held"]);
+}
+
+#[actix_web::test]
+async fn checkout_refuses_stock_held_by_another_checkout() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "held_vendor").await;
+    let first = common::create_user(&pool, "held_first", Role::Customer).await;
+    let second = common::create_user(&pool, "held_second", Role::Customer).await;
+    let mangoes = db::create_product(&pool, "Mangoes", 60.0, "Fruit", "Ripe mangoes", 3, None, vendor.id)
+        .await
+        .unwrap();
+    let mangoes_id = mangoes.id as i32;
+    db::add_to_cart(&pool, first.id, mangoes_id, 2).await.unwrap();
+    db::add_to_cart(&pool, second.id, mangoes_id, 2).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let checkout = |user| {
+        test::TestRequest::post()
+            .uri("/checkout")
+            .insert_header(common::bearer(user))
+            .set_json(json!({ "mpesa_number": "0712345678", "total_amount": 120.0 }))
+            .to_request()
+    };
+    let (a, b) = tokio::join!(test::call_service(&app, checkout(&first)), test::call_service(&app, checkout(&second)));
+    let mut statuses = [a.status().as_u16(), b.status().as_u16()];
+    statuses.sort();
+    assert_eq!(statuses, [200, 409]);
+    let refused = if a.status() == 409 { a } else { b };
+    let body: Value = test::read_body_json(refused).await;
+    assert_eq!(body["error"], "Insufficient stock");
+    assert_eq!(body["available"], 1);
+
+    // The paid checkout's hold became the sale without being deducted twice
+    assert_eq!(stock(&pool, mangoes_id).await, 1);
+    assert_eq!(reservation_statuses(&pool).await, vec!["converted"]);
+}
+
+#[actix_web::test]
+async fn failed_and_expired_reservations_return_their_stock() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "release_vendor").await;
+    let customer = common::create_user(&pool, "release_customer", Role::Customer).await;
+    let milk = db::create_product(&pool, "Milk", 65.0, "Dairy", "Fresh milk", 5, None, vendor.id)
+        .await
+        .unwrap();
+    let milk_id = milk.id as i32;
+
+    // Cancelling a pending payment releases its hold
+    db::reserve_stock(&pool, customer.id, "HOLD_cancel", &[(milk_id, 2)], 15).await.unwrap();
+    db::attach_stock_reservation(&pool, "HOLD_cancel", "ws_CO_cancel").await.unwrap();
+    db::create_payment_transaction(&pool, customer.id, "ws_CO_cancel", "m1", "254712345678", 130.0, None, None)
+        .await
+        .unwrap();
+    assert_eq!(stock(&pool, milk_id).await, 3);
+    let app = common::init_app(&pool).await;
+    let req = test::TestRequest::post()
+        .uri("/payments/ws_CO_cancel/cancel")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert_eq!(stock(&pool, milk_id).await, 5);
+
+    // Holds past their expiry are swept back into stock, once
+    db::reserve_stock(&pool, vendor.id, "ws_CO_expired", &[(milk_id, 3)], 15).await.unwrap();
+    db::reserve_stock(&pool, customer.id, "ws_CO_live", &[(milk_id, 1)], 15).await.unwrap();
+    sqlx::query("UPDATE stock_reservations SET expires_at = NOW() - INTERVAL '1 minute' WHERE checkout_request_id = 'ws_CO_expired'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stock(&pool, milk_id).await, 1);
+    assert_eq!(reservations::release_expired_reservations(&pool).await.unwrap(), 1);
+    assert_eq!(reservations::release_expired_reservations(&pool).await.unwrap(), 0);
+    assert_eq!(stock(&pool, milk_id).await, 4);
+    assert_eq!(reservation_statuses(&pool).await, vec!["released", "released", "held"]);
+}
+
+#[actix_web::test]
+async fn starting_a_new_checkout_releases_the_customers_earlier_hold() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "rehold_vendor").await;
+    let customer = common::create_user(&pool, "rehold_customer", Role::Customer).await;
+    let other = common::create_user(&pool, "rehold_other", Role::Customer).await;
+    let eggs = db::create_product(&pool, "Eggs", 15.0, "Poultry", "Tray of eggs", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let eggs_id = eggs.id as i32;
+
+    db::reserve_stock(&pool, other.id, "ws_CO_other", &[(eggs_id, 2)], 15).await.unwrap();
+    for reference in ["ws_CO_one", "ws_CO_two", "ws_CO_three"] {
+        db::reserve_stock(&pool, customer.id, reference, &[(eggs_id, 4)], 15).await.unwrap();
+    }
+
+    // Only the customer's latest hold and the other customer's remain
+    assert_eq!(stock(&pool, eggs_id).await, 4);
+    assert_eq!(reservation_statuses(&pool).await, vec!["held", "released", "released", "held"]);
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn only_owning_vendor_can_reply_to_review() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "rev_vendor").await;
+    let other_vendor = common::create_verified_vendor(&pool, "rev_other").await;
+    let customer = common::create_user(&pool, "rev_customer", Role::Customer).await;
+
+    let product = db::create_product(&pool, "Avocados", 25.0, "Fruits", "Hass", 40, None, vendor.id).await.unwrap();
+    let review = db::create_review(&pool, customer.id, product.id as i32, 2, Some("Arrived bruised"))
+        .await
+        .unwrap();
+
+    let app = common::init_app(&pool).await;
+    let uri = format!("/reviews/{}/reply", review.id);
+
+    let req = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(common::bearer(&other_vendor))
+        .set_json(json!({ "content": "Not our product, but sorry!" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "content": "Sorry - we've changed couriers." }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+    // Posting again edits the existing reply
+    let req = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "content": "Sorry - we've changed couriers and sent a replacement." }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/reviews/product/{}", product.id))
+        .to_request();
+    let reviews: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let reply = &reviews[0]["reply"];
+    assert_eq!(reply["vendor_id"], vendor.id);
+    assert_eq!(reply["content"], "Sorry - we've changed couriers and sent a replacement.");
+    assert!(reply["updated_at"].is_string());
+}
+
+#[actix_web::test]
+async fn helpful_votes_count_once_per_user() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "vote_vendor").await;
+    let author = common::create_user(&pool, "vote_author", Role::Customer).await;
+    let first = common::create_user(&pool, "vote_first", Role::Customer).await;
+    let second = common::create_user(&pool, "vote_second", Role::Customer).await;
+
+    let product = db::create_product(&pool, "Mangoes", 30.0, "Fruits", "Apple mango", 40, None, vendor.id).await.unwrap();
+    let older = db::create_review(&pool, author.id, product.id as i32, 5, Some("Sweet and ripe")).await.unwrap();
+    db::create_review(&pool, first.id, product.id as i32, 4, Some("Good")).await.unwrap();
+
+    let app = common::init_app(&pool).await;
+    let uri = format!("/reviews/{}/vote", older.id);
+
+    for voter in [&first, &second, &second] {
+        let req = test::TestRequest::post()
+            .uri(&uri)
+            .insert_header(common::bearer(voter))
+            .set_json(json!({ "helpful": true }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+    }
+
+    // Authors can't vote on their own review
+    let req = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(common::bearer(&author))
+        .set_json(json!({ "helpful": true }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/reviews/product/{}?sort=helpful", product.id))
+        .to_request();
+    let reviews: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(reviews[0]["id"], older.id);
+    assert_eq!(reviews[0]["helpful_count"], 2);
+
+    // Flipping a vote removes it from the helpful count
+    let req = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(common::bearer(&second))
+        .set_json(json!({ "helpful": false }))
+        .to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["data"]["helpful_count"], 1);
+}
+
+#[actix_web::test]
+async fn second_review_of_a_product_conflicts_with_the_first() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "dup_rev_vendor").await;
+    let customer = common::create_user(&pool, "dup_rev_customer", Role::Customer).await;
+    let product = db::create_product(&pool, "Pawpaw", 70.0, "Fruits", "Sweet", 10, None, vendor.id).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let review = |rating: i32, comment: &str| {
+        test::TestRequest::post()
+            .uri("/reviews")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "product_id": product.id, "rating": rating, "comment": comment }))
+            .to_request()
+    };
+    let resp = test::call_service(&app, review(5, "Perfectly ripe")).await;
+    assert_eq!(resp.status(), 201);
+    let first: Value = test::read_body_json(resp).await;
+
+    let resp = test::call_service(&app, review(1, "Changed my mind")).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["review_id"], first["id"]);
+    assert_eq!(body["review"]["rating"], 5);
+    assert_eq!(body["review"]["comment"], "Perfectly ripe");
+    assert!(body["message"].as_str().unwrap().contains("existing review"));
+}
+
+#[actix_web::test]
+async fn reviews_page_sorted_by_highest_rating() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "page_rev_vendor").await;
+    let product = db::create_product(&pool, "Passion Fruit", 10.0, "Fruits", "Purple", 50, None, vendor.id).await.unwrap();
+    for (n, rating) in [3, 5, 1, 4, 2].into_iter().enumerate() {
+        let customer = common::create_user(&pool, &format!("page_rev_customer{}", n), Role::Customer).await;
+        db::create_review(&pool, customer.id, product.id as i32, rating, None).await.unwrap();
+    }
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/reviews/product/{}?sort=highest&limit=3", product.id))
+        .to_request();
+    let page: Value = test::call_and_read_body_json(&app, req).await;
+    let ratings: Vec<i64> = page["items"].as_array().unwrap().iter().map(|r| r["rating"].as_i64().unwrap()).collect();
+    assert_eq!(ratings, vec![5, 4, 3]);
+    assert_eq!(page["total"], 5);
+    assert_eq!(page["has_more"], true);
+    assert_eq!(page["summary"], json!({ "average_rating": 3.0, "review_count": 5 }));
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/reviews/product/{}?sort=lowest&limit=2&offset=1", product.id))
+        .to_request();
+    let page: Value = test::call_and_read_body_json(&app, req).await;
+    let ratings: Vec<i64> = page["items"].as_array().unwrap().iter().map(|r| r["rating"].as_i64().unwrap()).collect();
+    assert_eq!(ratings, vec![2, 3]);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/reviews/product/{}?sort=loudest", product.id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+}
@@ -0,0 +1,206 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::Value;
+
+#[actix_web::test]
+async fn suggestions_match_prefix_and_ignore_short_queries() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "suggest_vendor").await;
+    let customer = common::create_user(&pool, "suggest_customer", Role::Customer).await;
+    let tomato = db::create_product(&pool, "Tomatoes", 50.0, "Vegetables", "Ripe", 100, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_product(&pool, "Tomato Paste", 120.0, "Pantry", "Homemade", 100, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_product(&pool, "Potatoes", 60.0, "Vegetables", "Shangi", 100, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_product(&pool, "100% Honey", 300.0, "Pantry", "Raw", 100, None, vendor.id)
+        .await
+        .unwrap();
+    // Tomatoes sell more, so they rank first
+    db::create_shipping_order(&pool, customer.id, tomato.id as i32, 5, "Thika").await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get().uri("/products/suggest?q=toM").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["names"], serde_json::json!(["Tomatoes", "Tomato Paste"]));
+
+    let req = test::TestRequest::get().uri("/products/suggest?q=veg").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["categories"], serde_json::json!(["Vegetables"]));
+    assert!(body["names"].as_array().unwrap().is_empty());
+
+    // Wildcards are matched literally
+    let req = test::TestRequest::get().uri("/products/suggest?q=100%25").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["names"], serde_json::json!(["100% Honey"]));
+    let req = test::TestRequest::get().uri("/products/suggest?q=%25o").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert!(body["names"].as_array().unwrap().is_empty());
+
+    for uri in ["/products/suggest?q=t", "/products/suggest", "/products/suggest?q=%20%20"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert!(body["names"].as_array().unwrap().is_empty());
+        assert!(body["categories"].as_array().unwrap().is_empty());
+    }
+}
+
+#[actix_web::test]
+async fn comparison_aligns_prices_and_ratings() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "compare_vendor").await;
+    let customer = common::create_user(&pool, "compare_customer", Role::Customer).await;
+    let other = common::create_user(&pool, "compare_other", Role::Customer).await;
+    let kale = db::create_product(&pool, "Kale", 45.0, "Vegetables", "Curly", 20, None, vendor.id)
+        .await
+        .unwrap();
+    let spinach = db::create_product(&pool, "Spinach", 30.0, "Vegetables", "Baby leaves", 8, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_review(&pool, customer.id, kale.id as i32, 5, None).await.unwrap();
+    db::create_review(&pool, other.id, kale.id as i32, 4, None).await.unwrap();
+    db::create_review(&pool, customer.id, spinach.id as i32, 3, None).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/products/compare")
+        .set_json(serde_json::json!({ "ids": [spinach.id, kale.id, 999_999] }))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let products = body["products"].as_array().unwrap();
+    assert_eq!(products.len(), 2);
+    assert_eq!(products[0]["name"], "Spinach");
+    assert_eq!(products[0]["price"], 30.0);
+    assert_eq!(products[0]["average_rating"], 3.0);
+    assert_eq!(products[0]["stock"], 8);
+    assert_eq!(products[1]["name"], "Kale");
+    assert_eq!(products[1]["price"], 45.0);
+    assert_eq!(products[1]["average_rating"], 4.5);
+    assert_eq!(products[1]["vendor_name"], "compare_vendor");
+    assert_eq!(body["missing_ids"], serde_json::json!([999_999]));
+
+    let req = test::TestRequest::post()
+        .uri("/products/compare")
+        .set_json(serde_json::json!({ "ids": [1, 2, 3, 4, 5, 6] }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+}
+
+#[actix_web::test]
+async fn similar_products_share_category_and_skip_source() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "similar_vendor").await;
+    let customer = common::create_user(&pool, "similar_customer", Role::Customer).await;
+    let mango = db::create_product(&pool, "Mango", 30.0, "Fruit", "Apple mango", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let papaya = db::create_product(&pool, "Papaya", 60.0, "Fruit", "Solo", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let banana = db::create_product(&pool, "Banana", 10.0, "fruit", "Sweet", 10, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_product(&pool, "Sold-out Lemon", 15.0, "Fruit", "Gone", 0, None, vendor.id)
+        .await
+        .unwrap();
+    let jam = db::create_product(&pool, "Mango Jam", 250.0, "Preserves", "Homemade", 10, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_product(&pool, "Cabbage", 40.0, "Vegetables", "Green", 10, None, vendor.id)
+        .await
+        .unwrap();
+    db::set_product_tags(&pool, mango.id as i32, &["mango".to_string()]).await.unwrap();
+    db::set_product_tags(&pool, jam.id as i32, &["mango".to_string()]).await.unwrap();
+    // Better rated products come first
+    db::create_review(&pool, customer.id, banana.id as i32, 5, None).await.unwrap();
+    db::create_review(&pool, customer.id, papaya.id as i32, 2, None).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get().uri(&format!("/products/{}/similar", mango.id)).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["match"], "category");
+    let names: Vec<&str> = body["products"].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["Banana", "Papaya"]);
+
+    // The only product in its category falls back to products sharing a tag
+    let req = test::TestRequest::get().uri(&format!("/products/{}/similar", jam.id)).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["match"], "tags");
+    assert_eq!(body["products"][0]["name"], "Mango");
+
+    let req = test::TestRequest::get().uri("/products/999999/similar").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn vendor_profile_includes_distance_when_both_have_coordinates() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "distance_vendor").await;
+    let customer = common::create_user(&pool, "distance_customer", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    let profile = || {
+        test::TestRequest::get()
+            .uri(&format!("/vendors/{}/profile", vendor.id))
+            .insert_header(common::bearer(&customer))
+            .to_request()
+    };
+
+    // Nakuru vendor, customer without a location yet
+    sqlx::query("UPDATE users SET latitude = -0.3031, longitude = 36.0800 WHERE id = $1")
+        .bind(vendor.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let body: Value = test::call_and_read_body_json(&app, profile()).await;
+    assert_eq!(body["username"], "distance_vendor");
+    assert!(body.get("distance_km").is_none());
+
+    // Nairobi customer
+    sqlx::query("UPDATE users SET latitude = -1.2921, longitude = 36.8219 WHERE id = $1")
+        .bind(customer.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let body: Value = test::call_and_read_body_json(&app, profile()).await;
+    assert_eq!(body["distance_km"].as_f64(), Some(137.5));
+
+    // Still requires a signed-in viewer
+    let req = test::TestRequest::get().uri(&format!("/vendors/{}/profile", vendor.id)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+}
+
+#[actix_web::test]
+async fn trending_ranks_recent_orders_above_views() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "trending_vendor").await;
+    let customer = common::create_user(&pool, "trending_customer", Role::Customer).await;
+    let honey = db::create_product(&pool, "Honey", 300.0, "Pantry", "Raw", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let eggs = db::create_product(&pool, "Eggs", 15.0, "Dairy", "Free range", 10, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_product(&pool, "Quiet Kale", 45.0, "Vegetables", "Curly", 10, None, vendor.id)
+        .await
+        .unwrap();
+    db::create_shipping_order(&pool, customer.id, honey.id as i32, 1, "Thika").await.unwrap();
+    db::record_product_view(&pool, eggs.id as i32, None, Some("10.0.0.1"), 30).await.unwrap();
+    db::record_product_view(&pool, eggs.id as i32, None, Some("10.0.0.2"), 30).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get().uri("/products/trending").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let names: Vec<&str> = body.as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+    // Products without recent activity aren't trending at all
+    assert_eq!(names, vec!["Honey", "Eggs"]);
+    assert_eq!(body[0]["recent_orders"], 1);
+    assert_eq!(body[1]["recent_views"], 2);
+    assert_eq!(body[1]["favorites"], 0);
+}
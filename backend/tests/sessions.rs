@@ -0,0 +1,79 @@
+mod common;
+
+use actix_web::test;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn revoked_sessions_can_no_longer_refresh() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let customer = common::create_user(&pool, "session_customer", Role::Customer).await;
+    let admin = common::create_user(&pool, "session_admin", Role::Admin).await;
+    let app = common::init_app(&pool).await;
+
+    let mut refresh_tokens = Vec::new();
+    for device in ["Phone", "Laptop"] {
+        let req = test::TestRequest::post()
+            .uri("/login")
+            .insert_header(("User-Agent", device))
+            .set_json(json!({ "username": "session_customer", "password": "password123" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        refresh_tokens.push(body["refresh_token"].as_str().unwrap().to_string());
+    }
+    let refresh = |token: &str| {
+        test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(json!({ "refresh_token": token }))
+            .to_request()
+    };
+
+    // Refreshing rotates the token
+    let resp = test::call_service(&app, refresh(&refresh_tokens[0])).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body["token"].is_string());
+    assert_eq!(test::call_service(&app, refresh(&refresh_tokens[0])).await.status(), 401);
+    refresh_tokens[0] = body["refresh_token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::get()
+        .uri("/account/sessions")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let sessions: Value = test::call_and_read_body_json(&app, req).await;
+    let sessions = sessions.as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    // The refreshed phone session was used most recently
+    assert_eq!(sessions[0]["user_agent"], "Phone");
+    let phone = sessions[0]["id"].as_i64().unwrap();
+
+    // Other users can't revoke it
+    let req = test::TestRequest::delete()
+        .uri(&format!("/account/sessions/{}", phone))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/account/sessions/{}", phone))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert_eq!(test::call_service(&app, refresh(&refresh_tokens[0])).await.status(), 401);
+
+    // An admin signs the customer out everywhere
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/admin/users/{}/sessions", customer.id))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["revoked"], 1);
+    assert_eq!(test::call_service(&app, refresh(&refresh_tokens[1])).await.status(), 401);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/admin/users/{}/sessions", customer.id))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    let sessions: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(sessions, json!([]));
+}
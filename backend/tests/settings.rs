@@ -0,0 +1,82 @@
+mod common;
+
+use actix_web::test;
+use backend::models::Role;
+use backend::settings;
+use serde_json::json;
+
+#[actix_web::test]
+async fn updated_setting_is_visible_through_cached_accessor() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "settings_admin", Role::Admin).await;
+
+    // Prime the cache with the default value
+    assert_eq!(settings::get_i64(&pool, settings::CART_REMINDER_HOURS).await, 24);
+
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::patch()
+        .uri("/api/admin/settings")
+        .insert_header(common::bearer(&admin))
+        .set_json(json!({ "cart_reminder_hours": 12, "vat_rate": "0.14" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    assert_eq!(settings::get_i64(&pool, settings::CART_REMINDER_HOURS).await, 12);
+    assert_eq!(settings::get_f64(&pool, settings::VAT_RATE).await, 0.14);
+
+    // Persisted too: a cold cache reloads the same values from the database
+    settings::invalidate(&pool);
+    assert_eq!(settings::get_i64(&pool, settings::CART_REMINDER_HOURS).await, 12);
+}
+
+#[actix_web::test]
+async fn known_settings_are_type_checked() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "settings_admin2", Role::Admin).await;
+
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::patch()
+        .uri("/api/admin/settings")
+        .insert_header(common::bearer(&admin))
+        .set_json(json!({ "maintenance_mode": "sometimes" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    assert!(!settings::get_bool(&pool, settings::MAINTENANCE_MODE).await);
+}
+
+#[actix_web::test]
+async fn known_settings_are_range_checked() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "settings_admin3", Role::Admin).await;
+
+    let app = common::init_app(&pool).await;
+
+    let update = |body| {
+        test::TestRequest::patch()
+            .uri("/api/admin/settings")
+            .insert_header(common::bearer(&admin))
+            .set_json(body)
+            .to_request()
+    };
+    for body in [
+        json!({ "vat_rate": -0.5 }),
+        json!({ "vat_rate": 1.5 }),
+        json!({ "max_cart_items": 0 }),
+        json!({ "order_acceptance_hours": -1 }),
+        json!({ "min_withdrawal_amount": 0 }),
+        json!({ "usd_exchange_rate": "-129" }),
+        json!({ "verification_document_retention_days": -1 }),
+    ] {
+        assert_eq!(test::call_service(&app, update(body.clone())).await.status(), 400, "{}", body);
+    }
+    assert_eq!(settings::get_f64(&pool, settings::VAT_RATE).await, 0.16);
+    assert_eq!(settings::get_i64(&pool, settings::MAX_CART_ITEMS).await, 50);
+
+    // The ends of each range are fine
+    let body = json!({ "vat_rate": 0, "max_cart_items": 1, "verification_document_retention_days": 0 });
+    assert_eq!(test::call_service(&app, update(body)).await.status(), 200);
+}
@@ -0,0 +1,460 @@
+mod common;
+
+use actix_web::test;
+use backend::{acceptance, db};
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn bulk_status_with_foreign_order() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "bulk_vendor").await;
+    let other_vendor = common::create_verified_vendor(&pool, "bulk_other").await;
+    let customer = common::create_user(&pool, "bulk_customer", Role::Customer).await;
+
+    let mine = db::create_product(&pool, "Tomatoes", 20.0, "Vegetables", "Ripe", 100, None, vendor.id)
+        .await
+        .unwrap();
+    let theirs = db::create_product(&pool, "Onions", 15.0, "Vegetables", "Red", 100, None, other_vendor.id)
+        .await
+        .unwrap();
+    let first = db::create_shipping_order(&pool, customer.id, mine.id as i32, 1, "Eldoret").await.unwrap();
+    let second = db::create_shipping_order(&pool, customer.id, mine.id as i32, 2, "Eldoret").await.unwrap();
+    let foreign = db::create_shipping_order(&pool, customer.id, theirs.id as i32, 1, "Eldoret").await.unwrap();
+
+    let app = common::init_app(&pool).await;
+    let order_ids = vec![first.id, second.id, foreign.id];
+
+    // By default one foreign order rejects the whole batch
+    let req = test::TestRequest::patch()
+        .uri("/shipping/bulk-status")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "order_ids": order_ids, "shipping_status": "delivered" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["order_ids"], json!([foreign.id]));
+    let orders = db::get_vendor_shipping_orders(&pool, vendor.id).await.unwrap();
+    assert!(orders.iter().all(|o| o.shipping_status != "delivered"));
+
+    // Unknown statuses are rejected before anything is written
+    let req = test::TestRequest::patch()
+        .uri("/shipping/bulk-status")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "order_ids": [first.id], "shipping_status": "lost_in_transit" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+    let req = test::TestRequest::patch()
+        .uri(&format!("/shipping/{}/status", first.id))
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "shipping_status": "pending" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    // Orders have to be accepted before they can be delivered
+    let req = test::TestRequest::patch()
+        .uri("/shipping/bulk-status")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "order_ids": [first.id], "shipping_status": "delivered" }))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["updated"], 0);
+    assert_eq!(body["data"]["results"][0]["error"], "Order must be accepted first");
+    // The single-order route applies the same rule
+    let req = test::TestRequest::patch()
+        .uri(&format!("/shipping/{}/status", second.id))
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "shipping_status": "Shipped" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, "Order must be accepted first");
+    assert!(db::accept_pending_order(&pool, first.id).await.unwrap());
+    assert!(db::accept_pending_order(&pool, second.id).await.unwrap());
+
+    // With skip_foreign the vendor's own orders are updated and the foreign one reported
+    let req = test::TestRequest::patch()
+        .uri("/shipping/bulk-status")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "order_ids": order_ids, "shipping_status": "delivered", "skip_foreign": true }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["updated"], 2);
+    let results = body["data"]["results"].as_array().unwrap();
+    let foreign_result = results.iter().find(|r| r["order_id"] == foreign.id).unwrap();
+    assert_eq!(foreign_result["success"], false);
+    assert_eq!(foreign_result["error"], "Order belongs to another vendor");
+
+    let orders = db::get_vendor_shipping_orders(&pool, vendor.id).await.unwrap();
+    assert_eq!(orders.len(), 2);
+    for order in &orders {
+        assert_eq!(order.shipping_status, "delivered");
+        assert!(order.verification_requested_at.is_some());
+    }
+    let untouched = db::get_vendor_shipping_orders(&pool, other_vendor.id).await.unwrap();
+    assert_ne!(untouched[0].shipping_status, "delivered");
+}
+
+#[actix_web::test]
+async fn customer_cancels_pending_order_but_not_shipped_one() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "cancel_vendor").await;
+    let customer = common::create_user(&pool, "cancel_customer", Role::Customer).await;
+    let stranger = common::create_user(&pool, "cancel_stranger", Role::Customer).await;
+    let honey = db::create_product(&pool, "Honey", 400.0, "Pantry", "Raw", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let pending = db::create_shipping_order(&pool, customer.id, honey.id as i32, 3, "Nyeri").await.unwrap();
+    let shipped = db::create_shipping_order(&pool, customer.id, honey.id as i32, 2, "Nyeri").await.unwrap();
+    db::update_shipping_status(&pool, shipped.id, "shipped", Some("TRK1")).await.unwrap();
+    let payment = db::create_payment_transaction(&pool, customer.id, "ws_CO_cancel", "mr_cancel", "254700000000", 1200.0, None, None)
+        .await
+        .unwrap();
+    db::set_order_payment_transaction(&pool, pending.id, payment).await.unwrap();
+    let stock = || async {
+        sqlx::query_scalar::<_, i32>("SELECT quantity FROM products WHERE id = $1")
+            .bind(honey.id as i32)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+    };
+    assert_eq!(stock().await, 5);
+    let app = common::init_app(&pool).await;
+
+    let cancel = |order_id: i32, user: &backend::models::User| {
+        test::TestRequest::post()
+            .uri(&format!("/shipping/{}/cancel", order_id))
+            .insert_header(common::bearer(user))
+            .to_request()
+    };
+
+    assert_eq!(test::call_service(&app, cancel(pending.id, &stranger)).await.status(), 403);
+
+    let resp = test::call_service(&app, cancel(pending.id, &customer)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["refunded"], 1200.0);
+    assert_eq!(stock().await, 8);
+    assert_eq!(db::get_wallet_balance(&pool, customer.id).await.unwrap(), 1200.0);
+
+    // Cancelling twice or after shipping is refused and leaves stock alone
+    assert_eq!(test::call_service(&app, cancel(pending.id, &customer)).await.status(), 409);
+    assert_eq!(test::call_service(&app, cancel(shipped.id, &customer)).await.status(), 409);
+    assert_eq!(stock().await, 8);
+}
+
+#[actix_web::test]
+async fn vendor_cancellation_restocks_exactly_once() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "restock_vendor").await;
+    let customer = common::create_user(&pool, "restock_customer", Role::Customer).await;
+    let eggs = db::create_product(&pool, "Eggs", 15.0, "Poultry", "Tray", 30, None, vendor.id)
+        .await
+        .unwrap();
+    let single = db::create_shipping_order(&pool, customer.id, eggs.id as i32, 4, "Kisumu").await.unwrap();
+    let bulk = db::create_shipping_order(&pool, customer.id, eggs.id as i32, 6, "Kisumu").await.unwrap();
+    let stock = || async {
+        sqlx::query_scalar::<_, i32>("SELECT quantity FROM products WHERE id = $1")
+            .bind(eggs.id as i32)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+    };
+    assert_eq!(stock().await, 20);
+    let app = common::init_app(&pool).await;
+
+    // Cancelling the same order twice only returns its quantity once
+    for _ in 0..2 {
+        let req = test::TestRequest::patch()
+            .uri(&format!("/shipping/{}/status", single.id))
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "shipping_status": "cancelled" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+    }
+    assert_eq!(stock().await, 24);
+
+    let req = test::TestRequest::patch()
+        .uri("/shipping/bulk-status")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "order_ids": [bulk.id, single.id], "shipping_status": "cancelled" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert_eq!(stock().await, 30);
+}
+
+#[actix_web::test]
+async fn admin_order_search_filters_by_vendor_and_status() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "orders_admin", Role::Admin).await;
+    let vendor = common::create_verified_vendor(&pool, "orders_vendor").await;
+    let other_vendor = common::create_verified_vendor(&pool, "orders_other").await;
+    let customer = common::create_user(&pool, "orders_customer", Role::Customer).await;
+    let beans = db::create_product(&pool, "Beans", 100.0, "Legumes", "Rosecoco", 100, None, vendor.id)
+        .await
+        .unwrap();
+    let peas = db::create_product(&pool, "Peas", 80.0, "Legumes", "Green", 100, None, other_vendor.id)
+        .await
+        .unwrap();
+    let shipped = db::create_shipping_order(&pool, customer.id, beans.id as i32, 1, "Kisumu").await.unwrap();
+    db::create_shipping_order(&pool, customer.id, beans.id as i32, 2, "Kisumu").await.unwrap();
+    let other = db::create_shipping_order(&pool, customer.id, peas.id as i32, 1, "Kisumu").await.unwrap();
+    db::update_shipping_status(&pool, shipped.id, "shipped", None).await.unwrap();
+    db::update_shipping_status(&pool, other.id, "shipped", None).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let search = |query: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/admin/orders?{}", query))
+            .insert_header(common::bearer(&admin))
+            .to_request()
+    };
+
+    let body: Value = test::call_and_read_body_json(&app, search("")).await;
+    assert_eq!(body["total"], 3);
+
+    let body: Value = test::call_and_read_body_json(&app, search(&format!("vendor_id={}", vendor.id))).await;
+    assert_eq!(body["total"], 2);
+
+    let body: Value =
+        test::call_and_read_body_json(&app, search(&format!("vendor_id={}&status=shipped", vendor.id))).await;
+    assert_eq!(body["total"], 1);
+    let orders = body["orders"].as_array().unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0]["id"], shipped.id);
+    assert_eq!(orders[0]["vendor_username"], "orders_vendor");
+    assert_eq!(orders[0]["product_name"], "Beans");
+
+    // Pages share the total
+    let body: Value = test::call_and_read_body_json(&app, search("per_page=2&page=2")).await;
+    assert_eq!(body["total"], 3);
+    assert_eq!(body["orders"].as_array().unwrap().len(), 1);
+
+    assert_eq!(test::call_service(&app, search("from=yesterday")).await.status(), 400);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/orders")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
+
+#[actix_web::test]
+async fn multi_item_order_reports_each_item_status() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let customer = common::create_user(&pool, "multi_customer", Role::Customer).await;
+    let stranger = common::create_user(&pool, "multi_stranger", Role::Customer).await;
+    let first_vendor = common::create_verified_vendor(&pool, "multi_vendor_one").await;
+    let second_vendor = common::create_verified_vendor(&pool, "multi_vendor_two").await;
+    let onions = db::create_product(&pool, "Onions", 30.0, "Vegetables", "Red", 20, None, first_vendor.id)
+        .await
+        .unwrap();
+    let garlic = db::create_product(&pool, "Garlic", 15.0, "Vegetables", "Bulbs", 20, None, second_vendor.id)
+        .await
+        .unwrap();
+
+    let order_id = db::create_order(&pool, customer.id, "Kisumu", None).await.unwrap();
+    let shipped = db::add_order_item(&pool, order_id, onions.id as i32, 2).await.unwrap();
+    let waiting = db::add_order_item(&pool, order_id, garlic.id as i32, 4).await.unwrap();
+    assert_eq!(shipped.order_id, Some(order_id));
+    assert_eq!(waiting.shipping_address.as_deref(), Some("Kisumu"));
+    db::update_shipping_status(&pool, shipped.id, "shipped", Some("TRK-1")).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/orders/{}", order_id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let order: Value = test::read_body_json(resp).await;
+    assert_eq!(order["status"], "partially_fulfilled");
+    assert_eq!(order["items_total"], 120.0);
+    let items = order["items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0]["id"], shipped.id);
+    assert_eq!(items[0]["shipping_status"], "shipped");
+    assert_eq!(items[0]["tracking_number"], "TRK-1");
+    assert_eq!(items[0]["vendor_username"], "multi_vendor_one");
+    assert_eq!(items[1]["id"], waiting.id);
+    assert_eq!(items[1]["shipping_status"], "pending");
+    assert_eq!(items[1]["tracking_number"], Value::Null);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/orders/{}", order_id))
+        .insert_header(common::bearer(&stranger))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+    let req = test::TestRequest::get()
+        .uri("/orders/999999")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn existing_shipping_orders_are_migrated_into_orders() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let customer = common::create_user(&pool, "legacy_customer", Role::Customer).await;
+    let vendor = common::create_verified_vendor(&pool, "legacy_vendor").await;
+    let product = db::create_product(&pool, "Millet", 90.0, "Grains", "Finger millet", 20, None, vendor.id)
+        .await
+        .unwrap();
+    // Rows as they looked before orders existed
+    let mut legacy = Vec::new();
+    for _ in 0..2 {
+        let order = db::create_shipping_order(&pool, customer.id, product.id as i32, 1, "Eldoret").await.unwrap();
+        legacy.push(order.id);
+    }
+    sqlx::query("UPDATE shipping_orders SET order_id = NULL").execute(&pool).await.unwrap();
+    sqlx::query("DELETE FROM orders").execute(&pool).await.unwrap();
+
+    db::init_schema(&pool).await;
+
+    let parents: Vec<Option<i32>> = sqlx::query_scalar("SELECT order_id FROM shipping_orders ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert!(parents.iter().all(Option::is_some));
+    assert_ne!(parents[0], parents[1]);
+    let order = db::get_order(&pool, parents[0].unwrap()).await.unwrap().unwrap();
+    assert_eq!(order.customer_id, customer.id);
+    assert_eq!(order.items.len(), 1);
+    assert_eq!(order.items[0].id, legacy[0]);
+}
+
+#[actix_web::test]
+async fn delivered_order_shows_its_mpesa_receipt() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let customer = common::create_user(&pool, "receipt_customer", Role::Customer).await;
+    let vendor = common::create_verified_vendor(&pool, "receipt_vendor").await;
+    let millet = db::create_product(&pool, "Millet", 90.0, "Grains", "Finger millet", 20, None, vendor.id)
+        .await
+        .unwrap();
+    let payment = db::create_payment_transaction(&pool, customer.id, "ws_CO_receipt", "mr_receipt", "254700000000", 180.0, None, None)
+        .await
+        .unwrap();
+    db::update_payment_transaction(&pool, "ws_CO_receipt", "completed", Some("QKX1RCPT23"), Some("20260101120000"))
+        .await
+        .unwrap();
+    let order_id = db::create_order(&pool, customer.id, "Nakuru", Some(payment)).await.unwrap();
+    let item = db::add_order_item(&pool, order_id, millet.id as i32, 2).await.unwrap();
+    db::set_order_payment_transaction(&pool, item.id, payment).await.unwrap();
+    db::update_shipping_status(&pool, item.id, "delivered", Some("TRK-R")).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get()
+        .uri("/shipping")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let orders: Value = test::call_and_read_body_json(&app, req).await;
+    let order = orders.as_array().unwrap().iter().find(|o| o["id"] == item.id).unwrap();
+    assert_eq!(order["shipping_status"], "delivered");
+    assert_eq!(order["mpesa_receipt_number"], "QKX1RCPT23");
+    // M-Pesa reports Nairobi time (UTC+3)
+    assert_eq!(order["transaction_date"], "2026-01-01T09:00:00+00:00");
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/orders/{}", order_id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let order: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(order["mpesa_receipt_number"], "QKX1RCPT23");
+
+    let req = test::TestRequest::get()
+        .uri("/shipping/vendor")
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    let orders: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(orders[0]["mpesa_receipt_number"], "QKX1RCPT23");
+}
+
+#[actix_web::test]
+async fn vendor_declines_or_lets_orders_lapse() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "accept_vendor").await;
+    let customer = common::create_user(&pool, "accept_customer", Role::Customer).await;
+    let mangoes = db::create_product(&pool, "Mangoes", 50.0, "Fruits", "Apple mangoes", 20, None, vendor.id)
+        .await
+        .unwrap();
+    let declined = db::create_shipping_order(&pool, customer.id, mangoes.id as i32, 4, "Machakos").await.unwrap();
+    let lapsed = db::create_shipping_order(&pool, customer.id, mangoes.id as i32, 2, "Machakos").await.unwrap();
+    let accepted = db::create_shipping_order(&pool, customer.id, mangoes.id as i32, 1, "Machakos").await.unwrap();
+    for (order, reference) in [(&declined, "ws_CO_decline"), (&lapsed, "ws_CO_lapse")] {
+        let payment = db::create_payment_transaction(&pool, customer.id, reference, reference, "254700000000", order.total_amount, None, None)
+            .await
+            .unwrap();
+        db::set_order_payment_transaction(&pool, order.id, payment).await.unwrap();
+    }
+    let stock = || async {
+        sqlx::query_scalar::<_, i32>("SELECT quantity FROM products WHERE id = $1")
+            .bind(mangoes.id as i32)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+    };
+    assert_eq!(stock().await, 13);
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::get()
+        .uri("/shipping/vendor/pending-acceptance")
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    let pending: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(pending["count"], 3);
+    assert_eq!(pending["orders"][0]["id"], declined.id);
+
+    // Shipping needs acceptance first
+    let ship = test::TestRequest::patch()
+        .uri(&format!("/shipping/{}/status", accepted.id))
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "shipping_status": "shipped" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, ship).await.status(), 409);
+    let req = test::TestRequest::post()
+        .uri(&format!("/shipping/{}/accept", accepted.id))
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["shipping_status"], "processing");
+
+    // Declining restocks and refunds
+    let decline = |order_id: i32| {
+        test::TestRequest::post()
+            .uri(&format!("/shipping/{}/decline", order_id))
+            .insert_header(common::bearer(&vendor))
+            .to_request()
+    };
+    let resp = test::call_service(&app, decline(declined.id)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["refunded"], 200.0);
+    assert_eq!(stock().await, 17);
+    assert_eq!(db::get_wallet_balance(&pool, customer.id).await.unwrap(), 200.0);
+    assert_eq!(test::call_service(&app, decline(declined.id)).await.status(), 409);
+    assert_eq!(test::call_service(&app, decline(accepted.id)).await.status(), 409);
+
+    // Within the window nothing lapses; past it the order is declined the same way
+    assert_eq!(acceptance::decline_overdue_orders(&pool).await.unwrap(), 0);
+    sqlx::query("UPDATE shipping_orders SET created_at = NOW() - INTERVAL '49 hours' WHERE id = ANY($1)")
+        .bind(vec![lapsed.id, accepted.id])
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(acceptance::decline_overdue_orders(&pool).await.unwrap(), 1);
+    assert_eq!(stock().await, 19);
+    assert_eq!(db::get_wallet_balance(&pool, customer.id).await.unwrap(), 300.0);
+
+    let statuses: Vec<String> = db::get_vendor_shipping_orders(&pool, vendor.id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|o| o.shipping_status)
+        .collect();
+    assert!(statuses.contains(&"processing".to_string()));
+    assert_eq!(statuses.iter().filter(|s| *s == "declined").count(), 2);
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn free_over_threshold_waives_shipping_fee() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "ship_opt_vendor").await;
+    let customer = common::create_user(&pool, "ship_opt_customer", Role::Customer).await;
+    let honey = db::create_product(&pool, "Honey", 200.0, "Pantry", "Raw honey", 50, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/vendor/shipping-options")
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "name": "Courier", "method": "free_over", "fee": 100.0, "free_threshold": 500.0 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let option: Value = test::read_body_json(resp).await;
+
+    // Below the threshold: KSh 400 of honey pays the fee
+    db::add_to_cart(&pool, customer.id, honey.id as i32, 2).await.unwrap();
+    let req = test::TestRequest::get()
+        .uri("/cart/summary")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let summary: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(summary["vendors"][0]["selected_option_id"], option["id"]);
+    assert_eq!(summary["shipping_total"].as_f64().unwrap(), 100.0);
+    assert_eq!(summary["grand_total"].as_f64().unwrap(), 500.0);
+
+    // At KSh 600 the fee is waived
+    db::add_to_cart(&pool, customer.id, honey.id as i32, 1).await.unwrap();
+    let req = test::TestRequest::get()
+        .uri("/cart/summary")
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let summary: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(summary["items_total"].as_f64().unwrap(), 600.0);
+    assert_eq!(summary["shipping_total"].as_f64().unwrap(), 0.0);
+
+    let req = test::TestRequest::post()
+        .uri("/checkout")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({
+            "mpesa_number": "0712345678",
+            "total_amount": 600.0,
+            "shipping_selections": [{ "vendor_id": vendor.id, "option_id": option["id"] }]
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let checkout: Value = test::read_body_json(resp).await;
+    assert_eq!(checkout["shipping_total"].as_f64().unwrap(), 0.0);
+
+    let orders = db::get_customer_shipping_orders(&pool, customer.id).await.unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].shipping_fee, 0.0);
+}
+
+#[actix_web::test]
+async fn checkout_records_fee_and_rejects_foreign_option() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "ship_fee_vendor").await;
+    let other_vendor = common::create_verified_vendor(&pool, "ship_fee_other").await;
+    let customer = common::create_user(&pool, "ship_fee_customer", Role::Customer).await;
+    let milk = db::create_product(&pool, "Milk", 60.0, "Dairy", "Fresh milk", 50, None, vendor.id)
+        .await
+        .unwrap();
+    db::add_to_cart(&pool, customer.id, milk.id as i32, 2).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let mut option_ids = Vec::new();
+    for seller in [&vendor, &other_vendor] {
+        let req = test::TestRequest::post()
+            .uri("/vendor/shipping-options")
+            .insert_header(common::bearer(seller))
+            .set_json(json!({ "name": "Boda", "method": "flat", "fee": 80.0 }))
+            .to_request();
+        let option: Value = test::call_and_read_body_json(&app, req).await;
+        option_ids.push(option["id"].clone());
+    }
+
+    // Another vendor's option can't be used for this vendor's items
+    let req = test::TestRequest::post()
+        .uri("/checkout")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({
+            "mpesa_number": "0712345678",
+            "total_amount": 200.0,
+            "shipping_selections": [{ "vendor_id": vendor.id, "option_id": option_ids[1] }]
+        }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let req = test::TestRequest::post()
+        .uri("/checkout")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({
+            "mpesa_number": "0712345678",
+            "total_amount": 200.0,
+            "shipping_selections": [{ "vendor_id": vendor.id, "option_id": option_ids[0] }]
+        }))
+        .to_request();
+    let checkout: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(checkout["shipping_total"].as_f64().unwrap(), 80.0);
+
+    let orders = db::get_customer_shipping_orders(&pool, customer.id).await.unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].shipping_fee, 80.0);
+
+    // Releasing the payment pays the vendor for goods and shipping, held
+    // until the monthly sweep since vendors default to monthly payouts
+    db::verify_delivery_and_release_payment(&pool, orders[0].id, customer.id).await.unwrap();
+    let (available, pending) = db::get_wallet_balances(&pool, vendor.id).await.unwrap();
+    assert_eq!(available, 0.0);
+    assert_eq!(pending, 200.0);
+}
+
+#[actix_web::test]
+async fn vendor_minimum_order_value_blocks_smaller_checkouts() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "min_order_vendor").await;
+    let customer = common::create_user(&pool, "min_order_customer", Role::Customer).await;
+    let eggs = db::create_product(&pool, "Eggs", 60.0, "Dairy", "Tray of eggs", 50, None, vendor.id)
+        .await
+        .unwrap();
+    db::add_to_cart(&pool, customer.id, eggs.id as i32, 2).await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let set_minimum = |user, minimum: f64| {
+        test::TestRequest::patch()
+            .uri("/profile")
+            .insert_header(common::bearer(user))
+            .set_json(json!({ "min_order_value": minimum }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, set_minimum(&customer, 100.0)).await.status(), 400);
+    assert_eq!(test::call_service(&app, set_minimum(&vendor, -1.0)).await.status(), 400);
+    assert_eq!(test::call_service(&app, set_minimum(&vendor, 300.0)).await.status(), 200);
+
+    // Shown on the vendor's storefront profile
+    let req = test::TestRequest::get()
+        .uri(&format!("/vendors/{}/profile", vendor.id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let profile: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(profile["min_order_value"], 300.0);
+
+    let checkout = || {
+        test::TestRequest::post()
+            .uri("/checkout")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "mpesa_number": "0712345678", "total_amount": 300.0 }))
+            .to_request()
+    };
+    let resp = test::call_service(&app, checkout()).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Below vendor minimum order");
+    assert_eq!(body["vendor_username"], "min_order_vendor");
+    assert_eq!(body["subtotal"], 120.0);
+    assert_eq!(body["shortfall"], 180.0);
+    assert!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().is_empty());
+
+    db::add_to_cart(&pool, customer.id, eggs.id as i32, 3).await.unwrap();
+    assert_eq!(test::call_service(&app, checkout()).await.status(), 200);
+    assert_eq!(db::get_customer_shipping_orders(&pool, customer.id).await.unwrap().len(), 1);
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use actix_web::test;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn tag_filter_returns_only_tagged_products() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "tag_vendor").await;
+    let app = common::init_app(&pool).await;
+
+    let mut ids = Vec::new();
+    for (name, tags) in [
+        ("Spinach", json!(["Organic", " organic ", "LOCAL"])),
+        ("Carrots", json!(["local"])),
+        ("Apples", json!([])),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/products")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({
+                "name": name, "price": 40.0, "category": "Vegetables",
+                "description": "Fresh", "quantity": 10, "tags": tags
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        let body: Value = test::read_body_json(resp).await;
+        ids.push(body["id"].as_i64().unwrap());
+        if name == "Spinach" {
+            // Casing normalized and duplicates dropped
+            assert_eq!(body["tags"], json!(["organic", "local"]));
+        }
+    }
+
+    let req = test::TestRequest::get().uri("/products?tag=Organic").to_request();
+    let products: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let products = products.as_array().unwrap();
+    assert_eq!(products.len(), 1);
+    assert_eq!(products[0]["id"].as_i64().unwrap(), ids[0]);
+    assert_eq!(products[0]["tags"], json!(["local", "organic"]));
+
+    let req = test::TestRequest::get().uri("/products?tag=local").to_request();
+    let products: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(products.as_array().unwrap().len(), 2);
+
+    // Updating without tags keeps them; an explicit list replaces them
+    let req = test::TestRequest::patch()
+        .uri(&format!("/products/{}", ids[1]))
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({
+            "name": "Carrots", "price": 45.0, "category": "Vegetables",
+            "description": "Fresh", "quantity": 10
+        }))
+        .to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["tags"], json!(["local"]));
+
+    let req = test::TestRequest::get().uri("/tags").to_request();
+    let tags: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(tags[0], json!({ "tag": "local", "product_count": 2 }));
+    assert_eq!(tags[1], json!({ "tag": "organic", "product_count": 1 }));
+}
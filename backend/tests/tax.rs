@@ -0,0 +1,77 @@
+mod common;
+
+use actix_web::test::{call_and_read_body_json, call_service, TestRequest};
+use backend::db;
+use backend::models::{OrderReceipt, Role, VendorSalesReport};
+use backend::tax::{self, TaxRates};
+use serde_json::{json, Value};
+
+#[test]
+fn tax_is_taken_out_of_inclusive_prices() {
+    let rates = TaxRates {
+        default_rate: 0.16,
+        categories: [("produce".to_string(), 0.0)].into_iter().collect(),
+    };
+    assert_eq!(rates.rate_for(" Produce "), 0.0);
+    assert_eq!(rates.rate_for("Pantry"), 0.16);
+    assert_eq!(tax::inclusive_tax(116.0, 0.16), 16.0);
+    assert_eq!(tax::inclusive_tax(100.0, 0.0), 0.0);
+}
+
+#[actix_web::test]
+async fn taxable_and_exempt_items_give_the_right_tax_total() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    common::enable_demo_mode();
+    let vendor = common::create_verified_vendor(&pool, "tax_vendor").await;
+    let customer = common::create_user(&pool, "tax_customer", Role::Customer).await;
+    let admin = common::create_user(&pool, "tax_admin", Role::Admin).await;
+    let honey = db::create_product(&pool, "Honey", 232.0, "Pantry", "Raw honey", 50, None, vendor.id)
+        .await
+        .unwrap();
+    let kales = db::create_product(&pool, "Kales", 50.0, "Produce", "Fresh kales", 50, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let set_rate = |rate: f64| {
+        TestRequest::put()
+            .uri("/api/admin/vat-rates/produce")
+            .insert_header(common::bearer(&admin))
+            .set_json(json!({ "rate": rate }))
+            .to_request()
+    };
+    assert_eq!(call_service(&app, set_rate(1.5)).await.status(), 400);
+    let rates: Value = call_and_read_body_json(&app, set_rate(0.0)).await;
+    assert_eq!(rates["default_rate"], 0.16);
+    assert_eq!(rates["categories"], json!([{ "category": "produce", "rate": 0.0, "exempt": true }]));
+
+    db::add_to_cart(&pool, customer.id, honey.id as i32, 1).await.unwrap();
+    db::add_to_cart(&pool, customer.id, kales.id as i32, 2).await.unwrap();
+    let req = TestRequest::post()
+        .uri("/checkout")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "mpesa_number": "0712345678", "total_amount": 332.0 }))
+        .to_request();
+    let checkout: Value = call_and_read_body_json(&app, req).await;
+    // 16% VAT included in the 232 of honey; the kales are exempt
+    assert_eq!(checkout["tax_total"], 32.0);
+
+    let order_id = db::get_customer_shipping_orders(&pool, customer.id).await.unwrap()[0].order_id.unwrap();
+    let req = TestRequest::get()
+        .uri(&format!("/orders/{}/receipt", order_id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let receipt: OrderReceipt = call_and_read_body_json(&app, req).await;
+    assert_eq!(receipt.tax_total, 32.0);
+    // VAT is part of the price, not added to it
+    assert_eq!(receipt.total, 332.0);
+    let kale_line = receipt.items.iter().find(|item| item.product_id == kales.id as i32).unwrap();
+    assert_eq!(kale_line.tax, 0.0);
+
+    let req = TestRequest::get()
+        .uri("/reports/vendor/sales")
+        .insert_header(common::bearer(&vendor))
+        .to_request();
+    let report: VendorSalesReport = call_and_read_body_json(&app, req).await;
+    assert_eq!(report.total_tax, 32.0);
+}
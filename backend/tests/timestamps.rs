@@ -0,0 +1,70 @@
+mod common;
+
+use actix_web::test::{call_and_read_body_json, call_service, read_body_json, TestRequest};
+use backend::db;
+use backend::models::Role;
+use backend::timestamps::{self, DisplayZone};
+use serde_json::{json, Value};
+
+#[test]
+fn timestamps_parse_postgres_output_and_render_with_offsets() {
+    let ts = timestamps::parse("2025-01-31 09:15:00.25+00").unwrap();
+    assert_eq!(timestamps::format(ts, DisplayZone::default()), "2025-01-31T09:15:00.250+00:00");
+    let nairobi: DisplayZone = "Africa/Nairobi".parse().unwrap();
+    assert_eq!(timestamps::format(ts, nairobi), "2025-01-31T12:15:00.250+03:00");
+
+    // Other offsets and offset-less values are normalized to the same instant
+    assert_eq!(timestamps::parse("2025-01-31 14:45:00.25+05:30"), Some(ts));
+    assert_eq!(timestamps::parse("2025-01-31T09:15:00.25Z"), Some(ts));
+    assert_eq!(timestamps::parse("2025-01-31 09:15:00.25"), Some(ts));
+    assert_eq!(timestamps::parse("Fresh kale"), None);
+
+    for invalid in ["Mars/Olympus", "+25:00", "+3:0", "+ab"] {
+        assert!(invalid.parse::<DisplayZone>().is_err(), "{}", invalid);
+    }
+}
+
+#[actix_web::test]
+async fn stored_utc_timestamps_render_in_the_requested_zone() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "tz_vendor").await;
+    let customer = common::create_user(&pool, "tz_customer", Role::Customer).await;
+    let kale = db::create_product(&pool, "Kale", 30.0, "Vegetables", "Curly kale", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let review = db::create_review(&pool, customer.id, kale.id as i32, 5, Some("Crisp")).await.unwrap();
+    sqlx::query("UPDATE reviews SET created_at = '2025-01-31 09:15:00+00' WHERE id = $1")
+        .bind(review.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let created_at = |query: &str| {
+        let req = TestRequest::get()
+            .uri(&format!("/reviews/product/{}{}", kale.id, query))
+            .to_request();
+        let app = &app;
+        async move {
+            let reviews: Value = call_and_read_body_json(app, req).await;
+            reviews[0]["created_at"].clone()
+        }
+    };
+    assert_eq!(created_at("").await, json!("2025-01-31T09:15:00+00:00"));
+    assert_eq!(created_at("?tz=Africa/Nairobi").await, json!("2025-01-31T12:15:00+03:00"));
+    assert_eq!(created_at("?tz=-05:30").await, json!("2025-01-31T03:45:00-05:30"));
+    // An unencoded + arrives as a space
+    assert_eq!(created_at("?tz=+03:00").await, json!("2025-01-31T12:15:00+03:00"));
+    // Text that merely looks like a field name is left alone
+    let req = TestRequest::get().uri(&format!("/reviews/product/{}", kale.id)).to_request();
+    let reviews: Value = call_and_read_body_json(&app, req).await;
+    assert_eq!(reviews[0]["comment"], "Crisp");
+
+    let req = TestRequest::get()
+        .uri(&format!("/reviews/product/{}?tz=Mars/Olympus", kale.id))
+        .to_request();
+    let resp = call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = read_body_json(resp).await;
+    assert_eq!(body["error"], "Invalid tz");
+}
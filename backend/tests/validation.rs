@@ -0,0 +1,146 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::{ReviewSort, Role};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn product_text_is_trimmed_and_length_checked() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "tidy_vendor").await;
+    let app = common::init_app(&pool).await;
+
+    let create = |name: String, description: &str| {
+        test::TestRequest::post()
+            .uri("/products")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({
+                "name": name,
+                "price": 50.0,
+                "category": "  Fruit ",
+                "description": description,
+                "quantity": 5
+            }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, create("  Pawpaw\u{7}  ".to_string(), " Sweet\nand ripe \u{0}")).await;
+    assert_eq!(resp.status(), 201);
+    let product: Value = test::read_body_json(resp).await;
+    assert_eq!(product["name"], "Pawpaw");
+    assert_eq!(product["category"], "Fruit");
+    assert_eq!(product["description"], "Sweet\nand ripe");
+
+    let resp = test::call_service(&app, create("x".repeat(101), "Too long")).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["field"], "name");
+
+    let resp = test::call_service(&app, create("   ".to_string(), "Blank name")).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["field"], "name");
+}
+
+#[actix_web::test]
+async fn blank_or_oversized_messages_and_reviews_are_rejected() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "tidy_seller").await;
+    let customer = common::create_user(&pool, "tidy_customer", Role::Customer).await;
+    let product = db::create_product(&pool, "Guavas", 20.0, "Fruit", "Pink", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let send = |content: String| {
+        test::TestRequest::post()
+            .uri("/messages")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "receiver_id": vendor.id, "content": content }))
+            .to_request()
+    };
+    let resp = test::call_service(&app, send(" \n\t ".to_string())).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["field"], "content");
+    assert_eq!(test::call_service(&app, send("y".repeat(2001))).await.status(), 400);
+    let resp = test::call_service(&app, send("  Are these ripe?  ".to_string())).await;
+    assert_eq!(resp.status(), 201);
+    let message: Value = test::read_body_json(resp).await;
+    assert_eq!(message["content"], "Are these ripe?");
+
+    let req = test::TestRequest::post()
+        .uri("/reviews")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "product_id": product.id, "rating": 4, "comment": "z".repeat(1001) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["field"], "comment");
+}
+
+#[actix_web::test]
+async fn edited_message_is_sanitized_like_a_new_one() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "edit_vendor").await;
+    let customer = common::create_user(&pool, "edit_customer", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/messages")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({ "receiver_id": vendor.id, "content": "Any mangoes?" }))
+        .to_request();
+    let message: Value = test::call_and_read_body_json(&app, req).await;
+
+    let edit = |content: String| {
+        test::TestRequest::put()
+            .uri(&format!("/messages/{}", message["id"]))
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "content": content }))
+            .to_request()
+    };
+    let resp = test::call_service(&app, edit("Any <b>mangoes</b>?<script>alert('x')</script>".to_string())).await;
+    assert_eq!(resp.status(), 200);
+    let edited: Value = test::read_body_json(resp).await;
+    assert_eq!(edited["content"], "Any mangoes?");
+
+    let resp = test::call_service(&app, edit("<img src=x onerror=alert(1)>".to_string())).await;
+    assert_eq!(resp.status(), 400);
+    // Anything a new message may hold, an edit may too
+    assert_eq!(test::call_service(&app, edit("y".repeat(2000))).await.status(), 200);
+    assert_eq!(test::call_service(&app, edit("y".repeat(2001))).await.status(), 400);
+}
+
+#[actix_web::test]
+async fn script_in_review_is_neutralized() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "xss_vendor").await;
+    let customer = common::create_user(&pool, "xss_customer", Role::Customer).await;
+    let product = db::create_product(&pool, "Passion fruit", 10.0, "Fruit", "Purple", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::post()
+        .uri("/reviews")
+        .insert_header(common::bearer(&customer))
+        .set_json(json!({
+            "product_id": product.id,
+            "rating": 5,
+            "comment": "Great <b>taste</b> & 5 < 6<script>alert('x')</script> &lt;script&gt;steal()&lt;/script&gt;"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let review: Value = test::read_body_json(resp).await;
+    assert_eq!(review["comment"], "Great taste & 5 < 6");
+
+    let stored = db::get_product_reviews(&pool, product.id as i32, ReviewSort::Newest, None).await.unwrap();
+    assert_eq!(stored[0].comment.as_deref(), Some("Great taste & 5 < 6"));
+
+    // Nothing but markup leaves nothing to store
+    assert_eq!(backend::validation::strip_html("<img src=x onerror=alert(1)>"), "");
+}
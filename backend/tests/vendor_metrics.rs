@@ -0,0 +1,92 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::Value;
+
+#[actix_web::test]
+async fn profile_reports_average_ship_and_response_times() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "metrics_vendor").await;
+    let customer = common::create_user(&pool, "metrics_customer", Role::Customer).await;
+    let other = common::create_user(&pool, "metrics_other", Role::Customer).await;
+    let product = db::create_product(&pool, "Kale", 40.0, "Vegetables", "Sukuma wiki", 100, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let profile = || async {
+        let req = test::TestRequest::get()
+            .uri(&format!("/vendors/{}/profile", vendor.id))
+            .insert_header(common::bearer(&customer))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        body
+    };
+
+    // Orders placed at a fixed time, shipped after 10, 20 and 30 hours
+    let mut orders = Vec::new();
+    for ship_after in [10, 20, 30] {
+        let order = db::create_shipping_order(&pool, customer.id, product.id as i32, 1, "Nakuru").await.unwrap();
+        sqlx::query("UPDATE shipping_orders SET created_at = '2026-01-01T00:00:00Z' WHERE id = $1")
+            .bind(order.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO shipping_status_history (shipping_order_id, status, changed_at)
+             VALUES ($1, 'shipped', '2026-01-01T00:00:00Z'::timestamptz + make_interval(hours => $2)),
+                    ($1, 'delivered', '2026-01-05T00:00:00Z')",
+        )
+        .bind(order.id)
+        .bind(ship_after)
+        .execute(&pool)
+        .await
+        .unwrap();
+        orders.push(order.id);
+    }
+    // Conversations: a run of two customer messages answered after 2h, a
+    // question answered after 4h, another customer answered after 6h, and an
+    // unanswered message that doesn't count
+    for (sender, receiver, at) in [
+        (customer.id, vendor.id, "2026-01-02T08:00:00Z"),
+        (customer.id, vendor.id, "2026-01-02T09:00:00Z"),
+        (vendor.id, customer.id, "2026-01-02T10:00:00Z"),
+        (vendor.id, customer.id, "2026-01-02T10:05:00Z"),
+        (customer.id, vendor.id, "2026-01-03T08:00:00Z"),
+        (vendor.id, customer.id, "2026-01-03T12:00:00Z"),
+        (other.id, vendor.id, "2026-01-03T08:00:00Z"),
+        (vendor.id, other.id, "2026-01-03T14:00:00Z"),
+        (other.id, vendor.id, "2026-01-04T08:00:00Z"),
+    ] {
+        sqlx::query("INSERT INTO messages (sender_id, receiver_id, content, created_at) VALUES ($1, $2, 'hi', $3::timestamptz)")
+            .bind(sender)
+            .bind(receiver)
+            .bind(at)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    let body = profile().await;
+    assert_eq!(body["avg_ship_hours"].as_f64(), Some(20.0));
+    assert_eq!(body["avg_response_hours"].as_f64(), Some(4.0));
+
+    // Too few samples gives null rather than a misleading average
+    sqlx::query("DELETE FROM shipping_status_history WHERE shipping_order_id = $1").bind(orders[0]).execute(&pool).await.unwrap();
+    sqlx::query("DELETE FROM messages WHERE sender_id = $1 OR receiver_id = $1").bind(other.id).execute(&pool).await.unwrap();
+    let body = profile().await;
+    assert!(body["avg_ship_hours"].is_null());
+    assert!(body["avg_response_hours"].is_null());
+
+    // Status updates are recorded as they happen
+    db::update_shipping_status(&pool, orders[0], "shipped", None).await.unwrap();
+    let statuses: Vec<String> = sqlx::query_scalar("SELECT status FROM shipping_status_history WHERE shipping_order_id = $1")
+        .bind(orders[0])
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses, vec!["shipped"]);
+    assert!(profile().await["avg_ship_hours"].as_f64().is_some());
+}
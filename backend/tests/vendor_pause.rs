@@ -0,0 +1,62 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn paused_vendor_products_leave_listings_until_resumed() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "pause_vendor").await;
+    let customer = common::create_user(&pool, "pause_customer", Role::Customer).await;
+    let honey = db::create_product(&pool, "Honey", 200.0, "Pantry", "Raw honey", 50, None, vendor.id)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let (app, honey_id) = (&app, honey.id);
+    let listed = move || async move {
+        let req = test::TestRequest::get().uri("/products").to_request();
+        let products: Vec<Value> = test::call_and_read_body_json(app, req).await;
+        products.iter().any(|p| p["id"] == honey_id)
+    };
+    let add_to_cart = || {
+        test::TestRequest::post()
+            .uri("/cart")
+            .insert_header(common::bearer(&customer))
+            .set_json(json!({ "product_id": honey_id, "quantity": 1 }))
+            .to_request()
+    };
+    let toggle = |user, action: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/vendor/{}", action))
+            .insert_header(common::bearer(user))
+            .to_request()
+    };
+    assert!(listed().await);
+
+    assert_eq!(test::call_service(app, toggle(&customer, "pause")).await.status(), 403);
+    let resp = test::call_service(app, toggle(&vendor, "pause")).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["is_paused"], true);
+
+    assert!(!listed().await);
+    let resp = test::call_service(app, add_to_cart()).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Vendor paused");
+
+    // The storefront still shows the vendor, flagged as paused
+    let req = test::TestRequest::get()
+        .uri(&format!("/vendors/{}/profile", vendor.id))
+        .insert_header(common::bearer(&customer))
+        .to_request();
+    let profile: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(profile["is_paused"], true);
+
+    assert_eq!(test::call_service(app, toggle(&vendor, "resume")).await.status(), 200);
+    assert!(listed().await);
+    assert_eq!(test::call_service(app, add_to_cart()).await.status(), 201);
+}
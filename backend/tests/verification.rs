@@ -0,0 +1,218 @@
+mod common;
+
+use actix_web::test;
+use backend::{db, retention};
+use backend::models::Role;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn verification_documents_must_be_images_or_pdfs() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_user(&pool, "docs_vendor", Role::Vendor).await;
+    let admin = common::create_user(&pool, "docs_admin", Role::Admin).await;
+    let app = common::init_app(&pool).await;
+
+    let upload = |document: &str| {
+        test::TestRequest::post()
+            .uri("/vendor/upload-verification")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "verification_document": document }))
+            .to_request()
+    };
+
+    for invalid in [
+        // Plain text, not a document
+        "aGVsbG8gd29ybGQ=",
+        "data:image/png;base64,aGVsbG8gd29ybGQ=",
+        // PNG bytes labelled as JPEG
+        "data:image/jpeg;base64,iVBORw0KGgoAAAANSUhEUg==",
+        "not base64 at all!",
+    ] {
+        assert_eq!(test::call_service(&app, upload(invalid)).await.status(), 400, "{}", invalid);
+    }
+
+    let png = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUg==";
+    let resp = test::call_service(&app, upload(png)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["content_type"], "image/png");
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/admin/users/{}/verification-document", vendor.id))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["document"], png);
+    assert_eq!(body["content_type"], "image/png");
+
+    // Bare base64 is sniffed too
+    let resp = test::call_service(&app, upload("JVBERi0xLjQK")).await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["content_type"], "application/pdf");
+}
+
+#[actix_web::test]
+async fn bulk_verification_flips_vendors_and_audits_each() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "bulk_verify_admin", Role::Admin).await;
+    let first = common::create_user(&pool, "bulk_verify_one", Role::Vendor).await;
+    let second = common::create_user(&pool, "bulk_verify_two", Role::Vendor).await;
+    let customer = common::create_user(&pool, "bulk_verify_customer", Role::Customer).await;
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::patch()
+        .uri("/api/admin/users/verify-bulk")
+        .insert_header(common::bearer(&admin))
+        .set_json(json!({ "user_ids": [first.id, second.id, customer.id, 999_999], "verified": true }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["updated"], 2);
+    let results = body["data"]["results"].as_array().unwrap();
+    let outcome = |id: i32| results.iter().find(|r| r["user_id"] == id).unwrap();
+    assert_eq!(outcome(first.id)["success"], true);
+    assert_eq!(outcome(second.id)["success"], true);
+    assert_eq!(outcome(customer.id)["success"], false);
+    assert_eq!(outcome(999_999)["error"], "User not found");
+
+    assert!(db::get_user_by_id(&pool, first.id).await.unwrap().verified);
+    assert!(db::get_user_by_id(&pool, second.id).await.unwrap().verified);
+
+    let entries = db::get_audit_log(&pool, Some(admin.id), None, 10).await.unwrap();
+    let mut actions: Vec<String> = entries.into_iter().map(|e| e.action).collect();
+    actions.sort();
+    assert_eq!(actions, vec![
+        format!("verification.approve user {}", first.id),
+        format!("verification.approve user {}", second.id),
+    ]);
+
+    // Vendors can't use it
+    let req = test::TestRequest::patch()
+        .uri("/api/admin/users/verify-bulk")
+        .insert_header(common::bearer(&first))
+        .set_json(json!({ "user_ids": [second.id], "verified": false }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
+
+#[actix_web::test]
+async fn approved_documents_are_purged_after_retention() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "retention_admin", Role::Admin).await;
+    let expired = common::create_user(&pool, "retention_expired", Role::Vendor).await;
+    let recent = common::create_user(&pool, "retention_recent", Role::Vendor).await;
+    let kept = common::create_user(&pool, "retention_kept", Role::Vendor).await;
+    let pending = common::create_user(&pool, "retention_pending", Role::Vendor).await;
+    let png = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUg==";
+    for vendor in [&expired, &recent, &kept, &pending] {
+        db::upload_verification_document(&pool, vendor.id, png, "image/png").await.unwrap();
+    }
+    for vendor in [&expired, &recent, &kept] {
+        db::update_user_verification(&pool, vendor.id, true).await.unwrap();
+    }
+    sqlx::query("UPDATE users SET verified_at = NOW() - INTERVAL '100 days' WHERE id = ANY($1)")
+        .bind(vec![expired.id, kept.id])
+        .execute(&pool)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/admin/users/{}/verification-document/retention", kept.id))
+        .insert_header(common::bearer(&admin))
+        .set_json(json!({ "retained": true }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    assert_eq!(retention::purge_expired_verification_documents(&pool).await.unwrap(), 1);
+    assert!(db::get_user_verification_document(&pool, expired.id).await.unwrap().is_none());
+    for vendor in [&recent, &kept, &pending] {
+        assert!(db::get_user_verification_document(&pool, vendor.id).await.unwrap().is_some());
+    }
+
+    // The purge leaves a note that the document existed and was reviewed
+    let entries = db::get_audit_log(&pool, None, None, 10).await.unwrap();
+    let note = entries
+        .iter()
+        .find(|e| e.action == format!("verification.document_purged user {}", expired.id))
+        .unwrap();
+    assert_eq!(note.actor_id, None);
+    assert!(note.details.as_deref().unwrap().starts_with("image/png document submitted"));
+    assert!(entries.iter().any(|e| e.action == format!("verification.retain_document user {}", kept.id)));
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/admin/users/{}/verification-document", expired.id))
+        .insert_header(common::bearer(&admin))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    // Running again finds nothing more to purge
+    assert_eq!(retention::purge_expired_verification_documents(&pool).await.unwrap(), 0);
+}
+
+#[actix_web::test]
+async fn resubmitting_after_rejection_clears_reason_and_is_flagged() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_user(&pool, "resubmit_vendor", Role::Vendor).await;
+    let admin = common::create_user(&pool, "resubmit_admin", Role::Admin).await;
+    sqlx::query("UPDATE users SET profile_image = 'data:image/png;base64,iVBORw0KGgo=' WHERE id = $1")
+        .bind(vendor.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let app = common::init_app(&pool).await;
+
+    let upload = || {
+        test::TestRequest::post()
+            .uri("/vendor/upload-verification")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "verification_document": "data:image/png;base64,iVBORw0KGgoAAAANSUhEUg==" }))
+            .to_request()
+    };
+    let rejection_reason = || async {
+        sqlx::query_scalar::<_, Option<String>>("SELECT verification_rejected_reason FROM users WHERE id = $1")
+            .bind(vendor.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+    };
+    let pending = || async {
+        let req = test::TestRequest::get()
+            .uri("/api/admin/pending-vendors")
+            .insert_header(common::bearer(&admin))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        body.as_array().unwrap().iter().find(|v| v["id"] == vendor.id).cloned()
+    };
+
+    let body: Value = test::call_and_read_body_json(&app, upload()).await;
+    assert_eq!(body["data"]["resubmission"], false);
+    let entry = pending().await.unwrap();
+    assert_eq!(entry["is_resubmission"], false);
+    let first_submitted = entry["submitted_at"].as_str().unwrap().to_string();
+
+    db::update_user_verification(&pool, vendor.id, false).await.unwrap();
+    assert!(rejection_reason().await.is_some());
+    sqlx::query("UPDATE users SET verification_submitted_at = '2026-01-01T00:00:00Z' WHERE id = $1")
+        .bind(vendor.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let body: Value = test::call_and_read_body_json(&app, upload()).await;
+    assert_eq!(body["data"]["resubmission"], true);
+    assert!(rejection_reason().await.is_none());
+    let entry = pending().await.unwrap();
+    assert_eq!(entry["is_resubmission"], true);
+    assert_ne!(entry["submitted_at"], "2026-01-01T00:00:00Z");
+    assert!(entry["submitted_at"].as_str().unwrap() >= first_submitted.as_str());
+
+    // Uploading again before review is still the same resubmission
+    let body: Value = test::call_and_read_body_json(&app, upload()).await;
+    assert_eq!(body["data"]["resubmission"], true);
+
+    // A decision settles it
+    db::update_user_verification(&pool, vendor.id, false).await.unwrap();
+    assert_eq!(pending().await.unwrap()["is_resubmission"], false);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use actix_web::test;
+use backend::db;
+use backend::models::Role;
+use backend::settings;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn concurrent_withdrawals_cannot_overdraw() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "race_vendor").await;
+    sqlx::query("UPDATE users SET wallet_balance = 500.0 WHERE id = $1")
+        .bind(vendor.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let (first, second) = tokio::join!(
+        db::process_wallet_withdrawal(&pool, vendor.id, 300.0, 1_000_000.0, "race_1"),
+        db::process_wallet_withdrawal(&pool, vendor.id, 300.0, 1_000_000.0, "race_2"),
+    );
+    let succeeded = [&first, &second].iter().filter(|r| r.is_ok()).count();
+    assert_eq!(succeeded, 1);
+    assert!(matches!(first.as_ref().err().or(second.as_ref().err()), Some(db::WithdrawalError::InsufficientFunds)));
+    assert_eq!(db::get_wallet_balance(&pool, vendor.id).await.unwrap(), 200.0);
+}
+
+#[actix_web::test]
+async fn concurrent_delivery_verification_credits_once() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "race_seller").await;
+    let customer = common::create_user(&pool, "race_customer", Role::Customer).await;
+    db::update_user_profile(&pool, vendor.id, None, None, None, None, Some("after_order")).await.unwrap();
+    let beans = db::create_product(&pool, "Beans", 150.0, "Legumes", "Yellow beans", 10, None, vendor.id)
+        .await
+        .unwrap();
+    let order = db::create_shipping_order(&pool, customer.id, beans.id as i32, 2, "Meru").await.unwrap();
+
+    let (first, second) = tokio::join!(
+        db::verify_delivery_and_release_payment(&pool, order.id, customer.id),
+        db::verify_delivery_and_release_payment(&pool, order.id, customer.id),
+    );
+    first.unwrap();
+    second.unwrap();
+    assert_eq!(db::get_wallet_balance(&pool, vendor.id).await.unwrap(), 300.0);
+}
+
+#[actix_web::test]
+async fn withdrawals_past_the_daily_limit_are_rejected() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let vendor = common::create_verified_vendor(&pool, "capped_vendor").await;
+    sqlx::query("UPDATE users SET wallet_balance = 5000.0, mpesa_number = '0722000444', mpesa_verified = TRUE WHERE id = $1")
+        .bind(vendor.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    settings::set_setting(&pool, settings::DAILY_WITHDRAWAL_LIMIT, "1000").await.unwrap();
+    settings::set_setting(&pool, settings::MAX_WITHDRAWAL_AMOUNT, "2000").await.unwrap();
+    let app = common::init_app(&pool).await;
+
+    let withdraw = |amount: f64| {
+        test::TestRequest::post()
+            .uri("/wallet/withdraw")
+            .insert_header(common::bearer(&vendor))
+            .set_json(json!({ "amount": amount, "mpesa_number": "0722000444" }))
+            .to_request()
+    };
+
+    // Over the per-transaction maximum
+    assert_eq!(test::call_service(&app, withdraw(2500.0)).await.status(), 400);
+
+    assert_eq!(test::call_service(&app, withdraw(700.0)).await.status(), 200);
+
+    let resp = test::call_service(&app, withdraw(400.0)).await;
+    assert_eq!(resp.status(), 429);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["remaining_allowance"], 300.0);
+    assert_eq!(db::get_wallet_balance(&pool, vendor.id).await.unwrap(), 4300.0);
+
+    // The remaining allowance can still be used
+    assert_eq!(test::call_service(&app, withdraw(300.0)).await.status(), 200);
+}
+
+#[actix_web::test]
+async fn admin_adjustment_is_ledgered_and_audited() {
+    let Some((pool, _db)) = common::test_pool().await else { return };
+    let admin = common::create_user(&pool, "ledger_admin", Role::Admin).await;
+    let vendor = common::create_verified_vendor(&pool, "adjusted_vendor").await;
+    let app = common::init_app(&pool).await;
+
+    let adjust = |amount: f64| {
+        test::TestRequest::post()
+            .uri(&format!("/api/admin/users/{}/wallet/adjust", vendor.id))
+            .insert_header(common::bearer(&admin))
+            .set_json(json!({ "amount": amount, "reason": "Refund dispute #42" }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, adjust(250.0)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["new_balance"], 250.0);
+
+    // Debits can't push the balance below zero
+    assert_eq!(test::call_service(&app, adjust(-300.0)).await.status(), 400);
+    assert_eq!(db::get_wallet_balance(&pool, vendor.id).await.unwrap(), 250.0);
+
+    let ledger: Vec<(String, f64, Option<String>)> =
+        sqlx::query_as("SELECT entry_type, amount, reference FROM wallet_ledger WHERE user_id = $1")
+            .bind(vendor.id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(ledger, vec![("admin_adjustment".to_string(), 250.0, Some("Refund dispute #42".to_string()))]);
+
+    let audit = db::get_audit_log(&pool, Some(admin.id), None, 10).await.unwrap();
+    assert_eq!(audit.len(), 1);
+    assert_eq!(audit[0].action, format!("wallet.adjust user {}", vendor.id));
+    assert!(audit[0].details.as_deref().unwrap().contains("Refund dispute #42"));
+
+    // Non-admins are refused
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/admin/users/{}/wallet/adjust", vendor.id))
+        .insert_header(common::bearer(&vendor))
+        .set_json(json!({ "amount": 1000.0, "reason": "Self service" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
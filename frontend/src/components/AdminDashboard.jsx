@@ -572,7 +572,9 @@ const AdminDashboard = () => {
                         : "Monthly"}
                     </p>
                     <p className="verification-status">
-                      Status: <span className="unverified">Pending Review</span>
+                      Status: <span className="unverified">
+                        {vendor.is_resubmission ? "Resubmitted" : "Pending Review"}
+                      </span>
                     </p>
                   </div>
                   <div className="verification-actions">
@@ -79,7 +79,7 @@ const CustomerDashboard = () => {
       const response = await axios.post("/payments/process-completed");
       if (response.data.success) {
         toast.success(
-          `Processed ${response.data.processed_count} payment(s) and created ${response.data.orders_created} order(s)`
+          `Processed ${response.data.data.payments_processed} payment(s) and created ${response.data.data.orders_created} order(s)`
         );
         // Reload both transactions and cart items since cart was cleared
         loadTransactions();
@@ -149,7 +149,7 @@ const CustomerDashboard = () => {
       const response = await axios.put("/user/profile", updateData);
 
       // Update user context with new data
-      const updatedUser = { ...user, ...response.data };
+      const updatedUser = { ...user, ...response.data.data };
       login({ user: updatedUser, token: localStorage.getItem("token") });
 
       toast.success("Profile updated successfully!");
@@ -48,16 +48,16 @@ export const FollowProvider = ({ children }) => {
     }
 
     try {
+      // Following again returns the existing follow, so replace rather than append
       const response = await axios.post("/follow", { vendor_id: vendorId });
-      setFollows((prev) => [...prev, response.data]);
+      setFollows((prev) => [
+        ...prev.filter((follow) => follow.vendor_id !== vendorId),
+        response.data,
+      ]);
       return true;
     } catch (error) {
-      if (error.response?.status === 409) {
-        toast.warning("You are already following this vendor");
-      } else {
-        console.error("Error following vendor:", error);
-        toast.error("Failed to follow vendor");
-      }
+      console.error("Error following vendor:", error);
+      toast.error("Failed to follow vendor");
       return false;
     }
   };
@@ -86,6 +86,13 @@ const VendorProfileView = () => {
         ← Back
       </button>
 
+      {vendorProfile.is_paused && (
+        <div className="pause-banner">
+          {vendorProfile.username} is away and isn't taking orders right now.
+          Their products will be back when they return.
+        </div>
+      )}
+
       <div className="vendor-header">
         {vendorProfile.profile_image && (
           <img
@@ -110,6 +117,22 @@ const VendorProfileView = () => {
               </span>
               <span className="stat-label">Followers</span>
             </div>
+            {vendorProfile.avg_ship_hours != null && (
+              <div className="stat">
+                <span className="stat-number">
+                  {vendorProfile.avg_ship_hours}h
+                </span>
+                <span className="stat-label">Avg. Time to Ship</span>
+              </div>
+            )}
+            {vendorProfile.avg_response_hours != null && (
+              <div className="stat">
+                <span className="stat-number">
+                  {vendorProfile.avg_response_hours}h
+                </span>
+                <span className="stat-label">Avg. Reply Time</span>
+              </div>
+            )}
           </div>
           <div className="verification-status">
             {vendorProfile.verified ? (